@@ -17,6 +17,11 @@
 //! - [`effect!`] - Defines effect families and operations
 //! - [`effectful`] - Transforms functions into effectful computations  
 //! - [`perform!`] - Performs effect operations within effectful functions
+//! - [`emit!`] - Produces values from streaming effectful functions
+//! - [`effectful_closure!`] - Builds closures returning effectful computations
+//! - [`algae_test`] - Runs effectful test functions with a handler fixture
+//! - [`RouteHandler`] - Derives a handler that routes each family to a field
+//! - [`handles`] - Makes a handler of single families a partial handler of any root
 //!
 //! These macros are typically used through the `algae::prelude` module rather than directly.
 //!
@@ -49,6 +54,7 @@ use syn::{
     parse::{Parse, ParseStream},
     parse_macro_input,
     punctuated::Punctuated,
+    visit_mut::VisitMut,
     Ident, Result, Token, Type,
 };
 
@@ -64,8 +70,67 @@ use syn::{
 
   The "(Payload)" part may be omitted when there is no payload.
   If you keep it, it can be the empty tuple "()".
-  `Ret` is parsed and stored but only used for documentation – the run‑time
-  uses dynamic down‑casting to recover it.
+  A payload may be a computation, as an `algae::higher::Computation<R, Op>`.
+
+  A family declared elsewhere (e.g. one of algae's standard families) is
+  embedded with a `use` line; only the root variant and impls are generated:
+
+        use algae::effects::progress::Progress;
+
+  The root enum gets one constructor per op line,
+  `Root::family_variant(…) -> Root`.
+
+  A `helpers;` header (next to the optional `root Name;` header) also emits
+  `mod family { pub fn variant(…) -> algae::PerformsOne<Ret, Op> }`.
+
+  Op lines may carry annotations that end up in the generated
+  `algae::OpMeta` impls:
+
+        #[retryable(max = 3, backoff = exp, delay_ms = 100)]
+        Http::Get (String) -> String;
+        #[mutates]
+        Account::Deposit (u64) -> ();
+        #[tag(io, billing)]
+        #[priority(high)]
+        Account::Charge (u64) -> ();
+
+  A `typed;` header emits `<Family>Call<'a>` and `<Root>Call<'a>` enums and
+  `algae::TypedOp` impls, for handlers whose replies are type-checked.
+
+  A `serde;` header (algae feature `serde`) derives `Serialize` and
+  `Deserialize` and implements `algae::remote::SerdeOp`, so operations and
+  their replies can be sent to handlers in other processes.
+
+  A `proptest;` header (algae feature `proptest`) implements proptest's
+  `Arbitrary` and `algae::property::DeclaredReply`, for checking handlers
+  against generated operations.
+
+  A `fuzz;` header (algae feature `proptest`) implements
+  `algae::fuzz::FuzzReply`, which makes up a reply of the declared type for
+  each operation, for fuzzing computations against unexpected replies.
+
+  An `automock;` header emits `<Root>AutoMock`, a handler with one
+  `algae::mock::OpMock<Payload, Ret>` field per op line.
+
+  Inner attributes at the top of the block go on every generated family
+  and root enum, derives next to the built-in `Debug, Clone, PartialEq`;
+  a `#[derive(...)]` on an op line goes on its family enum only:
+
+        #![derive(Eq, Hash)]
+        #[derive(PartialOrd)]
+        Level::Low -> ();
+
+  Families may take type parameters, repeated on each of their lines:
+
+        Cache<K, V>::Get (K) -> Option<V>;
+
+  The root enum then takes the parameters of all its families, those of
+  the same name being shared: `enum Op<K, V> { Cache(Cache<K, V>), … }`.
+
+  `Ret` is only used at compile time – by `perform!` to check the type of
+  `Family::Variant(…)` operations, the helper functions, the auto-mock and
+  the `algae::inline::ReplyBound` size bound; the run‑time uses dynamic
+  down‑casting to recover it.
 
   The expansion is roughly:
 
@@ -73,20 +138,211 @@ use syn::{
     pub enum Op     { Family(Family), … }
 
     impl From<Family> for Op { … }   // one per family
+    impl TryFrom<Op> for Family { … }           // one per family, also for refs
+    impl algae::Contains<Family> for Op { … }   // one per family
+    impl algae::OpMeta for Family { … }         // one per family
+    impl algae::OpMeta for Op { … }             // delegates to the families
+    impl algae::inline::ReplyBound for Family { … }  // largest `Ret`
+    impl algae::__private::VariantReply<{hash of "Variant"}, Ret> for Family {}
+    impl algae::inline::ReplyBound for Op { … }      // largest family bound
+    impl algae::FamilyOp for Op { … }
 ──────────────────────────────────────────────────────────────────────────────*/
 
-/// One operation line:  `Family::Variant (Payload?) -> Ret`
+/// One operation line:  `Family<Params?>::Variant (Payload?) -> Ret`
 struct OpLine {
+    attrs: OpAttrs,
     family: Ident,
+    generics: syn::Generics,
     variant: Ident,
     payload: Option<Type>,
     _arrow: Token![->],
-    _ret: Type,
+    ret: Type,
+}
+
+/// Annotations on an op line, e.g. `#[retryable(max = 3, backoff = exp)]`.
+#[derive(Default)]
+struct OpAttrs {
+    retryable: Option<RetrySpec>,
+    mutates: bool,
+    tags: Vec<String>,
+    priority: Option<Ident>,
+    /// `#[derive(...)]` paths, added to the line's family enum.
+    derives: Vec<syn::Path>,
+}
+
+/// Arguments of `#[retryable(...)]`.
+struct RetrySpec {
+    max: u32,
+    backoff: Ident,
+    delay_ms: u64,
+}
+
+impl Default for RetrySpec {
+    fn default() -> Self {
+        Self {
+            max: 3,
+            backoff: Ident::new("exp", proc_macro2::Span::call_site()),
+            delay_ms: 100,
+        }
+    }
+}
+
+impl OpAttrs {
+    fn parse_outer(input: ParseStream<'_>) -> Result<Self> {
+        let mut attrs = OpAttrs::default();
+        for attr in input.call(syn::Attribute::parse_outer)? {
+            if attr.path().is_ident("retryable") {
+                if attrs.retryable.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "duplicate `retryable` attribute",
+                    ));
+                }
+                attrs.retryable = Some(RetrySpec::from_attr(&attr)?);
+            } else if attr.path().is_ident("mutates") {
+                if attrs.mutates {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "duplicate `mutates` attribute",
+                    ));
+                }
+                attr.meta.require_path_only()?;
+                attrs.mutates = true;
+            } else if attr.path().is_ident("tag") {
+                attr.parse_nested_meta(|meta| {
+                    let tag = meta.path.require_ident()?;
+                    if attrs.tags.contains(&tag.to_string()) {
+                        return Err(meta.error(format!("duplicate tag `{tag}`")));
+                    }
+                    attrs.tags.push(tag.to_string());
+                    Ok(())
+                })?;
+            } else if attr.path().is_ident("priority") {
+                if attrs.priority.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "duplicate `priority` attribute",
+                    ));
+                }
+                let priority: Ident = attr.parse_args()?;
+                if !matches!(priority.to_string().as_str(), "high" | "normal" | "low") {
+                    return Err(syn::Error::new_spanned(
+                        priority,
+                        "expected `high`, `normal` or `low`",
+                    ));
+                }
+                attrs.priority = Some(priority);
+            } else if attr.path().is_ident("derive") {
+                attrs.derives.extend(derive_paths(&attr)?);
+            } else {
+                return Err(syn::Error::new_spanned(
+                    attr.path(),
+                    "unknown op attribute; expected `retryable`, `mutates`, `tag`, `priority` \
+                     or `derive`",
+                ));
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+impl RetrySpec {
+    fn from_attr(attr: &syn::Attribute) -> Result<Self> {
+        let mut spec = RetrySpec::default();
+        if matches!(attr.meta, syn::Meta::Path(_)) {
+            return Ok(spec);
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("max") {
+                spec.max = meta.value()?.parse::<syn::LitInt>()?.base10_parse()?;
+            } else if meta.path.is_ident("delay_ms") {
+                spec.delay_ms = meta.value()?.parse::<syn::LitInt>()?.base10_parse()?;
+            } else if meta.path.is_ident("backoff") {
+                let backoff: Ident = meta.value()?.parse()?;
+                if !matches!(backoff.to_string().as_str(), "exp" | "fixed" | "none") {
+                    return Err(syn::Error::new_spanned(
+                        backoff,
+                        "expected `exp`, `fixed` or `none`",
+                    ));
+                }
+                spec.backoff = backoff;
+            } else {
+                return Err(meta.error("expected `max`, `backoff` or `delay_ms`"));
+            }
+            Ok(())
+        })?;
+        Ok(spec)
+    }
+
+    /// `algae::retry::RetryPolicy` constructor expression.
+    fn to_tokens(&self) -> TokenStream2 {
+        let max = self.max;
+        let delay_ms = self.delay_ms;
+        let delay = quote!(::core::time::Duration::from_millis(#delay_ms));
+        let backoff = match self.backoff.to_string().as_str() {
+            "exp" => quote!(algae::retry::Backoff::Exponential(#delay)),
+            "fixed" => quote!(algae::retry::Backoff::Fixed(#delay)),
+            _ => quote!(algae::retry::Backoff::None),
+        };
+        quote!(algae::retry::RetryPolicy::new(#max, #backoff))
+    }
+}
+
+/// The paths of a `#[derive(...)]` attribute.
+fn derive_paths(attr: &syn::Attribute) -> Result<Punctuated<syn::Path, Token![,]>> {
+    attr.parse_args_with(Punctuated::parse_terminated)
+}
+
+/// The derives of a generated family or root enum: `Debug`, `Clone` and
+/// `PartialEq`, then `extra` without repeats of those.
+fn enum_derives<'a>(extra: impl IntoIterator<Item = &'a syn::Path>) -> TokenStream2 {
+    let mut derives: Vec<syn::Path> = vec![
+        syn::parse_quote!(Debug),
+        syn::parse_quote!(Clone),
+        syn::parse_quote!(PartialEq),
+    ];
+    for path in extra {
+        let name = |p: &syn::Path| p.segments.last().map(|s| s.ident.to_string());
+        let builtin = derives[..3].iter().any(|d| name(d) == name(path));
+        let repeated = derives
+            .iter()
+            .any(|d| quote!(#d).to_string() == quote!(#path).to_string());
+        if !builtin && !repeated {
+            derives.push(path.clone());
+        }
+    }
+    quote!(#[derive(#(#derives),*)])
+}
+
+/// `algae::priority::Priority` for a `#[priority(...)]` argument.
+fn priority_tokens(priority: &Ident) -> TokenStream2 {
+    match priority.to_string().as_str() {
+        "high" => quote!(algae::priority::Priority::High),
+        "low" => quote!(algae::priority::Priority::Low),
+        _ => quote!(algae::priority::Priority::Normal),
+    }
 }
 
 impl Parse for OpLine {
     fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let attrs = OpAttrs::parse_outer(input)?;
         let family: Ident = input.parse()?;
+        let generics = if input.peek(Token![<]) {
+            let generics: syn::Generics = input.parse()?;
+            if let Some(param) = generics
+                .params
+                .iter()
+                .find(|p| !matches!(p, syn::GenericParam::Type(_)))
+            {
+                return Err(syn::Error::new_spanned(
+                    param,
+                    "only type parameters are supported on families",
+                ));
+            }
+            generics
+        } else {
+            syn::Generics::default()
+        };
         input.parse::<Token![::]>()?;
         let variant: Ident = input.parse()?;
 
@@ -97,7 +353,22 @@ impl Parse for OpLine {
             if content.is_empty() {
                 None
             } else {
-                Some(content.parse::<Type>()?)
+                let payload = content.parse::<Type>()?;
+                if let Type::Path(path) = &payload {
+                    if path
+                        .path
+                        .segments
+                        .last()
+                        .is_some_and(|s| s.ident == "Effectful")
+                    {
+                        return Err(syn::Error::new_spanned(
+                            &payload,
+                            "operations are `Clone` and cannot carry an `Effectful` directly; \
+                             wrap it in an `algae::higher::Computation`",
+                        ));
+                    }
+                }
+                Some(payload)
             }
         } else {
             None
@@ -107,49 +378,165 @@ impl Parse for OpLine {
         let ret: Type = input.parse()?;
 
         Ok(Self {
+            attrs,
             family,
+            generics,
             variant,
             payload,
             _arrow: arrow,
-            _ret: ret,
+            ret,
         })
     }
 }
 
-/// The whole macro input – optional root header plus list of OpLines separated by `;` or `,`.
+/// A family defined outside this macro call:  `use path::to::Family`
+struct UseLine {
+    path: syn::Path,
+}
+
+impl UseLine {
+    /// The root variant name: the last segment of the path.
+    fn family(&self) -> &Ident {
+        &self
+            .path
+            .segments
+            .last()
+            .expect("paths have at least one segment")
+            .ident
+    }
+}
+
+impl Parse for UseLine {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        input.parse::<Token![use]>()?;
+        let path: syn::Path = input.parse()?;
+        // `use cache::Cache<String, u64>` embeds an instance of a generic family
+        let mut modules = path.segments.iter().rev().skip(1);
+        if let Some(segment) = modules.find(|s| !s.arguments.is_empty()) {
+            return Err(syn::Error::new_spanned(
+                segment,
+                "generic arguments are only supported on the family in `use` lines",
+            ));
+        }
+        Ok(Self { path })
+    }
+}
+
+/// The whole macro input – optional inner attributes and headers plus list
+/// of OpLines and UseLines separated by `;`.
 struct EffectInput {
+    /// `#![derive(...)]` paths, added to every generated family and root enum.
+    derives: Vec<syn::Path>,
+    /// Other inner attributes, passed through to the same enums.
+    enum_attrs: Vec<syn::Attribute>,
     root_ident: Option<Ident>,
+    helpers: bool,
+    automock: bool,
+    typed: bool,
+    serde: bool,
+    proptest: bool,
+    fuzz: bool,
     lines: Punctuated<OpLine, Token![;]>, // accept `;`  – we strip trailing ones.
+    uses: Vec<UseLine>,
 }
 
 impl Parse for EffectInput {
     fn parse(input: ParseStream<'_>) -> Result<Self> {
-        // Try to parse optional "root EnumName;" header
-        let root_ident = if input.peek(syn::Ident) {
-            // Fork the input to check if this starts with "root"
+        let mut derives = Vec::new();
+        let mut enum_attrs = Vec::new();
+        for mut attr in input.call(syn::Attribute::parse_inner)? {
+            if attr.path().is_ident("derive") {
+                derives.extend(derive_paths(&attr)?);
+            } else {
+                // Emitted on each enum
+                attr.style = syn::AttrStyle::Outer;
+                enum_attrs.push(attr);
+            }
+        }
+
+        // Optional "root EnumName;", "helpers;", "automock;", "typed;",
+        // "serde;", "proptest;" and "fuzz;" headers, in any order
+        let mut root_ident = None;
+        let mut helpers = false;
+        let mut automock = false;
+        let mut typed = false;
+        let mut serde = false;
+        let mut proptest = false;
+        let mut fuzz = false;
+        loop {
+            // Fork the input so that family names are not mistaken for headers
             let fork = input.fork();
-            if let Ok(ident) = fork.parse::<Ident>() {
-                if ident.to_string().as_str() == "root" {
-                    // Consume the "root" keyword
-                    let _root_kw: Ident = input.parse()?;
-                    // Parse the root enum name
-                    let root_name: Ident = input.parse()?;
-                    // Consume the semicolon
-                    input.parse::<Token![;]>()?;
-                    Some(root_name)
-                } else {
-                    // This is just a regular effect line starting with Family::
-                    None
-                }
+            let Ok(ident) = fork.parse::<Ident>() else {
+                break;
+            };
+            if ident == "root" && root_ident.is_none() && fork.peek(syn::Ident) {
+                // Consume the "root" keyword
+                let _root_kw: Ident = input.parse()?;
+                // Parse the root enum name
+                let root_name: Ident = input.parse()?;
+                // Consume the semicolon
+                input.parse::<Token![;]>()?;
+                root_ident = Some(root_name);
+            } else if ident == "helpers" && !helpers && fork.peek(Token![;]) {
+                let _helpers_kw: Ident = input.parse()?;
+                input.parse::<Token![;]>()?;
+                helpers = true;
+            } else if ident == "automock" && !automock && fork.peek(Token![;]) {
+                let _automock_kw: Ident = input.parse()?;
+                input.parse::<Token![;]>()?;
+                automock = true;
+            } else if ident == "typed" && !typed && fork.peek(Token![;]) {
+                let _typed_kw: Ident = input.parse()?;
+                input.parse::<Token![;]>()?;
+                typed = true;
+            } else if ident == "serde" && !serde && fork.peek(Token![;]) {
+                let _serde_kw: Ident = input.parse()?;
+                input.parse::<Token![;]>()?;
+                serde = true;
+            } else if ident == "proptest" && !proptest && fork.peek(Token![;]) {
+                let _proptest_kw: Ident = input.parse()?;
+                input.parse::<Token![;]>()?;
+                proptest = true;
+            } else if ident == "fuzz" && !fuzz && fork.peek(Token![;]) {
+                let _fuzz_kw: Ident = input.parse()?;
+                input.parse::<Token![;]>()?;
+                fuzz = true;
             } else {
-                None
+                // This is just a regular effect line starting with Family::
+                break;
             }
-        } else {
-            None
-        };
+        }
 
-        let lines = Punctuated::<OpLine, Token![;]>::parse_terminated(input)?;
-        Ok(Self { root_ident, lines })
+        let mut lines = Punctuated::<OpLine, Token![;]>::new();
+        let mut uses = Vec::new();
+        while !input.is_empty() {
+            if input.peek(Token![use]) {
+                uses.push(input.parse::<UseLine>()?);
+                if input.is_empty() {
+                    break;
+                }
+                input.parse::<Token![;]>()?;
+                continue;
+            }
+            lines.push_value(input.parse::<OpLine>()?);
+            if input.is_empty() {
+                break;
+            }
+            lines.push_punct(input.parse::<Token![;]>()?);
+        }
+        Ok(Self {
+            derives,
+            enum_attrs,
+            root_ident,
+            helpers,
+            automock,
+            typed,
+            serde,
+            proptest,
+            fuzz,
+            lines,
+            uses,
+        })
     }
 }
 
@@ -207,14 +594,323 @@ impl Parse for EffectInput {
 /// Without custom root names, the above would cause a compilation error due to
 /// duplicate `Op` enum definitions.
 ///
+/// ## Embedding Families Defined Elsewhere
+///
+/// A `use` line adds a family that was declared in another `effect!` call,
+/// such as one of algae's standard families, to this root enum:
+///
+/// ```ignore
+/// # use algae::prelude::*;
+/// effect! {
+///     use algae::effects::progress::Progress;
+///     Report::Build (String) -> ();
+/// }
+///
+/// // Generates:
+/// // enum Report { Build(String) }
+/// // enum Op { Progress(algae::effects::progress::Progress), Report(Report) }
+/// ```
+///
+/// ## Derives and Attributes
+///
+/// The generated family and root enums derive `Debug`, `Clone` and
+/// `PartialEq`. Inner attributes at the top of the block are added to all of
+/// them: further derives, and attributes for those derives such as
+/// `#[serde(...)]`. A `#[derive(...)]` on an op line is added to that line's
+/// family only:
+///
+/// ```ignore
+/// effect! {
+///     #![derive(Eq, Hash, serde::Serialize)]
+///     #![serde(rename_all = "snake_case")]
+///     root AppOp;
+///     #[derive(PartialOrd, Ord)]
+///     Level::Set (u8) -> ();
+///     Console::Print (String) -> ();
+/// }
+/// ```
+///
+/// The root enum's derives need the families embedded with `use` to
+/// implement the same traits.
+///
+/// ## Generic Families
+///
+/// A family may take type parameters, repeated on each of its lines. The
+/// root enum takes the parameters of all its families; parameters of the
+/// same name are shared:
+///
+/// ```ignore
+/// effect! {
+///     root CacheOp;
+///     Cache<K, V>::Get (K) -> Option<V>;
+///     Cache<K, V>::Put ((K, V)) -> ();
+///     Log::Line (String) -> ();
+/// }
+///
+/// // Generates:
+/// // enum Cache<K, V> { Get(K), Put((K, V)) }
+/// // enum CacheOp<K, V> { Cache(Cache<K, V>), Log(Log) }
+///
+/// #[effectful(root = CacheOp<String, u64>)]
+/// fn hits(page: String) -> Option<u64> {
+///     perform!(CacheOp::cache_get(page))
+/// }
+/// ```
+///
+/// A `use` line may embed an instance of a generic family declared
+/// elsewhere, e.g. `use cache::Cache<String, u64>;`. Generic families cannot
+/// be combined with `automock;`.
+///
+/// ## Op Annotations
+///
+/// `#[retryable]` declares that an operation may be retried when its handler
+/// aborts. The policy is exposed through `algae::OpMeta::retry_policy` and
+/// applied by `algae::retry::RetryLayer`:
+///
+/// ```ignore
+/// effect! {
+///     #[retryable(max = 3, backoff = exp)]
+///     Http::Get (String) -> String;
+///     Http::Post ((String, String)) -> String;
+/// }
+/// ```
+///
+/// - `max` - retries after the first attempt (default `3`)
+/// - `backoff` - `exp`, `fixed` or `none` (default `exp`)
+/// - `delay_ms` - the fixed delay, or the first exponential delay (default `100`)
+///
+/// `#[mutates]` declares that an operation changes the state of its handler.
+/// It is exposed through `algae::OpMeta::mutates_state`, and
+/// `algae::eventsource::EventSourced` persists exactly these operations.
+///
+/// `#[priority(high)]` (or `normal`, `low`) declares how urgently an
+/// operation should be serviced when computations share handlers. It is
+/// exposed through `algae::OpMeta::priority` and used by
+/// `algae::priority::run_by_priority`.
+///
+/// `#[tag(...)]` attaches free-form names, exposed through
+/// `algae::OpMeta::tags` and `algae::OpMeta::has_tag`, so middleware can
+/// select operations by tag instead of by variant:
+///
+/// ```ignore
+/// effect! {
+///     #[tag(io, mutating)]
+///     File::Write ((String, Vec<u8>)) -> std::io::Result<()>;
+///     #[tag(io)]
+///     File::Read (String) -> std::io::Result<Vec<u8>>;
+/// }
+///
+/// assert!(Op::File(File::Read("a".into())).has_tag("io"));
+/// ```
+///
+/// ## Typed Helper Functions
+///
+/// With a `helpers;` header the macro also emits one module per family with a
+/// function per operation. Each returns an `algae::PerformsOne` that carries
+/// the declared return type, so `perform!` no longer relies on annotations at
+/// the call site and a mismatch is a compile error:
+///
+/// ```ignore
+/// effect! {
+///     helpers;
+///     Console::Print (String) -> ();
+///     Math::Add ((i32, i32)) -> i32;
+/// }
+///
+/// #[effectful]
+/// fn sum() -> i32 {
+///     perform!(console::print("adding".into()));
+///     perform!(math::add(2, 3))
+/// }
+/// ```
+///
+/// Module and function names are the snake_case family and variant names.
+/// Tuple payloads are spread into one argument per element.
+///
+/// ## Operation Constructors
+///
+/// The root enum always gets an associated function per operation that builds
+/// it, named after the snake_case family and variant. Arguments are spread
+/// like those of the helpers, and `String` arguments take anything
+/// `Into<String>`:
+///
+/// ```ignore
+/// effect! {
+///     Console::Print (String) -> ();
+///     Math::Add ((i32, i32)) -> i32;
+/// }
+///
+/// assert_eq!(Op::console_print("hi"), Op::Console(Console::Print("hi".into())));
+/// assert_eq!(Op::math_add(2, 3), Op::Math(Math::Add((2, 3))));
+/// ```
+///
+/// ## Typed Handlers
+///
+/// With a `typed;` header the macro also emits, per family and for the root,
+/// a `...Call<'a>` enum with one variant per operation that borrows its
+/// payload and carries an `algae::Respond` for its return type, and
+/// implements `algae::TypedOp`. An `algae::TypedHandler` matches on calls and
+/// can only answer with the declared type:
+///
+/// ```ignore
+/// effect! {
+///     typed;
+///     Console::Print (String) -> ();
+///     Console::ReadLine -> String;
+/// }
+///
+/// impl TypedHandler<Op> for Terminal {
+///     fn handle(&mut self, call: OpCall<'_>) -> Answer {
+///         match call {
+///             OpCall::Console(ConsoleCall::Print(msg, reply)) => reply.send(println!("{msg}")),
+///             OpCall::Console(ConsoleCall::ReadLine(reply)) => reply.send(read_line()),
+///         }
+///     }
+/// }
+///
+/// let name = greet().handle(Typed(Terminal)).run()?;
+/// ```
+///
+/// Families embedded with `use` must be typed too; algae's standard families
+/// are.
+///
+/// ## Serializable Operations
+///
+/// With a `serde;` header and algae's `serde` feature, the family and root
+/// enums derive serde's `Serialize` and `Deserialize`, and implement
+/// `algae::remote::SerdeOp`, which (de)serializes each operation's reply as
+/// its declared type. That is all `algae::remote::json::JsonCodec` needs to
+/// send the operations to a handler in another process:
+///
+/// ```ignore
+/// effect! {
+///     serde;
+///     Db::Get (String) -> Option<String>;
+///     Db::Put ((String, String)) -> ();
+/// }
+///
+/// let remote = TcpRemoteHandler::connect("127.0.0.1:7000", JsonCodec)?;
+/// let user = load_user(id).try_run_with(remote)?;
+/// ```
+///
+/// Payloads and return types must implement `Serialize` and
+/// `DeserializeOwned`. The calling crate needs no serde dependency: the
+/// derives go through algae's re-export. Families embedded with `use` must
+/// be declared with `serde;` too, and `serde;` cannot be combined with type
+/// parameters.
+///
+/// ## Generated Operations
+///
+/// With a `proptest;` header and algae's `proptest` feature, the family and
+/// root enums implement proptest's `Arbitrary`, generating every operation
+/// with arbitrary payloads, and `algae::property::DeclaredReply`, which tells
+/// each operation's declared reply type at runtime. Together they let
+/// `algae::property::check_handler_total` find the operations a handler
+/// declines or answers with the wrong type:
+///
+/// ```ignore
+/// effect! {
+///     proptest;
+///     Db::Get (String) -> Option<String>;
+///     Db::Put ((String, String)) -> ();
+/// }
+///
+/// #[test]
+/// fn db_handler_is_total() {
+///     check_handler_total::<Op, _>(DbHandler::in_memory());
+/// }
+/// ```
+///
+/// Payloads must implement `Arbitrary`. As with `serde;`, the calling crate
+/// needs no proptest dependency, families embedded with `use` must be
+/// declared with `proptest;` too, and type parameters are not supported.
+///
+/// ## Fuzzed Replies
+///
+/// A `fuzz;` header, also with the `proptest` feature, implements
+/// `algae::fuzz::FuzzReply`: each operation can make up an arbitrary reply of
+/// its declared type. `algae::fuzz::fuzz` runs a computation against such
+/// replies, to find the ones its logic does not expect:
+///
+/// ```ignore
+/// effect! {
+///     fuzz;
+///     Api::Quota -> u32;
+///     Api::Fetch (u32) -> Vec<u8>;
+/// }
+///
+/// fuzz(0..500, |fuzzer| sync_all().try_run_with(fuzzer)).unwrap();
+/// ```
+///
+/// Return types must implement proptest's `Arbitrary`. Families embedded
+/// with `use` must be declared with `fuzz;` too, and type parameters are not
+/// supported.
+///
+/// ## Higher-Order Operations
+///
+/// An operation can carry an effectful computation, wrapped in an
+/// `algae::higher::Computation` since operations are `Clone`. Handlers take
+/// it out and drive it, or `Effectful::elaborate` runs it in place of the
+/// operation:
+///
+/// ```ignore
+/// effect! {
+///     Tasks::Spawn (Computation<(), Op>) -> TaskId;
+///     Tx::Atomically (Computation<u64, Op>) -> u64;
+/// }
+/// ```
+///
+/// ## Auto-Mocks
+///
+/// With an `automock;` header the macro also emits `<Root>AutoMock` (e.g.
+/// `OpAutoMock`), a partial handler with one `algae::mock::OpMock` field per
+/// operation. Fields are named like the helper functions, prefixed with the
+/// family name when two families declare operations of the same name:
+///
+/// ```ignore
+/// effect! {
+///     automock;
+///     Console::Print (String) -> ();
+///     Console::ReadLine -> String;
+/// }
+///
+/// let mock = OpAutoMock::new();
+/// mock.read_line.returns_iter(["Alice"]);
+/// let printed = mock.print.capture();
+/// greet().handle(mock.clone()).run()?;
+/// assert_eq!(printed.values(), ["Hello, Alice!"]);
+/// ```
+///
+/// Operations without a response left, and those of families embedded with
+/// `use`, are declined and listed by `unmatched()`.
+///
 /// # Generated Code
 ///
 /// For each effect family, this macro generates:
 /// - A family enum with variants for each operation
-/// - A unified root enum (default `Op` or custom name) that contains all families
+/// - A unified root enum (default `Op` or custom name) that contains all families,
+///   generic over the type parameters of generic families
 /// - `From` implementations to convert family enums to the root enum
-/// - `Default` implementations where applicable
-/// - Debug derive implementations
+/// - `algae::Contains` implementations so family-generic handlers can project
+///   their operations out of the root enum
+/// - `TryFrom<Root>` and `TryFrom<&Root>` for each family (and its references),
+///   failing with the root operation if it belongs to another family
+/// - `algae::OpMeta` implementations exposing op annotations and the
+///   family and operation names (`"Console"`, `"Print"`, `"Console.Print"`)
+/// - `algae::inline::ReplyBound` implementations giving the size of the
+///   largest declared return type
+/// - `From<algae::PerformsOne<_, Root>>` for the root enum
+/// - A constructor function per operation on the root enum
+/// - With `helpers;`, a module of typed helper functions per family
+/// - With `automock;`, a `<Root>AutoMock` handler
+/// - With `typed;`, `<Family>Call` and `<Root>Call` enums and
+///   `algae::TypedOp` implementations
+/// - With `serde;`, serde derives and `algae::remote::SerdeOp`
+///   implementations
+/// - With `proptest;`, proptest `Arbitrary` and
+///   `algae::property::DeclaredReply` implementations
+/// - With `fuzz;`, `algae::fuzz::FuzzReply` implementations
+/// - `Debug`, `Clone` and `PartialEq` derives, plus those declared in the block
 /// - A hidden sentry enum to detect duplicate root names
 ///
 /// # Examples
@@ -289,11 +985,25 @@ impl Parse for EffectInput {
 /// ```
 #[proc_macro]
 pub fn effect(item: TokenStream) -> TokenStream {
-    let EffectInput { root_ident, lines } = parse_macro_input!(item as EffectInput);
+    let EffectInput {
+        derives,
+        enum_attrs,
+        root_ident,
+        helpers,
+        automock,
+        typed,
+        serde,
+        proptest,
+        fuzz,
+        lines,
+        uses,
+    } = parse_macro_input!(item as EffectInput);
 
     // Determine the root enum name (default to "Op")
     let root_ident = root_ident.unwrap_or_else(|| Ident::new("Op", proc_macro2::Span::call_site()));
 
+    let root_request = quote::format_ident!("{}Call", root_ident);
+
     // Generate sentry enum to catch duplicate root names
     let sentry_name = format!("__ALGAE_EFFECT_SENTRY_FOR_{root_ident}");
     let sentry_ident = Ident::new(&sentry_name, proc_macro2::Span::call_site());
@@ -303,30 +1013,299 @@ pub fn effect(item: TokenStream) -> TokenStream {
     struct VariantInfo {
         variant: Ident,
         payload: Option<Type>,
+        ret: Type,
+        retry: Option<TokenStream2>,
+        mutates: bool,
+        tags: Vec<String>,
+        priority: Option<TokenStream2>,
     }
 
-    let mut families: BTreeMap<String, (Ident, Vec<VariantInfo>)> = BTreeMap::new();
+    let mut families: BTreeMap<String, (Ident, syn::Generics, Vec<VariantInfo>)> = BTreeMap::new();
+    // `#[derive(...)]`s on op lines, per family
+    let mut family_derives: BTreeMap<String, Vec<syn::Path>> = BTreeMap::new();
+    // The root enum takes the type parameters of all families, in order of
+    // appearance; parameters of the same name are shared.
+    let mut root_params: Vec<syn::TypeParam> = Vec::new();
 
     for l in lines {
+        for param in l.generics.type_params() {
+            match root_params.iter().find(|p| p.ident == param.ident) {
+                Some(seen) if quote!(#seen).to_string() != quote!(#param).to_string() => {
+                    return syn::Error::new_spanned(
+                        param,
+                        format!("`{}` is declared with other bounds elsewhere", param.ident),
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+                Some(_) => {}
+                None => root_params.push(param.clone()),
+            }
+        }
         let entry = families
             .entry(l.family.to_string())
-            .or_insert_with(|| (l.family.clone(), Vec::new()));
-        entry.1.push(VariantInfo {
+            .or_insert_with(|| (l.family.clone(), l.generics.clone(), Vec::new()));
+        let (declared, generics) = (&entry.1, &l.generics);
+        if quote!(#declared).to_string() != quote!(#generics).to_string() {
+            return syn::Error::new_spanned(
+                &l.family,
+                format!(
+                    "`{}` is declared with other type parameters on another line; \
+                     each line of a family repeats its parameters",
+                    l.family
+                ),
+            )
+            .to_compile_error()
+            .into();
+        }
+        family_derives
+            .entry(l.family.to_string())
+            .or_default()
+            .extend(l.attrs.derives);
+        entry.2.push(VariantInfo {
             variant: l.variant,
             payload: l.payload,
+            ret: l.ret,
+            retry: l.attrs.retryable.as_ref().map(RetrySpec::to_tokens),
+            mutates: l.attrs.mutates,
+            tags: l.attrs.tags,
+            priority: l.attrs.priority.as_ref().map(priority_tokens),
         });
     }
 
+    if serde && !root_params.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`serde;` does not support families with type parameters",
+        )
+        .to_compile_error()
+        .into();
+    }
+    for (enabled, header) in [(proptest, "proptest"), (fuzz, "fuzz")] {
+        if enabled && !root_params.is_empty() {
+            return syn::Error::new(
+                proc_macro2::Span::call_site(),
+                format!("`{header};` does not support families with type parameters"),
+            )
+            .to_compile_error()
+            .into();
+        }
+    }
+    // With `serde;`, the enums derive serde's traits through algae's
+    // re-export, so the calling crate needs no serde dependency of its own
+    let mut derives = derives;
+    let mut enum_attrs = enum_attrs;
+    if serde {
+        derives.push(syn::parse_quote!(algae::__private::serde::Serialize));
+        derives.push(syn::parse_quote!(algae::__private::serde::Deserialize));
+        enum_attrs.push(syn::parse_quote!(#[serde(crate = "algae::__private::serde")]));
+    }
+
+    let root_generics: syn::Generics = syn::parse_quote!(<#(#root_params),*>);
+    let (root_impl, root_ty_generics, _) = root_generics.split_for_impl();
+    let root_ty = quote!(#root_ident #root_ty_generics);
+    let root_param_idents: Vec<&Ident> = root_params.iter().map(|p| &p.ident).collect();
+
+    // ── 1b. Optional `<Root>AutoMock` with one field per declared op ─────────
+    let auto_mock = if automock {
+        if !root_params.is_empty() {
+            return syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "`automock;` does not support families with type parameters",
+            )
+            .to_compile_error()
+            .into();
+        }
+        let ops: Vec<(&Ident, &VariantInfo)> = families
+            .values()
+            .flat_map(|(family, _, variants)| variants.iter().map(move |v| (family, v)))
+            .collect();
+        // Ops whose snake_case names collide are prefixed with their family
+        let mut name_counts: BTreeMap<String, usize> = BTreeMap::new();
+        for (_, v) in &ops {
+            *name_counts
+                .entry(snake_case_ident(&v.variant).to_string())
+                .or_default() += 1;
+        }
+        let ops: Vec<MockOp<'_>> = ops
+            .into_iter()
+            .map(|(family, v)| {
+                let mut field = snake_case_ident(&v.variant);
+                if name_counts[&field.to_string()] > 1 {
+                    let unraw =
+                        |ident: Ident| ident.to_string().trim_start_matches("r#").to_owned();
+                    field = quote::format_ident!(
+                        "{}_{}",
+                        unraw(snake_case_ident(family)),
+                        unraw(field)
+                    );
+                }
+                MockOp {
+                    family,
+                    variant: &v.variant,
+                    payload: v.payload.as_ref(),
+                    ret: &v.ret,
+                    field,
+                }
+            })
+            .collect();
+        auto_mock(&root_ident, &ops)
+    } else {
+        TokenStream2::new()
+    };
+
     // ── 2.  Generate one enum per family ─────────────────────────────────────
     let mut family_enums = TokenStream2::new();
     let mut op_variants = TokenStream2::new();
     let mut impl_froms = TokenStream2::new();
+    let mut meta_arms = TokenStream2::new();
+    let mut mutates_arms = TokenStream2::new();
+    let mut default_reply_arms = TokenStream2::new();
+    let mut tags_arms = TokenStream2::new();
+    let mut priority_arms = TokenStream2::new();
+    let mut family_name_arms = TokenStream2::new();
+    let mut root_name_arms = TokenStream2::new();
+    let mut root_qualified_arms = TokenStream2::new();
+    let mut family_arms = TokenStream2::new();
+    let mut reply_bounds = Vec::new();
+    let mut helper_mods = TokenStream2::new();
+    let mut constructors = TokenStream2::new();
+    let mut typed_items = TokenStream2::new();
+    let mut request_variants = TokenStream2::new();
+    let mut request_arms = TokenStream2::new();
+    let mut serde_items = TokenStream2::new();
+    let mut serialize_arms = TokenStream2::new();
+    let mut deserialize_arms = TokenStream2::new();
+    let mut proptest_items = TokenStream2::new();
+    let mut strategies = Vec::new();
+    let mut declared_arms = TokenStream2::new();
+    let mut fuzz_items = TokenStream2::new();
+    let mut fuzz_arms = TokenStream2::new();
 
-    for (_fam_name_str, (family_ident, variants)) in families {
+    for (fam_name_str, (family_ident, generics, variants)) in families {
+        let (family_impl, family_ty_generics, _) = generics.split_for_impl();
+        let family_ty = quote!(#family_ident #family_ty_generics);
         // each variant
         let mut variant_tokens = TokenStream2::new();
+        let mut retry_arms = TokenStream2::new();
+        let mut mutating = Vec::new();
+        let mut family_default_arms = TokenStream2::new();
+        let mut tag_arms = TokenStream2::new();
+        let mut priority_fn_arms = TokenStream2::new();
+        let mut helper_fns = TokenStream2::new();
+        let mut op_name_arms = TokenStream2::new();
+        let mut op_qualified_arms = TokenStream2::new();
+        let request_ident =
+            quote::format_ident!("{}Call", family_ident.to_string().trim_start_matches("r#"));
+        let mut family_request_variants = TokenStream2::new();
+        let mut family_request_arms = TokenStream2::new();
+        let mut family_serialize_arms = TokenStream2::new();
+        let mut family_deserialize_arms = TokenStream2::new();
+        let mut family_strategies = Vec::new();
+        let mut family_declared_arms = TokenStream2::new();
+        let mut family_fuzz_arms = TokenStream2::new();
         for v in &variants {
-            let VariantInfo { variant, payload } = v;
+            let VariantInfo {
+                variant,
+                payload,
+                ret,
+                retry,
+                mutates,
+                tags,
+                priority,
+            } = v;
+            if helpers {
+                helper_fns.extend(helper_fn(
+                    &root_ident,
+                    &root_generics,
+                    &family_ident,
+                    variant,
+                    payload,
+                    ret,
+                ));
+            }
+            constructors.extend(constructor_fn(&family_ident, variant, payload));
+            if let Some(ty) = payload {
+                family_request_variants.extend(quote! { #variant(&'a #ty, algae::Respond<#ret>), });
+                family_request_arms.extend(quote! {
+                    #family_ident::#variant(payload) => {
+                        #request_ident::#variant(payload, algae::Respond::__new())
+                    }
+                });
+            } else {
+                family_request_variants.extend(quote! { #variant(algae::Respond<#ret>), });
+                family_request_arms.extend(quote! {
+                    #family_ident::#variant => #request_ident::#variant(algae::Respond::__new()),
+                });
+            }
+            let name = variant.to_string().trim_start_matches("r#").to_owned();
+            let qualified = format!(
+                "{}.{name}",
+                family_ident.to_string().trim_start_matches("r#")
+            );
+            op_name_arms.extend(quote! { #family_ident::#variant { .. } => #name, });
+            family_serialize_arms.extend(quote! {
+                #family_ident::#variant { .. } => match reply.downcast_ref::<#ret>() {
+                    ::core::option::Option::Some(reply) => {
+                        algae::__private::serde::Serialize::serialize(reply, serializer)
+                    }
+                    ::core::option::Option::None => ::core::result::Result::Err(
+                        <__S::Error as algae::__private::serde::ser::Error>::custom(
+                            ::core::concat!("reply to ", #qualified, " is not a `", ::core::stringify!(#ret), "`"),
+                        ),
+                    ),
+                },
+            });
+            family_deserialize_arms.extend(quote! {
+                #family_ident::#variant { .. } => {
+                    <#ret as algae::__private::serde::Deserialize>::deserialize(deserializer)
+                        .map(|reply| algae::__private::Box::new(reply) as algae::__private::Box<dyn ::core::any::Any + Send>)
+                }
+            });
+            op_qualified_arms.extend(quote! { #family_ident::#variant { .. } => #qualified, });
+            family_strategies.push(match payload {
+                Some(ty) => quote! {
+                    algae::__private::proptest::strategy::Strategy::prop_map(
+                        algae::__private::proptest::arbitrary::any::<#ty>(),
+                        #family_ident::#variant,
+                    )
+                },
+                None => quote! {
+                    algae::__private::proptest::strategy::Just(#family_ident::#variant)
+                },
+            });
+            family_declared_arms.extend(quote! {
+                #family_ident::#variant { .. } => (
+                    ::core::any::TypeId::of::<#ret>(),
+                    ::core::stringify!(#ret),
+                ),
+            });
+            family_fuzz_arms.extend(quote! {
+                #family_ident::#variant { .. } => algae::fuzz::FuzzedReply::arbitrary::<#ret>(runner),
+            });
+            if let Some(policy) = retry {
+                retry_arms.extend(quote! {
+                    #family_ident::#variant { .. } => ::core::option::Option::Some(#policy),
+                });
+            }
+            if *mutates {
+                mutating.push(quote! { #family_ident::#variant { .. } });
+            }
+            family_default_arms.extend(quote! {
+                #family_ident::#variant { .. } => {
+                    (&algae::__private::DeclaredReply::<#ret>::default()).default_reply()
+                }
+            });
+            if !tags.is_empty() {
+                tag_arms.extend(quote! {
+                    #family_ident::#variant { .. } => &[#(#tags),*],
+                });
+            }
+            if let Some(priority) = priority {
+                priority_fn_arms.extend(quote! {
+                    #family_ident::#variant { .. } => #priority,
+                });
+            }
             if let Some(ty) = payload {
                 variant_tokens.extend(quote! { #variant(#ty), });
             } else {
@@ -334,24 +1313,432 @@ pub fn effect(item: TokenStream) -> TokenStream {
             }
         }
 
+        let family_derive = enum_derives(derives.iter().chain(&family_derives[&fam_name_str]));
         family_enums.extend(quote! {
-            #[derive(Debug, Clone, PartialEq)]
-            pub enum #family_ident {
+            #family_derive
+            #(#enum_attrs)*
+            pub enum #family_ident #generics {
                 #variant_tokens
             }
         });
 
-        // RootEnum::Family(Family)
-        op_variants.extend(quote! { #family_ident(#family_ident), });
-
-        impl_froms.extend(quote! {
-            impl From<#family_ident> for #root_ident {
-                fn from(f: #family_ident) -> Self { #root_ident::#family_ident(f) }
+        // Metadata from op annotations; families without any keep the defaults.
+        let retry_fn = if retry_arms.is_empty() {
+            TokenStream2::new()
+        } else {
+            quote! {
+                fn retry_policy(&self) -> ::core::option::Option<algae::retry::RetryPolicy> {
+                    #[allow(unreachable_patterns)]
+                    match self {
+                        #retry_arms
+                        _ => ::core::option::Option::None,
+                    }
+                }
             }
-        });
+        };
+        let mutates_fn = if mutating.is_empty() {
+            TokenStream2::new()
+        } else {
+            quote! {
+                fn mutates_state(&self) -> bool {
+                    matches!(self, #(#mutating)|*)
+                }
+            }
+        };
+        let default_reply_fn = if family_default_arms.is_empty() {
+            TokenStream2::new()
+        } else {
+            quote! {
+                fn default_reply(
+                    &self,
+                ) -> ::core::option::Option<
+                    algae::__private::Box<dyn ::core::any::Any + ::core::marker::Send>,
+                > {
+                    #[allow(unused_imports)]
+                    use algae::__private::{DefaultedReply as _, UndefaultedReply as _};
+                    match self {
+                        #family_default_arms
+                    }
+                }
+            }
+        };
+        let tags_fn = if tag_arms.is_empty() {
+            TokenStream2::new()
+        } else {
+            quote! {
+                fn tags(&self) -> &'static [&'static str] {
+                    #[allow(unreachable_patterns)]
+                    match self {
+                        #tag_arms
+                        _ => &[],
+                    }
+                }
+            }
+        };
+        let priority_fn = if priority_fn_arms.is_empty() {
+            TokenStream2::new()
+        } else {
+            quote! {
+                fn priority(&self) -> algae::priority::Priority {
+                    #[allow(unreachable_patterns)]
+                    match self {
+                        #priority_fn_arms
+                        _ => algae::priority::Priority::Normal,
+                    }
+                }
+            }
+        };
+        let rets = variants.iter().map(|v| &v.ret);
+        let variant_replies = variants.iter().map(|v| {
+            let hash = variant_hash(&v.variant);
+            let ret = &v.ret;
+            quote! {
+                impl #family_impl algae::__private::VariantReply<#hash, #ret> for #family_ty {}
+            }
+        });
+        let family_name = family_ident.to_string().trim_start_matches("r#").to_owned();
+        family_enums.extend(quote! {
+            impl #family_impl algae::OpMeta for #family_ty {
+                #retry_fn
+                #mutates_fn
+                #default_reply_fn
+                #tags_fn
+                #priority_fn
+
+                fn family(&self) -> &'static str {
+                    #family_name
+                }
+
+                fn name(&self) -> &'static str {
+                    match self {
+                        #op_name_arms
+                    }
+                }
+
+                fn qualified_name(&self) -> &'static str {
+                    match self {
+                        #op_qualified_arms
+                    }
+                }
+            }
+
+            impl #family_impl algae::inline::ReplyBound for #family_ty {
+                const MAX_REPLY_SIZE: usize = algae::inline::max_size(&[
+                    #(::core::mem::size_of::<#rets>()),*
+                ]);
+            }
+
+            #(#variant_replies)*
+        });
+
+        if serde {
+            serde_items.extend(serde_op_impl(
+                &family_ty,
+                &family_serialize_arms,
+                &family_deserialize_arms,
+            ));
+        }
+
+        if proptest {
+            proptest_items.extend(proptest_impls(
+                &family_ty,
+                &family_strategies,
+                &family_declared_arms,
+            ));
+        }
+
+        if fuzz {
+            fuzz_items.extend(fuzz_reply_impl(&family_ty, &family_fuzz_arms));
+        }
+
+        if helpers {
+            let module = snake_case_ident(&family_ident);
+            let doc = format!("Typed helpers for the `{family_ident}` operations.");
+            helper_mods.extend(quote! {
+                #[doc = #doc]
+                #[allow(dead_code)]
+                pub mod #module {
+                    #[allow(unused_imports)]
+                    use super::*;
+
+                    #helper_fns
+                }
+            });
+        }
+
+        // RootEnum::Family(Family)
+        op_variants.extend(quote! { #family_ident(#family_ty), });
+        meta_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::retry_policy(f),
+        });
+        mutates_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::mutates_state(f),
+        });
+        default_reply_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::default_reply(f),
+        });
+        tags_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::tags(f),
+        });
+        priority_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::priority(f),
+        });
+        family_name_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::family(f),
+        });
+        root_name_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::name(f),
+        });
+        root_qualified_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::qualified_name(f),
+        });
+        family_arms.extend(quote! { #root_ident::#family_ident(f) => f, });
+        serialize_arms.extend(quote! {
+            #root_ident::#family_ident(f) => {
+                algae::remote::SerdeOp::serialize_reply(f, reply, serializer)
+            }
+        });
+        deserialize_arms.extend(quote! {
+            #root_ident::#family_ident(f) => {
+                algae::remote::SerdeOp::deserialize_reply(f, deserializer)
+            }
+        });
+        strategies.push(quote! {
+            algae::__private::proptest::strategy::Strategy::prop_map(
+                algae::__private::proptest::arbitrary::any::<#family_ty>(),
+                #root_ident::#family_ident,
+            )
+        });
+        declared_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::property::DeclaredReply::declared_reply(f),
+        });
+        fuzz_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::fuzz::FuzzReply::fuzz_reply(f, runner),
+        });
+        reply_bounds.push(family_ty.clone());
+        if typed {
+            // Only families with payloads borrow from the operation
+            let request_generics = if variants.iter().any(|v| v.payload.is_some()) {
+                with_lifetime(&generics)
+            } else {
+                generics.clone()
+            };
+            let (_, request_ty_generics, _) = request_generics.split_for_impl();
+            let doc = format!("A `{family_ident}` operation as seen by an `algae::TypedHandler`.");
+            typed_items.extend(quote! {
+                #[doc = #doc]
+                #[derive(Debug)]
+                pub enum #request_ident #request_generics {
+                    #family_request_variants
+                }
+
+                impl #family_impl algae::TypedOp for #family_ty {
+                    type Call<'a> = #request_ident #request_ty_generics where Self: 'a;
+
+                    fn as_call(&self) -> Self::Call<'_> {
+                        match self {
+                            #family_request_arms
+                        }
+                    }
+                }
+            });
+            request_variants.extend(quote! {
+                #family_ident(<#family_ty as algae::TypedOp>::Call<'a>),
+            });
+            request_arms.extend(quote! {
+                #root_ident::#family_ident(f) => {
+                    #root_request::#family_ident(algae::TypedOp::as_call(f))
+                }
+            });
+        }
+        impl_froms.extend(family_impls(
+            &root_ident,
+            &root_generics,
+            &family_ident,
+            &family_ty,
+        ));
+    }
+
+    // Families declared elsewhere only contribute a root variant and impls.
+    for used in &uses {
+        let family_ident = used.family();
+        let path = &used.path;
+        op_variants.extend(quote! { #family_ident(#path), });
+        meta_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::retry_policy(f),
+        });
+        mutates_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::mutates_state(f),
+        });
+        default_reply_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::default_reply(f),
+        });
+        tags_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::tags(f),
+        });
+        priority_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::priority(f),
+        });
+        family_name_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::family(f),
+        });
+        root_name_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::name(f),
+        });
+        root_qualified_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::OpMeta::qualified_name(f),
+        });
+        family_arms.extend(quote! { #root_ident::#family_ident(f) => f, });
+        serialize_arms.extend(quote! {
+            #root_ident::#family_ident(f) => {
+                algae::remote::SerdeOp::serialize_reply(f, reply, serializer)
+            }
+        });
+        deserialize_arms.extend(quote! {
+            #root_ident::#family_ident(f) => {
+                algae::remote::SerdeOp::deserialize_reply(f, deserializer)
+            }
+        });
+        strategies.push(quote! {
+            algae::__private::proptest::strategy::Strategy::prop_map(
+                algae::__private::proptest::arbitrary::any::<#path>(),
+                #root_ident::#family_ident,
+            )
+        });
+        declared_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::property::DeclaredReply::declared_reply(f),
+        });
+        fuzz_arms.extend(quote! {
+            #root_ident::#family_ident(f) => algae::fuzz::FuzzReply::fuzz_reply(f, runner),
+        });
+        reply_bounds.push(quote!(#path));
+        if typed {
+            request_variants.extend(quote! {
+                #family_ident(<#path as algae::TypedOp>::Call<'a>),
+            });
+            request_arms.extend(quote! {
+                #root_ident::#family_ident(f) => {
+                    #root_request::#family_ident(algae::TypedOp::as_call(f))
+                }
+            });
+        }
+        impl_froms.extend(family_impls(
+            &root_ident,
+            &root_generics,
+            family_ident,
+            &quote!(#path),
+        ));
+    }
+
+    // The root enum delegates metadata to the family enums.
+    let root_meta = if meta_arms.is_empty() {
+        quote! { impl algae::OpMeta for #root_ident {} }
+    } else {
+        quote! {
+            impl #root_impl algae::OpMeta for #root_ty {
+                fn retry_policy(&self) -> ::core::option::Option<algae::retry::RetryPolicy> {
+                    match self {
+                        #meta_arms
+                    }
+                }
+
+                fn mutates_state(&self) -> bool {
+                    match self {
+                        #mutates_arms
+                    }
+                }
+
+                fn default_reply(
+                    &self,
+                ) -> ::core::option::Option<
+                    algae::__private::Box<dyn ::core::any::Any + ::core::marker::Send>,
+                > {
+                    match self {
+                        #default_reply_arms
+                    }
+                }
+
+                fn tags(&self) -> &'static [&'static str] {
+                    match self {
+                        #tags_arms
+                    }
+                }
+
+                fn priority(&self) -> algae::priority::Priority {
+                    match self {
+                        #priority_arms
+                    }
+                }
+
+                fn family(&self) -> &'static str {
+                    match self {
+                        #family_name_arms
+                    }
+                }
+
+                fn name(&self) -> &'static str {
+                    match self {
+                        #root_name_arms
+                    }
+                }
+
+                fn qualified_name(&self) -> &'static str {
+                    match self {
+                        #root_qualified_arms
+                    }
+                }
+            }
+        }
+    };
+
+    if typed && !request_arms.is_empty() {
+        let doc = format!("A `{root_ident}` operation as seen by an `algae::TypedHandler`.");
+        let request_generics = with_lifetime(&root_generics);
+        let (_, request_ty_generics, _) = request_generics.split_for_impl();
+        let outlives = if root_params.is_empty() {
+            TokenStream2::new()
+        } else {
+            quote!(where #(#root_param_idents: 'a),*)
+        };
+        typed_items.extend(quote! {
+            #[doc = #doc]
+            #[derive(Debug)]
+            pub enum #root_request #request_generics #outlives {
+                #request_variants
+            }
+
+            impl #root_impl algae::TypedOp for #root_ty {
+                type Call<'a> = #root_request #request_ty_generics where Self: 'a;
+
+                fn as_call(&self) -> Self::Call<'_> {
+                    match self {
+                        #request_arms
+                    }
+                }
+            }
+        });
+    }
+
+    let family_match = if family_arms.is_empty() {
+        quote! { match *self {} }
+    } else {
+        quote! { match self { #family_arms } }
+    };
+
+    if serde {
+        serde_items.extend(serde_op_impl(&root_ty, &serialize_arms, &deserialize_arms));
+    }
+
+    if proptest && !strategies.is_empty() {
+        proptest_items.extend(proptest_impls(&root_ty, &strategies, &declared_arms));
+    }
+
+    if fuzz {
+        fuzz_items.extend(fuzz_reply_impl(&root_ty, &fuzz_arms));
     }
 
     // ── 3.  Root enum (configurable name) ────────────────────────────────────
+    let root_derive = enum_derives(&derives);
 
     let output = quote! {
         // Sentry enum to detect duplicate root names in same module
@@ -361,17 +1748,439 @@ pub fn effect(item: TokenStream) -> TokenStream {
 
         #family_enums
 
-        #[derive(Debug, Clone, PartialEq)]
-        pub enum #root_ident {
+        #root_derive
+        #(#enum_attrs)*
+        pub enum #root_ident #root_generics {
             #op_variants
         }
 
         #impl_froms
+
+        #root_meta
+
+        impl #root_impl algae::inline::ReplyBound for #root_ty {
+            const MAX_REPLY_SIZE: usize = algae::inline::max_size(&[
+                #(<#reply_bounds as algae::inline::ReplyBound>::MAX_REPLY_SIZE),*
+            ]);
+        }
+
+        impl #root_impl algae::FamilyOp for #root_ty
+        where
+            #(#root_param_idents: 'static),*
+        {
+            fn family_op(&self) -> &dyn ::core::any::Any {
+                #family_match
+            }
+        }
+
+        impl<__R, #(#root_params),*> From<algae::PerformsOne<__R, #root_ty>> for #root_ty {
+            fn from(p: algae::PerformsOne<__R, #root_ty>) -> Self { p.into_op() }
+        }
+
+        #[allow(dead_code)]
+        impl #root_impl #root_ty {
+            #constructors
+        }
+
+        #helper_mods
+
+        #typed_items
+
+        #serde_items
+
+        #proptest_items
+
+        #fuzz_items
+
+        #auto_mock
     };
 
     output.into()
 }
 
+/// `impl algae::remote::SerdeOp` for a family or root enum, from match arms
+/// over `self` that use `reply`, `serializer` and `deserializer`.
+fn serde_op_impl(
+    ty: &TokenStream2,
+    serialize_arms: &TokenStream2,
+    deserialize_arms: &TokenStream2,
+) -> TokenStream2 {
+    let (serialize_match, deserialize_match) = if serialize_arms.is_empty() {
+        (quote! { match *self {} }, quote! { match *self {} })
+    } else {
+        (
+            quote! { match self { #serialize_arms } },
+            quote! { match self { #deserialize_arms } },
+        )
+    };
+    quote! {
+        impl algae::remote::SerdeOp for #ty {
+            fn serialize_reply<__S: algae::__private::serde::Serializer>(
+                &self,
+                reply: &(dyn ::core::any::Any + Send),
+                serializer: __S,
+            ) -> ::core::result::Result<__S::Ok, __S::Error> {
+                #serialize_match
+            }
+
+            fn deserialize_reply<'de, __D: algae::__private::serde::Deserializer<'de>>(
+                &self,
+                deserializer: __D,
+            ) -> ::core::result::Result<
+                algae::__private::Box<dyn ::core::any::Any + Send>,
+                __D::Error,
+            > {
+                #deserialize_match
+            }
+        }
+    }
+}
+
+/// `impl Arbitrary` and `impl algae::property::DeclaredReply` for a family or
+/// root enum, choosing uniformly between `strategies`, from match arms over
+/// `self` for `declared_reply`.
+fn proptest_impls(
+    ty: &TokenStream2,
+    strategies: &[TokenStream2],
+    declared_arms: &TokenStream2,
+) -> TokenStream2 {
+    quote! {
+        impl algae::__private::proptest::arbitrary::Arbitrary for #ty {
+            type Parameters = ();
+            type Strategy = algae::__private::proptest::strategy::BoxedStrategy<Self>;
+
+            fn arbitrary_with(_: ()) -> Self::Strategy {
+                algae::__private::proptest::strategy::Strategy::boxed(
+                    algae::__private::proptest::strategy::Union::new([
+                        #(algae::__private::proptest::strategy::Strategy::boxed(#strategies)),*
+                    ]),
+                )
+            }
+        }
+
+        impl algae::property::DeclaredReply for #ty {
+            fn declared_reply(&self) -> (::core::any::TypeId, &'static str) {
+                match self {
+                    #declared_arms
+                }
+            }
+        }
+    }
+}
+
+/// `impl algae::fuzz::FuzzReply` for a family or root enum, from match arms
+/// over `self` that use `runner`.
+fn fuzz_reply_impl(ty: &TokenStream2, arms: &TokenStream2) -> TokenStream2 {
+    let body = if arms.is_empty() {
+        quote! { match *self {} }
+    } else {
+        quote! { match self { #arms } }
+    };
+    quote! {
+        impl algae::fuzz::FuzzReply for #ty {
+            fn fuzz_reply(
+                &self,
+                runner: &mut algae::__private::proptest::test_runner::TestRunner,
+            ) -> algae::fuzz::FuzzedReply {
+                #body
+            }
+        }
+    }
+}
+
+/// `family::variant(args…) -> algae::PerformsOne<Ret, Root>` for one op line.
+///
+/// Tuple payloads are spread into one argument per element.
+fn helper_fn(
+    root_ident: &Ident,
+    root_generics: &syn::Generics,
+    family_ident: &Ident,
+    variant: &Ident,
+    payload: &Option<Type>,
+    ret: &Type,
+) -> TokenStream2 {
+    let name = snake_case_ident(variant);
+    let doc = format!("Performs `{family_ident}::{variant}`.");
+    let (params, construct) = match payload {
+        None => (quote!(), quote!(#family_ident::#variant)),
+        Some(Type::Tuple(tuple)) => {
+            let args: Vec<Ident> = (0..tuple.elems.len())
+                .map(|i| quote::format_ident!("arg{}", i))
+                .collect();
+            let tys = tuple.elems.iter();
+            let value = if args.len() == 1 {
+                quote!((#(#args,)*))
+            } else {
+                quote!((#(#args),*))
+            };
+            (
+                quote!(#(#args: #tys),*),
+                quote!(#family_ident::#variant(#value)),
+            )
+        }
+        Some(ty) => (
+            quote!(payload: #ty),
+            quote!(#family_ident::#variant(payload)),
+        ),
+    };
+    let (fn_generics, root_ty_generics, _) = root_generics.split_for_impl();
+    quote! {
+        #[doc = #doc]
+        pub fn #name #fn_generics(#params) -> algae::PerformsOne<#ret, #root_ident #root_ty_generics> {
+            algae::PerformsOne::new(#root_ident::from(#construct))
+        }
+    }
+}
+
+/// `Root::family_variant(args…) -> Root` for one op line.
+///
+/// Tuple payloads are spread into one argument per element, and `String`
+/// arguments take anything `Into<String>`.
+fn constructor_fn(family_ident: &Ident, variant: &Ident, payload: &Option<Type>) -> TokenStream2 {
+    let unraw = |ident: Ident| ident.to_string().trim_start_matches("r#").to_owned();
+    let name = quote::format_ident!(
+        "{}_{}",
+        unraw(snake_case_ident(family_ident)),
+        unraw(snake_case_ident(variant))
+    );
+    let doc = format!("Builds a `{family_ident}::{variant}` operation.");
+    let param = |arg: &Ident, ty: &Type| {
+        let is_string = matches!(ty, Type::Path(path)
+            if path.qself.is_none() && path.path.is_ident("String"));
+        if is_string {
+            (
+                quote!(#arg: impl ::core::convert::Into<String>),
+                quote!(#arg.into()),
+            )
+        } else {
+            (quote!(#arg: #ty), quote!(#arg))
+        }
+    };
+    let (params, construct) = match payload {
+        None => (quote!(), quote!(#family_ident::#variant)),
+        Some(Type::Tuple(tuple)) => {
+            let (params, values): (Vec<_>, Vec<_>) = tuple
+                .elems
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| param(&quote::format_ident!("arg{}", i), ty))
+                .unzip();
+            let value = if values.len() == 1 {
+                quote!((#(#values,)*))
+            } else {
+                quote!((#(#values),*))
+            };
+            (
+                quote!(#(#params),*),
+                quote!(#family_ident::#variant(#value)),
+            )
+        }
+        Some(ty) => {
+            let (param, value) = param(&quote::format_ident!("payload"), ty);
+            (param, quote!(#family_ident::#variant(#value)))
+        }
+    };
+    quote! {
+        #[doc = #doc]
+        pub fn #name(#params) -> Self {
+            Self::from(#construct)
+        }
+    }
+}
+
+/// One declared op as seen by the generated auto-mock.
+struct MockOp<'a> {
+    family: &'a Ident,
+    variant: &'a Ident,
+    payload: Option<&'a Type>,
+    ret: &'a Type,
+    /// The `OpMock` field answering the op.
+    field: Ident,
+}
+
+/// `<Root>AutoMock`: an `algae::mock::OpMock` field per op and a
+/// `PartialHandler` that answers from them and records what it declines.
+fn auto_mock(root_ident: &Ident, ops: &[MockOp<'_>]) -> TokenStream2 {
+    let mock_ident = quote::format_ident!("{}AutoMock", root_ident);
+    let mut fields = TokenStream2::new();
+    let mut inits = TokenStream2::new();
+    let mut arms = TokenStream2::new();
+    for MockOp {
+        family,
+        variant,
+        payload,
+        ret,
+        field,
+    } in ops
+    {
+        let doc = format!("Responses to `{family}::{variant}`.");
+        let payload_ty = match payload {
+            Some(ty) => quote!(#ty),
+            None => quote!(()),
+        };
+        fields.extend(quote! {
+            #[doc = #doc]
+            pub #field: algae::mock::OpMock<#payload_ty, #ret>,
+        });
+        inits.extend(quote! { #field: algae::mock::OpMock::new(), });
+        let (pattern, payload_ref) = match payload {
+            Some(_) => (quote!(#family::#variant(payload)), quote!(payload)),
+            None => (quote!(#family::#variant), quote!(&())),
+        };
+        arms.extend(quote! {
+            #root_ident::#family(#pattern) => self
+                .#field
+                .respond(#payload_ref)
+                .map(|r| ::std::boxed::Box::new(r) as ::std::boxed::Box<dyn ::core::any::Any + Send>),
+        });
+    }
+    let doc = format!(
+        "A `{root_ident}` handler answering each operation from its `algae::mock::OpMock` field."
+    );
+    quote! {
+        #[doc = #doc]
+        ///
+        /// Clones share their responses and records.
+        #[derive(Debug, Clone)]
+        #[allow(dead_code)]
+        pub struct #mock_ident {
+            #fields
+            __unmatched: algae::mock::Captured<#root_ident>,
+        }
+
+        #[allow(dead_code)]
+        impl #mock_ident {
+            pub fn new() -> Self {
+                Self {
+                    #inits
+                    __unmatched: ::core::default::Default::default(),
+                }
+            }
+
+            /// The operations declined so far, oldest first.
+            pub fn unmatched(&self) -> ::std::vec::Vec<#root_ident> {
+                self.__unmatched.values()
+            }
+        }
+
+        impl ::core::default::Default for #mock_ident {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl algae::PartialHandler<#root_ident> for #mock_ident {
+            fn maybe_handle(
+                &mut self,
+                op: &#root_ident,
+            ) -> ::core::option::Option<::std::boxed::Box<dyn ::core::any::Any + Send>> {
+                #[allow(unreachable_patterns)]
+                let reply = match op {
+                    #arms
+                    _ => ::core::option::Option::None,
+                };
+                if reply.is_none() {
+                    self.__unmatched.push(::core::clone::Clone::clone(op));
+                }
+                reply
+            }
+        }
+
+        impl algae::IntoVecHandler<#root_ident> for #mock_ident {
+            fn into_vec_handler(self) -> algae::VecHandler<#root_ident> {
+                let mut vec = algae::VecHandler::new();
+                vec.push(self);
+                vec
+            }
+        }
+    }
+}
+
+/// `ReadLine` → `read_line`, `HTTPGet` → `http_get`; keywords become raw identifiers.
+fn snake_case_ident(ident: &Ident) -> Ident {
+    let name = ident.to_string();
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev != '_' && (prev.is_lowercase() || prev.is_numeric() || next_is_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    // These keywords cannot be raw identifiers
+    if matches!(snake.as_str(), "self" | "super" | "crate") {
+        snake.push('_');
+    }
+    if syn::parse_str::<Ident>(&snake).is_ok() {
+        Ident::new(&snake, ident.span())
+    } else {
+        Ident::new_raw(&snake, ident.span())
+    }
+}
+
+/// `From<Family>` and `algae::Contains<Family>` for the root enum.
+fn family_impls(
+    root_ident: &Ident,
+    root_generics: &syn::Generics,
+    family_ident: &Ident,
+    family_ty: &TokenStream2,
+) -> TokenStream2 {
+    let (root_impl, root_ty_generics, _) = root_generics.split_for_impl();
+    let ref_generics = with_lifetime(root_generics);
+    let (ref_impl, _, _) = ref_generics.split_for_impl();
+    let root_ty = quote!(#root_ident #root_ty_generics);
+    quote! {
+        impl #root_impl From<#family_ty> for #root_ty {
+            fn from(f: #family_ty) -> Self { #root_ident::#family_ident(f) }
+        }
+
+        impl #root_impl algae::Contains<#family_ty> for #root_ty {
+            #[allow(unreachable_patterns)]
+            fn project(&self) -> ::core::option::Option<&#family_ty> {
+                match self {
+                    #root_ident::#family_ident(f) => ::core::option::Option::Some(f),
+                    _ => ::core::option::Option::None,
+                }
+            }
+        }
+
+        impl #root_impl ::core::convert::TryFrom<#root_ty> for #family_ty {
+            type Error = #root_ty;
+
+            #[allow(unreachable_patterns)]
+            fn try_from(op: #root_ty) -> ::core::result::Result<Self, #root_ty> {
+                match op {
+                    #root_ident::#family_ident(f) => ::core::result::Result::Ok(f),
+                    other => ::core::result::Result::Err(other),
+                }
+            }
+        }
+
+        impl #ref_impl ::core::convert::TryFrom<&'a #root_ty> for &'a #family_ty {
+            type Error = &'a #root_ty;
+
+            #[allow(unreachable_patterns)]
+            fn try_from(op: &'a #root_ty) -> ::core::result::Result<Self, &'a #root_ty> {
+                match op {
+                    #root_ident::#family_ident(f) => ::core::result::Result::Ok(f),
+                    other => ::core::result::Result::Err(other),
+                }
+            }
+        }
+    }
+}
+
+/// `generics` with a leading `'a` lifetime, for types borrowing from an op.
+fn with_lifetime(generics: &syn::Generics) -> syn::Generics {
+    let mut generics = generics.clone();
+    generics.params.insert(0, syn::parse_quote!('a));
+    generics
+}
+
 /*──────────────────────────────────────────────────────────────────────────────
    effectful!  and  perform!  are unchanged except for *one* tiny tweak:
    perform!( … ) now calls `.into()` so any Family enum is automatically
@@ -415,6 +2224,60 @@ pub fn effect(item: TokenStream) -> TokenStream {
 /// }
 /// ```
 ///
+/// ## Streams
+/// ```ignore
+/// # #![feature(coroutines, coroutine_trait, yield_expr)]
+/// # use algae::prelude::*;
+/// # effect! { File::ReadLine -> Option<String>; }
+/// #[effectful(yields = String)]
+/// fn tail_log() {
+///     while let Some(line) = perform!(File::ReadLine) {
+///         emit!(line);
+///     }
+/// }
+/// ```
+///
+/// With `yields = T` the function returns an `algae::stream::EffectStream<T, RootType>`
+/// instead. `emit!(value)` hands a value to the caller, which pulls values one at a
+/// time while the effects in between are handled. Stream functions cannot declare a
+/// return type, and `perform!`/`emit!` must appear directly in the body rather than
+/// inside the arguments of another macro.
+///
+/// ## Multi-Shot Computations
+/// ```ignore
+/// # #![feature(coroutines, coroutine_trait, coroutine_clone, yield_expr)]
+/// # use algae::prelude::*;
+/// # effect! { Choice::Flip -> bool; }
+/// #[effectful(multishot)]
+/// fn two_coins() -> (bool, bool) {
+///     let a: bool = perform!(Choice::Flip);
+///     let b: bool = perform!(Choice::Flip);
+///     (a, b)
+/// }
+/// ```
+///
+/// With `multishot` the function returns an `algae::multishot::MultiShotEffectful<R, RootType>`,
+/// whose handlers may resume the rest of the computation more than once. This needs the
+/// nightly `coroutine_clone` feature, and everything held across a `perform!` must be `Clone`.
+///
+/// ## Async Functions
+/// ```ignore
+/// # use algae::prelude::*;
+/// # effect! { Db::User (u32) -> String; }
+/// #[effectful]
+/// async fn profile(id: u32) -> Profile {
+///     let name: String = perform!(Db::User(id));
+///     let avatar = fetch_avatar(&name).await;
+///     Profile { name, avatar }
+/// }
+/// ```
+///
+/// On an `async fn` the function returns an `algae::future::EffectFuture<R, RootType>`
+/// instead, a future-based computation whose body can `.await` between two `perform!`s.
+/// It is run with `.run(async_handler, handler).await`. As in streams, `perform!` must
+/// appear directly in the body, and `async fn` cannot be combined with `yields` or
+/// `multishot`.
+///
 /// # Transformation
 ///
 /// The macro transforms the function in several ways:
@@ -485,6 +2348,34 @@ pub fn effect(item: TokenStream) -> TokenStream {
 /// }
 /// ```
 ///
+/// # Methods
+///
+/// `#[effectful]` also goes on methods in `impl` blocks and in traits, where
+/// a method without a body declares one that implementations mark
+/// `#[effectful]` as well. The receiver is moved into the computation. A
+/// `&self` method, whose computation cannot borrow it, runs on a clone of the
+/// receiver instead, so `Self` must be `Clone + Send + Sync + 'static`.
+///
+/// `&mut self` methods are rejected: the computation runs after the call has
+/// returned, so it could only change a copy, and the changes would be lost.
+/// Take `self` by value and return it, or keep the state to change behind a
+/// shared handle such as `Arc<Mutex<_>>`.
+///
+/// ```ignore
+/// impl Greeter {
+///     #[effectful]
+///     fn greet(&self) -> String {
+///         let name: String = perform!(Console::ReadLine);
+///         format!("{}, {name}!", self.greeting)
+///     }
+/// }
+///
+/// trait Service {
+///     #[effectful]
+///     fn call(&self, request: Request) -> Response;
+/// }
+/// ```
+///
 /// # Usage
 ///
 /// Effectful functions are called like normal functions but return an `Effectful`
@@ -504,66 +2395,929 @@ pub fn effect(item: TokenStream) -> TokenStream {
 /// # fn my_computation() -> i32 { perform!(Test::GetValue) }
 /// let result = my_computation()
 ///     .handle(TestHandler)
-///     .run();
+///     .run()?;
 /// ```
 ///
 /// # Limitations
 ///
-/// - Functions must not be `async` (effectful functions use coroutines, not async/await)
+/// - `async fn`s become `EffectFuture`s rather than `Effectful`s, see above
 /// - Generic parameters are preserved but may require careful handling with effects
 /// - Lifetime parameters are supported but the coroutine has `'static` requirements
+/// - `multishot` methods must take `self` by value
+/// - Trait default methods spell out the bounds on `Self` above in a `where` clause
 #[proc_macro_attribute]
 pub fn effectful(args: TokenStream, item: TokenStream) -> TokenStream {
+    // Trait methods may be declared without a body; only their signature
+    // is transformed then.
+    let (mut f, declaration) = match syn::parse::<syn::ItemFn>(item.clone()) {
+        Ok(f) => (f, false),
+        Err(err) => match syn::parse::<syn::TraitItemFn>(item) {
+            Ok(method) if method.default.is_none() => (
+                syn::ItemFn {
+                    attrs: method.attrs,
+                    vis: syn::Visibility::Inherited,
+                    sig: method.sig,
+                    block: Box::new(syn::parse_quote!({})),
+                },
+                true,
+            ),
+            _ => return err.to_compile_error().into(),
+        },
+    };
+
+    // Parse optional `root = FooOp` and `yields = T` arguments
+    let mut root_type: Option<Type> = None;
+    let mut yields: Option<Type> = None;
+    let mut multishot = false;
+    let args_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("root") {
+            root_type = Some(meta.value()?.parse().map_err(|_| {
+                meta.error("Invalid root type name. Expected: #[effectful(root = YourRootType)]")
+            })?);
+            Ok(())
+        } else if meta.path.is_ident("yields") {
+            yields = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("multishot") {
+            multishot = true;
+            Ok(())
+        } else {
+            Err(meta.error(
+                "Invalid attribute argument. Expected: #[effectful(root = YourRootType)], \
+                 #[effectful(yields = ItemType)], #[effectful(multishot)] or #[effectful]",
+            ))
+        }
+    });
+    parse_macro_input!(args with args_parser);
+
+    // Default to unqualified Op for backwards compatibility
+    // The Op type is generated locally by effect! macro in the current module
+    let root_type = root_type.unwrap_or_else(|| syn::parse_quote! { Op });
+
+    // The computation cannot borrow a `&self` receiver, so it works on its
+    // own clone, which the body sees as `self`. Holding a reference to it
+    // across yields makes the coroutine immovable.
+    let borrowed = match borrowed_receiver(&f.sig, multishot) {
+        Ok(borrowed) => borrowed,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let (receiver, movability) = if borrowed {
+        let block = &f.block;
+        let body = rename_self(quote!(#block));
+        f.block = syn::parse_quote!({
+            let __self = &__self_owned;
+            #body
+        });
+        (
+            quote!(let __self_owned = algae::__private::owned_receiver(self);),
+            quote!(static),
+        )
+    } else {
+        (TokenStream2::new(), TokenStream2::new())
+    };
+
+    let expanded = expand_effectful(f, root_type, yields, multishot, receiver, movability);
+    match expanded {
+        Ok(f) if declaration => {
+            let (attrs, sig) = (&f.attrs, &f.sig);
+            quote!(#(#attrs)* #sig;).into()
+        }
+        Ok(f) => quote!(#f).into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Turns the signature and body of an `#[effectful]` function into those of
+/// a function returning the computation.
+///
+/// `receiver` is spliced in before the computation is built, and
+/// `movability` in front of its coroutine; see [`effectful`].
+fn expand_effectful(
+    mut f: syn::ItemFn,
+    root_type: Type,
+    yields: Option<Type>,
+    multishot: bool,
+    receiver: TokenStream2,
+    movability: TokenStream2,
+) -> Result<syn::ItemFn> {
+    if f.sig.asyncness.is_some() {
+        if multishot || yields.is_some() {
+            return Err(syn::Error::new_spanned(
+                f.sig.asyncness,
+                "`async fn` cannot be combined with `multishot` or `yields`",
+            ));
+        }
+        let inner_type: Type = match &f.sig.output {
+            syn::ReturnType::Default => syn::parse_quote! { () },
+            syn::ReturnType::Type(_, ty) => ty.as_ref().clone(),
+        };
+        f.sig.asyncness = None;
+        f.sig.output = syn::parse_quote! {
+            -> algae::future::EffectFuture<#inner_type, #root_type>
+        };
+
+        // perform! awaits its reply instead of yielding, so it is expanded
+        // here rather than by its own macro.
+        let mut body = f.block.as_ref().clone();
+        BodyRewriter::Async.visit_block_mut(&mut body);
+        f.block = syn::parse_quote! {{
+            #receiver
+            algae::future::EffectFuture::new(
+                move |__effects: algae::future::Effects<#root_type>| async move #body
+            )
+        }};
+        return Ok(f);
+    }
+
+    let Some(item_type) = yields else {
+        // Transform return type from -> T to -> Effectful<T, RootType>
+        let inner_type = match &f.sig.output {
+            syn::ReturnType::Default => syn::parse_quote! { () },
+            syn::ReturnType::Type(_, ty) => ty.as_ref().clone(),
+        };
+
+        if multishot {
+            // The first resume argument is dropped right away: a coroutine is
+            // only `Clone` if everything it holds across a yield is.
+            let body = &f.block;
+            f.sig.output = syn::parse_quote! {
+                -> algae::multishot::MultiShotEffectful<#inner_type, #root_type>
+            };
+            f.block = syn::parse_quote! {{
+                algae::multishot::MultiShotEffectful::new(
+                    #[coroutine] move |__resume: Option<algae::Reply>| {
+                        ::core::mem::drop(__resume);
+                        #body
+                    }
+                )
+            }};
+            return Ok(f);
+        }
+
+        f.sig.output = syn::parse_quote! {
+            -> algae::Effectful<#inner_type, #root_type>
+        };
+
+        // The root type is spelled out here, so this is where it is checked
+        // for what `algae::trace` and reply type errors need to render operations.
+        let body = &f.block;
+        let name = &f.sig.ident;
+        f.block = syn::parse_quote! {{
+            #receiver
+            #[allow(unused_imports)]
+            use algae::__private::{DescribedOp as _, OpaqueOp as _};
+            algae::__private::describing(
+                algae::__private::named(
+                    algae::Effectful::new(#[coroutine] #movability move |mut _reply: Option<algae::Reply>| {
+                        #body
+                    }),
+                    ::core::concat!(::core::module_path!(), "::", ::core::stringify!(#name)),
+                ),
+                (&algae::__private::RootType::<#root_type>::default()).op_describer(),
+            )
+        }};
+        return Ok(f);
+    };
+
+    if multishot {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`multishot` and `yields` cannot be combined",
+        ));
+    }
+
+    // Streams: the body produces values with emit! and returns ()
+    if let syn::ReturnType::Type(_, ty) = &f.sig.output {
+        return Err(syn::Error::new_spanned(
+            ty,
+            "#[effectful(yields = ...)] functions produce values with emit! and cannot return a value",
+        ));
+    }
+    f.sig.output = syn::parse_quote! {
+        -> algae::stream::EffectStream<#item_type, #root_type>
+    };
+
+    // The coroutine yields StreamStep instead of Effect, so perform! and emit!
+    // are expanded here rather than by their own macros.
+    let mut body = f.block.as_ref().clone();
+    BodyRewriter::Stream.visit_block_mut(&mut body);
+    f.block = syn::parse_quote! {{
+        #receiver
+        algae::stream::EffectStream::new(#[coroutine] #movability move |mut _reply: Option<algae::Reply>| {
+            #body
+        })
+    }};
+    Ok(f)
+}
+
+/// Whether the method takes `&self`, which its computation runs on a clone
+/// of; `false` for functions and methods taking `self` by value.
+///
+/// Fails for `&mut self`, whose changes the clone would lose, and for
+/// borrowing `multishot` methods.
+fn borrowed_receiver(sig: &syn::Signature, multishot: bool) -> syn::Result<bool> {
+    let Some(syn::FnArg::Receiver(receiver)) = sig.inputs.first() else {
+        return Ok(false);
+    };
+    let Type::Reference(reference) = receiver.ty.as_ref() else {
+        return Ok(false);
+    };
+    if reference.mutability.is_some() {
+        return Err(syn::Error::new_spanned(
+            receiver,
+            "`#[effectful]` methods cannot take `&mut self`: the computation runs after \
+             the call returns, so its changes to `self` would be lost. Take `self` by \
+             value and return it, or keep the state to change in shared state such as \
+             `Arc<Mutex<_>>`",
+        ));
+    }
+    if multishot {
+        return Err(syn::Error::new_spanned(
+            receiver,
+            "`multishot` methods must take `self` by value",
+        ));
+    }
+    Ok(true)
+}
+
+/// Replaces `self` in a method body with `__self`, leaving `self::` paths and
+/// the bodies of nested items, which have a `self` of their own, alone.
+fn rename_self(tokens: TokenStream2) -> TokenStream2 {
+    use proc_macro2::{Delimiter, Group, TokenTree};
+
+    let mut renamed = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+    let mut in_item = false;
+    while let Some(token) = tokens.next() {
+        let token = match token {
+            TokenTree::Ident(ident) if ident == "impl" || ident == "trait" => {
+                in_item = true;
+                TokenTree::Ident(ident)
+            }
+            TokenTree::Group(group) if in_item && group.delimiter() == Delimiter::Brace => {
+                in_item = false;
+                TokenTree::Group(group)
+            }
+            TokenTree::Group(group) => {
+                let mut inner = Group::new(group.delimiter(), rename_self(group.stream()));
+                inner.set_span(group.span());
+                TokenTree::Group(inner)
+            }
+            TokenTree::Ident(ident)
+                if ident == "self"
+                    && !matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == ':') =>
+            {
+                TokenTree::Ident(Ident::new("__self", ident.span()))
+            }
+            token => token,
+        };
+        renamed.push(token);
+    }
+    renamed.into_iter().collect()
+}
+
+/// Builds a closure returning an effectful computation, for small ad-hoc
+/// computations that do not deserve a named `#[effectful]` function.
+///
+/// The body of the closure is written like that of an `#[effectful]`
+/// function, with `perform!` and `perform_all!`; calling the closure returns
+/// an `algae::Effectful` running it. The closure takes what it captures by
+/// value, and each call moves it into the computation, so closures capturing
+/// values that are not `Copy` can be called once.
+///
+/// The root type is inferred from where the closure is used, as in `bind`;
+/// `root = Type,` before the closure names it, as for `#[effectful]`. The
+/// macro is not called `effectful!` because the attribute owns that name.
+///
+/// ```ignore
+/// let greeting = user_id().bind(effectful_closure!(|id: u64| {
+///     let name: String = perform!(Db::UserName(id));
+///     format!("Hello, {name}!")
+/// }));
+///
+/// let roll = effectful_closure!(root = Op, |sides: u32| perform!(Random::Below(sides)) + 1);
+/// let total = roll(6).handle(Dice).run_or_panic() + roll(20).handle(Dice).run_or_panic();
+/// ```
+#[proc_macro]
+pub fn effectful_closure(ts: TokenStream) -> TokenStream {
+    let EffectfulClosure { root, closure } = parse_macro_input!(ts as EffectfulClosure);
+    if let Some(token) = closure.asyncness {
+        return syn::Error::new_spanned(token, "effectful closures cannot be `async`")
+            .to_compile_error()
+            .into();
+    }
+    let syn::ExprClosure {
+        attrs,
+        inputs,
+        output,
+        body,
+        ..
+    } = closure;
+    let root_type = match &root {
+        Some(root) => quote!(#root),
+        None => quote!(_),
+    };
+    let output = match output {
+        syn::ReturnType::Default => TokenStream2::new(),
+        syn::ReturnType::Type(_, ty) => quote!(-> algae::Effectful<#ty, #root_type>),
+    };
+    let computation = quote! {
+        algae::Effectful::<_, #root_type>::new(#[coroutine] move |mut _reply: Option<algae::Reply>| {
+            #body
+        })
+    };
+    // Operations can only be rendered, for `algae::trace` and reply type
+    // errors, with a named root.
+    let computation = match root {
+        Some(root) => quote! {{
+            #[allow(unused_imports)]
+            use algae::__private::{DescribedOp as _, OpaqueOp as _};
+            algae::__private::describing(
+                #computation,
+                (&algae::__private::RootType::<#root>::default()).op_describer(),
+            )
+        }},
+        None => computation,
+    };
+    quote!(#(#attrs)* move |#inputs| #output { #computation }).into()
+}
+
+/// The input of `effectful_closure!`: an optional `root = Type,`, then the
+/// closure.
+struct EffectfulClosure {
+    root: Option<Type>,
+    closure: syn::ExprClosure,
+}
+
+impl Parse for EffectfulClosure {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let root = if input.peek(Ident) && input.peek2(Token![=]) {
+            let key: Ident = input.parse()?;
+            if key != "root" {
+                return Err(syn::Error::new_spanned(
+                    key,
+                    "Invalid argument. Expected: effectful_closure!(root = YourRootType, |..| ..)",
+                ));
+            }
+            input.parse::<Token![=]>()?;
+            let root = input.parse()?;
+            input.parse::<Token![,]>()?;
+            Some(root)
+        } else {
+            None
+        };
+        Ok(Self {
+            root,
+            closure: input.parse()?,
+        })
+    }
+}
+
+/// Runs an effectful test function with a handler fixture.
+///
+/// The annotated function is written like an `#[effectful]` function without
+/// parameters. It becomes a `#[test]` that runs its body with
+/// `algae::testing::run_test`, which prints the operations the body performed
+/// if the test fails.
+///
+/// # Arguments
+///
+/// - `fixture = expr` - the `algae::HandlerFactory` building the handler to
+///   run with, usually the path of a function returning it. It builds one
+///   per test.
+///   Defaults to `algae::testing::DeterministicEnv::new`.
+/// - `root = Type` - the root operation type, as for `#[effectful]`. Defaults
+///   to `Op`.
+///
+/// Other attributes, such as `#[should_panic]` or `#[ignore]`, are kept.
+///
+/// # Examples
+///
+/// ```ignore
+/// # #![feature(coroutines, coroutine_trait, yield_expr)]
+/// # use algae::prelude::*;
+/// # use std::time::{Duration, SystemTime};
+/// # use algae::testing::DeterministicEnv;
+/// effect! {
+///     use algae::effects::time::Time;
+///     Db::Get (u32) -> Option<String>;
+/// }
+///
+/// #[algae_test]
+/// fn sleeping_advances_virtual_time() {
+///     let _: () = perform!(Time::Sleep(Duration::from_secs(60)));
+///     let now: SystemTime = perform!(Time::Now);
+///     assert_eq!(now, SystemTime::UNIX_EPOCH + Duration::from_secs(60));
+/// }
+///
+/// fn with_users() -> VecHandler<Op> {
+///     let mut handlers = VecHandler::new();
+///     handlers.push(FakeDb::with_users(["alice"]));
+///     handlers.push(DeterministicEnv::new());
+///     handlers
+/// }
+///
+/// #[algae_test(fixture = with_users)]
+/// fn finds_alice() -> Result<(), String> {
+///     let user: Option<String> = perform!(Db::Get(1));
+///     user.filter(|name| name == "alice").map(drop).ok_or("no alice".into())
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn algae_test(args: TokenStream, item: TokenStream) -> TokenStream {
     let mut f = parse_macro_input!(item as syn::ItemFn);
+
+    let mut fixture: Option<syn::Expr> = None;
+    let mut root_type: Option<Type> = None;
+    let args_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("fixture") {
+            fixture = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("root") {
+            root_type = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error(
+                "Invalid attribute argument. Expected: #[algae_test(fixture = path::to_fn)], \
+                 #[algae_test(root = YourRootType)] or #[algae_test]",
+            ))
+        }
+    });
+    parse_macro_input!(args with args_parser);
+
+    if !f.sig.inputs.is_empty() {
+        return syn::Error::new_spanned(
+            &f.sig.inputs,
+            "#[algae_test] functions cannot take arguments; use a fixture for setup",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let root_type = root_type.unwrap_or_else(|| syn::parse_quote! { Op });
+    let fixture = match fixture {
+        Some(factory) => quote! { algae::HandlerFactory::<#root_type>::build(&(#factory)) },
+        None => quote! { algae::testing::DeterministicEnv::new() },
+    };
+    let inner_type: Type = match &f.sig.output {
+        syn::ReturnType::Default => syn::parse_quote! { () },
+        syn::ReturnType::Type(_, ty) => ty.as_ref().clone(),
+    };
+
     let body = &f.block;
+    f.block = syn::parse_quote! {{
+        algae::testing::run_test(
+            algae::Effectful::<#inner_type, #root_type>::new(
+                #[coroutine] move |mut _reply: Option<algae::Reply>| {
+                    #body
+                }
+            ),
+            #fixture,
+        )
+    }};
+    f.attrs.insert(0, syn::parse_quote! { #[test] });
+    quote!(#f).into()
+}
+
+/// Derives a handler for a struct of sub-handlers, routing each effect
+/// family to the field annotated with it.
+///
+/// Each routed field is marked with `#[route(...)]`, listing the root enum
+/// variants it handles; other fields are left alone. All routes must name
+/// variants of the same root enum, which becomes the handler's `Op`.
+///
+/// By default the derive implements `Handler<Op>` over fields that are
+/// `Handler<Op>`s, and panics on operations of a family no field is routed
+/// to. With `#[route_handler(partial)]` it implements `PartialHandler<Op>`
+/// over fields that are `PartialHandler<Op>`s instead, declining unrouted
+/// operations and whatever the routed field declines.
+///
+/// # Examples
+///
+/// ```ignore
+/// effect! {
+///     State::Get -> i32;
+///     Console::Print (String) -> ();
+///     Log::Info (String) -> ();
+/// }
+///
+/// #[derive(RouteHandler)]
+/// struct AppHandler {
+///     #[route(Op::State)]
+///     state: StateHandler,
+///     #[route(Op::Console, Op::Log)]
+///     output: StdoutHandler,
+///     started: std::time::Instant, // not a route
+/// }
+///
+/// // Generates, roughly:
+/// impl algae::Handler<Op> for AppHandler {
+///     fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+///         match op {
+///             Op::State(..) => algae::Handler::handle(&mut self.state, op),
+///             Op::Console(..) | Op::Log(..) => algae::Handler::handle(&mut self.output, op),
+///             _ => panic!("AppHandler has no route for this operation"),
+///         }
+///     }
+/// }
+/// ```
+#[proc_macro_derive(RouteHandler, attributes(route, route_handler))]
+pub fn derive_route_handler(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    route_handler_tokens(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// The routes of a `#[derive(RouteHandler)]` struct.
+struct Routes {
+    /// The root enum all routes belong to
+    root: syn::Path,
+    /// Each routed field, its type and the root variants it handles
+    fields: Vec<(syn::Member, Type, Vec<syn::Path>)>,
+    partial: bool,
+}
+
+impl Routes {
+    fn parse(input: &syn::DeriveInput) -> Result<Self> {
+        let mut partial = false;
+        for attr in &input.attrs {
+            if attr.path().is_ident("route_handler") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("partial") {
+                        partial = true;
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `#[route_handler(partial)]`"))
+                    }
+                })?;
+            }
+        }
+
+        let syn::Data::Struct(data) = &input.data else {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "#[derive(RouteHandler)] only supports structs",
+            ));
+        };
+
+        let mut root: Option<syn::Path> = None;
+        let mut seen: Vec<String> = Vec::new();
+        let mut fields = Vec::new();
+        for (index, field) in data.fields.iter().enumerate() {
+            let member = match &field.ident {
+                Some(ident) => syn::Member::Named(ident.clone()),
+                None => syn::Member::Unnamed(index.into()),
+            };
+            let mut variants = Vec::new();
+            for attr in field.attrs.iter().filter(|a| a.path().is_ident("route")) {
+                let paths =
+                    attr.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)?;
+                if paths.is_empty() {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        "expected at least one variant, e.g. #[route(Op::State)]",
+                    ));
+                }
+                for path in paths {
+                    if path.segments.len() < 2 {
+                        return Err(syn::Error::new_spanned(
+                            &path,
+                            "expected a root enum variant, e.g. `Op::State`",
+                        ));
+                    }
+                    let mut enum_path = path.clone();
+                    enum_path.segments.pop();
+                    enum_path.segments.pop_punct();
+                    match &root {
+                        None => root = Some(enum_path),
+                        Some(root)
+                            if quote!(#root).to_string() != quote!(#enum_path).to_string() =>
+                        {
+                            return Err(syn::Error::new_spanned(
+                                &path,
+                                format!(
+                                    "all routes must be variants of the same root enum, `{}`",
+                                    quote!(#root).to_string().replace(' ', "")
+                                ),
+                            ));
+                        }
+                        Some(_) => {}
+                    }
+                    let key = quote!(#path).to_string();
+                    if seen.contains(&key) {
+                        return Err(syn::Error::new_spanned(
+                            &path,
+                            format!("`{}` is routed twice", key.replace(' ', "")),
+                        ));
+                    }
+                    seen.push(key);
+                    variants.push(path);
+                }
+            }
+            if !variants.is_empty() {
+                fields.push((member, field.ty.clone(), variants));
+            }
+        }
+
+        let Some(root) = root else {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "#[derive(RouteHandler)] needs at least one field marked with #[route(...)]",
+            ));
+        };
+        Ok(Routes {
+            root,
+            fields,
+            partial,
+        })
+    }
+}
+
+/// The expansion of `#[derive(RouteHandler)]`.
+fn route_handler_tokens(input: &syn::DeriveInput) -> Result<TokenStream2> {
+    let Routes {
+        root,
+        fields,
+        partial,
+    } = Routes::parse(input)?;
+    let name = &input.ident;
+    let trait_path = if partial {
+        quote!(algae::PartialHandler)
+    } else {
+        quote!(algae::Handler)
+    };
+
+    // Routed fields must handle the root enum, which also bounds generic
+    // fields
+    let mut generics = input.generics.clone();
+    let predicates = &mut generics.make_where_clause().predicates;
+    for (_, ty, _) in &fields {
+        predicates.push(syn::parse_quote!(#ty: #trait_path<#root>));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let arms = fields.iter().map(|(member, _, variants)| {
+        let call = if partial {
+            quote!(algae::PartialHandler::maybe_handle(&mut self.#member, op))
+        } else {
+            quote!(algae::Handler::handle(&mut self.#member, op))
+        };
+        quote!(#(#variants(..))|* => #call,)
+    });
+
+    let (method, reply, fallback) = if partial {
+        (
+            quote!(maybe_handle),
+            quote!(Option<algae::__private::Box<dyn ::core::any::Any + Send>>),
+            quote!(None),
+        )
+    } else {
+        let message = format!("{name} has no route for this operation");
+        (
+            quote!(handle),
+            quote!(algae::__private::Box<dyn ::core::any::Any + Send>),
+            quote!(panic!(#message)),
+        )
+    };
+
+    Ok(quote! {
+        impl #impl_generics #trait_path<#root> for #name #ty_generics #where_clause {
+            fn #method(&mut self, op: &#root) -> #reply {
+                match op {
+                    #(#arms)*
+                    #[allow(unreachable_patterns)]
+                    _ => #fallback,
+                }
+            }
+        }
+    })
+}
+
+/// Makes a handler of single effect families a partial handler of any root
+/// enum embedding them.
+///
+/// The annotated type implements `Handler<Family>` for each family listed;
+/// the attribute implements `PartialHandler<Op>` and `IntoVecHandler<Op>`
+/// for every root type `Op` that is `algae::Contains` all of them, routing
+/// the operations of each family to its `Handler` and declining the others.
+/// It saves writing the `Op::Console(inner) => ...` match of every program
+/// using the handler.
+///
+/// A handler listing several families is only a partial handler of root
+/// types embedding all of them.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[handles(Console)]
+/// struct Terminal;
+///
+/// impl Handler<Console> for Terminal {
+///     fn handle(&mut self, op: &Console) -> Box<dyn std::any::Any + Send> {
+///         match op {
+///             Console::Print(line) => Box::new(println!("{line}")),
+///             Console::ReadLine => Box::new(read_line()),
+///         }
+///     }
+/// }
+///
+/// // Any root type with a `Console` family can use it:
+/// let name = greet().begin_chain().handle(Terminal).handle(Clock).run()?;
+///
+/// // Generates, roughly:
+/// impl<Op: algae::Contains<Console>> algae::PartialHandler<Op> for Terminal {
+///     fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn std::any::Any + Send>> {
+///         if let Some(op) = algae::Contains::<Console>::project(op) {
+///             return Some(algae::Handler::<Console>::handle(self, op));
+///         }
+///         None
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn handles(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let families =
+        parse_macro_input!(attr with Punctuated::<syn::Path, Token![,]>::parse_terminated);
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    let tokens = handles_tokens(&families, &input).unwrap_or_else(syn::Error::into_compile_error);
+    quote!(#input #tokens).into()
+}
+
+/// The impls added by `#[handles(...)]` to `input`.
+fn handles_tokens(
+    families: &Punctuated<syn::Path, Token![,]>,
+    input: &syn::DeriveInput,
+) -> Result<TokenStream2> {
+    if families.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "expected at least one effect family, e.g. #[handles(Console)]",
+        ));
+    }
+    let mut seen = Vec::new();
+    for family in families {
+        let key = quote!(#family).to_string();
+        if seen.contains(&key) {
+            return Err(syn::Error::new_spanned(
+                family,
+                format!("`{}` is listed twice", key.replace(' ', "")),
+            ));
+        }
+        seen.push(key);
+    }
+
+    let name = &input.ident;
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let mut generics = input.generics.clone();
+    generics.params.push(syn::parse_quote!(__Op));
+    let predicates = &mut generics.make_where_clause().predicates;
+    for family in families {
+        predicates.push(syn::parse_quote!(#name #ty_generics: algae::Handler<#family>));
+        predicates.push(syn::parse_quote!(__Op: algae::Contains<#family>));
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+
+    // `Send + 'static` bounds only the `IntoVecHandler` impl, which boxes
+    // the handler
+    let mut boxed = generics.clone();
+    boxed
+        .make_where_clause()
+        .predicates
+        .push(syn::parse_quote!(Self: algae::PartialHandler<__Op> + Send + 'static));
+    let (boxed_generics, _, boxed_where) = boxed.split_for_impl();
+
+    let route = |method: TokenStream2| {
+        let arms = families.iter().map(|family| {
+            quote! {
+                if let Some(op) = algae::Contains::<#family>::project(op) {
+                    return Some(algae::Handler::<#family>::#method(self, op));
+                }
+            }
+        });
+        quote!(#(#arms)* None)
+    };
+    let handle = route(quote!(handle));
+    let reply = route(quote!(reply));
+
+    Ok(quote! {
+        impl #impl_generics algae::PartialHandler<__Op> for #name #ty_generics #where_clause {
+            fn maybe_handle(&mut self, op: &__Op) -> Option<algae::__private::Box<dyn ::core::any::Any + Send>> {
+                #handle
+            }
+
+            fn maybe_reply(&mut self, op: &__Op) -> Option<algae::Reply> {
+                #reply
+            }
+        }
+
+        impl #boxed_generics algae::IntoVecHandler<__Op> for #name #ty_generics #boxed_where {
+            fn into_vec_handler(self) -> algae::VecHandler<__Op> {
+                let mut handlers = algae::VecHandler::new();
+                handlers.push(self);
+                handlers
+            }
+        }
+    })
+}
 
-    // Parse optional root argument
-    let root_type = if args.is_empty() {
-        // Default to unqualified Op for backwards compatibility
-        // The Op type is generated locally by effect! macro in the current module
-        syn::parse_quote! { Op }
-    } else {
-        // Parse root = FooOp syntax - we expect: root = identifier
-        // Convert args to string and parse manually since syn's AttributeArgs
-        // doesn't handle identifier values well
-        let args_str = args.to_string().replace(" ", "");
-
-        if let Some(root_name) = args_str.strip_prefix("root=") {
-            match syn::parse_str::<syn::Ident>(root_name) {
-                Ok(ident) => ident,
-                Err(_) => {
-                    return syn::Error::new(
-                        proc_macro2::Span::call_site(),
-                        "Invalid root type name. Expected: #[effectful(root = YourRootType)]",
-                    )
-                    .to_compile_error()
-                    .into();
+/// Expands `perform!` and `perform_all!`, and `emit!` in streams, inside the body of a stream
+/// function or an `async fn`.
+///
+/// Nested items are left alone, and invocations inside the arguments of other
+/// macros are not visible to the rewriter.
+enum BodyRewriter {
+    /// `#[effectful(yields = T)]`: operations and items are yielded as
+    /// `StreamStep`s.
+    Stream,
+    /// `#[effectful] async fn`: operations are awaited through the
+    /// `__effects` handle.
+    Async,
+}
+
+impl BodyRewriter {
+    fn expand(&mut self, mac: &syn::Macro) -> Option<syn::Expr> {
+        let name = &mac.path.segments.last()?.ident;
+        if name == "defer_perform" {
+            let err = syn::Error::new_spanned(
+                mac,
+                "defer_perform! cannot be used in streams or `async fn`s; \
+                 use `Effectful::bracket` on the computation instead",
+            )
+            .to_compile_error();
+            return Some(syn::parse_quote!({ #err }));
+        }
+        if name == "for_each_perform" {
+            let mut input: ForEachPerform = match mac.parse_body() {
+                Ok(input) => input,
+                Err(err) => {
+                    let err = err.to_compile_error();
+                    return Some(syn::parse_quote!({ #err }));
                 }
+            };
+            self.visit_expr_mut(&mut input.op);
+            self.visit_block_mut(&mut input.body);
+            let tokens = for_each_perform_tokens(&input, |eff| self.suspend(eff));
+            return Some(syn::parse_quote!(#tokens));
+        }
+        let is_perform = name == "perform" || name == "perform_all";
+        if !is_perform && (name != "emit" || matches!(self, BodyRewriter::Async)) {
+            return None;
+        }
+        let mut arg: syn::Expr = match mac.parse_body() {
+            Ok(arg) => arg,
+            Err(err) => {
+                let err = err.to_compile_error();
+                return Some(syn::parse_quote!({ #err }));
+            }
+        };
+        self.visit_expr_mut(&mut arg);
+        let tokens = if is_perform {
+            let suspend = |eff| self.suspend(eff);
+            if name == "perform" {
+                perform_tokens(&arg, suspend)
+            } else {
+                perform_all_tokens(&arg, suspend)
             }
         } else {
-            return syn::Error::new(
-                proc_macro2::Span::call_site(),
-                "Invalid attribute argument. Expected: #[effectful(root = YourRootType)] or #[effectful]"
-            ).to_compile_error().into();
+            quote! {{
+                let _ = yield algae::stream::StreamStep::Item(#arg);
+            }}
+        };
+        Some(syn::parse_quote!(#tokens))
+    }
+
+    /// The expression handing `eff` over and evaluating to its reply.
+    fn suspend(&self, eff: TokenStream2) -> TokenStream2 {
+        match self {
+            BodyRewriter::Stream => quote!(yield algae::stream::StreamStep::Effect(#eff)),
+            BodyRewriter::Async => quote!(__effects.perform(#eff).await),
         }
-    };
+    }
+}
 
-    // Transform return type from -> T to -> Effectful<T, RootType>
-    let inner_type = match &f.sig.output {
-        syn::ReturnType::Default => syn::parse_quote! { () },
-        syn::ReturnType::Type(_, ty) => ty.as_ref().clone(),
-    };
+impl syn::visit_mut::VisitMut for BodyRewriter {
+    fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+        if let syn::Expr::Macro(m) = expr {
+            if let Some(expanded) = self.expand(&m.mac) {
+                *expr = expanded;
+                return;
+            }
+        }
+        syn::visit_mut::visit_expr_mut(self, expr);
+    }
 
-    f.sig.output = syn::parse_quote! {
-        -> algae::Effectful<#inner_type, #root_type>
-    };
+    fn visit_stmt_mut(&mut self, stmt: &mut syn::Stmt) {
+        if let syn::Stmt::Macro(m) = stmt {
+            if let Some(expanded) = self.expand(&m.mac) {
+                *stmt = syn::Stmt::Expr(expanded, m.semi_token);
+                return;
+            }
+        }
+        syn::visit_mut::visit_stmt_mut(self, stmt);
+    }
 
-    f.block = syn::parse_quote! {{
-        algae::Effectful::new(#[coroutine] move |mut _reply: Option<algae::Reply>| {
-            #body
-        })
-    }};
-    quote!(#f).into()
+    fn visit_item_mut(&mut self, _item: &mut syn::Item) {}
 }
 
 /// Performs an effect operation within an effectful function.
@@ -602,6 +3356,10 @@ pub fn effectful(args: TokenStream, item: TokenStream) -> TokenStream {
 /// 4. Extracting the reply with the correct type using `Reply::take()`
 ///
 /// The return type must match what the effect definition specifies for that operation.
+/// For an operation written as `Family::Variant(…)`, and for an `algae::PerformsOne` as
+/// returned by the helper functions of `effect! { helpers; … }`, it is the declared
+/// return type and checked at compile time. For other operations it is inferred from
+/// the call site.
 ///
 /// # Examples
 ///
@@ -702,9 +3460,10 @@ pub fn effectful(args: TokenStream, item: TokenStream) -> TokenStream {
 /// # effect! { Test::GetValue -> i32; }
 /// // perform!(Test::GetValue) expands to roughly:
 /// // {
-/// //     let __eff = algae::Effect::new(Test::GetValue.into());
-/// //     let __reply_opt = yield __eff;
-/// //     __reply_opt.unwrap().take::<i32>()
+/// //     let __op = Test::GetValue;
+/// //     let __reply_type = (&__op).reply_type(); // declared type for `PerformsOne`
+/// //     let __reply_opt = yield algae::Effect::new(__op.into());
+/// //     __reply_type.take(__reply_opt) // Reply::take::<i32>()
 /// // }
 /// ```
 ///
@@ -723,11 +3482,267 @@ pub fn effectful(args: TokenStream, item: TokenStream) -> TokenStream {
 #[proc_macro]
 pub fn perform(ts: TokenStream) -> TokenStream {
     let input: syn::Expr = syn::parse(ts).unwrap();
+    perform_tokens(&input, |eff| quote!(yield #eff)).into()
+}
+
+/// Performs a batch of operations in a single suspension, returning their
+/// replies in order.
+///
+/// `perform_all!(ops)` takes anything iterable whose items convert into the
+/// root `Op` type, and evaluates to a `Vec<R>` of the replies, all of which
+/// must have the same type `R`. The whole batch reaches the handler at once:
+/// through [`Handler::handle_batch`] or [`PartialHandler::maybe_handle_batch`]
+/// when run by the `run*` drivers, which handlers override to amortize I/O,
+/// e.g. with one SQL round-trip for many queries. An empty batch is not
+/// performed at all.
+///
+/// ```ignore
+/// #[effectful]
+/// fn names(ids: Vec<u64>) -> Vec<String> {
+///     perform_all!(ids.into_iter().map(Db::UserName))
+/// }
+/// ```
+///
+/// [`Handler::handle_batch`]: ../algae/trait.Handler.html#method.handle_batch
+/// [`PartialHandler::maybe_handle_batch`]: ../algae/trait.PartialHandler.html#method.maybe_handle_batch
+#[proc_macro]
+pub fn perform_all(ts: TokenStream) -> TokenStream {
+    let input: syn::Expr = match syn::parse(ts) {
+        Ok(input) => input,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    perform_all_tokens(&input, |eff| quote!(yield #eff)).into()
+}
+
+/// The expansion of `perform_all!`, suspending like [`perform_tokens`].
+fn perform_all_tokens(
+    input: &syn::Expr,
+    suspend: impl FnOnce(TokenStream2) -> TokenStream2,
+) -> TokenStream2 {
+    let suspended = suspend(quote!(__batch_type.expect(algae::Effect::batch(__ops))));
+    quote! {{
+        let __ops: algae::__private::Vec<_> = ::core::iter::IntoIterator::into_iter(#input)
+            .map(::core::convert::Into::into)
+            .collect();
+        if __ops.is_empty() {
+            algae::__private::Vec::new()
+        } else {
+            let __batch_type = algae::__private::BatchType::default();
+            let __reply_opt = #suspended;
+            __batch_type.take(__reply_opt)
+        }
+    }}
+}
+
+/// The expansion of `perform!`; `suspend` turns the effect into the
+/// expression that hands it over and evaluates to the reply.
+fn perform_tokens(
+    input: &syn::Expr,
+    suspend: impl FnOnce(TokenStream2) -> TokenStream2,
+) -> TokenStream2 {
+    // No binding for the effect: it would be held across the yield, which
+    // keeps multi-shot coroutines from being `Clone`.
+    let suspended = suspend(quote!(__reply_type.expect(algae::Effect::new(__op.into()))));
+    // `PerformsOne` and family variants carry their reply type; other
+    // operations infer it.
+    let (fallback, reply_type) = match built_variant(input) {
+        Some(variant) => {
+            let hash = variant_hash(variant);
+            (
+                quote!(InferredVariantReply),
+                quote!((&algae::__private::Variant::<_, _, #hash>::of(&__op)).reply_type()),
+            )
+        }
+        None => (quote!(InferredReply), quote!((&__op).reply_type())),
+    };
     quote! {{
-        let __eff = algae::Effect::new((#input).into());
-        let __reply_opt = yield __eff;
-        __reply_opt.unwrap().take::<_>()
+        #[allow(unused_imports)]
+        use algae::__private::{#fallback as _, TypedReply as _};
+        let __op = #input;
+        let __reply_type = #reply_type;
+        let __reply_opt = #suspended;
+        __reply_type.take(__reply_opt)
     }}
+}
+
+/// The variant an operation is built with, if `expr` looks like
+/// `Family::Variant`, `Family::Variant(..)` or `Family::Variant { .. }`.
+fn built_variant(expr: &syn::Expr) -> Option<&Ident> {
+    let path = match expr {
+        syn::Expr::Path(path) => &path.path,
+        syn::Expr::Call(call) => match &*call.func {
+            syn::Expr::Path(path) => &path.path,
+            _ => return None,
+        },
+        syn::Expr::Struct(strukt) => &strukt.path,
+        _ => return None,
+    };
+    let mut segments = path.segments.iter().rev();
+    let variant = &segments.next()?.ident;
+    let family = &segments.next()?.ident;
+    let capitalized = |ident: &Ident| {
+        let name = ident.to_string();
+        name.trim_start_matches("r#")
+            .starts_with(|c: char| c.is_ascii_uppercase())
+    };
+    (capitalized(variant) && capitalized(family)).then_some(variant)
+}
+
+/// The key of `algae::__private::VariantReply` for operations built with
+/// `variant`: the FNV-1a hash of its name.
+fn variant_hash(variant: &Ident) -> u64 {
+    let name = variant.to_string();
+    name.trim_start_matches("r#")
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Runs a block for every item an operation hands out, performing the
+/// operation again after each one until it replies `None`.
+///
+/// `for_each_perform!(item in op => { ... })` is the loop
+/// `while let Some(item) = perform!(op) { ... }`: `op` is evaluated and
+/// performed once per iteration and must reply with an `Option`, and `item`
+/// can be any irrefutable pattern. A label goes before the pattern, as in
+/// `for_each_perform!('jobs: job in Queue::Next => { ... })`, so that
+/// `break 'jobs` and `continue 'jobs` work from nested loops.
+///
+/// ```ignore
+/// effect! {
+///     Queue::Next -> Option<Job>;
+///     Queue::Ack (JobId) -> ();
+/// }
+///
+/// #[effectful]
+/// fn worker() -> usize {
+///     let mut done = 0;
+///     for_each_perform!(job in Queue::Next => {
+///         run(&job);
+///         let _: () = perform!(Queue::Ack(job.id));
+///         done += 1;
+///     });
+///     done
+/// }
+/// ```
+///
+/// The computation pulls: the handler is asked for the next item only once
+/// the block, and every operation it performs, has finished with the previous
+/// one. A handler answering `Queue::Next` from a bounded channel therefore
+/// holds back its producer when the worker falls behind.
+///
+/// Works in plain, streaming and `async` effectful functions.
+#[proc_macro]
+pub fn for_each_perform(ts: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(ts as ForEachPerform);
+    for_each_perform_tokens(&input, |eff| quote!(yield #eff)).into()
+}
+
+/// The input of `for_each_perform!`: `['label:] pat in op => { body }`.
+struct ForEachPerform {
+    label: Option<syn::Label>,
+    pat: syn::Pat,
+    op: syn::Expr,
+    body: syn::Block,
+}
+
+impl Parse for ForEachPerform {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let label = if input.peek(syn::Lifetime) {
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        let pat = syn::Pat::parse_multi_with_leading_vert(input)?;
+        input.parse::<Token![in]>()?;
+        let op = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let body = input.parse()?;
+        Ok(Self {
+            label,
+            pat,
+            op,
+            body,
+        })
+    }
+}
+
+/// The expansion of `for_each_perform!`, suspending like [`perform_tokens`].
+fn for_each_perform_tokens(
+    input: &ForEachPerform,
+    suspend: impl FnOnce(TokenStream2) -> TokenStream2,
+) -> TokenStream2 {
+    let ForEachPerform {
+        label,
+        pat,
+        op,
+        body,
+    } = input;
+    let next = perform_tokens(op, suspend);
+    quote! {
+        #label while let ::core::option::Option::Some(#pat) = #next #body
+    }
+}
+
+/// Performs an operation when the enclosing `#[effectful]` function ends,
+/// however it ends.
+///
+/// `defer_perform!(op);` performs `op`, ignoring its reply, when the block it
+/// appears in is left: after the last statement, at a `return`, or when the
+/// computation is dropped because the run stopped early, e.g. on an abort.
+/// Operations deferred in the same block are performed in reverse order:
+///
+/// ```ignore
+/// #[effectful]
+/// fn locked_update(key: String) -> u64 {
+///     let lock: LockId = perform!(Locks::Acquire(key.clone()));
+///     defer_perform!(Locks::Release(lock));
+///     let value: u64 = perform!(Store::Get(key.clone()));
+///     perform!(Store::Put((key, value + 1)))
+/// }
+/// ```
+///
+/// The operation is performed by the `run*` driver of the computation, see
+/// `algae::bracket`; it is not available in streams or `async fn`s.
+#[proc_macro]
+pub fn defer_perform(ts: TokenStream) -> TokenStream {
+    let op = parse_macro_input!(ts as syn::Expr);
+    quote! {
+        let __deferred = {
+            let __deferred =
+                algae::bracket::Deferred::perform(::core::convert::Into::into(#op));
+            // Ties the root type to the function's
+            if false {
+                let _ = yield __deferred.__effect();
+            }
+            __deferred
+        };
+    }
+    .into()
+}
+
+/// Passes a value to the caller of a streaming effectful function.
+///
+/// Only valid inside `#[effectful(yields = T)]` functions, which expand it
+/// themselves; `value` must be a `T`. The stream resumes when the caller asks
+/// for the next item.
+///
+/// ```ignore
+/// #[effectful(yields = String)]
+/// fn lines() {
+///     while let Some(line) = perform!(File::ReadLine) {
+///         emit!(line);
+///     }
+/// }
+/// ```
+#[proc_macro]
+pub fn emit(_ts: TokenStream) -> TokenStream {
+    syn::Error::new(
+        proc_macro2::Span::call_site(),
+        "emit! can only be used directly in the body of an #[effectful(yields = ...)] function",
+    )
+    .to_compile_error()
     .into()
 }
 
@@ -896,4 +3911,483 @@ mod tests {
         assert_eq!(first_line.family.to_string(), "ROOT");
         assert_eq!(first_line.variant.to_string(), "GetValue");
     }
+
+    #[test]
+    fn test_effect_input_parsing_use_lines() {
+        // `use` lines can be mixed with operation lines in any order
+        let input: EffectInput = parse_quote! {
+            root AppOp;
+            use algae::effects::progress::Progress;
+            Report::Build (String) -> ();
+            use crate::log::Log;
+            Report::Publish -> bool
+        };
+
+        assert_eq!(input.root_ident.unwrap().to_string(), "AppOp");
+        assert_eq!(input.lines.len(), 2);
+        assert_eq!(input.uses.len(), 2);
+        assert_eq!(input.uses[0].family().to_string(), "Progress");
+        assert_eq!(input.uses[1].family().to_string(), "Log");
+    }
+
+    #[test]
+    fn test_effect_input_parsing_helpers_header() {
+        let input: EffectInput = parse_quote! {
+            helpers;
+            root AppOp;
+            Console::Print (String) -> ();
+        };
+        assert!(input.helpers);
+        assert_eq!(input.root_ident.unwrap().to_string(), "AppOp");
+        assert_eq!(input.lines.len(), 1);
+
+        // A family that happens to be called `helpers` is still an op line
+        let input: EffectInput = parse_quote! { helpers::Get -> i32; };
+        assert!(!input.helpers);
+        assert_eq!(input.lines[0].family.to_string(), "helpers");
+    }
+
+    #[test]
+    fn test_effect_input_parsing_automock_header() {
+        let input: EffectInput = parse_quote! {
+            root AppOp;
+            automock;
+            helpers;
+            Console::Print (String) -> ();
+        };
+        assert!(input.automock);
+        assert!(input.helpers);
+        assert_eq!(input.lines.len(), 1);
+
+        let input: EffectInput = parse_quote! { automock::Get -> i32; };
+        assert!(!input.automock);
+        assert_eq!(input.lines[0].family.to_string(), "automock");
+    }
+
+    #[test]
+    fn test_effect_input_parsing_typed_header() {
+        let input: EffectInput = parse_quote! {
+            typed;
+            root AppOp;
+            Console::Print (String) -> ();
+        };
+        assert!(input.typed);
+        assert_eq!(input.root_ident.unwrap().to_string(), "AppOp");
+
+        let input: EffectInput = parse_quote! { typed::Get -> i32; };
+        assert!(!input.typed);
+        assert_eq!(input.lines[0].family.to_string(), "typed");
+    }
+
+    #[test]
+    fn test_effect_input_parsing_proptest_header() {
+        let input: EffectInput = parse_quote! {
+            proptest;
+            serde;
+            fuzz;
+            Db::Get (String) -> Option<String>;
+        };
+        assert!(input.proptest);
+        assert!(input.serde);
+        assert!(input.fuzz);
+
+        let input: EffectInput = parse_quote! { proptest::Get -> i32; };
+        assert!(!input.proptest);
+        assert_eq!(input.lines[0].family.to_string(), "proptest");
+    }
+
+    #[test]
+    fn test_effect_input_parsing_serde_header() {
+        let input: EffectInput = parse_quote! {
+            serde;
+            typed;
+            Console::Print (String) -> ();
+        };
+        assert!(input.serde);
+        assert!(input.typed);
+
+        let input: EffectInput = parse_quote! { serde::Get -> i32; };
+        assert!(!input.serde);
+        assert_eq!(input.lines[0].family.to_string(), "serde");
+    }
+
+    #[test]
+    fn test_effect_input_parsing_derives() {
+        let input: EffectInput = parse_quote! {
+            #![derive(Eq, Hash)]
+            #![allow(dead_code)]
+            root AppOp;
+            #[derive(PartialOrd)]
+            #[tag(io)]
+            Level::Low -> ();
+        };
+        let names = |paths: &[syn::Path]| -> Vec<String> {
+            paths.iter().map(|p| quote!(#p).to_string()).collect()
+        };
+        assert_eq!(names(&input.derives), ["Eq", "Hash"]);
+        assert_eq!(input.enum_attrs.len(), 1);
+        assert_eq!(input.root_ident.unwrap().to_string(), "AppOp");
+        assert_eq!(names(&input.lines[0].attrs.derives), ["PartialOrd"]);
+        assert_eq!(input.lines[0].attrs.tags, ["io"]);
+
+        let derives = enum_derives(&input.derives).to_string();
+        assert_eq!(
+            derives,
+            quote!(#[derive(Debug, Clone, PartialEq, Eq, Hash)]).to_string()
+        );
+        let repeated: Vec<syn::Path> = vec![parse_quote!(Clone), parse_quote!(Eq)];
+        let derives = enum_derives(&repeated).to_string();
+        assert_eq!(
+            derives,
+            quote!(#[derive(Debug, Clone, PartialEq, Eq)]).to_string()
+        );
+    }
+
+    #[test]
+    fn test_effect_input_parsing_generic_family() {
+        let input: EffectInput = parse_quote! {
+            Cache<K, V: Clone>::Get (K) -> Option<V>;
+            Log::Line (String) -> ();
+            use cache::Cache<String, u64>;
+        };
+        let params: Vec<String> = input.lines[0]
+            .generics
+            .type_params()
+            .map(|p| p.ident.to_string())
+            .collect();
+        assert_eq!(params, ["K", "V"]);
+        assert!(input.lines[1].generics.params.is_empty());
+        assert_eq!(input.uses[0].family().to_string(), "Cache");
+
+        let result = syn::parse2::<EffectInput>(quote! { Cache<'a>::Get -> u8; });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_snake_case_ident() {
+        let cases = [
+            ("Console", "console"),
+            ("ReadLine", "read_line"),
+            ("IO", "io"),
+            ("HTTPGet", "http_get"),
+            ("GetV2Value", "get_v2_value"),
+            ("Already_Snake", "already_snake"),
+            ("Move", "r#move"),
+            ("Crate", "crate_"),
+        ];
+        for (input, expected) in cases {
+            let ident = Ident::new(input, proc_macro2::Span::call_site());
+            assert_eq!(snake_case_ident(&ident).to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_use_line_rejects_generic_arguments_on_modules() {
+        let result = syn::parse_str::<EffectInput>("use my<String>::Cache;");
+        assert!(result.is_err());
+        let result = syn::parse_str::<EffectInput>("use my::Cache<String>;");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_op_line_retryable_attribute() {
+        let line: OpLine = parse_quote! {
+            #[retryable(max = 5, backoff = fixed, delay_ms = 250)]
+            Http::Get (String) -> String
+        };
+        let spec = line.attrs.retryable.unwrap();
+        assert_eq!(spec.max, 5);
+        assert_eq!(spec.backoff.to_string(), "fixed");
+        assert_eq!(spec.delay_ms, 250);
+
+        // A bare attribute uses the defaults
+        let line: OpLine = parse_quote! { #[retryable] Http::Get (String) -> String };
+        let spec = line.attrs.retryable.unwrap();
+        assert_eq!((spec.max, spec.delay_ms), (3, 100));
+        assert_eq!(spec.backoff.to_string(), "exp");
+
+        let plain: OpLine = parse_quote! { Http::Post (String) -> String };
+        assert!(plain.attrs.retryable.is_none());
+        assert!(!plain.attrs.mutates);
+    }
+
+    #[test]
+    fn test_op_line_mutates_attribute() {
+        let line: OpLine = parse_quote! {
+            #[mutates]
+            #[retryable]
+            Account::Deposit (u64) -> ()
+        };
+        assert!(line.attrs.mutates);
+        assert!(line.attrs.retryable.is_some());
+    }
+
+    #[test]
+    fn test_op_line_tag_attributes() {
+        let line: OpLine = parse_quote! {
+            #[tag(io, mutating)]
+            #[tag(slow)]
+            File::Write (String) -> ()
+        };
+        assert_eq!(line.attrs.tags, ["io", "mutating", "slow"]);
+        assert!(line.attrs.priority.is_none());
+
+        let line: OpLine = parse_quote! {
+            #[priority(low)]
+            Log::Line (String) -> ()
+        };
+        assert_eq!(line.attrs.priority.unwrap().to_string(), "low");
+    }
+
+    #[test]
+    fn test_op_line_rejects_bad_attributes() {
+        for src in [
+            "#[cache] Http::Get (String) -> String",
+            "#[retryable(max = 3, backoff = linear)] Http::Get (String) -> String",
+            "#[retryable(tries = 3)] Http::Get (String) -> String",
+            "#[retryable] #[retryable] Http::Get (String) -> String",
+            "#[mutates(always)] Account::Deposit (u64) -> ()",
+            "#[mutates] #[mutates] Account::Deposit (u64) -> ()",
+            "#[tag] File::Write (String) -> ()",
+            "#[tag(io = true)] File::Write (String) -> ()",
+            "#[tag(io, io)] File::Write (String) -> ()",
+            "#[tag(io)] #[tag(io)] File::Write (String) -> ()",
+            "#[priority] Log::Line (String) -> ()",
+            "#[priority(urgent)] Log::Line (String) -> ()",
+            "#[priority(low)] #[priority(high)] Log::Line (String) -> ()",
+        ] {
+            assert!(syn::parse_str::<OpLine>(src).is_err(), "accepted {src}");
+        }
+    }
+
+    #[test]
+    fn test_op_line_rejects_bare_computations() {
+        let error = syn::parse_str::<OpLine>("Tasks::Spawn (Effectful<(), Op>) -> usize")
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("algae::higher::Computation"));
+        assert!(syn::parse_str::<OpLine>("Tasks::Spawn (Computation<(), Op>) -> usize").is_ok());
+    }
+
+    #[test]
+    fn test_route_handler_routes() {
+        let input: syn::DeriveInput = parse_quote! {
+            #[route_handler(partial)]
+            struct App {
+                #[route(Op::State)]
+                state: StateHandler,
+                #[route(Op::Console, Op::Log)]
+                output: Output,
+                started: Instant,
+            }
+        };
+        let routes = Routes::parse(&input).unwrap();
+        assert!(routes.partial);
+        let root = &routes.root;
+        assert_eq!(quote!(#root).to_string(), "Op");
+        let routed: Vec<String> = routes
+            .fields
+            .iter()
+            .map(|(member, _, variants)| format!("{} {}", quote!(#member), variants.len()))
+            .collect();
+        assert_eq!(routed, ["state 1", "output 2"]);
+        assert!(route_handler_tokens(&input).is_ok());
+    }
+
+    #[test]
+    fn test_route_handler_rejects_bad_routes() {
+        let error = |input: syn::DeriveInput| Routes::parse(&input).err().unwrap().to_string();
+
+        assert!(error(parse_quote! {
+            struct App {
+                #[route(Op::State)]
+                a: A,
+                #[route(Op::State)]
+                b: B,
+            }
+        })
+        .contains("`Op::State` is routed twice"));
+        assert!(error(parse_quote! {
+            struct App {
+                #[route(Op::State)]
+                a: A,
+                #[route(OtherOp::Log)]
+                b: B,
+            }
+        })
+        .contains("same root enum, `Op`"));
+        assert!(error(parse_quote! {
+            struct App {
+                #[route(State)]
+                a: A,
+            }
+        })
+        .contains("expected a root enum variant"));
+        assert!(error(parse_quote! {
+            struct App {
+                a: A,
+            }
+        })
+        .contains("at least one field"));
+        assert!(error(parse_quote! {
+            enum App {
+                A,
+            }
+        })
+        .contains("only supports structs"));
+        assert!(error(parse_quote! {
+            #[route_handler(total)]
+            struct App {
+                #[route(Op::State)]
+                a: A,
+            }
+        })
+        .contains("route_handler(partial)"));
+    }
+
+    #[test]
+    fn test_handles_routes_each_family() {
+        let input: syn::DeriveInput = parse_quote! {
+            struct Fixed<T>(T);
+        };
+        let tokens = handles_tokens(&parse_quote!(Clock, io::Console), &input)
+            .unwrap()
+            .to_string();
+        assert!(
+            tokens.contains("impl < T , __Op > algae :: PartialHandler < __Op > for Fixed < T >")
+        );
+        assert!(tokens.contains("__Op : algae :: Contains < io :: Console >"));
+        assert!(tokens.contains("algae :: Handler :: < Clock > :: reply (self , op)"));
+
+        let error = |families| handles_tokens(&families, &input).unwrap_err().to_string();
+        assert!(error(parse_quote!(Clock, Clock)).contains("`Clock` is listed twice"));
+        assert!(error(Punctuated::new()).contains("at least one effect family"));
+    }
+
+    #[test]
+    fn test_perform_recognizes_variant_paths() {
+        let variant = |expr: syn::Expr| built_variant(&expr).map(Ident::to_string);
+        assert_eq!(
+            variant(parse_quote!(Math::Add((1, 2)))).as_deref(),
+            Some("Add")
+        );
+        assert_eq!(
+            variant(parse_quote!(Console::ReadLine)).as_deref(),
+            Some("ReadLine")
+        );
+        assert_eq!(
+            variant(parse_quote!(crate::effects::Cache::<K, V>::Get(key))).as_deref(),
+            Some("Get")
+        );
+        assert_eq!(
+            variant(parse_quote!(Form::Submit { id: 3 })).as_deref(),
+            Some("Submit")
+        );
+        // Helper functions, modules and other expressions are left to inference
+        assert_eq!(variant(parse_quote!(math::add(1, 2))), None);
+        assert_eq!(variant(parse_quote!(ops::Ping)), None);
+        assert_eq!(variant(parse_quote!(Math::Add((1, 2)).into())), None);
+        assert_eq!(variant(parse_quote!(op)), None);
+
+        assert_ne!(
+            variant_hash(&parse_quote!(Add)),
+            variant_hash(&parse_quote!(Get))
+        );
+        assert_eq!(
+            variant_hash(&parse_quote!(r#Move)),
+            variant_hash(&parse_quote!(Move))
+        );
+    }
+
+    #[test]
+    fn test_method_bodies_refer_to_the_owned_receiver() {
+        let body = quote! {{
+            let total = self.count + helper(self);
+            println!("{}", self.name);
+            self::helpers::log(total);
+            impl Local for Other {
+                fn name(&self) -> String { self.0.clone() }
+            }
+            Self::new(total)
+        }};
+        let renamed = rename_self(body).to_string();
+        assert!(renamed.contains("__self . count + helper (__self)"));
+        assert!(renamed.contains("\"{}\" , __self . name"));
+        assert!(renamed.contains("self :: helpers :: log"));
+        assert!(renamed.contains("fn name (& self) -> String { self . 0 . clone () }"));
+        assert!(renamed.contains("Self :: new"));
+
+        let method: syn::ItemFn = parse_quote!(
+            fn get(self: &Self) -> u32 {
+                1
+            }
+        );
+        assert!(borrowed_receiver(&method.sig, false).unwrap());
+        let error = borrowed_receiver(&method.sig, true).unwrap_err();
+        assert!(error.to_string().contains("must take `self` by value"));
+        let method: syn::ItemFn = parse_quote!(
+            fn get(mut self: Box<Self>) -> u32 {
+                1
+            }
+        );
+        assert!(!borrowed_receiver(&method.sig, true).unwrap());
+        let function: syn::ItemFn = parse_quote!(
+            fn get(x: &u32) -> u32 {
+                *x
+            }
+        );
+        assert!(!borrowed_receiver(&function.sig, false).unwrap());
+    }
+
+    #[test]
+    fn test_mutably_borrowed_receivers_are_rejected() {
+        for method in [
+            parse_quote!(
+                fn bump(&mut self) -> u32 {
+                    1
+                }
+            ),
+            parse_quote!(
+                fn bump(self: &mut Self) -> u32 {
+                    1
+                }
+            ),
+        ] {
+            let method: syn::ItemFn = method;
+            let error = borrowed_receiver(&method.sig, false).unwrap_err();
+            assert!(error.to_string().contains("cannot take `&mut self`"));
+            assert!(error.to_string().contains("Arc<Mutex<_>>"));
+        }
+    }
+
+    #[test]
+    fn test_effectful_closure_parsing() {
+        let input: EffectfulClosure = parse_quote!(|x: u32| x + 1);
+        assert!(input.root.is_none());
+        assert_eq!(input.closure.inputs.len(), 1);
+
+        let input: EffectfulClosure = parse_quote!(root = MyOp, move |a, b| -> u32 { a + b });
+        let root = input.root.unwrap();
+        assert_eq!(quote!(#root).to_string(), "MyOp");
+        assert_eq!(input.closure.inputs.len(), 2);
+
+        let err = syn::parse2::<EffectfulClosure>(quote!(base = MyOp, || 1))
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("effectful_closure!(root = YourRootType"));
+    }
+
+    #[test]
+    fn test_for_each_perform_parsing() {
+        let input: ForEachPerform = parse_quote!('jobs: (id, job) in Queue::Next => { run(job); });
+        assert_eq!(input.label.unwrap().name.ident, "jobs");
+        assert!(matches!(input.pat, syn::Pat::Tuple(_)));
+        assert_eq!(input.body.stmts.len(), 1);
+
+        let input: ForEachPerform = parse_quote!(job in Queue::Take { max: 1 } => {});
+        assert!(input.label.is_none());
+        assert!(matches!(input.op, syn::Expr::Struct(_)));
+    }
 }
@@ -1,4 +1,4 @@
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 use std::collections::HashMap;
 
@@ -168,13 +168,11 @@ impl Handler<Op> for MockHandler {
 fn batch_process(filenames: Vec<String>) -> Vec<Result<usize, String>> {
     let _: () = perform!(Logger::Info("Starting batch processing".to_string()));
 
-    let mut results = Vec::new();
-    for filename in filenames {
-        let result = process_file(filename)
-            .handle(ProductionHandler::new())
-            .run();
-        results.push(result);
-    }
+    // One handler serves every file
+    let results: Vec<_> = filenames
+        .into_iter()
+        .effectful_map(process_file)
+        .run_all(ProductionHandler::new());
 
     let file_count = results.len();
     let _: () = perform!(Logger::Info(format!("Processed {file_count} files")));
@@ -205,7 +203,7 @@ fn main() {
     println!("1. Processing single file:");
     let result = process_file("test.txt".to_string())
         .handle(ProductionHandler::new())
-        .run();
+        .run_or_panic();
 
     match result {
         Ok(count) => println!("   Successfully processed file, inserted {count} rows"),
@@ -219,7 +217,7 @@ fn main() {
 
     let mock_result = process_file("test.txt".to_string())
         .handle(mock_handler)
-        .run();
+        .run_or_panic();
 
     match mock_result {
         Ok(count) => println!("   Mock test passed, processed {count} rows"),
@@ -228,14 +226,16 @@ fn main() {
 
     // Example 3: Report generation
     println!("\n3. Generating report:");
-    let report = create_report().handle(ProductionHandler::new()).run();
+    let report = create_report()
+        .handle(ProductionHandler::new())
+        .run_or_panic();
     println!("   {report}");
 
     // Example 4: Error handling
     println!("\n4. Error handling (non-existent file):");
     let error_result = process_file("nonexistent.txt".to_string())
         .handle(ProductionHandler::new())
-        .run();
+        .run_or_panic();
 
     match error_result {
         Ok(_) => println!("   Unexpected success"),
@@ -257,7 +257,9 @@ mod tests {
             .files
             .insert("test.txt".to_string(), "test content".to_string());
 
-        let result = process_file("test.txt".to_string()).handle(handler).run();
+        let result = process_file("test.txt".to_string())
+            .handle(handler)
+            .run_or_panic();
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), 2); // Mock returns 2 rows
@@ -269,7 +271,7 @@ mod tests {
 
         let result = process_file("missing.txt".to_string())
             .handle(handler)
-            .run();
+            .run_or_panic();
 
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Read error"));
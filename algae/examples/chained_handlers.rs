@@ -4,7 +4,7 @@
 //! - `.handle_all()` followed by `.handle()` calls
 //! - Building up handler chains incrementally
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::impl_into_vec_handler;
 use algae::prelude::*;
 
@@ -96,11 +96,11 @@ fn main() {
         .handle(ConsoleHandler) // Add first handler
         .handle(FileHandler) // Add second handler
         .handle(LoggerHandler) // Add third handler
-        .run_checked();
+        .run();
 
     match result {
         Ok(value) => println!("\nComputation succeeded with: {value}"),
-        Err(UnhandledOp(op)) => eprintln!("\nUnhandled operation: {op:?}"),
+        Err(error) => eprintln!("\n{error}"),
     }
 
     println!("\n=== Example 2: Starting with one handler ===\n");
@@ -110,11 +110,11 @@ fn main() {
         .handle_all([ConsoleHandler]) // Start with one
         .handle(FileHandler) // Add second
         .handle(LoggerHandler) // Add third
-        .run_checked();
+        .run();
 
     match result {
         Ok(value) => println!("\nComputation succeeded with: {value}"),
-        Err(UnhandledOp(op)) => eprintln!("\nUnhandled operation: {op:?}"),
+        Err(error) => eprintln!("\n{error}"),
     }
 
     println!("\n=== Example 3: Mixing Handler and PartialHandler ===\n");
@@ -144,11 +144,11 @@ fn main() {
         .handle_all([ConsoleHandler])
         .handle(FileHandler)
         .handle_total(TotalHandler) // This will override the previous handlers
-        .run_checked();
+        .run();
 
     match result {
         Ok(value) => println!("\nComputation succeeded with: {value}"),
-        Err(UnhandledOp(op)) => eprintln!("\nUnhandled operation: {op:?}"),
+        Err(error) => eprintln!("\n{error}"),
     }
 
     println!("\n=== Example 4: Building handler chain dynamically ===\n");
@@ -167,8 +167,8 @@ fn main() {
         handled = handled.handle(LoggerHandler);
     }
 
-    match handled.run_checked() {
+    match handled.run() {
         Ok(value) => println!("\nComputation succeeded with: {value}"),
-        Err(UnhandledOp(op)) => eprintln!("\nUnhandled operation: {op:?}"),
+        Err(error) => eprintln!("\n{error}"),
     }
 }
@@ -3,7 +3,7 @@
 //! This example shows the simplest way to chain partial handlers
 //! using `.begin_chain().handle().handle().handle()`.
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::impl_into_vec_handler;
 use algae::prelude::*;
 
@@ -124,17 +124,17 @@ fn main() {
         .handle(AuthHandler)
         .handle(DatabaseHandler)
         .handle(CacheHandler::new())
-        .run_checked();
+        .run_try();
 
     match result {
-        Ok(Ok(posts)) => {
+        Ok(posts) => {
             println!("\nUser posts:");
             for post in posts {
                 println!("  - {post}");
             }
         }
-        Ok(Err(err)) => println!("\nAuthentication error: {err}"),
-        Err(UnhandledOp(op)) => eprintln!("\nUnhandled operation: {op:?}"),
+        Err(RunError::Failed(err)) => println!("\nAuthentication error: {err}"),
+        Err(err) => eprintln!("\n{err}"),
     }
 
     // Second call should hit the cache
@@ -145,12 +145,11 @@ fn main() {
         .handle(AuthHandler)
         .handle(DatabaseHandler)
         .handle(CacheHandler::new()) // Note: new cache instance, so won't hit
-        .run_checked();
+        .run_try();
 
     match result {
-        Ok(Ok(_)) => println!("Retrieved posts"),
-        Ok(Err(err)) => println!("Error: {err}"),
-        Err(UnhandledOp(op)) => eprintln!("Unhandled: {op:?}"),
+        Ok(_) => println!("Retrieved posts"),
+        Err(err) => println!("Error: {err}"),
     }
 
     // Example with invalid token
@@ -161,11 +160,11 @@ fn main() {
         .handle(AuthHandler)
         .handle(DatabaseHandler)
         .handle(CacheHandler::new())
-        .run_checked();
+        .run_try();
 
     match result {
-        Ok(Ok(_)) => println!("This shouldn't happen"),
-        Ok(Err(err)) => println!("Expected error: {err}"),
-        Err(UnhandledOp(op)) => eprintln!("Unhandled: {op:?}"),
+        Ok(_) => println!("This shouldn't happen"),
+        Err(RunError::Failed(err)) => println!("Expected error: {err}"),
+        Err(err) => eprintln!("{err}"),
     }
 }
@@ -103,8 +103,11 @@ fn program() -> i32 {
 }
 
 // Combined handler that handles both Console and Random effects
+#[derive(RouteHandler)]
 pub struct CombinedHandler {
+    #[route(Op::Console)]
     console_handler: RealConsole,
+    #[route(Op::Random)]
     rand_handler: Rand,
 }
 
@@ -123,17 +126,11 @@ impl CombinedHandler {
     }
 }
 
-impl Handler<Op> for CombinedHandler {
-    fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-        match op {
-            Op::Console(_) => self.console_handler.handle(op),
-            Op::Random(_) => self.rand_handler.handle(op),
-        }
-    }
-}
-
+#[derive(RouteHandler)]
 pub struct MockCombinedHandler {
+    #[route(Op::Console)]
     console_handler: MockConsole,
+    #[route(Op::Random)]
     rand_handler: Rand,
 }
 
@@ -146,23 +143,14 @@ impl MockCombinedHandler {
     }
 }
 
-impl Handler<Op> for MockCombinedHandler {
-    fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-        match op {
-            Op::Console(_) => self.console_handler.handle(op),
-            Op::Random(_) => self.rand_handler.handle(op),
-        }
-    }
-}
-
 fn main() {
     println!("=== Interactive Console Demo ===");
-    let answer = program().handle(CombinedHandler::new()).run();
+    let answer = program().handle(CombinedHandler::new()).run_or_panic();
     println!("Final result: {answer}");
 
     println!("\n=== Mock Console Demo ===");
     let mock_answer = program()
         .handle(MockCombinedHandler::new(vec!["Alice".to_string()]))
-        .run();
+        .run_or_panic();
     println!("Mock result: {mock_answer}");
 }
@@ -3,7 +3,7 @@
 //! This example shows how to use the new `root EnumName;` syntax to avoid
 //! conflicts when using multiple effect! macros in the same module.
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // ============================================================================
@@ -316,23 +316,25 @@ fn main() {
     // Console demo
     let console_result = console_demo()
         .handle(ConsoleHandler::new(vec!["Alice".to_string()]))
-        .run();
+        .run_or_panic();
     println!("Console result: {console_result}\n");
 
     // Math demo
-    let math_result = math_demo(10, 5).handle(MathHandler).run();
+    let math_result = math_demo(10, 5).handle(MathHandler).run_or_panic();
     println!("Math result: {math_result}\n");
 
     // File demo
     let file_result = file_demo("test.txt".to_string())
         .handle(FileHandler::new())
-        .run();
+        .run_or_panic();
     println!("File result: {file_result}\n");
 
     println!("2. Unified handler using combine_roots! macro:");
 
     // Unified demo
-    let unified_result = console_demo_unified().handle(UnifiedHandler::new()).run();
+    let unified_result = console_demo_unified()
+        .handle(UnifiedHandler::new())
+        .run_or_panic();
     println!("Unified result: {unified_result}\n");
 
     println!("=== Key Benefits ===");
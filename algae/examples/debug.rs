@@ -60,6 +60,6 @@ fn main() {
     println!("Converted to main op: {main_op:?}");
 
     // Test effectful function with handler
-    let result = test_function().handle(TestHandler).run();
+    let result = test_function().handle(TestHandler).run_or_panic();
     println!("Result from effectful function: {result}");
 }
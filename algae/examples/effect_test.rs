@@ -1,4 +1,4 @@
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 
 use algae::prelude::*;
 
@@ -41,6 +41,6 @@ fn main() {
         perform!(TestEffect::GetString)
     }
 
-    let result = test_effectful().handle(StringHandler).run();
+    let result = test_effectful().handle(StringHandler).run_or_panic();
     println!("Effectful test result: {result}");
 }
@@ -3,7 +3,7 @@
 //! This example shows that `#[effectful]` and `perform!` are pure convenience macros
 //! that generate exactly the same code as the explicit approach.
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // Define our effects
@@ -172,12 +172,14 @@ fn main() {
     // Both approaches produce identical results
     println!("1. Math calculation example:");
 
-    let result_explicit = calculate_explicit(3, 7).handle(MockHandler::new("")).run();
+    let result_explicit = calculate_explicit(3, 7)
+        .handle(MockHandler::new(""))
+        .run_or_panic();
     println!("Explicit result: {result_explicit}\n");
 
     let result_convenient = calculate_convenient(3, 7)
         .handle(MockHandler::new(""))
-        .run();
+        .run_or_panic();
     println!("Convenient result: {result_convenient}\n");
 
     // Verify they're identical
@@ -189,12 +191,12 @@ fn main() {
 
     let user_explicit = greet_user_explicit()
         .handle(MockHandler::new("Alice"))
-        .run();
+        .run_or_panic();
     println!("Explicit result: {user_explicit}\n");
 
     let user_convenient = greet_user_convenient()
         .handle(MockHandler::new("Alice"))
-        .run();
+        .run_or_panic();
     println!("Convenient result: {user_convenient}\n");
 
     // Verify they're identical
@@ -5,7 +5,7 @@
 //! 2. Multiple effect! with custom root names
 //! 3. Module-based separation for large codebases
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // ============================================================================
@@ -628,7 +628,9 @@ fn main() {
     println!("=== Multiple Effects Patterns Demo ===\n");
 
     println!("1. Single effect! macro with multiple families (traditional approach):");
-    let result1 = comprehensive_demo().handle(UnifiedHandler::new()).run();
+    let result1 = comprehensive_demo()
+        .handle(UnifiedHandler::new())
+        .run_or_panic();
     println!("Traditional result: {result1}\n");
 
     println!("2. Multiple effect! with custom root names:");
@@ -636,19 +638,19 @@ fn main() {
     // Console demo
     let console_result = console_demo_v2()
         .handle(ConsoleHandler::new(vec!["Alice".to_string()]))
-        .run();
+        .run_or_panic();
     println!("Custom Console result: {console_result}");
 
     // Math demo
-    let math_result = math_demo_v2(7, 3).handle(MathHandler).run();
+    let math_result = math_demo_v2(7, 3).handle(MathHandler).run_or_panic();
     println!("Custom Math result: {math_result}");
 
     // File demo
-    let file_result = file_demo_v2().handle(FileHandler::new()).run();
+    let file_result = file_demo_v2().handle(FileHandler::new()).run_or_panic();
     println!("Custom File result: {file_result}");
 
     // Logger demo
-    let logger_result = logger_demo_v2().handle(LoggerHandler::new()).run();
+    let logger_result = logger_demo_v2().handle(LoggerHandler::new()).run_or_panic();
     println!("Custom Logger result: {logger_result} log entries\n");
 
     println!("3. Module-based separation (for large codebases):");
@@ -660,13 +662,13 @@ fn main() {
             "Green".to_string(),
             "Red".to_string(),
         ]))
-        .run();
+        .run_or_panic();
     println!("Console responses: {console_responses:?}");
 
     // Math module demo
     let math_result = math_module::complex_calculation(5)
         .handle(math_module::MathHandler)
-        .run();
+        .run_or_panic();
     println!("Math result: {math_result:?}\n");
 
     println!("=== Approaches Summary ===");
@@ -5,10 +5,10 @@
 //!
 //! To run this example with macros disabled:
 //! ```
-//! cargo run --example no_macros --no-default-features
+//! cargo run --example no_macros --no-default-features --features std
 //! ```
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 use std::any::Any;
 
@@ -102,7 +102,7 @@ fn main() {
     println!("This example demonstrates using algae without the macros feature.");
 
     let handler = MockConsole::new(vec!["Alice".to_string()]);
-    let result = greet_user().handle(handler).run();
+    let result = greet_user().handle(handler).run_or_panic();
 
     println!("Result: {result}");
     println!("\nAs you can see, the core functionality works without macros,");
@@ -1,4 +1,4 @@
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 //! # Algae Overview - Where to Find Examples
 //!
 //! This example provides a roadmap to all the examples and documentation
@@ -39,7 +39,7 @@ fn main() {
     println!("=====================================\n");
 
     // Run a quick demo
-    let result = demo_computation().handle(DemoHandler).run();
+    let result = demo_computation().handle(DemoHandler).run_or_panic();
 
     println!("Demo result: {result}\n");
 
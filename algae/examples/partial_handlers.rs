@@ -6,7 +6,7 @@
 //! - Get Result-based error handling instead of panics
 //! - Build modular, testable effect systems
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // Define our effects
@@ -224,9 +224,12 @@ fn main() {
     vec_handler.push(calculator);
     vec_handler.push(logger);
 
-    match calculator_program().run_checked(vec_handler) {
+    match calculator_program().handle(vec_handler).run() {
         Ok(result) => println!("\nProgram completed successfully with result: {result}"),
-        Err(UnhandledOp(op)) => eprintln!("\nError: Unhandled operation: {op:?}"),
+        Err(EffectError::Unhandled(UnhandledOp { op, .. })) => {
+            eprintln!("\nError: Unhandled operation: {op:?}")
+        }
+        Err(err) => eprintln!("\nError: {err}"),
     }
 
     println!("\n=== Example 2: Missing Handler Demonstration ===\n");
@@ -234,12 +237,13 @@ fn main() {
     // Only provide math handler, missing logger handler
     let calculator_only = CalculatorHandler;
 
-    match risky_program().run_checked(calculator_only) {
+    match risky_program().handle_all([calculator_only]).run() {
         Ok(_) => println!("This shouldn't happen - we're missing the logger!"),
-        Err(UnhandledOp(op)) => {
+        Err(EffectError::Unhandled(UnhandledOp { op, .. })) => {
             println!("As expected, got unhandled operation: {op:?}");
             println!("This is because we didn't provide a Logger handler.");
         }
+        Err(err) => eprintln!("\nError: {err}"),
     }
 
     println!("\n=== Example 3: Using handle_all for Convenience ===\n");
@@ -255,13 +259,14 @@ fn main() {
         Box::new(LoggerHandler::new()),
     ];
 
-    match interactive_calculator().handle_all(handlers).run_checked() {
+    match interactive_calculator().handle_all(handlers).run() {
         Ok(result) => {
             println!("\nInteractive calculation completed: {result:?}");
         }
-        Err(UnhandledOp(op)) => {
+        Err(EffectError::Unhandled(UnhandledOp { op, .. })) => {
             eprintln!("\nUnhandled operation in interactive mode: {op:?}");
         }
+        Err(err) => eprintln!("\nError: {err}"),
     }
 
     println!("\n=== Example 4: Stateful Handler ===\n");
@@ -272,16 +277,17 @@ fn main() {
     vec_handler_stateful.push(CalculatorHandler);
     vec_handler_stateful.push(logger_with_state);
 
-    let result = interactive_calculator().run_checked(vec_handler_stateful);
+    let result = interactive_calculator().handle(vec_handler_stateful).run();
 
     match result {
         Ok(_) => {
             // Note: We can't access logger_with_state here because it was moved
             println!("\nProgram with stateful logger completed");
         }
-        Err(UnhandledOp(op)) => {
+        Err(EffectError::Unhandled(UnhandledOp { op, .. })) => {
             eprintln!("\nUnhandled: {op:?}");
         }
+        Err(err) => eprintln!("\nError: {err}"),
     }
 
     println!("\n=== Example 5: Handler Ordering Matters ===\n");
@@ -308,7 +314,7 @@ fn main() {
     vec1.push(CalculatorHandler);
 
     println!("With interceptor first:");
-    let _ = calculator_program().run_checked(vec1);
+    let _ = calculator_program().handle(vec1).run();
 
     // Real calculator first, then interceptor (interceptor never gets called)
     let mut vec2 = VecHandler::new();
@@ -318,7 +324,7 @@ fn main() {
     vec2.push(InterceptorHandler);
 
     println!("\nWith real calculator first:");
-    let _ = calculator_program().run_checked(vec2);
+    let _ = calculator_program().handle(vec2).run();
 
     println!("\n=== Summary ===\n");
     println!("Partial handlers provide:");
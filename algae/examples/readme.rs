@@ -1,4 +1,4 @@
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // 1. Define your effects
@@ -76,15 +76,16 @@ fn main() {
     println!("Type your name when prompted:");
 
     // Production: use real I/O
-    let result = greet_user().handle(RealConsoleHandler).run();
-
-    println!("Result: {result}");
+    match greet_user().handle(RealConsoleHandler).run() {
+        Ok(result) => println!("Result: {result}"),
+        Err(error) => eprintln!("Run failed: {error}"),
+    }
 
     println!("\n=== Testing Example (Mock I/O) ===");
 
     // Testing: use mock I/O
     let mock_handler = MockConsoleHandler::new(vec!["Alice".to_string()]);
-    let mock_result = greet_user().handle(mock_handler).run();
+    let mock_result = greet_user().handle(mock_handler).run_or_panic();
 
     println!("Mock Result: {mock_result}");
     assert_eq!(mock_result, "Hello, Alice!");
@@ -3,7 +3,7 @@
 //! This example tests the bug fix where #[effectful] was hardcoded to use `Op`
 //! but now supports custom root types via #[effectful(root = CustomOp)].
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // Define effects with custom root
@@ -92,11 +92,11 @@ fn main() {
     // Test custom root type
     let custom_result = greet_and_calculate(10, 20)
         .handle(CustomHandler::new("Alice"))
-        .run();
+        .run_or_panic();
     println!("Custom root result: {custom_result}");
 
     // Test default root type (Op)
-    let simple_result = simple_function().handle(SimpleHandler).run();
+    let simple_result = simple_function().handle(SimpleHandler).run_or_panic();
     println!("Simple result: {simple_result}");
 
     // Verify types are correct at compile time
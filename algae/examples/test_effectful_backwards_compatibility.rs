@@ -3,7 +3,7 @@
 //! This example verifies that existing code using #[effectful] without
 //! the root argument continues to work exactly as before.
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // Traditional effect definition (no custom root)
@@ -44,7 +44,7 @@ fn main() {
 
     let result = traditional_function(15, 25)
         .handle(TraditionalHandler)
-        .run();
+        .run_or_panic();
 
     println!("Final result: {result}");
 
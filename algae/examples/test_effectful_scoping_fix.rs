@@ -4,7 +4,7 @@
 //! - #[effectful] - defaults to Op type
 //! - #[effectful(root = CustomOp)] - uses custom root type
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // Test default behavior
@@ -61,11 +61,11 @@ fn main() {
     println!("Testing #[effectful] argument parsing...");
 
     // Test default behavior
-    let default_result = default_function().handle(DefaultHandler).run();
+    let default_result = default_function().handle(DefaultHandler).run_or_panic();
     println!("Default result: {default_result}");
 
     // Test custom root behavior
-    let custom_result = custom_function().handle(CustomHandler).run();
+    let custom_result = custom_function().handle(CustomHandler).run_or_panic();
     println!("Custom result: {custom_result}");
 
     // Verify type signatures are correct
@@ -3,7 +3,7 @@
 //! This example tests the specific case where #[effectful] doesn't work
 //! when the effect is defined in one scope but the function is in another.
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // Define effects at the top level
@@ -46,7 +46,7 @@ impl Handler<Op> for TestHandler {
 fn main() {
     println!("Testing simple scoping with #[effectful]...");
 
-    let result = inner::inner_function().handle(TestHandler).run();
+    let result = inner::inner_function().handle(TestHandler).run_or_panic();
 
     println!("Final result: {result}");
     println!("✅ Success!");
@@ -1,6 +1,6 @@
 //! Example demonstrating improved error messages for type mismatches
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 effect! {
@@ -39,7 +39,7 @@ fn main() {
     println!("This example demonstrates improved error messages for type mismatches.\n");
 
     println!("1. Trying to get i32 but handler returns String:");
-    let result = std::panic::catch_unwind(|| test_number().handle(BadHandler).run());
+    let result = std::panic::catch_unwind(|| test_number().handle(BadHandler).run_or_panic());
 
     if let Err(panic_payload) = result {
         if let Some(msg) = panic_payload.downcast_ref::<String>() {
@@ -50,7 +50,7 @@ fn main() {
     }
 
     println!("2. Trying to get String but handler returns i32:");
-    let result = std::panic::catch_unwind(|| test_string().handle(BadHandler).run());
+    let result = std::panic::catch_unwind(|| test_string().handle(BadHandler).run_or_panic());
 
     if let Err(panic_payload) = result {
         if let Some(msg) = panic_payload.downcast_ref::<String>() {
@@ -3,7 +3,7 @@
 //! This demonstrates that while we no longer auto-generate Default implementations,
 //! users can still add them manually when appropriate.
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 effect! {
@@ -61,7 +61,7 @@ fn main() {
     println!("Default SimpleOps: {default_simple:?}");
     println!("Default Op: {default_op:?}");
 
-    let result = test_function().handle(TestHandler).run();
+    let result = test_function().handle(TestHandler).run_or_panic();
     println!("Result: {result}");
 
     println!("✅ Success! Manual Default implementations work correctly");
@@ -4,7 +4,7 @@
 //! generated for effect families without proper bounds, causing compilation
 //! errors when payload types don't implement Default.
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // A type that deliberately doesn't implement Default
@@ -65,7 +65,7 @@ impl Handler<Op> for TestHandler {
 fn main() {
     println!("Testing effect! macro with non-Default payload types...");
 
-    let result = test_function().handle(TestHandler).run();
+    let result = test_function().handle(TestHandler).run_or_panic();
     println!("Result: {result}");
 
     println!("✅ Success! effect! macro works with non-Default payload types");
@@ -3,7 +3,7 @@
 //! This example demonstrates that with the + Send bound added to EffectCoroutine,
 //! effectful computations can now be safely transferred between threads.
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 use std::thread;
 
@@ -69,7 +69,7 @@ fn main() {
     let handle = thread::spawn(move || {
         println!("[THREAD] Starting computation in spawned thread");
         let handler = ThreadSafeHandler::new("WORKER");
-        computation.handle(handler).run()
+        computation.handle(handler).run_or_panic()
     });
 
     // Wait for the thread to complete
@@ -82,7 +82,7 @@ fn main() {
         let computation = compute_in_thread(i * 10, i * 5);
         let handle = thread::spawn(move || {
             let handler = ThreadSafeHandler::new(&format!("WORKER-{i}"));
-            computation.handle(handler).run()
+            computation.handle(handler).run_or_panic()
         });
         handles.push(handle);
     }
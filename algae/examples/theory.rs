@@ -1,4 +1,4 @@
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 //! This example demonstrates the mapping between algebraic effects theory
 //! and the algae implementation, as described in the README.
 
@@ -57,8 +57,11 @@ impl Handler<Op> for CounterHandler {
 }
 
 // Combined handler for multiple effect families
+#[derive(RouteHandler)]
 struct CombinedHandler {
+    #[route(Op::Math)]
     math: MathHandler,
+    #[route(Op::Counter)]
     counter: CounterHandler,
 }
 
@@ -71,15 +74,6 @@ impl CombinedHandler {
     }
 }
 
-impl Handler<Op> for CombinedHandler {
-    fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-        match op {
-            Op::Math(_) => self.math.handle(op),
-            Op::Counter(_) => self.counter.handle(op),
-        }
-    }
-}
-
 // Effectful computation that uses multiple effect families
 #[effectful]
 fn complex_computation(a: i32, b: i32) -> i32 {
@@ -124,7 +118,7 @@ fn demonstrate_theory() {
     println!("\n4. Handled Computation returns Effectful<R, Op>");
 
     // 5. Handler Installation → .handle(h).run()
-    println!("\n5. Handler Installation with .handle(handler).run()");
+    println!("\n5. Handler Installation with .handle(handler).run_or_panic()");
 
     println!("\n--- Running the computation ---");
 
@@ -134,7 +128,7 @@ fn demonstrate_theory() {
     // Handler Installation: applies handler to computation
     let result = computation
         .handle(CombinedHandler::new(0)) // Install the handler
-        .run(); // Execute the computation
+        .run_or_panic(); // Execute the computation
 
     println!("\nFinal computation result: {result}");
 
@@ -142,7 +136,7 @@ fn demonstrate_theory() {
     println!("\n--- Same computation, different handler ---");
     let result2 = complex_computation(3, 7)
         .handle(CombinedHandler::new(100)) // Different initial state
-        .run();
+        .run_or_panic();
 
     println!("With different initial state: {result2}");
 }
@@ -180,11 +174,11 @@ fn demonstrate_algebraic_laws() {
     // Demonstrate distributivity: a * (b + c) = a * b + a * c
     let left = demonstrate_distributivity_left(2, 3, 4)
         .handle(MathHandler)
-        .run();
+        .run_or_panic();
 
     let right = demonstrate_distributivity_right(2, 3, 4)
         .handle(MathHandler)
-        .run();
+        .run_or_panic();
 
     println!("Distributivity law: 2 * (3 + 4) = 2 * 3 + 2 * 4");
     println!("Left side:  {left} (should be 14)");
@@ -193,7 +187,9 @@ fn demonstrate_algebraic_laws() {
     println!("Law holds: {holds}");
 
     // Demonstrate associativity with different computations
-    let assoc_result = demonstrate_associativity().handle(MathHandler).run();
+    let assoc_result = demonstrate_associativity()
+        .handle(MathHandler)
+        .run_or_panic();
 
     println!("\nAssociativity: (2 + 3) * 4 = {assoc_result}");
 }
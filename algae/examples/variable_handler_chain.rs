@@ -3,10 +3,10 @@
 //! This example shows how to:
 //! - Chain multiple handlers together
 //! - Mix Handler and PartialHandler types
-//! - Use run_checked for panic-free execution
+//! - Use run for panic-free execution
 //! - Handle unhandled operations gracefully
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::prelude::*;
 
 // Define our effects
@@ -170,9 +170,9 @@ fn main() {
         Box::new(LoggerHandler::new()),
     ];
 
-    match three_handler_example().handle_all(handlers).run_checked() {
+    match three_handler_example().handle_all(handlers).run() {
         Ok(result) => println!("\nResult: {result}"),
-        Err(UnhandledOp(op)) => eprintln!("\nError: Unhandled operation: {op:?}"),
+        Err(error) => eprintln!("\nError: {error}"),
     }
 
     println!("\n=== Example 2: Interactive Application ===\n");
@@ -183,10 +183,13 @@ fn main() {
     vec_handler.push(FileHandler::new());
     vec_handler.push(LoggerHandler::new());
 
-    match interactive_app().run_checked(vec_handler) {
+    match interactive_app().handle(vec_handler).run() {
         Ok(Ok(result)) => println!("\nSuccess: {result}"),
         Ok(Err(err)) => println!("\nApplication error: {err}"),
-        Err(UnhandledOp(op)) => eprintln!("\nUnhandled operation: {op:?}"),
+        Err(EffectError::Unhandled(UnhandledOp { op, .. })) => {
+            eprintln!("\nUnhandled operation: {op:?}")
+        }
+        Err(err) => eprintln!("\nError: {err}"),
     }
 
     println!("\n=== Example 3: Missing Handler Demonstration ===\n");
@@ -197,12 +200,13 @@ fn main() {
     partial_handlers.push(FileHandler::new());
     // Note: LoggerHandler is missing!
 
-    match interactive_app().run_checked(partial_handlers) {
+    match interactive_app().handle(partial_handlers).run() {
         Ok(_) => println!("This shouldn't happen - we're missing the logger!"),
-        Err(UnhandledOp(op)) => {
+        Err(EffectError::Unhandled(UnhandledOp { op, .. })) => {
             println!("As expected, got unhandled operation: {op:?}");
             println!("This demonstrates safe error handling without panics.");
         }
+        Err(err) => eprintln!("\nError: {err}"),
     }
 
     println!("\n=== Example 4: Total Handler Wrapping ===\n");
@@ -231,17 +235,18 @@ fn main() {
         logger: LoggerHandler::new(),
     };
 
-    // Total handlers can use run() (may panic) or run_checked_with() (safe)
-    match interactive_app().run_checked_with(total_handler) {
+    // Total handlers run the same way, or with run_or_panic() where a
+    // failed run is a bug
+    match interactive_app().handle(total_handler).run() {
         Ok(Ok(result)) => println!("\nWith total handler: {result}"),
         Ok(Err(err)) => println!("\nApplication error: {err}"),
-        Err(_) => unreachable!("Total handler handles everything"),
+        Err(error) => eprintln!("\nRun failed: {error}"),
     }
 
     println!("\n=== Summary ===");
     println!("✅ Variable-length handler chains enable modular effect handling");
     println!("✅ PartialHandler allows handlers to decline operations");
-    println!("✅ run_checked() provides panic-free execution");
+    println!("✅ run() provides panic-free execution");
     println!("✅ Both Handler and PartialHandler types are supported");
     println!("✅ Unhandled operations return clear error information");
 }
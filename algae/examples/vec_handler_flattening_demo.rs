@@ -3,7 +3,7 @@
 //! This example shows that when you pass a VecHandler to .handle(), its contents
 //! are properly flattened into the receiving VecHandler, avoiding nested structures.
 
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 use algae::impl_into_vec_handler;
 use algae::prelude::*;
 
@@ -82,11 +82,11 @@ fn main() {
             .begin_chain()
             .handle(vec1) // Pass entire VecHandler
             .handle(vec2) // Pass another VecHandler
-            .run_checked();
+            .run();
 
         match result {
             Ok(n) => println!("Result: {n}\n"),
-            Err(UnhandledOp(op)) => println!("Unhandled: {op:?}\n"),
+            Err(error) => println!("{error}\n"),
         }
     }
 
@@ -103,11 +103,11 @@ fn main() {
             .begin_chain()
             .handle(vec1)
             .handle(vec2)
-            .run_checked();
+            .run();
 
         match result {
             Ok(n) => println!("Result: {n}\n"),
-            Err(UnhandledOp(op)) => println!("Unhandled: {op:?}\n"),
+            Err(error) => println!("{error}\n"),
         }
     }
 
@@ -124,11 +124,11 @@ fn main() {
             .begin_chain()
             .handle(vec1)
             .handle(vec2)
-            .run_checked();
+            .run();
 
         match result {
             Ok(n) => println!("Result: {n}\n"),
-            Err(UnhandledOp(op)) => println!("Unhandled: {op:?}\n"),
+            Err(error) => println!("{error}\n"),
         }
     }
 
@@ -146,11 +146,11 @@ fn main() {
             .begin_chain()
             .handle(vec2) // SquareHandler first
             .handle(vec1) // Other handlers second
-            .run_checked();
+            .run();
 
         match result {
             Ok(n) => println!("Result: {n} (SquareHandler took precedence)\n"),
-            Err(UnhandledOp(op)) => println!("Unhandled: {op:?}\n"),
+            Err(error) => println!("{error}\n"),
         }
     }
 
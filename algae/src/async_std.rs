@@ -0,0 +1,111 @@
+//! Spawning computations on async-std (feature `async-std`).
+//!
+//! [`AsyncStd`] implements [`Spawner`] with async-std's task functions, so
+//! handled computations can run as async-std tasks:
+//!
+//! ```rust,ignore
+//! use algae::async_std::AsyncStd;
+//! use algae::executor::Spawner;
+//!
+//! #[async_std::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let report = AsyncStd.spawn_handled(build_report(), ReportHandler::new()).await?;
+//!     println!("{report}");
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Dropping a handle detaches the task; it keeps running.
+
+use crate::executor::Spawner;
+use ::async_std::task::{self, JoinHandle};
+use std::future::Future;
+
+/// Spawns tasks with `async_std::task::spawn`, and blocking work with
+/// `async_std::task::spawn_blocking`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStd;
+
+impl Spawner for AsyncStd {
+    type Handle<T: Send + 'static> = JoinHandle<T>;
+
+    fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        task::spawn(future)
+    }
+
+    fn spawn_blocking<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        task::spawn_blocking(f)
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::executor::{AsyncHandler, BoxFuture};
+    use algae::prelude::*;
+    use std::{any::Any, time::Duration};
+
+    effect! {
+        Clock::Sleep (u64) -> ();
+        Counter::Add (u32) -> u32;
+    }
+
+    struct Timer;
+
+    impl AsyncHandler<Op> for Timer {
+        fn maybe_handle<'a>(
+            &'a mut self,
+            op: &'a Op,
+        ) -> BoxFuture<'a, Option<Box<dyn Any + Send>>> {
+            Box::pin(async move {
+                let Op::Clock(Clock::Sleep(ms)) = op else {
+                    return None;
+                };
+                task::sleep(Duration::from_millis(*ms)).await;
+                Some(Box::new(()) as Box<dyn Any + Send>)
+            })
+        }
+    }
+
+    struct Tally(u32);
+
+    impl PartialHandler<Op> for Tally {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Counter(Counter::Add(n)) = op else {
+                return None;
+            };
+            self.0 += n;
+            Some(Box::new(self.0))
+        }
+    }
+
+    #[effectful]
+    fn count_slowly(n: u32) -> u32 {
+        let mut total = 0;
+        for _ in 0..n {
+            let _: () = perform!(Clock::Sleep(1));
+            total = perform!(Counter::Add(1));
+        }
+        total
+    }
+
+    #[test]
+    fn test_spawned_computations() {
+        let (handled, async_handled) = task::block_on(async {
+            let handled = AsyncStd.spawn_handled(count_slowly(0), Tally(10));
+            let async_handled = AsyncStd.spawn_async(count_slowly(3), Timer, Tally(0));
+            (handled.await, async_handled.await)
+        });
+        assert_eq!(handled.unwrap(), 0);
+        assert_eq!(async_handled.unwrap(), 3);
+    }
+}
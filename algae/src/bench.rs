@@ -0,0 +1,201 @@
+//! Utilities for benchmarking computations and handlers.
+//!
+//! Timing a computation against a real handler measures both the cost of
+//! performing operations and the work the handler does. [`NoopHandler`]
+//! answers every operation with a precomputed reply and does nothing else,
+//! so it gives the baseline: what `perform!` and dispatch cost on their own.
+//! [`Counted`] counts the operations a real handler answers, to turn a
+//! timing into a per-operation cost.
+//!
+//! ```rust,ignore
+//! use algae::bench::{self, Counted, NoopHandler};
+//! use criterion::Criterion;
+//!
+//! fn dispatch(c: &mut Criterion) {
+//!     let mut noop = NoopHandler::new(());
+//!     c.bench_function("log 100 lines, no-op", |b| {
+//!         b.iter(|| bench::run(log_lines(100), &mut noop))
+//!     });
+//!     let mut real = Counted::new(MemoryLog::new());
+//!     c.bench_function("log 100 lines, MemoryLog", |b| {
+//!         b.iter(|| bench::run(log_lines(100), &mut real))
+//!     });
+//! }
+//! ```
+
+use crate::{Effectful, Handler, OpMeta, PartialHandler, Reply};
+use alloc::boxed::Box;
+use core::{any::Any, fmt, hint::black_box};
+
+/// Answers every operation with a clone of the same reply, counting them.
+///
+/// Suits computations whose operations all return the reply's type, which
+/// is usually `()`. Small replies are kept inline (see [`Reply::new`]), so
+/// a dispatch costs no more than the computation's own bookkeeping.
+#[derive(Debug, Clone, Default)]
+pub struct NoopHandler<T> {
+    reply: T,
+    count: u64,
+}
+
+impl<T> NoopHandler<T> {
+    /// A handler answering every operation with `reply`.
+    pub fn new(reply: T) -> Self {
+        Self { reply, count: 0 }
+    }
+
+    /// The number of operations answered so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<Op, T: Clone + Send + 'static> PartialHandler<Op> for NoopHandler<T> {
+    fn maybe_handle(&mut self, _op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.count += 1;
+        Some(Box::new(self.reply.clone()))
+    }
+
+    fn maybe_reply(&mut self, _op: &Op) -> Option<Reply> {
+        self.count += 1;
+        Some(Reply::new(self.reply.clone()))
+    }
+}
+
+impl<Op, T: Clone + Send + 'static> Handler<Op> for NoopHandler<T> {
+    fn handle(&mut self, _op: &Op) -> Box<dyn Any + Send> {
+        self.count += 1;
+        Box::new(self.reply.clone())
+    }
+
+    fn reply(&mut self, _op: &Op) -> Reply {
+        self.count += 1;
+        Reply::new(self.reply.clone())
+    }
+}
+
+/// Passes operations to `inner`, counting those it answers.
+#[derive(Debug, Clone, Default)]
+pub struct Counted<H> {
+    inner: H,
+    count: u64,
+}
+
+impl<H> Counted<H> {
+    pub fn new(inner: H) -> Self {
+        Self { inner, count: 0 }
+    }
+
+    /// The number of operations answered so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<Op, H: PartialHandler<Op>> PartialHandler<Op> for Counted<H> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let reply = self.inner.maybe_handle(op)?;
+        self.count += 1;
+        Some(reply)
+    }
+
+    fn maybe_reply(&mut self, op: &Op) -> Option<Reply> {
+        let reply = self.inner.maybe_reply(op)?;
+        self.count += 1;
+        Some(reply)
+    }
+}
+
+/// Runs `computation` with `h`, which keeps its state for the next run, and
+/// passes the result through [`black_box`].
+///
+/// # Panics
+///
+/// Panics if an operation is unhandled or aborted: a benchmark that does
+/// not run to the end measures the wrong thing.
+pub fn run<R, Op, H>(computation: Effectful<R, Op>, h: &mut H) -> R
+where
+    Op: fmt::Debug + OpMeta,
+    H: PartialHandler<Op>,
+{
+    match computation.drive(|op| h.maybe_reply(op)) {
+        Ok(result) => black_box(result),
+        Err(error) => panic!("benchmarked computation failed: {error}"),
+    }
+}
+
+/// Runs `n` computations built by `make` one after another with the same
+/// handler, as [`run`] does; for timing loops outside a benchmark framework.
+pub fn run_n<R, Op, H, F>(n: usize, mut make: F, h: &mut H)
+where
+    Op: fmt::Debug + OpMeta + 'static,
+    H: PartialHandler<Op>,
+    F: FnMut() -> Effectful<R, Op>,
+{
+    for _ in 0..n {
+        run(make(), h);
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        Log::Line (u32) -> ();
+        Log::Flush -> ();
+    }
+
+    #[effectful]
+    fn log_lines(n: u32) -> u32 {
+        for i in 0..n {
+            let _: () = perform!(Log::Line(i));
+        }
+        let _: () = perform!(Log::Flush);
+        n
+    }
+
+    /// Answers only `Log::Line`.
+    struct Lines;
+
+    impl PartialHandler<Op> for Lines {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            matches!(op, Op::Log(Log::Line(_))).then(|| Box::new(()) as Box<dyn Any + Send>)
+        }
+    }
+
+    #[test]
+    fn test_noop_handler_counts_operations() {
+        let mut noop = NoopHandler::new(());
+        assert_eq!(run(log_lines(3), &mut noop), 3);
+        run_n(2, || log_lines(1), &mut noop);
+        assert_eq!(noop.count(), 4 + 2 * 2);
+        assert_eq!(log_lines(2).handle(NoopHandler::new(())).run_or_panic(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "benchmarked computation failed")]
+    fn test_failed_runs_panic() {
+        let mut lines = Counted::new(Lines);
+        run(log_lines(2), &mut lines);
+    }
+
+    #[test]
+    fn test_counted_counts_answered_operations() {
+        let mut lines = Counted::new(VecHandler::new());
+        lines.inner.push(Lines);
+        lines.inner.push(NoopHandler::new(()));
+        run(log_lines(5), &mut lines);
+        assert_eq!(lines.count(), 6);
+    }
+}
@@ -0,0 +1,188 @@
+//! Driving computations through a trait object.
+//!
+//! [`Drive`] is the step-by-step interface of a computation: resume it with
+//! the reply to its last operation and it either yields the next one or
+//! returns. The trait is dyn-compatible, so computations built in different
+//! ways can be stored together as [`BoxedEffectful`]s, queued, and run by a
+//! worker that does not know how each one is implemented:
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::boxed::{BoxedEffectful, Drive};
+//! use std::collections::VecDeque;
+//!
+//! let mut queue: VecDeque<BoxedEffectful<String, Op>> = VecDeque::new();
+//! queue.push_back(greet("alice").boxed());
+//! queue.push_back(Box::new(ScriptedSession::new(lines)));
+//!
+//! let mut handler = AppHandler::new();
+//! while let Some(mut job) = queue.pop_front() {
+//!     println!("{:?}", job.try_run_with(&mut handler));
+//! }
+//! ```
+//!
+//! An [`Effectful`] already keeps its coroutine behind a box, so
+//! computations of different `#[effectful]` functions fit a
+//! `Vec<Effectful<R, Op>>` as they are; a [`BoxedEffectful`] can also hold
+//! drivers that are not coroutines, e.g. hand-written state machines or
+//! adapters around another runtime.
+
+use crate::{dispatch_effect, Effect, EffectError, Effectful, PartialHandler, Reply};
+use alloc::boxed::Box;
+use core::ops::CoroutineState;
+
+/// A computation that can be resumed one operation at a time.
+pub trait Drive<Op: 'static> {
+    /// The value the computation returns.
+    type Output;
+
+    /// Resumes the computation up to its next operation or its end. `reply`
+    /// answers the operation yielded last, and is `None` on the first call.
+    ///
+    /// Implementations may panic if resumed after returning.
+    fn resume(&mut self, reply: Option<Reply>) -> CoroutineState<Effect<Op>, Self::Output>;
+
+    /// Resumes the computation to its end, answering its operations with
+    /// `h`.
+    ///
+    /// Fails like [`Effectful::try_run_with`]; the computation must not be
+    /// resumed afterwards.
+    fn try_run_with(
+        &mut self,
+        h: &mut dyn PartialHandler<Op>,
+    ) -> Result<Self::Output, EffectError<Op>> {
+        let mut reply = None;
+        loop {
+            match self.resume(reply) {
+                CoroutineState::Yielded(eff) => {
+                    reply = Some(dispatch_effect(eff, &mut |op| h.maybe_handle(op))?);
+                }
+                CoroutineState::Complete(result) => return Ok(result),
+            }
+        }
+    }
+}
+
+/// A computation behind a [`Drive`] trait object.
+pub type BoxedEffectful<R, Op> = Box<dyn Drive<Op, Output = R> + Send>;
+
+impl<R, Op: 'static> Drive<Op> for Effectful<R, Op> {
+    type Output = R;
+
+    fn resume(&mut self, reply: Option<Reply>) -> CoroutineState<Effect<Op>, R> {
+        self.step(reply)
+    }
+}
+
+impl<Op: 'static, D: Drive<Op> + ?Sized> Drive<Op> for Box<D> {
+    type Output = D::Output;
+
+    fn resume(&mut self, reply: Option<Reply>) -> CoroutineState<Effect<Op>, D::Output> {
+        (**self).resume(reply)
+    }
+}
+
+impl<R: 'static, Op: 'static> Effectful<R, Op> {
+    /// Boxes the computation as a [`Drive`] trait object.
+    pub fn boxed(self) -> BoxedEffectful<R, Op> {
+        Box::new(self)
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::{any::Any, collections::VecDeque};
+
+    effect! {
+        Console::Print (String) -> ();
+        Console::ReadLine -> String;
+    }
+
+    struct Terminal {
+        input: VecDeque<&'static str>,
+        output: Vec<String>,
+    }
+
+    impl PartialHandler<Op> for Terminal {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Console(Console::Print(line)) => {
+                    self.output.push(line.clone());
+                    Some(Box::new(()))
+                }
+                Op::Console(Console::ReadLine) => {
+                    Some(Box::new(self.input.pop_front()?.to_string()))
+                }
+            }
+        }
+    }
+
+    #[effectful]
+    fn greet() -> String {
+        let name: String = perform!(Console::ReadLine);
+        let _: () = perform!(Console::Print(format!("hello {name}")));
+        name
+    }
+
+    /// Prints its lines one by one, then returns how many there were.
+    struct Announcer {
+        lines: Vec<String>,
+        printed: usize,
+    }
+
+    impl Drive<Op> for Announcer {
+        type Output = String;
+
+        fn resume(&mut self, _reply: Option<Reply>) -> CoroutineState<Effect<Op>, String> {
+            match self.lines.get(self.printed) {
+                Some(line) => {
+                    self.printed += 1;
+                    CoroutineState::Yielded(Effect::new(Console::Print(line.clone()).into()))
+                }
+                None => CoroutineState::Complete(format!("{} lines", self.printed)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_heterogeneous_queue() {
+        let mut queue: VecDeque<BoxedEffectful<String, Op>> = VecDeque::new();
+        queue.push_back(greet().boxed());
+        queue.push_back(Box::new(Announcer {
+            lines: vec!["a".into(), "b".into()],
+            printed: 0,
+        }));
+        queue.push_back(greet().boxed());
+
+        let mut terminal = Terminal {
+            input: VecDeque::from(["ann", "bob"]),
+            output: Vec::new(),
+        };
+        let results: Vec<String> = queue
+            .iter_mut()
+            .map(|job| job.try_run_with(&mut terminal).unwrap())
+            .collect();
+        assert_eq!(results, ["ann", "2 lines", "bob"]);
+        assert_eq!(terminal.output, ["hello ann", "a", "b", "hello bob"]);
+    }
+
+    #[test]
+    fn test_driving_step_by_step_and_failures() {
+        let mut job = greet().boxed();
+        let CoroutineState::Yielded(eff) = job.resume(None) else {
+            panic!("greet reads a line first")
+        };
+        assert!(matches!(eff.op, Op::Console(Console::ReadLine)));
+
+        let mut terminal = Terminal {
+            input: VecDeque::new(),
+            output: Vec::new(),
+        };
+        let err = greet().boxed().try_run_with(&mut terminal).unwrap_err();
+        assert!(matches!(err, EffectError::Unhandled(_)));
+    }
+}
@@ -0,0 +1,384 @@
+//! Releasing resources acquired through effects.
+//!
+//! A computation that acquires a resource with one operation and releases it
+//! with another leaks the resource when it never gets to the release: when a
+//! handler aborts the run, or when the computation is dropped before it
+//! finishes. [`Effectful::bracket`] pairs the acquisition with its release,
+//! and `defer_perform!` schedules an operation for the end of the enclosing
+//! `#[effectful]` function:
+//!
+//! ```rust,ignore
+//! let copied = Effectful::bracket(
+//!     open(path),                  // Effectful<Fd, Op>
+//!     |fd| copy_to(fd, dest),      // Effectful<usize, Op>
+//!     |fd| close(fd),              // Effectful<(), Op>
+//! );
+//!
+//! #[effectful]
+//! fn locked_update(key: String) -> u64 {
+//!     let lock: LockId = perform!(Locks::Acquire(key.clone()));
+//!     defer_perform!(Locks::Release(lock));
+//!     let value: u64 = perform!(Store::Get(key.clone()));
+//!     perform!(Store::Put((key, value + 1)))
+//! }
+//! ```
+//!
+//! The `run*` drivers and [`HandlerSession`](crate::session::HandlerSession)
+//! perform a pending release with the handlers of the run:
+//!
+//! - when the computation reaches it, in order with its other operations,
+//! - when the run stops early, because an operation was aborted, declined or
+//!   answered with the wrong type, before the error is returned.
+//!
+//! A release is also performed when its computation is dropped in the middle
+//! of a run, e.g. by [`with_handler`](Effectful::with_handler) after a local
+//! abort. Releases of computations that are dropped outside of a run, or
+//! while a panic unwinds, are dropped without being performed, as are those
+//! of a different root type than the run's, e.g. of a computation run with
+//! [`lift_into`](Effectful::lift_into).
+
+use crate::{dispatch_in_run, Dispatch, Effect, EffectError, Effectful, Reply};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    ops::CoroutineState,
+    thread,
+};
+
+thread_local! {
+    /// How many runs are active on this thread, one inside the other.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+
+    /// The releases registered while a run was active, with the depth of
+    /// that run; the innermost run's come last.
+    static PENDING: RefCell<Vec<(usize, Box<dyn Any + Send>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Takes out the releases registered with the run at `depth`.
+fn take_registered(depth: usize) -> Vec<(usize, Box<dyn Any + Send>)> {
+    PENDING.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        let own = pending
+            .iter()
+            .position(|&(registered_at, _)| registered_at == depth)
+            .unwrap_or(pending.len());
+        pending.split_off(own)
+    })
+}
+
+/// Marks a run as active on this thread; releases registered meanwhile are
+/// performed by it.
+pub(crate) struct ActiveRun {
+    depth: usize,
+}
+
+impl ActiveRun {
+    pub(crate) fn enter() -> Self {
+        let depth = DEPTH.with(|depth| depth.get() + 1);
+        DEPTH.with(|current| current.set(depth));
+        Self { depth }
+    }
+
+    /// Performs the releases registered so far with `dispatch`, and those
+    /// they register in turn, returning the first error.
+    ///
+    /// Every release is performed even if an earlier one fails.
+    pub(crate) fn release<Op: 'static, D: Dispatch<Op>>(
+        &self,
+        handler: &'static str,
+        dispatch: &mut D,
+    ) -> Result<(), EffectError<Op>> {
+        let mut outcome = Ok(());
+        loop {
+            // Taken out before they are dropped, which may register more
+            let registered = take_registered(self.depth);
+            if registered.is_empty() {
+                return outcome;
+            }
+            for (_, release) in registered {
+                let Ok(release) = release.downcast::<Effectful<(), Op>>() else {
+                    continue;
+                };
+                let performed = release.drive_released(handler, dispatch);
+                if outcome.is_ok() {
+                    outcome = performed;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ActiveRun {
+    fn drop(&mut self) {
+        drop(take_registered(self.depth));
+        DEPTH.with(|depth| depth.set(self.depth - 1));
+    }
+}
+
+impl<Op: 'static> Effectful<(), Op> {
+    /// Runs a release to its end with `dispatch`.
+    fn drive_released<D: Dispatch<Op>>(
+        mut self,
+        handler: &'static str,
+        dispatch: &mut D,
+    ) -> Result<(), EffectError<Op>> {
+        let mut reply = None;
+        loop {
+            match self.step(reply.take()) {
+                CoroutineState::Yielded(eff) => {
+                    reply = Some(dispatch_in_run(eff, handler, dispatch)?)
+                }
+                CoroutineState::Complete(()) => return Ok(()),
+            }
+        }
+    }
+}
+
+/// A release that is registered with the active run when dropped, unless it
+/// was [`disarm`](Deferred::disarm)ed.
+///
+/// This is what `defer_perform!` and [`Effectful::bracket`] keep until the
+/// computation ends.
+pub struct Deferred<Op: 'static> {
+    release: Option<Effectful<(), Op>>,
+}
+
+impl<Op: 'static> Deferred<Op> {
+    /// Defers running `release` until the computation ends.
+    pub fn new(release: Effectful<(), Op>) -> Self {
+        Self {
+            release: Some(release),
+        }
+    }
+
+    /// Defers performing `op`, ignoring its reply.
+    #[track_caller]
+    pub fn perform(op: Op) -> Self
+    where
+        Op: Send,
+    {
+        let eff = Effect::new(op);
+        Self::new(Effectful::new(
+            #[coroutine]
+            move |_: Option<Reply>| {
+                let _ = yield eff;
+            },
+        ))
+    }
+
+    /// Takes the release back, e.g. to run it as part of the computation.
+    pub fn disarm(mut self) -> Option<Effectful<(), Op>> {
+        self.release.take()
+    }
+
+    #[doc(hidden)]
+    pub fn __effect(&self) -> Effect<Op> {
+        unreachable!("only mentioned to infer the root type")
+    }
+}
+
+impl<Op: 'static> Drop for Deferred<Op> {
+    fn drop(&mut self) {
+        let Some(release) = self.release.take() else {
+            return;
+        };
+        if thread::panicking() {
+            return;
+        }
+        let depth = DEPTH.with(Cell::get);
+        if depth == 0 {
+            return;
+        }
+        let release: Box<dyn Any + Send> = Box::new(release);
+        PENDING.with(|pending| pending.borrow_mut().push((depth, release)));
+    }
+}
+
+impl<R, Op: 'static> Effectful<R, Op> {
+    /// Acquires a resource with `acquire`, runs `use_` with it, and releases
+    /// it with `release`, even if the run stops early.
+    ///
+    /// On success the release runs after `use_`, and the result of `use_` is
+    /// returned. If the run stops before, the release is performed by the
+    /// driver as described in the [module docs](crate::bracket). An
+    /// acquisition that does not complete is not released.
+    pub fn bracket<A, U, F>(acquire: Effectful<A, Op>, use_: U, release: F) -> Self
+    where
+        A: Clone + Send + 'static,
+        U: FnOnce(A) -> Effectful<R, Op> + Send + 'static,
+        F: FnOnce(A) -> Effectful<(), Op> + Send + 'static,
+        R: Send + 'static,
+        Op: Send,
+    {
+        let mut acquire = acquire;
+        Effectful::new(
+            #[coroutine]
+            move |_: Option<Reply>| {
+                let mut reply = None;
+                let resource = loop {
+                    match acquire.step(reply.take()) {
+                        CoroutineState::Yielded(eff) => reply = yield eff,
+                        CoroutineState::Complete(resource) => break resource,
+                    }
+                };
+                let mut body = use_(resource.clone());
+                let deferred = Deferred::new(release(resource));
+                let result = loop {
+                    match body.step(reply.take()) {
+                        CoroutineState::Yielded(eff) => reply = yield eff,
+                        CoroutineState::Complete(result) => break result,
+                    }
+                };
+                drop(body);
+                if let Some(mut release) = deferred.disarm() {
+                    while let CoroutineState::Yielded(eff) = release.step(reply.take()) {
+                        reply = yield eff;
+                    }
+                }
+                result
+            },
+        )
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        File::Open (String) -> u32;
+        File::Read (u32) -> String;
+        File::Close (u32) -> ();
+        Lock::Acquire -> ();
+        Lock::Release -> ();
+    }
+
+    /// Files whose reads abort for names starting with "bad", and a log of
+    /// the operations answered.
+    #[derive(Default)]
+    struct Files {
+        log: Vec<String>,
+        names: Vec<String>,
+    }
+
+    impl PartialHandler<Op> for Files {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            self.log.push(format!("{op:?}"));
+            Some(match op {
+                Op::File(File::Open(name)) => {
+                    self.names.push(name.clone());
+                    Box::new(self.names.len() as u32 - 1)
+                }
+                Op::File(File::Read(fd)) if self.names[*fd as usize].starts_with("bad") => {
+                    Abort::boxed("unreadable")
+                }
+                Op::File(File::Read(fd)) => Box::new(self.names[*fd as usize].to_uppercase()),
+                Op::File(File::Close(_)) | Op::Lock(_) => Box::new(()),
+            })
+        }
+    }
+
+    /// Aborts every read.
+    struct Offline;
+
+    impl PartialHandler<Op> for Offline {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            matches!(op, Op::File(File::Read(_))).then(|| Abort::boxed("offline"))
+        }
+    }
+
+    #[effectful]
+    fn open(name: String) -> u32 {
+        perform!(File::Open(name))
+    }
+
+    #[effectful]
+    fn read(fd: u32) -> String {
+        perform!(File::Read(fd))
+    }
+
+    #[effectful]
+    fn close(fd: u32) {
+        perform!(File::Close(fd))
+    }
+
+    fn read_file(name: &str) -> Effectful<String, Op> {
+        Effectful::bracket(open(name.to_string()), read, close)
+    }
+
+    #[test]
+    fn test_bracket_releases_after_use() {
+        let (text, files) = read_file("notes").try_run_with_handler(Files::default());
+        assert_eq!(text.unwrap(), "NOTES");
+        assert_eq!(
+            files.log,
+            ["File(Open(\"notes\"))", "File(Read(0))", "File(Close(0))"]
+        );
+    }
+
+    #[test]
+    fn test_bracket_releases_when_the_run_aborts() {
+        let (text, files) = read_file("bad").try_run_with_handler(Files::default());
+        assert!(matches!(text, Err(EffectError::Aborted(_))));
+        assert_eq!(
+            files.log,
+            ["File(Open(\"bad\"))", "File(Read(0))", "File(Close(0))"]
+        );
+    }
+
+    #[test]
+    fn test_bracket_releases_when_dropped_mid_run() {
+        #[effectful]
+        fn read_offline() -> Option<String> {
+            let text = embed!(read_file("notes").with_handler(Offline));
+            let _: () = perform!(Lock::Acquire);
+            text.ok()
+        }
+
+        let (text, files) = read_offline().try_run_with_handler(Files::default());
+        assert_eq!(text.unwrap(), None);
+        assert_eq!(
+            files.log,
+            ["File(Open(\"notes\"))", "File(Close(0))", "Lock(Acquire)"]
+        );
+    }
+
+    #[effectful]
+    fn locked(name: String) -> String {
+        let _: () = perform!(Lock::Acquire);
+        defer_perform!(Lock::Release);
+        let fd: u32 = perform!(File::Open(name));
+        perform!(File::Read(fd))
+    }
+
+    #[test]
+    fn test_deferred_operations_run_when_the_function_ends() {
+        let (_, files) = locked("a".into())
+            .bind(|_| close(0))
+            .try_run_with_handler(Files::default());
+        assert_eq!(
+            files.log,
+            [
+                "Lock(Acquire)",
+                "File(Open(\"a\"))",
+                "File(Read(0))",
+                "Lock(Release)",
+                "File(Close(0))"
+            ]
+        );
+
+        let (text, files) = locked("bad".into()).try_run_with_handler(Files::default());
+        assert!(text.is_err());
+        assert_eq!(files.log.last().unwrap(), "Lock(Release)");
+    }
+
+    #[test]
+    fn test_releases_outside_a_run_are_dropped() {
+        let mut computation = read_file("notes");
+        assert!(matches!(computation.step(None), CoroutineState::Yielded(_)));
+        drop(computation);
+        PENDING.with(|pending| assert!(pending.borrow().is_empty()));
+    }
+}
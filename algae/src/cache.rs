@@ -0,0 +1,511 @@
+//! Memoizing replies.
+//!
+//! A [`CachingHandler`] wraps a handler whose operations are expensive, such
+//! as database queries, and answers repeated identical operations from a
+//! cache instead of asking the handler again. Operations are the cache keys,
+//! so their enums must derive `Eq` and `Hash`:
+//!
+//! ```rust,ignore
+//! effect! {
+//!     #![derive(Eq, Hash)]
+//!     Db::Query (String) -> Vec<Row>;
+//!     #[mutates]
+//!     Db::Execute (String) -> u64;
+//! }
+//!
+//! let handler = CachingHandler::new(DbHandler::connect()?)
+//!     .cache_replies::<Vec<Row>>()
+//!     .ttl(Duration::from_secs(30));
+//! let cache = handler.cache();
+//! let report = build_report().try_run_with(handler)?;
+//! cache.invalidate_where(|op| matches!(op, Op::Db(Db::Query(sql)) if sql.contains("orders")));
+//! ```
+//!
+//! Replies are boxed `Any`s, so the handler can only copy those whose types
+//! were registered with [`cache_replies`](CachingHandler::cache_replies);
+//! replies of other types, including [`Abort`]s, are passed on without being
+//! cached. Operations declared with `#[mutates]` are never cached, and
+//! clear the cache once their handler has answered them, since they may
+//! change what every other operation would reply.
+
+use crate::{Abort, OpMeta, PartialHandler, Reply, Unanswered};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+type CloneFn = fn(&(dyn Any + Send)) -> Box<dyn Any + Send>;
+type NowFn = Box<dyn Fn() -> Instant + Send>;
+
+fn clone_reply<R: Clone + Send + 'static>(reply: &(dyn Any + Send)) -> Box<dyn Any + Send> {
+    Box::new(
+        reply
+            .downcast_ref::<R>()
+            .expect("cloned as the type it was stored with")
+            .clone(),
+    )
+}
+
+struct Entry {
+    reply: Box<dyn Any + Send>,
+    clone: CloneFn,
+    stored_at: Instant,
+}
+
+struct Store<Op> {
+    entries: HashMap<Op, Entry>,
+    hits: u64,
+    misses: u64,
+}
+
+/// The replies cached by a [`CachingHandler`], for inspecting and
+/// invalidating them while the handler is in use.
+///
+/// Clones share the same cache.
+pub struct ReplyCache<Op> {
+    store: Arc<Mutex<Store<Op>>>,
+}
+
+impl<Op> Clone for ReplyCache<Op> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+        }
+    }
+}
+
+impl<Op> Default for ReplyCache<Op> {
+    fn default() -> Self {
+        Self {
+            store: Arc::new(Mutex::new(Store {
+                entries: HashMap::new(),
+                hits: 0,
+                misses: 0,
+            })),
+        }
+    }
+}
+
+impl<Op: Hash + Eq> ReplyCache<Op> {
+    /// An empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops the reply cached for `op`, returning whether there was one.
+    pub fn invalidate(&self, op: &Op) -> bool {
+        self.store.lock().unwrap().entries.remove(op).is_some()
+    }
+
+    /// Drops the replies cached for the operations `f` returns `true` for.
+    pub fn invalidate_where(&self, mut f: impl FnMut(&Op) -> bool) {
+        self.store.lock().unwrap().entries.retain(|op, _| !f(op));
+    }
+
+    /// Drops every cached reply.
+    pub fn clear(&self) {
+        self.store.lock().unwrap().entries.clear();
+    }
+
+    /// The number of cached replies, including expired ones not yet dropped.
+    pub fn len(&self) -> usize {
+        self.store.lock().unwrap().entries.len()
+    }
+
+    /// Whether no reply is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many operations were answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.store.lock().unwrap().hits
+    }
+
+    /// How many operations the wrapped handler answered, as no fresh reply
+    /// was cached for them.
+    pub fn misses(&self) -> u64 {
+        self.store.lock().unwrap().misses
+    }
+}
+
+impl<Op> fmt::Debug for ReplyCache<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let store = self.store.lock().unwrap();
+        f.debug_struct("ReplyCache")
+            .field("entries", &store.entries.len())
+            .field("hits", &store.hits)
+            .field("misses", &store.misses)
+            .finish()
+    }
+}
+
+/// A handler wrapper that answers repeated identical operations with the
+/// reply its handler gave the first time.
+///
+/// # Type Parameters
+///
+/// * `Op` - The operation type, used as the cache key
+/// * `H` - The wrapped partial handler
+pub struct CachingHandler<Op, H> {
+    inner: H,
+    cache: ReplyCache<Op>,
+    cloners: HashMap<TypeId, CloneFn>,
+    ttl: Option<Duration>,
+    now: NowFn,
+}
+
+impl<Op: Hash + Eq, H> CachingHandler<Op, H> {
+    /// Wraps `inner` with an empty cache whose entries never expire.
+    ///
+    /// Nothing is cached until reply types are registered with
+    /// [`cache_replies`](Self::cache_replies).
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            cache: ReplyCache::new(),
+            cloners: HashMap::new(),
+            ttl: None,
+            now: Box::new(Instant::now),
+        }
+    }
+
+    /// Caches replies of type `R`.
+    pub fn cache_replies<R: Clone + Send + 'static>(mut self) -> Self {
+        self.cloners.insert(TypeId::of::<R>(), clone_reply::<R>);
+        self
+    }
+
+    /// Asks the wrapped handler again once a cached reply is older than
+    /// `ttl`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Stores replies in `cache` instead of a cache of its own, e.g. to
+    /// share one between handlers.
+    pub fn with_cache(mut self, cache: &ReplyCache<Op>) -> Self {
+        self.cache = cache.clone();
+        self
+    }
+
+    /// Reads the time for the TTL from `now` instead of `Instant::now`.
+    ///
+    /// Useful in tests, e.g. to expire entries without waiting.
+    pub fn clock_with<F>(mut self, now: F) -> Self
+    where
+        F: Fn() -> Instant + Send + 'static,
+    {
+        self.now = Box::new(now);
+        self
+    }
+
+    /// A handle on the cache, for invalidating entries after the handler
+    /// was handed to a computation.
+    pub fn cache(&self) -> ReplyCache<Op> {
+        self.cache.clone()
+    }
+
+    /// The wrapped handler.
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<Op: Hash + Eq + Clone, H> CachingHandler<Op, H> {
+    /// A copy of the fresh reply cached for `op`, if any; a stale one is
+    /// dropped.
+    fn cached(&self, op: &Op, now: Instant) -> Option<Box<dyn Any + Send>> {
+        let mut store = self.cache.store.lock().unwrap();
+        let entry = store.entries.get(op)?;
+        let fresh = self
+            .ttl
+            .is_none_or(|ttl| now.saturating_duration_since(entry.stored_at) < ttl);
+        if !fresh {
+            store.entries.remove(op);
+            return None;
+        }
+        let reply = (entry.clone)(&*entry.reply);
+        store.hits += 1;
+        Some(reply)
+    }
+
+    /// Counts a miss the wrapped handler answered with `reply`, and caches
+    /// a copy of it if its type was registered.
+    fn remember(&self, op: &Op, reply: &(dyn Any + Send), now: Instant) {
+        let entry = self.cloners.get(&reply.type_id()).map(|&clone| Entry {
+            reply: clone(reply),
+            clone,
+            stored_at: now,
+        });
+        let mut store = self.cache.store.lock().unwrap();
+        store.misses += 1;
+        if let Some(entry) = entry {
+            store.entries.insert(op.clone(), entry);
+        }
+    }
+}
+
+impl<Op, H> PartialHandler<Op> for CachingHandler<Op, H>
+where
+    Op: Hash + Eq + Clone + OpMeta,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        if op.mutates_state() {
+            let reply = self.inner.maybe_handle(op)?;
+            if !reply.is::<Abort>() {
+                self.cache.clear();
+            }
+            return Some(reply);
+        }
+
+        let now = (self.now)();
+        if let Some(reply) = self.cached(op, now) {
+            return Some(reply);
+        }
+        // The lock is not held while the handler runs, so it may use the
+        // cache itself
+        let reply = self.inner.maybe_handle(op)?;
+        self.remember(op, &*reply, now);
+        Some(reply)
+    }
+
+    /// Answers the operations with a fresh cached reply itself, and passes
+    /// the others on as one batch. A batch with a write in it is passed on
+    /// whole, and clears the cache once the write is answered.
+    fn maybe_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        if ops.iter().any(OpMeta::mutates_state) {
+            let replies = self.inner.maybe_handle_batch(ops)?;
+            let wrote = ops.iter().zip(&replies).any(|(op, reply)| {
+                op.mutates_state() && !reply.is::<Abort>() && !reply.is::<Unanswered>()
+            });
+            if wrote {
+                self.cache.clear();
+            }
+            return Some(replies);
+        }
+
+        let now = (self.now)();
+        let mut replies: Vec<Option<Reply>> = ops
+            .iter()
+            .map(|op| self.cached(op, now).map(Reply::from))
+            .collect();
+        let missed: Vec<Op> = ops
+            .iter()
+            .zip(&replies)
+            .filter(|(_, reply)| reply.is_none())
+            .map(|(op, _)| op.clone())
+            .collect();
+        if !missed.is_empty() {
+            let answers = self.inner.maybe_handle_batch(&missed);
+            if answers.is_none() && missed.len() == ops.len() {
+                return None;
+            }
+            let mut answers = answers.into_iter().flatten();
+            for (op, slot) in ops.iter().zip(&mut replies) {
+                if slot.is_some() {
+                    continue;
+                }
+                *slot = Some(match answers.next() {
+                    Some(reply) if !reply.is::<Unanswered>() => {
+                        let reply = reply.into_boxed();
+                        self.remember(op, &*reply, now);
+                        Reply::from(reply)
+                    }
+                    _ => Reply::new(Unanswered),
+                });
+            }
+        }
+        Some(replies.into_iter().flatten().collect())
+    }
+
+    fn handler_names(&self) -> Vec<&'static str> {
+        self.inner.handler_names()
+    }
+}
+
+impl<Op, H: fmt::Debug> fmt::Debug for CachingHandler<Op, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachingHandler")
+            .field("inner", &self.inner)
+            .field("cache", &self.cache)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        #![derive(Eq, Hash)]
+        Db::Query (String) -> Vec<u32>;
+        Db::Count (String) -> u64;
+        #[mutates]
+        Db::Insert ((String, u32)) -> ();
+    }
+
+    /// A table per name, counting how often it is asked.
+    #[derive(Debug, Default)]
+    struct Tables {
+        rows: HashMap<String, Vec<u32>>,
+        calls: Arc<Mutex<u32>>,
+    }
+
+    impl PartialHandler<Op> for Tables {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            *self.calls.lock().unwrap() += 1;
+            let Op::Db(op) = op;
+            Some(match op {
+                Db::Query(table) => Box::new(self.rows.get(table).cloned().unwrap_or_default()),
+                Db::Count(table) => Box::new(self.rows.get(table).map_or(0, |r| r.len() as u64)),
+                Db::Insert((table, row)) => {
+                    self.rows.entry(table.clone()).or_default().push(*row);
+                    Box::new(())
+                }
+            })
+        }
+    }
+
+    #[effectful]
+    fn query_twice(table: &'static str) -> (Vec<u32>, Vec<u32>) {
+        let first: Vec<u32> = perform!(Db::Query(table.into()));
+        let second: Vec<u32> = perform!(Db::Query(table.into()));
+        (first, second)
+    }
+
+    #[effectful]
+    fn insert_between(table: &'static str) -> (Vec<u32>, Vec<u32>) {
+        let before: Vec<u32> = perform!(Db::Query(table.into()));
+        let _: () = perform!(Db::Insert((table.into(), 7)));
+        let after: Vec<u32> = perform!(Db::Query(table.into()));
+        (before, after)
+    }
+
+    #[effectful]
+    fn count(table: &'static str) -> u64 {
+        perform!(Db::Count(table.into()))
+    }
+
+    fn tables() -> (Tables, Arc<Mutex<u32>>) {
+        let tables = Tables {
+            rows: HashMap::from([("users".to_string(), vec![1, 2])]),
+            ..Tables::default()
+        };
+        let calls = Arc::clone(&tables.calls);
+        (tables, calls)
+    }
+
+    #[test]
+    fn test_repeated_operations_are_answered_once() {
+        let (tables, calls) = tables();
+        let handler = CachingHandler::new(tables).cache_replies::<Vec<u32>>();
+        let cache = handler.cache();
+        let mut session = crate::session::HandlerSession::new(handler);
+
+        let (first, second) = session.try_run(query_twice("users")).unwrap();
+        assert_eq!((first, second), (vec![1, 2], vec![1, 2]));
+        assert_eq!(session.try_run(query_twice("users")).unwrap().0, [1, 2]);
+        assert_eq!(*calls.lock().unwrap(), 1);
+        assert_eq!((cache.hits(), cache.misses()), (3, 1));
+
+        // Replies of unregistered types are not cached
+        session.try_run(count("users")).unwrap();
+        session.try_run(count("users")).unwrap();
+        assert_eq!(*calls.lock().unwrap(), 3);
+        assert_eq!(cache.len(), 1);
+
+        assert!(cache.invalidate(&Op::db_query("users".to_string())));
+        assert!(!cache.invalidate(&Op::db_query("users".to_string())));
+        session.try_run(query_twice("users")).unwrap();
+        assert_eq!(*calls.lock().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_declined_operations_are_not_misses() {
+        struct Offline;
+
+        impl PartialHandler<Op> for Offline {
+            fn maybe_handle(&mut self, _: &Op) -> Option<Box<dyn Any + Send>> {
+                None
+            }
+        }
+
+        let handler = CachingHandler::new(Offline).cache_replies::<Vec<u32>>();
+        let cache = handler.cache();
+        assert!(query_twice("users").try_run_with(handler).is_err());
+        assert_eq!((cache.hits(), cache.misses()), (0, 0));
+    }
+
+    #[effectful]
+    fn query_all(tables: Vec<&'static str>) -> Vec<Vec<u32>> {
+        perform_all!(tables.into_iter().map(|table| Db::Query(table.into())))
+    }
+
+    #[test]
+    fn test_batches_are_cached_per_operation() {
+        let (tables, calls) = tables();
+        let handler = CachingHandler::new(tables).cache_replies::<Vec<u32>>();
+        let cache = handler.cache();
+        assert_eq!(handler.handler_names(), [core::any::type_name::<Tables>()]);
+        let mut session = crate::session::HandlerSession::new(handler);
+
+        session.try_run(query_twice("users")).unwrap();
+        let replies = session.try_run(query_all(vec!["users", "orders"])).unwrap();
+        assert_eq!(replies, [vec![1, 2], vec![]]);
+        // Only the orders reached the tables
+        assert_eq!(*calls.lock().unwrap(), 2);
+        assert_eq!((cache.hits(), cache.misses()), (2, 2));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_entries_expire_after_the_ttl() {
+        let (tables, calls) = tables();
+        let start = Instant::now();
+        let now = Arc::new(Mutex::new(start));
+        let clock = Arc::clone(&now);
+        let handler = CachingHandler::new(tables)
+            .cache_replies::<Vec<u32>>()
+            .ttl(Duration::from_secs(10))
+            .clock_with(move || *clock.lock().unwrap());
+        let mut session = crate::session::HandlerSession::new(handler);
+
+        session.try_run(query_twice("users")).unwrap();
+        *now.lock().unwrap() = start + Duration::from_secs(9);
+        session.try_run(query_twice("users")).unwrap();
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        *now.lock().unwrap() = start + Duration::from_secs(10);
+        session.try_run(query_twice("users")).unwrap();
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_mutations_clear_the_cache() {
+        let (tables, calls) = tables();
+        let handler = CachingHandler::new(tables)
+            .cache_replies::<Vec<u32>>()
+            .cache_replies::<()>();
+        let cache = handler.cache();
+        let mut session = crate::session::HandlerSession::new(handler);
+
+        let (before, after) = session.try_run(insert_between("users")).unwrap();
+        assert_eq!((before, after), (vec![1, 2], vec![1, 2, 7]));
+        assert_eq!(*calls.lock().unwrap(), 3);
+        // Only the query after the insert is cached
+        assert_eq!(cache.len(), 1);
+
+        cache.invalidate_where(|op| matches!(op, Op::Db(Db::Query(t)) if t == "users"));
+        assert!(cache.is_empty());
+    }
+}
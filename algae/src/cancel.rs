@@ -0,0 +1,369 @@
+//! Stopping a run from outside the computation.
+//!
+//! [`Effectful::run_with_cancellation`] checks a [`CancellationToken`] each
+//! time before it resumes the computation, and
+//! [`Effectful::run_with_deadline`] checks the time left for the run as a
+//! whole. Either stops the computation at the next resumption and fails the
+//! run with an [`Abort`] carrying [`Cancelled`], without the computation
+//! having to poll for it. Computations that want to stop at points of their
+//! own choosing use the [`ctl`](crate::effects::ctl) effect family instead,
+//! which watches the same tokens.
+//!
+//! An operation that is being handled when the token is tripped or the
+//! deadline passes is waited for; the [`timeout`](crate::timeout) layer bounds
+//! the operations themselves.
+//!
+//! [`Effectful::race`] runs two computations on threads of their own and
+//! returns the result of the first to finish, cancelling the other the same
+//! way.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! use algae::cancel::{CancellationToken, Cancelled};
+//! use std::time::Duration;
+//!
+//! let token = CancellationToken::new();
+//! let trip = token.clone();
+//! ctrlc::set_handler(move || trip.cancel())?;
+//!
+//! match import_all(files).run_with_cancellation(&token, ImportHandler::new()) {
+//!     Err(EffectError::Aborted(abort)) if abort.is::<Cancelled>() => eprintln!("cancelled"),
+//!     other => other.map(|_| ())?,
+//! }
+//!
+//! let report = nightly_report().run_with_deadline(Duration::from_secs(60), Backends::connect()?)?;
+//! ```
+
+use crate::{
+    dispatch_in_run, Abort, EffectError, Effectful, HandlerFactory, Partial, PartialHandler, Reply,
+};
+use std::{
+    fmt,
+    ops::CoroutineState,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A shared flag that requests cancellation of the computations watching it.
+///
+/// Clones share the same flag. Cancellation cannot be undone.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Whether cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+/// Typed error carried by the [`Abort`] of a cancelled run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("computation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl<R, Op: 'static> Effectful<R, Op> {
+    /// Runs the computation with `h` like
+    /// [`try_run_with`](Effectful::try_run_with), but stops it before the
+    /// next resumption once `token` has been tripped.
+    ///
+    /// A stopped run fails with [`EffectError::Aborted`] carrying
+    /// [`Cancelled`].
+    pub fn run_with_cancellation<H>(
+        self,
+        token: &CancellationToken,
+        h: H,
+    ) -> Result<R, EffectError<Op>>
+    where
+        H: PartialHandler<Op>,
+    {
+        self.drive_until(h, || token.is_cancelled())
+    }
+
+    /// Runs the computation with `h` like
+    /// [`try_run_with`](Effectful::try_run_with), but stops it before the
+    /// next resumption once `limit` has passed since the start.
+    ///
+    /// A stopped run fails with [`EffectError::Aborted`] carrying
+    /// [`Cancelled`].
+    pub fn run_with_deadline<H>(self, limit: Duration, h: H) -> Result<R, EffectError<Op>>
+    where
+        H: PartialHandler<Op>,
+    {
+        let deadline = Instant::now() + limit;
+        self.drive_until(h, || Instant::now() >= deadline)
+    }
+
+    /// Runs this computation and `other` concurrently, each on its own
+    /// thread with a handler built by `factory`, and returns the result of
+    /// the first to finish, cancelling the other.
+    ///
+    /// The other computation stops at its next resumption as if by
+    /// [`run_with_cancellation`](Effectful::run_with_cancellation). A failure
+    /// finishes a computation like a result does, and a panic of the first
+    /// to finish is resumed on the calling thread. This expresses
+    /// work-versus-timeout with effects alone:
+    ///
+    /// ```rust,ignore
+    /// let page = fetch(url).race(
+    ///     sleep(Duration::from_secs(5)).bind(|_| fallback_page()),
+    ///     || Backends::connect(&config),
+    /// )?;
+    /// ```
+    ///
+    /// `race` does not wait for the other computation: its thread is
+    /// detached, and keeps running, with its handler, until the operation
+    /// being handled for it returns, e.g. the sleep of a timer. An operation
+    /// that never returns keeps the thread alive for good, so bound
+    /// operations that may block with the [`timeout`](crate::timeout) layer.
+    pub fn race<F>(self, other: Self, factory: F) -> Result<R, EffectError<Op>>
+    where
+        R: Send + 'static,
+        Op: Send,
+        F: HandlerFactory<Op> + Send + Sync + 'static,
+    {
+        let token = CancellationToken::new();
+        let factory = Arc::new(factory);
+        let (finished, first) = mpsc::channel();
+        for (i, computation) in [self, other].into_iter().enumerate() {
+            let (token, factory, finished) = (token.clone(), factory.clone(), finished.clone());
+            thread::spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let h = factory.build();
+                    computation
+                        .scoped(i as u64)
+                        .run_with_cancellation(&token, h)
+                }));
+                // Report before cancelling, so the result of the cancelled
+                // computation cannot arrive first
+                let _ = finished.send(result);
+                token.cancel();
+            });
+        }
+        first
+            .recv()
+            .expect("a raced computation reports its result")
+            .unwrap_or_else(|panic| panic::resume_unwind(panic))
+    }
+
+    fn drive_until<H, F>(mut self, mut h: H, mut stop: F) -> Result<R, EffectError<Op>>
+    where
+        H: PartialHandler<Op>,
+        F: FnMut() -> bool,
+    {
+        let mut resume_arg: Option<Reply> = None;
+        loop {
+            if stop() {
+                return Err(EffectError::Aborted(Abort::new(Cancelled)));
+            }
+            match self.step(resume_arg) {
+                CoroutineState::Complete(r) => return Ok(r),
+                CoroutineState::Yielded(eff) => {
+                    let handler = std::any::type_name::<H>();
+                    resume_arg = Some(dispatch_in_run(eff, handler, &mut Partial(&mut h))?);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::{
+        any::Any,
+        sync::{atomic::AtomicU32, RwLock},
+        thread,
+    };
+
+    effect! {
+        Job::Step (u32) -> ();
+    }
+
+    /// Takes `pause` for each step and trips `token` after `budget` steps.
+    struct Worker {
+        budget: u32,
+        pause: Duration,
+        token: CancellationToken,
+        done: u32,
+    }
+
+    impl Worker {
+        fn new(budget: u32, pause: Duration) -> Self {
+            Self {
+                budget,
+                pause,
+                token: CancellationToken::new(),
+                done: 0,
+            }
+        }
+    }
+
+    impl PartialHandler<Op> for &mut Worker {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Job(Job::Step(_)) = op;
+            thread::sleep(self.pause);
+            self.done += 1;
+            if self.done == self.budget {
+                self.token.cancel();
+            }
+            Some(Box::new(()))
+        }
+    }
+
+    #[effectful]
+    fn steps(n: u32) -> u32 {
+        for i in 0..n {
+            let _: () = perform!(Job::Step(i));
+        }
+        n
+    }
+
+    fn assert_cancelled(result: Result<u32, EffectError<Op>>) {
+        match result {
+            Err(EffectError::Aborted(abort)) => {
+                assert_eq!(abort.downcast::<Cancelled>().unwrap(), Cancelled)
+            }
+            other => panic!("expected cancellation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_cancellation_stops_before_the_next_resumption() {
+        let mut worker = Worker::new(3, Duration::ZERO);
+        let token = worker.token.clone();
+        assert_cancelled(steps(10).run_with_cancellation(&token, &mut worker));
+        assert_eq!(worker.done, 3);
+
+        let mut worker = Worker::new(100, Duration::ZERO);
+        let token = worker.token.clone();
+        assert_eq!(
+            steps(10)
+                .run_with_cancellation(&token, &mut worker)
+                .unwrap(),
+            10
+        );
+    }
+
+    #[test]
+    fn test_tokens_can_be_tripped_before_the_run() {
+        let mut worker = Worker::new(100, Duration::ZERO);
+        let token = worker.token.clone();
+        thread::spawn({
+            let token = token.clone();
+            move || token.cancel()
+        })
+        .join()
+        .unwrap();
+        assert_cancelled(steps(10).run_with_cancellation(&token, &mut worker));
+        assert_eq!(worker.done, 0);
+    }
+
+    #[test]
+    fn test_deadline_bounds_the_whole_run() {
+        let mut worker = Worker::new(100, Duration::from_millis(5));
+        assert_cancelled(steps(1000).run_with_deadline(Duration::from_millis(20), &mut worker));
+        assert!(worker.done < 1000);
+
+        let mut worker = Worker::new(100, Duration::ZERO);
+        let done = steps(10).run_with_deadline(Duration::from_secs(60), &mut worker);
+        assert_eq!(done.unwrap(), 10);
+    }
+
+    /// Steps freely up to `Step(3)`, and from there only once `gate` is
+    /// open, counting the steps taken by all copies; reports on `ended` when
+    /// dropped, i.e. once its run is over.
+    #[derive(Clone)]
+    struct Gated {
+        gate: Arc<RwLock<()>>,
+        steps: Arc<AtomicU32>,
+        ended: mpsc::Sender<()>,
+    }
+
+    impl PartialHandler<Op> for Gated {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Job(Job::Step(i)) = op;
+            if *i >= 3 {
+                drop(self.gate.read().unwrap());
+            }
+            self.steps.fetch_add(1, Ordering::Relaxed);
+            Some(Box::new(()))
+        }
+    }
+
+    impl Drop for Gated {
+        fn drop(&mut self) {
+            let _ = self.ended.send(());
+        }
+    }
+
+    /// Races `first` and `second` with [`Gated`] handlers, opening the gate
+    /// once the race is decided, and returns its result with the steps both
+    /// took once both runs are over.
+    fn gated_race(
+        first: Effectful<u32, Op>,
+        second: Effectful<u32, Op>,
+    ) -> (Result<u32, EffectError<Op>>, u32) {
+        let gate = Arc::new(RwLock::new(()));
+        let closed = gate.write().unwrap();
+        let (ended, runs_ended) = mpsc::channel();
+        let gated = Gated {
+            gate: Arc::clone(&gate),
+            steps: Arc::default(),
+            ended,
+        };
+        let taken = Arc::clone(&gated.steps);
+        let result = first.race(second, move || gated.clone());
+        drop(closed);
+        // The handlers of both runs are dropped before the one the factory
+        // clones
+        runs_ended.recv().unwrap();
+        runs_ended.recv().unwrap();
+        (result, taken.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn test_race_returns_the_first_to_finish_and_cancels_the_other() {
+        // The loser is held at its fourth step until the race is decided,
+        // and stops right after it
+        let (result, taken) = gated_race(steps(1000), steps(3));
+        assert_eq!(result.unwrap(), 3);
+        assert!(taken <= 3 + 4, "{taken} steps taken");
+
+        let (result, taken) = gated_race(steps(0), steps(1000));
+        assert_eq!(result.unwrap(), 0);
+        assert!(taken <= 4, "{taken} steps taken");
+    }
+
+    #[test]
+    #[should_panic(expected = "no handler today")]
+    fn test_race_resumes_panics() {
+        let _ = steps(1).race(steps(2), || -> Gated { panic!("no handler today") });
+    }
+}
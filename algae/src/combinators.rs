@@ -0,0 +1,420 @@
+//! Combinators building handlers out of other handlers.
+//!
+//! [`PartialHandler`] provides them as methods, like the adapters of
+//! `Iterator`:
+//!
+//! | Method | Handler |
+//! |--------|---------|
+//! | [`or_else`](PartialHandler::or_else) | [`OrElse`]: the operations the first declines go to the second |
+//! | [`filter`](PartialHandler::filter) | [`Filter`]: only operations a predicate accepts reach the handler |
+//! | [`map_reply`](PartialHandler::map_reply) | [`MapReply`]: replies go through a function |
+//! | [`and_then`](PartialHandler::and_then) | [`AndThen`]: replies go through a function that may decline |
+//!
+//! Where pushing handlers on a [`VecHandler`] only orders them, combinators
+//! express a routing policy per handler:
+//!
+//! ```rust,ignore
+//! let handler = Cache::new()
+//!     .filter(|op: &Op| !op.mutates_state())
+//!     .or_else(Database::connect(url)?)
+//!     .or_else(Offline.map_reply(|op: &Op, reply: Reply| {
+//!         eprintln!("answered {} offline", op.qualified_name());
+//!         reply
+//!     }));
+//!
+//! let report = build_report().try_run_with(handler)?;
+//! ```
+//!
+//! Every combinator forwards [`maybe_reply`](PartialHandler::maybe_reply)
+//! and [`maybe_handle_batch`](PartialHandler::maybe_handle_batch), so inline
+//! replies and batches reach the handlers they combine.
+
+use crate::{
+    answer_rest, reply_each, Handler, IntoVecHandler, PartialHandler, Reply, Unanswered, VecHandler,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{any::Any, fmt};
+
+/// Tries `first`, then `second` for the operations it declines; made by
+/// [`PartialHandler::or_else`].
+///
+/// It is a total [`Handler`] when `second` is.
+pub struct OrElse<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> OrElse<A, B> {
+    pub(crate) fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+
+    /// The two handlers, in the order they are tried.
+    pub fn into_parts(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<Op, A, B> PartialHandler<Op> for OrElse<A, B>
+where
+    A: PartialHandler<Op>,
+    B: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.first
+            .maybe_handle(op)
+            .or_else(|| self.second.maybe_handle(op))
+    }
+
+    fn maybe_reply(&mut self, op: &Op) -> Option<Reply> {
+        self.first
+            .maybe_reply(op)
+            .or_else(|| self.second.maybe_reply(op))
+    }
+
+    fn maybe_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        let Some(mut replies) = self.first.maybe_handle_batch(ops) else {
+            return self.second.maybe_handle_batch(ops);
+        };
+        answer_rest(ops, &mut replies, |op| self.second.maybe_reply(op));
+        Some(replies)
+    }
+
+    fn handler_names(&self) -> Vec<&'static str> {
+        let mut names = self.first.handler_names();
+        names.extend(self.second.handler_names());
+        names
+    }
+}
+
+impl<Op, A, B> Handler<Op> for OrElse<A, B>
+where
+    A: PartialHandler<Op>,
+    B: Handler<Op>,
+{
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        self.first
+            .maybe_handle(op)
+            .unwrap_or_else(|| self.second.handle(op))
+    }
+
+    fn reply(&mut self, op: &Op) -> Reply {
+        self.first
+            .maybe_reply(op)
+            .unwrap_or_else(|| self.second.reply(op))
+    }
+
+    fn handle_batch(&mut self, ops: &[Op]) -> Vec<Reply> {
+        let Some(mut replies) = self.first.maybe_handle_batch(ops) else {
+            return self.second.handle_batch(ops);
+        };
+        answer_rest(ops, &mut replies, |op| Some(self.second.reply(op)));
+        replies
+    }
+
+    fn try_reply(&mut self, op: &Op) -> Option<Reply> {
+        self.first
+            .maybe_reply(op)
+            .or_else(|| self.second.try_reply(op))
+    }
+
+    fn try_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        let Some(mut replies) = self.first.maybe_handle_batch(ops) else {
+            return self.second.try_handle_batch(ops);
+        };
+        answer_rest(ops, &mut replies, |op| self.second.try_reply(op));
+        Some(replies)
+    }
+
+    fn handler_names(&self) -> Vec<&'static str> {
+        let mut names = PartialHandler::handler_names(&self.first);
+        names.extend(Handler::handler_names(&self.second));
+        names
+    }
+}
+
+/// Passes `handler` only the operations `predicate` accepts, declining the
+/// others; made by [`PartialHandler::filter`].
+pub struct Filter<H, P> {
+    handler: H,
+    predicate: P,
+}
+
+impl<H, P> Filter<H, P> {
+    pub(crate) fn new(handler: H, predicate: P) -> Self {
+        Self { handler, predicate }
+    }
+
+    /// The filtered handler.
+    pub fn into_inner(self) -> H {
+        self.handler
+    }
+}
+
+impl<Op, H, P> PartialHandler<Op> for Filter<H, P>
+where
+    H: PartialHandler<Op>,
+    P: FnMut(&Op) -> bool,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        (self.predicate)(op)
+            .then(|| self.handler.maybe_handle(op))
+            .flatten()
+    }
+
+    fn maybe_reply(&mut self, op: &Op) -> Option<Reply> {
+        (self.predicate)(op)
+            .then(|| self.handler.maybe_reply(op))
+            .flatten()
+    }
+
+    /// Passes the batch whole if the predicate accepts all of it, and the
+    /// operations it accepts one at a time otherwise.
+    fn maybe_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        if ops.iter().all(&mut self.predicate) {
+            return self.handler.maybe_handle_batch(ops);
+        }
+        reply_each(ops, |op| self.maybe_reply(op))
+    }
+}
+
+/// Replaces each reply of `handler` with what `f` makes of it; made by
+/// [`PartialHandler::map_reply`].
+pub struct MapReply<H, F> {
+    handler: H,
+    f: F,
+}
+
+impl<H, F> MapReply<H, F> {
+    pub(crate) fn new(handler: H, f: F) -> Self {
+        Self { handler, f }
+    }
+
+    /// The handler whose replies are mapped.
+    pub fn into_inner(self) -> H {
+        self.handler
+    }
+}
+
+impl<Op, H, F> PartialHandler<Op> for MapReply<H, F>
+where
+    H: PartialHandler<Op>,
+    F: FnMut(&Op, Reply) -> Reply,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.maybe_reply(op).map(Reply::into_boxed)
+    }
+
+    fn maybe_reply(&mut self, op: &Op) -> Option<Reply> {
+        let reply = self.handler.maybe_reply(op)?;
+        Some((self.f)(op, reply))
+    }
+
+    fn maybe_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        let replies = self.handler.maybe_handle_batch(ops)?;
+        Some(
+            ops.iter()
+                .zip(replies)
+                .map(|(op, reply)| match reply.is::<Unanswered>() {
+                    true => reply,
+                    false => (self.f)(op, reply),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Passes each reply of `handler` through `f`, which may replace it or
+/// decline the operation after all; made by [`PartialHandler::and_then`].
+pub struct AndThen<H, F> {
+    handler: H,
+    f: F,
+}
+
+impl<H, F> AndThen<H, F> {
+    pub(crate) fn new(handler: H, f: F) -> Self {
+        Self { handler, f }
+    }
+
+    /// The handler whose replies are passed on.
+    pub fn into_inner(self) -> H {
+        self.handler
+    }
+}
+
+impl<Op, H, F> PartialHandler<Op> for AndThen<H, F>
+where
+    H: PartialHandler<Op>,
+    F: FnMut(&Op, Reply) -> Option<Reply>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.maybe_reply(op).map(Reply::into_boxed)
+    }
+
+    fn maybe_reply(&mut self, op: &Op) -> Option<Reply> {
+        let reply = self.handler.maybe_reply(op)?;
+        (self.f)(op, reply)
+    }
+
+    /// Leaves the operations `f` declines for other handlers, like those
+    /// `handler` declines.
+    fn maybe_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        let mut replies = self.handler.maybe_handle_batch(ops)?.into_iter();
+        reply_each(ops, |op| {
+            let reply = replies.next()?;
+            match reply.is::<Unanswered>() {
+                true => None,
+                false => (self.f)(op, reply),
+            }
+        })
+    }
+}
+
+macro_rules! combinator_impls {
+    ($($name:ident<$a:ident, $b:ident>),*) => {$(
+        impl<$a, $b> fmt::Debug for $name<$a, $b> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_tuple(stringify!($name))
+                    .field(&core::any::type_name::<$a>())
+                    .field(&core::any::type_name::<$b>())
+                    .finish()
+            }
+        }
+
+        impl<Op, $a, $b> IntoVecHandler<Op> for $name<$a, $b>
+        where
+            Self: PartialHandler<Op> + Send + 'static,
+        {
+            fn into_vec_handler(self) -> VecHandler<Op> {
+                let mut handlers = VecHandler::new();
+                handlers.push(self);
+                handlers
+            }
+        }
+    )*};
+}
+
+combinator_impls!(OrElse<A, B>, Filter<H, P>, MapReply<H, F>, AndThen<H, F>);
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use crate as algae;
+    use algae::prelude::*;
+    use std::{
+        any::Any,
+        sync::{Arc, Mutex},
+    };
+
+    effect! {
+        Cache::Get (u32) -> Option<String>;
+        Db::Get (u32) -> String;
+        Log::Line (String) -> ();
+    }
+
+    #[effectful]
+    fn lookup(id: u32) -> String {
+        let name = match perform!(Cache::Get(id)) {
+            Some(name) => name,
+            None => perform!(Db::Get(id)),
+        };
+        perform!(Log::Line(format!("{id}: {name}")));
+        name
+    }
+
+    /// Answers every operation, recording which ones it saw.
+    #[derive(Clone, Default)]
+    struct Backend {
+        seen: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Handler<Op> for Backend {
+        fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+            self.seen
+                .lock()
+                .unwrap()
+                .push(op.qualified_name().to_string());
+            match op {
+                Op::Cache(Cache::Get(id)) => Box::new((*id < 10).then(|| format!("cached {id}"))),
+                Op::Db(Db::Get(id)) => Box::new(format!("stored {id}")),
+                Op::Log(_) => Box::new(()),
+            }
+        }
+    }
+
+    impl PartialHandler<Op> for Backend {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            Some(self.handle(op))
+        }
+    }
+
+    /// Answers only log lines.
+    struct Logs;
+
+    impl PartialHandler<Op> for Logs {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            matches!(op, Op::Log(_)).then(|| Box::new(()) as Box<dyn Any + Send>)
+        }
+    }
+
+    /// Misses every cache lookup.
+    struct Misses;
+
+    impl PartialHandler<Op> for Misses {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            matches!(op, Op::Cache(_)).then(|| Box::new(None::<String>) as Box<dyn Any + Send>)
+        }
+    }
+
+    #[test]
+    fn test_or_else_falls_back_to_the_second_handler() {
+        let backend = Backend::default();
+        let handler = Logs.or_else(backend.clone());
+        assert_eq!(lookup(20).handle(handler).run_or_panic(), "stored 20");
+        assert_eq!(*backend.seen.lock().unwrap(), ["Cache.Get", "Db.Get"]);
+
+        let result = lookup(3).try_run_with(Logs.or_else(Logs));
+        assert!(matches!(result, Err(EffectError::Unhandled(_))));
+    }
+
+    #[test]
+    fn test_filter_hides_operations_from_the_handler() {
+        let backend = Backend::default();
+        let handler = backend
+            .clone()
+            .filter(|op: &Op| !matches!(op, Op::Cache(_)))
+            .or_else(Misses);
+        assert_eq!(lookup(3).try_run_with(handler).unwrap(), "stored 3");
+        assert_eq!(*backend.seen.lock().unwrap(), ["Db.Get", "Log.Line"]);
+    }
+
+    #[test]
+    fn test_map_reply_and_and_then_transform_replies() {
+        let upper = Backend::default().map_reply(|op: &Op, reply: Reply| match op {
+            Op::Db(_) => Reply::new(reply.take::<String>().to_uppercase()),
+            _ => reply,
+        });
+        assert_eq!(lookup(42).try_run_with(upper).unwrap(), "STORED 42");
+
+        // Cache misses are declined and answered by the database instead
+        let handler = Backend::default()
+            .and_then(|op: &Op, reply: Reply| match op {
+                Op::Cache(_) if reply.is::<Option<String>>() => {
+                    let hit = reply.take::<Option<String>>();
+                    hit.is_some().then(|| Reply::new(hit))
+                }
+                _ => Some(reply),
+            })
+            .or_else(Backend::default());
+        assert_eq!(lookup(5).try_run_with(handler).unwrap(), "cached 5");
+    }
+
+    #[test]
+    fn test_combinators_join_handler_chains() {
+        let result = lookup(12)
+            .begin_chain()
+            .handle(Logs.filter(|_: &Op| true))
+            .handle(Backend::default().or_else(Logs))
+            .run_or_panic();
+        assert_eq!(result, "stored 12");
+    }
+}
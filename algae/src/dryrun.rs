@@ -0,0 +1,348 @@
+//! Dry runs: performing the reads, only recording the writes.
+//!
+//! A [`DryRunHandler`] wraps the handler a program normally runs with, and
+//! passes it the operations that only read, so the program sees real data.
+//! The operations that write are recorded instead of being handled, and
+//! answered with fabricated replies, which makes it easy to give a CLI tool
+//! a `--dry-run` flag:
+//!
+//! ```rust,ignore
+//! effect! {
+//!     Fs::List (PathBuf) -> Vec<PathBuf>;
+//!     #[mutates]
+//!     Fs::Remove (PathBuf) -> ();
+//! }
+//!
+//! let handler = DryRunHandler::new(FsHandler)
+//!     .report_with(|op| eprintln!("would run {op:?}"));
+//! let log = handler.log();
+//! clean_up(dir).try_run_with(handler)?;
+//! println!("{} files would be removed", log.len());
+//! ```
+//!
+//! Operations declared with `#[mutates]` are the writes unless
+//! [`writes_when`](DryRunHandler::writes_when) says otherwise. Writes are
+//! answered with the default of the reply type declared in `effect!`; writes
+//! whose reply type has no default need a
+//! [`fabricate_with`](DryRunHandler::fabricate_with) that makes them up.
+
+use crate::{OpMeta, PartialHandler};
+use std::{
+    any::Any,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+type WritesFn<Op> = Box<dyn Fn(&Op) -> bool + Send>;
+type FabricateFn<Op> = Box<dyn Fn(&Op) -> Option<Box<dyn Any + Send>> + Send>;
+type ReportFn<Op> = Box<dyn Fn(&Op) + Send>;
+
+/// The writes a [`DryRunHandler`] skipped, in the order they were performed.
+///
+/// Clones share the same log.
+pub struct DryRunLog<Op> {
+    writes: Arc<Mutex<Vec<Op>>>,
+}
+
+impl<Op> Clone for DryRunLog<Op> {
+    fn clone(&self) -> Self {
+        Self {
+            writes: Arc::clone(&self.writes),
+        }
+    }
+}
+
+impl<Op> Default for DryRunLog<Op> {
+    fn default() -> Self {
+        Self {
+            writes: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl<Op: Clone> DryRunLog<Op> {
+    /// An empty log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The skipped writes.
+    pub fn writes(&self) -> Vec<Op> {
+        self.writes.lock().unwrap().clone()
+    }
+
+    /// Removes and returns the skipped writes.
+    pub fn take(&self) -> Vec<Op> {
+        std::mem::take(&mut *self.writes.lock().unwrap())
+    }
+
+    /// How many writes were skipped.
+    pub fn len(&self) -> usize {
+        self.writes.lock().unwrap().len()
+    }
+
+    /// Whether no write was skipped.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<Op: fmt::Debug> fmt::Debug for DryRunLog<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DryRunLog")
+            .field(&*self.writes.lock().unwrap())
+            .finish()
+    }
+}
+
+/// A handler wrapper that handles reads with its handler and only records
+/// writes.
+///
+/// # Type Parameters
+///
+/// * `Op` - The operation type
+/// * `H` - The wrapped partial handler, asked for the reads
+pub struct DryRunHandler<Op, H> {
+    inner: H,
+    log: DryRunLog<Op>,
+    writes: WritesFn<Op>,
+    fabricate: FabricateFn<Op>,
+    report: Option<ReportFn<Op>>,
+}
+
+impl<Op: OpMeta + Clone + 'static, H> DryRunHandler<Op, H> {
+    /// Wraps `inner`, treating the operations declared with `#[mutates]` as
+    /// writes and answering them with [`OpMeta::default_reply`].
+    ///
+    /// Writes whose reply type has no default are declined.
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            log: DryRunLog::new(),
+            writes: Box::new(Op::mutates_state),
+            fabricate: Box::new(Op::default_reply),
+            report: None,
+        }
+    }
+
+    /// Treats the operations `f` returns `true` for as writes, instead of
+    /// those declared with `#[mutates]`.
+    pub fn writes_when<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Op) -> bool + Send + 'static,
+    {
+        self.writes = Box::new(f);
+        self
+    }
+
+    /// Answers writes with the replies `f` makes up for them.
+    ///
+    /// Writes `f` returns `None` for are declined, so a handler after this
+    /// one can answer them; their reply must still have the type `perform!`
+    /// takes it as.
+    pub fn fabricate_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Op) -> Option<Box<dyn Any + Send>> + Send + 'static,
+    {
+        self.fabricate = Box::new(f);
+        self
+    }
+
+    /// Calls `f` with every write as it is skipped, e.g. to print what the
+    /// program would have done.
+    pub fn report_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Op) + Send + 'static,
+    {
+        self.report = Some(Box::new(f));
+        self
+    }
+
+    /// Records writes to `log` instead of a log of its own, e.g. to share
+    /// one between handlers.
+    pub fn with_log(mut self, log: &DryRunLog<Op>) -> Self {
+        self.log = log.clone();
+        self
+    }
+
+    /// A handle on the log, for reading the skipped writes after the
+    /// handler was handed to a computation.
+    pub fn log(&self) -> DryRunLog<Op> {
+        self.log.clone()
+    }
+
+    /// The wrapped handler.
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<Op, H> PartialHandler<Op> for DryRunHandler<Op, H>
+where
+    Op: Clone,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        if !(self.writes)(op) {
+            return self.inner.maybe_handle(op);
+        }
+        let reply = (self.fabricate)(op)?;
+        if let Some(report) = &self.report {
+            report(op);
+        }
+        self.log.writes.lock().unwrap().push(op.clone());
+        Some(reply)
+    }
+}
+
+impl<Op: fmt::Debug, H: fmt::Debug> fmt::Debug for DryRunHandler<Op, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DryRunHandler")
+            .field("inner", &self.inner)
+            .field("log", &self.log)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::collections::BTreeMap;
+
+    effect! {
+        Files::List -> Vec<String>;
+        #[mutates]
+        Files::Remove (String) -> ();
+        #[mutates]
+        Files::Prune (u64) -> u64;
+        #[mutates]
+        Files::Lock (String) -> Lock;
+    }
+
+    /// A lock on a file; there is no sensible default one.
+    #[derive(Debug)]
+    struct Lock;
+
+    /// Files by name, with their sizes.
+    #[derive(Debug, Default)]
+    struct Disk {
+        files: BTreeMap<String, u64>,
+    }
+
+    impl PartialHandler<Op> for Disk {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Files(op) = op;
+            Some(match op {
+                Files::List => Box::new(self.files.keys().cloned().collect::<Vec<_>>()),
+                Files::Remove(name) => {
+                    self.files.remove(name);
+                    Box::new(())
+                }
+                Files::Prune(max) => {
+                    let before = self.files.len();
+                    self.files.retain(|_, size| *size <= *max);
+                    Box::new((before - self.files.len()) as u64)
+                }
+                Files::Lock(_) => Box::new(Lock),
+            })
+        }
+    }
+
+    fn disk() -> Disk {
+        Disk {
+            files: BTreeMap::from([("a.log".to_string(), 10), ("b.txt".to_string(), 900)]),
+        }
+    }
+
+    #[effectful]
+    fn remove_logs() -> usize {
+        let names: Vec<String> = perform!(Files::List);
+        let mut removed = 0;
+        for name in names.into_iter().filter(|n| n.ends_with(".log")) {
+            let _: () = perform!(Files::Remove(name));
+            removed += 1;
+        }
+        removed
+    }
+
+    #[effectful]
+    fn prune(max: u64) -> u64 {
+        perform!(Files::Prune(max))
+    }
+
+    #[effectful]
+    fn lock(name: &'static str) -> Lock {
+        perform!(Files::Lock(name.to_string()))
+    }
+
+    #[test]
+    fn test_reads_are_handled_and_writes_recorded() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&reported);
+        let handler = DryRunHandler::new(disk())
+            .report_with(move |op| seen.lock().unwrap().push(format!("{op:?}")));
+        let log = handler.log();
+        let mut session = crate::session::HandlerSession::new(handler);
+
+        assert_eq!(session.try_run(remove_logs()).unwrap(), 1);
+        // Nothing was removed, so a second run removes the same file again
+        assert_eq!(session.try_run(remove_logs()).unwrap(), 1);
+        assert_eq!(
+            log.take(),
+            [
+                Op::files_remove("a.log".to_string()),
+                Op::files_remove("a.log".to_string())
+            ]
+        );
+        assert_eq!(reported.lock().unwrap().len(), 2);
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn test_writes_get_default_replies() {
+        let handler = DryRunHandler::new(disk());
+        let log = handler.log();
+        assert_eq!(prune(100).try_run_with(handler).unwrap(), 0);
+        assert_eq!(log.writes(), [Op::files_prune(100)]);
+
+        // Writes whose reply has no default are declined
+        let result = lock("a.log").try_run_with(DryRunHandler::new(disk()));
+        let Err(EffectError::Unhandled(unhandled)) = result else {
+            panic!("the lock should be unhandled");
+        };
+        assert_eq!(unhandled.op, Op::files_lock("a.log".to_string()));
+    }
+
+    #[test]
+    fn test_writes_get_fabricated_replies() {
+        let handler = DryRunHandler::new(disk()).fabricate_with(|op| match op {
+            Op::Files(Files::Prune(_)) => Some(Box::new(7u64)),
+            Op::Files(Files::Lock(_)) => Some(Box::new(Lock)),
+            _ => Some(Box::new(())),
+        });
+        let log = handler.log();
+        let mut session = crate::session::HandlerSession::new(handler);
+        assert_eq!(session.try_run(prune(100)).unwrap(), 7);
+        assert!(session.try_run(lock("b.txt")).is_ok());
+        assert_eq!(
+            log.writes(),
+            [Op::files_prune(100), Op::files_lock("b.txt".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_the_predicate_decides_what_is_written() {
+        let handler = DryRunHandler::new(disk())
+            .writes_when(|op| matches!(op, Op::Files(Files::Prune(_))))
+            .fabricate_with(|_| Some(Box::new(0u64)));
+        let log = handler.log();
+        let mut session = crate::session::HandlerSession::new(handler);
+        // Removes are not writes here, so they reach the disk
+        assert_eq!(session.try_run(remove_logs()).unwrap(), 1);
+        assert_eq!(session.try_run(remove_logs()).unwrap(), 0);
+        assert_eq!(session.try_run(prune(100)).unwrap(), 0);
+        assert_eq!(log.writes(), [Op::files_prune(100)]);
+    }
+}
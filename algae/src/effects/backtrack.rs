@@ -0,0 +1,250 @@
+//! Backtracking search.
+//!
+//! A computation describes a search by choosing among alternatives with
+//! [`Backtrack::Choose`] and rejecting dead ends with [`Backtrack::Fail`]. A
+//! [`Solver`] runs it depth-first: after a failure it goes back to the most
+//! recent choice with an untried alternative and continues from there, until
+//! the computation completes without failing.
+//!
+//! The solver is built on [`ChoiceExplorer`]: every attempt replays the
+//! computation from the start. Other operations are therefore performed once
+//! per attempt, and the handler answering them is rewound with
+//! [`Snapshot`] before each one.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::backtrack::{Backtrack, Solver};
+//! use std::convert::Infallible;
+//!
+//! effect! {
+//!     use algae::effects::backtrack::Backtrack;
+//! }
+//!
+//! #[effectful]
+//! fn pythagorean(limit: u32) -> (u32, u32, u32) {
+//!     let a: u32 = perform!(Backtrack::choose((1..=limit).collect()));
+//!     let b: u32 = perform!(Backtrack::choose((a..=limit).collect()));
+//!     let c: u32 = perform!(Backtrack::choose((b..=limit).collect()));
+//!     if a * a + b * b != c * c {
+//!         let _: Infallible = perform!(Backtrack::Fail);
+//!     }
+//!     (a, b, c)
+//! }
+//!
+//! assert_eq!(Solver::new().solve(|| pythagorean(20))?, Some((3, 4, 5)));
+//! ```
+
+use crate::{
+    nondet::{Alternatives, ChoiceExplorer, Snapshot},
+    Contains, EffectError, Effectful, PartialHandler,
+};
+use algae_macros::effect;
+use std::any::Any;
+
+effect! {
+    root BacktrackOp;
+    typed;
+    // `Choose` replies with one of the alternatives; annotate its type at the
+    // perform site.
+    Backtrack::Choose (Alternatives) -> Box<dyn Any + Send>;
+    Backtrack::Fail -> std::convert::Infallible;
+}
+
+impl Backtrack {
+    /// Chooses one of `values`.
+    pub fn choose<T>(values: Vec<T>) -> Self
+    where
+        T: Any + Send + Sync + Clone,
+    {
+        Backtrack::Choose(Alternatives::of(values))
+    }
+}
+
+/// Treats `Choose` as a choice among its alternatives and `Fail` as a choice
+/// without any.
+fn choices<Op: Contains<Backtrack>>(op: &Op) -> Option<Alternatives> {
+    match op.project()? {
+        Backtrack::Choose(alternatives) => Some(alternatives.clone()),
+        Backtrack::Fail => Some(Alternatives::none()),
+    }
+}
+
+/// Declines every operation, for searches that perform only [`Backtrack`].
+struct NoHandler;
+
+impl<Op> PartialHandler<Op> for NoHandler {
+    fn maybe_handle(&mut self, _: &Op) -> Option<Box<dyn Any + Send>> {
+        None
+    }
+}
+
+impl Snapshot for NoHandler {
+    type State = ();
+
+    fn snapshot(&self) {}
+
+    fn restore(&mut self, _: &()) {}
+}
+
+/// Runs [`Backtrack`] computations depth-first.
+///
+/// `computation` arguments build a fresh run of the search each time they
+/// are called; see [`ChoiceExplorer::explore`].
+pub struct Solver<Op> {
+    explorer: ChoiceExplorer<Op>,
+}
+
+impl<Op: Contains<Backtrack> + 'static> Solver<Op> {
+    pub fn new() -> Self {
+        Self {
+            explorer: ChoiceExplorer::new(choices::<Op>),
+        }
+    }
+
+    /// Returns the result of the first attempt that does not fail, or `None`
+    /// if every attempt fails.
+    ///
+    /// # Errors
+    ///
+    /// Any operation other than [`Backtrack`] is reported as unhandled.
+    pub fn solve<R, C>(&mut self, computation: C) -> Result<Option<R>, EffectError<Op>>
+    where
+        C: FnMut() -> Effectful<R, Op>,
+    {
+        self.solve_with(computation, &mut NoHandler)
+    }
+
+    /// Returns the results of every attempt that does not fail.
+    pub fn solve_all<R, C>(&mut self, computation: C) -> Result<Vec<R>, EffectError<Op>>
+    where
+        C: FnMut() -> Effectful<R, Op>,
+    {
+        self.solve_all_with(computation, &mut NoHandler)
+    }
+
+    /// Like [`solve`](Self::solve), answering other operations with `h`.
+    ///
+    /// `h` is rewound to its initial state before every attempt, so the
+    /// successful attempt leaves it as if it had been the only one.
+    pub fn solve_with<R, C, H>(
+        &mut self,
+        computation: C,
+        h: &mut H,
+    ) -> Result<Option<R>, EffectError<Op>>
+    where
+        C: FnMut() -> Effectful<R, Op>,
+        H: PartialHandler<Op> + Snapshot,
+    {
+        let initial = h.snapshot();
+        let mut solutions =
+            self.explorer
+                .explore_with(computation, h, |h| h.restore(&initial), 1)?;
+        Ok(solutions.pop())
+    }
+
+    /// Like [`solve_all`](Self::solve_all), answering other operations with
+    /// `h`.
+    pub fn solve_all_with<R, C, H>(
+        &mut self,
+        computation: C,
+        h: &mut H,
+    ) -> Result<Vec<R>, EffectError<Op>>
+    where
+        C: FnMut() -> Effectful<R, Op>,
+        H: PartialHandler<Op> + Snapshot,
+    {
+        self.explorer.explore_snapshotted(computation, h)
+    }
+}
+
+impl<Op: Contains<Backtrack> + 'static> Default for Solver<Op> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Op> std::fmt::Debug for Solver<Op> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Solver").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use crate::effects::log::{Log, LogEvent, MemoryLog};
+    use algae::prelude::*;
+    use std::convert::Infallible;
+
+    effect! {
+        use algae::effects::backtrack::Backtrack;
+        use algae::effects::log::Log;
+    }
+
+    #[effectful]
+    fn queens(n: usize) -> Vec<usize> {
+        let mut columns: Vec<usize> = Vec::new();
+        for row in 0..n {
+            let col: usize = perform!(Backtrack::choose((0..n).collect::<Vec<usize>>()));
+            let attacked = columns
+                .iter()
+                .enumerate()
+                .any(|(r, &c)| c == col || row - r == col.abs_diff(c));
+            if attacked {
+                let _: Infallible = perform!(Backtrack::Fail);
+            }
+            columns.push(col);
+        }
+        columns
+    }
+
+    #[effectful]
+    fn logged_digit(target: u32) -> u32 {
+        let digit: u32 = perform!(Backtrack::choose((0..10).collect::<Vec<u32>>()));
+        let _: () = perform!(Log::Event(LogEvent::info(format!("trying {digit}"))));
+        if digit * digit != target {
+            let _: Infallible = perform!(Backtrack::Fail);
+        }
+        digit
+    }
+
+    #[test]
+    fn test_solve_finds_first_solution() {
+        let solution = Solver::new().solve(|| queens(6)).unwrap();
+        assert_eq!(solution, Some(vec![1, 3, 5, 0, 2, 4]));
+    }
+
+    #[test]
+    fn test_solve_all_finds_every_solution() {
+        assert_eq!(Solver::new().solve_all(|| queens(6)).unwrap().len(), 4);
+        assert_eq!(Solver::new().solve_all(|| queens(8)).unwrap().len(), 92);
+    }
+
+    #[test]
+    fn test_no_solution() {
+        assert_eq!(Solver::new().solve(|| queens(3)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_handler_is_rewound_between_attempts() {
+        let log = MemoryLog::new();
+        let solution = Solver::new()
+            .solve_with(|| logged_digit(49), &mut log.clone())
+            .unwrap();
+        assert_eq!(solution, Some(7));
+        let messages: Vec<String> = log.events().into_iter().map(|e| e.msg).collect();
+        assert_eq!(messages, ["trying 7"]);
+    }
+
+    #[test]
+    fn test_other_ops_need_a_handler() {
+        match Solver::new().solve(|| logged_digit(4)) {
+            Err(EffectError::Unhandled(UnhandledOp { op: Op::Log(_), .. })) => {}
+            other => panic!("expected an unhandled log op, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Handlers for programs running in a browser (wasm32, feature `browser` or
+//! its alias `wasm`).
+//!
+//! [`WebConsole`] writes [`Log`] events to the browser console, at the
+//! console method matching their level. [`WebStorage`] keeps [`Storage`]
+//! values in `localStorage` or `sessionStorage`. [`WebFetch`] answers [`Http`]
+//! operations with `fetch`; since a browser cannot block on a request,
+//! [`WebFetch::run`] is `async` and suspends the computation until the
+//! response arrives, so it runs inside the page's event loop:
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::browser::{WebConsole, WebFetch};
+//!
+//! effect! {
+//!     use algae::effects::http::Http;
+//!     use algae::effects::log::Log;
+//! }
+//!
+//! #[wasm_bindgen(start)]
+//! fn start() {
+//!     wasm_bindgen_futures::spawn_local(async {
+//!         let result = WebFetch::new().run(load_dashboard(), WebConsole::new()).await;
+//!     });
+//! }
+//! ```
+//!
+//! `WebConsole` and `WebFetch` work on the main thread and in web workers;
+//! web storage only exists on the main thread.
+//!
+//! The rest of the runtime compiles to `wasm32-unknown-unknown` too, but
+//! that target has no threads and no clock: [`Effectful::join`] and
+//! [`join_all`](Effectful::join_all) panic there, as do the layers that time
+//! operations (`timeout`, `observe`, the `tracing` spans).
+
+use crate::{
+    dispatch_effect,
+    effects::{
+        http::{Http, HttpRequest, HttpResponse},
+        log::{Level, Log},
+        storage::Storage,
+    },
+    Contains, EffectError, Effectful, PartialHandler,
+};
+use js_sys::Uint8Array;
+use std::{any::Any, io, ops::CoroutineState};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{console, Request, RequestInit, Response, WorkerGlobalScope};
+
+/// Handles [`Log`] operations by writing to the browser console.
+///
+/// Events are rendered as `msg key=value ...`. Trace and debug events go to
+/// `console.debug`, the others to `console.info`, `console.warn` and
+/// `console.error`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WebConsole;
+
+impl WebConsole {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<Op: Contains<Log>> PartialHandler<Op> for WebConsole {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let Log::Event(event) = op.project()?;
+        let line = if event.fields.is_empty() {
+            JsValue::from_str(&event.msg)
+        } else {
+            JsValue::from_str(&format!("{} {}", event.msg, event.fields_display()))
+        };
+        match event.level {
+            Level::Trace | Level::Debug => console::debug_1(&line),
+            Level::Info => console::info_1(&line),
+            Level::Warn => console::warn_1(&line),
+            Level::Error => console::error_1(&line),
+        }
+        Some(Box::new(()))
+    }
+}
+
+impl_into_vec_handler_for_family!(Log: WebConsole);
+
+/// Handles [`Storage`] operations with the page's `localStorage`, or its
+/// `sessionStorage` with [`WebStorage::session`].
+///
+/// Failures, e.g. storage disabled by the user or a write over the quota,
+/// are replied as `io::Error`s, as is every operation in a web worker.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WebStorage {
+    session: bool,
+}
+
+impl WebStorage {
+    /// Uses `localStorage`, which persists across visits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `sessionStorage`, which lasts as long as the tab.
+    pub fn session() -> Self {
+        Self { session: true }
+    }
+
+    fn storage(&self) -> io::Result<web_sys::Storage> {
+        let window = web_sys::window()
+            .ok_or_else(|| io::Error::other("web storage is only available on the main thread"))?;
+        let storage = if self.session {
+            window.session_storage()
+        } else {
+            window.local_storage()
+        };
+        storage
+            .map_err(js_error)?
+            .ok_or_else(|| io::Error::other("web storage is disabled"))
+    }
+}
+
+impl<Op: Contains<Storage>> PartialHandler<Op> for WebStorage {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let op = op.project()?;
+        let storage = self.storage();
+        Some(match op {
+            Storage::Get(key) => Box::new(storage.and_then(|s| s.get_item(key).map_err(js_error))),
+            Storage::Set((key, value)) => {
+                Box::new(storage.and_then(|s| s.set_item(key, value).map_err(js_error)))
+            }
+            Storage::Remove(key) => {
+                Box::new(storage.and_then(|s| s.remove_item(key).map_err(js_error)))
+            }
+        })
+    }
+}
+
+impl_into_vec_handler_for_family!(Storage: WebStorage);
+
+/// Answers [`Http`] operations with the browser's `fetch`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WebFetch;
+
+impl WebFetch {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Runs `computation`, fetching its [`Http`] requests and passing every
+    /// other operation to `other`.
+    ///
+    /// A failed `fetch`, e.g. a network error or a CORS rejection, is
+    /// replied as an `io::Error`.
+    pub async fn run<R, Op, H>(
+        &self,
+        mut computation: Effectful<R, Op>,
+        mut other: H,
+    ) -> Result<R, EffectError<Op>>
+    where
+        Op: Contains<Http>,
+        H: PartialHandler<Op>,
+    {
+        let mut reply = None;
+        loop {
+            let eff = match computation.step(reply) {
+                CoroutineState::Yielded(eff) => eff,
+                CoroutineState::Complete(result) => return Ok(result),
+            };
+            let fetched = match eff.op.project() {
+                Some(Http::Fetch(request)) => Some(fetch(request).await),
+                None => None,
+            };
+            reply = Some(match fetched {
+                Some(response) => {
+                    let mut response = Some(Box::new(response) as Box<dyn Any + Send>);
+                    dispatch_effect(eff, &mut |_| response.take())?
+                }
+                None => dispatch_effect(eff, &mut |op| other.maybe_handle(op))?,
+            });
+        }
+    }
+}
+
+fn js_error(value: JsValue) -> io::Error {
+    let message = value
+        .as_string()
+        .or_else(|| {
+            value
+                .dyn_ref::<js_sys::Error>()
+                .map(|e| String::from(e.message()))
+        })
+        .unwrap_or_else(|| format!("{value:?}"));
+    io::Error::other(message)
+}
+
+async fn fetch(request: &HttpRequest) -> io::Result<HttpResponse> {
+    let init = RequestInit::new();
+    init.set_method(&request.method);
+    if !request.body.is_empty() {
+        init.set_body(&Uint8Array::from(&request.body[..]));
+    }
+    let js_request = Request::new_with_str_and_init(&request.url, &init).map_err(js_error)?;
+    for (name, value) in &request.headers {
+        js_request.headers().append(name, value).map_err(js_error)?;
+    }
+
+    // `fetch` lives on the window on the main thread and on the global
+    // scope in workers
+    let promise = match web_sys::window() {
+        Some(window) => window.fetch_with_request(&js_request),
+        None => js_sys::global()
+            .unchecked_into::<WorkerGlobalScope>()
+            .fetch_with_request(&js_request),
+    };
+    let response: Response = JsFuture::from(promise)
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+
+    let mut headers = Vec::new();
+    let entries = js_sys::try_iter(&response.headers())
+        .map_err(js_error)?
+        .ok_or_else(|| io::Error::other("response headers are not iterable"))?;
+    for entry in entries {
+        let entry: js_sys::Array = entry.map_err(js_error)?.unchecked_into();
+        headers.push((
+            entry.get(0).as_string().unwrap_or_default(),
+            entry.get(1).as_string().unwrap_or_default(),
+        ));
+    }
+    let body = JsFuture::from(response.array_buffer().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    Ok(HttpResponse {
+        status: response.status(),
+        headers,
+        body: Uint8Array::new(&body).to_vec(),
+    })
+}
@@ -0,0 +1,554 @@
+//! Layered configuration.
+//!
+//! Code reads settings with [`Config::Get`] (missing keys are `None`) or
+//! [`Config::Require`] (missing keys abort the computation with
+//! [`MissingConfig`]). Keys are dotted paths such as `"db.url"`.
+//!
+//! Each source is its own handler and *declines* keys it does not know, so
+//! sources layer naturally in a handler chain, earlier handlers overriding
+//! later ones. End the chain with [`ConfigFallback`], which answers for keys
+//! no source knows:
+//!
+//! - [`EnvConfig`] - environment variables (`db.url` → `APP_DB_URL`)
+//! - `TomlConfig` - a TOML document, nested tables flattened (feature `toml`)
+//! - [`MapConfig`] - hard-coded values, for defaults and tests
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::config::*;
+//!
+//! effect! {
+//!     use algae::effects::config::Config;
+//!     Db::Connect (String) -> bool;
+//! }
+//!
+//! #[effectful]
+//! fn connect() -> bool {
+//!     let url: Value = perform!(Config::Require("db.url".into()));
+//!     let pool: Option<Value> = perform!(Config::Get("db.pool".into()));
+//!     let pool = pool.and_then(|v| v.as_i64()).unwrap_or(4);
+//!     perform!(Db::Connect(format!("{url}?pool={pool}")))
+//! }
+//!
+//! // env overrides file overrides defaults
+//! connect()
+//!     .begin_chain()
+//!     .handle(DbHandler)
+//!     .handle(EnvConfig::with_prefix("APP"))
+//!     .handle(TomlConfig::from_file("app.toml")?)
+//!     .handle(MapConfig::new().set("db.pool", 8))
+//!     .handle(ConfigFallback)
+//!     .run()?;
+//! ```
+
+use crate::{Abort, Contains, PartialHandler};
+use algae_macros::effect;
+use std::{any::Any, collections::HashMap, fmt};
+
+effect! {
+    root ConfigOp;
+    typed;
+    Config::Get (String) -> Option<Value>;
+    Config::Require (String) -> Value;
+}
+
+/// A configuration value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    List(Vec<Value>),
+}
+
+impl Value {
+    /// The value as a string slice, if it is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value as an integer. Strings are parsed, since sources such as
+    /// environment variables only produce strings.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Str(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value as a float. Integers are widened and strings are parsed.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Float(n) => Some(*n),
+            Value::Int(n) => Some(*n as f64),
+            Value::Str(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value as a boolean. The strings `true`/`false`, `1`/`0`,
+    /// `yes`/`no` and `on`/`off` are accepted, ignoring case.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            Value::Str(s) => match s.trim().to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => Some(true),
+                "false" | "0" | "no" | "off" => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The value as a list.
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => f.write_str(s),
+            Value::Int(n) => write!(f, "{n}"),
+            Value::Float(n) => write!(f, "{n}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::List(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Str(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Str(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Self {
+        Value::Int(v.into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(v: Vec<T>) -> Self {
+        Value::List(v.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Typed error carried by the [`Abort`] that [`ConfigFallback`] replies with
+/// when a required key is not set by any source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingConfig {
+    pub key: String,
+}
+
+impl fmt::Display for MissingConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "required configuration key `{}` is not set", self.key)
+    }
+}
+
+impl std::error::Error for MissingConfig {}
+
+/// Answers a [`Config`] operation from `lookup`, declining unknown keys.
+fn answer<Op: Contains<Config>>(
+    op: &Op,
+    lookup: impl FnOnce(&str) -> Option<Value>,
+) -> Option<Box<dyn Any + Send>> {
+    match op.project()? {
+        Config::Get(key) => lookup(key).map(|v| Box::new(Some(v)) as Box<dyn Any + Send>),
+        Config::Require(key) => lookup(key).map(|v| Box::new(v) as Box<dyn Any + Send>),
+    }
+}
+
+/// Reads configuration from environment variables.
+///
+/// A key maps to a variable by upper-casing it and replacing `.` and `-`
+/// with `_`, after an optional prefix: with prefix `APP`, `db.url` is read
+/// from `APP_DB_URL`. Values are always [`Value::Str`].
+#[derive(Debug, Clone, Default)]
+pub struct EnvConfig {
+    prefix: Option<String>,
+    vars: Option<HashMap<String, String>>,
+}
+
+impl EnvConfig {
+    /// Reads the process environment without a prefix.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the process environment, prefixing variable names with `prefix_`.
+    pub fn with_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: Some(prefix.into()),
+            vars: None,
+        }
+    }
+
+    /// Reads from the given variables instead of the process environment.
+    pub fn from_vars<K, V>(vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        Self {
+            prefix: None,
+            vars: Some(
+                vars.into_iter()
+                    .map(|(k, v)| (k.into(), v.into()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Sets the prefix for variable names.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// The environment variable `key` is read from.
+    pub fn var_name(&self, key: &str) -> String {
+        let key = key.to_ascii_uppercase().replace(['.', '-'], "_");
+        match &self.prefix {
+            Some(prefix) => format!("{prefix}_{key}"),
+            None => key,
+        }
+    }
+
+    fn lookup(&self, key: &str) -> Option<Value> {
+        let name = self.var_name(key);
+        let value = match &self.vars {
+            Some(vars) => vars.get(&name).cloned(),
+            None => std::env::var(&name).ok(),
+        };
+        value.map(Value::Str)
+    }
+}
+
+impl<Op: Contains<Config>> PartialHandler<Op> for EnvConfig {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        answer(op, |key| self.lookup(key))
+    }
+}
+
+/// Hard-coded configuration values, for defaults and tests.
+#[derive(Debug, Clone, Default)]
+pub struct MapConfig {
+    values: HashMap<String, Value>,
+}
+
+impl MapConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `key` to `value`.
+    pub fn set(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.values.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> FromIterator<(K, V)> for MapConfig {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            values: iter
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        }
+    }
+}
+
+impl<Op: Contains<Config>> PartialHandler<Op> for MapConfig {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        answer(op, |key| self.values.get(key).cloned())
+    }
+}
+
+/// Answers [`Config`] operations for keys no earlier source knows.
+///
+/// [`Config::Get`] replies `None`; [`Config::Require`] aborts with
+/// [`MissingConfig`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfigFallback;
+
+impl<Op: Contains<Config>> PartialHandler<Op> for ConfigFallback {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            Config::Get(_) => Some(Box::new(None::<Value>)),
+            Config::Require(key) => Some(Abort::boxed(MissingConfig { key: key.clone() })),
+        }
+    }
+}
+
+impl_into_vec_handler_for_family!(Config: EnvConfig, MapConfig, ConfigFallback);
+
+#[cfg(feature = "toml")]
+pub use self::toml_config::{TomlConfig, TomlConfigError};
+
+#[cfg(feature = "toml")]
+mod toml_config {
+    use super::{answer, Config, Value};
+    use crate::{Contains, PartialHandler};
+    use std::{any::Any, collections::HashMap, fmt, path::Path};
+
+    /// Reads configuration from a TOML document.
+    ///
+    /// Nested tables are flattened into dotted keys, so
+    ///
+    /// ```toml
+    /// [db]
+    /// url = "postgres://localhost"
+    /// ```
+    ///
+    /// answers the key `db.url`. Dates and times are returned as strings.
+    #[derive(Debug, Clone, Default)]
+    pub struct TomlConfig {
+        values: HashMap<String, Value>,
+    }
+
+    /// Error returned when a TOML configuration file cannot be loaded.
+    #[derive(Debug)]
+    pub enum TomlConfigError {
+        Io(std::io::Error),
+        Parse(toml::de::Error),
+    }
+
+    impl fmt::Display for TomlConfigError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                TomlConfigError::Io(e) => write!(f, "cannot read configuration file: {e}"),
+                TomlConfigError::Parse(e) => write!(f, "invalid TOML configuration: {e}"),
+            }
+        }
+    }
+
+    impl std::error::Error for TomlConfigError {}
+
+    impl TomlConfig {
+        /// Parses a TOML document.
+        pub fn parse(source: &str) -> Result<Self, TomlConfigError> {
+            let table: toml::Table = source.parse().map_err(TomlConfigError::Parse)?;
+            let mut values = HashMap::new();
+            flatten("", table, &mut values);
+            Ok(Self { values })
+        }
+
+        /// Reads and parses a TOML file.
+        pub fn from_file(path: impl AsRef<Path>) -> Result<Self, TomlConfigError> {
+            let source = std::fs::read_to_string(path).map_err(TomlConfigError::Io)?;
+            Self::parse(&source)
+        }
+    }
+
+    fn flatten(prefix: &str, table: toml::Table, out: &mut HashMap<String, Value>) {
+        for (key, value) in table {
+            let key = if prefix.is_empty() {
+                key
+            } else {
+                format!("{prefix}.{key}")
+            };
+            match value {
+                toml::Value::Table(table) => flatten(&key, table, out),
+                value => {
+                    out.insert(key, convert(value));
+                }
+            }
+        }
+    }
+
+    fn convert(value: toml::Value) -> Value {
+        match value {
+            toml::Value::String(s) => Value::Str(s),
+            toml::Value::Integer(n) => Value::Int(n),
+            toml::Value::Float(n) => Value::Float(n),
+            toml::Value::Boolean(b) => Value::Bool(b),
+            toml::Value::Datetime(dt) => Value::Str(dt.to_string()),
+            toml::Value::Array(items) => Value::List(items.into_iter().map(convert).collect()),
+            // Tables nested in arrays have no dotted key; keep them readable.
+            toml::Value::Table(table) => Value::Str(table.to_string()),
+        }
+    }
+
+    impl<Op: Contains<Config>> PartialHandler<Op> for TomlConfig {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            answer(op, |key| self.values.get(key).cloned())
+        }
+    }
+
+    impl_into_vec_handler_for_family!(Config: TomlConfig);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        use algae::effects::config::Config;
+        Db::Connect (String) -> String;
+    }
+
+    struct DbHandler;
+
+    impl PartialHandler<Op> for DbHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Db(Db::Connect(dsn)) => Some(Box::new(format!("connected to {dsn}"))),
+                _ => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(DbHandler, Op);
+
+    #[effectful]
+    fn connect() -> String {
+        let url: Value = perform!(Config::Require("db.url".into()));
+        let pool: Option<Value> = perform!(Config::Get("db.pool".into()));
+        let pool = pool.and_then(|v| v.as_i64()).unwrap_or(4);
+        perform!(Db::Connect(format!("{url}?pool={pool}")))
+    }
+
+    fn defaults() -> MapConfig {
+        MapConfig::new()
+            .set("db.url", "postgres://defaults")
+            .set("db.pool", 8)
+    }
+
+    #[test]
+    fn test_earlier_sources_override_later_ones() {
+        let env = EnvConfig::from_vars([("APP_DB_POOL", "16")]).prefix("APP");
+        let result = connect()
+            .begin_chain()
+            .handle(DbHandler)
+            .handle(env)
+            .handle(defaults())
+            .handle(ConfigFallback)
+            .run_or_panic();
+        assert_eq!(result, "connected to postgres://defaults?pool=16");
+    }
+
+    #[test]
+    fn test_optional_key_falls_back_to_none() {
+        let result = connect()
+            .begin_chain()
+            .handle(DbHandler)
+            .handle(MapConfig::from_iter([("db.url", "sqlite::memory:")]))
+            .handle(ConfigFallback)
+            .run_or_panic();
+        assert_eq!(result, "connected to sqlite::memory:?pool=4");
+    }
+
+    #[test]
+    fn test_missing_required_key_aborts() {
+        let err = connect()
+            .begin_chain()
+            .handle(DbHandler)
+            .handle(EnvConfig::from_vars::<&str, &str>([]))
+            .handle(ConfigFallback)
+            .run()
+            .unwrap_err();
+        match err {
+            EffectError::Aborted(abort) => assert_eq!(
+                abort.downcast::<MissingConfig>().unwrap(),
+                MissingConfig {
+                    key: "db.url".into()
+                }
+            ),
+            other => panic!("expected missing config, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_value_conversions() {
+        assert_eq!(
+            EnvConfig::with_prefix("APP").var_name("db.max-conns"),
+            "APP_DB_MAX_CONNS"
+        );
+        assert_eq!(Value::from(" 42 ").as_i64(), Some(42));
+        assert_eq!(Value::from(3).as_f64(), Some(3.0));
+        assert_eq!(Value::from("Off").as_bool(), Some(false));
+        assert_eq!(Value::from("maybe").as_bool(), None);
+        assert_eq!(Value::from(vec![1, 2]).to_string(), "[1, 2]");
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_config() {
+        let toml = TomlConfig::parse(
+            r#"
+            [db]
+            url = "postgres://file"
+            replicas = ["a", "b"]
+            "#,
+        )
+        .unwrap();
+        let result = connect()
+            .begin_chain()
+            .handle(DbHandler)
+            .handle(toml.clone())
+            .handle(defaults())
+            .handle(ConfigFallback)
+            .run_or_panic();
+        assert_eq!(result, "connected to postgres://file?pool=8");
+
+        let mut toml = toml;
+        let reply = PartialHandler::<ConfigOp>::maybe_handle(
+            &mut toml,
+            &Config::Get("db.replicas".into()).into(),
+        );
+        let replicas = reply.unwrap().downcast::<Option<Value>>().unwrap();
+        assert_eq!(*replicas, Some(Value::from(vec!["a", "b"])));
+        assert!(TomlConfig::parse("not = [valid").is_err());
+    }
+}
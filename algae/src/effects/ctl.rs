@@ -0,0 +1,208 @@
+//! Cooperative cancellation.
+//!
+//! Long computations poll for cancellation at points where stopping is safe.
+//! [`Ctl::IsCancelled`] lets the computation decide what to do itself, while
+//! [`Ctl::CheckCancelled`] simply stops it: if cancellation was requested the
+//! [`CancellationHandler`] replies with an [`Abort`] carrying [`Cancelled`].
+//!
+//! The handler is wired to a [`CancellationToken`]. Clones of the token can be
+//! handed to other threads, signal handlers, UI buttons, ... and tripped from
+//! there.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::ctl::{CancellationHandler, CancellationToken, Cancelled, Ctl};
+//!
+//! effect! {
+//!     use algae::effects::ctl::Ctl;
+//!     Job::Process (u32) -> ();
+//! }
+//!
+//! #[effectful]
+//! fn process_all(items: Vec<u32>) {
+//!     for item in items {
+//!         let _: () = perform!(Ctl::CheckCancelled);
+//!         let _: () = perform!(Job::Process(item));
+//!     }
+//! }
+//!
+//! let token = CancellationToken::new();
+//! let trip = token.clone();
+//! ctrlc::set_handler(move || trip.cancel())?;
+//!
+//! match process_all(items)
+//!     .begin_chain()
+//!     .handle(JobHandler)
+//!     .handle(CancellationHandler::new(token))
+//!     .run()
+//! {
+//!     Err(EffectError::Aborted(abort)) if abort.is::<Cancelled>() => eprintln!("cancelled"),
+//!     other => other.map(|_| ())?,
+//! }
+//! ```
+
+use crate::{Abort, Contains, PartialHandler};
+use algae_macros::effect;
+use std::any::Any;
+
+pub use crate::cancel::{CancellationToken, Cancelled};
+
+effect! {
+    root CtlOp;
+    typed;
+    Ctl::IsCancelled -> bool;
+    Ctl::CheckCancelled -> ();
+}
+
+/// Handles [`Ctl`] operations by consulting a [`CancellationToken`].
+#[derive(Debug, Clone)]
+pub struct CancellationHandler {
+    token: CancellationToken,
+}
+
+impl CancellationHandler {
+    pub fn new(token: CancellationToken) -> Self {
+        Self { token }
+    }
+
+    /// The token this handler watches.
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl<Op: Contains<Ctl>> PartialHandler<Op> for CancellationHandler {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let cancelled = self.token.is_cancelled();
+        match op.project()? {
+            Ctl::IsCancelled => Some(Box::new(cancelled)),
+            Ctl::CheckCancelled if cancelled => Some(Abort::boxed(Cancelled)),
+            Ctl::CheckCancelled => Some(Box::new(())),
+        }
+    }
+}
+
+impl_into_vec_handler_for_family!(Ctl: CancellationHandler);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        use algae::effects::ctl::Ctl;
+        Job::Process (u32) -> ();
+    }
+
+    /// Trips the token after `budget` items have been processed.
+    struct JobHandler {
+        budget: u32,
+        token: CancellationToken,
+    }
+
+    impl PartialHandler<Op> for JobHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Job(Job::Process(_)) => {
+                    self.budget -= 1;
+                    if self.budget == 0 {
+                        self.token.cancel();
+                    }
+                    Some(Box::new(()))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(JobHandler, Op);
+
+    #[effectful]
+    fn process_checked(items: u32) -> u32 {
+        let mut done = 0;
+        for item in 0..items {
+            let _: () = perform!(Ctl::CheckCancelled);
+            let _: () = perform!(Job::Process(item));
+            done += 1;
+        }
+        done
+    }
+
+    #[effectful]
+    fn process_polling(items: u32) -> u32 {
+        let mut done = 0;
+        for item in 0..items {
+            let cancelled: bool = perform!(Ctl::IsCancelled);
+            if cancelled {
+                break;
+            }
+            let _: () = perform!(Job::Process(item));
+            done += 1;
+        }
+        done
+    }
+
+    fn handlers(budget: u32) -> (JobHandler, CancellationHandler) {
+        let token = CancellationToken::new();
+        let job = JobHandler {
+            budget,
+            token: token.clone(),
+        };
+        (job, CancellationHandler::new(token))
+    }
+
+    #[test]
+    fn test_check_cancelled_aborts() {
+        let (job, ctl) = handlers(3);
+        let err = process_checked(10)
+            .begin_chain()
+            .handle(job)
+            .handle(ctl)
+            .run()
+            .unwrap_err();
+        match err {
+            EffectError::Aborted(abort) => {
+                assert_eq!(abort.downcast::<Cancelled>().unwrap(), Cancelled)
+            }
+            other => panic!("expected cancellation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_uncancelled_runs_to_completion() {
+        let (job, ctl) = handlers(100);
+        let done = process_checked(10)
+            .begin_chain()
+            .handle(job)
+            .handle(ctl)
+            .run_or_panic();
+        assert_eq!(done, 10);
+    }
+
+    #[test]
+    fn test_is_cancelled_lets_computation_stop_itself() {
+        let (job, ctl) = handlers(4);
+        let done = process_polling(10)
+            .begin_chain()
+            .handle(job)
+            .handle(ctl)
+            .run_or_panic();
+        assert_eq!(done, 4);
+    }
+
+    #[test]
+    fn test_token_tripped_from_another_thread() {
+        let token = CancellationToken::new();
+        let trip = token.clone();
+        std::thread::spawn(move || trip.cancel()).join().unwrap();
+
+        assert!(token.is_cancelled());
+        let mut ctl = CancellationHandler::new(token);
+        let reply = PartialHandler::<CtlOp>::maybe_handle(&mut ctl, &Ctl::IsCancelled.into());
+        assert!(*reply.unwrap().downcast::<bool>().unwrap());
+    }
+}
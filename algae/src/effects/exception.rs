@@ -0,0 +1,219 @@
+//! Exceptions: stopping a computation early with an error.
+//!
+//! [`Exception::Throw`] ends the computation with an `E` instead of its
+//! result. Its reply type is [`Infallible`], as a throw never returns.
+//! [`run_exception`] catches the exceptions of one computation and returns
+//! `Result<R, E>`, with every other operation performed outwards.
+//! [`ExceptionHandler`] instead turns a throw into an [`Abort`] carrying the
+//! `E`, failing the whole run.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::exception::{run_exception, Exception};
+//! use std::convert::Infallible;
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! struct Overdrawn(u64);
+//!
+//! effect! {
+//!     use algae::effects::exception::Exception<Overdrawn>;
+//!     Bank::Balance -> u64;
+//! }
+//!
+//! #[effectful]
+//! fn withdraw(amount: u64) -> u64 {
+//!     let balance: u64 = perform!(Bank::Balance);
+//!     if balance < amount {
+//!         let _: Infallible = perform!(Exception::Throw(Overdrawn(amount - balance)));
+//!     }
+//!     balance - amount
+//! }
+//!
+//! match run_exception(withdraw(50)).handle(BankHandler).run_try() {
+//!     Ok(left) => println!("{left} left"),
+//!     Err(RunError::Failed(Overdrawn(by))) => eprintln!("overdrawn by {by}"),
+//!     Err(other) => eprintln!("{other}"),
+//! }
+//! ```
+
+use crate::{Abort, Contains, Effectful, IntoVecHandler, PartialHandler, Reply, VecHandler};
+use algae_macros::effect;
+use std::{any::Any, convert::Infallible, fmt, marker::PhantomData, ops::CoroutineState};
+
+effect! {
+    root ExceptionOp;
+    typed;
+    Exception<E>::Throw (E) -> Infallible;
+}
+
+/// Handles [`Exception<E>`] operations by aborting with the thrown `E`.
+///
+/// The run then fails with [`EffectError::Aborted`](crate::EffectError::Aborted),
+/// and [`Abort::downcast`] gives the `E` back.
+pub struct ExceptionHandler<E> {
+    _error: PhantomData<fn(E)>,
+}
+
+impl<E> ExceptionHandler<E> {
+    pub fn new() -> Self {
+        Self {
+            _error: PhantomData,
+        }
+    }
+}
+
+impl<E> Default for ExceptionHandler<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> Clone for ExceptionHandler<E> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<E> fmt::Debug for ExceptionHandler<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExceptionHandler").finish()
+    }
+}
+
+impl<E, Op> PartialHandler<Op> for ExceptionHandler<E>
+where
+    E: Clone + fmt::Debug + Send + 'static,
+    Op: Contains<Exception<E>>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let Exception::Throw(error) = op.project()?;
+        Some(Abort::boxed(error.clone()))
+    }
+}
+
+// `impl_into_vec_handler_for_family!` takes no type parameters.
+impl<E, Op> IntoVecHandler<Op> for ExceptionHandler<E>
+where
+    E: Clone + fmt::Debug + Send + 'static,
+    Op: Contains<Exception<E>> + 'static,
+{
+    fn into_vec_handler(self) -> VecHandler<Op> {
+        let mut vec = VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+/// Runs `computation` until it finishes with `Ok` of its result or throws
+/// an `E`, which stops it and becomes the `Err`.
+///
+/// All other operations are performed by the returned computation, to be
+/// answered by the handlers it is run with. [`Handled::run_try`](crate::Handled::run_try)
+/// then reports a thrown `E` as [`RunError::Failed`](crate::RunError::Failed).
+pub fn run_exception<R, E, Op>(computation: Effectful<R, Op>) -> Effectful<Result<R, E>, Op>
+where
+    R: Send + 'static,
+    E: Clone + Send + 'static,
+    Op: Contains<Exception<E>> + Send + 'static,
+{
+    let mut inner = computation;
+    Effectful::new(
+        #[coroutine]
+        move |_reply: Option<Reply>| {
+            let mut reply = None;
+            loop {
+                let eff = match inner.step(reply.take()) {
+                    CoroutineState::Yielded(eff) => eff,
+                    CoroutineState::Complete(result) => return Ok(result),
+                };
+                if let Some(Exception::Throw(error)) = eff.op.project() {
+                    return Err(error.clone());
+                }
+                reply = yield eff;
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Overdrawn(u64);
+
+    effect! {
+        use algae::effects::exception::Exception<Overdrawn>;
+        Bank::Balance -> u64;
+        Bank::Debit (u64) -> ();
+    }
+
+    /// A bank account; declines `Exception`.
+    struct Account {
+        balance: u64,
+    }
+
+    impl PartialHandler<Op> for Account {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Bank(Bank::Balance) => Some(Box::new(self.balance)),
+                Op::Bank(Bank::Debit(amount)) => {
+                    self.balance -= amount;
+                    Some(Box::new(()))
+                }
+                Op::Exception(_) => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(Account, Op);
+
+    #[effectful]
+    fn withdraw(amount: u64) -> u64 {
+        let balance: u64 = perform!(Bank::Balance);
+        if balance < amount {
+            let _: Infallible = perform!(Exception::Throw(Overdrawn(amount - balance)));
+        }
+        let _: () = perform!(Bank::Debit(amount));
+        balance - amount
+    }
+
+    #[test]
+    fn test_run_exception_stops_at_the_throw() {
+        let mut account = Account { balance: 30 };
+        let left = run_exception(withdraw(20)).drive(|op| account.maybe_handle(op));
+        assert_eq!(left.unwrap(), Ok(10));
+
+        let left = run_exception(withdraw(20)).drive(|op| account.maybe_handle(op));
+        assert_eq!(left.unwrap(), Err(Overdrawn(10)));
+        assert_eq!(account.balance, 10, "nothing after the throw ran");
+    }
+
+    #[test]
+    fn test_thrown_errors_surface_through_run_try() {
+        let err = run_exception(withdraw(50))
+            .handle(Account { balance: 20 })
+            .run_try()
+            .unwrap_err();
+        assert!(matches!(err, RunError::Failed(Overdrawn(30))));
+    }
+
+    #[test]
+    fn test_exception_handler_aborts_the_run() {
+        let err = withdraw(50)
+            .begin_chain()
+            .handle(Account { balance: 20 })
+            .handle(ExceptionHandler::new())
+            .run()
+            .unwrap_err();
+        let EffectError::Aborted(abort) = err else {
+            panic!("expected an abort, got {err:?}");
+        };
+        assert_eq!(abort.downcast::<Overdrawn>().unwrap(), Overdrawn(30));
+    }
+}
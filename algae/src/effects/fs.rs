@@ -0,0 +1,327 @@
+//! Reading and writing files.
+//!
+//! [`Fs::Read`] reads a whole file and [`Fs::Write`] replaces one. Failures
+//! are part of the reply, as `std::io::Result`s, rather than aborts. [`StdFs`]
+//! performs them with `std::fs`; with the `io-uring` feature on Linux,
+//! [`UringIo`](crate::effects::uring::UringIo) submits them to io_uring.
+//! [`MemoryFs`] keeps files in memory, for tests.
+//!
+//! [`Fs::TempDir`] and [`Fs::TempFile`] create scratch space that belongs to
+//! the run: the real handlers delete it when they are dropped, which happens
+//! when the run ends, whether it returned, was aborted or cancelled, or
+//! panicked. Workflows that produce files can be tested in isolation this
+//! way, without picking paths themselves.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::fs::{Fs, StdFs};
+//! use std::io;
+//!
+//! effect! {
+//!     use algae::effects::fs::Fs;
+//! }
+//!
+//! #[effectful]
+//! fn copy(from: &'static str, to: &'static str) -> io::Result<()> {
+//!     let contents: io::Result<Vec<u8>> = perform!(Fs::Read(from.into()));
+//!     perform!(Fs::Write((to.into(), contents?)))
+//! }
+//!
+//! copy("a.txt", "b.txt").handle(StdFs::new()).run()?;
+//! ```
+
+use crate::{Contains, PartialHandler};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+effect! {
+    root FsOp;
+    typed;
+    Fs::Read (PathBuf) -> std::io::Result<Vec<u8>>;
+    Fs::Write ((PathBuf, Vec<u8>)) -> std::io::Result<()>;
+    Fs::TempDir -> std::io::Result<PathBuf>;
+    Fs::TempFile -> std::io::Result<PathBuf>;
+}
+
+/// Temporary directories and files created for a run, deleted on drop.
+#[derive(Debug, Default)]
+pub(crate) struct Scratch {
+    paths: Mutex<Vec<PathBuf>>,
+}
+
+impl Scratch {
+    /// Creates an empty directory under the system's temporary directory.
+    pub(crate) fn create_dir(&self) -> io::Result<PathBuf> {
+        self.create(|path| fs::create_dir(path))
+    }
+
+    /// Creates an empty file under the system's temporary directory.
+    pub(crate) fn create_file(&self) -> io::Result<PathBuf> {
+        self.create(|path| {
+            fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .map(drop)
+        })
+    }
+
+    fn create(&self, create: impl Fn(&Path) -> io::Result<()>) -> io::Result<PathBuf> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        loop {
+            let n = NEXT.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!("algae-{}-{n}", std::process::id()));
+            match create(&path) {
+                // Left over from an earlier process with the same id
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                result => {
+                    result?;
+                    self.paths.lock().unwrap().push(path.clone());
+                    return Ok(path);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let paths = self.paths.get_mut().unwrap_or_else(|e| e.into_inner());
+        for path in paths.drain(..) {
+            // Best effort: the run may have removed or moved it already
+            let _ = if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+        }
+    }
+}
+
+/// Handles [`Fs`] operations with `std::fs`.
+///
+/// Temporary directories and files are deleted, with their contents, once
+/// the handler and all its clones are dropped.
+#[derive(Debug, Clone, Default)]
+pub struct StdFs {
+    scratch: Arc<Scratch>,
+}
+
+impl StdFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<Op: Contains<Fs>> PartialHandler<Op> for StdFs {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            Fs::Read(path) => Some(Box::new(fs::read(path))),
+            Fs::Write((path, contents)) => Some(Box::new(fs::write(path, contents))),
+            Fs::TempDir => Some(Box::new(self.scratch.create_dir())),
+            Fs::TempFile => Some(Box::new(self.scratch.create_file())),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryFiles {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    temp: Vec<PathBuf>,
+}
+
+/// Handles [`Fs`] operations with files kept in memory.
+///
+/// Temporary directories and files get the paths `/tmp/algae-0`,
+/// `/tmp/algae-1`, ... in the order they are requested, so they are the same
+/// on every run; temporary files start out empty. Nothing reaches the disk,
+/// so there is nothing to clean up.
+///
+/// Clones share the same files, so keep a clone to inspect what a
+/// computation wrote after handing the handler to it.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFs {
+    state: Arc<Mutex<MemoryFiles>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file with `contents`.
+    pub fn with_file(self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) -> Self {
+        let mut state = self.state.lock().unwrap();
+        state.files.insert(path.into(), contents.into());
+        drop(state);
+        self
+    }
+
+    /// The contents of the file at `path`, if there is one.
+    pub fn contents(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().files.get(path.as_ref()).cloned()
+    }
+
+    /// The paths of all files, in order.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.state.lock().unwrap().files.keys().cloned().collect()
+    }
+
+    /// The temporary directories and files handed out so far, in order.
+    pub fn temp_paths(&self) -> Vec<PathBuf> {
+        self.state.lock().unwrap().temp.clone()
+    }
+}
+
+impl<Op: Contains<Fs>> PartialHandler<Op> for MemoryFs {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let mut state = self.state.lock().unwrap();
+        match op.project()? {
+            Fs::Read(path) => Some(Box::new(state.files.get(path).cloned().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} not found", path.display()),
+                )
+            }))),
+            Fs::Write((path, contents)) => {
+                state.files.insert(path.clone(), contents.clone());
+                Some(Box::new(Ok::<(), io::Error>(())))
+            }
+            op @ (Fs::TempDir | Fs::TempFile) => {
+                let path = PathBuf::from(format!("/tmp/algae-{}", state.temp.len()));
+                state.temp.push(path.clone());
+                if matches!(op, Fs::TempFile) {
+                    state.files.insert(path.clone(), Vec::new());
+                }
+                Some(Box::new(Ok::<_, io::Error>(path)))
+            }
+        }
+    }
+}
+
+impl_into_vec_handler_for_family!(Fs: StdFs, MemoryFs);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::io;
+
+    effect! {
+        use algae::effects::fs::Fs;
+    }
+
+    #[effectful]
+    fn append(path: PathBuf, suffix: &'static str) -> io::Result<String> {
+        let contents: io::Result<Vec<u8>> = perform!(Fs::Read(path.clone()));
+        let mut contents = contents?;
+        contents.extend_from_slice(suffix.as_bytes());
+        let written: io::Result<()> = perform!(Fs::Write((path.clone(), contents)));
+        written?;
+        let contents: io::Result<Vec<u8>> = perform!(Fs::Read(path));
+        Ok(String::from_utf8(contents?).unwrap())
+    }
+
+    #[test]
+    fn test_std_fs_reads_and_writes() {
+        let path = std::env::temp_dir().join(format!("algae-fs-{}", std::process::id()));
+        fs::write(&path, "hello").unwrap();
+        let result = append(path.clone(), ", world")
+            .try_run_with(StdFs::new())
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap(), "hello, world");
+    }
+
+    #[effectful]
+    fn write_report() -> io::Result<PathBuf> {
+        let dir: io::Result<PathBuf> = perform!(Fs::TempDir);
+        let report = dir?.join("report.txt");
+        let written: io::Result<()> = perform!(Fs::Write((report.clone(), b"done".to_vec())));
+        written?;
+        let scratch: io::Result<PathBuf> = perform!(Fs::TempFile);
+        scratch?;
+        Ok(report)
+    }
+
+    /// Creates temporary paths with `StdFs`, recording them, and aborts
+    /// everything else.
+    struct TempThenAbort {
+        fs: StdFs,
+        created: Arc<Mutex<Vec<PathBuf>>>,
+    }
+
+    impl PartialHandler<Op> for TempThenAbort {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let (Fs::TempDir | Fs::TempFile) = op.project()? else {
+                return Some(algae::Abort::boxed("stop"));
+            };
+            let reply = self.fs.maybe_handle(op)?;
+            let path = reply.downcast_ref::<io::Result<PathBuf>>()?.as_ref().ok()?;
+            self.created.lock().unwrap().push(path.clone());
+            Some(reply)
+        }
+    }
+
+    #[test]
+    fn test_std_fs_deletes_temp_paths_at_end_of_run() {
+        let std_fs = StdFs::new();
+        let report = write_report()
+            .try_run_with(std_fs.clone())
+            .unwrap()
+            .unwrap();
+        // A clone is still alive, so the scratch space is too
+        assert_eq!(fs::read(&report).unwrap(), b"done");
+        let dir = report.parent().unwrap().to_path_buf();
+        drop(std_fs);
+        assert!(!dir.exists());
+
+        let created = Arc::new(Mutex::new(Vec::new()));
+        let handler = TempThenAbort {
+            fs: StdFs::new(),
+            created: created.clone(),
+        };
+        let err = write_report().try_run_with(handler).unwrap_err();
+        assert!(matches!(err, EffectError::Aborted(_)));
+        let created = created.lock().unwrap();
+        assert_eq!(created.len(), 1);
+        assert!(!created[0].exists());
+    }
+
+    #[test]
+    fn test_memory_fs_simulates_temp_paths() {
+        let fs = MemoryFs::new().with_file("/etc/motd", "hi");
+        let report = write_report().try_run_with(fs.clone()).unwrap().unwrap();
+        assert_eq!(report, PathBuf::from("/tmp/algae-0/report.txt"));
+        assert_eq!(fs.contents(&report).unwrap(), b"done");
+        assert_eq!(
+            fs.temp_paths(),
+            ["/tmp/algae-0", "/tmp/algae-1"].map(PathBuf::from)
+        );
+        assert_eq!(fs.contents("/tmp/algae-1").unwrap(), b"");
+        assert_eq!(fs.paths().len(), 3);
+
+        let result = append("/missing".into(), "!").try_run_with(fs).unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_errors_are_replies() {
+        let path = std::env::temp_dir().join("algae-fs-missing/none.txt");
+        let result = append(path, "!").try_run_with(StdFs::new()).unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,165 @@
+//! HTTP requests.
+//!
+//! [`Http::Fetch`] sends an [`HttpRequest`] and replies with the
+//! [`HttpResponse`]. Like [`fs`](crate::effects::fs), failures to get a
+//! response at all are part of the reply, as `std::io::Result`s; error
+//! statuses are ordinary responses. With the `browser` feature,
+//! [`WebFetch`](crate::effects::browser::WebFetch) answers them with the
+//! browser's `fetch`.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::http::{Http, HttpRequest, HttpResponse};
+//! use std::io;
+//!
+//! effect! {
+//!     use algae::effects::http::Http;
+//! }
+//!
+//! #[effectful]
+//! fn latest_version() -> io::Result<String> {
+//!     let request = HttpRequest::get("https://example.com/version")
+//!         .header("accept", "text/plain");
+//!     let response: io::Result<HttpResponse> = perform!(Http::Fetch(request));
+//!     Ok(response?.text()?)
+//! }
+//! ```
+
+use algae_macros::effect;
+use std::io;
+
+effect! {
+    root HttpOp;
+    typed;
+    Http::Fetch (HttpRequest) -> std::io::Result<HttpResponse>;
+}
+
+/// A request for [`Http::Fetch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn new(method: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            method: method.into(),
+            url: url.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new("GET", url)
+    }
+
+    pub fn post(url: impl Into<String>, body: impl Into<Vec<u8>>) -> Self {
+        Self::new("POST", url).body(body)
+    }
+
+    /// Appends a header.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+/// The reply to [`Http::Fetch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Whether the status is in the 2xx range.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The body as UTF-8 text.
+    pub fn text(&self) -> io::Result<String> {
+        String::from_utf8(self.body.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::any::Any;
+
+    effect! {
+        use algae::effects::http::Http;
+    }
+
+    /// Echoes request bodies, and 404s anything but `/echo`.
+    struct EchoServer;
+
+    impl PartialHandler<Op> for EchoServer {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Http::Fetch(request) = op.project()?;
+            let status = if request.url.ends_with("/echo") {
+                200
+            } else {
+                404
+            };
+            Some(Box::new(Ok::<_, io::Error>(HttpResponse {
+                status,
+                headers: vec![("Content-Type".into(), "text/plain".into())],
+                body: request.body.clone(),
+            })))
+        }
+    }
+
+    #[effectful]
+    fn echo(url: &'static str, body: &'static str) -> io::Result<HttpResponse> {
+        let request = HttpRequest::post(url, body).header("accept", "text/plain");
+        perform!(Http::Fetch(request))
+    }
+
+    #[test]
+    fn test_requests_and_responses() {
+        let request = HttpRequest::get("http://localhost/a").header("x-id", "1");
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.headers, [("x-id".to_string(), "1".to_string())]);
+        assert!(request.body.is_empty());
+
+        let response = echo("http://localhost/echo", "hi")
+            .try_run_with(EchoServer)
+            .unwrap()
+            .unwrap();
+        assert!(response.is_success());
+        assert_eq!(response.header("content-type"), Some("text/plain"));
+        assert_eq!(response.text().unwrap(), "hi");
+
+        let missing = echo("http://localhost/nope", "")
+            .try_run_with(EchoServer)
+            .unwrap()
+            .unwrap();
+        assert!(!missing.is_success());
+    }
+}
@@ -0,0 +1,258 @@
+//! Identifier generation.
+//!
+//! Code that creates entities asks for identifiers with [`Id::NewUuid`] or
+//! [`Id::NewSequential`] instead of calling `Uuid::new_v4()` directly. In
+//! production [`SystemIds`] hands out random UUIDs; in tests
+//! [`DeterministicIds`] hands out the same identifiers on every run, so
+//! snapshots of created entities stay stable.
+//!
+//! This module requires the `uuid` feature.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::id::{DeterministicIds, Id};
+//! use uuid::Uuid;
+//!
+//! effect! {
+//!     use algae::effects::id::Id;
+//!     Db::Insert ((Uuid, String)) -> ();
+//! }
+//!
+//! #[effectful]
+//! fn create_user(name: String) -> Uuid {
+//!     let id: Uuid = perform!(Id::NewUuid);
+//!     let _: () = perform!(Db::Insert((id, name)));
+//!     id
+//! }
+//!
+//! let id = create_user("alice".into())
+//!     .begin_chain()
+//!     .handle(DbHandler)
+//!     .handle(DeterministicIds::sequential())
+//!     .run()?;
+//! assert_eq!(id.to_string(), "00000000-0000-0000-0000-000000000001");
+//! ```
+
+use crate::{nondet::Snapshot, splitmix64, Contains, PartialHandler, Scope};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use uuid::Uuid;
+
+effect! {
+    root IdOp;
+    typed;
+    Id::NewUuid -> Uuid;
+    Id::NewSequential -> u64;
+}
+
+/// Handles [`Id`] operations with random v4 UUIDs and a shared counter.
+///
+/// Sequential ids start at 1. Clones share the counter, so every clone hands
+/// out distinct sequential ids.
+#[derive(Debug, Clone, Default)]
+pub struct SystemIds {
+    next: Arc<AtomicU64>,
+}
+
+impl SystemIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<Op: Contains<Id>> PartialHandler<Op> for SystemIds {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            Id::NewUuid => Some(Box::new(Uuid::new_v4())),
+            Id::NewSequential => Some(Box::new(self.next.fetch_add(1, Ordering::Relaxed) + 1)),
+        }
+    }
+}
+
+/// Handles [`Id`] operations with reproducible identifiers.
+///
+/// - [`DeterministicIds::sequential`] returns the UUIDs `…0001`, `…0002`, ...,
+///   which are easy to read in snapshots.
+/// - [`DeterministicIds::seeded`] returns valid, random-looking v4 UUIDs
+///   derived from a seed, for code that inspects UUID versions. Each
+///   [`Scope`] draws from its own stream, seeded with [`Scope::seed`], so the
+///   UUIDs a sub-computation gets do not depend on what runs beside it.
+///
+/// In both modes sequential ids count from 1, independently of the UUIDs.
+#[derive(Debug, Clone)]
+pub struct DeterministicIds {
+    seed: Option<u64>,
+    /// UUIDs handed out so far, per scope when seeded
+    uuids: HashMap<Scope, u64>,
+    sequential: u64,
+}
+
+impl DeterministicIds {
+    /// UUIDs are the numbers 1, 2, 3, ... in UUID form.
+    pub fn sequential() -> Self {
+        Self {
+            seed: None,
+            uuids: HashMap::new(),
+            sequential: 0,
+        }
+    }
+
+    /// UUIDs are v4 UUIDs generated from `seed`.
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            uuids: HashMap::new(),
+            sequential: 0,
+        }
+    }
+
+    fn next_uuid(&mut self) -> Uuid {
+        let scope = match self.seed {
+            Some(_) => crate::perform_scope().unwrap_or_default(),
+            None => Scope::ROOT,
+        };
+        let n = self.uuids.entry(scope).or_default();
+        *n += 1;
+        match self.seed {
+            None => Uuid::from_u128(*n as u128),
+            Some(seed) => {
+                let seed = scope.seed(seed);
+                let hi = splitmix64(seed ^ n.wrapping_mul(2));
+                let lo = splitmix64(seed ^ n.wrapping_mul(2).wrapping_add(1));
+                let bytes = ((hi as u128) << 64 | lo as u128).to_be_bytes();
+                uuid::Builder::from_random_bytes(bytes).into_uuid()
+            }
+        }
+    }
+}
+
+impl<Op: Contains<Id>> PartialHandler<Op> for DeterministicIds {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            Id::NewUuid => Some(Box::new(self.next_uuid())),
+            Id::NewSequential => {
+                self.sequential += 1;
+                Some(Box::new(self.sequential))
+            }
+        }
+    }
+}
+
+/// Restoring a snapshot makes the handler hand out the same ids again.
+impl Snapshot for DeterministicIds {
+    type State = (HashMap<Scope, u64>, u64);
+
+    fn snapshot(&self) -> Self::State {
+        (self.uuids.clone(), self.sequential)
+    }
+
+    fn restore(&mut self, (uuids, sequential): &Self::State) {
+        self.uuids = uuids.clone();
+        self.sequential = *sequential;
+    }
+}
+
+impl_into_vec_handler_for_family!(Id: SystemIds, DeterministicIds);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use algae::priority::run_by_priority;
+
+    effect! {
+        use algae::effects::id::Id;
+        Db::Insert (String) -> ();
+    }
+
+    struct DbHandler;
+
+    impl PartialHandler<Op> for DbHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Db(Db::Insert(_)) => Some(Box::new(())),
+                _ => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(DbHandler, Op);
+
+    #[effectful]
+    fn create_users(names: Vec<&'static str>) -> Vec<(Uuid, u64)> {
+        let mut created = Vec::new();
+        for name in names {
+            let id: Uuid = perform!(Id::NewUuid);
+            let row: u64 = perform!(Id::NewSequential);
+            let _: () = perform!(Db::Insert(format!("{id} {name}")));
+            created.push((id, row));
+        }
+        created
+    }
+
+    fn run_with(ids: impl PartialHandler<Op> + IntoVecHandler<Op>) -> Vec<(Uuid, u64)> {
+        create_users(vec!["alice", "bob"])
+            .begin_chain()
+            .handle(DbHandler)
+            .handle(ids)
+            .run_or_panic()
+    }
+
+    #[test]
+    fn test_sequential_ids() {
+        let created = run_with(DeterministicIds::sequential());
+        assert_eq!(
+            created,
+            vec![(Uuid::from_u128(1), 1), (Uuid::from_u128(2), 2)]
+        );
+        assert_eq!(
+            created[1].0.to_string(),
+            "00000000-0000-0000-0000-000000000002"
+        );
+    }
+
+    #[test]
+    fn test_seeded_ids_are_reproducible_v4() {
+        let first = run_with(DeterministicIds::seeded(7));
+        assert_eq!(first, run_with(DeterministicIds::seeded(7)));
+        assert_ne!(first, run_with(DeterministicIds::seeded(8)));
+        assert_ne!(first[0].0, first[1].0);
+        assert!(first.iter().all(|(id, _)| id.get_version_num() == 4));
+    }
+
+    #[test]
+    fn test_seeded_ids_do_not_depend_on_sibling_computations() {
+        let run_beside = |sibling: Vec<&'static str>| {
+            let mut h = VecHandler::new();
+            h.push(DbHandler);
+            h.push(DeterministicIds::seeded(7));
+            let mut results = run_by_priority(
+                vec![create_users(vec!["alice", "bob"]), create_users(sibling)],
+                h,
+            );
+            let created = results.swap_remove(0).unwrap();
+            created.into_iter().map(|(id, _)| id).collect::<Vec<_>>()
+        };
+        let alone = run_beside(vec![]);
+        assert_eq!(alone, run_beside(vec!["carol", "dave", "erin"]));
+        assert_ne!(alone[0], run_with(DeterministicIds::seeded(7))[0].0);
+    }
+
+    #[test]
+    fn test_system_ids_are_unique() {
+        let created = run_with(SystemIds::new());
+        assert_ne!(created[0].0, created[1].0);
+        assert_eq!((created[0].1, created[1].1), (1, 2));
+    }
+}
@@ -0,0 +1,667 @@
+//! Structured logging.
+//!
+//! [`Log::Event`] carries a [`LogEvent`]: a level, a message and a list of
+//! key/value fields. Handlers decide where events go:
+//!
+//! - [`MemoryLog`] collects events in memory and offers assertions for tests
+//! - `TracingBridge` emits `tracing` events (feature `tracing`)
+//! - `LogBridge` forwards to the `log` facade (feature `log`)
+//! - `DefmtBridge` emits `defmt` frames on embedded targets (feature `defmt`)
+//!
+//! Because every project can use the same family, logging middleware (sampling,
+//! redaction, ...) only has to be written once.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::log::{Level, Log, LogEvent, MemoryLog};
+//!
+//! effect! {
+//!     use algae::effects::log::Log;
+//!     Auth::Login (String) -> bool;
+//! }
+//!
+//! #[effectful]
+//! fn login(user: String) -> bool {
+//!     let ok: bool = perform!(Auth::Login(user.clone()));
+//!     let _: () = perform!(Log::Event(
+//!         LogEvent::info("login attempt").field("user", user).field("ok", ok)
+//!     ));
+//!     ok
+//! }
+//!
+//! let log = MemoryLog::new();
+//! login("alice".into()).begin_chain().handle(AuthHandler).handle(log.clone()).run()?;
+//! log.assert_logged(Level::Info, "login attempt");
+//! ```
+
+use crate::{nondet::Snapshot, Contains, PartialHandler};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+effect! {
+    root LogOp;
+    typed;
+    Log::Event (LogEvent) -> ();
+}
+
+/// Severity of a [`LogEvent`], from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        })
+    }
+}
+
+/// The value of a [`LogEvent`] field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{s:?}"),
+            Value::I64(n) => write!(f, "{n}"),
+            Value::U64(n) => write!(f, "{n}"),
+            Value::F64(n) => write!(f, "{n}"),
+            Value::Bool(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+macro_rules! impl_value_from {
+    ($($ty:ty => $variant:ident as $as:ty),* $(,)?) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(v: $ty) -> Self {
+                    Value::$variant(v as $as)
+                }
+            }
+        )*
+    };
+}
+
+impl_value_from! {
+    i8 => I64 as i64, i16 => I64 as i64, i32 => I64 as i64, i64 => I64 as i64, isize => I64 as i64,
+    u8 => U64 as u64, u16 => U64 as u64, u32 => U64 as u64, u64 => U64 as u64, usize => U64 as u64,
+    f32 => F64 as f64, f64 => F64 as f64,
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Str(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Str(v.to_string())
+    }
+}
+
+/// A structured log record: level, message and key/value fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEvent {
+    pub level: Level,
+    pub msg: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl LogEvent {
+    pub fn new(level: Level, msg: impl Into<String>) -> Self {
+        Self {
+            level,
+            msg: msg.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn trace(msg: impl Into<String>) -> Self {
+        Self::new(Level::Trace, msg)
+    }
+
+    pub fn debug(msg: impl Into<String>) -> Self {
+        Self::new(Level::Debug, msg)
+    }
+
+    pub fn info(msg: impl Into<String>) -> Self {
+        Self::new(Level::Info, msg)
+    }
+
+    pub fn warn(msg: impl Into<String>) -> Self {
+        Self::new(Level::Warn, msg)
+    }
+
+    pub fn error(msg: impl Into<String>) -> Self {
+        Self::new(Level::Error, msg)
+    }
+
+    /// Appends a key/value field.
+    pub fn field(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.fields.push((key.into(), value.into()));
+        self
+    }
+
+    /// The value of the first field called `key`.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// The fields rendered as `key=value` pairs separated by spaces.
+    pub fn fields_display(&self) -> impl fmt::Display + '_ {
+        Fields(&self.fields)
+    }
+}
+
+impl fmt::Display for LogEvent {
+    /// Renders as `LEVEL msg key=value ...`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.level, self.msg)?;
+        if !self.fields.is_empty() {
+            write!(f, " {}", Fields(&self.fields))?;
+        }
+        Ok(())
+    }
+}
+
+struct Fields<'a>(&'a [(String, Value)]);
+
+impl fmt::Display for Fields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects [`Log`] events in memory.
+///
+/// Clones share the same buffer, so keep a clone to inspect what a
+/// computation logged after handing the handler to it.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLog {
+    events: Arc<Mutex<Vec<LogEvent>>>,
+}
+
+impl MemoryLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All events collected so far, oldest first.
+    pub fn events(&self) -> Vec<LogEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Events at exactly `level`.
+    pub fn at_level(&self, level: Level) -> Vec<LogEvent> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| e.level == level)
+            .cloned()
+            .collect()
+    }
+
+    /// Whether an event at `level` has a message containing `needle`.
+    pub fn contains(&self, level: Level, needle: &str) -> bool {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|e| e.level == level && e.msg.contains(needle))
+    }
+
+    /// Panics, listing everything that was logged, unless an event at
+    /// `level` has a message containing `needle`.
+    #[track_caller]
+    pub fn assert_logged(&self, level: Level, needle: &str) {
+        if !self.contains(level, needle) {
+            panic!(
+                "no {level} event containing {needle:?} was logged; got:\n{}",
+                self.dump()
+            );
+        }
+    }
+
+    /// Panics unless no event at `level` or above was logged.
+    #[track_caller]
+    pub fn assert_nothing_at_or_above(&self, level: Level) {
+        if self.events.lock().unwrap().iter().any(|e| e.level >= level) {
+            panic!(
+                "expected no event at {level} or above; got:\n{}",
+                self.dump()
+            );
+        }
+    }
+
+    /// Discards all collected events.
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+
+    fn dump(&self) -> String {
+        let events = self.events.lock().unwrap();
+        if events.is_empty() {
+            return "  (nothing)".to_string();
+        }
+        events.iter().map(|e| format!("  {e}\n")).collect()
+    }
+}
+
+impl<Op: Contains<Log>> PartialHandler<Op> for MemoryLog {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let Log::Event(event) = op.project()?;
+        self.events.lock().unwrap().push(event.clone());
+        Some(Box::new(()))
+    }
+}
+
+/// Snapshots are the number of collected events; restoring forgets the
+/// events collected since.
+impl Snapshot for MemoryLog {
+    type State = usize;
+
+    fn snapshot(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    fn restore(&mut self, state: &usize) {
+        self.events.lock().unwrap().truncate(*state);
+    }
+}
+
+impl_into_vec_handler_for_family!(Log: MemoryLog);
+
+#[cfg(feature = "tracing")]
+pub use self::tracing_bridge::TracingBridge;
+
+#[cfg(feature = "tracing")]
+mod tracing_bridge {
+    use super::{Level, Log, LogEvent};
+    use crate::{Contains, PartialHandler};
+    use std::any::Any;
+
+    /// Handles [`Log`] operations by emitting `tracing` events.
+    ///
+    /// Events use the `algae` target. The message is recorded as the event
+    /// message and the fields, rendered as `key=value` pairs, as the `fields`
+    /// field.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct TracingBridge;
+
+    impl TracingBridge {
+        pub fn new() -> Self {
+            Self
+        }
+    }
+
+    fn emit(event: &LogEvent) {
+        macro_rules! emit_at {
+            ($level:expr) => {
+                if event.fields.is_empty() {
+                    tracing::event!(target: "algae", $level, "{}", event.msg)
+                } else {
+                    tracing::event!(
+                        target: "algae",
+                        $level,
+                        fields = %event.fields_display(),
+                        "{}",
+                        event.msg
+                    )
+                }
+            };
+        }
+        match event.level {
+            Level::Trace => emit_at!(tracing::Level::TRACE),
+            Level::Debug => emit_at!(tracing::Level::DEBUG),
+            Level::Info => emit_at!(tracing::Level::INFO),
+            Level::Warn => emit_at!(tracing::Level::WARN),
+            Level::Error => emit_at!(tracing::Level::ERROR),
+        }
+    }
+
+    impl<Op: Contains<Log>> PartialHandler<Op> for TracingBridge {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Log::Event(event) = op.project()?;
+            emit(event);
+            Some(Box::new(()))
+        }
+    }
+
+    impl_into_vec_handler_for_family!(Log: TracingBridge);
+}
+
+#[cfg(feature = "log")]
+pub use self::log_bridge::LogBridge;
+
+#[cfg(feature = "log")]
+mod log_bridge {
+    use super::{Level, Log};
+    use crate::{Contains, PartialHandler};
+    use std::any::Any;
+
+    /// Handles [`Log`] operations by forwarding them to the `log` facade.
+    ///
+    /// Records are formatted as `msg key=value ...` under a configurable
+    /// target (`algae` by default).
+    #[derive(Debug, Clone)]
+    pub struct LogBridge {
+        target: String,
+    }
+
+    impl LogBridge {
+        pub fn new() -> Self {
+            Self::with_target("algae")
+        }
+
+        pub fn with_target(target: impl Into<String>) -> Self {
+            Self {
+                target: target.into(),
+            }
+        }
+    }
+
+    impl Default for LogBridge {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    fn level(level: Level) -> ::log::Level {
+        match level {
+            Level::Trace => ::log::Level::Trace,
+            Level::Debug => ::log::Level::Debug,
+            Level::Info => ::log::Level::Info,
+            Level::Warn => ::log::Level::Warn,
+            Level::Error => ::log::Level::Error,
+        }
+    }
+
+    impl<Op: Contains<Log>> PartialHandler<Op> for LogBridge {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Log::Event(event) = op.project()?;
+            let target = self.target.as_str();
+            if event.fields.is_empty() {
+                ::log::log!(target: target, level(event.level), "{}", event.msg);
+            } else {
+                ::log::log!(
+                    target: target,
+                    level(event.level),
+                    "{} {}",
+                    event.msg,
+                    event.fields_display()
+                );
+            }
+            Some(Box::new(()))
+        }
+    }
+
+    impl_into_vec_handler_for_family!(Log: LogBridge);
+}
+
+#[cfg(feature = "defmt")]
+pub use self::defmt_bridge::DefmtBridge;
+
+#[cfg(feature = "defmt")]
+mod defmt_bridge {
+    use super::{Level, Log};
+    use crate::{Contains, PartialHandler};
+    use std::any::Any;
+
+    /// Handles [`Log`] operations by emitting `defmt` log frames.
+    ///
+    /// Meant for `thumbv*` targets that already ship `defmt` logs over RTT.
+    /// The format strings are interned, so only the message and the fields,
+    /// rendered as `key=value` pairs, go over the wire. Which levels are kept
+    /// is decided by `DEFMT_LOG` at compile time, as for any `defmt` log.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct DefmtBridge;
+
+    impl DefmtBridge {
+        pub fn new() -> Self {
+            Self
+        }
+    }
+
+    impl<Op: Contains<Log>> PartialHandler<Op> for DefmtBridge {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Log::Event(event) = op.project()?;
+            let msg = event.msg.as_str();
+            macro_rules! emit_at {
+                ($level:ident) => {
+                    if event.fields.is_empty() {
+                        defmt::$level!("{=str}", msg)
+                    } else {
+                        defmt::$level!(
+                            "{=str} {}",
+                            msg,
+                            defmt::Display2Format(&event.fields_display())
+                        )
+                    }
+                };
+            }
+            match event.level {
+                Level::Trace => emit_at!(trace),
+                Level::Debug => emit_at!(debug),
+                Level::Info => emit_at!(info),
+                Level::Warn => emit_at!(warn),
+                Level::Error => emit_at!(error),
+            }
+            Some(Box::new(()))
+        }
+    }
+
+    impl_into_vec_handler_for_family!(Log: DefmtBridge);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        use algae::effects::log::Log;
+        Auth::Login (String) -> bool;
+    }
+
+    struct AuthHandler;
+
+    impl PartialHandler<Op> for AuthHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Auth(Auth::Login(user)) => Some(Box::new(user != "mallory")),
+                _ => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(AuthHandler, Op);
+
+    #[effectful]
+    fn login(user: String) -> bool {
+        let ok: bool = perform!(Auth::Login(user.clone()));
+        let event = if ok {
+            LogEvent::info("login succeeded")
+        } else {
+            LogEvent::warn("login rejected")
+        };
+        let _: () = perform!(Log::Event(event.field("user", user).field("ok", ok)));
+        ok
+    }
+
+    #[test]
+    fn test_event_rendering() {
+        let event = LogEvent::error("upload failed")
+            .field("file", "a b.txt")
+            .field("bytes", 1024u32)
+            .field("retry", false);
+        assert_eq!(event.get("bytes"), Some(&Value::U64(1024)));
+        assert_eq!(event.get("missing"), None);
+        assert_eq!(
+            event.to_string(),
+            r#"ERROR upload failed file="a b.txt" bytes=1024 retry=false"#
+        );
+        assert_eq!(LogEvent::debug("tick").to_string(), "DEBUG tick");
+        assert!(Level::Trace < Level::Error);
+    }
+
+    #[test]
+    fn test_memory_log_collects_events() {
+        let log = MemoryLog::new();
+        assert!(login("alice".into())
+            .begin_chain()
+            .handle(AuthHandler)
+            .handle(log.clone())
+            .run_or_panic());
+        assert!(!login("mallory".into())
+            .begin_chain()
+            .handle(AuthHandler)
+            .handle(log.clone())
+            .run_or_panic());
+
+        log.assert_logged(Level::Info, "succeeded");
+        log.assert_logged(Level::Warn, "rejected");
+        let warnings = log.at_level(Level::Warn);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].get("user"), Some(&Value::from("mallory")));
+
+        log.clear();
+        assert!(log.events().is_empty());
+        log.assert_nothing_at_or_above(Level::Trace);
+    }
+
+    #[test]
+    #[should_panic(expected = "no ERROR event containing \"boom\" was logged; got:\n  INFO")]
+    fn test_assert_logged_lists_events() {
+        let log = MemoryLog::new();
+        login("alice".into())
+            .begin_chain()
+            .handle(AuthHandler)
+            .handle(log.clone())
+            .run_or_panic();
+        log.assert_logged(Level::Error, "boom");
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing_bridge() {
+        use std::sync::Mutex;
+        use tracing::field::{Field, Visit};
+
+        #[derive(Default)]
+        struct Capture(Mutex<Vec<String>>);
+
+        struct Visitor(String);
+
+        impl Visit for Visitor {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.push_str(&format!(" {}={:?}", field.name(), value));
+            }
+        }
+
+        impl tracing::Subscriber for Capture {
+            fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+                tracing::span::Id::from_u64(1)
+            }
+            fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+            fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+            fn event(&self, event: &tracing::Event<'_>) {
+                let mut visitor = Visitor(event.metadata().level().to_string());
+                event.record(&mut visitor);
+                self.0.lock().unwrap().push(visitor.0);
+            }
+            fn enter(&self, _: &tracing::span::Id) {}
+            fn exit(&self, _: &tracing::span::Id) {}
+        }
+
+        let capture = std::sync::Arc::new(Capture::default());
+        tracing::subscriber::with_default(capture.clone(), || {
+            login("mallory".into())
+                .begin_chain()
+                .handle(AuthHandler)
+                .handle(TracingBridge::new())
+                .run_or_panic();
+        });
+        assert_eq!(
+            *capture.0.lock().unwrap(),
+            vec![r#"WARN message=login rejected fields=user="mallory" ok=false"#]
+        );
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_log_bridge() {
+        use std::sync::Mutex;
+
+        static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        struct Capture;
+
+        impl ::log::Log for Capture {
+            fn enabled(&self, _: &::log::Metadata<'_>) -> bool {
+                true
+            }
+            fn log(&self, record: &::log::Record<'_>) {
+                if record.target() == "audit" {
+                    let line = format!("{} {}", record.level(), record.args());
+                    RECORDS.lock().unwrap().push(line);
+                }
+            }
+            fn flush(&self) {}
+        }
+
+        ::log::set_logger(&Capture).unwrap();
+        ::log::set_max_level(::log::LevelFilter::Trace);
+        login("alice".into())
+            .begin_chain()
+            .handle(AuthHandler)
+            .handle(LogBridge::with_target("audit"))
+            .run_or_panic();
+        assert_eq!(
+            *RECORDS.lock().unwrap(),
+            vec![r#"INFO login succeeded user="alice" ok=true"#]
+        );
+    }
+}
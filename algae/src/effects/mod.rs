@@ -0,0 +1,87 @@
+//! Standard effect families.
+//!
+//! Every project ends up needing the same handful of cross-cutting effects.
+//! The families in this module are defined once so that handlers and
+//! middleware for them can be shared between projects. Embed them in your own
+//! root operation type with a `use` line:
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::progress::{Progress, SilentProgress};
+//!
+//! effect! {
+//!     use algae::effects::progress::Progress;
+//!     Job::Process (u32) -> ();
+//! }
+//! ```
+//!
+//! The handlers shipped with each family are generic over any root type that
+//! [`Contains`](crate::Contains) the family, so they compose with your own
+//! handlers through `.handle()` chains or a [`VecHandler`](crate::VecHandler).
+//!
+//! # Families
+//!
+//! - [`backtrack`] - depth-first search with choice and failure
+//! - [`config`] - layered configuration
+//! - [`ctl`] - cooperative cancellation
+//! - [`exception`] - stopping a computation early with an error
+//! - [`fs`] - reading and writing files
+//! - [`http`] - HTTP requests
+//! - `id` - UUID and sequential id generation (feature `uuid`)
+//! - [`log`] - structured logging
+//! - [`net`] - name resolution and TCP connections
+//! - [`progress`] - progress reporting for long-running jobs
+//! - [`random`] - random numbers, seeded for reproducible tests
+//! - [`reader`] - a read-only environment, changeable for sub-computations
+//! - [`state`] - mutable state threaded through a computation
+//! - [`stm`] - transactional variables shared between computations
+//! - [`storage`] - key-value storage of strings, like `localStorage`
+//! - [`time`] - wall clock, monotonic clock and sleeping
+//! - [`transaction`] - writes committed or discarded together
+//! - [`writer`] - output accumulated alongside the result
+//!
+//! With the `io-uring` feature on Linux, `uring` provides handlers for [`fs`]
+//! and [`net`] that submit operations to io_uring in batches. With the
+//! `browser` feature (or its alias `wasm`), `browser` provides handlers for
+//! [`log`], [`http`] and [`storage`] in wasm32 programs running in a browser.
+
+/// Lets family-generic handlers join `.handle()` chains, which require
+/// [`IntoVecHandler`](crate::IntoVecHandler).
+macro_rules! impl_into_vec_handler_for_family {
+    ($family:ty: $($handler:ty),+ $(,)?) => {
+        $(
+            impl<Op: $crate::Contains<$family> + 'static> $crate::IntoVecHandler<Op> for $handler {
+                fn into_vec_handler(self) -> $crate::VecHandler<Op> {
+                    let mut vec = $crate::VecHandler::new();
+                    vec.push(self);
+                    vec
+                }
+            }
+        )+
+    };
+}
+
+pub mod backtrack;
+#[cfg(feature = "browser")]
+pub mod browser;
+pub mod config;
+pub mod ctl;
+pub mod exception;
+pub mod fs;
+pub mod http;
+#[cfg(feature = "uuid")]
+pub mod id;
+pub mod log;
+pub mod net;
+pub mod progress;
+pub mod random;
+pub mod reader;
+pub mod state;
+pub mod stm;
+pub mod storage;
+pub mod time;
+pub mod transaction;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
+pub mod writer;
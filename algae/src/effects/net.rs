@@ -0,0 +1,432 @@
+//! Name resolution and TCP connections.
+//!
+//! [`Net::Resolve`] looks up the addresses of a host name. [`Net::Connect`]
+//! opens a connection and replies with a [`ConnId`] that the other
+//! operations refer to; [`Net::Send`] and [`Net::Recv`] behave like a single
+//! `write` and `read` on the socket. Failures are part of the reply, as
+//! `std::io::Result`s. [`StdNet`] uses the system resolver and `std::net`;
+//! with the `io-uring` feature on Linux,
+//! [`UringIo`](crate::effects::uring::UringIo) submits sends and receives to
+//! io_uring. [`FakeNet`] answers from static records and scripted peers, for
+//! tests.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::net::{ConnId, Net, StdNet};
+//! use std::io;
+//!
+//! effect! {
+//!     use algae::effects::net::Net;
+//! }
+//!
+//! #[effectful]
+//! fn ping(addr: std::net::SocketAddr) -> io::Result<Vec<u8>> {
+//!     let conn: io::Result<ConnId> = perform!(Net::Connect(addr));
+//!     let conn = conn?;
+//!     let sent: io::Result<usize> = perform!(Net::Send((conn, b"ping".to_vec())));
+//!     sent?;
+//!     let reply: io::Result<Vec<u8>> = perform!(Net::Recv((conn, 64)));
+//!     let _: () = perform!(Net::Close(conn));
+//!     reply
+//! }
+//! ```
+
+use crate::{effects::time::VirtualClock, Contains, PartialHandler};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Identifies a connection opened with [`Net::Connect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnId(pub u64);
+
+effect! {
+    root NetOp;
+    typed;
+    // Replies with the host's addresses, without duplicates.
+    Net::Resolve (String) -> std::io::Result<Vec<IpAddr>>;
+    Net::Connect (SocketAddr) -> std::io::Result<ConnId>;
+    // Replies with the number of bytes written.
+    Net::Send ((ConnId, Vec<u8>)) -> std::io::Result<usize>;
+    // Replies with at most the given number of bytes; empty at end of stream.
+    Net::Recv ((ConnId, usize)) -> std::io::Result<Vec<u8>>;
+    Net::Close (ConnId) -> ();
+}
+
+/// The error for operations on a connection that is not open.
+pub(crate) fn not_connected(conn: ConnId) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotConnected,
+        format!("connection {} is not open", conn.0),
+    )
+}
+
+/// Looks up `host` with the system resolver.
+pub(crate) fn resolve(host: &str) -> io::Result<Vec<IpAddr>> {
+    let mut ips: Vec<IpAddr> = Vec::new();
+    for addr in (host, 0).to_socket_addrs()? {
+        if !ips.contains(&addr.ip()) {
+            ips.push(addr.ip());
+        }
+    }
+    Ok(ips)
+}
+
+/// Open connections by id.
+#[derive(Debug, Default)]
+pub(crate) struct Connections {
+    streams: HashMap<ConnId, TcpStream>,
+    next: u64,
+}
+
+impl Connections {
+    pub(crate) fn insert(&mut self, stream: TcpStream) -> ConnId {
+        let conn = ConnId(self.next);
+        self.next += 1;
+        self.streams.insert(conn, stream);
+        conn
+    }
+
+    pub(crate) fn get(&self, conn: ConnId) -> io::Result<&TcpStream> {
+        self.streams.get(&conn).ok_or_else(|| not_connected(conn))
+    }
+
+    pub(crate) fn remove(&mut self, conn: ConnId) {
+        self.streams.remove(&conn);
+    }
+}
+
+/// Handles [`Net`] operations with `std::net`.
+#[derive(Debug, Default)]
+pub struct StdNet {
+    conns: Connections,
+}
+
+impl StdNet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a connection opened elsewhere, e.g. accepted by a listener.
+    pub fn adopt(&mut self, stream: TcpStream) -> ConnId {
+        self.conns.insert(stream)
+    }
+}
+
+impl<Op: Contains<Net>> PartialHandler<Op> for StdNet {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        Some(match op.project()? {
+            Net::Resolve(host) => Box::new(resolve(host)),
+            Net::Connect(addr) => Box::new(TcpStream::connect(addr).map(|s| self.conns.insert(s))),
+            Net::Send((conn, data)) => {
+                Box::new(self.conns.get(*conn).and_then(|mut s| s.write(data)))
+            }
+            Net::Recv((conn, max)) => Box::new(self.conns.get(*conn).and_then(|mut s| {
+                let mut buf = vec![0; *max];
+                let n = s.read(&mut buf)?;
+                buf.truncate(n);
+                Ok(buf)
+            })),
+            Net::Close(conn) => {
+                self.conns.remove(*conn);
+                Box::new(())
+            }
+        })
+    }
+}
+
+type Respond = Box<dyn FnMut(&[u8]) -> Vec<u8> + Send>;
+type FailWhen = Box<dyn FnMut(&Net) -> Option<io::ErrorKind> + Send>;
+
+/// Handles [`Net`] operations without touching the network.
+///
+/// - Host names resolve to the addresses registered with
+///   [`host`](FakeNet::host); other names fail with `NotFound`.
+/// - Connections can only be opened to peers registered with
+///   [`serve`](FakeNet::serve); other addresses refuse them. A peer answers
+///   each chunk sent to it with a response, which later [`Net::Recv`]s
+///   return; with nothing left to receive they return no bytes, like a closed
+///   stream.
+/// - [`fail_when`](FakeNet::fail_when) injects failures and
+///   [`latency`](FakeNet::latency) delays every operation.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// let clock = VirtualClock::new();
+/// let net = FakeNet::new()
+///     .host("db.internal", ["10.0.0.7".parse()?])
+///     .serve("10.0.0.7:5432".parse()?, |query| answer(query))
+///     .fail_when(|op| matches!(op, Net::Resolve(host) if host == "flaky").then_some(ErrorKind::TimedOut))
+///     .latency(Duration::from_millis(20))
+///     .virtual_clock(&clock);
+/// ```
+#[derive(Default)]
+pub struct FakeNet {
+    hosts: HashMap<String, Vec<IpAddr>>,
+    peers: HashMap<SocketAddr, Respond>,
+    conns: HashMap<ConnId, (SocketAddr, VecDeque<u8>)>,
+    next: u64,
+    fail_when: Option<FailWhen>,
+    latency: Duration,
+    clock: Option<VirtualClock>,
+}
+
+impl FakeNet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolves `name` to `ips`.
+    pub fn host(mut self, name: impl Into<String>, ips: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.hosts.insert(name.into(), ips.into_iter().collect());
+        self
+    }
+
+    /// Accepts connections to `addr`, answering each chunk sent on them with
+    /// `respond(chunk)`.
+    pub fn serve<F>(mut self, addr: SocketAddr, respond: F) -> Self
+    where
+        F: FnMut(&[u8]) -> Vec<u8> + Send + 'static,
+    {
+        self.peers.insert(addr, Box::new(respond));
+        self
+    }
+
+    /// Fails the operations for which `fail` returns an error kind, before
+    /// they reach a host or peer. [`Net::Close`] cannot fail.
+    pub fn fail_when<F>(mut self, fail: F) -> Self
+    where
+        F: FnMut(&Net) -> Option<io::ErrorKind> + Send + 'static,
+    {
+        self.fail_when = Some(Box::new(fail));
+        self
+    }
+
+    /// Delays every operation by `latency`, with `std::thread::sleep` or on
+    /// the [`virtual_clock`](FakeNet::virtual_clock).
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Spends the [`latency`](FakeNet::latency) on `clock` instead of
+    /// sleeping.
+    pub fn virtual_clock(mut self, clock: &VirtualClock) -> Self {
+        self.clock = Some(clock.clone());
+        self
+    }
+
+    fn handle(&mut self, op: &Net) -> Box<dyn Any + Send> {
+        if !self.latency.is_zero() {
+            match &self.clock {
+                Some(clock) => clock.advance(self.latency),
+                None => std::thread::sleep(self.latency),
+            }
+        }
+        let failure = match op {
+            Net::Close(_) => None,
+            _ => self.fail_when.as_mut().and_then(|fail| fail(op)),
+        };
+        if let Some(kind) = failure {
+            let error = io::Error::new(kind, format!("injected failure of {op:?}"));
+            return match op {
+                Net::Resolve(_) => Box::new(Err::<Vec<IpAddr>, _>(error)),
+                Net::Connect(_) => Box::new(Err::<ConnId, _>(error)),
+                Net::Send(_) => Box::new(Err::<usize, _>(error)),
+                Net::Recv(_) => Box::new(Err::<Vec<u8>, _>(error)),
+                Net::Close(_) => unreachable!("closing cannot fail"),
+            };
+        }
+
+        match op {
+            Net::Resolve(host) => Box::new(self.hosts.get(host).cloned().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("no records for {host}"))
+            })),
+            Net::Connect(addr) => Box::new(if self.peers.contains_key(addr) {
+                let conn = ConnId(self.next);
+                self.next += 1;
+                self.conns.insert(conn, (*addr, VecDeque::new()));
+                Ok(conn)
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!("nothing serves {addr}"),
+                ))
+            }),
+            Net::Send((conn, data)) => Box::new(match self.conns.get_mut(conn) {
+                Some((addr, inbox)) => {
+                    let respond = self.peers.get_mut(addr).expect("connections are to peers");
+                    inbox.extend(respond(data));
+                    Ok(data.len())
+                }
+                None => Err(not_connected(*conn)),
+            }),
+            Net::Recv((conn, max)) => Box::new(
+                self.conns
+                    .get_mut(conn)
+                    .ok_or_else(|| not_connected(*conn))
+                    .map(|(_, inbox)| {
+                        let n = (*max).min(inbox.len());
+                        inbox.drain(..n).collect::<Vec<u8>>()
+                    }),
+            ),
+            Net::Close(conn) => {
+                self.conns.remove(conn);
+                Box::new(())
+            }
+        }
+    }
+}
+
+impl<Op: Contains<Net>> PartialHandler<Op> for FakeNet {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        Some(self.handle(op.project()?))
+    }
+}
+
+impl_into_vec_handler_for_family!(Net: StdNet, FakeNet);
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::{net::TcpListener, thread};
+
+    effect! {
+        use algae::effects::net::Net;
+    }
+
+    /// Listens on a local port and echoes every connection until it closes.
+    pub(crate) fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                thread::spawn(move || {
+                    let mut buf = [0; 1024];
+                    while let Ok(n @ 1..) = stream.read(&mut buf) {
+                        if stream.write_all(&buf[..n]).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    #[effectful]
+    fn echo(addr: SocketAddr, message: &'static [u8]) -> io::Result<Vec<u8>> {
+        let conn: io::Result<ConnId> = perform!(Net::Connect(addr));
+        let conn = conn?;
+        let sent: io::Result<usize> = perform!(Net::Send((conn, message.to_vec())));
+        assert_eq!(sent?, message.len());
+        let mut received = Vec::new();
+        while received.len() < message.len() {
+            let chunk: io::Result<Vec<u8>> = perform!(Net::Recv((conn, 64)));
+            received.extend(chunk?);
+        }
+        let _: () = perform!(Net::Close(conn));
+        Ok(received)
+    }
+
+    #[test]
+    fn test_std_net_round_trip() {
+        let addr = echo_server();
+        let reply = echo(addr, b"hello").try_run_with(StdNet::new()).unwrap();
+        assert_eq!(reply.unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_closed_connections_are_errors() {
+        #[effectful]
+        fn send_after_close(addr: SocketAddr) -> io::Result<usize> {
+            let conn: io::Result<ConnId> = perform!(Net::Connect(addr));
+            let conn = conn?;
+            let _: () = perform!(Net::Close(conn));
+            perform!(Net::Send((conn, b"late".to_vec())))
+        }
+
+        let result = send_after_close(echo_server())
+            .try_run_with(StdNet::new())
+            .unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotConnected);
+    }
+
+    #[effectful]
+    fn lookup(host: &'static str) -> io::Result<Vec<IpAddr>> {
+        perform!(Net::Resolve(host.into()))
+    }
+
+    #[test]
+    fn test_std_net_resolves_localhost() {
+        let ips = lookup("localhost")
+            .try_run_with(StdNet::new())
+            .unwrap()
+            .unwrap();
+        assert!(ips.iter().all(IpAddr::is_loopback));
+        assert!(!ips.is_empty());
+    }
+
+    #[effectful]
+    fn echo_host(host: &'static str, message: &'static [u8]) -> io::Result<Vec<u8>> {
+        let ips: io::Result<Vec<IpAddr>> = perform!(Net::Resolve(host.into()));
+        let addr = SocketAddr::new(ips?[0], 7);
+        let conn: io::Result<ConnId> = perform!(Net::Connect(addr));
+        let conn = conn?;
+        let sent: io::Result<usize> = perform!(Net::Send((conn, message.to_vec())));
+        sent?;
+        let reply: io::Result<Vec<u8>> = perform!(Net::Recv((conn, 64)));
+        let _: () = perform!(Net::Close(conn));
+        reply
+    }
+
+    fn fake_echo() -> FakeNet {
+        let ip: IpAddr = "10.0.0.7".parse().unwrap();
+        FakeNet::new()
+            .host("echo.internal", [ip])
+            .serve(SocketAddr::new(ip, 7), |chunk| chunk.to_ascii_uppercase())
+    }
+
+    #[test]
+    fn test_fake_net_records_and_peers() {
+        let reply = echo_host("echo.internal", b"hello")
+            .try_run_with(fake_echo())
+            .unwrap();
+        assert_eq!(reply.unwrap(), b"HELLO");
+
+        let missing = lookup("nowhere").try_run_with(fake_echo()).unwrap();
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let refused = echo(SocketAddr::from(([10, 0, 0, 8], 7)), b"x")
+            .try_run_with(fake_echo())
+            .unwrap();
+        assert_eq!(
+            refused.unwrap_err().kind(),
+            io::ErrorKind::ConnectionRefused
+        );
+    }
+
+    #[test]
+    fn test_fake_net_injects_failures_and_latency() {
+        let clock = VirtualClock::new();
+        let net = fake_echo()
+            .fail_when(|op| matches!(op, Net::Send(_)).then_some(io::ErrorKind::BrokenPipe))
+            .latency(Duration::from_millis(20))
+            .virtual_clock(&clock);
+        let result = echo_host("echo.internal", b"hi").try_run_with(net).unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        // resolve, connect and the failed send
+        assert_eq!(clock.elapsed(), Duration::from_millis(60));
+    }
+}
@@ -0,0 +1,428 @@
+//! Progress reporting.
+//!
+//! Long-running jobs announce their work with [`Progress::Start`], report it
+//! with [`Progress::Tick`] and [`Progress::Message`], and close it with
+//! [`Progress::Finish`]. How (and whether) progress is displayed is left to the
+//! handler:
+//!
+//! - [`SilentProgress`] discards everything; use it in tests and mocks
+//! - [`LogProgress`] writes plain text lines to any `Write` sink
+//! - `IndicatifProgress` draws terminal progress bars (feature `indicatif`)
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::progress::{LogProgress, Progress};
+//!
+//! effect! {
+//!     use algae::effects::progress::Progress;
+//!     Job::Process (u32) -> ();
+//! }
+//!
+//! #[effectful]
+//! fn process_all(items: Vec<u32>) -> usize {
+//!     let _: () = perform!(Progress::Start(Some(items.len() as u64)));
+//!     for item in &items {
+//!         let _: () = perform!(Job::Process(*item));
+//!         let _: () = perform!(Progress::Tick(1));
+//!     }
+//!     let _: () = perform!(Progress::Finish);
+//!     items.len()
+//! }
+//!
+//! let processed = process_all(vec![1, 2, 3])
+//!     .begin_chain()
+//!     .handle(JobHandler)
+//!     .handle(LogProgress::stderr().every(10))
+//!     .run()?;
+//! ```
+
+use crate::{Contains, PartialHandler};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    io::{self, Write},
+};
+
+effect! {
+    root ProgressOp;
+    typed;
+    Progress::Start (Option<u64>) -> ();
+    Progress::Tick (u64) -> ();
+    Progress::Message (String) -> ();
+    Progress::Finish -> ();
+}
+
+/// Position bookkeeping shared by the progress handlers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Counter {
+    total: Option<u64>,
+    position: u64,
+}
+
+impl Counter {
+    fn start(&mut self, total: Option<u64>) {
+        *self = Counter { total, position: 0 };
+    }
+
+    fn tick(&mut self, n: u64) -> u64 {
+        self.position = self.position.saturating_add(n);
+        self.position
+    }
+}
+
+/// Handles [`Progress`] operations by discarding them.
+///
+/// The current position is still tracked so tests can check that a job
+/// reported the work it was expected to do.
+#[derive(Debug, Default, Clone)]
+pub struct SilentProgress {
+    counter: Counter,
+}
+
+impl SilentProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The total announced by the last [`Progress::Start`], if any.
+    pub fn total(&self) -> Option<u64> {
+        self.counter.total
+    }
+
+    /// Sum of all [`Progress::Tick`] amounts since the last start.
+    pub fn position(&self) -> u64 {
+        self.counter.position
+    }
+}
+
+impl<Op: Contains<Progress>> PartialHandler<Op> for SilentProgress {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            Progress::Start(total) => self.counter.start(*total),
+            Progress::Tick(n) => {
+                self.counter.tick(*n);
+            }
+            Progress::Message(_) | Progress::Finish => {}
+        }
+        Some(Box::new(()))
+    }
+}
+
+impl_into_vec_handler_for_family!(Progress: SilentProgress);
+
+/// Handles [`Progress`] operations by writing one line per event.
+///
+/// Output looks like:
+///
+/// ```text
+/// [progress] started (total 3)
+/// [progress] 1/3
+/// [progress] fetching page 2
+/// [progress] 2/3
+/// [progress] 3/3
+/// [progress] finished (3/3)
+/// ```
+///
+/// Write errors are ignored: progress output must never fail the job.
+pub struct LogProgress {
+    out: Box<dyn Write + Send>,
+    every: u64,
+    counter: Counter,
+}
+
+impl LogProgress {
+    /// Logs to `out`.
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Box::new(out),
+            every: 1,
+            counter: Counter::default(),
+        }
+    }
+
+    /// Logs to standard error.
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+
+    /// Only logs a tick line when the position crosses a multiple of `step`.
+    ///
+    /// Messages, starts and finishes are always logged.
+    pub fn every(mut self, step: u64) -> Self {
+        self.every = step.max(1);
+        self
+    }
+
+    fn position(&self) -> String {
+        match self.counter.total {
+            Some(total) => format!("{}/{}", self.counter.position, total),
+            None => self.counter.position.to_string(),
+        }
+    }
+}
+
+impl<Op: Contains<Progress>> PartialHandler<Op> for LogProgress {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let line = match op.project()? {
+            Progress::Start(total) => {
+                self.counter.start(*total);
+                match total {
+                    Some(total) => Some(format!("started (total {total})")),
+                    None => Some("started".to_string()),
+                }
+            }
+            Progress::Tick(n) => {
+                let before = self.counter.position / self.every;
+                let after = self.counter.tick(*n) / self.every;
+                (after != before).then(|| self.position())
+            }
+            Progress::Message(msg) => Some(msg.clone()),
+            Progress::Finish => Some(format!("finished ({})", self.position())),
+        };
+        if let Some(line) = line {
+            let _ = writeln!(self.out, "[progress] {line}");
+        }
+        Some(Box::new(()))
+    }
+}
+
+impl_into_vec_handler_for_family!(Progress: LogProgress);
+
+#[cfg(feature = "indicatif")]
+pub use self::indicatif_progress::IndicatifProgress;
+
+#[cfg(feature = "indicatif")]
+mod indicatif_progress {
+    use super::Progress;
+    use crate::{Contains, PartialHandler};
+    use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+    use std::any::Any;
+
+    /// Handles [`Progress`] operations by drawing [`indicatif`] progress bars.
+    ///
+    /// Each [`Progress::Start`] opens a new bar (or a spinner when no total is
+    /// known); [`Progress::Finish`] leaves it on screen in its final state.
+    /// Bars are added to a [`MultiProgress`], which can be shared with other
+    /// output so that log lines do not tear the bars.
+    pub struct IndicatifProgress {
+        multi: MultiProgress,
+        bar: Option<ProgressBar>,
+    }
+
+    impl IndicatifProgress {
+        /// Draws to standard error.
+        pub fn new() -> Self {
+            Self::with_multi(MultiProgress::new())
+        }
+
+        /// Adds bars to an existing [`MultiProgress`].
+        pub fn with_multi(multi: MultiProgress) -> Self {
+            Self { multi, bar: None }
+        }
+
+        /// The bar opened by the last [`Progress::Start`], if any.
+        pub fn bar(&self) -> Option<&ProgressBar> {
+            self.bar.as_ref()
+        }
+
+        fn current(&mut self) -> &ProgressBar {
+            // Progress reported without a start still gets somewhere to go.
+            self.bar
+                .get_or_insert_with(|| self.multi.add(ProgressBar::new_spinner()))
+        }
+    }
+
+    impl Default for IndicatifProgress {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<Op: Contains<Progress>> PartialHandler<Op> for IndicatifProgress {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op.project()? {
+                Progress::Start(total) => {
+                    if let Some(previous) = self.bar.take() {
+                        previous.finish();
+                    }
+                    let bar = match total {
+                        Some(total) => ProgressBar::new(*total).with_style(
+                            ProgressStyle::with_template(
+                                "{bar:40} {pos}/{len} [{elapsed_precise}] {msg}",
+                            )
+                            .expect("valid progress template"),
+                        ),
+                        None => ProgressBar::new_spinner(),
+                    };
+                    self.bar = Some(self.multi.add(bar));
+                }
+                Progress::Tick(n) => self.current().inc(*n),
+                Progress::Message(msg) => self.current().set_message(msg.clone()),
+                Progress::Finish => {
+                    if let Some(bar) = &self.bar {
+                        bar.finish();
+                    }
+                }
+            }
+            Some(Box::new(()))
+        }
+    }
+
+    impl_into_vec_handler_for_family!(Progress: IndicatifProgress);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    effect! {
+        use algae::effects::progress::Progress;
+        Job::Process (u32) -> u32;
+    }
+
+    struct JobHandler;
+
+    impl PartialHandler<Op> for JobHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Job(Job::Process(n)) => Some(Box::new(n * 2)),
+                _ => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(JobHandler, Op);
+
+    #[effectful]
+    fn process_all(items: Vec<u32>) -> u32 {
+        let _: () = perform!(Progress::Start(Some(items.len() as u64)));
+        let mut sum = 0;
+        for item in items {
+            let doubled: u32 = perform!(Job::Process(item));
+            sum += doubled;
+            if item == 2 {
+                let _: () = perform!(Progress::Message("halfway".to_string()));
+            }
+            let _: () = perform!(Progress::Tick(1));
+        }
+        let _: () = perform!(Progress::Finish);
+        sum
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_contains_projection() {
+        let op: Op = Progress::Tick(3).into();
+        assert_eq!(Contains::<Progress>::project(&op), Some(&Progress::Tick(3)));
+        assert_eq!(Contains::<Job>::project(&op), None);
+        // Every family trivially contains itself.
+        assert_eq!(Progress::Finish.project(), Some(&Progress::Finish));
+    }
+
+    #[test]
+    fn test_silent_progress_tracks_position() {
+        let mut silent = SilentProgress::new();
+        for op in [
+            Progress::Start(Some(5)),
+            Progress::Tick(2),
+            Progress::Tick(1),
+        ] {
+            let reply = PartialHandler::<Op>::maybe_handle(&mut silent, &op.into());
+            assert!(reply.is_some());
+        }
+        assert_eq!(silent.total(), Some(5));
+        assert_eq!(silent.position(), 3);
+
+        // Operations of other families are declined.
+        let declined = PartialHandler::<Op>::maybe_handle(&mut silent, &Job::Process(1).into());
+        assert!(declined.is_none());
+    }
+
+    #[test]
+    fn test_progress_composes_with_job_handler() {
+        let result = process_all(vec![1, 2, 3])
+            .begin_chain()
+            .handle(JobHandler)
+            .handle(SilentProgress::new())
+            .run_or_panic();
+        assert_eq!(result, 12);
+    }
+
+    #[test]
+    fn test_log_progress_lines() {
+        let buf = SharedBuf::default();
+        let result = process_all(vec![1, 2, 3])
+            .begin_chain()
+            .handle(JobHandler)
+            .handle(LogProgress::new(buf.clone()))
+            .run_or_panic();
+        assert_eq!(result, 12);
+        assert_eq!(
+            buf.contents(),
+            "[progress] started (total 3)\n\
+             [progress] 1/3\n\
+             [progress] halfway\n\
+             [progress] 2/3\n\
+             [progress] 3/3\n\
+             [progress] finished (3/3)\n"
+        );
+    }
+
+    #[test]
+    fn test_log_progress_every() {
+        let buf = SharedBuf::default();
+        let mut log = LogProgress::new(buf.clone()).every(10);
+        let ops = [
+            Progress::Start(None),
+            Progress::Tick(4),
+            Progress::Tick(4),
+            Progress::Tick(4),
+        ];
+        for op in ops {
+            PartialHandler::<ProgressOp>::maybe_handle(&mut log, &op.into());
+        }
+        assert_eq!(buf.contents(), "[progress] started\n[progress] 12\n");
+    }
+
+    #[cfg(feature = "indicatif")]
+    #[test]
+    fn test_indicatif_progress() {
+        use indicatif::{MultiProgress, ProgressDrawTarget};
+
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        let mut bars = IndicatifProgress::with_multi(multi);
+        for op in [Progress::Start(Some(3)), Progress::Tick(2)] {
+            PartialHandler::<Op>::maybe_handle(&mut bars, &op.into());
+        }
+        let bar = bars.bar().unwrap().clone();
+        assert_eq!(bar.length(), Some(3));
+        assert_eq!(bar.position(), 2);
+
+        PartialHandler::<Op>::maybe_handle(&mut bars, &Progress::Finish.into());
+        assert!(bar.is_finished());
+    }
+}
@@ -0,0 +1,227 @@
+//! Random numbers.
+//!
+//! [`Random::U64`] draws a uniformly distributed `u64`, [`Random::Below`] one
+//! below a bound, [`Random::Float`] an `f64` in `[0, 1)` and
+//! [`Random::Bytes`] a buffer of random bytes. In production
+//! [`SystemRandom`] draws from a stream seeded differently in every process;
+//! in tests [`SeededRandom`] draws the same values on every run with the
+//! same seed.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::random::{Random, SeededRandom};
+//!
+//! effect! {
+//!     use algae::effects::random::Random;
+//! }
+//!
+//! #[effectful]
+//! fn roll() -> u64 {
+//!     let below: u64 = perform!(Random::Below(6));
+//!     below + 1
+//! }
+//!
+//! let first = roll().try_run_with(SeededRandom::new(7))?;
+//! assert_eq!(first, roll().try_run_with(SeededRandom::new(7))?);
+//! ```
+
+use crate::{nondet::Snapshot, splitmix64, Abort, Contains, PartialHandler, Scope};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hasher},
+};
+
+effect! {
+    root RandomOp;
+    typed;
+    Random::U64 -> u64;
+    Random::Below (u64) -> u64;
+    Random::Float -> f64;
+    Random::Bytes (usize) -> Vec<u8>;
+}
+
+/// The increment of the SplitMix64 generator.
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Answers `random` with values drawn from `next`.
+fn answer(random: &Random, mut next: impl FnMut() -> u64) -> Box<dyn Any + Send> {
+    match random {
+        Random::U64 => Box::new(next()),
+        Random::Below(0) => Abort::boxed("Random::Below(0) has no values to draw"),
+        Random::Below(bound) => {
+            // Rejecting the top partial range keeps every value equally likely
+            let zone = u64::MAX - u64::MAX % bound;
+            let value = loop {
+                let value = next();
+                if value < zone {
+                    break value;
+                }
+            };
+            Box::new(value % bound)
+        }
+        Random::Float => Box::new((next() >> 11) as f64 / (1u64 << 53) as f64),
+        Random::Bytes(len) => {
+            let mut bytes = Vec::with_capacity(len + 8);
+            while bytes.len() < *len {
+                bytes.extend_from_slice(&next().to_le_bytes());
+            }
+            bytes.truncate(*len);
+            Box::new(bytes)
+        }
+    }
+}
+
+/// Handles [`Random`] operations with a SplitMix64 stream whose seed differs
+/// in every process.
+///
+/// The values are not suitable for cryptography.
+#[derive(Debug, Clone)]
+pub struct SystemRandom {
+    state: u64,
+}
+
+impl SystemRandom {
+    pub fn new() -> Self {
+        Self {
+            state: RandomState::new().build_hasher().finish(),
+        }
+    }
+}
+
+impl Default for SystemRandom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Op: Contains<Random>> PartialHandler<Op> for SystemRandom {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        Some(answer(op.project()?, || {
+            self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+            splitmix64(self.state)
+        }))
+    }
+}
+
+/// Handles [`Random`] operations with values derived from a seed.
+///
+/// Each [`Scope`] draws from its own stream, seeded with [`Scope::seed`], so
+/// the values a sub-computation gets do not depend on what runs beside it.
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    seed: u64,
+    /// Values drawn so far, per scope
+    drawn: HashMap<Scope, u64>,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            drawn: HashMap::new(),
+        }
+    }
+
+    /// The seed values are derived from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl<Op: Contains<Random>> PartialHandler<Op> for SeededRandom {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let random = op.project()?;
+        let scope = crate::perform_scope().unwrap_or_default();
+        let seed = scope.seed(self.seed);
+        let drawn = self.drawn.entry(scope).or_default();
+        Some(answer(random, || {
+            let value = splitmix64(seed.wrapping_add(drawn.wrapping_mul(GOLDEN_GAMMA)));
+            *drawn += 1;
+            value
+        }))
+    }
+}
+
+/// Restoring a snapshot makes the handler draw the same values again.
+impl Snapshot for SeededRandom {
+    type State = HashMap<Scope, u64>;
+
+    fn snapshot(&self) -> Self::State {
+        self.drawn.clone()
+    }
+
+    fn restore(&mut self, drawn: &Self::State) {
+        self.drawn = drawn.clone();
+    }
+}
+
+impl_into_vec_handler_for_family!(Random: SystemRandom, SeededRandom);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        use algae::effects::random::Random;
+    }
+
+    #[effectful]
+    fn draw_all() -> (u64, u64, f64, Vec<u8>) {
+        let any: u64 = perform!(Random::U64);
+        let die: u64 = perform!(Random::Below(6));
+        let float: f64 = perform!(Random::Float);
+        let bytes: Vec<u8> = perform!(Random::Bytes(11));
+        (any, die, float, bytes)
+    }
+
+    #[effectful]
+    fn below(bound: u64) -> u64 {
+        perform!(Random::Below(bound))
+    }
+
+    #[test]
+    fn test_seeded_values_are_reproducible() {
+        let first = draw_all().try_run_with(SeededRandom::new(7)).unwrap();
+        assert_eq!(
+            first,
+            draw_all().try_run_with(SeededRandom::new(7)).unwrap()
+        );
+        assert_ne!(
+            first,
+            draw_all().try_run_with(SeededRandom::new(8)).unwrap()
+        );
+
+        let (_, die, float, bytes) = first;
+        assert!(die < 6);
+        assert!((0.0..1.0).contains(&float));
+        assert_eq!(bytes.len(), 11);
+    }
+
+    #[test]
+    fn test_below_covers_the_range() {
+        let mut session = crate::session::HandlerSession::new(SeededRandom::new(1));
+        let mut seen = [false; 6];
+        for _ in 0..200 {
+            seen[session.try_run(below(6)).unwrap() as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+        assert!(matches!(
+            session.try_run(below(0)),
+            Err(EffectError::Aborted(_))
+        ));
+    }
+
+    #[test]
+    fn test_system_random_draws_distinct_values() {
+        let (a, _, _, _) = draw_all().try_run_with(SystemRandom::new()).unwrap();
+        let (b, _, _, _) = draw_all().try_run_with(SystemRandom::new()).unwrap();
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,291 @@
+//! A read-only environment available throughout a computation.
+//!
+//! [`Reader<Env>`] replies with the whole environment to [`Reader::Ask`], and
+//! with a part of it to [`Reader::Asks`], built with [`Reader::asks`].
+//! [`ReaderHandler`] holds the environment, and [`local`] runs a
+//! sub-computation against a changed copy of it without touching the
+//! environment seen by the rest.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::reader::{local, Reader, ReaderHandler};
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! struct Settings {
+//!     indent: usize,
+//! }
+//!
+//! effect! {
+//!     use algae::effects::reader::Reader<Settings>;
+//!     Out::Line (String) -> ();
+//! }
+//!
+//! #[effectful]
+//! fn line(text: &'static str) {
+//!     let indent: usize = perform!(Reader::asks(|s: &Settings| s.indent));
+//!     let _: () = perform!(Out::Line(format!("{}{text}", " ".repeat(indent))));
+//! }
+//!
+//! let nested = local(|s: &Settings| Settings { indent: s.indent + 2 }, line("child"));
+//! nested
+//!     .begin_chain()
+//!     .handle(ReaderHandler::new(Settings { indent: 0 }))
+//!     .handle(Stdout)
+//!     .run()?;
+//! ```
+
+use crate::{
+    offer_effect, Contains, Effect, Effectful, IntoVecHandler, Offered, PartialHandler, Reply,
+    VecHandler,
+};
+use algae_macros::effect;
+use std::{any::Any, fmt, ops::CoroutineState, sync::Arc};
+
+effect! {
+    root ReaderOp;
+    typed;
+    Reader<Env>::Ask -> Env;
+    Reader<Env>::Asks (Query<Env>) -> Box<dyn Any + Send>;
+}
+
+impl<Env> Reader<Env> {
+    /// Reads `f` of the environment; the reply is an `R`.
+    pub fn asks<R, F>(f: F) -> Self
+    where
+        F: Fn(&Env) -> R + Send + Sync + 'static,
+        R: Any + Send,
+    {
+        Reader::Asks(Query(Arc::new(move |env| Box::new(f(env)))))
+    }
+}
+
+/// The function carried by [`Reader::Asks`].
+///
+/// Clones share the same function. Two `Query`s are equal when one is a
+/// clone of the other.
+pub struct Query<Env>(Arc<QueryFn<Env>>);
+
+type QueryFn<Env> = dyn Fn(&Env) -> Box<dyn Any + Send> + Send + Sync;
+
+impl<Env> Query<Env> {
+    /// Applies the query to `env`, boxing its result as a reply.
+    pub fn apply(&self, env: &Env) -> Box<dyn Any + Send> {
+        (self.0)(env)
+    }
+}
+
+impl<Env> Clone for Query<Env> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<Env> PartialEq for Query<Env> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<Env> fmt::Debug for Query<Env> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Query").finish_non_exhaustive()
+    }
+}
+
+/// Handles [`Reader<Env>`] operations from an environment fixed up front.
+#[derive(Debug, Clone, Default)]
+pub struct ReaderHandler<Env> {
+    env: Env,
+}
+
+impl<Env> ReaderHandler<Env> {
+    pub fn new(env: Env) -> Self {
+        Self { env }
+    }
+
+    /// The environment.
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+}
+
+impl<Env, Op> PartialHandler<Op> for ReaderHandler<Env>
+where
+    Env: Clone + Send + 'static,
+    Op: Contains<Reader<Env>>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            Reader::Ask => Some(Box::new(self.env.clone())),
+            Reader::Asks(query) => Some(query.apply(&self.env)),
+        }
+    }
+}
+
+// `impl_into_vec_handler_for_family!` takes no type parameters.
+impl<Env, Op> IntoVecHandler<Op> for ReaderHandler<Env>
+where
+    Env: Clone + Send + 'static,
+    Op: Contains<Reader<Env>> + 'static,
+{
+    fn into_vec_handler(self) -> VecHandler<Op> {
+        let mut vec = VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+/// Runs `computation` against the environment changed by `f`.
+///
+/// The environment is asked for outwards when `computation` first reads it,
+/// and its [`Reader<Env>`] operations are then answered from `f` of it. All
+/// other operations, and every operation performed after `computation`, see
+/// the environment unchanged.
+pub fn local<R, Env, Op, F>(f: F, computation: Effectful<R, Op>) -> Effectful<R, Op>
+where
+    R: Send + 'static,
+    Env: Clone + Send + 'static,
+    Op: Contains<Reader<Env>> + Send + 'static,
+    F: FnOnce(&Env) -> Env + Send + 'static,
+{
+    let mut inner = computation;
+    let mut f = Some(f);
+    let mut env: Option<ReaderHandler<Env>> = None;
+    Effectful::new(
+        #[coroutine]
+        move |_reply: Option<Reply>| {
+            let mut reply = None;
+            loop {
+                let eff = match inner.step(reply.take()) {
+                    CoroutineState::Yielded(eff) => eff,
+                    CoroutineState::Complete(result) => return result,
+                };
+                if env.is_none() && eff.op.project().is_some() {
+                    let outer: Option<Reply> = yield Effect::new(Op::from(Reader::Ask));
+                    let outer: Env = outer.expect("`Reader::Ask` got no reply").take();
+                    let f = f.take().expect("the environment is changed only once");
+                    env = Some(ReaderHandler::new(f(&outer)));
+                }
+                let Some(handler) = env.as_mut() else {
+                    reply = yield eff;
+                    continue;
+                };
+                reply = match offer_effect(eff, &mut |op| handler.maybe_handle(op)) {
+                    Offered::Replied(local) => Some(local),
+                    Offered::Aborted(_) => unreachable!("ReaderHandler never aborts"),
+                    Offered::Declined(eff) => yield eff,
+                    Offered::Partly(batch) => {
+                        let (rest, replies) = batch.into_parts();
+                        replies.complete(yield rest)
+                    }
+                };
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Settings {
+        indent: usize,
+        name: &'static str,
+    }
+
+    effect! {
+        use algae::effects::reader::Reader<Settings>;
+        Out::Line (String) -> ();
+    }
+
+    /// Collects output lines; declines `Reader`.
+    #[derive(Default)]
+    struct Lines(Vec<String>);
+
+    impl PartialHandler<Op> for Lines {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Out(Out::Line(line)) = op else {
+                return None;
+            };
+            self.0.push(line.clone());
+            Some(Box::new(()))
+        }
+    }
+
+    #[effectful]
+    fn line(text: &'static str) {
+        let indent: usize = perform!(Reader::asks(|s: &Settings| s.indent));
+        let _: () = perform!(Out::Line(format!("{}{text}", " ".repeat(indent))));
+    }
+
+    #[effectful]
+    fn ask() -> Settings {
+        perform!(Reader::Ask)
+    }
+
+    fn tree() -> Effectful<Settings, Op> {
+        line("root")
+            .bind(|()| {
+                local(
+                    |s: &Settings| Settings {
+                        indent: s.indent + 2,
+                        ..s.clone()
+                    },
+                    line("child"),
+                )
+            })
+            .bind(|()| line("sibling"))
+            .bind(|()| ask())
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            indent: 1,
+            name: "tree",
+        }
+    }
+
+    #[test]
+    fn test_ask_and_asks_read_the_environment() {
+        #[effectful]
+        fn name_and_indent() -> (&'static str, usize) {
+            let name: &'static str = perform!(Reader::asks(|s: &Settings| s.name));
+            let env: Settings = perform!(Reader::Ask);
+            (name, env.indent)
+        }
+
+        let mut reader = ReaderHandler::new(settings());
+        let result = name_and_indent().drive(|op| reader.maybe_handle(op));
+        assert_eq!(result.unwrap(), ("tree", 1));
+    }
+
+    #[test]
+    fn test_local_changes_the_environment_for_a_sub_computation_only() {
+        let mut lines = Lines::default();
+        let mut reader = ReaderHandler::new(settings());
+        let env = tree()
+            .drive(|op| reader.maybe_handle(op).or_else(|| lines.maybe_handle(op)))
+            .unwrap();
+        assert_eq!(env, settings());
+        assert_eq!(lines.0, [" root", "   child", " sibling"]);
+    }
+
+    #[test]
+    fn test_local_asks_only_when_the_environment_is_read() {
+        #[effectful]
+        fn quiet() -> u32 {
+            let _: () = perform!(Out::Line("no settings needed".into()));
+            7
+        }
+
+        let mut lines = Lines::default();
+        let result = local(|s: &Settings| s.clone(), quiet()).drive(|op| lines.maybe_handle(op));
+        assert_eq!(result.unwrap(), 7);
+    }
+}
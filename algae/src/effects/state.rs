@@ -0,0 +1,260 @@
+//! Mutable state threaded through a computation.
+//!
+//! [`State<T>`] reads a value with [`State::Get`], replaces it with
+//! [`State::Put`] and updates it in place with [`State::Modify`], which
+//! replies with the new value. [`StateHandler`] keeps the value between
+//! operations, and [`run_state`] scopes it to one computation: the state
+//! operations are answered inside, everything else is performed outwards, and
+//! the final value is returned next to the result.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::state::{run_state, State};
+//!
+//! effect! {
+//!     use algae::effects::state::State<u64>;
+//!     Log::Line (String) -> ();
+//! }
+//!
+//! #[effectful]
+//! fn count_words(text: String) {
+//!     for word in text.split_whitespace() {
+//!         let seen: u64 = perform!(State::modify(|n: &u64| n + 1));
+//!         let _: () = perform!(Log::Line(format!("{seen}: {word}")));
+//!     }
+//! }
+//!
+//! let ((), words) = run_state(0, count_words(text)).try_run_with(MemoryLog::new())?;
+//! ```
+
+use crate::{
+    offer_effect, Contains, Effectful, IntoVecHandler, Offered, PartialHandler, Reply, VecHandler,
+};
+use algae_macros::effect;
+use std::{any::Any, fmt, ops::CoroutineState, sync::Arc};
+
+effect! {
+    root StateOp;
+    typed;
+    State<T>::Get -> T;
+    State<T>::Put (T) -> ();
+    State<T>::Modify (Update<T>) -> T;
+}
+
+impl<T> State<T> {
+    /// Updates the state to `f` of its current value and replies with the new
+    /// value.
+    pub fn modify<F>(f: F) -> Self
+    where
+        F: Fn(&T) -> T + Send + Sync + 'static,
+    {
+        State::Modify(Update(Arc::new(f)))
+    }
+}
+
+/// The function carried by [`State::Modify`].
+///
+/// Clones share the same function. Two `Update`s are equal when one is a
+/// clone of the other.
+pub struct Update<T>(Arc<dyn Fn(&T) -> T + Send + Sync>);
+
+impl<T> Update<T> {
+    /// Applies the update to `value`.
+    pub fn apply(&self, value: &T) -> T {
+        (self.0)(value)
+    }
+}
+
+impl<T> Clone for Update<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> PartialEq for Update<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> fmt::Debug for Update<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Update").finish_non_exhaustive()
+    }
+}
+
+/// Handles [`State<T>`] operations by keeping the value.
+#[derive(Debug, Clone, Default)]
+pub struct StateHandler<T> {
+    value: T,
+}
+
+impl<T> StateHandler<T> {
+    pub fn new(initial: T) -> Self {
+        Self { value: initial }
+    }
+
+    /// The current value.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T, Op> PartialHandler<Op> for StateHandler<T>
+where
+    T: Clone + Send + 'static,
+    Op: Contains<State<T>>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            State::Get => Some(Box::new(self.value.clone())),
+            State::Put(value) => {
+                self.value = value.clone();
+                Some(Box::new(()))
+            }
+            State::Modify(update) => {
+                self.value = update.apply(&self.value);
+                Some(Box::new(self.value.clone()))
+            }
+        }
+    }
+}
+
+// `impl_into_vec_handler_for_family!` takes no type parameters.
+impl<T, Op> IntoVecHandler<Op> for StateHandler<T>
+where
+    T: Clone + Send + 'static,
+    Op: Contains<State<T>> + 'static,
+{
+    fn into_vec_handler(self) -> VecHandler<Op> {
+        let mut vec = VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+/// Runs `computation` with its own state starting at `initial`, and returns
+/// its result together with the final state.
+///
+/// The [`State<T>`] operations are answered inside; all other operations are
+/// performed by the returned computation, to be answered by the handlers it is
+/// run with. Each call starts from `initial`, so nested calls keep separate
+/// states.
+pub fn run_state<R, T, Op>(initial: T, computation: Effectful<R, Op>) -> Effectful<(R, T), Op>
+where
+    R: Send + 'static,
+    T: Clone + Send + 'static,
+    Op: Contains<State<T>> + Send + 'static,
+{
+    let mut inner = computation;
+    let mut state = StateHandler::new(initial);
+    Effectful::new(
+        #[coroutine]
+        move |_reply: Option<Reply>| {
+            let mut reply = None;
+            loop {
+                let eff = match inner.step(reply.take()) {
+                    CoroutineState::Yielded(eff) => eff,
+                    CoroutineState::Complete(result) => return (result, state.into_inner()),
+                };
+                reply = match offer_effect(eff, &mut |op| state.maybe_handle(op)) {
+                    Offered::Replied(local) => Some(local),
+                    Offered::Aborted(_) => unreachable!("StateHandler never aborts"),
+                    Offered::Declined(eff) => yield eff,
+                    Offered::Partly(batch) => {
+                        let (rest, replies) = batch.into_parts();
+                        replies.complete(yield rest)
+                    }
+                };
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        use algae::effects::state::State<u64>;
+        Log::Line (String) -> ();
+    }
+
+    /// Collects log lines; declines `State`.
+    #[derive(Default)]
+    struct Lines(Vec<String>);
+
+    impl PartialHandler<Op> for Lines {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Log(Log::Line(line)) = op else {
+                return None;
+            };
+            self.0.push(line.clone());
+            Some(Box::new(()))
+        }
+    }
+
+    algae::impl_into_vec_handler!(Lines, Op);
+
+    #[effectful]
+    fn count_words(text: &'static str) -> usize {
+        let mut words = 0;
+        for word in text.split_whitespace() {
+            let seen: u64 = perform!(State::modify(|n: &u64| n + 1));
+            let _: () = perform!(Log::Line(format!("{seen}: {word}")));
+            words += 1;
+        }
+        words
+    }
+
+    #[effectful(root = StateOp<Vec<&'static str>>)]
+    fn push_and_reset() -> Vec<&'static str> {
+        let _: () = perform!(State::Put(vec!["a"]));
+        let mut items: Vec<&'static str> = perform!(State::Get);
+        items.push("b");
+        let _: () = perform!(State::Put(items));
+        perform!(State::Get)
+    }
+
+    #[test]
+    fn test_state_handler_keeps_the_value() {
+        let mut state = StateHandler::new(Vec::new());
+        let items = push_and_reset().drive(|op| state.maybe_handle(op));
+        assert_eq!(items.unwrap(), ["a", "b"]);
+        assert_eq!(state.get(), &["a", "b"]);
+
+        let words = count_words("one two")
+            .begin_chain()
+            .handle(Lines::default())
+            .handle(StateHandler::new(10u64))
+            .run_or_panic();
+        assert_eq!(words, 2);
+    }
+
+    #[test]
+    fn test_run_state_answers_state_inside_and_passes_the_rest_on() {
+        let mut lines = Lines::default();
+        let (words, seen) = run_state(0, count_words("to be or not"))
+            .drive(|op| lines.maybe_handle(op))
+            .unwrap();
+        assert_eq!((words, seen), (4, 4));
+        assert_eq!(lines.0, ["1: to", "2: be", "3: or", "4: not"]);
+    }
+
+    #[test]
+    fn test_nested_run_state_keeps_separate_states() {
+        let ((words, inner), outer) = run_state(7, run_state(0, count_words("a b")))
+            .try_run_with(Lines::default())
+            .unwrap();
+        assert_eq!((words, inner, outer), (2, 2, 7));
+    }
+}
@@ -0,0 +1,605 @@
+//! Software transactional memory.
+//!
+//! Computations running on different threads share [`TVar`]s held in a
+//! [`TxStore`]. Reading and writing a variable on its own is atomic, but a
+//! sequence of reads and writes is only atomic when it is wrapped in
+//! [`TxState::Atomically`]: the [`TxStateHandler`] runs the transaction
+//! optimistically against a private log, then commits its writes only if no
+//! variable it read has been committed to by someone else in the meantime.
+//! If one has, the transaction is thrown away and run again.
+//!
+//! Transactions are ordinary effectful functions whose root is [`TxStateOp`],
+//! so the type system keeps I/O out of them: a transaction may be run any
+//! number of times, and only its transactional reads and writes are undone.
+//! Transactions nested inside a transaction become part of it.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::stm::{TVar, TxState, TxStateHandler, TxStateOp, TxStore};
+//!
+//! effect! {
+//!     use algae::effects::stm::TxState;
+//! }
+//!
+//! #[effectful(root = TxStateOp)]
+//! fn transfer(from: TVar<u64>, to: TVar<u64>, amount: u64) -> bool {
+//!     let balance: u64 = perform!(from.read());
+//!     if balance < amount {
+//!         return false;
+//!     }
+//!     let credit: u64 = perform!(to.read());
+//!     let _: () = perform!(from.write(balance - amount));
+//!     let _: () = perform!(to.write(credit + amount));
+//!     true
+//! }
+//!
+//! #[effectful]
+//! fn pay(from: TVar<u64>, to: TVar<u64>) -> bool {
+//!     perform!(TxState::atomically(move || transfer(from, to, 30)))
+//! }
+//!
+//! let store = TxStore::new();
+//! let (alice, bob) = (store.var(100u64), store.var(0u64));
+//! let workers: Vec<_> = (0..4)
+//!     .map(|_| {
+//!         let store = store.clone();
+//!         std::thread::spawn(move || pay(alice, bob).try_run_with(TxStateHandler::new(store)))
+//!     })
+//!     .collect();
+//! // ...
+//! assert_eq!(store.get(alice) + store.get(bob), 100);
+//! ```
+
+use crate::{Abort, Contains, EffectError, Effectful, PartialHandler};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+effect! {
+    root TxStateOp;
+    typed;
+    TxState::Read (VarId) -> Box<dyn Any + Send>;
+    TxState::Write ((VarId, TxValue)) -> ();
+    TxState::Atomically (Transaction) -> Box<dyn Any + Send>;
+}
+
+impl TxState {
+    /// Runs `body` as one transaction and replies with its result.
+    ///
+    /// `body` builds a fresh run of the transaction; it is called again each
+    /// time the transaction has to be retried.
+    pub fn atomically<R, F>(body: F) -> Self
+    where
+        F: Fn() -> Effectful<R, TxStateOp> + Send + Sync + 'static,
+        R: Any + Send,
+    {
+        TxState::Atomically(Transaction::new(body))
+    }
+}
+
+/// Identifies a variable within its [`TxStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VarId(u64);
+
+/// A transactional variable holding a `T`.
+///
+/// `TVar`s are cheap handles: copy them into every computation that uses the
+/// variable. The value lives in the [`TxStore`] that created the variable.
+pub struct TVar<T> {
+    id: VarId,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for TVar<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TVar<T> {}
+
+impl<T> fmt::Debug for TVar<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TVar").field(&self.id.0).finish()
+    }
+}
+
+impl<T: Any + Send + Sync + Clone> TVar<T> {
+    /// The id carried by this variable's operations.
+    pub fn id(&self) -> VarId {
+        self.id
+    }
+
+    /// Reads the variable; the reply is a `T`.
+    pub fn read(&self) -> TxState {
+        TxState::Read(self.id)
+    }
+
+    /// Writes `value` to the variable.
+    pub fn write(&self, value: T) -> TxState {
+        TxState::Write((self.id, TxValue::new(value)))
+    }
+}
+
+/// A type-erased variable value.
+///
+/// Clones share the same value. Two `TxValue`s are equal when one is a clone
+/// of the other.
+#[derive(Clone)]
+pub struct TxValue {
+    value: Arc<dyn Any + Send + Sync>,
+    reply: fn(&(dyn Any + Send + Sync)) -> Box<dyn Any + Send>,
+}
+
+impl TxValue {
+    fn new<T: Any + Send + Sync + Clone>(value: T) -> Self {
+        fn reply<T: Any + Send + Sync + Clone>(
+            value: &(dyn Any + Send + Sync),
+        ) -> Box<dyn Any + Send> {
+            let value = (value as &dyn Any)
+                .downcast_ref::<T>()
+                .expect("a value keeps its own type");
+            Box::new(value.clone())
+        }
+
+        Self {
+            value: Arc::new(value),
+            reply: reply::<T>,
+        }
+    }
+
+    /// A copy of the value, boxed as a reply.
+    fn reply(&self) -> Box<dyn Any + Send> {
+        (self.reply)(&*self.value)
+    }
+}
+
+impl PartialEq for TxValue {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+impl fmt::Debug for TxValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TxValue").finish_non_exhaustive()
+    }
+}
+
+type TransactionFn =
+    dyn Fn(Attempt<'_>) -> Result<Box<dyn Any + Send>, EffectError<TxStateOp>> + Send + Sync;
+
+/// The body of a [`TxState::Atomically`] operation.
+///
+/// Clones share the same body. Two `Transaction`s are equal when one is a
+/// clone of the other.
+#[derive(Clone)]
+pub struct Transaction {
+    run: Arc<TransactionFn>,
+}
+
+impl Transaction {
+    /// Wraps a function that builds a fresh run of the transaction.
+    pub fn new<R, F>(body: F) -> Self
+    where
+        F: Fn() -> Effectful<R, TxStateOp> + Send + Sync + 'static,
+        R: Any + Send,
+    {
+        Self {
+            run: Arc::new(move |attempt| {
+                body()
+                    .try_run_with(attempt)
+                    .map(|result| Box::new(result) as Box<dyn Any + Send>)
+            }),
+        }
+    }
+}
+
+impl PartialEq for Transaction {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.run, &other.run)
+    }
+}
+
+impl fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction").finish_non_exhaustive()
+    }
+}
+
+#[derive(Default)]
+struct StoreState {
+    next_id: u64,
+    /// Each variable's value and the number of commits that changed it.
+    vars: HashMap<VarId, (TxValue, u64)>,
+    conflicts: u64,
+}
+
+impl StoreState {
+    fn version(&self, id: VarId) -> u64 {
+        self.slot(id).1
+    }
+
+    fn slot(&self, id: VarId) -> &(TxValue, u64) {
+        self.vars
+            .get(&id)
+            .unwrap_or_else(|| panic!("variable {} does not belong to this store", id.0))
+    }
+
+    /// Whether every variable in `reads` is still at the version that was read.
+    fn validate(&self, reads: &HashMap<VarId, u64>) -> bool {
+        reads
+            .iter()
+            .all(|(&id, &version)| self.version(id) == version)
+    }
+
+    fn commit(&mut self, id: VarId, value: TxValue) {
+        let slot = self
+            .vars
+            .get_mut(&id)
+            .unwrap_or_else(|| panic!("variable {} does not belong to this store", id.0));
+        slot.0 = value;
+        slot.1 += 1;
+    }
+}
+
+/// The variables shared by transactional computations.
+///
+/// Clones share the same variables.
+#[derive(Clone, Default)]
+pub struct TxStore {
+    state: Arc<Mutex<StoreState>>,
+}
+
+impl TxStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a variable holding `initial`.
+    pub fn var<T: Any + Send + Sync + Clone>(&self, initial: T) -> TVar<T> {
+        let mut state = self.state.lock().unwrap();
+        let id = VarId(state.next_id);
+        state.next_id += 1;
+        state.vars.insert(id, (TxValue::new(initial), 0));
+        TVar {
+            id,
+            _value: PhantomData,
+        }
+    }
+
+    /// The committed value of `var`.
+    pub fn get<T: Any + Send + Sync + Clone>(&self, var: TVar<T>) -> T {
+        let state = self.state.lock().unwrap();
+        *state
+            .slot(var.id)
+            .0
+            .reply()
+            .downcast::<T>()
+            .expect("a variable keeps its own type")
+    }
+
+    /// Commits `value` to `var`, as a write outside any transaction would.
+    pub fn set<T: Any + Send + Sync + Clone>(&self, var: TVar<T>, value: T) {
+        self.state
+            .lock()
+            .unwrap()
+            .commit(var.id, TxValue::new(value));
+    }
+
+    /// How many transaction attempts have been thrown away because of a
+    /// conflict.
+    pub fn conflicts(&self) -> u64 {
+        self.state.lock().unwrap().conflicts
+    }
+}
+
+impl fmt::Debug for TxStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("TxStore")
+            .field("vars", &state.vars.len())
+            .field("conflicts", &state.conflicts)
+            .finish()
+    }
+}
+
+/// Abort reason for an attempt that read a variable changed by a concurrent
+/// commit; the attempt is retried.
+#[derive(Debug)]
+struct Conflict;
+
+/// What one attempt at a transaction has read and wants to write.
+#[derive(Default)]
+struct TxLog {
+    reads: HashMap<VarId, u64>,
+    writes: HashMap<VarId, TxValue>,
+}
+
+/// Handles the operations of one attempt at a transaction.
+struct Attempt<'a> {
+    store: &'a TxStore,
+    log: &'a mut TxLog,
+}
+
+impl PartialHandler<TxStateOp> for Attempt<'_> {
+    fn maybe_handle(&mut self, op: &TxStateOp) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            TxState::Read(id) => {
+                if let Some(value) = self.log.writes.get(id) {
+                    return Some(value.reply());
+                }
+                let state = self.store.state.lock().unwrap();
+                // Everything read so far must still be current, or the
+                // transaction could act on values that never coexisted.
+                if !state.validate(&self.log.reads) {
+                    return Some(Abort::boxed(Conflict));
+                }
+                let (value, version) = state.slot(*id);
+                self.log.reads.entry(*id).or_insert(*version);
+                Some(value.reply())
+            }
+            TxState::Write((id, value)) => {
+                self.log.writes.insert(*id, value.clone());
+                Some(Box::new(()))
+            }
+            // Nested transactions join the enclosing one.
+            TxState::Atomically(tx) => match (tx.run)(Attempt {
+                store: self.store,
+                log: &mut *self.log,
+            }) {
+                Ok(result) => Some(result),
+                Err(EffectError::Aborted(abort)) => Some(Box::new(abort)),
+                Err(EffectError::Unhandled(_)) => None,
+                Err(EffectError::ReplyMismatch(error)) => panic!("{error}"),
+            },
+        }
+    }
+}
+
+/// Handles [`TxState`] operations against a [`TxStore`].
+///
+/// Reads and writes outside a transaction take effect immediately.
+/// Transactions are retried until they commit without a conflict.
+#[derive(Debug, Clone)]
+pub struct TxStateHandler {
+    store: TxStore,
+}
+
+impl TxStateHandler {
+    pub fn new(store: TxStore) -> Self {
+        Self { store }
+    }
+
+    /// The store this handler works on.
+    pub fn store(&self) -> &TxStore {
+        &self.store
+    }
+
+    fn run(&self, tx: &Transaction) -> Box<dyn Any + Send> {
+        loop {
+            let mut log = TxLog::default();
+            let attempt = Attempt {
+                store: &self.store,
+                log: &mut log,
+            };
+            match (tx.run)(attempt) {
+                Ok(result) => {
+                    let mut state = self.store.state.lock().unwrap();
+                    if state.validate(&log.reads) {
+                        for (id, value) in log.writes {
+                            state.commit(id, value);
+                        }
+                        return result;
+                    }
+                    state.conflicts += 1;
+                }
+                Err(EffectError::Aborted(abort)) if abort.is::<Conflict>() => {
+                    self.store.state.lock().unwrap().conflicts += 1;
+                }
+                Err(EffectError::Aborted(abort)) => return Box::new(abort),
+                Err(EffectError::Unhandled(_)) => {
+                    unreachable!("attempts answer every transactional operation")
+                }
+                Err(EffectError::ReplyMismatch(error)) => panic!("{error}"),
+            }
+        }
+    }
+}
+
+impl<Op: Contains<TxState>> PartialHandler<Op> for TxStateHandler {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            TxState::Read(id) => Some(self.store.state.lock().unwrap().slot(*id).0.reply()),
+            TxState::Write((id, value)) => {
+                self.store.state.lock().unwrap().commit(*id, value.clone());
+                Some(Box::new(()))
+            }
+            TxState::Atomically(tx) => Some(self.run(tx)),
+        }
+    }
+}
+
+impl_into_vec_handler_for_family!(TxState: TxStateHandler);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::{sync::Barrier, thread};
+
+    effect! {
+        use algae::effects::stm::TxState;
+        Audit::Note (String) -> ();
+    }
+
+    #[derive(Default)]
+    struct AuditLog(Vec<String>);
+
+    impl PartialHandler<Op> for AuditLog {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Audit(Audit::Note(note)) => {
+                    self.0.push(note.clone());
+                    Some(Box::new(()))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(AuditLog, Op);
+
+    #[effectful(root = TxStateOp)]
+    fn transfer(from: TVar<u64>, to: TVar<u64>, amount: u64) -> bool {
+        let balance: u64 = perform!(from.read());
+        if balance < amount {
+            return false;
+        }
+        let credit: u64 = perform!(to.read());
+        let _: () = perform!(from.write(balance - amount));
+        let _: () = perform!(to.write(credit + amount));
+        true
+    }
+
+    #[effectful(root = TxStateOp)]
+    fn increment(counter: TVar<u64>) -> u64 {
+        let value: u64 = perform!(counter.read());
+        let _: () = perform!(counter.write(value + 1));
+        perform!(counter.read())
+    }
+
+    #[effectful(root = TxStateOp)]
+    fn increment_twice(counter: TVar<u64>) -> u64 {
+        let _: u64 = perform!(TxState::atomically(move || increment(counter)));
+        perform!(TxState::atomically(move || increment(counter)))
+    }
+
+    #[effectful]
+    fn pay(from: TVar<u64>, to: TVar<u64>, amount: u64) -> bool {
+        perform!(TxState::atomically(move || transfer(from, to, amount)))
+    }
+
+    #[effectful]
+    fn audited_increment(counter: TVar<u64>) -> u64 {
+        let value: u64 = perform!(TxState::atomically(move || increment(counter)));
+        let _: () = perform!(Audit::Note(format!("now {value}")));
+        value
+    }
+
+    #[test]
+    fn test_transaction_commits_writes() {
+        let store = TxStore::new();
+        let (alice, bob) = (store.var(100u64), store.var(5u64));
+        let handler = TxStateHandler::new(store.clone());
+
+        assert!(pay(alice, bob, 30).try_run_with(handler.clone()).unwrap());
+        assert_eq!((store.get(alice), store.get(bob)), (70, 35));
+
+        assert!(!pay(alice, bob, 80).try_run_with(handler).unwrap());
+        assert_eq!((store.get(alice), store.get(bob)), (70, 35));
+    }
+
+    #[test]
+    fn test_reads_and_writes_outside_transactions() {
+        #[effectful]
+        fn reset(var: TVar<String>) -> String {
+            let old: String = perform!(var.read());
+            let _: () = perform!(var.write(String::new()));
+            old
+        }
+
+        let store = TxStore::new();
+        let name = store.var(String::from("draft"));
+        let old = reset(name).try_run_with(TxStateHandler::new(store.clone()));
+        assert_eq!(old.unwrap(), "draft");
+        assert_eq!(store.get(name), "");
+    }
+
+    #[test]
+    fn test_nested_transactions_join_the_outer_one() {
+        #[effectful]
+        fn run(counter: TVar<u64>) -> u64 {
+            perform!(TxState::atomically(move || increment_twice(counter)))
+        }
+
+        let store = TxStore::new();
+        let counter = store.var(0u64);
+        let value = run(counter).try_run_with(TxStateHandler::new(store.clone()));
+        assert_eq!(value.unwrap(), 2);
+        assert_eq!(store.get(counter), 2);
+    }
+
+    #[test]
+    fn test_conflicting_commit_retries_the_transaction() {
+        let store = TxStore::new();
+        let counter = store.var(0u64);
+        let interferer = store.clone();
+        let attempts = Arc::new(Mutex::new(0));
+        let seen = attempts.clone();
+
+        #[effectful]
+        fn run(tx: TxState) -> u64 {
+            perform!(tx)
+        }
+
+        // The first attempt is overtaken by a commit between its read and
+        // its own commit.
+        let tx = TxState::atomically(move || {
+            let interferer = interferer.clone();
+            let attempts = attempts.clone();
+            increment(counter).bind(move |value| {
+                let mut attempts = attempts.lock().unwrap();
+                *attempts += 1;
+                if *attempts == 1 {
+                    interferer.set(counter, 100);
+                }
+                Effectful::new(
+                    #[coroutine]
+                    move |_: Option<Reply>| value,
+                )
+            })
+        });
+        let value = run(tx).try_run_with(TxStateHandler::new(store.clone()));
+        assert_eq!(value.unwrap(), 101);
+        assert_eq!(*seen.lock().unwrap(), 2);
+        assert_eq!(store.conflicts(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_transactions_do_not_lose_updates() {
+        let store = TxStore::new();
+        let counter = store.var(0u64);
+        let threads = 8;
+        let barrier = Arc::new(Barrier::new(threads));
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let store = store.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    for _ in 0..100 {
+                        audited_increment(counter)
+                            .begin_chain()
+                            .handle(TxStateHandler::new(store.clone()))
+                            .handle(AuditLog::default())
+                            .run_or_panic();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(store.get(counter), 800);
+    }
+}
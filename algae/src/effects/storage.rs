@@ -0,0 +1,153 @@
+//! Key-value storage of strings, like the browser's `localStorage`.
+//!
+//! [`Storage::Get`] reads the value stored under a key, [`Storage::Set`]
+//! replaces it and [`Storage::Remove`] deletes it. Like
+//! [`fs`](crate::effects::fs), failures are part of the reply, as
+//! `std::io::Result`s: a browser may have storage disabled or refuse a write
+//! over its quota. With the `browser` feature,
+//! [`WebStorage`](crate::effects::browser::WebStorage) keeps the values in
+//! the page's web storage; [`MemoryStorage`] keeps them in memory, for tests
+//! and for programs outside a browser.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::storage::{MemoryStorage, Storage};
+//! use std::io;
+//!
+//! effect! {
+//!     use algae::effects::storage::Storage;
+//! }
+//!
+//! #[effectful]
+//! fn count_visit() -> io::Result<u32> {
+//!     let stored: io::Result<Option<String>> = perform!(Storage::Get("visits".into()));
+//!     let visits = stored?.and_then(|v| v.parse().ok()).unwrap_or(0) + 1;
+//!     let saved: io::Result<()> = perform!(Storage::Set(("visits".into(), visits.to_string())));
+//!     saved.map(|()| visits)
+//! }
+//!
+//! let storage = MemoryStorage::new().with_item("visits", "41");
+//! assert_eq!(count_visit().try_run_with(storage.clone())??, 42);
+//! assert_eq!(storage.get("visits").as_deref(), Some("42"));
+//! ```
+
+use crate::{Contains, PartialHandler};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+effect! {
+    root StorageOp;
+    typed;
+    Storage::Get (String) -> std::io::Result<Option<String>>;
+    Storage::Set ((String, String)) -> std::io::Result<()>;
+    Storage::Remove (String) -> std::io::Result<()>;
+}
+
+/// Handles [`Storage`] operations with values kept in memory.
+///
+/// Clones share the same values, so keep a clone to inspect what a
+/// computation stored after handing the handler to it.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    items: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `value` under `key`.
+    pub fn with_item(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.items.lock().unwrap().insert(key.into(), value.into());
+        self
+    }
+
+    /// The value stored under `key`, if there is one.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.items.lock().unwrap().get(key).cloned()
+    }
+
+    /// The keys with a value, in order.
+    pub fn keys(&self) -> Vec<String> {
+        self.items.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+impl<Op: Contains<Storage>> PartialHandler<Op> for MemoryStorage {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let mut items = self.items.lock().unwrap();
+        match op.project()? {
+            Storage::Get(key) => Some(Box::new(Ok::<_, io::Error>(items.get(key).cloned()))),
+            Storage::Set((key, value)) => {
+                items.insert(key.clone(), value.clone());
+                Some(Box::new(Ok::<(), io::Error>(())))
+            }
+            Storage::Remove(key) => {
+                items.remove(key);
+                Some(Box::new(Ok::<(), io::Error>(())))
+            }
+        }
+    }
+}
+
+impl_into_vec_handler_for_family!(Storage: MemoryStorage);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        use algae::effects::storage::Storage;
+    }
+
+    #[effectful]
+    fn count_visit() -> io::Result<u32> {
+        let stored: io::Result<Option<String>> = perform!(Storage::Get("visits".into()));
+        let visits = stored?.and_then(|v| v.parse().ok()).unwrap_or(0) + 1;
+        let saved: io::Result<()> = perform!(Storage::Set(("visits".into(), visits.to_string())));
+        saved.map(|()| visits)
+    }
+
+    #[effectful]
+    fn forget(key: &'static str) -> io::Result<()> {
+        perform!(Storage::Remove(key.into()))
+    }
+
+    #[test]
+    fn test_memory_storage_keeps_values_between_runs() {
+        let storage = MemoryStorage::new().with_item("theme", "dark");
+        assert_eq!(
+            count_visit()
+                .try_run_with(storage.clone())
+                .unwrap()
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            count_visit()
+                .try_run_with(storage.clone())
+                .unwrap()
+                .unwrap(),
+            2
+        );
+        assert_eq!(storage.keys(), ["theme", "visits"]);
+
+        forget("theme")
+            .try_run_with(storage.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(storage.get("theme"), None);
+        assert_eq!(storage.get("visits").as_deref(), Some("2"));
+    }
+}
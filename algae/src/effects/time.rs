@@ -0,0 +1,283 @@
+//! Reading and advancing time.
+//!
+//! [`Time::Now`] and [`Time::MonotonicNow`] read the wall clock and the
+//! monotonic clock; [`Time::Sleep`] waits. Going through effects lets tests
+//! swap the real [`SystemClock`] for a [`VirtualClock`], on which sleeping
+//! returns immediately and simply moves virtual time forward, so code with
+//! timeouts, backoff or scheduling can be tested quickly and deterministically.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::time::{Time, VirtualClock};
+//! use std::time::{Duration, Instant};
+//!
+//! effect! {
+//!     use algae::effects::time::Time;
+//!     Net::Ping -> bool;
+//! }
+//!
+//! #[effectful]
+//! fn wait_until_up() -> u32 {
+//!     let mut attempts = 1;
+//!     loop {
+//!         let up: bool = perform!(Net::Ping);
+//!         if up {
+//!             break;
+//!         }
+//!         let _: () = perform!(Time::Sleep(Duration::from_secs(1 << attempts)));
+//!         attempts += 1;
+//!     }
+//!     attempts
+//! }
+//!
+//! let clock = VirtualClock::new();
+//! wait_until_up().begin_chain().handle(FlakyNet::new(3)).handle(clock.clone()).run()?;
+//! assert_eq!(clock.elapsed(), Duration::from_secs(2 + 4 + 8));
+//! ```
+
+use crate::{nondet::Snapshot, Contains, PartialHandler};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+effect! {
+    root TimeOp;
+    typed;
+    Time::Now -> SystemTime;
+    Time::MonotonicNow -> Instant;
+    Time::Sleep (Duration) -> ();
+}
+
+/// Handles [`Time`] operations with the real clocks and `std::thread::sleep`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<Op: Contains<Time>> PartialHandler<Op> for SystemClock {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project()? {
+            Time::Now => Some(Box::new(SystemTime::now())),
+            Time::MonotonicNow => Some(Box::new(Instant::now())),
+            Time::Sleep(duration) => {
+                std::thread::sleep(*duration);
+                Some(Box::new(()))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct VirtualState {
+    wall_start: SystemTime,
+    mono_start: Instant,
+    elapsed: Duration,
+    sleeps: Vec<Duration>,
+}
+
+/// Handles [`Time`] operations with a virtual clock.
+///
+/// The clock only moves when the computation sleeps or when
+/// [`VirtualClock::advance`] is called; sleeping never blocks. Clones share
+/// the same clock, so keep one to control and inspect time from the test.
+///
+/// [`Time::MonotonicNow`] replies with real `Instant`s offset by the virtual
+/// elapsed time, so durations between them are exact.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    state: Arc<Mutex<VirtualState>>,
+}
+
+impl VirtualClock {
+    /// A virtual clock starting at the Unix epoch.
+    pub fn new() -> Self {
+        Self::starting_at(SystemTime::UNIX_EPOCH)
+    }
+
+    /// A virtual clock whose wall time starts at `start`.
+    pub fn starting_at(start: SystemTime) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(VirtualState {
+                wall_start: start,
+                mono_start: Instant::now(),
+                elapsed: Duration::ZERO,
+                sleeps: Vec::new(),
+            })),
+        }
+    }
+
+    /// Moves virtual time forward without recording a sleep.
+    pub fn advance(&self, by: Duration) {
+        self.state.lock().unwrap().elapsed += by;
+    }
+
+    /// Sets the virtual wall time to `now`.
+    ///
+    /// Like a system clock being adjusted, only the wall clock jumps, possibly
+    /// backwards: neither [`elapsed`](Self::elapsed) nor the replies to
+    /// [`Time::MonotonicNow`] change.
+    pub fn set(&self, now: SystemTime) {
+        let mut state = self.state.lock().unwrap();
+        state.wall_start = now - state.elapsed;
+    }
+
+    /// Virtual time elapsed since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().elapsed
+    }
+
+    /// The current virtual wall time.
+    pub fn now(&self) -> SystemTime {
+        let state = self.state.lock().unwrap();
+        state.wall_start + state.elapsed
+    }
+
+    /// Every [`Time::Sleep`] duration requested so far, in order.
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state.lock().unwrap().sleeps.clone()
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Op: Contains<Time>> PartialHandler<Op> for VirtualClock {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let time = op.project()?;
+        let mut state = self.state.lock().unwrap();
+        match time {
+            Time::Now => Some(Box::new(state.wall_start + state.elapsed)),
+            Time::MonotonicNow => Some(Box::new(state.mono_start + state.elapsed)),
+            Time::Sleep(duration) => {
+                state.elapsed += *duration;
+                state.sleeps.push(*duration);
+                Some(Box::new(()))
+            }
+        }
+    }
+}
+
+/// Restoring a snapshot rewinds the clock and forgets the sleeps recorded
+/// since.
+impl Snapshot for VirtualClock {
+    type State = (Duration, usize);
+
+    fn snapshot(&self) -> Self::State {
+        let state = self.state.lock().unwrap();
+        (state.elapsed, state.sleeps.len())
+    }
+
+    fn restore(&mut self, &(elapsed, sleeps): &Self::State) {
+        let mut state = self.state.lock().unwrap();
+        state.elapsed = elapsed;
+        state.sleeps.truncate(sleeps);
+    }
+}
+
+impl_into_vec_handler_for_family!(Time: SystemClock, VirtualClock);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        use algae::effects::time::Time;
+        Net::Ping -> bool;
+    }
+
+    /// Fails the first `failures` pings.
+    struct FlakyNet {
+        failures: u32,
+    }
+
+    impl PartialHandler<Op> for FlakyNet {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Net(Net::Ping) => {
+                    let up = self.failures == 0;
+                    self.failures = self.failures.saturating_sub(1);
+                    Some(Box::new(up))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(FlakyNet, Op);
+
+    #[effectful]
+    fn wait_until_up() -> (u32, Duration) {
+        let started: Instant = perform!(Time::MonotonicNow);
+        let mut attempts = 1;
+        loop {
+            let up: bool = perform!(Net::Ping);
+            if up {
+                break;
+            }
+            let _: () = perform!(Time::Sleep(Duration::from_secs(1 << attempts)));
+            attempts += 1;
+        }
+        let finished: Instant = perform!(Time::MonotonicNow);
+        (attempts, finished - started)
+    }
+
+    #[test]
+    fn test_virtual_sleep_advances_time_instantly() {
+        let clock = VirtualClock::new();
+        let started = Instant::now();
+        let (attempts, waited) = wait_until_up()
+            .begin_chain()
+            .handle(FlakyNet { failures: 3 })
+            .handle(clock.clone())
+            .run_or_panic();
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(attempts, 4);
+        assert_eq!(waited, Duration::from_secs(14));
+        assert_eq!(clock.elapsed(), Duration::from_secs(14));
+        assert_eq!(clock.sleeps(), [2, 4, 8].map(Duration::from_secs).to_vec());
+    }
+
+    #[test]
+    fn test_virtual_wall_clock() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut clock = VirtualClock::starting_at(start);
+        clock.advance(Duration::from_secs(60));
+
+        let reply = PartialHandler::<TimeOp>::maybe_handle(&mut clock, &Time::Now.into());
+        let now = *reply.unwrap().downcast::<SystemTime>().unwrap();
+        assert_eq!(now, start + Duration::from_secs(60));
+        assert_eq!(clock.now(), now);
+        assert!(clock.sleeps().is_empty());
+
+        clock.set(start);
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.elapsed(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_system_clock_sleeps() {
+        let (attempts, waited) = wait_until_up()
+            .begin_chain()
+            .handle(FlakyNet { failures: 0 })
+            .handle(SystemClock::new())
+            .run_or_panic();
+        assert_eq!(attempts, 1);
+        assert!(waited < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,416 @@
+//! All-or-nothing groups of writes.
+//!
+//! A [`TransactionHandler`] wraps the handler that answers a program's
+//! writes. Between [`Transactional::Begin`] and [`Transactional::Commit`] it
+//! buffers the writes instead of passing them on, answering them with made-up
+//! replies, and hands them to the wrapped handler only when the transaction
+//! commits; [`Transactional::Rollback`] discards them. Inside `#[effectful]`
+//! functions, `transaction!` runs a block that evaluates to a `Result` this
+//! way, committing on `Ok` and rolling back on `Err`:
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::transaction::{TransactionHandler, Transactional};
+//!
+//! effect! {
+//!     use algae::effects::transaction::Transactional;
+//!     Bank::Balance (String) -> u64;
+//!     #[mutates]
+//!     Bank::Deposit ((String, u64)) -> ();
+//!     #[mutates]
+//!     Bank::Withdraw ((String, u64)) -> ();
+//! }
+//!
+//! #[effectful]
+//! fn transfer(from: String, to: String, amount: u64) -> Result<(), Overdrawn> {
+//!     transaction! {
+//!         let _: () = perform!(Bank::Deposit((to, amount)));
+//!         let balance: u64 = perform!(Bank::Balance(from.clone()));
+//!         if balance < amount {
+//!             return Err(Overdrawn(from)); // the deposit is discarded
+//!         }
+//!         let _: () = perform!(Bank::Withdraw((from, amount)));
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let handler = TransactionHandler::new(Accounts::default());
+//! transfer("alice".into(), "bob".into(), 30).try_run_with(handler)?;
+//! ```
+//!
+//! Operations declared with `#[mutates]` are the writes unless
+//! [`writes_when`](TransactionHandler::writes_when) says otherwise; the other
+//! operations reach the wrapped handler right away, so reads see the state
+//! from before the transaction. Writes are answered with `()` unless
+//! [`fabricate_with`](TransactionHandler::fabricate_with) makes up their
+//! replies.
+//!
+//! Transactions nest: a nested commit keeps its writes in the enclosing
+//! transaction, which commits or discards them with its own. A computation
+//! that stops in the middle of [`Effectful::transactional`], because an
+//! operation was aborted or the computation was dropped during a run, rolls
+//! its transaction back, see [`bracket`](crate::bracket).
+
+use crate::{bracket::Deferred, Abort, Contains, Effect, Effectful, OpMeta, PartialHandler, Reply};
+use algae_macros::effect;
+use std::{any::Any, fmt, ops::CoroutineState};
+
+effect! {
+    root TransactionalOp;
+    typed;
+    Transactional::Begin -> ();
+    Transactional::Commit -> ();
+    Transactional::Rollback -> ();
+}
+
+type WritesFn<Op> = Box<dyn Fn(&Op) -> bool + Send>;
+type FabricateFn<Op> = Box<dyn Fn(&Op) -> Option<Box<dyn Any + Send>> + Send>;
+
+/// Abort reason for a commit whose write the wrapped handler declined.
+///
+/// The writes buffered before it have been handed over, those after it are
+/// discarded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uncommitted<Op>(pub Op);
+
+/// A handler wrapper that buffers the writes performed in a transaction and
+/// hands them to its handler when the transaction commits.
+///
+/// # Type Parameters
+///
+/// * `Op` - The operation type
+/// * `H` - The wrapped partial handler, asked for the reads and the committed
+///   writes
+pub struct TransactionHandler<Op, H> {
+    inner: H,
+    pending: Vec<Op>,
+    /// How many writes were pending when each open transaction began,
+    /// innermost last.
+    savepoints: Vec<usize>,
+    writes: WritesFn<Op>,
+    fabricate: FabricateFn<Op>,
+}
+
+impl<Op: OpMeta + 'static, H> TransactionHandler<Op, H> {
+    /// Wraps `inner`, treating the operations declared with `#[mutates]` as
+    /// writes and answering them with `()` until they are committed.
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            pending: Vec::new(),
+            savepoints: Vec::new(),
+            writes: Box::new(Op::mutates_state),
+            fabricate: Box::new(|_| Some(Box::new(()))),
+        }
+    }
+}
+
+impl<Op, H> TransactionHandler<Op, H> {
+    /// Treats the operations `f` returns `true` for as writes, instead of
+    /// those declared with `#[mutates]`.
+    pub fn writes_when<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Op) -> bool + Send + 'static,
+    {
+        self.writes = Box::new(f);
+        self
+    }
+
+    /// Answers buffered writes with the replies `f` makes up for them.
+    ///
+    /// Writes `f` returns `None` for are declined, so a handler after this
+    /// one can answer them; their reply must still have the type `perform!`
+    /// takes it as.
+    pub fn fabricate_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Op) -> Option<Box<dyn Any + Send>> + Send + 'static,
+    {
+        self.fabricate = Box::new(f);
+        self
+    }
+
+    /// The writes buffered by the open transactions, in the order they were
+    /// performed.
+    pub fn pending(&self) -> &[Op] {
+        &self.pending
+    }
+
+    /// Whether a transaction is open.
+    pub fn in_transaction(&self) -> bool {
+        !self.savepoints.is_empty()
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<Op, H> TransactionHandler<Op, H>
+where
+    Op: fmt::Debug + Send + 'static,
+    H: PartialHandler<Op>,
+{
+    /// Hands the pending writes to the wrapped handler, stopping at the first
+    /// one it aborts or declines.
+    fn commit(&mut self) -> Box<dyn Any + Send> {
+        for op in std::mem::take(&mut self.pending) {
+            match self.inner.maybe_handle(&op) {
+                Some(reply) if reply.is::<Abort>() => return reply,
+                Some(_) => {}
+                None => return Abort::boxed(Uncommitted(op)),
+            }
+        }
+        Box::new(())
+    }
+}
+
+impl<Op, H> PartialHandler<Op> for TransactionHandler<Op, H>
+where
+    Op: Contains<Transactional> + Clone + fmt::Debug + Send + 'static,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match op.project() {
+            Some(Transactional::Begin) => self.savepoints.push(self.pending.len()),
+            Some(Transactional::Commit) => {
+                self.savepoints.pop();
+                if self.savepoints.is_empty() {
+                    return Some(self.commit());
+                }
+            }
+            Some(Transactional::Rollback) => {
+                if let Some(savepoint) = self.savepoints.pop() {
+                    self.pending.truncate(savepoint);
+                }
+            }
+            None if self.savepoints.is_empty() || !(self.writes)(op) => {
+                return self.inner.maybe_handle(op)
+            }
+            None => {
+                let reply = (self.fabricate)(op)?;
+                self.pending.push(op.clone());
+                return Some(reply);
+            }
+        }
+        Some(Box::new(()))
+    }
+}
+
+impl<Op, H> crate::IntoVecHandler<Op> for TransactionHandler<Op, H>
+where
+    Op: Contains<Transactional> + Clone + fmt::Debug + Send + 'static,
+    H: PartialHandler<Op> + Send + 'static,
+{
+    fn into_vec_handler(self) -> crate::VecHandler<Op> {
+        let mut vec = crate::VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+impl<Op: fmt::Debug, H: fmt::Debug> fmt::Debug for TransactionHandler<Op, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransactionHandler")
+            .field("inner", &self.inner)
+            .field("pending", &self.pending)
+            .field("depth", &self.savepoints.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, E, Op> Effectful<Result<T, E>, Op>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+    Op: From<Transactional> + Send + 'static,
+{
+    /// Runs the computation as a transaction, committing its writes if it
+    /// returns `Ok` and rolling them back if it returns `Err`.
+    ///
+    /// The computation is surrounded by [`Transactional::Begin`] and
+    /// [`Transactional::Commit`] or [`Transactional::Rollback`]; a
+    /// [`TransactionHandler`] among the handlers it is run with does the
+    /// buffering. Inside `#[effectful]` functions, `transaction!` runs a
+    /// block this way.
+    pub fn transactional(self) -> Self {
+        let mut body = self;
+        Effectful::new(
+            #[coroutine]
+            move |_: Option<Reply>| {
+                let _ = yield Effect::<Op>::new(Transactional::Begin.into());
+                // Rolls back if the body never gets to the end
+                let rollback = Deferred::<Op>::perform(Transactional::Rollback.into());
+                let mut reply = None;
+                let result = loop {
+                    match body.step(reply.take()) {
+                        CoroutineState::Yielded(eff) => reply = yield eff,
+                        CoroutineState::Complete(result) => break result,
+                    }
+                };
+                rollback.disarm();
+                let end = match result {
+                    Ok(_) => Transactional::Commit,
+                    Err(_) => Transactional::Rollback,
+                };
+                let _ = yield Effect::new(end.into());
+                result
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::collections::BTreeMap;
+
+    effect! {
+        use algae::effects::transaction::Transactional;
+        Bank::Balance (String) -> u64;
+        #[mutates]
+        Bank::Deposit ((String, u64)) -> ();
+        #[mutates]
+        Bank::Withdraw ((String, u64)) -> ();
+    }
+
+    /// Balances by account; withdrawing more than the balance aborts.
+    #[derive(Debug, Default)]
+    struct Accounts(BTreeMap<String, u64>);
+
+    impl Accounts {
+        fn with(balances: &[(&str, u64)]) -> Self {
+            Self(
+                balances
+                    .iter()
+                    .map(|&(name, balance)| (name.to_string(), balance))
+                    .collect(),
+            )
+        }
+    }
+
+    impl PartialHandler<Op> for Accounts {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Bank(op) = op else {
+                return None;
+            };
+            Some(match op {
+                Bank::Balance(name) => Box::new(self.0.get(name).copied().unwrap_or(0)),
+                Bank::Deposit((name, amount)) => {
+                    *self.0.entry(name.clone()).or_default() += amount;
+                    Box::new(())
+                }
+                Bank::Withdraw((name, amount)) => match self.0.get_mut(name) {
+                    Some(balance) if *balance >= *amount => {
+                        *balance -= amount;
+                        Box::new(())
+                    }
+                    _ => Abort::boxed(format!("{name} is overdrawn")),
+                },
+            })
+        }
+    }
+
+    fn balances(handler: &TransactionHandler<Op, Accounts>) -> Vec<u64> {
+        handler.inner.0.values().copied().collect()
+    }
+
+    #[effectful]
+    fn transfer(from: String, to: String, amount: u64) -> Result<(), String> {
+        transaction! {
+            let _: () = perform!(Bank::Deposit((to, amount)));
+            let balance: u64 = perform!(Bank::Balance(from.clone()));
+            if balance < amount {
+                return Err(format!("{from} has {balance}"));
+            }
+            let _: () = perform!(Bank::Withdraw((from, amount)));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writes_are_committed_on_success() {
+        let handler = TransactionHandler::new(Accounts::with(&[("alice", 50), ("bob", 0)]));
+        let (result, handler) =
+            transfer("alice".into(), "bob".into(), 30).try_run_with_handler(handler);
+        assert_eq!(result.unwrap(), Ok(()));
+        assert_eq!(balances(&handler), [20, 30]);
+        assert!(!handler.in_transaction());
+    }
+
+    #[test]
+    fn test_writes_are_discarded_on_error() {
+        let handler = TransactionHandler::new(Accounts::with(&[("alice", 10), ("bob", 0)]));
+        let (result, handler) =
+            transfer("alice".into(), "bob".into(), 30).try_run_with_handler(handler);
+        assert_eq!(result.unwrap(), Err("alice has 10".to_string()));
+        assert_eq!(balances(&handler), [10, 0]);
+        assert!(handler.pending().is_empty());
+    }
+
+    #[test]
+    fn test_aborted_runs_roll_back() {
+        #[effectful]
+        fn deposit_then_fail() -> Result<(), String> {
+            let result: Result<(), String> = transaction! {
+                let _: () = perform!(Bank::Deposit(("bob".to_string(), 5)));
+                let _: () = perform!(Bank::Withdraw(("carol".to_string(), 5)));
+                Ok(())
+            };
+            result
+        }
+
+        // The withdrawal is buffered too, so it aborts when committed
+        let handler = TransactionHandler::new(Accounts::with(&[("bob", 0)]));
+        let (result, handler) = deposit_then_fail().try_run_with_handler(handler);
+        assert!(matches!(result, Err(EffectError::Aborted(_))));
+        assert_eq!(balances(&handler), [5], "writes before it are handed over");
+
+        // With withdrawals handled right away, the run aborts mid-transaction
+        let handler = TransactionHandler::new(Accounts::with(&[("bob", 0)]))
+            .writes_when(|op| matches!(op, Op::Bank(Bank::Deposit(_))));
+        let (result, handler) = deposit_then_fail().try_run_with_handler(handler);
+        assert!(matches!(result, Err(EffectError::Aborted(_))));
+        assert_eq!(balances(&handler), [0]);
+        assert!(!handler.in_transaction());
+    }
+
+    #[test]
+    fn test_nested_transactions_commit_with_the_outer_one() {
+        #[effectful]
+        fn pay_twice(fail_second: bool) -> Result<(), String> {
+            transaction! {
+                let first = embed!(transfer("alice".into(), "bob".into(), 10));
+                let amount = if fail_second { 1000 } else { 10 };
+                let second = embed!(transfer("alice".into(), "bob".into(), amount));
+                first.and(second)
+            }
+        }
+
+        let handler = TransactionHandler::new(Accounts::with(&[("alice", 50), ("bob", 0)]));
+        let (result, handler) = pay_twice(false).try_run_with_handler(handler);
+        assert_eq!(result.unwrap(), Ok(()));
+        assert_eq!(balances(&handler), [30, 20]);
+
+        // The first transfer committed, but only into the outer transaction
+        let handler = TransactionHandler::new(Accounts::with(&[("alice", 50), ("bob", 0)]));
+        let (result, handler) = pay_twice(true).try_run_with_handler(handler);
+        assert!(result.unwrap().is_err());
+        assert_eq!(balances(&handler), [50, 0]);
+    }
+
+    #[test]
+    fn test_outside_transactions_writes_pass_through() {
+        #[effectful]
+        fn deposit() {
+            perform!(Bank::Deposit(("bob".to_string(), 5)))
+        }
+
+        let handler = TransactionHandler::new(Accounts::with(&[("bob", 0)]));
+        let (result, handler) = deposit().try_run_with_handler(handler);
+        result.unwrap();
+        assert_eq!(balances(&handler), [5]);
+    }
+}
@@ -0,0 +1,648 @@
+//! [`Fs`] and [`Net`] operations on io_uring (Linux, feature `io-uring`).
+//!
+//! [`UringIo`] answers file reads and writes and socket sends and receives by
+//! submitting them to an io_uring instance. As an ordinary handler it submits
+//! one operation at a time. [`UringIo::run_batch`] instead runs many
+//! computations side by side: whenever every computation is waiting for I/O,
+//! their operations go to the kernel in a single submission, so a batch of
+//! `n` computations costs one `io_uring_enter` per round instead of `n`
+//! syscalls.
+//!
+//! Opening files, creating temporary ones, resolving names, connecting and
+//! closing connections use ordinary syscalls; only the data transfer is submitted to
+//! the ring. Temporary directories and files are deleted when the
+//! [`UringIo`] is dropped.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::{fs::Fs, log::MemoryLog, uring::UringIo};
+//!
+//! effect! {
+//!     use algae::effects::fs::Fs;
+//!     use algae::effects::log::Log;
+//! }
+//!
+//! let mut io = UringIo::new(256)?;
+//! let checksums = io.run_batch(
+//!     paths.into_iter().map(checksum).collect(),
+//!     MemoryLog::new(),
+//! );
+//! ```
+
+use crate::{
+    dispatch_effect,
+    effects::{
+        fs::{Fs, Scratch},
+        net::{self, ConnId, Connections, Net},
+    },
+    Effect, EffectError, Effectful, FamilyOp, OpMeta, PartialHandler, Reply,
+};
+use io_uring::{opcode, squeue, types, IoUring};
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io, mem,
+    net::TcpStream,
+    ops::CoroutineState,
+    os::fd::{AsRawFd, RawFd},
+    path::Path,
+};
+
+type BoxedReply = Box<dyn Any + Send>;
+
+/// Handles [`Fs`] and [`Net`] operations by submitting them to io_uring.
+///
+/// Works with any root that embeds either family or both.
+pub struct UringIo {
+    ring: IoUring,
+    conns: Connections,
+    scratch: Scratch,
+    next_token: u64,
+}
+
+impl UringIo {
+    /// Sets up a ring with room for `entries` operations per submission.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the kernel does not support io_uring or refuses to set it
+    /// up, e.g. under a seccomp policy.
+    pub fn new(entries: u32) -> io::Result<Self> {
+        Ok(Self {
+            ring: IoUring::new(entries)?,
+            conns: Connections::default(),
+            scratch: Scratch::default(),
+            next_token: 0,
+        })
+    }
+
+    /// Registers a connection opened elsewhere, e.g. accepted by a listener.
+    pub fn adopt(&mut self, stream: TcpStream) -> ConnId {
+        self.conns.insert(stream)
+    }
+
+    /// Runs `computations` together, batching their I/O into shared
+    /// submissions; `other` answers all other operations.
+    ///
+    /// Returns each computation's outcome, in order. A computation that
+    /// performs an unhandled operation or is aborted stops there without
+    /// affecting the others.
+    ///
+    /// When more operations wait than the ring holds, those with a higher
+    /// [`Priority`](crate::priority::Priority) are submitted first.
+    pub fn run_batch<R, Op, H>(
+        &mut self,
+        computations: Vec<Effectful<R, Op>>,
+        mut other: H,
+    ) -> Vec<Result<R, EffectError<Op>>>
+    where
+        Op: FamilyOp + OpMeta,
+        H: PartialHandler<Op>,
+    {
+        let mut tasks: Vec<Option<Task<R, Op>>> = computations
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| Some(Task::resume(c.scoped(i as u64), Ok(None))))
+            .collect();
+        loop {
+            // Answer everything that needs no submission, until each task
+            // has finished or waits for I/O.
+            for slot in &mut tasks {
+                while matches!(slot, Some(Task::Running(..))) {
+                    let Some(Task::Running(c, eff)) = slot.take() else {
+                        unreachable!("the task was checked to be running")
+                    };
+                    let task = match self.start(eff.op.family_op()) {
+                        Some(Step::Submit(io)) => Task::Waiting(c, eff, io),
+                        Some(Step::Done(reply)) => {
+                            let mut reply = Some(reply);
+                            Task::dispatch(c, eff, &mut |_| reply.take())
+                        }
+                        None => Task::dispatch(c, eff, &mut |op| other.maybe_handle(op)),
+                    };
+                    *slot = Some(task);
+                }
+            }
+
+            let mut waiting: Vec<usize> = (0..tasks.len())
+                .filter(|&i| matches!(tasks[i], Some(Task::Waiting(..))))
+                .collect();
+            if waiting.is_empty() {
+                break;
+            }
+            // Urgent operations go into the first submission
+            waiting.sort_by_key(|&i| match &tasks[i] {
+                Some(Task::Waiting(_, eff, _)) => std::cmp::Reverse(eff.op.priority()),
+                _ => unreachable!("only waiting tasks are submitted"),
+            });
+            let capacity = self.ring.params().sq_entries() as usize;
+            for chunk in waiting.chunks(capacity) {
+                let mut in_flight: Vec<(u64, usize, InFlight)> = chunk
+                    .iter()
+                    .map(|&i| {
+                        let Some(Task::Waiting(_, _, io)) = tasks[i].as_mut() else {
+                            unreachable!("only waiting tasks are submitted")
+                        };
+                        let token = self.token();
+                        (token, i, mem::replace(io, InFlight::Taken))
+                    })
+                    .collect();
+                let entries: Vec<squeue::Entry> = in_flight
+                    .iter_mut()
+                    .map(|(token, _, io)| io.entry().user_data(*token))
+                    .collect();
+                let outcomes = self.submit(&entries);
+                for (token, i, io) in in_flight.drain(..) {
+                    let Some(Task::Waiting(c, eff, _)) = tasks[i].take() else {
+                        unreachable!("submitted tasks are waiting")
+                    };
+                    let task = match outcomes.as_ref().map(|results| results.get(&token)) {
+                        Ok(Some(&res)) => match io.complete(res) {
+                            Step::Submit(io) => Task::Waiting(c, eff, io),
+                            Step::Done(reply) => {
+                                let mut reply = Some(reply);
+                                Task::dispatch(c, eff, &mut |_| reply.take())
+                            }
+                        },
+                        Ok(None) => {
+                            let mut reply = Some(io.fail(lost()));
+                            Task::dispatch(c, eff, &mut |_| reply.take())
+                        }
+                        Err(e) => {
+                            let mut reply = Some(io.fail(io::Error::new(e.kind(), e.to_string())));
+                            Task::dispatch(c, eff, &mut |_| reply.take())
+                        }
+                    };
+                    tasks[i] = Some(task);
+                }
+            }
+        }
+
+        tasks
+            .into_iter()
+            .map(|task| match task {
+                Some(Task::Finished(result)) => result,
+                _ => unreachable!("every task has finished"),
+            })
+            .collect()
+    }
+
+    fn token(&mut self) -> u64 {
+        self.next_token += 1;
+        self.next_token
+    }
+
+    /// Begins an [`Fs`] or [`Net`] operation; `None` for other operations.
+    fn start(&mut self, op: &dyn Any) -> Option<Step> {
+        if let Some(op) = op.downcast_ref::<Fs>() {
+            Some(match op {
+                Fs::Read(path) => match InFlight::read(path) {
+                    Ok(io) => Step::Submit(io),
+                    Err(e) => Step::Done(Box::new(Err::<Vec<u8>, _>(e))),
+                },
+                Fs::Write((path, data)) => match File::create(path) {
+                    Ok(_) if data.is_empty() => Step::Done(Box::new(Ok::<(), io::Error>(()))),
+                    Ok(file) => Step::Submit(InFlight::Write {
+                        file,
+                        data: data.clone(),
+                        written: 0,
+                    }),
+                    Err(e) => Step::Done(Box::new(Err::<(), _>(e))),
+                },
+                Fs::TempDir => Step::Done(Box::new(self.scratch.create_dir())),
+                Fs::TempFile => Step::Done(Box::new(self.scratch.create_file())),
+            })
+        } else if let Some(op) = op.downcast_ref::<Net>() {
+            Some(match op {
+                Net::Resolve(host) => Step::Done(Box::new(net::resolve(host))),
+                Net::Connect(addr) => Step::Done(Box::new(
+                    TcpStream::connect(addr).map(|s| self.conns.insert(s)),
+                )),
+                Net::Send((conn, data)) => match self.conns.get(*conn) {
+                    Ok(stream) => Step::Submit(InFlight::Send {
+                        fd: stream.as_raw_fd(),
+                        data: data.clone(),
+                    }),
+                    Err(e) => Step::Done(Box::new(Err::<usize, _>(e))),
+                },
+                Net::Recv((conn, max)) => match self.conns.get(*conn) {
+                    Ok(stream) => Step::Submit(InFlight::Recv {
+                        fd: stream.as_raw_fd(),
+                        buf: vec![0; *max],
+                    }),
+                    Err(e) => Step::Done(Box::new(Err::<Vec<u8>, _>(e))),
+                },
+                Net::Close(conn) => {
+                    self.conns.remove(*conn);
+                    Step::Done(Box::new(()))
+                }
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Submits `entries` and waits for all of their completions, returning
+    /// each result by token.
+    ///
+    /// `entries` must fit into the submission queue.
+    fn submit(&mut self, entries: &[squeue::Entry]) -> io::Result<HashMap<u64, i32>> {
+        {
+            let mut sq = self.ring.submission();
+            for entry in entries.iter() {
+                // SAFETY: the buffers an entry points to are owned by its
+                // `InFlight`, which is kept alive until the entry completes
+                // or, if the ring fails, leaked by `InFlight::fail`.
+                unsafe { sq.push(entry) }.map_err(|_| io::Error::other("submission queue full"))?;
+            }
+        }
+        let mut results = HashMap::with_capacity(entries.len());
+        while results.len() < entries.len() {
+            match self.ring.submit_and_wait(entries.len() - results.len()) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            for cqe in self.ring.completion() {
+                // Completions left over from a failed submission are dropped
+                if entries.iter().any(|e| e.get_user_data() == cqe.user_data()) {
+                    results.insert(cqe.user_data(), cqe.result());
+                }
+            }
+        }
+        Ok(results)
+    }
+}
+
+impl fmt::Debug for UringIo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UringIo")
+            .field("conns", &self.conns)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Op: FamilyOp> PartialHandler<Op> for UringIo {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let mut io = match self.start(op.family_op())? {
+            Step::Done(reply) => return Some(reply),
+            Step::Submit(io) => io,
+        };
+        loop {
+            let token = self.token();
+            let entry = [io.entry().user_data(token)];
+            let res = match self.submit(&entry) {
+                Ok(results) => match results.get(&token) {
+                    Some(&res) => res,
+                    None => return Some(io.fail(lost())),
+                },
+                Err(e) => return Some(io.fail(e)),
+            };
+            match io.complete(res) {
+                Step::Done(reply) => return Some(reply),
+                Step::Submit(next) => io = next,
+            }
+        }
+    }
+}
+
+impl<Op: FamilyOp + 'static> crate::IntoVecHandler<Op> for UringIo {
+    fn into_vec_handler(self) -> crate::VecHandler<Op> {
+        let mut vec = crate::VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+fn lost() -> io::Error {
+    io::Error::other("io_uring completion was lost")
+}
+
+/// A computation in a batch.
+enum Task<R, Op: 'static> {
+    Running(Effectful<R, Op>, Effect<Op>),
+    Waiting(Effectful<R, Op>, Effect<Op>, InFlight),
+    Finished(Result<R, EffectError<Op>>),
+}
+
+impl<R, Op> Task<R, Op> {
+    /// Answers the pending operation with `dispatch` and resumes.
+    fn dispatch<F>(c: Effectful<R, Op>, eff: Effect<Op>, dispatch: &mut F) -> Self
+    where
+        F: FnMut(&Op) -> Option<BoxedReply>,
+    {
+        Self::resume(c, dispatch_effect(eff, dispatch).map(Some))
+    }
+
+    /// Resumes `c` up to its next operation or its end.
+    fn resume(mut c: Effectful<R, Op>, reply: Result<Option<Reply>, EffectError<Op>>) -> Self {
+        match reply {
+            Ok(reply) => match c.step(reply) {
+                CoroutineState::Yielded(eff) => Task::Running(c, eff),
+                CoroutineState::Complete(result) => Task::Finished(Ok(result)),
+            },
+            Err(error) => Task::Finished(Err(error)),
+        }
+    }
+}
+
+enum Step {
+    /// The reply is ready.
+    Done(BoxedReply),
+    /// The operation has to be submitted (again).
+    Submit(InFlight),
+}
+
+/// An operation being transferred by the kernel. Its buffers must outlive
+/// the submission.
+enum InFlight {
+    Read {
+        file: File,
+        buf: Vec<u8>,
+        filled: usize,
+        /// The file's size, if it is a regular file.
+        size: Option<usize>,
+    },
+    Write {
+        file: File,
+        data: Vec<u8>,
+        written: usize,
+    },
+    Send {
+        fd: RawFd,
+        data: Vec<u8>,
+    },
+    Recv {
+        fd: RawFd,
+        buf: Vec<u8>,
+    },
+    /// Placeholder while an operation is moved out of its task.
+    Taken,
+}
+
+impl InFlight {
+    fn read(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.is_file().then_some(metadata.len() as usize);
+        Ok(InFlight::Read {
+            file,
+            buf: vec![0; size.unwrap_or(0).max(4096)],
+            filled: 0,
+            size,
+        })
+    }
+
+    fn entry(&mut self) -> squeue::Entry {
+        match self {
+            InFlight::Read {
+                file, buf, filled, ..
+            } => {
+                let rest = &mut buf[*filled..];
+                opcode::Read::new(
+                    types::Fd(file.as_raw_fd()),
+                    rest.as_mut_ptr(),
+                    rest.len() as u32,
+                )
+                .offset(*filled as u64)
+                .build()
+            }
+            InFlight::Write {
+                file,
+                data,
+                written,
+            } => {
+                let rest = &data[*written..];
+                opcode::Write::new(
+                    types::Fd(file.as_raw_fd()),
+                    rest.as_ptr(),
+                    rest.len() as u32,
+                )
+                .offset(*written as u64)
+                .build()
+            }
+            InFlight::Send { fd, data } => {
+                opcode::Send::new(types::Fd(*fd), data.as_ptr(), data.len() as u32).build()
+            }
+            InFlight::Recv { fd, buf } => {
+                opcode::Recv::new(types::Fd(*fd), buf.as_mut_ptr(), buf.len() as u32).build()
+            }
+            InFlight::Taken => unreachable!("taken operations are not submitted"),
+        }
+    }
+
+    /// Applies the completion result `res`: a byte count, or a negated
+    /// errno.
+    fn complete(self, res: i32) -> Step {
+        if res < 0 {
+            return Step::Done(self.fail(io::Error::from_raw_os_error(-res)));
+        }
+        let n = res as usize;
+        match self {
+            InFlight::Read {
+                file,
+                mut buf,
+                mut filled,
+                size,
+            } => {
+                filled += n;
+                if n == 0 || Some(filled) == size {
+                    buf.truncate(filled);
+                    return Step::Done(Box::new(Ok::<_, io::Error>(buf)));
+                }
+                if filled == buf.len() {
+                    buf.resize(buf.len() * 2, 0);
+                }
+                Step::Submit(InFlight::Read {
+                    file,
+                    buf,
+                    filled,
+                    size,
+                })
+            }
+            InFlight::Write {
+                file,
+                data,
+                written,
+            } => {
+                if n == 0 {
+                    return Step::Done(Box::new(Err::<(), _>(io::Error::from(
+                        io::ErrorKind::WriteZero,
+                    ))));
+                }
+                let written = written + n;
+                if written == data.len() {
+                    return Step::Done(Box::new(Ok::<(), io::Error>(())));
+                }
+                Step::Submit(InFlight::Write {
+                    file,
+                    data,
+                    written,
+                })
+            }
+            InFlight::Send { .. } => Step::Done(Box::new(Ok::<usize, io::Error>(n))),
+            InFlight::Recv { mut buf, .. } => {
+                buf.truncate(n);
+                Step::Done(Box::new(Ok::<_, io::Error>(buf)))
+            }
+            InFlight::Taken => unreachable!("taken operations are not completed"),
+        }
+    }
+
+    /// The error reply for an operation whose submission failed.
+    ///
+    /// The kernel may still use the buffers, so they are leaked.
+    fn fail(self, error: io::Error) -> BoxedReply {
+        let reply: BoxedReply = match &self {
+            InFlight::Read { .. } | InFlight::Recv { .. } => Box::new(Err::<Vec<u8>, _>(error)),
+            InFlight::Write { .. } => Box::new(Err::<(), _>(error)),
+            InFlight::Send { .. } => Box::new(Err::<usize, _>(error)),
+            InFlight::Taken => unreachable!("taken operations have no reply"),
+        };
+        mem::forget(self);
+        reply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use crate::effects::{
+        log::{Log, LogEvent, MemoryLog},
+        net::tests::echo_server,
+    };
+    use algae::prelude::*;
+    use std::{net::SocketAddr, path::PathBuf};
+
+    effect! {
+        use algae::effects::fs::Fs;
+        use algae::effects::net::Net;
+        use algae::effects::log::Log;
+    }
+
+    /// A ring, or `None` where io_uring is unavailable (e.g. blocked by a
+    /// container's seccomp policy).
+    fn ring() -> Option<UringIo> {
+        match UringIo::new(8) {
+            Ok(io) => Some(io),
+            Err(e) => {
+                eprintln!("skipping: io_uring unavailable: {e}");
+                None
+            }
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("algae-uring-{}-{name}", std::process::id()))
+    }
+
+    #[effectful]
+    fn copy_upper(from: PathBuf, to: PathBuf) -> io::Result<usize> {
+        let contents: io::Result<Vec<u8>> = perform!(Fs::Read(from));
+        let contents = contents?.to_ascii_uppercase();
+        let len = contents.len();
+        let _: () = perform!(Log::Event(LogEvent::info(format!("copying {len} bytes"))));
+        let written: io::Result<()> = perform!(Fs::Write((to, contents)));
+        written.map(|()| len)
+    }
+
+    #[effectful]
+    fn echo(addr: SocketAddr, message: &'static [u8]) -> io::Result<Vec<u8>> {
+        let conn: io::Result<ConnId> = perform!(Net::Connect(addr));
+        let conn = conn?;
+        let sent: io::Result<usize> = perform!(Net::Send((conn, message.to_vec())));
+        assert_eq!(sent?, message.len());
+        let mut received = Vec::new();
+        while received.len() < message.len() {
+            let chunk: io::Result<Vec<u8>> = perform!(Net::Recv((conn, 64)));
+            received.extend(chunk?);
+        }
+        let _: () = perform!(Net::Close(conn));
+        Ok(received)
+    }
+
+    #[test]
+    fn test_single_ops_as_a_handler() {
+        let Some(io) = ring() else { return };
+        let (from, to) = (temp_path("single-in"), temp_path("single-out"));
+        let large = "x".repeat(10_000);
+        std::fs::write(&from, &large).unwrap();
+
+        let copied = copy_upper(from.clone(), to.clone())
+            .begin_chain()
+            .handle(io)
+            .handle(MemoryLog::new())
+            .run_or_panic();
+        let written = std::fs::read_to_string(&to);
+        let _ = (std::fs::remove_file(&from), std::fs::remove_file(&to));
+
+        assert_eq!(copied.unwrap(), 10_000);
+        assert_eq!(written.unwrap(), large.to_uppercase());
+    }
+
+    #[test]
+    fn test_batch_runs_every_computation() {
+        let Some(mut io) = ring() else { return };
+        // More computations than ring entries, so rounds need several chunks
+        let paths: Vec<(PathBuf, PathBuf)> = (0..20)
+            .map(|i| {
+                (
+                    temp_path(&format!("{i}-in")),
+                    temp_path(&format!("{i}-out")),
+                )
+            })
+            .collect();
+        for (i, (from, _)) in paths.iter().enumerate() {
+            std::fs::write(from, format!("file {i}")).unwrap();
+        }
+        let log = MemoryLog::new();
+
+        let results = io.run_batch(
+            paths
+                .iter()
+                .map(|(from, to)| copy_upper(from.clone(), to.clone()))
+                .collect(),
+            log.clone(),
+        );
+
+        for (i, ((from, to), result)) in paths.iter().zip(results).enumerate() {
+            assert_eq!(result.unwrap().unwrap(), format!("file {i}").len());
+            assert_eq!(std::fs::read_to_string(to).unwrap(), format!("FILE {i}"));
+            let _ = (std::fs::remove_file(from), std::fs::remove_file(to));
+        }
+        assert_eq!(log.events().len(), 20);
+    }
+
+    #[test]
+    fn test_batch_keeps_failures_separate() {
+        #[effectful]
+        fn read(path: PathBuf) -> io::Result<Vec<u8>> {
+            perform!(Fs::Read(path))
+        }
+
+        let Some(mut io) = ring() else { return };
+        let addr = echo_server();
+        let results = io.run_batch(
+            vec![
+                read(temp_path("missing/none")),
+                echo(addr, b"hello"),
+                echo(addr, b"world"),
+            ],
+            MemoryLog::new(),
+        );
+
+        let results: Vec<io::Result<Vec<u8>>> = results.into_iter().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            results[0].as_ref().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(results[1].as_ref().unwrap(), b"hello");
+        assert_eq!(results[2].as_ref().unwrap(), b"world");
+    }
+}
@@ -0,0 +1,214 @@
+//! Output accumulated alongside a computation's result.
+//!
+//! [`Writer<W>`] appends a value to the output with [`Writer::Tell`].
+//! [`WriterHandler`] collects the values into a `Vec<W>` or any other
+//! collection that can be extended, and [`run_writer`] scopes the output to
+//! one computation: it is returned next to the result, while every other
+//! operation is performed outwards.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::writer::{run_writer, Writer};
+//!
+//! effect! {
+//!     use algae::effects::writer::Writer<String>;
+//!     Db::Query (String) -> u64;
+//! }
+//!
+//! #[effectful]
+//! fn audit(tables: Vec<String>) -> u64 {
+//!     let mut rows = 0;
+//!     for table in tables {
+//!         let count: u64 = perform!(Db::Query(table.clone()));
+//!         let _: () = perform!(Writer::Tell(format!("{table}: {count} rows")));
+//!         rows += count;
+//!     }
+//!     rows
+//! }
+//!
+//! let (rows, notes) = run_writer(audit(tables)).try_run_with(db)?;
+//! ```
+
+use crate::{
+    offer_effect, Contains, Effectful, IntoVecHandler, Offered, PartialHandler, Reply, VecHandler,
+};
+use algae_macros::effect;
+use std::{any::Any, marker::PhantomData, ops::CoroutineState};
+
+effect! {
+    root WriterOp;
+    typed;
+    Writer<W>::Tell (W) -> ();
+}
+
+/// Handles [`Writer<W>`] operations by extending a collection, a `Vec<W>`
+/// unless given another.
+#[derive(Debug, Clone)]
+pub struct WriterHandler<W, C = Vec<W>> {
+    written: C,
+    _item: PhantomData<fn(W)>,
+}
+
+impl<W> WriterHandler<W> {
+    pub fn new() -> Self {
+        Self::extending(Vec::new())
+    }
+}
+
+impl<W> Default for WriterHandler<W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W, C> WriterHandler<W, C> {
+    /// A handler adding the values it is told to `collection`.
+    pub fn extending(collection: C) -> Self {
+        Self {
+            written: collection,
+            _item: PhantomData,
+        }
+    }
+
+    /// The values written so far.
+    pub fn written(&self) -> &C {
+        &self.written
+    }
+
+    pub fn into_inner(self) -> C {
+        self.written
+    }
+}
+
+impl<W, C, Op> PartialHandler<Op> for WriterHandler<W, C>
+where
+    W: Clone,
+    C: Extend<W>,
+    Op: Contains<Writer<W>>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let Writer::Tell(value) = op.project()?;
+        self.written.extend([value.clone()]);
+        Some(Box::new(()))
+    }
+}
+
+// `impl_into_vec_handler_for_family!` takes no type parameters.
+impl<W, C, Op> IntoVecHandler<Op> for WriterHandler<W, C>
+where
+    W: Clone + 'static,
+    C: Extend<W> + Send + 'static,
+    Op: Contains<Writer<W>> + 'static,
+{
+    fn into_vec_handler(self) -> VecHandler<Op> {
+        let mut vec = VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+/// Runs `computation`, collecting what it writes, and returns its result
+/// together with the values written, in order.
+///
+/// The [`Writer<W>`] operations are answered inside; all other operations are
+/// performed by the returned computation, to be answered by the handlers it is
+/// run with.
+pub fn run_writer<R, W, Op>(computation: Effectful<R, Op>) -> Effectful<(R, Vec<W>), Op>
+where
+    R: Send + 'static,
+    W: Clone + Send + 'static,
+    Op: Contains<Writer<W>> + Send + 'static,
+{
+    let mut inner = computation;
+    let mut writer = WriterHandler::new();
+    Effectful::new(
+        #[coroutine]
+        move |_reply: Option<Reply>| {
+            let mut reply = None;
+            loop {
+                let eff = match inner.step(reply.take()) {
+                    CoroutineState::Yielded(eff) => eff,
+                    CoroutineState::Complete(result) => return (result, writer.into_inner()),
+                };
+                reply = match offer_effect(eff, &mut |op| writer.maybe_handle(op)) {
+                    Offered::Replied(local) => Some(local),
+                    Offered::Aborted(_) => unreachable!("WriterHandler never aborts"),
+                    Offered::Declined(eff) => yield eff,
+                    Offered::Partly(batch) => {
+                        let (rest, replies) = batch.into_parts();
+                        replies.complete(yield rest)
+                    }
+                };
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::collections::BTreeSet;
+
+    effect! {
+        use algae::effects::writer::Writer<String>;
+        Db::Query (&'static str) -> u64;
+    }
+
+    /// Counts the letters of the table name; declines `Writer`.
+    struct Tables;
+
+    impl PartialHandler<Op> for Tables {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Db(Db::Query(table)) = op else {
+                return None;
+            };
+            Some(Box::new(table.len() as u64))
+        }
+    }
+
+    algae::impl_into_vec_handler!(Tables, Op);
+
+    #[effectful]
+    fn audit(tables: Vec<&'static str>) -> u64 {
+        let mut rows = 0;
+        for table in tables {
+            let count: u64 = perform!(Db::Query(table));
+            let _: () = perform!(Writer::Tell(format!("{table}: {count}")));
+            rows += count;
+        }
+        rows
+    }
+
+    #[test]
+    fn test_run_writer_returns_the_output_with_the_result() {
+        let (rows, notes) = run_writer(audit(vec!["users", "orders"]))
+            .try_run_with(Tables)
+            .unwrap();
+        assert_eq!(rows, 11);
+        assert_eq!(notes, ["users: 5", "orders: 6"]);
+    }
+
+    #[test]
+    fn test_writer_handler_extends_any_collection() {
+        let mut seen = WriterHandler::extending(BTreeSet::new());
+        let rows = audit(vec!["b", "a", "b"])
+            .drive(|op| seen.maybe_handle(op).or_else(|| Tables.maybe_handle(op)));
+        assert_eq!(rows.unwrap(), 3);
+        assert_eq!(
+            seen.into_inner().into_iter().collect::<Vec<_>>(),
+            ["a: 1", "b: 1"]
+        );
+
+        let rows = audit(vec!["t"])
+            .begin_chain()
+            .handle(Tables)
+            .handle(WriterHandler::new())
+            .run_or_panic();
+        assert_eq!(rows, 1);
+    }
+}
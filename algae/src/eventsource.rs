@@ -0,0 +1,629 @@
+//! Event sourcing.
+//!
+//! The operations a computation performs already describe everything that
+//! happens to a handler's state. Declaring the state-changing ones with
+//! `#[mutates]` is enough to persist that history:
+//!
+//! ```rust,ignore
+//! effect! {
+//!     #[mutates]
+//!     Account::Deposit (u64) -> ();
+//!     #[mutates]
+//!     Account::Withdraw (u64) -> ();
+//!     Account::Balance -> u64;
+//! }
+//! ```
+//!
+//! An [`EventSourced`] wrapper appends every answered `#[mutates]` operation
+//! to an [`EventStore`] before the computation sees the reply.
+//! [`EventSourced::recover`] rebuilds a fresh handler by feeding it the
+//! stored operations again, e.g. at startup:
+//!
+//! ```rust,ignore
+//! let store = FileStore::open("account.log", encode, decode)?;
+//! let mut account = EventSourced::recover(AccountHandler::default(), store)?;
+//! transfer().try_run_with(account)?;
+//! ```
+//!
+//! Handlers must apply operations deterministically for replay to rebuild
+//! the same state; replies produced during replay are discarded.
+//!
+//! # Schema versions
+//!
+//! Logs outlive the operations they were written with. A [`FileStore`] made
+//! [`versioned`](FileStore::versioned) stamps every record with the current
+//! schema version, and its [`Migrator`] upgrades older records step by step
+//! before they are decoded, e.g. after a variant was renamed:
+//!
+//! ```rust,ignore
+//! let migrator = Migrator::new(2).step(1, |old| Ok(old.replace("Add", "Deposit")));
+//! let store = FileStore::open("account.log", encode, decode)?.versioned(migrator);
+//! ```
+
+use crate::{Abort, EffectError, OpMeta, PartialHandler, UnhandledOp};
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Durable storage for the operations of an [`EventSourced`] handler.
+pub trait EventStore<Op> {
+    /// Appends `op`. It must be durable once this returns.
+    fn append(&mut self, op: &Op) -> io::Result<()>;
+
+    /// Every operation appended so far, oldest first.
+    fn load(&mut self) -> io::Result<Vec<Op>>;
+}
+
+/// An in-memory [`EventStore`]; clones share the log.
+///
+/// Not durable, but handy in tests and for rebuilding state in-process.
+pub struct MemoryStore<Op> {
+    ops: Arc<Mutex<Vec<Op>>>,
+}
+
+impl<Op> MemoryStore<Op> {
+    pub fn new() -> Self {
+        Self {
+            ops: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// A copy of the stored operations, oldest first.
+    pub fn ops(&self) -> Vec<Op>
+    where
+        Op: Clone,
+    {
+        self.ops.lock().unwrap().clone()
+    }
+}
+
+impl<Op> Default for MemoryStore<Op> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Op> Clone for MemoryStore<Op> {
+    fn clone(&self) -> Self {
+        Self {
+            ops: Arc::clone(&self.ops),
+        }
+    }
+}
+
+impl<Op: fmt::Debug> fmt::Debug for MemoryStore<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.ops.lock().unwrap().iter())
+            .finish()
+    }
+}
+
+impl<Op: Clone> EventStore<Op> for MemoryStore<Op> {
+    fn append(&mut self, op: &Op) -> io::Result<()> {
+        self.ops.lock().unwrap().push(op.clone());
+        Ok(())
+    }
+
+    fn load(&mut self) -> io::Result<Vec<Op>> {
+        Ok(self.ops())
+    }
+}
+
+type Upgrade = Box<dyn Fn(Vec<u8>) -> io::Result<Vec<u8>> + Send>;
+
+/// Upgrades encoded operations written with older schema versions.
+///
+/// Each step upgrades a record from one version to the next, so a record is
+/// passed through every step between the version it was written with and
+/// the current one. Steps work on the encoded bytes, since old records may
+/// no longer decode into the current operations.
+pub struct Migrator {
+    version: u32,
+    steps: BTreeMap<u32, Upgrade>,
+}
+
+impl Migrator {
+    /// A migrator for logs whose current schema version is `version`.
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            steps: BTreeMap::new(),
+        }
+    }
+
+    /// Upgrades records of version `from` to version `from + 1` with
+    /// `upgrade`.
+    pub fn step<F>(mut self, from: u32, upgrade: F) -> Self
+    where
+        F: Fn(Vec<u8>) -> io::Result<Vec<u8>> + Send + 'static,
+    {
+        self.steps.insert(from, Box::new(upgrade));
+        self
+    }
+
+    /// The current schema version, stamped on new records.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Upgrades `bytes`, written with schema version `from`, to the current
+    /// version.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if `from` is newer than the
+    /// current version or a step in between is missing, and with the error
+    /// of a failing step.
+    pub fn migrate(&self, from: u32, mut bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+        if from > self.version {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "record has schema version {from}, newer than {}",
+                    self.version
+                ),
+            ));
+        }
+        for version in from..self.version {
+            let upgrade = self.steps.get(&version).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("no migration from schema version {version}"),
+                )
+            })?;
+            bytes = upgrade(bytes)?;
+        }
+        Ok(bytes)
+    }
+}
+
+impl fmt::Debug for Migrator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migrator")
+            .field("version", &self.version)
+            .field("steps", &self.steps.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// An append-only file of length-prefixed records, synced after every
+/// append.
+///
+/// Operations are converted to and from bytes with the given functions. A
+/// record cut short by a crash during an append is ignored when loading.
+pub struct FileStore<Op> {
+    path: PathBuf,
+    file: File,
+    encode: fn(&Op) -> Vec<u8>,
+    decode: fn(&[u8]) -> io::Result<Op>,
+    migrator: Option<Migrator>,
+}
+
+impl<Op> FileStore<Op> {
+    /// Opens the log at `path`, creating it if needed.
+    pub fn open(
+        path: impl AsRef<Path>,
+        encode: fn(&Op) -> Vec<u8>,
+        decode: fn(&[u8]) -> io::Result<Op>,
+    ) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            file,
+            encode,
+            decode,
+            migrator: None,
+        })
+    }
+
+    /// Stamps new records with the schema version of `migrator`, and
+    /// upgrades stamped records with it when loading.
+    ///
+    /// Versioned records start with their version, so a log must be written
+    /// by versioned stores from the start.
+    pub fn versioned(mut self, migrator: Migrator) -> Self {
+        self.migrator = Some(migrator);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<Op> fmt::Debug for FileStore<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileStore")
+            .field("path", &self.path)
+            .field("migrator", &self.migrator)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Op> EventStore<Op> for FileStore<Op> {
+    fn append(&mut self, op: &Op) -> io::Result<()> {
+        let mut bytes = (self.encode)(op);
+        if let Some(migrator) = &self.migrator {
+            bytes.splice(0..0, migrator.version().to_le_bytes());
+        }
+        let len = u32::try_from(bytes.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "event too large"))?;
+        let mut record = Vec::with_capacity(4 + bytes.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&bytes);
+        self.file.write_all(&record)?;
+        self.file.sync_data()
+    }
+
+    fn load(&mut self) -> io::Result<Vec<Op>> {
+        let mut contents = Vec::new();
+        File::open(&self.path)?.read_to_end(&mut contents)?;
+        let mut ops = Vec::new();
+        let mut rest = &contents[..];
+        while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+            let len = u32::from_le_bytes(*len) as usize;
+            let Some(record) = tail.get(..len) else {
+                break;
+            };
+            let op = match &self.migrator {
+                Some(migrator) => {
+                    let (version, encoded) = record.split_first_chunk::<4>().ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "record without a schema version",
+                        )
+                    })?;
+                    let encoded =
+                        migrator.migrate(u32::from_le_bytes(*version), encoded.to_vec())?;
+                    (self.decode)(&encoded)?
+                }
+                None => (self.decode)(record)?,
+            };
+            ops.push(op);
+            rest = &tail[len..];
+        }
+        Ok(ops)
+    }
+}
+
+/// Why [`EventSourced::recover`] failed.
+pub enum RecoverError<Op> {
+    /// The store could not be read.
+    Store(io::Error),
+    /// The handler declined or aborted a stored operation.
+    Replay(EffectError<Op>),
+}
+
+impl<Op: fmt::Debug + OpMeta> fmt::Debug for RecoverError<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecoverError::Store(e) => f.debug_tuple("Store").field(e).finish(),
+            RecoverError::Replay(e) => f.debug_tuple("Replay").field(e).finish(),
+        }
+    }
+}
+
+impl<Op: fmt::Debug + OpMeta> fmt::Display for RecoverError<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecoverError::Store(e) => write!(f, "failed to load the event log: {e}"),
+            RecoverError::Replay(e) => write!(f, "failed to replay the event log: {e}"),
+        }
+    }
+}
+
+impl<Op: fmt::Debug + OpMeta> std::error::Error for RecoverError<Op> {}
+
+/// A handler layer that persists the `#[mutates]` operations answered by
+/// `inner` to `store`.
+///
+/// An operation is appended after `inner` answers it and before the reply
+/// reaches the computation, so every reply a computation has seen is
+/// durable. Declined and aborted operations are not stored. If the append
+/// fails, the computation is aborted with the `io::Error`.
+pub struct EventSourced<H, S> {
+    inner: H,
+    store: S,
+    replayed: usize,
+}
+
+impl<H, S> EventSourced<H, S> {
+    /// Wraps `inner`, which is taken to already reflect `store`.
+    pub fn new(inner: H, store: S) -> Self {
+        Self {
+            inner,
+            store,
+            replayed: 0,
+        }
+    }
+
+    /// Rebuilds the state of `inner` by replaying every operation in
+    /// `store`, then wraps it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store cannot be read, or if `inner` declines or aborts a
+    /// stored operation.
+    pub fn recover<Op>(mut inner: H, mut store: S) -> Result<Self, RecoverError<Op>>
+    where
+        H: PartialHandler<Op>,
+        S: EventStore<Op>,
+    {
+        let ops = store.load().map_err(RecoverError::Store)?;
+        let replayed = ops.len();
+        for (index, op) in ops.into_iter().enumerate() {
+            match inner
+                .maybe_handle(&op)
+                .map(|reply| reply.downcast::<Abort>())
+            {
+                Some(Ok(abort)) => return Err(RecoverError::Replay(EffectError::Aborted(*abort))),
+                Some(Err(_)) => {}
+                None => {
+                    let unhandled = UnhandledOp {
+                        index,
+                        declined: inner.handler_names(),
+                        ..UnhandledOp::new(op)
+                    };
+                    return Err(RecoverError::Replay(EffectError::Unhandled(unhandled)));
+                }
+            }
+        }
+        Ok(Self {
+            inner,
+            store,
+            replayed,
+        })
+    }
+
+    /// The number of operations replayed by [`recover`](Self::recover).
+    pub fn replayed(&self) -> usize {
+        self.replayed
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<H: fmt::Debug, S: fmt::Debug> fmt::Debug for EventSourced<H, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventSourced")
+            .field("inner", &self.inner)
+            .field("store", &self.store)
+            .field("replayed", &self.replayed)
+            .finish()
+    }
+}
+
+impl<Op, H, S> PartialHandler<Op> for EventSourced<H, S>
+where
+    Op: OpMeta,
+    H: PartialHandler<Op>,
+    S: EventStore<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let reply = self.inner.maybe_handle(op)?;
+        if op.mutates_state() && !reply.is::<Abort>() {
+            if let Err(error) = self.store.append(op) {
+                return Some(Abort::boxed(error));
+            }
+        }
+        Some(reply)
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        #[mutates]
+        Account::Deposit (u64) -> ();
+        #[mutates]
+        Account::Withdraw (u64) -> ();
+        Account::Balance -> u64;
+    }
+
+    #[derive(Debug)]
+    struct Overdrawn;
+
+    #[derive(Debug, Default)]
+    struct AccountHandler {
+        balance: u64,
+    }
+
+    impl PartialHandler<Op> for AccountHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Account(op) = op;
+            Some(match op {
+                Account::Deposit(amount) => {
+                    self.balance += amount;
+                    Box::new(())
+                }
+                Account::Withdraw(amount) if *amount > self.balance => Abort::boxed(Overdrawn),
+                Account::Withdraw(amount) => {
+                    self.balance -= amount;
+                    Box::new(())
+                }
+                Account::Balance => Box::new(self.balance),
+            })
+        }
+    }
+
+    #[effectful]
+    fn pay_in(amounts: Vec<u64>) -> u64 {
+        for amount in amounts {
+            let _: () = perform!(Account::Deposit(amount));
+        }
+        perform!(Account::Balance)
+    }
+
+    #[effectful]
+    fn withdraw(amount: u64) -> u64 {
+        let _: () = perform!(Account::Withdraw(amount));
+        perform!(Account::Balance)
+    }
+
+    fn encode(op: &Op) -> Vec<u8> {
+        let (tag, amount) = match op {
+            Op::Account(Account::Deposit(amount)) => (b'd', amount),
+            Op::Account(Account::Withdraw(amount)) => (b'w', amount),
+            Op::Account(Account::Balance) => unreachable!("balance does not mutate"),
+        };
+        let mut bytes = vec![tag];
+        bytes.extend_from_slice(&amount.to_le_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Op> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "bad event");
+        let (&tag, amount) = bytes.split_first().ok_or_else(invalid)?;
+        let amount = u64::from_le_bytes(amount.try_into().map_err(|_| invalid())?);
+        match tag {
+            b'd' => Ok(Account::Deposit(amount).into()),
+            b'w' => Ok(Account::Withdraw(amount).into()),
+            _ => Err(invalid()),
+        }
+    }
+
+    #[test]
+    fn test_generated_mutates_metadata() {
+        assert!(Account::Deposit(1).mutates_state());
+        assert!(Op::from(Account::Withdraw(1)).mutates_state());
+        assert!(!Op::from(Account::Balance).mutates_state());
+    }
+
+    #[test]
+    fn test_only_answered_mutations_are_stored() {
+        let store = MemoryStore::new();
+        let account = EventSourced::new(AccountHandler::default(), store.clone());
+        assert_eq!(pay_in(vec![5, 10]).try_run_with(account).unwrap(), 15);
+
+        let account = EventSourced::new(AccountHandler { balance: 15 }, store.clone());
+        assert!(withdraw(100).try_run_with(account).is_err());
+
+        assert_eq!(
+            store.ops(),
+            [Account::Deposit(5).into(), Account::Deposit(10).into()]
+        );
+    }
+
+    #[test]
+    fn test_recover_rebuilds_state_from_a_file() {
+        let path = std::env::temp_dir().join(format!("algae-events-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = FileStore::open(&path, encode, decode).unwrap();
+        let account = EventSourced::recover(AccountHandler::default(), store).unwrap();
+        assert_eq!(account.replayed(), 0);
+        assert_eq!(pay_in(vec![30, 12]).try_run_with(account).unwrap(), 42);
+
+        let store = FileStore::open(&path, encode, decode).unwrap();
+        let account = EventSourced::recover(AccountHandler::default(), store).unwrap();
+        assert_eq!(account.replayed(), 2);
+        assert_eq!(account.inner().balance, 42);
+        assert_eq!(withdraw(40).try_run_with(account).unwrap(), 2);
+
+        // A torn final record is ignored
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[9, 0, 0, 0, b'd'])
+            .unwrap();
+        let store = FileStore::open(&path, encode, decode).unwrap();
+        let account = EventSourced::recover(AccountHandler::default(), store).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(account.replayed(), 3);
+        assert_eq!(account.inner().balance, 2);
+    }
+
+    #[test]
+    fn test_versioned_logs_are_migrated_on_recovery() {
+        let path = std::env::temp_dir().join(format!("algae-events-v-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // Version 1 wrote deposits as text, e.g. `add 30`
+        fn encode_v1(op: &Op) -> Vec<u8> {
+            let Op::Account(Account::Deposit(amount)) = op else {
+                unreachable!("version 1 only had deposits")
+            };
+            format!("add {amount}").into_bytes()
+        }
+        let mut store = FileStore::open(&path, encode_v1, decode)
+            .unwrap()
+            .versioned(Migrator::new(1));
+        store.append(&Account::Deposit(30).into()).unwrap();
+
+        let migrator = || {
+            Migrator::new(2).step(1, |old| {
+                let text = String::from_utf8(old).map_err(io::Error::other)?;
+                let amount: u64 = text
+                    .strip_prefix("add ")
+                    .and_then(|amount| amount.parse().ok())
+                    .ok_or_else(|| io::Error::other("bad version 1 event"))?;
+                Ok(encode(&Account::Deposit(amount).into()))
+            })
+        };
+        let store = FileStore::open(&path, encode, decode)
+            .unwrap()
+            .versioned(migrator());
+        let account = EventSourced::recover(AccountHandler::default(), store).unwrap();
+        assert_eq!(pay_in(vec![12]).try_run_with(account).unwrap(), 42);
+
+        let store = FileStore::open(&path, encode, decode)
+            .unwrap()
+            .versioned(migrator());
+        let account = EventSourced::recover(AccountHandler::default(), store).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(account.inner().balance, 42);
+    }
+
+    #[test]
+    fn test_migrator_refuses_unknown_versions() {
+        let migrator = Migrator::new(3).step(2, Ok);
+        assert_eq!(migrator.migrate(2, b"x".to_vec()).unwrap(), b"x");
+        assert_eq!(
+            migrator.migrate(1, Vec::new()).unwrap_err().to_string(),
+            "no migration from schema version 1"
+        );
+        assert_eq!(
+            migrator.migrate(4, Vec::new()).unwrap_err().to_string(),
+            "record has schema version 4, newer than 3"
+        );
+    }
+
+    #[test]
+    fn test_recover_reports_rejected_events() {
+        let store = MemoryStore::new();
+        store.clone().append(&Account::Withdraw(1).into()).unwrap();
+        match EventSourced::recover(AccountHandler::default(), store) {
+            Err(RecoverError::Replay(EffectError::Aborted(abort))) => {
+                assert!(abort.is::<Overdrawn>())
+            }
+            other => panic!("expected an aborted replay, got {other:?}"),
+        }
+    }
+}
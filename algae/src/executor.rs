@@ -0,0 +1,317 @@
+//! Running computations on an async executor.
+//!
+//! Effectful computations are driven synchronously, one reply at a time. To
+//! use them from async code, this module provides:
+//!
+//! - [`AsyncHandler`], for handlers that answer operations with a future,
+//!   e.g. an HTTP client or a database pool,
+//! - [`run_async`], which drives a computation to its end, awaiting the
+//!   replies of an async handler and answering the other operations with an
+//!   ordinary [`PartialHandler`], and [`Effectful::run_async`] for
+//!   computations an async handler answers on its own,
+//! - [`Spawner`], the interface to an executor's task spawning, with
+//!   [`spawn_handled`](Spawner::spawn_handled),
+//!   [`spawn_with`](Spawner::spawn_with) and
+//!   [`spawn_async`](Spawner::spawn_async) built on top of it.
+//!
+//! Nothing here depends on a particular executor. Spawners for async-std,
+//! smol and tokio live in `algae::async_std`, `algae::smol` and
+//! `algae::tokio` (features `async-std`, `smol` and `tokio`).
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::executor::{AsyncHandler, BoxFuture, Spawner};
+//! use algae::smol::Smol;
+//!
+//! struct Users(PgPool);
+//!
+//! impl AsyncHandler<Op> for Users {
+//!     fn maybe_handle<'a>(&'a mut self, op: &'a Op) -> BoxFuture<'a, Option<Box<dyn Any + Send>>> {
+//!         Box::pin(async move {
+//!             let Op::Db(Db::User(id)) = op else { return None };
+//!             Some(Box::new(self.0.find_user(*id).await) as Box<dyn Any + Send>)
+//!         })
+//!     }
+//! }
+//!
+//! let profile = Smol.spawn_async(load_profile(7), Users(pool), MemoryLog::new());
+//! let profile = smol::block_on(profile)?;
+//! ```
+
+use crate::{dispatch_effect, EffectError, Effectful, HandlerFactory, PartialHandler};
+use alloc::boxed::Box;
+use core::{any::Any, future::Future, ops::CoroutineState, pin::Pin};
+
+/// A boxed future, as returned by [`AsyncHandler::maybe_handle`].
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A handler that answers operations asynchronously.
+///
+/// The async counterpart of [`PartialHandler`]: the future resolves to the
+/// reply, or to `None` if the handler declines the operation. It returns a
+/// boxed future rather than being an `async fn` so the future is known to
+/// be `Send`, which [`Spawner::spawn_async`] relies on.
+pub trait AsyncHandler<Op> {
+    fn maybe_handle<'a>(&'a mut self, op: &'a Op) -> BoxFuture<'a, Option<Box<dyn Any + Send>>>;
+}
+
+impl<Op, H: AsyncHandler<Op> + ?Sized> AsyncHandler<Op> for Box<H> {
+    fn maybe_handle<'a>(&'a mut self, op: &'a Op) -> BoxFuture<'a, Option<Box<dyn Any + Send>>> {
+        (**self).maybe_handle(op)
+    }
+}
+
+/// Runs `computation` to its end, offering each operation to `async_h`
+/// first and passing the ones it declines to `other`.
+///
+/// Fails like [`Effectful::try_run_with`] if neither handler accepts an
+/// operation or one of them aborts.
+pub async fn run_async<R, Op, A, H>(
+    mut computation: Effectful<R, Op>,
+    mut async_h: A,
+    mut other: H,
+) -> Result<R, EffectError<Op>>
+where
+    A: AsyncHandler<Op>,
+    H: PartialHandler<Op>,
+{
+    let mut reply = None;
+    loop {
+        let eff = match computation.step(reply) {
+            CoroutineState::Yielded(eff) => eff,
+            CoroutineState::Complete(result) => return Ok(result),
+        };
+        let answered = async_h.maybe_handle(&eff.op).await;
+        reply = Some(match answered {
+            Some(answer) => {
+                let mut answer = Some(answer);
+                dispatch_effect(eff, &mut |_| answer.take())?
+            }
+            None => dispatch_effect(eff, &mut |op| other.maybe_handle(op))?,
+        });
+    }
+}
+
+impl<R, Op: 'static> Effectful<R, Op> {
+    /// Runs the computation to its end with an async handler alone, awaiting
+    /// its reply to each operation.
+    ///
+    /// Fails with [`EffectError::Unhandled`] if `async_h` declines an
+    /// operation; use [`run_async`] to pass those to an ordinary handler.
+    pub async fn run_async<A: AsyncHandler<Op>>(self, async_h: A) -> Result<R, EffectError<Op>> {
+        run_async(self, async_h, Declines).await
+    }
+}
+
+/// Declines every operation.
+pub(crate) struct Declines;
+
+impl<Op> PartialHandler<Op> for Declines {
+    fn maybe_handle(&mut self, _op: &Op) -> Option<Box<dyn Any + Send>> {
+        None
+    }
+}
+
+impl<Op> AsyncHandler<Op> for Declines {
+    fn maybe_handle<'a>(&'a mut self, _op: &'a Op) -> BoxFuture<'a, Option<Box<dyn Any + Send>>> {
+        Box::pin(async { None })
+    }
+}
+
+/// An executor's task spawning.
+///
+/// Handles are futures of the task's output, so a spawned computation can be
+/// awaited like any other task of the executor.
+pub trait Spawner {
+    /// A running task.
+    type Handle<T: Send + 'static>: Future<Output = T> + Send + 'static;
+
+    /// Runs `future` as a task of the executor.
+    fn spawn<F>(&self, future: F) -> Self::Handle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static;
+
+    /// Runs `f` on a thread where it may block.
+    fn spawn_blocking<F, T>(&self, f: F) -> Self::Handle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static;
+
+    /// Runs `computation` with `h` on a thread where it may block, since
+    /// ordinary handlers answer synchronously.
+    fn spawn_handled<R, Op, H>(
+        &self,
+        computation: Effectful<R, Op>,
+        h: H,
+    ) -> Self::Handle<Result<R, EffectError<Op>>>
+    where
+        R: Send + 'static,
+        Op: Send + 'static,
+        H: PartialHandler<Op> + Send + 'static,
+    {
+        self.spawn_blocking(move || computation.try_run_with(h))
+    }
+
+    /// Runs `computation` like [`spawn_handled`](Spawner::spawn_handled),
+    /// with a handler chain built for the task by `factory`.
+    fn spawn_with<R, Op, F>(
+        &self,
+        computation: Effectful<R, Op>,
+        factory: &F,
+    ) -> Self::Handle<Result<R, EffectError<Op>>>
+    where
+        R: Send + 'static,
+        Op: Send + 'static,
+        F: HandlerFactory<Op>,
+        F::Handler: Send + 'static,
+    {
+        self.spawn_handled(computation, factory.build())
+    }
+
+    /// Runs `computation` as a task with [`run_async`].
+    fn spawn_async<R, Op, A, H>(
+        &self,
+        computation: Effectful<R, Op>,
+        async_h: A,
+        other: H,
+    ) -> Self::Handle<Result<R, EffectError<Op>>>
+    where
+        R: Send + 'static,
+        Op: Send + Sync + 'static,
+        A: AsyncHandler<Op> + Send + 'static,
+        H: PartialHandler<Op> + Send + 'static,
+    {
+        self.spawn(run_async(computation, async_h, other))
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::{
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+        thread::{self, Thread},
+    };
+
+    effect! {
+        Db::User (u32) -> String;
+        Log::Line (String) -> ();
+    }
+
+    /// Replies to `Db::User` after yielding to the executor once.
+    struct SlowDb;
+
+    impl AsyncHandler<Op> for SlowDb {
+        fn maybe_handle<'a>(
+            &'a mut self,
+            op: &'a Op,
+        ) -> BoxFuture<'a, Option<Box<dyn Any + Send>>> {
+            Box::pin(async move {
+                let Op::Db(Db::User(id)) = op else {
+                    return None;
+                };
+                YieldOnce(false).await;
+                Some(Box::new(format!("user {id}")) as Box<dyn Any + Send>)
+            })
+        }
+    }
+
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    struct Lines(Vec<String>);
+
+    impl PartialHandler<Op> for Lines {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Log(Log::Line(line)) = op else {
+                return None;
+            };
+            self.0.push(line.clone());
+            Some(Box::new(()))
+        }
+    }
+
+    struct Unparker(Thread);
+
+    impl Wake for Unparker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(Unparker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[effectful]
+    fn greet(id: u32) -> String {
+        let name: String = perform!(Db::User(id));
+        let _: () = perform!(Log::Line(format!("greeting {name}")));
+        format!("hello {name}")
+    }
+
+    #[test]
+    fn test_run_async_combines_async_and_sync_handlers() {
+        let greeting = block_on(run_async(greet(7), SlowDb, Lines(Vec::new())));
+        assert_eq!(greeting.unwrap(), "hello user 7");
+    }
+
+    #[test]
+    fn test_effectful_run_async_uses_only_the_async_handler() {
+        #[effectful]
+        fn lookup(id: u32) -> String {
+            perform!(Db::User(id))
+        }
+
+        assert_eq!(block_on(lookup(3).run_async(SlowDb)).unwrap(), "user 3");
+        let err = block_on(greet(3).run_async(SlowDb)).unwrap_err();
+        assert!(matches!(
+            err,
+            EffectError::Unhandled(UnhandledOp { op: Op::Log(_), .. })
+        ));
+    }
+
+    #[test]
+    fn test_run_async_reports_unhandled_operations() {
+        struct Nothing;
+
+        impl PartialHandler<Op> for Nothing {
+            fn maybe_handle(&mut self, _op: &Op) -> Option<Box<dyn Any + Send>> {
+                None
+            }
+        }
+
+        let err = block_on(run_async(greet(1), SlowDb, Nothing)).unwrap_err();
+        assert!(matches!(
+            err,
+            EffectError::Unhandled(UnhandledOp { op: Op::Log(_), .. })
+        ));
+    }
+}
@@ -0,0 +1,253 @@
+//! Handlers whose operations can fail.
+//!
+//! A [`Handler`] answers every operation, so a handler backed by I/O has to
+//! put its failures in the reply payloads or panic. A [`TryHandler`] returns a
+//! `Result` instead, and [`Effectful::run_fallible`] stops the computation at
+//! the first operation it fails, returning the error as a [`HandlerError`]:
+//!
+//! ```rust,ignore
+//! use algae::fallible::{HandlerError, TryHandler};
+//!
+//! struct Files;
+//!
+//! impl TryHandler<Op> for Files {
+//!     type Error = std::io::Error;
+//!
+//!     fn try_handle(&mut self, op: &Op) -> Result<Box<dyn Any + Send>, std::io::Error> {
+//!         match op {
+//!             Op::File(File::Read(path)) => Ok(Box::new(std::fs::read_to_string(path)?)),
+//!         }
+//!     }
+//! }
+//!
+//! match load_config().run_fallible(Files) {
+//!     Ok(config) => start(config),
+//!     Err(HandlerError { error, location }) => eprintln!("{location:?}: {error}"),
+//! }
+//! ```
+//!
+//! Under the hood the failure is an [`Abort`] carrying the `HandlerError`, so
+//! [`Fallible`] also runs a `TryHandler` in handler chains, where the error
+//! comes back from `run` as [`EffectError::Aborted`].
+
+use crate::{
+    perform_location, Abort, EffectError, Effectful, Handled, Handler, IntoVecHandler,
+    PartialHandler, VecHandler,
+};
+use alloc::boxed::Box;
+use core::{any::Any, fmt, panic::Location};
+
+/// A handler that answers each operation or fails it with an error.
+pub trait TryHandler<Op> {
+    /// The error operations fail with.
+    type Error;
+
+    /// Processes an operation, returning its reply or the error it failed
+    /// with.
+    fn try_handle(&mut self, op: &Op) -> Result<Box<dyn Any + Send>, Self::Error>;
+}
+
+/// The error a [`TryHandler`] failed an operation with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerError<E> {
+    /// The error returned by the handler.
+    pub error: E,
+    /// Where the failed operation was performed, if the handler ran inside a
+    /// dispatch.
+    pub location: Option<&'static Location<'static>>,
+}
+
+impl<E: fmt::Display> fmt::Display for HandlerError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(location) => write!(f, "handler failed at {location}: {}", self.error),
+            None => write!(f, "handler failed: {}", self.error),
+        }
+    }
+}
+
+impl<E> core::error::Error for HandlerError<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Runs a [`TryHandler`] as an ordinary [`Handler`] and [`PartialHandler`],
+/// replying to failed operations with an [`Abort`] carrying the
+/// [`HandlerError`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fallible<H>(pub H);
+
+impl<Op, H> Handler<Op> for Fallible<H>
+where
+    H: TryHandler<Op>,
+    H::Error: fmt::Debug + Send + 'static,
+{
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        self.0.try_handle(op).unwrap_or_else(|error| {
+            Abort::boxed(HandlerError {
+                error,
+                location: perform_location(),
+            })
+        })
+    }
+}
+
+impl<Op, H> PartialHandler<Op> for Fallible<H>
+where
+    H: TryHandler<Op>,
+    H::Error: fmt::Debug + Send + 'static,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        Some(self.handle(op))
+    }
+}
+
+impl<Op, H> IntoVecHandler<Op> for Fallible<H>
+where
+    Op: 'static,
+    H: TryHandler<Op> + Send + 'static,
+    H::Error: fmt::Debug + Send + 'static,
+{
+    fn into_vec_handler(self) -> VecHandler<Op> {
+        let mut vec = VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+impl<R, Op: 'static> Effectful<R, Op> {
+    /// Executes the computation with the fallible handler `h`, stopping at
+    /// the first operation it fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics like [`run_with`](Effectful::run_with) if the handler aborts
+    /// the computation with an [`Abort`] of its own.
+    pub fn run_fallible<H>(self, h: H) -> Result<R, HandlerError<H::Error>>
+    where
+        H: TryHandler<Op>,
+        H::Error: fmt::Debug + Send + 'static,
+    {
+        match self.try_run_with(Fallible(h)) {
+            Ok(result) => Ok(result),
+            Err(EffectError::Aborted(abort)) => match abort.downcast() {
+                Ok(failure) => Err(failure),
+                Err(abort) => panic!("Effectful computation aborted: {abort}"),
+            },
+            Err(EffectError::Unhandled(_)) => {
+                unreachable!("fallible handlers answer every operation")
+            }
+            Err(EffectError::ReplyMismatch(error)) => panic!("{error}"),
+        }
+    }
+}
+
+impl<R, Op: 'static, H> Handled<R, Op, H>
+where
+    H: TryHandler<Op>,
+    H::Error: fmt::Debug + Send + 'static,
+{
+    /// Executes the computation with its fallible handler; see
+    /// [`Effectful::run_fallible`].
+    pub fn run_fallible(self) -> Result<R, HandlerError<H::Error>> {
+        let (computation, h) = self.into_parts();
+        computation.run_fallible(h)
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        Disk::Read (String) -> String;
+        Disk::Crash -> ();
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct NotFound(String);
+
+    impl fmt::Display for NotFound {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} not found", self.0)
+        }
+    }
+
+    impl std::error::Error for NotFound {}
+
+    /// Reads the files it has; aborts on `Disk::Crash`.
+    struct Files(Vec<(&'static str, &'static str)>);
+
+    impl TryHandler<Op> for Files {
+        type Error = NotFound;
+
+        fn try_handle(&mut self, op: &Op) -> Result<Box<dyn Any + Send>, NotFound> {
+            match op {
+                Op::Disk(Disk::Read(path)) => match self.0.iter().find(|(p, _)| p == path) {
+                    Some((_, text)) => Ok(Box::new(text.to_string())),
+                    None => Err(NotFound(path.clone())),
+                },
+                Op::Disk(Disk::Crash) => Ok(Abort::boxed("crashed")),
+            }
+        }
+    }
+
+    #[effectful]
+    fn concat(paths: Vec<&'static str>) -> String {
+        let mut text = String::new();
+        for path in paths {
+            let part = perform!(Disk::Read(path.to_string()));
+            text += &part;
+        }
+        text
+    }
+
+    fn files() -> Files {
+        Files(vec![("a", "1"), ("b", "2")])
+    }
+
+    #[test]
+    fn test_failed_operations_stop_the_run() {
+        assert_eq!(concat(vec!["a", "b"]).run_fallible(files()).unwrap(), "12");
+
+        let failure = concat(vec!["a", "c", "b"])
+            .handle(files())
+            .run_fallible()
+            .unwrap_err();
+        assert_eq!(failure.error, NotFound("c".into()));
+        assert!(failure.location.unwrap().file().ends_with("fallible.rs"));
+        assert!(failure.to_string().ends_with(": c not found"));
+        let source = std::error::Error::source(&failure).unwrap();
+        assert_eq!(source.to_string(), "c not found");
+    }
+
+    #[test]
+    fn test_fallible_handlers_join_chains() {
+        let result = concat(vec!["x"])
+            .begin_chain()
+            .handle(Fallible(files()))
+            .run();
+        let Err(EffectError::Aborted(abort)) = result else {
+            panic!("expected an abort, got {result:?}");
+        };
+        let failure = abort.downcast::<HandlerError<NotFound>>().unwrap();
+        assert_eq!(failure.error, NotFound("x".into()));
+    }
+
+    #[test]
+    #[should_panic(expected = "aborted: \"crashed\"")]
+    fn test_other_aborts_panic() {
+        #[effectful]
+        fn crash() {
+            perform!(Disk::Crash)
+        }
+
+        let _ = crash().run_fallible(files());
+    }
+}
@@ -0,0 +1,325 @@
+//! Effectful computations that also await futures.
+//!
+//! An [`Effectful`](crate::Effectful) is a coroutine and cannot `.await`. An
+//! [`EffectFuture`] is a future instead, so its body can await I/O between
+//! two operations. It is usually written as an `async fn` with
+//! `#[effectful]`, where `perform!` and `.await` mix freely:
+//!
+//! ```rust,ignore
+//! use algae::prelude::*;
+//!
+//! effect! {
+//!     Db::User (u32) -> String;
+//!     Log::Line (String) -> ();
+//! }
+//!
+//! #[effectful]
+//! async fn profile(id: u32) -> String {
+//!     let name: String = perform!(Db::User(id));
+//!     let avatar = reqwest::get(avatar_url(&name)).await?.bytes().await?;
+//!     let _: () = perform!(Log::Line(format!("{name}: {} bytes", avatar.len())));
+//!     name
+//! }
+//!
+//! let name = profile(7).run(UsersDb(pool), MemoryLog::new()).await?;
+//! ```
+//!
+//! Performing an operation suspends the future until its driver has
+//! answered it, with an [`AsyncHandler`] or an ordinary [`PartialHandler`]
+//! as in [`run_async`](crate::executor::run_async). Other futures the body
+//! awaits are polled by the executor as usual.
+
+use crate::{
+    dispatch_effect,
+    executor::{AsyncHandler, Declines},
+    Effect, EffectError, PartialHandler, Reply,
+};
+use std::{
+    future::{poll_fn, Future},
+    ops::CoroutineState,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+/// What passes between an [`EffectFuture`] and its driver.
+enum Slot<Op: 'static> {
+    Empty,
+    /// An operation waiting for its reply.
+    Performed(Effect<Op>),
+    /// The reply to the last operation, waiting to be picked up.
+    Replied(Reply),
+}
+
+type Shared<Op> = Arc<Mutex<Slot<Op>>>;
+
+/// An effectful computation that may await futures, returning `R`.
+///
+/// # Type Parameters
+///
+/// * `R` - The return type of the computation
+/// * `Op` - The type of effects that can be performed
+pub struct EffectFuture<R, Op: 'static> {
+    future: Pin<Box<dyn Future<Output = R> + Send>>,
+    slot: Shared<Op>,
+}
+
+/// The body's side of an [`EffectFuture`], through which it performs
+/// operations.
+pub struct Effects<Op: 'static> {
+    slot: Shared<Op>,
+}
+
+impl<Op> Effects<Op> {
+    /// Hands `eff` to the driver; the future resolves to the reply.
+    ///
+    /// This is called by `perform!` in `#[effectful] async fn` bodies.
+    pub fn perform(&self, eff: Effect<Op>) -> Perform<Op> {
+        Perform {
+            slot: self.slot.clone(),
+            eff: Some(eff),
+        }
+    }
+}
+
+/// The future of one performed operation, see [`Effects::perform`].
+pub struct Perform<Op: 'static> {
+    slot: Shared<Op>,
+    eff: Option<Effect<Op>>,
+}
+
+// Nothing in `Perform` is pinned.
+impl<Op> Unpin for Perform<Op> {}
+
+impl<Op> Future for Perform<Op> {
+    type Output = Option<Reply>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Reply>> {
+        let this = self.get_mut();
+        let mut slot = this.slot.lock().unwrap();
+        if let Some(eff) = this.eff.take() {
+            // No wake-up: the driver looks at the slot whenever the
+            // computation is pending, and polls again once it has replied.
+            *slot = Slot::Performed(eff);
+            return Poll::Pending;
+        }
+        match std::mem::replace(&mut *slot, Slot::Empty) {
+            Slot::Replied(reply) => Poll::Ready(Some(reply)),
+            other => {
+                *slot = other;
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<R, Op: 'static> EffectFuture<R, Op> {
+    /// Creates a computation from the async body built by `body`, which
+    /// performs operations through the [`Effects`] it is given.
+    ///
+    /// This is typically called by `#[effectful]` on an `async fn`.
+    pub fn new<F, Fut>(body: F) -> Self
+    where
+        F: FnOnce(Effects<Op>) -> Fut,
+        Fut: Future<Output = R> + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot::Empty));
+        let future = body(Effects { slot: slot.clone() });
+        Self {
+            future: Box::pin(future),
+            slot,
+        }
+    }
+
+    /// Runs the computation to its end, offering each operation to `async_h`
+    /// first and passing the ones it declines to `other`.
+    ///
+    /// Fails like [`Effectful::try_run_with`](crate::Effectful::try_run_with)
+    /// if neither handler accepts an operation or one of them aborts.
+    pub async fn run<A, H>(mut self, mut async_h: A, mut other: H) -> Result<R, EffectError<Op>>
+    where
+        A: AsyncHandler<Op>,
+        H: PartialHandler<Op>,
+    {
+        loop {
+            let eff = match self.next().await {
+                CoroutineState::Yielded(eff) => eff,
+                CoroutineState::Complete(result) => return Ok(result),
+            };
+            let answered = async_h.maybe_handle(&eff.op).await;
+            let reply = match answered {
+                Some(answer) => {
+                    let mut answer = Some(answer);
+                    dispatch_effect(eff, &mut |_| answer.take())?
+                }
+                None => dispatch_effect(eff, &mut |op| other.maybe_handle(op))?,
+            };
+            *self.slot.lock().unwrap() = Slot::Replied(reply);
+        }
+    }
+
+    /// Runs the computation to its end with ordinary handlers only.
+    pub async fn run_with<H: PartialHandler<Op>>(self, h: H) -> Result<R, EffectError<Op>> {
+        self.run(Declines, h).await
+    }
+
+    /// Polls the body until it finishes or performs an operation.
+    fn next(&mut self) -> impl Future<Output = CoroutineState<Effect<Op>, R>> + '_ {
+        poll_fn(|cx| {
+            if let Poll::Ready(result) = self.future.as_mut().poll(cx) {
+                return Poll::Ready(CoroutineState::Complete(result));
+            }
+            let mut slot = self.slot.lock().unwrap();
+            match std::mem::replace(&mut *slot, Slot::Empty) {
+                Slot::Performed(eff) => Poll::Ready(CoroutineState::Yielded(eff)),
+                other => {
+                    *slot = other;
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+impl<R, Op: 'static> std::fmt::Debug for EffectFuture<R, Op> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EffectFuture").finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::executor::BoxFuture;
+    use algae::prelude::*;
+    use std::{
+        any::Any,
+        sync::Arc,
+        task::{Wake, Waker},
+        thread::{self, Thread},
+    };
+
+    effect! {
+        Db::User (u32) -> String;
+        Log::Line (String) -> ();
+    }
+
+    /// Replies to `Db::User` after yielding to the executor once.
+    struct SlowDb;
+
+    impl AsyncHandler<Op> for SlowDb {
+        fn maybe_handle<'a>(
+            &'a mut self,
+            op: &'a Op,
+        ) -> BoxFuture<'a, Option<Box<dyn Any + Send>>> {
+            Box::pin(async move {
+                let Op::Db(Db::User(id)) = op else {
+                    return None;
+                };
+                YieldOnce(false).await;
+                Some(Box::new(format!("user {id}")) as Box<dyn Any + Send>)
+            })
+        }
+    }
+
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    struct Lines(Vec<String>);
+
+    impl PartialHandler<Op> for Lines {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Log(Log::Line(line)) = op else {
+                return None;
+            };
+            self.0.push(line.clone());
+            Some(Box::new(()))
+        }
+    }
+
+    struct Unparker(Thread);
+
+    impl Wake for Unparker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(Unparker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[effectful]
+    async fn greet(id: u32) -> String {
+        let name: String = perform!(Db::User(id));
+        YieldOnce(false).await;
+        let _: () = perform!(Log::Line(format!("greeting {name}")));
+        format!("hello {name}")
+    }
+
+    #[test]
+    fn test_async_bodies_interleave_perform_and_await() {
+        let greeting = block_on(greet(7).run(SlowDb, Lines(Vec::new())));
+        assert_eq!(greeting.unwrap(), "hello user 7");
+    }
+
+    #[derive(Clone)]
+    struct Greeter {
+        prefix: &'static str,
+    }
+
+    impl Greeter {
+        #[effectful]
+        async fn greet(&self, id: u32) -> String {
+            let name: String = perform!(Db::User(id));
+            YieldOnce(false).await;
+            format!("{} {name}", self.prefix)
+        }
+    }
+
+    #[test]
+    fn test_async_methods_capture_their_receiver() {
+        let greeter = Greeter { prefix: "hi" };
+        let greeting = block_on(greeter.greet(3).run(SlowDb, Lines(Vec::new())));
+        assert_eq!(greeting.unwrap(), "hi user 3");
+    }
+
+    #[test]
+    fn test_run_with_answers_with_ordinary_handlers() {
+        #[effectful]
+        async fn log_twice() {
+            for i in 0..2 {
+                let _: () = perform!(Log::Line(format!("line {i}")));
+                YieldOnce(false).await;
+            }
+        }
+
+        block_on(log_twice().run_with(Lines(Vec::new()))).unwrap();
+        let err = block_on(greet(1).run_with(Lines(Vec::new()))).unwrap_err();
+        assert!(matches!(
+            err,
+            EffectError::Unhandled(UnhandledOp { op: Op::Db(_), .. })
+        ));
+    }
+}
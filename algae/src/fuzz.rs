@@ -0,0 +1,338 @@
+//! Fuzzing computations with made-up replies.
+//!
+//! Business logic tends to be written against the replies its handlers
+//! usually give: a quota that is never zero, a list that is never empty. An
+//! `effect!` block with a `fuzz;` header implements [`FuzzReply`], so each
+//! operation can make up an arbitrary reply of its declared type, and a
+//! [`FuzzHandler`] answers a computation with such replies. [`fuzz`] runs a
+//! test once per seed and reports the first run that panics, with the seed
+//! and the replies that led to the panic:
+//!
+//! ```rust,ignore
+//! effect! {
+//!     fuzz;
+//!     Api::Quota -> u32;
+//!     Api::Fetch (u32) -> Vec<u8>;
+//! }
+//!
+//! #[effectful]
+//! fn average_page_size() -> usize {
+//!     let pages: u32 = perform!(Api::Quota);
+//!     let mut total = 0;
+//!     for page in 0..pages {
+//!         let bytes: Vec<u8> = perform!(Api::Fetch(page));
+//!         total += bytes.len();
+//!     }
+//!     total / pages as usize // panics when the quota is 0
+//! }
+//!
+//! let failure = fuzz(0..200, |fuzzer| average_page_size().try_run_with(fuzzer)).unwrap_err();
+//! println!("{failure}");
+//! ```
+//!
+//! Only the operations a [`fuzz_when`](FuzzHandler::fuzz_when) predicate
+//! picks get made-up replies; chain real handlers after the fuzzer for the
+//! others.
+
+use crate::PartialHandler;
+use proptest::{
+    arbitrary::{any, Arbitrary},
+    strategy::{Strategy, ValueTree},
+    test_runner::{Config, RngAlgorithm, TestRng, TestRunner},
+};
+use std::{
+    any::Any,
+    error::Error,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
+
+type FuzzWhenFn<Op> = Box<dyn Fn(&Op) -> bool + Send>;
+
+/// Makes up replies of an operation's declared type, implemented by
+/// `effect!` with a `fuzz;` header.
+pub trait FuzzReply {
+    /// An arbitrary reply to `self`, drawn from `runner`.
+    fn fuzz_reply(&self, runner: &mut TestRunner) -> FuzzedReply;
+}
+
+/// A made-up reply, and how it looks for failure reports.
+pub struct FuzzedReply {
+    reply: Box<dyn Any + Send>,
+    shown: String,
+}
+
+impl FuzzedReply {
+    /// An arbitrary value of type `R`, drawn from `runner`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `R`'s strategy fails to generate a value, e.g. because its
+    /// filters reject too many.
+    pub fn arbitrary<R: Arbitrary + Send + 'static>(runner: &mut TestRunner) -> Self {
+        let value = any::<R>()
+            .new_tree(runner)
+            .unwrap_or_else(|reason| panic!("cannot generate a reply: {reason}"))
+            .current();
+        Self {
+            shown: format!("{value:?}"),
+            reply: Box::new(value),
+        }
+    }
+
+    /// The reply, as its `Debug` impl shows it.
+    pub fn shown(&self) -> &str {
+        &self.shown
+    }
+
+    pub fn into_reply(self) -> Box<dyn Any + Send> {
+        self.reply
+    }
+}
+
+impl fmt::Debug for FuzzedReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FuzzedReply").field(&self.shown).finish()
+    }
+}
+
+/// The operations a [`FuzzHandler`] answered, each with the reply it made
+/// up, as `op -> reply` lines.
+///
+/// Clones share the same trace.
+#[derive(Debug, Clone, Default)]
+pub struct FuzzTrace {
+    entries: Arc<Mutex<Vec<String>>>,
+}
+
+impl FuzzTrace {
+    pub fn entries(&self) -> Vec<String> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+/// Answers operations with arbitrary replies of their declared types, drawn
+/// from a seeded generator.
+pub struct FuzzHandler<Op> {
+    runner: TestRunner,
+    fuzz_when: Option<FuzzWhenFn<Op>>,
+    trace: FuzzTrace,
+}
+
+impl<Op> FuzzHandler<Op> {
+    /// A handler drawing replies from `seed`; the same seed makes up the
+    /// same replies to the same operations.
+    pub fn new(seed: u64) -> Self {
+        let mut bytes = [0; 32];
+        for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+            chunk.copy_from_slice(&crate::splitmix64(seed ^ i as u64).to_le_bytes());
+        }
+        Self {
+            runner: TestRunner::new_with_rng(
+                Config::default(),
+                TestRng::from_seed(RngAlgorithm::ChaCha, &bytes),
+            ),
+            fuzz_when: None,
+            trace: FuzzTrace::default(),
+        }
+    }
+
+    /// Only makes up replies to the operations `f` returns `true` for, and
+    /// declines the others.
+    pub fn fuzz_when<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Op) -> bool + Send + 'static,
+    {
+        self.fuzz_when = Some(Box::new(f));
+        self
+    }
+
+    /// A handle on the replies made up so far.
+    pub fn trace(&self) -> FuzzTrace {
+        self.trace.clone()
+    }
+}
+
+impl<Op> fmt::Debug for FuzzHandler<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FuzzHandler")
+            .field("trace", &self.trace)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Op: FuzzReply + fmt::Debug> PartialHandler<Op> for FuzzHandler<Op> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        if self.fuzz_when.as_ref().is_some_and(|f| !f(op)) {
+            return None;
+        }
+        let reply = op.fuzz_reply(&mut self.runner);
+        self.trace
+            .entries
+            .lock()
+            .unwrap()
+            .push(format!("{op:?} -> {}", reply.shown()));
+        Some(reply.into_reply())
+    }
+}
+
+impl<Op: FuzzReply + fmt::Debug + 'static> crate::IntoVecHandler<Op> for FuzzHandler<Op> {
+    fn into_vec_handler(self) -> crate::VecHandler<Op> {
+        let mut vec = crate::VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+/// A fuzzed run that panicked, returned by [`fuzz`].
+#[derive(Debug, Clone)]
+pub struct FuzzFailure {
+    /// The seed of the run; [`FuzzHandler::new`] with it makes up the same
+    /// replies again.
+    pub seed: u64,
+    /// The panic message.
+    pub message: String,
+    /// The made-up replies, in order, as `op -> reply` lines.
+    pub replies: Vec<String>,
+}
+
+impl fmt::Display for FuzzFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fuzzed run with seed {} panicked: {}",
+            self.seed, self.message
+        )?;
+        for reply in &self.replies {
+            write!(f, "\n  {reply}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for FuzzFailure {}
+
+/// Runs `test` with a [`FuzzHandler`] for each of `seeds`, stopping at the
+/// first run that panics.
+///
+/// Runs that return, whatever they return, pass: a computation ending with
+/// an error is not a failure, a panic in its logic is.
+pub fn fuzz<Op, T, F>(seeds: impl IntoIterator<Item = u64>, mut test: F) -> Result<(), FuzzFailure>
+where
+    F: FnMut(FuzzHandler<Op>) -> T,
+{
+    for seed in seeds {
+        let fuzzer = FuzzHandler::new(seed);
+        let trace = fuzzer.trace();
+        if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| test(fuzzer))) {
+            let message = match panic.downcast::<String>() {
+                Ok(message) => *message,
+                Err(panic) => panic
+                    .downcast_ref::<&str>()
+                    .map_or_else(|| "<non-string panic>".to_string(), |m| m.to_string()),
+            };
+            return Err(FuzzFailure {
+                seed,
+                message,
+                replies: trace.entries(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        fuzz;
+        Api::Quota -> u8;
+        Api::Fetch (u8) -> Vec<u8>;
+        Cache::Hit (u8) -> bool;
+    }
+
+    #[effectful]
+    fn average_page_size() -> usize {
+        let pages: u8 = perform!(Api::Quota);
+        let mut total = 0;
+        for page in 0..pages {
+            let bytes: Vec<u8> = perform!(Api::Fetch(page));
+            total += bytes.len();
+        }
+        total / pages as usize
+    }
+
+    #[effectful]
+    fn checked_average() -> usize {
+        let pages: u8 = perform!(Api::Quota);
+        let mut total = 0;
+        for page in 0..pages.min(3) {
+            let cached: bool = perform!(Cache::Hit(page));
+            if !cached {
+                let bytes: Vec<u8> = perform!(Api::Fetch(page));
+                total += bytes.len();
+            }
+        }
+        total.checked_div(pages as usize).unwrap_or(0)
+    }
+
+    /// Every page is cached.
+    struct AlwaysCached;
+
+    impl PartialHandler<Op> for AlwaysCached {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Cache(Cache::Hit(_)) => Some(Box::new(true)),
+                _ => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(AlwaysCached, Op);
+
+    #[test]
+    fn test_replies_are_reproducible_per_seed() {
+        let run = |seed| {
+            let fuzzer = FuzzHandler::<Op>::new(seed);
+            let trace = fuzzer.trace();
+            checked_average().try_run_with(fuzzer).unwrap();
+            trace.entries()
+        };
+        assert_eq!(run(1), run(1));
+        assert!((0..10).any(|seed| run(seed) != run(1)));
+        assert!(run(1)[0].starts_with("Api(Quota) -> "));
+    }
+
+    #[test]
+    fn test_fuzzing_finds_unexpected_replies() {
+        let failure = fuzz(0..500, |fuzzer| average_page_size().try_run_with(fuzzer)).unwrap_err();
+        assert!(failure.message.contains("divide by zero"), "{failure}");
+        assert_eq!(failure.replies, ["Api(Quota) -> 0"]);
+        assert!(failure
+            .to_string()
+            .starts_with(&format!("fuzzed run with seed {} panicked", failure.seed)));
+
+        assert!(fuzz(0..200, |fuzzer| checked_average().try_run_with(fuzzer)).is_ok());
+    }
+
+    #[test]
+    fn test_real_handlers_answer_what_is_not_fuzzed() {
+        let fuzzer = FuzzHandler::new(3).fuzz_when(|op| matches!(op, Op::Api(_)));
+        let trace = fuzzer.trace();
+        let result = checked_average()
+            .begin_chain()
+            .handle(fuzzer)
+            .handle(AlwaysCached)
+            .run()
+            .unwrap();
+        assert_eq!(result, 0);
+        assert!(trace
+            .entries()
+            .iter()
+            .all(|entry| entry.starts_with("Api(Quota)")));
+    }
+}
@@ -0,0 +1,395 @@
+//! Higher-order effects: operations that carry computations.
+//!
+//! An operation may take an effectful computation as its payload, wrapped in
+//! a [`Computation`], to express spawning, transactions or bracketing as
+//! effects:
+//!
+//! ```rust,ignore
+//! effect! {
+//!     Tx::Atomically (Computation<u64, Op>) -> u64;
+//!     Tx::Begin -> ();
+//!     Tx::Commit -> ();
+//!     Bank::Withdraw (u64) -> u64;
+//! }
+//!
+//! #[effectful]
+//! fn pay(amount: u64) -> u64 {
+//!     perform!(Tx::Atomically(Computation::new(withdraw(amount))))
+//! }
+//! ```
+//!
+//! There are two ways to answer such an operation:
+//!
+//! - A handler [`take`](Computation::take)s the computation and drives it
+//!   itself, e.g. by queueing it as a task and replying with its id.
+//! - [`Effectful::elaborate`] rewrites the operation into a computation that
+//!   runs in its place, in the same run: the nested computation's operations
+//!   reach the handlers of the enclosing one, and its result is the reply.
+//!   Transactions and brackets elaborate into the nested computation between
+//!   two first-order operations:
+//!
+//! ```rust,ignore
+//! #[effectful]
+//! fn atomically(body: Effectful<u64, Op>) -> u64 {
+//!     let _: () = perform!(Tx::Begin);
+//!     let result = embed!(body);
+//!     let _: () = perform!(Tx::Commit);
+//!     result
+//! }
+//!
+//! let receipt = pay(30)
+//!     .elaborate(|op| match op {
+//!         Op::Tx(Tx::Atomically(body)) => {
+//!             let body = body.take()?;
+//!             Some(Elaborated::new(atomically(body)))
+//!         }
+//!         _ => None,
+//!     })
+//!     .handle(Ledger::default())
+//!     .run_or_panic();
+//! ```
+
+use crate::{Effectful, Reply};
+use std::{
+    any::Any,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::CoroutineState,
+    sync::{Arc, Mutex},
+};
+
+/// An effectful computation carried by an operation.
+///
+/// Operations are `Clone` and `PartialEq`, which computations are not, so a
+/// computation is shared between the clones of an operation and taken out by
+/// whoever runs it. Clones are equal, and hash alike, if they share the same
+/// computation.
+pub struct Computation<R, Op: 'static> {
+    inner: Arc<Mutex<Option<Effectful<R, Op>>>>,
+}
+
+impl<R, Op: 'static> Computation<R, Op> {
+    pub fn new(computation: Effectful<R, Op>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Some(computation))),
+        }
+    }
+
+    /// Takes the computation out to run it; `None` if it was taken already,
+    /// e.g. through a clone of the operation.
+    pub fn take(&self) -> Option<Effectful<R, Op>> {
+        self.inner.lock().unwrap().take()
+    }
+
+    /// Whether the computation has been taken out.
+    pub fn is_taken(&self) -> bool {
+        self.inner.lock().unwrap().is_none()
+    }
+}
+
+impl<R, Op: 'static> From<Effectful<R, Op>> for Computation<R, Op> {
+    fn from(computation: Effectful<R, Op>) -> Self {
+        Self::new(computation)
+    }
+}
+
+impl<R, Op: 'static> Clone for Computation<R, Op> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<R, Op: 'static> PartialEq for Computation<R, Op> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl<R, Op: 'static> Eq for Computation<R, Op> {}
+
+impl<R, Op: 'static> Hash for Computation<R, Op> {
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        Arc::as_ptr(&self.inner).hash(state);
+    }
+}
+
+impl<R, Op: 'static> fmt::Debug for Computation<R, Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.is_taken() { "taken" } else { "pending" };
+        write!(f, "Computation({state})")
+    }
+}
+
+/// A computation answering an operation in place, from the function given to
+/// [`Effectful::elaborate`].
+pub struct Elaborated<Op: 'static> {
+    computation: Effectful<Reply, Op>,
+}
+
+impl<Op: 'static> Elaborated<Op> {
+    /// Runs `computation` in place of the operation, replying with its
+    /// result, which must be of the type the operation was declared with.
+    pub fn new<R>(computation: Effectful<R, Op>) -> Self
+    where
+        R: Any + Send,
+        Op: Send,
+    {
+        Self {
+            computation: computation.erased(),
+        }
+    }
+
+    /// Replies with `value` without performing anything.
+    pub fn reply<R: Any + Send>(value: R) -> Self
+    where
+        Op: Send,
+    {
+        Self {
+            computation: Effectful::new(
+                #[coroutine]
+                move |_: Option<Reply>| Reply::new(value),
+            ),
+        }
+    }
+}
+
+impl<Op: 'static> fmt::Debug for Elaborated<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Elaborated")
+            .field(&self.computation)
+            .finish()
+    }
+}
+
+impl<R: Any + Send, Op: Send + 'static> Effectful<R, Op> {
+    /// The computation with its result wrapped in a [`Reply`], so
+    /// computations with different result types can be kept together.
+    pub(crate) fn erased(self) -> Effectful<Reply, Op> {
+        let mut computation = self;
+        Effectful::new(
+            #[coroutine]
+            move |mut reply: Option<Reply>| loop {
+                match computation.step(reply.take()) {
+                    CoroutineState::Yielded(eff) => reply = yield eff,
+                    CoroutineState::Complete(result) => return Reply::new(result),
+                }
+            },
+        )
+    }
+}
+
+impl<R, Op: 'static> Effectful<R, Op> {
+    /// Runs the computation, answering the operations `f` elaborates with the
+    /// computations it returns, in place.
+    ///
+    /// An elaborated operation does not reach the handler: its computation
+    /// runs instead, as part of this one, with its operations handled like
+    /// those of this computation, elaborated ones included, and its result is
+    /// the reply. Operations `f` returns `None` for, and batches, pass
+    /// through unchanged.
+    pub fn elaborate<F>(self, mut f: F) -> Effectful<R, Op>
+    where
+        F: FnMut(&Op) -> Option<Elaborated<Op>> + Send + 'static,
+        R: Send + 'static,
+        Op: Send,
+    {
+        let mut inner = self;
+        Effectful::new(
+            #[coroutine]
+            move |_reply: Option<Reply>| {
+                // The elaborations being run, innermost last
+                let mut nested: Vec<Effectful<Reply, Op>> = Vec::new();
+                let mut reply = None;
+                loop {
+                    let step = match nested.last_mut() {
+                        Some(elaboration) => elaboration.step(reply.take()),
+                        None => match inner.step(reply.take()) {
+                            CoroutineState::Yielded(eff) => CoroutineState::Yielded(eff),
+                            CoroutineState::Complete(result) => return result,
+                        },
+                    };
+                    let eff = match step {
+                        CoroutineState::Yielded(eff) => eff,
+                        CoroutineState::Complete(answer) => {
+                            nested.pop();
+                            reply = Some(answer);
+                            continue;
+                        }
+                    };
+                    match (!eff.is_batch()).then(|| f(&eff.op)).flatten() {
+                        Some(elaborated) => nested.push(elaborated.computation),
+                        None => reply = yield eff,
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::collections::VecDeque;
+
+    effect! {
+        Tx::Atomically (Computation<u64, Op>) -> u64;
+        Tx::Begin -> ();
+        Tx::Commit -> ();
+        Tasks::Spawn (Computation<u64, Op>) -> usize;
+        Bank::Withdraw (u64) -> u64;
+    }
+
+    /// A balance, and a log of the operations it answered.
+    struct Ledger {
+        balance: u64,
+        log: Vec<String>,
+    }
+
+    impl Ledger {
+        fn new(balance: u64) -> Self {
+            Self {
+                balance,
+                log: Vec::new(),
+            }
+        }
+    }
+
+    impl PartialHandler<Op> for Ledger {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let reply: Box<dyn Any + Send> = match op {
+                Op::Tx(Tx::Begin) | Op::Tx(Tx::Commit) => Box::new(()),
+                Op::Bank(Bank::Withdraw(amount)) => {
+                    self.balance -= amount;
+                    Box::new(self.balance)
+                }
+                _ => return None,
+            };
+            self.log.push(format!("{op:?}"));
+            Some(reply)
+        }
+    }
+
+    algae::impl_into_vec_handler!(Ledger, Op);
+
+    #[effectful]
+    fn withdraw_twice(amount: u64) -> u64 {
+        let _: u64 = perform!(Bank::Withdraw(amount));
+        perform!(Bank::Withdraw(amount))
+    }
+
+    #[effectful]
+    fn pay(amount: u64) -> u64 {
+        perform!(Tx::Atomically(Computation::new(withdraw_twice(amount))))
+    }
+
+    #[effectful]
+    fn atomically(body: Effectful<u64, Op>) -> u64 {
+        let _: () = perform!(Tx::Begin);
+        let result = embed!(body);
+        let _: () = perform!(Tx::Commit);
+        result
+    }
+
+    fn transactions(op: &Op) -> Option<Elaborated<Op>> {
+        match op {
+            Op::Tx(Tx::Atomically(body)) => Some(Elaborated::new(atomically(body.take()?))),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_elaborated_operations_run_in_place() {
+        let (balance, ledger) = pay(10)
+            .elaborate(transactions)
+            .try_run_with_handler(Ledger::new(100));
+        assert_eq!(balance.unwrap(), 80);
+        assert_eq!(
+            ledger.log,
+            [
+                "Tx(Begin)",
+                "Bank(Withdraw(10))",
+                "Bank(Withdraw(10))",
+                "Tx(Commit)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_computations_are_elaborated_too() {
+        #[effectful]
+        fn pay_in_pay() -> u64 {
+            perform!(Tx::Atomically(Computation::new(pay(5))))
+        }
+
+        let (balance, ledger) = pay_in_pay()
+            .elaborate(transactions)
+            .try_run_with_handler(Ledger::new(100));
+        assert_eq!(balance.unwrap(), 90);
+        assert_eq!(ledger.log.iter().filter(|op| *op == "Tx(Begin)").count(), 2);
+        assert_eq!(ledger.log.last().unwrap(), "Tx(Commit)");
+
+        let unhandled = pay(1).try_run_with(Ledger::new(100)).unwrap_err();
+        assert!(format!("{unhandled:?}").contains("Computation(pending)"));
+    }
+
+    /// Queues spawned computations, to be run by the test.
+    #[derive(Default)]
+    struct Spawner {
+        queued: VecDeque<Effectful<u64, Op>>,
+    }
+
+    impl PartialHandler<Op> for Spawner {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Tasks(Tasks::Spawn(task)) = op else {
+                return None;
+            };
+            self.queued.push_back(task.take()?);
+            Some(Box::new(self.queued.len()))
+        }
+    }
+
+    #[test]
+    fn test_handlers_can_take_computations_to_drive() {
+        #[effectful]
+        fn fan_out() -> usize {
+            let mut spawned = 0;
+            for amount in [1, 2, 3] {
+                spawned = perform!(Tasks::Spawn(Computation::new(withdraw_twice(amount))));
+            }
+            spawned
+        }
+
+        let (spawned, mut spawner) = fan_out().try_run_with_handler(Spawner::default());
+        assert_eq!(spawned.unwrap(), 3);
+        let mut session = crate::session::HandlerSession::new(Ledger::new(100));
+        while let Some(task) = spawner.queued.pop_front() {
+            session.try_run(task).unwrap();
+        }
+        assert_eq!(session.handler().balance, 88);
+
+        let op = Op::tasks_spawn(Computation::new(withdraw_twice(1)));
+        let copy = op.clone();
+        assert_eq!(copy, op);
+        assert!(spawner.maybe_handle(&op).is_some());
+        assert!(
+            spawner.maybe_handle(&copy).is_none(),
+            "taken through the clone"
+        );
+        assert_eq!(format!("{copy:?}"), "Tasks(Spawn(Computation(taken)))");
+    }
+
+    #[test]
+    fn test_elaborations_can_reply_directly() {
+        let balance = pay(10)
+            .elaborate(|op| match op {
+                Op::Tx(Tx::Atomically(_)) => Some(Elaborated::reply(7u64)),
+                _ => None,
+            })
+            .try_run_with(Ledger::new(100));
+        assert_eq!(balance.unwrap(), 7);
+    }
+}
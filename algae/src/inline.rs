@@ -0,0 +1,249 @@
+//! Fixed-size reply storage.
+//!
+//! [`InlineReply<N>`] holds one reply of any type in an `N`-byte buffer
+//! inside the value itself, instead of in a `Box`. Whether a type fits is
+//! checked when the code is compiled, not when it runs.
+//!
+//! `effect!` implements [`ReplyBound`] for every family and root enum, giving
+//! the size of the largest reply its operations declare, so the buffer can be
+//! sized to fit exactly the effects in use:
+//!
+//! ```rust,ignore
+//! effect! {
+//!     Sensor::Read (u8) -> u16;
+//!     Sensor::Calibrate -> [i16; 3];
+//! }
+//!
+//! type SensorReply = InlineReply<{ Op::MAX_REPLY_SIZE }>;
+//!
+//! let mut reply = SensorReply::new(512u16);
+//! assert_eq!(reply.take::<u16>(), Ok(512));
+//! ```
+//!
+//! Storing and taking a reply does not allocate. The runtime in this crate
+//! uses the same storage for [`Reply`](crate::Reply): replies of up to
+//! [`INLINE_REPLY_SIZE`] bytes made with [`Reply::new`](crate::Reply::new)
+//! are kept inline instead of being boxed.
+//!
+//! This saves allocations, but does not make the runtime heap-free: it still
+//! boxes computations and larger replies, so the crate needs `alloc`, and
+//! there is no dispatch path for targets without an allocator.
+
+use crate::ReplyError;
+use alloc::{boxed::Box, string::ToString};
+use core::{
+    any::{type_name, Any, TypeId},
+    fmt,
+    mem::{self, MaybeUninit},
+    ptr,
+};
+
+/// The largest alignment a reply stored in an [`InlineReply`] may have.
+pub const MAX_REPLY_ALIGN: usize = 16;
+
+/// The size of the largest reply a [`Reply`](crate::Reply) keeps inline.
+///
+/// Enough for `()`, integers, `bool`s, `Option`s of them and small structs.
+pub const INLINE_REPLY_SIZE: usize = 16;
+
+/// The size of the largest reply the operations of an effect enum declare.
+///
+/// Implemented by `effect!`; root enums take the largest bound of their
+/// families.
+pub trait ReplyBound {
+    const MAX_REPLY_SIZE: usize;
+}
+
+/// The largest of `sizes`, usable in constants.
+#[doc(hidden)]
+pub const fn max_size(sizes: &[usize]) -> usize {
+    let mut max = 0;
+    let mut i = 0;
+    while i < sizes.len() {
+        if sizes[i] > max {
+            max = sizes[i];
+        }
+        i += 1;
+    }
+    max
+}
+
+#[repr(C, align(16))]
+struct Buffer<const N: usize>([MaybeUninit<u8>; N]);
+
+/// A reply stored inline in an `N`-byte buffer.
+///
+/// Like [`Reply`](crate::Reply), the value can be taken once, as the type it
+/// was stored with.
+pub struct InlineReply<const N: usize> {
+    buffer: Buffer<N>,
+    type_id: TypeId,
+    type_name: &'static str,
+    // Drops the stored value; `None` once it has been taken
+    drop: Option<unsafe fn(*mut u8)>,
+    // Moves the stored value into a box
+    into_box: unsafe fn(*mut u8) -> Box<dyn Any + Send>,
+}
+
+// SAFETY: only `Send` values are stored.
+unsafe impl<const N: usize> Send for InlineReply<N> {}
+
+impl<const N: usize> InlineReply<N> {
+    /// Stores `value`.
+    ///
+    /// Fails to compile if `T` is larger than `N` bytes or more aligned than
+    /// [`MAX_REPLY_ALIGN`]:
+    ///
+    /// ```compile_fail
+    /// let reply = algae::inline::InlineReply::<2>::new(0u32);
+    /// ```
+    pub fn new<T: Any + Send>(value: T) -> Self {
+        const {
+            assert!(
+                mem::size_of::<T>() <= N,
+                "the reply type does not fit in this InlineReply"
+            );
+            assert!(
+                mem::align_of::<T>() <= MAX_REPLY_ALIGN,
+                "the reply type is too strictly aligned for an InlineReply"
+            );
+        }
+        // SAFETY: checked above.
+        unsafe { Self::new_unchecked(value) }
+    }
+
+    /// Whether a `T` can be stored.
+    pub(crate) const fn fits<T>() -> bool {
+        mem::size_of::<T>() <= N && mem::align_of::<T>() <= MAX_REPLY_ALIGN
+    }
+
+    /// Stores `value`, for callers that checked [`fits`](Self::fits) at
+    /// run time.
+    ///
+    /// # Safety
+    ///
+    /// `T` must fit.
+    pub(crate) unsafe fn new_unchecked<T: Any + Send>(value: T) -> Self {
+        debug_assert!(Self::fits::<T>());
+        let mut buffer = Buffer([MaybeUninit::uninit(); N]);
+        // SAFETY: the buffer is large and aligned enough for `T`, as the
+        // caller ensures.
+        unsafe { ptr::write(buffer.0.as_mut_ptr().cast::<T>(), value) };
+        Self {
+            buffer,
+            type_id: TypeId::of::<T>(),
+            type_name: type_name::<T>(),
+            drop: Some(|value| unsafe { ptr::drop_in_place(value.cast::<T>()) }),
+            into_box: |value| Box::new(unsafe { ptr::read(value.cast::<T>()) }),
+        }
+    }
+
+    /// Moves the value into a box, if it was not taken.
+    pub(crate) fn into_boxed(mut self) -> Option<Box<dyn Any + Send>> {
+        self.drop.take()?;
+        // SAFETY: the buffer holds the value `into_box` was made for, which
+        // is no longer dropped with the reply now that `drop` is cleared.
+        Some(unsafe { (self.into_box)(self.buffer.0.as_mut_ptr().cast()) })
+    }
+
+    /// Returns `true` if the reply holds a value of type `T`.
+    pub fn is<T: Any>(&self) -> bool {
+        self.drop.is_some() && self.type_id == TypeId::of::<T>()
+    }
+
+    /// Takes the value out, if it is a `T`.
+    pub fn take<T: Any>(&mut self) -> Result<T, ReplyError> {
+        if self.drop.is_none() {
+            return Err(ReplyError::AlreadyTaken);
+        }
+        if self.type_id != TypeId::of::<T>() {
+            return Err(ReplyError::WrongType {
+                expected: type_name::<T>(),
+                actual: self.type_name.to_string(),
+                op: None,
+            });
+        }
+        self.drop = None;
+        // SAFETY: the buffer holds a `T`, which is no longer dropped with the
+        // reply now that `drop` is cleared.
+        Ok(unsafe { ptr::read(self.buffer.0.as_ptr().cast::<T>()) })
+    }
+}
+
+impl<const N: usize> Drop for InlineReply<N> {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop.take() {
+            // SAFETY: `drop` was made for the type of the stored value.
+            unsafe { drop(self.buffer.0.as_mut_ptr().cast()) }
+        }
+    }
+}
+
+impl<const N: usize> fmt::Debug for InlineReply<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("InlineReply");
+        match self.drop {
+            Some(_) => debug.field("type", &self.type_name),
+            None => debug.field("type", &format_args!("<taken>")),
+        };
+        debug.finish()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::sync::Arc;
+
+    effect! {
+        Sensor::Read (u8) -> u16;
+        Sensor::Calibrate -> [i16; 3];
+        Led::Set (bool) -> ();
+    }
+
+    #[test]
+    fn test_generated_reply_bounds() {
+        assert_eq!(Sensor::MAX_REPLY_SIZE, 6);
+        assert_eq!(Led::MAX_REPLY_SIZE, 0);
+        assert_eq!(Op::MAX_REPLY_SIZE, 6);
+
+        // Bounds are constants, so they can size a buffer
+        let mut reply = InlineReply::<{ Op::MAX_REPLY_SIZE }>::new([1i16, -2, 3]);
+        assert_eq!(reply.take::<[i16; 3]>(), Ok([1, -2, 3]));
+    }
+
+    #[test]
+    fn test_take_checks_type_and_one_shot_use() {
+        let mut reply = InlineReply::<8>::new(7u32);
+        assert!(reply.is::<u32>());
+        assert_eq!(
+            reply.take::<u64>(),
+            Err(ReplyError::WrongType {
+                expected: "u64",
+                actual: "u32".to_string(),
+                op: None,
+            })
+        );
+        assert_eq!(reply.take::<u32>(), Ok(7));
+        assert_eq!(reply.take::<u32>(), Err(ReplyError::AlreadyTaken));
+        assert!(!reply.is::<u32>());
+    }
+
+    #[test]
+    fn test_stored_values_are_dropped_once() {
+        let value = Arc::new(());
+        let reply = InlineReply::<{ mem::size_of::<Arc<()>>() }>::new(Arc::clone(&value));
+        assert_eq!(Arc::strong_count(&value), 2);
+        drop(reply);
+        assert_eq!(Arc::strong_count(&value), 1);
+
+        let mut reply = InlineReply::<16>::new(Arc::clone(&value));
+        let taken = reply.take::<Arc<()>>().unwrap();
+        drop(reply);
+        assert_eq!(Arc::strong_count(&value), 2);
+        drop(taken);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
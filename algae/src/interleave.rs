@@ -0,0 +1,442 @@
+//! Systematic exploration of task interleavings.
+//!
+//! When several computations share one handler, the order in which their
+//! operations reach it can matter: a handler that is correct for any single
+//! computation may still lose updates or deadlock logically when two of them
+//! interleave. Running the computations on threads finds such bugs only by
+//! luck.
+//!
+//! An [`InterleavingExplorer`] instead runs the computations as tasks of a
+//! deterministic scheduler that switches tasks only at effect boundaries, and
+//! tries the possible schedules one after the other, depth-first, up to a
+//! budget. Each schedule starts from a fresh handler and fresh tasks. The
+//! first schedule in which a task panics, the handler aborts, or the final
+//! check fails is reported as a [`ScheduleFailure`], and can be run again on
+//! its own with [`InterleavingExplorer::replay`].
+//!
+//! Code between two `perform!`s runs without interruption, so only state
+//! that tasks share through their effects is exercised.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, coroutine_trait, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::interleave::InterleavingExplorer;
+//!
+//! effect! {
+//!     Counter::Get -> u32;
+//!     Counter::Set (u32) -> ();
+//! }
+//!
+//! #[effectful]
+//! fn increment() {
+//!     let n: u32 = perform!(Counter::Get);
+//!     let _: () = perform!(Counter::Set(n + 1));
+//! }
+//!
+//! let outcome = InterleavingExplorer::new().explore(
+//!     || (CounterHandler::default(), vec![increment(), increment()]),
+//!     |handler, _| assert_eq!(handler.value, 2),
+//! );
+//! let failure = outcome.unwrap_err();
+//! println!("{failure}"); // schedule [0, 1, 0, 1] failed: assertion `left == right` failed ...
+//! ```
+
+use crate::{dispatch_effect, Effect, EffectError, Effectful, Handler, Reply};
+use std::{
+    any::Any,
+    fmt,
+    ops::CoroutineState,
+    panic::{self, AssertUnwindSafe},
+};
+
+/// Summary of an exploration in which every schedule passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explored {
+    /// How many schedules were run.
+    pub schedules: usize,
+    /// Whether those were all possible schedules, or the budget ran out first.
+    pub exhaustive: bool,
+}
+
+/// A schedule under which the tasks failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleFailure {
+    /// The index of the task that performed each operation, in dispatch
+    /// order.
+    pub schedule: Vec<usize>,
+    /// The panic message, or a description of the abort.
+    pub message: String,
+}
+
+impl fmt::Display for ScheduleFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "schedule {:?} failed: {}", self.schedule, self.message)
+    }
+}
+
+impl std::error::Error for ScheduleFailure {}
+
+/// Explores the interleavings of tasks sharing a handler.
+///
+/// See the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct InterleavingExplorer {
+    max_schedules: usize,
+}
+
+impl InterleavingExplorer {
+    /// An explorer that runs at most 10 000 schedules.
+    pub fn new() -> Self {
+        Self {
+            max_schedules: 10_000,
+        }
+    }
+
+    /// Stops after `max_schedules` schedules.
+    pub fn max_schedules(mut self, max_schedules: usize) -> Self {
+        self.max_schedules = max_schedules;
+        self
+    }
+
+    /// Runs the tasks under every schedule, up to the budget.
+    ///
+    /// `setup` builds a fresh handler and fresh tasks for each schedule.
+    /// `check` receives the handler and the task results, in task order, after
+    /// every task has finished; it fails a schedule by panicking. Each task
+    /// runs as [`scoped`](Effectful::scoped) sub-computation of its position,
+    /// so seeded handlers give it the same values under every schedule.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first schedule under which a task or the handler panicked,
+    /// the handler aborted, or `check` panicked.
+    pub fn explore<R, Op: 'static, H, S, C>(
+        &self,
+        mut setup: S,
+        mut check: C,
+    ) -> Result<Explored, ScheduleFailure>
+    where
+        S: FnMut() -> (H, Vec<Effectful<R, Op>>),
+        C: FnMut(H, Vec<R>),
+        H: Handler<Op>,
+    {
+        let mut path: Vec<Decision> = Vec::new();
+        let mut schedules = 0;
+        loop {
+            let (h, tasks) = setup();
+            let mut schedule = Vec::new();
+            schedules += 1;
+            let outcome = catch(|| {
+                let scheduler = Scheduler::new(h, tasks);
+                let mut depth = 0;
+                let (h, results) = scheduler.run(|runnable| {
+                    if depth == path.len() {
+                        path.push(Decision {
+                            taken: 0,
+                            count: runnable.len(),
+                        });
+                    }
+                    let task = runnable[path[depth].taken];
+                    depth += 1;
+                    schedule.push(task);
+                    task
+                })?;
+                check(h, results);
+                Ok(())
+            });
+            if let Err(message) = outcome {
+                return Err(ScheduleFailure { schedule, message });
+            }
+
+            while path.last().is_some_and(|d| d.taken + 1 == d.count) {
+                path.pop();
+            }
+            match path.last_mut() {
+                None => {
+                    return Ok(Explored {
+                        schedules,
+                        exhaustive: true,
+                    })
+                }
+                Some(_) if schedules == self.max_schedules => {
+                    return Ok(Explored {
+                        schedules,
+                        exhaustive: false,
+                    })
+                }
+                Some(decision) => decision.taken += 1,
+            }
+        }
+    }
+
+    /// Runs the tasks under one `schedule`, e.g. one reported by
+    /// [`explore`](Self::explore), to debug it.
+    ///
+    /// Once `schedule` is used up, the lowest-numbered task that can run goes
+    /// next.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a task in `schedule` has already finished, and with the
+    /// original panic if the schedule fails.
+    pub fn replay<R, Op: 'static, H, S, C>(&self, schedule: &[usize], mut setup: S, mut check: C)
+    where
+        S: FnMut() -> (H, Vec<Effectful<R, Op>>),
+        C: FnMut(H, Vec<R>),
+        H: Handler<Op>,
+    {
+        let (h, tasks) = setup();
+        let mut steps = schedule.iter();
+        let run = Scheduler::new(h, tasks).run(|runnable| match steps.next() {
+            Some(task) if runnable.contains(task) => *task,
+            Some(task) => panic!("task {task} cannot run at this point of the schedule"),
+            None => runnable[0],
+        });
+        match run {
+            Ok((h, results)) => check(h, results),
+            Err(message) => panic!("{message}"),
+        }
+    }
+}
+
+impl Default for InterleavingExplorer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One scheduling point on the path to the current schedule.
+struct Decision {
+    taken: usize,
+    count: usize,
+}
+
+/// A task that is waiting for its pending operation to be dispatched, or
+/// that has finished.
+enum Task<R, Op: 'static> {
+    Pending(Effectful<R, Op>, Box<Effect<Op>>),
+    Done(R),
+}
+
+/// Runs tasks one operation at a time.
+struct Scheduler<R, Op: 'static, H> {
+    h: H,
+    tasks: Vec<Option<Task<R, Op>>>,
+}
+
+impl<R, Op: 'static, H: Handler<Op>> Scheduler<R, Op, H> {
+    fn new(h: H, tasks: Vec<Effectful<R, Op>>) -> Self {
+        let tasks = tasks
+            .into_iter()
+            .enumerate()
+            .map(|(i, task)| Some(Self::advance(task.scoped(i as u64), None)))
+            .collect();
+        Self { h, tasks }
+    }
+
+    /// Resumes `task` up to its next operation or its end.
+    fn advance(mut task: Effectful<R, Op>, reply: Option<Reply>) -> Task<R, Op> {
+        match task.step(reply) {
+            CoroutineState::Yielded(eff) => Task::Pending(task, Box::new(eff)),
+            CoroutineState::Complete(result) => Task::Done(result),
+        }
+    }
+
+    /// Dispatches operations, letting `pick` choose among the runnable tasks
+    /// before each one, until every task has finished.
+    fn run<P>(mut self, mut pick: P) -> Result<(H, Vec<R>), String>
+    where
+        P: FnMut(&[usize]) -> usize,
+    {
+        loop {
+            let runnable: Vec<usize> = (0..self.tasks.len())
+                .filter(|&i| matches!(self.tasks[i], Some(Task::Pending(..))))
+                .collect();
+            if runnable.is_empty() {
+                break;
+            }
+            let index = pick(&runnable);
+            let Some(Task::Pending(task, eff)) = self.tasks[index].take() else {
+                unreachable!("only pending tasks are runnable")
+            };
+            let h = &mut self.h;
+            match dispatch_effect(*eff, &mut |op: &Op| Some(h.handle(op))) {
+                Ok(reply) => self.tasks[index] = Some(Self::advance(task, Some(reply))),
+                Err(EffectError::Aborted(abort)) => {
+                    return Err(format!("task {index} aborted: {abort}"))
+                }
+                Err(EffectError::ReplyMismatch(error)) => {
+                    return Err(format!(
+                        "task {index} got a reply of the wrong type: {error}"
+                    ))
+                }
+                Err(EffectError::Unhandled(_)) => {
+                    unreachable!("total handlers answer every operation")
+                }
+            }
+        }
+        let results = self
+            .tasks
+            .into_iter()
+            .map(|task| match task {
+                Some(Task::Done(result)) => result,
+                _ => unreachable!("every task has finished"),
+            })
+            .collect();
+        Ok((self.h, results))
+    }
+}
+
+/// Runs `f`, turning a panic into an error carrying its message.
+fn catch<F>(f: F) -> Result<(), String>
+where
+    F: FnOnce() -> Result<(), String>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(outcome) => outcome,
+        Err(payload) => Err(panic_message(&*payload)),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use crate::Abort;
+    use algae::prelude::*;
+
+    effect! {
+        Counter::Get -> u32;
+        Counter::Set (u32) -> ();
+        Counter::Add (u32) -> ();
+    }
+
+    #[derive(Default)]
+    struct CounterHandler {
+        value: u32,
+        limit: Option<u32>,
+    }
+
+    impl Handler<Op> for CounterHandler {
+        fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+            match op {
+                Op::Counter(Counter::Get) => Box::new(self.value),
+                Op::Counter(Counter::Set(n)) => {
+                    self.value = *n;
+                    Box::new(())
+                }
+                Op::Counter(Counter::Add(n)) if self.limit.is_some_and(|l| self.value + n > l) => {
+                    Abort::boxed("over the limit")
+                }
+                Op::Counter(Counter::Add(n)) => {
+                    self.value += n;
+                    Box::new(())
+                }
+            }
+        }
+    }
+
+    /// A read-modify-write through separate operations: racy.
+    #[effectful]
+    fn increment() -> u32 {
+        let n: u32 = perform!(Counter::Get);
+        let _: () = perform!(Counter::Set(n + 1));
+        n
+    }
+
+    /// A single atomic operation: safe.
+    #[effectful]
+    fn add_one() {
+        let _: () = perform!(Counter::Add(1));
+        let _: () = perform!(Counter::Add(1));
+    }
+
+    #[test]
+    fn test_finds_lost_update() {
+        let failure = InterleavingExplorer::new()
+            .explore(
+                || (CounterHandler::default(), vec![increment(), increment()]),
+                |h, _| assert_eq!(h.value, 2, "lost update"),
+            )
+            .unwrap_err();
+        assert_eq!(failure.schedule, [0, 1, 0, 1]);
+        assert!(failure.message.contains("lost update"), "{failure}");
+    }
+
+    #[test]
+    fn test_exhausts_safe_programs() {
+        let explored = InterleavingExplorer::new()
+            .explore(
+                || (CounterHandler::default(), vec![add_one(), add_one()]),
+                |h, _| assert_eq!(h.value, 4),
+            )
+            .unwrap();
+        // Two tasks with two operations each: 4 choose 2 schedules.
+        assert_eq!(
+            explored,
+            Explored {
+                schedules: 6,
+                exhaustive: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_budget_bounds_exploration() {
+        let explored = InterleavingExplorer::new()
+            .max_schedules(3)
+            .explore(
+                || (CounterHandler::default(), vec![add_one(), add_one()]),
+                |_, _| {},
+            )
+            .unwrap();
+        assert_eq!(
+            explored,
+            Explored {
+                schedules: 3,
+                exhaustive: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_reports_aborts() {
+        let failure = InterleavingExplorer::new()
+            .explore(
+                || {
+                    let h = CounterHandler {
+                        value: 0,
+                        limit: Some(3),
+                    };
+                    (h, vec![add_one(), add_one()])
+                },
+                |_, _| {},
+            )
+            .unwrap_err();
+        assert_eq!(failure.schedule, [0, 0, 1, 1]);
+        assert!(failure.message.starts_with("task 1 aborted"), "{failure}");
+    }
+
+    #[test]
+    fn test_replay_reproduces_schedule() {
+        let mut observed = Vec::new();
+        InterleavingExplorer::new().replay(
+            &[0, 1, 1],
+            || (CounterHandler::default(), vec![increment(), increment()]),
+            |h, results| observed.push((h.value, results)),
+        );
+        assert_eq!(observed, [(1, vec![0, 0])]);
+    }
+}
@@ -0,0 +1,230 @@
+//! Running an effectful function over the items of an iterator.
+//!
+//! [`EffectfulIterator::effectful_map`] pairs an iterator with a function
+//! building a computation per item. The computations then run one after the
+//! other with a single handler, instead of a `.handle(h).run()` per item that
+//! builds the handler again each time:
+//!
+//! ```rust,ignore
+//! use algae::prelude::*;
+//!
+//! let sizes: Vec<usize> = files
+//!     .into_iter()
+//!     .effectful_map(process_file)
+//!     .run_all(ProductionHandler::new());
+//! ```
+//!
+//! [`sequence`](EffectfulMap::sequence) instead combines them into one
+//! computation returning all the results, to run inside another or with the
+//! drivers of [`Effectful`]. Each computation runs as the
+//! [`scoped`](Effectful::scoped) sub-computation of its position, as with
+//! [`par::run_all`](crate::par).
+
+use crate::{EffectError, Effectful, Handler, Partial, PartialHandler, Reply};
+use alloc::vec::Vec;
+use core::{fmt, ops::CoroutineState};
+
+/// Adds [`effectful_map`](Self::effectful_map) to every iterator.
+pub trait EffectfulIterator: Iterator + Sized {
+    /// Maps each item to a computation with `f`, to be run by the methods
+    /// of [`EffectfulMap`].
+    ///
+    /// Like [`Iterator::map`] it is lazy: computations are built one at a
+    /// time as they are run. It is itself an iterator over them.
+    fn effectful_map<R, Op: 'static, F>(self, f: F) -> EffectfulMap<Self, F>
+    where
+        F: FnMut(Self::Item) -> Effectful<R, Op>,
+    {
+        EffectfulMap { iter: self, f }
+    }
+}
+
+impl<I: Iterator> EffectfulIterator for I {}
+
+/// An iterator mapped to computations; made by
+/// [`EffectfulIterator::effectful_map`].
+pub struct EffectfulMap<I, F> {
+    iter: I,
+    f: F,
+}
+
+impl<I, F, R, Op: 'static> Iterator for EffectfulMap<I, F>
+where
+    I: Iterator,
+    F: FnMut(I::Item) -> Effectful<R, Op>,
+{
+    type Item = Effectful<R, Op>;
+
+    fn next(&mut self) -> Option<Effectful<R, Op>> {
+        self.iter.next().map(&mut self.f)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<I, F, R, Op: 'static> EffectfulMap<I, F>
+where
+    I: Iterator,
+    F: FnMut(I::Item) -> Effectful<R, Op>,
+{
+    /// Runs the computations one after the other with the total handler
+    /// `h`, and returns their results in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics like [`Effectful::run_with`] if a computation is aborted.
+    pub fn run_all<H: Handler<Op>>(self, mut h: H) -> Vec<R> {
+        self.enumerate()
+            .map(|(i, c)| c.scoped(i as u64).run_unchecked(&mut h))
+            .collect()
+    }
+
+    /// Runs the computations one after the other with the partial handler
+    /// `h`, and returns their results in order.
+    ///
+    /// The first computation to fail, like [`Effectful::try_run_with`],
+    /// fails the run; the items after it are not mapped.
+    pub fn try_run_all<H: PartialHandler<Op>>(self, mut h: H) -> Result<Vec<R>, EffectError<Op>> {
+        let handler = core::any::type_name::<H>();
+        self.enumerate()
+            .map(|(i, c)| c.scoped(i as u64).drive_as(handler, Partial(&mut h)))
+            .collect()
+    }
+
+    /// Combines the computations into one that runs them one after the
+    /// other and returns their results in order.
+    pub fn sequence(self) -> Effectful<Vec<R>, Op>
+    where
+        I: Send + 'static,
+        F: Send + 'static,
+        R: Send + 'static,
+        Op: Send,
+    {
+        let computations = self;
+        Effectful::new(
+            #[coroutine]
+            move |_reply: Option<Reply>| {
+                let mut results = Vec::new();
+                for (i, computation) in computations.enumerate() {
+                    let mut computation = computation.scoped(i as u64);
+                    let mut reply = None;
+                    let result = loop {
+                        match computation.step(reply.take()) {
+                            CoroutineState::Yielded(eff) => reply = yield eff,
+                            CoroutineState::Complete(result) => break result,
+                        }
+                    };
+                    results.push(result);
+                }
+                results
+            },
+        )
+    }
+}
+
+impl<I, F> fmt::Debug for EffectfulMap<I, F>
+where
+    I: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EffectfulMap")
+            .field("iter", &self.iter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use crate as algae;
+    use algae::prelude::*;
+    use std::any::Any;
+
+    effect! {
+        Store::Get (String) -> Option<u32>;
+        Store::Count -> usize;
+    }
+
+    /// A store of values, counting the lookups made.
+    struct Values(Vec<(&'static str, u32)>, usize);
+
+    impl Handler<Op> for Values {
+        fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+            match op {
+                Op::Store(Store::Get(key)) => {
+                    self.1 += 1;
+                    let found = self.0.iter().find(|(k, _)| k == key);
+                    Box::new(found.map(|(_, v)| *v))
+                }
+                Op::Store(Store::Count) => Box::new(self.1),
+            }
+        }
+    }
+
+    impl PartialHandler<Op> for Values {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Store(Store::Get(key)) if !self.0.iter().any(|(k, _)| k == key) => None,
+                _ => Some(self.handle(op)),
+            }
+        }
+    }
+
+    #[effectful]
+    fn lookup(key: &'static str) -> (Option<u32>, usize) {
+        let value = perform!(Store::Get(key.to_string()));
+        (value, perform!(Store::Count))
+    }
+
+    fn values() -> Values {
+        Values(vec![("a", 1), ("b", 2)], 0)
+    }
+
+    #[test]
+    fn test_run_all_shares_one_handler() {
+        let results = ["a", "b", "c"]
+            .into_iter()
+            .effectful_map(lookup)
+            .run_all(values());
+        assert_eq!(results, [(Some(1), 1), (Some(2), 2), (None, 3)]);
+    }
+
+    #[test]
+    fn test_try_run_all_stops_at_the_first_failure() {
+        let found = ["a", "b"]
+            .into_iter()
+            .effectful_map(lookup)
+            .try_run_all(values());
+        assert_eq!(found.unwrap(), [(Some(1), 1), (Some(2), 2)]);
+
+        let mut mapped = Vec::new();
+        let failed = ["a", "c", "b"]
+            .into_iter()
+            .effectful_map(|key| {
+                mapped.push(key);
+                lookup(key)
+            })
+            .try_run_all(values());
+        assert!(matches!(failed, Err(EffectError::Unhandled(_))));
+        assert_eq!(mapped, ["a", "c"]);
+    }
+
+    #[test]
+    fn test_sequence_runs_inside_other_computations() {
+        #[effectful]
+        fn total(keys: Vec<&'static str>) -> u32 {
+            let found = embed!(keys.into_iter().effectful_map(lookup).sequence());
+            found.into_iter().filter_map(|(value, _)| value).sum()
+        }
+
+        assert_eq!(
+            total(vec!["a", "b", "c", "b"])
+                .handle(values())
+                .run_or_panic(),
+            5
+        );
+        let computations: Vec<_> = ["a"].into_iter().effectful_map(lookup).collect();
+        assert_eq!(computations.len(), 1);
+    }
+}
@@ -74,7 +74,7 @@
 //!     responses: vec!["Alice".to_string()],
 //!     index: std::cell::RefCell::new(0),
 //! };
-//! let result = greet_user().handle(handler).run();
+//! let result = greet_user().handle(handler).run()?;
 //! assert_eq!(result, "Hello, Alice!");
 //! ```
 //!
@@ -85,16 +85,119 @@
 //! - **Testable**: Effects can be easily mocked for testing
 //! - **Zero-cost abstractions**: Minimal runtime overhead
 //! - **Rust coroutines**: Built on Rust's native coroutine support
+//!
+//! ## `no_std`
+//!
+//! The core runtime (`Effect`, `Reply`, `Effectful`, the handler traits and
+//! the drivers running computations on the calling thread) only needs an
+//! allocator. Without the default `std` feature the crate is `#![no_std]`
+//! and links only `alloc`; the `alloc` feature changes nothing and is kept
+//! so manifests naming it still build:
+//!
+//! ```toml
+//! algae = { version = "0.1", default-features = false, features = ["macros"] }
+//! ```
+//!
+//! The `std` feature adds the thread-based drivers (`Effectful::join`,
+//! `Effectful::join_all`), the modules doing I/O, timing or locking, and
+//! every optional integration.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![feature(coroutines, coroutine_trait)]
-use std::{
+#![cfg_attr(test, feature(coroutine_clone))]
+
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
     any::{Any, TypeId},
-    collections::HashMap,
     ops::{Coroutine, CoroutineState},
+    panic::Location,
     pin::Pin,
+};
+#[cfg(feature = "std")]
+use std::{
+    collections::HashMap,
     sync::{Mutex, OnceLock},
 };
 
+// Lets `effect!` expansions inside this crate refer to `algae::…` paths.
+extern crate self as algae;
+
+#[cfg(feature = "async-std")]
+pub mod async_std;
+pub mod bench;
+pub mod boxed;
+#[cfg(feature = "std")]
+pub mod bracket;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod cancel;
+pub mod combinators;
+#[cfg(feature = "std")]
+pub mod dryrun;
+#[cfg(all(feature = "std", feature = "macros"))]
+pub mod effects;
+#[cfg(feature = "std")]
+pub mod eventsource;
+pub mod executor;
+pub mod fallible;
+#[cfg(feature = "std")]
+pub mod future;
+#[cfg(feature = "proptest")]
+pub mod fuzz;
+#[cfg(feature = "std")]
+pub mod higher;
+pub mod inline;
+#[cfg(feature = "std")]
+pub mod interleave;
+pub mod iter;
+pub mod middleware;
+#[cfg(feature = "std")]
+pub mod mock;
+pub mod multishot;
+pub mod nondet;
+#[cfg(feature = "std")]
+pub mod observe;
+#[cfg(feature = "rayon")]
+pub mod par;
+pub mod policy;
+pub mod priority;
+#[cfg(feature = "proptest")]
+pub mod property;
+pub mod quota;
+pub mod redact;
+#[cfg(feature = "std")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod replay;
+pub mod retry;
+#[cfg(all(feature = "std", feature = "macros"))]
+pub mod scheduler;
+pub mod session;
+#[cfg(all(feature = "std", feature = "macros"))]
+pub mod sim;
+#[cfg(feature = "smol")]
+pub mod smol;
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod tee;
+#[cfg(all(feature = "std", feature = "macros"))]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod timeout;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+#[cfg(feature = "tracing")]
+pub mod trace;
+
 /// An effect operation request paired with a slot for the handler's reply.
 ///
 /// An `Effect` represents a single effectful operation that has been yielded from
@@ -139,24 +242,61 @@ pub struct Effect<Op: 'static> {
     /// The operation being requested
     pub op: Op,
     /// Storage for the handler's reply (filled by the handler)
-    reply: Option<Box<dyn Any + Send>>,
+    reply: Option<Reply>,
+    /// Source location of the `perform!` that created this effect
+    location: &'static Location<'static>,
+    /// Sub-computation that created this effect
+    scope: Scope,
+    /// The operations after `op`, when the effect is a batch yielded by
+    /// `perform_all!`
+    batch: Option<Box<[Op]>>,
+    /// The `#[effectful]` function that performed it, if it was one
+    function: Option<&'static str>,
+    /// How many operations the run performed before it
+    index: usize,
+    /// The type `perform!` takes the reply as, and its name; for a batch,
+    /// the type `perform_all!` takes the reply to each operation as
+    expects: Option<fn() -> (TypeId, &'static str)>,
+    /// Renders the operation for the spans of [`trace`] and for replies of
+    /// the wrong type, if it can be
+    describe: Option<fn(&Op) -> String>,
 }
 
 /// Internal storage for a reply value along with its type information.
 #[derive(Debug)]
 struct Stored {
-    value: Box<dyn Any + Send>,
+    value: Slot,
     type_id: TypeId,
+    /// The name of the type, when the reply was made knowing it
+    type_name: Option<&'static str>,
+}
+
+impl Stored {
+    /// The name of the value's type, for errors.
+    #[cold]
+    fn type_name(&self) -> String {
+        self.type_name
+            .map_or_else(|| lookup_type_name(self.type_id), str::to_string)
+    }
+}
+
+/// Where a reply value is kept: small values made with [`Reply::new`] live
+/// inline, everything else in a box.
+#[derive(Debug)]
+enum Slot {
+    Boxed(Box<dyn Any + Send>),
+    Inline(inline::InlineReply<{ inline::INLINE_REPLY_SIZE }>),
 }
 
-// Stored is Send because Box<dyn Any + Send> is Send
+// Stored is Send because both slots are Send
 // We cannot derive Clone because Box<dyn Any + Send> is not Clone
 
 /// A type-erased container for handler replies that can be safely extracted.
 ///
 /// A `Reply` holds the result from a handler after it processes an effect operation.
-/// The value is stored in a type-erased `Box<dyn Any + Send>` but can be safely
-/// extracted with the correct type using the `take` method.
+/// The value is stored type-erased, in a `Box<dyn Any + Send>` or inline if it
+/// was made with [`Reply::new`] and is small, but can be safely extracted with
+/// the correct type using the `take` method.
 ///
 /// ## One-Shot Extraction
 ///
@@ -188,7 +328,7 @@ struct Stored {
 ///     let value: i32 = perform!(Test::GetValue); // Creates and uses Reply internally
 ///     value
 /// }
-/// let result = example().handle(TestHandler).run();
+/// let result = example().handle(TestHandler).run()?;
 /// assert_eq!(result, 42);
 /// ```
 #[derive(Debug)]
@@ -196,6 +336,18 @@ pub struct Reply {
     /// Storage for the reply value along with its type information
     /// We use Option to track whether the value has been taken (one-shot semantics)
     inner: Option<Stored>,
+    /// What `perform!` expected instead, when the reply does not have the
+    /// type it takes the reply as; see [`Effect::fill`]
+    mismatch: Option<Box<Mismatch>>,
+}
+
+/// The operation a reply of the wrong type answers, and the type expected.
+#[derive(Debug)]
+struct Mismatch {
+    op: String,
+    expected: &'static str,
+    /// The type of the reply to `op`, when it is one of a batch's replies
+    actual: Option<String>,
 }
 
 // Reply is Send because Stored is Send
@@ -208,15 +360,19 @@ pub enum ReplyError {
     AlreadyTaken,
     /// Type mismatch between expected and actual types.
     WrongType {
-        /// The expected type name.
+        /// The expected type name: the reply type declared in `effect!`,
+        /// unless `perform!` inferred it from the surrounding code.
         expected: &'static str,
         /// The actual type name (if known).
         actual: String,
+        /// The operation the reply answers, rendered with its `Debug`
+        /// output, if the reply came from a handler through a `run*` driver.
+        op: Option<String>,
     },
 }
 
-impl std::fmt::Display for ReplyError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             ReplyError::AlreadyTaken => {
                 write!(
@@ -224,47 +380,63 @@ impl std::fmt::Display for ReplyError {
                     "Reply::take called, but no value was supplied (possibly already taken)"
                 )
             }
-            ReplyError::WrongType { expected, actual } => {
+            ReplyError::WrongType {
+                expected,
+                actual,
+                op: None,
+            } => {
                 write!(f, "Reply::take type mismatch: expected `{expected}`, but reply contains `{actual}`")
             }
+            ReplyError::WrongType {
+                expected,
+                actual,
+                op: Some(op),
+            } => {
+                write!(f, "Reply::take type mismatch for `{op}`: expected `{expected}`, but the handler replied with `{actual}`")
+            }
         }
     }
 }
 
-impl std::error::Error for ReplyError {}
+impl core::error::Error for ReplyError {}
 
 /// Global registry mapping TypeId to human-readable type names.
+#[cfg(feature = "std")]
 static TYPE_NAMES: OnceLock<Mutex<HashMap<TypeId, &'static str>>> = OnceLock::new();
 
-/// Creates a HashMap with common primitive and standard library types pre-registered.
-/// This is used to initialize the type registry with human-readable names for common types.
-fn common_type_names() -> HashMap<TypeId, &'static str> {
-    let mut map = HashMap::new();
-    // Pre-register common primitive and standard library types
-    map.insert(TypeId::of::<i8>(), "i8");
-    map.insert(TypeId::of::<i16>(), "i16");
-    map.insert(TypeId::of::<i32>(), "i32");
-    map.insert(TypeId::of::<i64>(), "i64");
-    map.insert(TypeId::of::<i128>(), "i128");
-    map.insert(TypeId::of::<isize>(), "isize");
-    map.insert(TypeId::of::<u8>(), "u8");
-    map.insert(TypeId::of::<u16>(), "u16");
-    map.insert(TypeId::of::<u32>(), "u32");
-    map.insert(TypeId::of::<u64>(), "u64");
-    map.insert(TypeId::of::<u128>(), "u128");
-    map.insert(TypeId::of::<usize>(), "usize");
-    map.insert(TypeId::of::<f32>(), "f32");
-    map.insert(TypeId::of::<f64>(), "f64");
-    map.insert(TypeId::of::<bool>(), "bool");
-    map.insert(TypeId::of::<char>(), "char");
-    map.insert(TypeId::of::<String>(), "String");
-    map.insert(TypeId::of::<&str>(), "&str");
-    map.insert(TypeId::of::<()>(), "()");
-    map.insert(TypeId::of::<Vec<u8>>(), "Vec<u8>");
-    map.insert(TypeId::of::<Vec<String>>(), "Vec<String>");
-    map.insert(TypeId::of::<Option<String>>(), "Option<String>");
-    map.insert(TypeId::of::<Result<(), String>>(), "Result<(), String>");
-    map
+/// Common primitive and standard library types, pre-registered so that error
+/// messages name them.
+fn common_type_names() -> [(TypeId, &'static str); 23] {
+    [
+        (TypeId::of::<i8>(), "i8"),
+        (TypeId::of::<i16>(), "i16"),
+        (TypeId::of::<i32>(), "i32"),
+        (TypeId::of::<i64>(), "i64"),
+        (TypeId::of::<i128>(), "i128"),
+        (TypeId::of::<isize>(), "isize"),
+        (TypeId::of::<u8>(), "u8"),
+        (TypeId::of::<u16>(), "u16"),
+        (TypeId::of::<u32>(), "u32"),
+        (TypeId::of::<u64>(), "u64"),
+        (TypeId::of::<u128>(), "u128"),
+        (TypeId::of::<usize>(), "usize"),
+        (TypeId::of::<f32>(), "f32"),
+        (TypeId::of::<f64>(), "f64"),
+        (TypeId::of::<bool>(), "bool"),
+        (TypeId::of::<char>(), "char"),
+        (TypeId::of::<String>(), "String"),
+        (TypeId::of::<&str>(), "&str"),
+        (TypeId::of::<()>(), "()"),
+        (TypeId::of::<Vec<u8>>(), "Vec<u8>"),
+        (TypeId::of::<Vec<String>>(), "Vec<String>"),
+        (TypeId::of::<Option<String>>(), "Option<String>"),
+        (TypeId::of::<Result<(), String>>(), "Result<(), String>"),
+    ]
+}
+
+#[cfg(feature = "std")]
+fn type_names() -> &'static Mutex<HashMap<TypeId, &'static str>> {
+    TYPE_NAMES.get_or_init(|| Mutex::new(common_type_names().into_iter().collect()))
 }
 
 /// Register a type with the global type name registry.
@@ -283,6 +455,9 @@ fn common_type_names() -> HashMap<TypeId, &'static str> {
 /// `String`, `Vec<u8>`, etc.). Custom application types must be explicitly registered to
 /// appear in error messages with their proper names.
 ///
+/// The registry needs the `std` feature; without it only the common types are
+/// named.
+///
 /// ## Example
 ///
 /// ```rust,ignore
@@ -295,27 +470,613 @@ fn common_type_names() -> HashMap<TypeId, &'static str> {
 /// algae::register_type::<Vec<MyDomainType>>();
 /// algae::register_type::<Option<MyDomainType>>();
 /// ```
+#[cfg(feature = "std")]
 pub fn register_type<T: Any + 'static>() {
-    let type_names = TYPE_NAMES.get_or_init(|| Mutex::new(common_type_names()));
-
-    if let Ok(mut map) = type_names.lock() {
-        map.insert(TypeId::of::<T>(), std::any::type_name::<T>());
+    if let Ok(mut map) = type_names().lock() {
+        map.insert(TypeId::of::<T>(), core::any::type_name::<T>());
     }
 }
 
 /// Look up a type name from the registry (cold path for error handling).
 /// This function is marked as #[cold] to keep it out of the hot instruction cache.
+///
+/// Without `std` there is no registry, and only the common types are named.
 #[cold]
 fn lookup_type_name(id: TypeId) -> String {
-    // Ensure the registry is initialized
-    let type_names = TYPE_NAMES.get_or_init(|| Mutex::new(common_type_names()));
+    #[cfg(feature = "std")]
+    let name = type_names()
+        .lock()
+        .ok()
+        .and_then(|map| map.get(&id).copied());
+    #[cfg(not(feature = "std"))]
+    let name = common_type_names()
+        .into_iter()
+        .find(|&(common, _)| common == id)
+        .map(|(_, name)| name);
+    name.map(ToString::to_string)
+        .unwrap_or_else(|| format!("<unknown type with TypeId {id:?}>"))
+}
+
+/// The dispatch context of the running computation: the location and scope
+/// of the `perform!` being dispatched, and the scope being resumed.
+///
+/// With `std` it is kept per thread.
+#[cfg(feature = "std")]
+mod context {
+    use super::{Location, Scope};
+    use core::cell::Cell;
+
+    thread_local! {
+        /// Location and scope of the `perform!` whose operation is being
+        /// dispatched on this thread.
+        static DISPATCH: Cell<Option<(&'static Location<'static>, Scope)>> = const { Cell::new(None) };
+
+        /// Scope of the computation being resumed on this thread.
+        static RESUME_SCOPE: Cell<Scope> = const { Cell::new(Scope::ROOT) };
+    }
+
+    pub(super) fn dispatch() -> Option<(&'static Location<'static>, Scope)> {
+        DISPATCH.with(Cell::get)
+    }
+
+    pub(super) fn replace_dispatch(
+        dispatch: Option<(&'static Location<'static>, Scope)>,
+    ) -> Option<(&'static Location<'static>, Scope)> {
+        DISPATCH.with(|current| current.replace(dispatch))
+    }
+
+    pub(super) fn resume_scope() -> Scope {
+        RESUME_SCOPE.with(Cell::get)
+    }
+
+    pub(super) fn replace_resume_scope(scope: Scope) -> Scope {
+        RESUME_SCOPE.with(|current| current.replace(scope))
+    }
+}
+
+/// Without `std` there are no threads to keep the context apart, so it is
+/// global. It is kept in atomics, which only need loads and stores, so runs
+/// on other cores or in interrupt handlers cannot corrupt it; they can only
+/// make it report the location or scope of another run.
+#[cfg(not(feature = "std"))]
+mod context {
+    use super::{Location, Scope};
+    use core::{
+        ptr,
+        sync::atomic::{AtomicPtr, AtomicU32, Ordering::Relaxed},
+    };
+
+    /// A [`Scope`] stored as two halves.
+    struct ScopeCell(AtomicU32, AtomicU32);
+
+    impl ScopeCell {
+        const fn new() -> Self {
+            Self(AtomicU32::new(0), AtomicU32::new(0))
+        }
+
+        fn get(&self) -> Scope {
+            Scope(u64::from(self.0.load(Relaxed)) << 32 | u64::from(self.1.load(Relaxed)))
+        }
+
+        fn replace(&self, scope: Scope) -> Scope {
+            let previous = self.get();
+            self.0.store((scope.0 >> 32) as u32, Relaxed);
+            self.1.store(scope.0 as u32, Relaxed);
+            previous
+        }
+    }
+
+    /// Location of the `perform!` being dispatched, or null outside of a
+    /// dispatch. Only ever set from a `&'static Location`.
+    static LOCATION: AtomicPtr<Location<'static>> = AtomicPtr::new(ptr::null_mut());
+    static DISPATCH_SCOPE: ScopeCell = ScopeCell::new();
+    static RESUME_SCOPE: ScopeCell = ScopeCell::new();
+
+    pub(super) fn dispatch() -> Option<(&'static Location<'static>, Scope)> {
+        // SAFETY: `LOCATION` is null or was stored from a `&'static Location`.
+        let location = unsafe { LOCATION.load(Relaxed).as_ref() }?;
+        Some((location, DISPATCH_SCOPE.get()))
+    }
+
+    pub(super) fn replace_dispatch(
+        dispatch: Option<(&'static Location<'static>, Scope)>,
+    ) -> Option<(&'static Location<'static>, Scope)> {
+        let previous = self::dispatch();
+        let (location, scope) = match dispatch {
+            Some((location, scope)) => (ptr::from_ref(location).cast_mut(), scope),
+            None => (ptr::null_mut(), Scope::ROOT),
+        };
+        DISPATCH_SCOPE.replace(scope);
+        LOCATION.store(location, Relaxed);
+        previous
+    }
+
+    pub(super) fn resume_scope() -> Scope {
+        RESUME_SCOPE.get()
+    }
+
+    pub(super) fn replace_resume_scope(scope: Scope) -> Scope {
+        RESUME_SCOPE.replace(scope)
+    }
+}
+
+/// Returns the source location of the `perform!` currently being handled.
+///
+/// The runtime records the location of each effect while its operation is
+/// being dispatched, so handlers (and layers wrapping handlers) can attribute
+/// work to the line of effectful code that requested it. Outside of a dispatch
+/// this returns `None`.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// impl PartialHandler<Op> for TracingHandler {
+///     fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn std::any::Any + Send>> {
+///         if let Some(location) = algae::perform_location() {
+///             eprintln!("{op:?} performed at {location}");
+///         }
+///         self.inner.maybe_handle(op)
+///     }
+/// }
+/// ```
+pub fn perform_location() -> Option<&'static Location<'static>> {
+    context::dispatch().map(|(location, _)| location)
+}
+
+/// Returns the [`Scope`] of the sub-computation whose operation is currently
+/// being handled, or `None` outside of a dispatch.
+///
+/// Handlers that hand out pseudo-random values use it to keep one stream
+/// per sub-computation, seeded with [`Scope::seed`]. Each stream then only
+/// depends on what its own sub-computation does, not on how the others are
+/// nested or interleaved with it.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// impl PartialHandler<Op> for SeededDice {
+///     fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn std::any::Any + Send>> {
+///         let Op::Dice(Dice::Roll) = op else { return None };
+///         let scope = algae::perform_scope().unwrap_or_default();
+///         let rng = self.streams.entry(scope).or_insert_with(|| Rng::new(scope.seed(self.seed)));
+///         Some(Box::new(rng.below(6) + 1))
+///     }
+/// }
+/// ```
+pub fn perform_scope() -> Option<Scope> {
+    context::dispatch().map(|(_, scope)| scope)
+}
+
+/// Identifies a sub-computation by its path from the computation being run.
+///
+/// The computation handed to a driver has the root scope. Each
+/// [`Effectful::scoped`] computation inside it, including the two halves of
+/// a [`bind`](Effectful::bind) and the tasks of drivers that run several
+/// computations at once, is a child of the scope it runs in. A scope only
+/// depends on this path, so it is the same on every run of the same code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Scope(u64);
+
+impl Scope {
+    /// The scope of the computation handed to a driver.
+    pub const ROOT: Scope = Scope(0);
+
+    /// The scope of sub-computation `index` of this one.
+    pub fn child(self, index: u64) -> Scope {
+        Scope(splitmix64(self.0 ^ splitmix64(index)))
+    }
+
+    /// Derives this scope's seed from the seed of the run. The root scope
+    /// keeps `seed` itself.
+    pub fn seed(self, seed: u64) -> u64 {
+        if self == Scope::ROOT {
+            seed
+        } else {
+            splitmix64(seed ^ self.0)
+        }
+    }
+}
+
+/// One step of the SplitMix64 generator: a cheap, well-mixed hash of `x`.
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Publishes a perform location and scope for the duration of one dispatch.
+///
+/// Dispatches can nest (a handler may run another computation), so the
+/// previous ones are restored afterwards.
+struct DispatchGuard {
+    previous: Option<(&'static Location<'static>, Scope)>,
+}
+
+impl DispatchGuard {
+    fn enter(location: &'static Location<'static>, scope: Scope) -> Self {
+        Self {
+            previous: context::replace_dispatch(Some((location, scope))),
+        }
+    }
+}
+
+impl Drop for DispatchGuard {
+    fn drop(&mut self) {
+        context::replace_dispatch(self.previous);
+    }
+}
+
+/// Sets the scope effects are created in while a scoped computation is
+/// resumed, restoring the enclosing one afterwards.
+struct ScopeGuard {
+    previous: Scope,
+}
+
+impl ScopeGuard {
+    fn enter(path: &[u64]) -> Self {
+        let scope = path
+            .iter()
+            .fold(context::resume_scope(), |scope, &index| scope.child(index));
+        Self {
+            previous: context::replace_resume_scope(scope),
+        }
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        context::replace_resume_scope(self.previous);
+    }
+}
+
+/// Answers one yielded effect; the step shared by every driver loop.
+///
+/// `dispatch` is called with the perform location published, and answers
+/// with a boxed reply or a [`Reply`]. Returning `None` yields
+/// [`EffectError::Unhandled`]; returning an [`Abort`] yields
+/// [`EffectError::Aborted`]. Any other reply is written into the effect and
+/// returned, ready to resume the coroutine with.
+pub(crate) fn dispatch_effect<Op, F, B>(
+    eff: Effect<Op>,
+    dispatch: &mut F,
+) -> Result<Reply, EffectError<Op>>
+where
+    F: FnMut(&Op) -> Option<B>,
+    B: Into<Reply>,
+{
+    settle(offer_effect(eff, dispatch))
+}
+
+/// [`dispatch_effect`] for the `run*` drivers, which answer with `handler`.
+///
+/// Batches go to `handler` whole, through [`Dispatch::reply_batch`]. With
+/// the `tracing` feature the dispatch gets a span; see [`trace`].
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn dispatch_in_run<Op, D: Dispatch<Op>>(
+    eff: Effect<Op>,
+    handler: &'static str,
+    dispatch: &mut D,
+) -> Result<Reply, EffectError<Op>> {
+    #[cfg(feature = "tracing")]
+    return trace::dispatch(eff, handler, |eff| answer_in_run(eff, dispatch));
+    #[cfg(not(feature = "tracing"))]
+    answer_in_run(eff, dispatch)
+}
 
-    if let Ok(map) = type_names.lock() {
-        map.get(&id)
-            .map(|&s| s.to_string())
-            .unwrap_or_else(|| format!("<unknown type with TypeId {id:?}>"))
+fn answer_in_run<Op, D: Dispatch<Op>>(
+    eff: Effect<Op>,
+    dispatch: &mut D,
+) -> Result<Reply, EffectError<Op>> {
+    let answer = if eff.is_batch() {
+        settle(offer_batch(eff, &mut |ops| dispatch.reply_batch(ops)))
     } else {
-        format!("<unknown type with TypeId {id:?}>")
+        dispatch_effect(eff, &mut |op| dispatch.reply(op))
+    };
+    answer.map_err(|err| match err {
+        EffectError::Unhandled(unhandled) => EffectError::Unhandled(UnhandledOp {
+            declined: dispatch.declined_by(),
+            ..unhandled
+        }),
+        err => err,
+    })
+}
+
+/// What a `run*` driver answers operations with.
+pub(crate) trait Dispatch<Op> {
+    fn reply(&mut self, op: &Op) -> Option<Reply>;
+
+    /// The handlers an operation it declines was offered to.
+    fn declined_by(&self) -> Vec<&'static str>;
+
+    /// Answers a batch whole, or declines it.
+    fn reply_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        reply_each(ops, |op| self.reply(op))
+    }
+}
+
+/// Dispatches to a closure, one operation at a time.
+struct PerOp<F>(F);
+
+impl<Op, F, B> Dispatch<Op> for PerOp<F>
+where
+    F: FnMut(&Op) -> Option<B>,
+    B: Into<Reply>,
+{
+    fn reply(&mut self, op: &Op) -> Option<Reply> {
+        (self.0)(op).map(Into::into)
+    }
+
+    fn declined_by(&self) -> Vec<&'static str> {
+        vec![core::any::type_name::<F>()]
+    }
+}
+
+/// Dispatches to a [`PartialHandler`].
+pub(crate) struct Partial<'a, H>(pub(crate) &'a mut H);
+
+impl<Op, H: PartialHandler<Op>> Dispatch<Op> for Partial<'_, H> {
+    fn reply(&mut self, op: &Op) -> Option<Reply> {
+        self.0.maybe_reply(op)
+    }
+
+    fn reply_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        self.0.maybe_handle_batch(ops)
+    }
+
+    fn declined_by(&self) -> Vec<&'static str> {
+        self.0.handler_names()
+    }
+}
+
+/// Dispatches to a [`Handler`], through the methods that let it decline.
+struct Checked<'a, H>(&'a mut H);
+
+impl<Op, H: Handler<Op>> Dispatch<Op> for Checked<'_, H> {
+    fn reply(&mut self, op: &Op) -> Option<Reply> {
+        self.0.try_reply(op)
+    }
+
+    fn reply_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        self.0.try_handle_batch(ops)
+    }
+
+    fn declined_by(&self) -> Vec<&'static str> {
+        Handler::handler_names(self.0)
+    }
+}
+
+/// Dispatches to a [`Handler`].
+struct Total<'a, H>(&'a mut H);
+
+impl<Op, H: Handler<Op>> Dispatch<Op> for Total<'_, H> {
+    fn reply(&mut self, op: &Op) -> Option<Reply> {
+        Some(self.0.reply(op))
+    }
+
+    fn reply_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        Some(self.0.handle_batch(ops))
+    }
+
+    fn declined_by(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+}
+
+/// Stands in for the reply to an operation of a batch that the handler
+/// answering the rest of it declined.
+pub(crate) struct Unanswered;
+
+/// Answers a batch one operation at a time with `reply`, declining it if
+/// every operation is declined.
+///
+/// The operations `reply` declines are left [`Unanswered`], for
+/// [`answer_rest`] to offer elsewhere.
+fn reply_each<Op>(ops: &[Op], mut reply: impl FnMut(&Op) -> Option<Reply>) -> Option<Vec<Reply>> {
+    let replies: Vec<Reply> = ops
+        .iter()
+        .map(|op| reply(op).unwrap_or_else(|| Reply::new(Unanswered)))
+        .collect();
+    replies
+        .iter()
+        .any(|reply| !reply.is::<Unanswered>())
+        .then_some(replies)
+}
+
+/// Offers the operations of a batch left [`Unanswered`] in `replies` to
+/// `reply`, one at a time.
+fn answer_rest<Op>(ops: &[Op], replies: &mut [Reply], mut reply: impl FnMut(&Op) -> Option<Reply>) {
+    for (op, slot) in ops.iter().zip(replies) {
+        if slot.is::<Unanswered>() {
+            if let Some(reply) = reply(op) {
+                *slot = reply;
+            }
+        }
+    }
+}
+
+/// Turns what became of an effect into the result of dispatching it.
+fn settle<Op>(offered: Offered<Op>) -> Result<Reply, EffectError<Op>> {
+    match offered {
+        Offered::Replied(reply) => match reply.mismatch() {
+            Some(error) => Err(EffectError::ReplyMismatch(error)),
+            None => Ok(reply),
+        },
+        Offered::Aborted(abort) => Err(EffectError::Aborted(abort)),
+        Offered::Declined(eff) => Err(EffectError::Unhandled(UnhandledOp::of(eff))),
+        Offered::Partly(batch) => Err(EffectError::Unhandled(UnhandledOp::of(batch.rest))),
+    }
+}
+
+/// Offers a batch to `dispatch` whole, answering it with a `Vec<Reply>`.
+///
+/// An [`Abort`] among the replies aborts the batch. Replies of the wrong
+/// type are told the operation they answer, like [`Effect::fill`] does.
+fn offer_batch<Op, F>(eff: Effect<Op>, dispatch: &mut F) -> Offered<Op>
+where
+    F: FnMut(&[Op]) -> Option<Vec<Reply>>,
+{
+    let Effect {
+        op,
+        batch,
+        location,
+        scope,
+        function,
+        index,
+        expects,
+        describe,
+        ..
+    } = eff;
+    let mut ops = vec![op];
+    ops.extend(batch.into_iter().flat_map(Vec::from));
+    let replies = {
+        let _dispatching = DispatchGuard::enter(location, scope);
+        dispatch(&ops)
+    };
+    let rebuild = |ops: Vec<Op>| {
+        let mut ops = ops.into_iter();
+        Effect {
+            op: ops.next().expect("batches are never empty"),
+            reply: None,
+            location,
+            scope,
+            batch: Some(ops.collect()),
+            function,
+            index,
+            expects,
+            describe,
+        }
+    };
+    let Some(mut replies) = replies else {
+        return Offered::Declined(rebuild(ops));
+    };
+    assert_eq!(
+        replies.len(),
+        ops.len(),
+        "a batch must be answered with a reply per operation"
+    );
+    if let Some(index) = replies.iter().position(Reply::is::<Abort>) {
+        return Offered::Aborted(replies.swap_remove(index).take());
+    }
+    let mut mismatch = None;
+    if let Some(expects) = expects {
+        let (type_id, expected) = expects();
+        for (op, reply) in ops.iter().zip(&mut replies) {
+            if reply.stored_type() != Some(type_id) && !reply.is::<Unanswered>() {
+                let op = describe_op(describe, op);
+                // The batch's reply names the first mismatch, for `settle`.
+                mismatch.get_or_insert_with(|| Mismatch {
+                    op: op.clone(),
+                    expected,
+                    actual: reply.inner.as_ref().map(Stored::type_name),
+                });
+                reply.mismatch = Some(Box::new(Mismatch {
+                    op,
+                    expected,
+                    actual: None,
+                }));
+            }
+        }
+    }
+    if replies.iter().any(Reply::is::<Unanswered>) {
+        let rest = ops
+            .into_iter()
+            .zip(&replies)
+            .filter(|(_, reply)| reply.is::<Unanswered>())
+            .map(|(op, _)| op)
+            .collect();
+        return Offered::Partly(PartlyAnswered {
+            replies,
+            rest: rebuild(rest),
+        });
+    }
+    let mut reply = Reply::new(replies);
+    reply.mismatch = mismatch.map(Box::new);
+    Offered::Replied(reply)
+}
+
+/// Renders `op` like [`Effect::describe_op`], given the effect's describer.
+#[cold]
+fn describe_op<Op>(describe: Option<fn(&Op) -> String>, op: &Op) -> String {
+    match describe {
+        Some(describe) => describe(op),
+        None => format!("an operation of `{}`", core::any::type_name::<Op>()),
+    }
+}
+
+/// What became of an effect offered to a handler.
+pub(crate) enum Offered<Op: 'static> {
+    Replied(Reply),
+    Aborted(Abort),
+    /// The effect, untouched, so it can be offered elsewhere.
+    Declined(Effect<Op>),
+    /// A batch the handler answered only some operations of.
+    Partly(PartlyAnswered<Op>),
+}
+
+/// A batch a handler answered only some operations of.
+pub(crate) struct PartlyAnswered<Op: 'static> {
+    /// A reply per operation, [`Unanswered`] for those in `rest`.
+    replies: Vec<Reply>,
+    /// The operations left unanswered, as a batch of their own.
+    rest: Effect<Op>,
+}
+
+impl<Op> PartlyAnswered<Op> {
+    /// The batch of the operations left unanswered, to offer elsewhere, and
+    /// what completes the replies once it is answered.
+    pub(crate) fn into_parts(self) -> (Effect<Op>, BatchReplies) {
+        (self.rest, BatchReplies(self.replies))
+    }
+}
+
+/// The replies to a batch answered in part; see [`PartlyAnswered`].
+pub(crate) struct BatchReplies(Vec<Reply>);
+
+impl BatchReplies {
+    /// The reply to the whole batch, given the reply to the batch of the
+    /// operations left unanswered.
+    pub(crate) fn complete(self, rest: Option<Reply>) -> Option<Reply> {
+        let mut rest = rest
+            .expect("the runtime resumes every effect with a reply")
+            .take::<Vec<Reply>>()
+            .into_iter();
+        let mut replies = self.0;
+        for slot in replies.iter_mut().filter(|reply| reply.is::<Unanswered>()) {
+            *slot = rest
+                .next()
+                .expect("a batch is answered with a reply per operation");
+        }
+        Some(Reply::new(replies))
+    }
+}
+
+/// Offers one yielded effect to `dispatch`, like [`dispatch_effect`], but
+/// hands a declined effect back.
+///
+/// A batch is offered one operation at a time; the operations `dispatch`
+/// declines are handed back as a batch of their own.
+pub(crate) fn offer_effect<Op, F, B>(mut eff: Effect<Op>, dispatch: &mut F) -> Offered<Op>
+where
+    F: FnMut(&Op) -> Option<B>,
+    B: Into<Reply>,
+{
+    if eff.is_batch() {
+        return offer_batch(eff, &mut |ops| {
+            reply_each(ops, |op| dispatch(op).map(Into::into))
+        });
+    }
+    let reply = {
+        let _dispatching = DispatchGuard::enter(eff.location, eff.scope);
+        dispatch(&eff.op)
+    };
+    match reply.map(Into::into) {
+        Some(reply) if reply.is::<Abort>() => Offered::Aborted(reply.take()),
+        Some(reply) => {
+            eff.fill(reply);
+            Offered::Replied(eff.get_reply())
+        }
+        None => Offered::Declined(eff),
     }
 }
 
@@ -342,8 +1103,65 @@ impl<Op> Effect<Op> {
     /// let effect = Effect::new(Test::GetValue);
     /// // Effect is now ready to be handled
     /// ```
+    #[track_caller]
     pub fn new(op: Op) -> Self {
-        Self { op, reply: None }
+        Self {
+            op,
+            reply: None,
+            location: Location::caller(),
+            scope: context::resume_scope(),
+            batch: None,
+            function: None,
+            index: 0,
+            expects: None,
+            describe: None,
+        }
+    }
+
+    /// Creates an effect performing all of `ops` at once, in order.
+    ///
+    /// This is called by the `perform_all!` macro. The effect is answered
+    /// with a `Vec<Reply>` holding a reply per operation; its [`op`] is the
+    /// first operation, and [`ops`](Self::ops) lists them all.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ops` is empty.
+    ///
+    /// [`op`]: Self::op
+    #[track_caller]
+    pub fn batch(ops: Vec<Op>) -> Self {
+        let mut ops = ops.into_iter();
+        let op = ops.next().expect("a batch needs at least one operation");
+        Self {
+            batch: Some(ops.collect()),
+            ..Self::new(op)
+        }
+    }
+
+    /// Returns `true` if the effect is a batch made by [`Effect::batch`].
+    pub fn is_batch(&self) -> bool {
+        self.batch.is_some()
+    }
+
+    /// The operations the effect performs: [`op`](Self::op), followed by the
+    /// rest of the batch if it is one.
+    pub fn ops(&self) -> impl Iterator<Item = &Op> {
+        core::iter::once(&self.op).chain(self.batch.iter().flat_map(|rest| rest.iter()))
+    }
+
+    /// Returns the source location where this effect was created.
+    ///
+    /// For effects created by `perform!` this is the location of the
+    /// `perform!` invocation. While a handler is dispatching an operation the
+    /// same location is available through [`perform_location`].
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// Returns the [`Scope`] of the sub-computation that created this effect.
+    pub fn scope(&self) -> Scope {
+        self.scope
     }
 
     /// Stores a pre-boxed reply value in this effect (one-shot only).
@@ -377,10 +1195,37 @@ impl<Op> Effect<Op> {
     /// // Effect now contains the reply value
     /// ```
     pub fn fill_boxed(&mut self, r: Box<dyn Any + Send>) {
+        self.fill(Reply::from_boxed(r));
+    }
+
+    /// Stores a reply in this effect (one-shot only), like
+    /// [`fill_boxed`](Self::fill_boxed).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the effect already has a reply.
+    ///
+    /// A reply that does not have the type `perform!` takes it as is told
+    /// the operation it answers, for the error [`Reply::take`] reports.
+    pub fn fill(&mut self, mut reply: Reply) {
         assert!(self.reply.is_none(), "reply filled twice");
-        self.reply = Some(r);
-        // Note: we store type_id in Reply when converting in get_reply()
-        // This is because Effect stores Box<dyn Any + Send> directly
+        if let Some(expects) = self.expects {
+            let (type_id, expected) = expects();
+            if reply.stored_type() != Some(type_id) {
+                reply.mismatch = Some(Box::new(Mismatch {
+                    op: self.describe_op(),
+                    expected,
+                    actual: None,
+                }));
+            }
+        }
+        self.reply = Some(reply);
+    }
+
+    /// The operation as errors show it: rendered by the `#[effectful]`
+    /// function that performed it, or by its family and name otherwise.
+    fn describe_op(&self) -> String {
+        describe_op(self.describe, &self.op)
     }
 
     /// Consumes the effect and extracts the reply value (one-shot consumption).
@@ -416,19 +1261,137 @@ impl<Op> Effect<Op> {
     /// assert_eq!(value, 42);
     /// ```
     pub fn get_reply(self) -> Reply {
-        let reply_value = self.reply.expect("Effect has no reply");
-        let type_id = (*reply_value).type_id();
-        Reply {
-            inner: Some(Stored {
-                value: reply_value,
-                type_id,
-            }),
+        self.reply.expect("Effect has no reply")
+    }
+
+    /// The same effect, with its operations converted into the root type
+    /// `Root`; its reply, if any, is dropped.
+    pub(crate) fn lift<Root: From<Op>>(self) -> Effect<Root> {
+        Effect {
+            op: self.op.into(),
+            reply: None,
+            location: self.location,
+            scope: self.scope,
+            batch: self
+                .batch
+                .map(|rest| rest.into_vec().into_iter().map(Root::from).collect()),
+            function: self.function,
+            index: self.index,
+            expects: None,
+            describe: None,
+        }
+    }
+}
+
+/// Cloning an effect clones its operation; the clone has no reply yet.
+///
+/// Multi-shot coroutines (see [`multishot`]) are only `Clone` if the effects
+/// they yield are.
+impl<Op: Clone> Clone for Effect<Op> {
+    fn clone(&self) -> Self {
+        Self {
+            op: self.op.clone(),
+            reply: None,
+            location: self.location,
+            scope: self.scope,
+            batch: self.batch.clone(),
+            function: self.function,
+            index: self.index,
+            expects: self.expects,
+            describe: self.describe,
         }
     }
 }
 
+/// Wraps a boxed reply, as the [`Handler`] and [`PartialHandler`] methods
+/// give it.
+impl From<Box<dyn Any + Send>> for Reply {
+    fn from(value: Box<dyn Any + Send>) -> Self {
+        Reply::from_boxed(value)
+    }
+}
+
 impl Reply {
-    /// Attempts to extract the contained value with the specified type.
+    /// A reply holding `value`.
+    ///
+    /// Values of up to [`INLINE_REPLY_SIZE`](inline::INLINE_REPLY_SIZE)
+    /// bytes, such as `()`, integers and small structs, are kept in the reply
+    /// itself, so handlers answering through
+    /// [`PartialHandler::maybe_reply`] or [`Handler::reply`] do not allocate
+    /// for them.
+    pub fn new<T: Any + Send>(value: T) -> Self {
+        type Inline = inline::InlineReply<{ inline::INLINE_REPLY_SIZE }>;
+        let value = if Inline::fits::<T>() {
+            // SAFETY: checked just above.
+            Slot::Inline(unsafe { Inline::new_unchecked(value) })
+        } else {
+            Slot::Boxed(Box::new(value))
+        };
+        Reply {
+            inner: Some(Stored {
+                value,
+                type_id: TypeId::of::<T>(),
+                type_name: Some(core::any::type_name::<T>()),
+            }),
+            mismatch: None,
+        }
+    }
+
+    /// Wraps a handler's reply value.
+    pub(crate) fn from_boxed(value: Box<dyn Any + Send>) -> Self {
+        let type_id = (*value).type_id();
+        Reply {
+            inner: Some(Stored {
+                value: Slot::Boxed(value),
+                type_id,
+                type_name: None,
+            }),
+            mismatch: None,
+        }
+    }
+
+    /// The type of the value, if it has not been taken.
+    fn stored_type(&self) -> Option<TypeId> {
+        self.inner.as_ref().map(|stored| stored.type_id)
+    }
+
+    /// The error taking the reply as the type `perform!` expects fails with,
+    /// if the reply does not have that type.
+    fn mismatch(&self) -> Option<ReplyError> {
+        let mismatch = self.mismatch.as_ref()?;
+        let actual = match &mismatch.actual {
+            Some(actual) => actual.clone(),
+            None => self.inner.as_ref()?.type_name(),
+        };
+        Some(ReplyError::WrongType {
+            expected: mismatch.expected,
+            actual,
+            op: Some(mismatch.op.clone()),
+        })
+    }
+
+    /// Returns `true` if the reply holds a value of type `T`.
+    pub fn is<T: Any>(&self) -> bool {
+        self.inner
+            .as_ref()
+            .is_some_and(|stored| stored.type_id == TypeId::of::<T>())
+    }
+
+    /// The value, boxed as the [`Handler`] and [`PartialHandler`] methods
+    /// reply.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value was already taken.
+    pub fn into_boxed(mut self) -> Box<dyn Any + Send> {
+        let stored = self.inner.take().expect("reply already taken");
+        match stored.value {
+            Slot::Boxed(value) => value,
+            Slot::Inline(value) => value.into_boxed().expect("reply already taken"),
+        }
+    }
+
+    /// Attempts to extract the contained value with the specified type.
     ///
     /// This method performs a runtime type check to ensure the stored value
     /// matches the requested type `R`. If successful, it returns the value.
@@ -463,17 +1426,20 @@ impl Reply {
         // 2. Type check first, *without* moving the value.
         if stored.type_id != TypeId::of::<R>() {
             return Err(ReplyError::WrongType {
-                expected: std::any::type_name::<R>(),
-                actual: lookup_type_name(stored.type_id),
+                expected: core::any::type_name::<R>(),
+                actual: stored.type_name(),
+                op: self.mismatch.as_ref().map(|mismatch| mismatch.op.clone()),
             });
         }
 
         // 3. Now we can safely move it out.
         let stored = self.inner.take().unwrap();
-        Ok(*stored
-            .value
-            .downcast::<R>()
-            .expect("TypeId check guaranteed success"))
+        Ok(match stored.value {
+            Slot::Boxed(value) => *value
+                .downcast::<R>()
+                .expect("TypeId check guaranteed success"),
+            Slot::Inline(mut value) => value.take::<R>().expect("TypeId check guaranteed success"),
+        })
     }
 
     /// Extracts the contained value with the specified type (one-shot extraction).
@@ -519,7 +1485,7 @@ impl Reply {
     ///     let value: i32 = perform!(Test::GetValue); // take() called internally
     ///     value
     /// }
-    /// let result = example().handle(TestHandler).run();
+    /// let result = example().handle(TestHandler).run()?;
     /// assert_eq!(result, 42);
     /// ```
     pub fn take<R: Any + Send + 'static>(mut self) -> R {
@@ -579,12 +1545,61 @@ type EffectCoroutine<R, Op> =
 ///
 /// let result = my_computation()
 ///     .handle(TestHandler)
-///     .run();
+///     .run()?;
 /// assert_eq!(result, 84);
 /// ```
 pub struct Effectful<R, Op: 'static> {
     /// The underlying coroutine representing the effectful computation
     gen: EffectCoroutine<R, Op>,
+    /// How far the coroutine has run, for `Debug`
+    state: RunState,
+    /// Path from the enclosing computation's scope to this one's; empty
+    /// unless the computation is [`scoped`](Effectful::scoped)
+    scope: Vec<u64>,
+    /// The `#[effectful]` function it is the body of, reported by
+    /// [`UnhandledOp`]
+    function: Option<&'static str>,
+    /// How many operations it has performed
+    performed: usize,
+    /// Renders the operations it performs for the spans of [`trace`] and
+    /// for replies of the wrong type; set by `#[effectful]` when the root
+    /// type allows it
+    describe: __private::Describer<Op>,
+}
+
+/// How far an [`Effectful`] has run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunState {
+    Unstarted,
+    /// Waiting for the reply to the operation performed at this location
+    Suspended(&'static Location<'static>),
+    Finished,
+}
+
+/// Waits for a run on a scoped thread, resuming its panic if it panicked.
+#[cfg(feature = "std")]
+fn joined<T>(run: std::thread::ScopedJoinHandle<'_, T>) -> T {
+    run.join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// Reports how far the computation has run: `unstarted`, `suspended` at the
+/// `perform!` whose reply it waits for, or `finished`.
+///
+/// The pending operation itself is owned by whoever resumed the computation,
+/// so only its location is known here.
+impl<R, Op: 'static> core::fmt::Debug for Effectful<R, Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut debug = f.debug_struct("Effectful");
+        match self.state {
+            RunState::Unstarted => debug.field("state", &format_args!("unstarted")),
+            RunState::Suspended(location) => debug
+                .field("state", &format_args!("suspended"))
+                .field("pending_at", &format_args!("{location}")),
+            RunState::Finished => debug.field("state", &format_args!("finished")),
+        };
+        debug.finish()
+    }
 }
 
 impl<R, Op: 'static> Effectful<R, Op> {
@@ -594,7 +1609,8 @@ impl<R, Op: 'static> Effectful<R, Op> {
     /// When it finishes, its result is passed to `f` to build the
     /// right-hand computation, which is then run with the **same** handler
     /// stream. All effects are yielded outwards in the order they occur, so
-    /// to the handler it looks like one flat computation.
+    /// to the handler it looks like one flat computation. The two halves run
+    /// as [`scoped`](Effectful::scoped) sub-computations 0 and 1.
     ///
     /// # Arguments
     ///
@@ -632,17 +1648,15 @@ impl<R, Op: 'static> Effectful<R, Op> {
         S: Send + 'static,
         Op: Send + 'static,
     {
-        // Extract the pinned generator from `self` **outside** the closure,
-        // because `self` will be moved into the closure body.
-        let lhs_gen = self.gen; // type = Pin<Box<...>>
+        let state = self.state;
+        let mut lhs = self.scoped(0);
 
-        Effectful::new(
+        let mut bound = Effectful::new(
             #[coroutine]
             move |mut reply: Option<Reply>| {
                 // Stage 1: run the left-hand computation
-                let mut lhs = lhs_gen;
                 let lhs_result = loop {
-                    match lhs.as_mut().resume(reply.take()) {
+                    match lhs.step(reply.take()) {
                         CoroutineState::Yielded(eff) => {
                             reply = yield eff; // forward to handler
                         }
@@ -654,10 +1668,10 @@ impl<R, Op: 'static> Effectful<R, Op> {
                 reply = None;
 
                 // Stage 2: build and run the right-hand side
-                let mut rhs = f(lhs_result).gen; // already pinned
+                let mut rhs = f(lhs_result).scoped(1);
 
                 loop {
-                    match rhs.as_mut().resume(reply.take()) {
+                    match rhs.step(reply.take()) {
                         CoroutineState::Yielded(eff) => {
                             reply = yield eff;
                         }
@@ -665,9 +1679,108 @@ impl<R, Op: 'static> Effectful<R, Op> {
                     }
                 }
             },
+        );
+        // A computation bound after it started waits at the same operation
+        bound.state = state;
+        bound
+    }
+
+    /// Runs the computation with a local handler: `h` answers the operations
+    /// it accepts, and the others are performed by the resulting computation,
+    /// to be answered by the handlers it is run with.
+    ///
+    /// If `h` aborts, the computation stops there and returns the [`Abort`]
+    /// instead of failing the enclosing run, which gives "catch" semantics.
+    /// Inside `#[effectful]` functions, [`with_handler!`] runs a block this
+    /// way.
+    ///
+    /// ```rust,ignore
+    /// // `Fail::Raise` aborts with its message; everything else goes outwards
+    /// let parsed: Result<Config, Abort> = parse_config(text)
+    ///     .with_handler(Raise)
+    ///     .handle(env)
+    ///     .run_or_panic()?;
+    /// ```
+    pub fn with_handler<H>(self, mut h: H) -> Effectful<Result<R, Abort>, Op>
+    where
+        H: PartialHandler<Op> + Send + 'static,
+        R: Send + 'static,
+        Op: Send,
+    {
+        let mut inner = self;
+        Effectful::new(
+            #[coroutine]
+            move |_reply: Option<Reply>| {
+                let mut reply = None;
+                loop {
+                    let eff = match inner.step(reply.take()) {
+                        CoroutineState::Yielded(eff) => eff,
+                        CoroutineState::Complete(result) => return Ok(result),
+                    };
+                    reply = match offer_effect(eff, &mut |op| h.maybe_handle(op)) {
+                        Offered::Replied(local) => Some(local),
+                        Offered::Aborted(abort) => return Err(abort),
+                        Offered::Declined(eff) => yield eff,
+                        Offered::Partly(batch) => {
+                            let (rest, replies) = batch.into_parts();
+                            replies.complete(yield rest)
+                        }
+                    };
+                }
+            },
         )
     }
 
+    /// Runs the computation under the root type `Root`, which embeds `Op`,
+    /// so computations written against a sub-root run with the handlers of
+    /// the combined root unchanged.
+    ///
+    /// Each operation is converted with `From`, as generated by
+    /// [`combine_roots!`] for the roots it combines and by `effect!` for
+    /// its families; the replies are passed back as they are. Inside
+    /// `#[effectful]` functions, [`embed!`] runs a computation this way.
+    ///
+    /// ```rust,ignore
+    /// combine_roots!(pub AppOp = ConsoleOp, FileOp);
+    ///
+    /// let computation = read_name()                 // Effectful<String, ConsoleOp>
+    ///     .lift_into::<AppOp>()
+    ///     .bind(|name| save(name).lift_into());     // Effectful<(), FileOp>
+    /// computation.handle(AppHandler::new()).run_or_panic();
+    /// ```
+    pub fn lift_into<Root>(self) -> Effectful<R, Root>
+    where
+        Root: From<Op> + Send + 'static,
+        R: Send + 'static,
+        Op: Send,
+    {
+        let state = self.state;
+        let mut inner = self;
+        let mut lifted = Effectful::new(
+            #[coroutine]
+            move |mut reply: Option<Reply>| loop {
+                match inner.step(reply.take()) {
+                    CoroutineState::Yielded(eff) => reply = yield eff.lift(),
+                    CoroutineState::Complete(result) => return result,
+                }
+            },
+        );
+        lifted.state = state;
+        lifted
+    }
+
+    /// Runs the computation as sub-computation `index` of the one it runs
+    /// in, so the effects it performs report that child [`Scope`].
+    ///
+    /// Drivers that run several computations at once scope each of them by
+    /// its position, and [`bind`](Effectful::bind) scopes its two halves.
+    /// Scope sub-computations explicitly where their effects should be told
+    /// apart, e.g. to give each one its own seeded stream of ids.
+    pub fn scoped(mut self, index: u64) -> Self {
+        self.scope.insert(0, index);
+        self
+    }
+
     /// Creates a new effectful computation from a coroutine.
     ///
     /// This is typically called by the `#[effectful]` macro to wrap the generated
@@ -707,7 +1820,34 @@ impl<R, Op: 'static> Effectful<R, Op> {
     where
         G: Coroutine<Option<Reply>, Return = R, Yield = Effect<Op>> + 'static + Send,
     {
-        Self { gen: Box::pin(g) }
+        Self {
+            gen: Box::pin(g),
+            state: RunState::Unstarted,
+            scope: Vec::new(),
+            function: None,
+            performed: 0,
+            describe: None,
+        }
+    }
+
+    /// Resumes the coroutine up to its next operation or its end, keeping
+    /// track of its state.
+    pub(crate) fn step(&mut self, reply: Option<Reply>) -> CoroutineState<Effect<Op>, R> {
+        let _scope = (!self.scope.is_empty()).then(|| ScopeGuard::enter(&self.scope));
+        let mut step = self.gen.as_mut().resume(reply);
+        if let CoroutineState::Yielded(eff) = &mut step {
+            // Effects of nested computations keep the innermost function, and
+            // the index in the outermost one, which steps them last.
+            eff.function = eff.function.or(self.function);
+            eff.index = self.performed;
+            self.performed += 1;
+            eff.describe = eff.describe.or(self.describe);
+        }
+        self.state = match &step {
+            CoroutineState::Yielded(eff) => RunState::Suspended(eff.location()),
+            CoroutineState::Complete(_) => RunState::Finished,
+        };
+        step
     }
 
     /// Executes the effectful computation using a single handler.
@@ -744,25 +1884,79 @@ impl<R, Op: 'static> Effectful<R, Op> {
     /// let result = computation().run_with(TestHandler);
     /// assert_eq!(result, 42);
     /// ```
-    pub fn run_with<H: Handler<Op>>(self, h: H) -> R {
-        self.run_unchecked(h)
+    #[deprecated(
+        note = "use `handle(h).run()`, or `handle(h).run_or_panic()` to panic like `run_with`"
+    )]
+    pub fn run_with<H: Handler<Op>>(self, mut h: H) -> R {
+        self.run_unchecked(&mut h)
     }
 
     /// Private unchecked execution that may panic on unhandled operations.
-    fn run_unchecked<H: Handler<Op>>(mut self, mut h: H) -> R {
+    fn run_unchecked<H: Handler<Op>>(self, h: &mut H) -> R {
+        match self.drive_as(core::any::type_name::<H>(), Total(h)) {
+            Ok(r) => r,
+            Err(EffectError::Aborted(abort)) => panic!("Effectful computation aborted: {abort}"),
+            Err(EffectError::ReplyMismatch(error)) => panic!("{error}"),
+            Err(EffectError::Unhandled(_)) => unreachable!("total handlers answer every operation"),
+        }
+    }
+
+    /// The single driver loop shared by every `run*` entry point.
+    ///
+    /// `dispatch` is asked to answer each yielded operation; see
+    /// [`dispatch_effect`] for how its answers stop or resume the computation.
+    fn drive<F, B>(self, dispatch: F) -> Result<R, EffectError<Op>>
+    where
+        F: FnMut(&Op) -> Option<B>,
+        B: Into<Reply>,
+    {
+        self.drive_as(core::any::type_name::<F>(), PerOp(dispatch))
+    }
+
+    /// [`drive`](Self::drive), naming `handler` as the one answering in the
+    /// spans of [`trace`].
+    fn drive_as<D: Dispatch<Op>>(
+        mut self,
+        handler: &'static str,
+        mut dispatch: D,
+    ) -> Result<R, EffectError<Op>> {
+        #[cfg(feature = "tracing")]
+        let span = trace::run_span(handler);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+
+        // Releases deferred by the computation, see `bracket`
+        #[cfg(feature = "std")]
+        let run = bracket::ActiveRun::enter();
+
         // Start with None for the first call
         let mut resume_arg: Option<Reply> = None;
 
-        loop {
-            match self.gen.as_mut().resume(resume_arg) {
-                CoroutineState::Complete(r) => return r,
-                CoroutineState::Yielded(mut eff) => {
-                    let reply_any = h.handle(&eff.op);
-                    eff.fill_boxed(reply_any);
-                    resume_arg = Some(eff.get_reply());
+        let result = loop {
+            let step = self.step(resume_arg);
+            #[cfg(feature = "std")]
+            if let Err(err) = run.release(handler, &mut dispatch) {
+                break Err(err);
+            }
+            match step {
+                CoroutineState::Complete(r) => break Ok(r),
+                CoroutineState::Yielded(eff) => {
+                    match dispatch_in_run(eff, handler, &mut dispatch) {
+                        Ok(reply) => resume_arg = Some(reply),
+                        Err(err) => break Err(err),
+                    }
                 }
             }
-        }
+        };
+        // A computation stopped early releases what it still holds
+        #[cfg(feature = "std")]
+        if result.is_err() {
+            drop(self);
+            let _ = run.release(handler, &mut dispatch);
+        }
+        #[cfg(feature = "tracing")]
+        trace::record_run(&span, &result);
+        result
     }
 
     /// Chains a handler with this effectful computation for fluent syntax.
@@ -803,7 +1997,7 @@ impl<R, Op: 'static> Effectful<R, Op> {
     ///
     /// let result = computation()
     ///     .handle(TestHandler)
-    ///     .run();
+    ///     .run()?;
     /// assert_eq!(result, 42);
     /// ```
     pub fn handle<H>(self, h: H) -> Handled<R, Op, H> {
@@ -845,7 +2039,7 @@ impl<R, Op: 'static> Effectful<R, Op> {
     ///     .handle(ConsoleHandler)
     ///     .handle(FileHandler)
     ///     .handle(LoggerHandler)
-    ///     .run_checked()?;
+    ///     .run()?;
     /// ```
     pub fn begin_chain(self) -> Handled<R, Op, VecHandler<Op>>
     where
@@ -857,11 +2051,25 @@ impl<R, Op: 'static> Effectful<R, Op> {
         }
     }
 
+    /// Attaches a handler that only implements [`PartialHandler`], as a
+    /// chain of one, so that [`Handled::run`] reports the operations it
+    /// declines.
+    ///
+    /// More handlers can be added with [`handle`](Handled::handle), like on
+    /// [`begin_chain`](Self::begin_chain).
+    pub fn handle_partial<H>(self, h: H) -> Handled<R, Op, VecHandler<Op>>
+    where
+        H: PartialHandler<Op> + Send + 'static,
+        Op: Send,
+    {
+        self.handle_all([h])
+    }
+
     /// Executes the effectful computation with a partial handler that may decline operations.
     ///
     /// Unlike `run_with`, this method returns a `Result` indicating whether all effects
     /// were successfully handled. If the handler declines to handle an operation (returns `None`),
-    /// execution stops and returns `Err(UnhandledOp(op))`.
+    /// execution stops and returns `Err(UnhandledOp { op, .. })`.
     ///
     /// # Arguments
     ///
@@ -870,7 +2078,12 @@ impl<R, Op: 'static> Effectful<R, Op> {
     /// # Returns
     ///
     /// * `Ok(result)` - If all effects were handled successfully
-    /// * `Err(UnhandledOp(op))` - If an effect operation was not handled
+    /// * `Err(UnhandledOp { op, .. })` - If an effect operation was not handled
+    ///
+    /// # Panics
+    ///
+    /// Panics if a handler replies with an [`Abort`]; use [`Effectful::try_run_with`]
+    /// to receive aborts as errors instead.
     ///
     /// # Examples
     ///
@@ -899,33 +2112,177 @@ impl<R, Op: 'static> Effectful<R, Op> {
     ///
     /// match computation().run_checked(MathOnlyHandler) {
     ///     Ok(result) => println!("Result: {}", result),
-    ///     Err(UnhandledOp(op)) => eprintln!("Unhandled: {:?}", op),
+    ///     Err(UnhandledOp { op, .. }) => eprintln!("Unhandled: {:?}", op),
     /// }
     /// ```
-    pub fn run_checked<H>(mut self, mut h: H) -> Result<R, UnhandledOp<Op>>
+    #[deprecated(
+        note = "use `handle(h).run()`, or `handle_partial(h).run()` for a handler that only \
+                implements `PartialHandler`; both report every `EffectError`"
+    )]
+    pub fn run_checked<H>(self, mut h: H) -> Result<R, UnhandledOp<Op>>
     where
         H: PartialHandler<Op>,
     {
-        let mut resume_arg = None;
-        loop {
-            match self.gen.as_mut().resume(resume_arg) {
-                CoroutineState::Complete(r) => return Ok(r),
-                CoroutineState::Yielded(mut eff) => match h.maybe_handle(&eff.op) {
-                    Some(reply_any) => {
-                        eff.fill_boxed(reply_any);
-                        resume_arg = Some(eff.get_reply());
-                    }
-                    None => return Err(UnhandledOp(eff.op)),
-                },
-            }
+        match self.drive_as(core::any::type_name::<H>(), Partial(&mut h)) {
+            Ok(r) => Ok(r),
+            Err(EffectError::Unhandled(unhandled)) => Err(unhandled),
+            Err(EffectError::Aborted(abort)) => panic!("Effectful computation aborted: {abort}"),
+            Err(EffectError::ReplyMismatch(error)) => panic!("{error}"),
         }
     }
 
+    /// Executes the computation with a partial handler, reporting every way it can stop early.
+    ///
+    /// This is the most complete of the `run*` methods. Besides unhandled operations
+    /// it also surfaces [`Abort`] replies, which handlers use to terminate a
+    /// computation instead of resuming it (for example when a policy denies an
+    /// operation). The suspended coroutine is dropped at the `perform!` that was
+    /// aborted, so no code after it runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `h` - A partial handler that may decline or abort operations
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(result)` - If all effects were handled successfully
+    /// * `Err(EffectError::Unhandled(_))` - If an operation was declined
+    /// * `Err(EffectError::Aborted(_))` - If a handler replied with an [`Abort`]
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// # #![feature(coroutines, coroutine_trait, yield_expr)]
+    /// # use algae::prelude::*;
+    /// # effect! { Bank::Withdraw (u32) -> u32; }
+    /// struct Overdraft;
+    ///
+    /// struct BankHandler { balance: u32 }
+    /// impl PartialHandler<Op> for BankHandler {
+    ///     fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn std::any::Any + Send>> {
+    ///         match op {
+    ///             Op::Bank(Bank::Withdraw(amount)) if *amount > self.balance => {
+    ///                 Some(Abort::boxed("insufficient funds"))
+    ///             }
+    ///             Op::Bank(Bank::Withdraw(amount)) => {
+    ///                 self.balance -= amount;
+    ///                 Some(Box::new(self.balance))
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// #[effectful]
+    /// fn withdraw_twice() -> u32 {
+    ///     let _: u32 = perform!(Bank::Withdraw(70));
+    ///     perform!(Bank::Withdraw(70))
+    /// }
+    ///
+    /// match withdraw_twice().try_run_with(BankHandler { balance: 100 }) {
+    ///     Err(EffectError::Aborted(abort)) => println!("stopped: {abort}"),
+    ///     other => panic!("unexpected: {other:?}"),
+    /// }
+    /// ```
+    pub fn try_run_with<H>(self, mut h: H) -> Result<R, EffectError<Op>>
+    where
+        H: PartialHandler<Op>,
+    {
+        self.drive_as(core::any::type_name::<H>(), Partial(&mut h))
+    }
+
+    /// Executes the computation like [`Handled::run`], and returns the
+    /// handler along with the outcome, so the state it accumulated, such as
+    /// logs or a cache, can be inspected whether the run succeeded or not.
+    ///
+    /// ```rust,ignore
+    /// let (total, logger) = checkout(cart).run_with_handler(MemoryLogger::default());
+    /// assert_eq!(total?, 42);
+    /// assert_eq!(logger.lines.len(), 3);
+    /// ```
+    pub fn run_with_handler<H: Handler<Op>>(self, mut h: H) -> (Result<R, EffectError<Op>>, H) {
+        let result = self.drive_as(core::any::type_name::<H>(), Checked(&mut h));
+        (result, h)
+    }
+
+    /// Executes the computation like [`try_run_with`](Self::try_run_with),
+    /// and returns the handler along with the outcome, whether the run
+    /// succeeded or not.
+    pub fn try_run_with_handler<H>(self, mut h: H) -> (Result<R, EffectError<Op>>, H)
+    where
+        H: PartialHandler<Op>,
+    {
+        let result = self.drive_as(core::any::type_name::<H>(), Partial(&mut h));
+        (result, h)
+    }
+
+    /// Runs this computation and `other` concurrently, each on its own
+    /// thread with a handler built by `factory`, and returns both results.
+    ///
+    /// Where [`bind`](Self::bind) runs computations one after the other,
+    /// `join` suits independent ones, e.g. two slow requests. Handlers
+    /// built by the factory share state only through what they hold, such
+    /// as an `Arc<Mutex<_>>` or a connection pool. The computations run as
+    /// [`scoped`](Self::scoped) sub-computations 0 and 1.
+    ///
+    /// Both computations run to their end; if either fails, the error of the
+    /// first one to fail in argument order is returned. A panic in either
+    /// is resumed on the calling thread.
+    ///
+    /// ```rust,ignore
+    /// let (user, orders) = Effectful::join(load_user(id), load_orders(id), || Api::new(&client))?;
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn join<S, F>(self, other: Effectful<S, Op>, factory: F) -> Result<(R, S), EffectError<Op>>
+    where
+        R: Send,
+        S: Send,
+        Op: Send,
+        F: HandlerFactory<Op> + Sync,
+    {
+        let factory = &factory;
+        let (left, right) = std::thread::scope(|scope| {
+            let right = scope.spawn(move || other.scoped(1).try_run_with(factory.build()));
+            let left = self.scoped(0).try_run_with(factory.build());
+            (left, joined(right))
+        });
+        Ok((left?, right?))
+    }
+
+    /// Runs `computations` concurrently, each on its own thread with a
+    /// handler built by `factory`, and returns their results in order.
+    ///
+    /// Like [`join`](Self::join) for any number of computations of one type;
+    /// each runs as the [`scoped`](Self::scoped) sub-computation of its
+    /// position. It starts a thread per computation, so for many of them
+    /// prefer [`par::run_all`](crate::par) (feature `rayon`), which shares a
+    /// pool.
+    #[cfg(feature = "std")]
+    pub fn join_all<F>(computations: Vec<Self>, factory: F) -> Result<Vec<R>, EffectError<Op>>
+    where
+        R: Send,
+        Op: Send,
+        F: HandlerFactory<Op> + Sync,
+    {
+        let factory = &factory;
+        std::thread::scope(|scope| {
+            let runs: Vec<_> = computations
+                .into_iter()
+                .enumerate()
+                .map(|(i, c)| scope.spawn(move || c.scoped(i as u64).try_run_with(factory.build())))
+                .collect();
+            runs.into_iter().map(joined).collect::<Vec<_>>()
+        })
+        .into_iter()
+        .collect()
+    }
+
     /// Executes the effectful computation with a total handler wrapped to support checked execution.
     ///
     /// This is a convenience method for using total handlers with `run_checked`.
     /// Since total handlers always handle all operations, this will only return
     /// `Err` if the handler itself panics.
+    /// [`Handled::run`] also reports aborts and mismatched replies, and lets
+    /// handlers decline operations through [`Handler::try_reply`].
     ///
     /// # Arguments
     ///
@@ -958,11 +2315,12 @@ impl<R, Op: 'static> Effectful<R, Op> {
     /// let result = computation().run_checked_with(MathHandler);
     /// assert_eq!(result, Ok(5));
     /// ```
-    pub fn run_checked_with<H>(self, h: H) -> Result<R, UnhandledOp<Op>>
+    #[deprecated(note = "use `handle(h).run()`, which reports every `EffectError`")]
+    pub fn run_checked_with<H>(self, mut h: H) -> Result<R, UnhandledOp<Op>>
     where
         H: Handler<Op>,
     {
-        Ok(self.run_with(h))
+        Ok(self.run_unchecked(&mut h))
     }
 
     /// Attaches multiple handlers at once for processing effects.
@@ -1006,7 +2364,7 @@ impl<R, Op: 'static> Effectful<R, Op> {
     ///
     /// let result = complex_computation()
     ///     .handle_all([MathHandler, LoggerHandler, FileHandler])
-    ///     .run_checked()?;
+    ///     .run()?;
     /// ```
     pub fn handle_all<I, H>(self, iter: I) -> Handled<R, Op, VecHandler<Op>>
     where
@@ -1053,7 +2411,7 @@ impl<R, Op: 'static> Effectful<R, Op> {
 ///
 /// // .handle() returns a Handled<i32, Op, TestHandler>
 /// let handled = computation().handle(TestHandler);
-/// let result = handled.run(); // Execute the computation
+/// let result = handled.run()?; // Execute the computation
 /// assert_eq!(result, 42);
 /// ```
 pub struct Handled<R, Op: 'static, H> {
@@ -1063,22 +2421,40 @@ pub struct Handled<R, Op: 'static, H> {
     h: H,
 }
 
+/// Reports the computation's state and the handler: the chain of a
+/// [`VecHandler`], or the type name of any other handler.
+impl<R, Op: 'static, H: 'static> core::fmt::Debug for Handled<R, Op, H> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut debug = f.debug_struct("Handled");
+        debug.field("computation", &self.eff);
+        match (&self.h as &dyn Any).downcast_ref::<VecHandler<Op>>() {
+            Some(chain) => debug.field("handler", chain),
+            None => debug.field("handler", &core::any::type_name::<H>()),
+        };
+        debug.finish()
+    }
+}
+
 impl<R, Op: 'static, H: Handler<Op>> Handled<R, Op, H> {
     /// Executes the bundled effectful computation with its handler.
     ///
-    /// This is a convenience method that calls `run_with` on the effectful
-    /// computation using the bundled handler. This method may panic if
-    /// an operation is not handled.
-    ///
-    /// For a panic-free alternative, use `run_checked()` instead.
-    ///
-    /// # Returns
+    /// Operations the handler declines, aborts and replies of the wrong type
+    /// come back as an [`EffectError`] instead of a panic, so `run` is safe to
+    /// call on any handler, including chains of partial handlers built with
+    /// `.handle(..).handle(..)` or [`Effectful::handle_partial`]. It only
+    /// panics if the handler itself does, or answers a batch with a number of
+    /// replies other than its number of operations.
     ///
-    /// The final result of the effectful computation.
+    /// # Errors
     ///
-    /// # Panics
+    /// * [`EffectError::Unhandled`] - If the handler declined an operation,
+    ///   through [`Handler::try_reply`]
+    /// * [`EffectError::Aborted`] - If the handler replied with an [`Abort`]
+    /// * [`EffectError::ReplyMismatch`] - If the handler replied to an
+    ///   operation with a value of the wrong type
     ///
-    /// Panics if the handler does not handle an operation.
+    /// Use [`run_or_panic`](Self::run_or_panic) where these are bugs rather
+    /// than outcomes to handle, e.g. in tests.
     ///
     /// # Examples
     ///
@@ -1097,13 +2473,36 @@ impl<R, Op: 'static, H: Handler<Op>> Handled<R, Op, H> {
     ///     perform!(Test::GetValue)
     /// }
     ///
-    /// let result = computation()
-    ///     .handle(TestHandler)
-    ///     .run(); // This calls run()
-    /// assert_eq!(result, 42);
+    /// let result = computation().handle(TestHandler).run();
+    /// assert_eq!(result.unwrap(), 42);
     /// ```
-    pub fn run(self) -> R {
-        self.eff.run_with(self.h)
+    pub fn run(mut self) -> Result<R, EffectError<Op>> {
+        let handler = core::any::type_name::<H>();
+        self.eff.drive_as(handler, Checked(&mut self.h))
+    }
+
+    /// Executes the computation like [`run`](Self::run), returning its
+    /// result directly.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handler does not handle an operation, replies with an
+    /// [`Abort`], or replies with a value of the wrong type.
+    pub fn run_or_panic(mut self) -> R {
+        self.eff.run_unchecked(&mut self.h)
+    }
+
+    /// Executes the computation like [`run`](Self::run), and returns the
+    /// handler along with the outcome; see [`Effectful::run_with_handler`].
+    pub fn run_with_handler(self) -> (Result<R, EffectError<Op>>, H) {
+        self.eff.run_with_handler(self.h)
+    }
+}
+
+impl<R, Op: 'static, H> Handled<R, Op, H> {
+    /// Separates the computation from its handler without running it.
+    pub fn into_parts(self) -> (Effectful<R, Op>, H) {
+        (self.eff, self.h)
     }
 }
 
@@ -1113,13 +2512,13 @@ where
 {
     /// Executes the computation with the partial handler(s).
     ///
-    /// Unlike `run()`, this method returns a `Result` that indicates whether
-    /// all effects were successfully handled.
+    /// Unlike [`run`](Self::run), only unhandled operations are returned as
+    /// errors; aborts and mismatched replies panic.
     ///
     /// # Returns
     ///
     /// * `Ok(result)` - If all effects were handled
-    /// * `Err(UnhandledOp(op))` - If an effect was not handled
+    /// * `Err(UnhandledOp { op, .. })` - If an effect was not handled
     ///
     /// # Examples
     ///
@@ -1145,12 +2544,81 @@ where
     ///     .handle_all([MathHandler, LoggerHandler])
     ///     .run_checked() {
     ///     Ok(result) => println!("Result: {}", result),
-    ///     Err(UnhandledOp(op)) => eprintln!("Unhandled: {:?}", op),
+    ///     Err(UnhandledOp { op, .. }) => eprintln!("Unhandled: {:?}", op),
     /// }
     /// ```
+    #[deprecated(
+        note = "use `run`, with `Effectful::handle_partial` for a handler that only \
+                implements `PartialHandler`; it reports every `EffectError`"
+    )]
+    #[allow(deprecated)]
     pub fn run_checked(self) -> Result<R, UnhandledOp<Op>> {
         self.eff.run_checked(self.h)
     }
+
+    /// Executes the computation, returning unhandled operations and aborts as errors.
+    ///
+    /// This is the `Handled` counterpart of [`Effectful::try_run_with`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(result)` - If all effects were handled
+    /// * `Err(EffectError::Unhandled(_))` - If an effect was not handled
+    /// * `Err(EffectError::Aborted(_))` - If a handler aborted the computation
+    #[deprecated(
+        note = "use `run`, with `Effectful::handle_partial` for a handler that only \
+                implements `PartialHandler`; it reports every `EffectError`"
+    )]
+    pub fn try_run(self) -> Result<R, EffectError<Op>> {
+        self.eff.try_run_with(self.h)
+    }
+
+    /// Executes the computation like [`try_run`](Self::try_run), and returns
+    /// the handler along with the outcome; see
+    /// [`Effectful::try_run_with_handler`].
+    #[deprecated(
+        note = "use `run_with_handler`, with `Effectful::handle_partial` for a handler that \
+                only implements `PartialHandler`"
+    )]
+    pub fn try_run_with_handler(self) -> (Result<R, EffectError<Op>>, H) {
+        self.eff.try_run_with_handler(self.h)
+    }
+}
+
+impl<T, E, Op: 'static, H> Handled<Result<T, E>, Op, H>
+where
+    H: PartialHandler<Op>,
+{
+    /// Executes a computation that returns a `Result`, merging its error with
+    /// the effect errors of [`Effectful::try_run_with`].
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(value)` - If all effects were handled and the computation returned `Ok(value)`
+    /// * `Err(RunError::Failed(_))` - If the computation returned an error
+    /// * `Err(RunError::Unhandled(_))` - If an effect was not handled
+    /// * `Err(RunError::Aborted(_))` - If a handler aborted the computation
+    ///
+    /// # Examples
+    ///
+    /// ```rust,ignore
+    /// # #![feature(coroutines, coroutine_trait, yield_expr)]
+    /// # use algae::prelude::*;
+    /// # effect! { Math::Divide ((i32, i32)) -> Result<i32, String>; }
+    /// #[effectful]
+    /// fn halve(n: i32) -> Result<i32, String> {
+    ///     perform!(Math::Divide((n, 2)))
+    /// }
+    ///
+    /// match halve(10).handle(MathHandler).run_try() {
+    ///     Ok(half) => println!("{half}"),
+    ///     Err(RunError::Failed(msg)) => eprintln!("math error: {msg}"),
+    ///     Err(other) => eprintln!("{other}"),
+    /// }
+    /// ```
+    pub fn run_try(self) -> Result<T, RunError<E, Op>> {
+        self.eff.try_run_with(self.h)?.map_err(RunError::Failed)
+    }
 }
 
 /// Trait that all effect handlers must implement.
@@ -1248,6 +2716,49 @@ pub trait Handler<Op> {
     /// }
     /// ```
     fn handle(&mut self, op: &Op) -> Box<dyn Any + Send>;
+
+    /// Processes an effect operation like [`handle`](Self::handle), replying
+    /// with a [`Reply`].
+    ///
+    /// The `run*` drivers call this method. Override it to answer with
+    /// [`Reply::new`], which keeps small replies inline instead of boxing
+    /// them; by default it boxes the reply of `handle`.
+    fn reply(&mut self, op: &Op) -> Reply {
+        Reply::from_boxed(self.handle(op))
+    }
+
+    /// Processes a batch of operations performed together by `perform_all!`,
+    /// replying to each of them in order.
+    ///
+    /// Override it to answer the batch at once, e.g. with a single database
+    /// round-trip for many queries; by default each operation goes to
+    /// [`reply`](Self::reply).
+    fn handle_batch(&mut self, ops: &[Op]) -> Vec<Reply> {
+        ops.iter().map(|op| self.reply(op)).collect()
+    }
+
+    /// Replies like [`reply`](Self::reply), or declines an operation the
+    /// handler cannot answer after all.
+    ///
+    /// [`Handled::run`] calls this method, so that handlers made of partial
+    /// ones, such as [`VecHandler`], report an [`UnhandledOp`] instead of
+    /// panicking. By default it always replies.
+    fn try_reply(&mut self, op: &Op) -> Option<Reply> {
+        Some(self.reply(op))
+    }
+
+    /// Replies to a batch like [`handle_batch`](Self::handle_batch), or
+    /// declines it; see [`try_reply`](Self::try_reply).
+    fn try_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        Some(self.handle_batch(ops))
+    }
+
+    /// The type names of the handlers an operation this handler declines
+    /// was offered to, reported in [`UnhandledOp::declined`]; see
+    /// [`PartialHandler::handler_names`].
+    fn handler_names(&self) -> Vec<&'static str> {
+        vec![core::any::type_name::<Self>()]
+    }
 }
 
 /// Trait for handlers that can selectively handle operations.
@@ -1291,42 +2802,183 @@ pub trait PartialHandler<Op> {
     /// * `Some(result)` - If this handler processed the operation
     /// * `None` - If this handler declines to handle this operation
     fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>>;
-}
 
-/// Implementation of PartialHandler for `Box<dyn PartialHandler>`
-impl<Op> PartialHandler<Op> for Box<dyn PartialHandler<Op> + Send> {
-    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-        (**self).maybe_handle(op)
+    /// Attempts to process an operation like
+    /// [`maybe_handle`](Self::maybe_handle), replying with a [`Reply`].
+    ///
+    /// The `run*` drivers call this method. Override it to answer with
+    /// [`Reply::new`], which keeps small replies inline instead of boxing
+    /// them; by default it boxes the reply of `maybe_handle`.
+    fn maybe_reply(&mut self, op: &Op) -> Option<Reply> {
+        self.maybe_handle(op).map(Reply::from_boxed)
     }
-}
 
-/// A dynamic collection of partial handlers that attempts each in order.
-///
-/// `VecHandler` allows composing multiple partial handlers at runtime. When handling
-/// an operation, it tries each handler in insertion order until one accepts the operation.
-///
-/// # Type Parameters
-///
-/// * `Op` - The type of operations these handlers process
-///
-/// # Examples
-///
-/// ```rust,ignore
-/// # #![feature(coroutines, coroutine_trait, yield_expr)]
-/// # use algae::prelude::*;
-/// let mut vec_handler = VecHandler::<Op>::new();
-/// vec_handler.push(MathHandler);
+    /// Attempts to process a batch of operations performed together by
+    /// `perform_all!`, returning a reply to each of them in order, or `None`
+    /// to decline the whole batch.
+    ///
+    /// Override it to answer the batch at once, e.g. with a single database
+    /// round-trip for many queries. By default each operation goes to
+    /// [`maybe_reply`](Self::maybe_reply): the batch is declined if every
+    /// operation is, and the operations declined in a batch answered in part
+    /// are offered to the handlers after this one, like single operations.
+    fn maybe_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        reply_each(ops, |op| self.maybe_reply(op))
+    }
+
+    /// The type names of the handlers an operation this handler declines
+    /// was offered to, reported in [`UnhandledOp::declined`].
+    ///
+    /// By default it is this handler's own type name; handlers made of
+    /// others, such as [`VecHandler`], list theirs instead.
+    fn handler_names(&self) -> Vec<&'static str> {
+        vec![core::any::type_name::<Self>()]
+    }
+
+    /// Routes the operations this handler declines to `other`.
+    ///
+    /// The result is a total [`Handler`] when `other` is. See
+    /// [`combinators`] for the other ways of combining handlers.
+    fn or_else<H>(self, other: H) -> combinators::OrElse<Self, H>
+    where
+        Self: Sized,
+        H: PartialHandler<Op>,
+    {
+        combinators::OrElse::new(self, other)
+    }
+
+    /// Restricts this handler to the operations `predicate` accepts; the
+    /// others are declined without reaching it.
+    ///
+    /// A batch is declined unless `predicate` accepts all of its operations.
+    fn filter<P>(self, predicate: P) -> combinators::Filter<Self, P>
+    where
+        Self: Sized,
+        P: FnMut(&Op) -> bool,
+    {
+        combinators::Filter::new(self, predicate)
+    }
+
+    /// Replaces each reply of this handler with `f(op, reply)`.
+    fn map_reply<F>(self, f: F) -> combinators::MapReply<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Op, Reply) -> Reply,
+    {
+        combinators::MapReply::new(self, f)
+    }
+
+    /// Passes each reply of this handler through `f`, which replaces it with
+    /// `Some`, or declines the operation after all with `None`.
+    ///
+    /// A batch is declined if `f` declines any of its operations.
+    fn and_then<F>(self, f: F) -> combinators::AndThen<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Op, Reply) -> Option<Reply>,
+    {
+        combinators::AndThen::new(self, f)
+    }
+}
+
+/// Implementation of PartialHandler for `Box<dyn PartialHandler>`
+impl<Op> PartialHandler<Op> for Box<dyn PartialHandler<Op> + Send> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        (**self).maybe_handle(op)
+    }
+
+    fn maybe_reply(&mut self, op: &Op) -> Option<Reply> {
+        (**self).maybe_reply(op)
+    }
+
+    fn maybe_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        (**self).maybe_handle_batch(ops)
+    }
+
+    fn handler_names(&self) -> Vec<&'static str> {
+        (**self).handler_names()
+    }
+}
+
+/// Builds a fresh handler chain for each run.
+///
+/// Runtimes that start many computations take a factory instead of a
+/// handler: [`par::run_all`](crate::par) builds one per worker,
+/// [`executor::Spawner::spawn_with`] one per task, and
+/// `#[algae_test(fixture = ...)]` one per test. Any `Fn() -> H` is a
+/// factory; implement the trait for configuration structs that know how to
+/// assemble a chain.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// struct App {
+///     db_url: String,
+/// }
+///
+/// impl HandlerFactory<Op> for App {
+///     type Handler = VecHandler<Op>;
+///
+///     fn build(&self) -> VecHandler<Op> {
+///         let mut handlers = VecHandler::new();
+///         handlers.push(Db::connect(&self.db_url));
+///         handlers.push(StdTime);
+///         handlers
+///     }
+/// }
+/// ```
+pub trait HandlerFactory<Op> {
+    /// The handler chain built for a run.
+    type Handler: PartialHandler<Op>;
+
+    /// Builds a handler chain for one run.
+    fn build(&self) -> Self::Handler;
+}
+
+impl<Op, H, F> HandlerFactory<Op> for F
+where
+    H: PartialHandler<Op>,
+    F: Fn() -> H,
+{
+    type Handler = H;
+
+    fn build(&self) -> H {
+        self()
+    }
+}
+
+/// A dynamic collection of partial handlers that attempts each in order.
+///
+/// `VecHandler` allows composing multiple partial handlers at runtime. When handling
+/// an operation, it tries each handler in insertion order until one accepts the operation.
+///
+/// # Type Parameters
+///
+/// * `Op` - The type of operations these handlers process
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// # #![feature(coroutines, coroutine_trait, yield_expr)]
+/// # use algae::prelude::*;
+/// let mut vec_handler = VecHandler::<Op>::new();
+/// vec_handler.push(MathHandler);
 /// vec_handler.push(LoggerHandler);
 /// vec_handler.push(FileHandler);
 /// ```
 pub struct VecHandler<Op> {
     inner: Vec<Box<dyn PartialHandler<Op> + Send>>,
+    /// The type name of each handler, for `Debug`
+    names: Vec<&'static str>,
 }
 
 impl<Op> VecHandler<Op> {
     /// Creates a new empty handler collection.
     pub fn new() -> Self {
-        Self { inner: Vec::new() }
+        Self {
+            inner: Vec::new(),
+            names: Vec::new(),
+        }
     }
 
     /// Adds a handler to the collection.
@@ -1341,6 +2993,7 @@ impl<Op> VecHandler<Op> {
         H: PartialHandler<Op> + Send + 'static,
     {
         self.inner.push(Box::new(h));
+        self.names.push(core::any::type_name::<H>());
     }
 
     /// Extends this handler collection with all handlers from another VecHandler.
@@ -1352,6 +3005,14 @@ impl<Op> VecHandler<Op> {
     /// * `other` - The VecHandler whose handlers to add
     pub fn extend_from(&mut self, other: VecHandler<Op>) {
         self.inner.extend(other.inner);
+        self.names.extend(other.names);
+    }
+}
+
+/// Lists the type names of the handlers, in the order they are tried.
+impl<Op> core::fmt::Debug for VecHandler<Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("VecHandler").field(&self.names).finish()
     }
 }
 
@@ -1370,12 +3031,32 @@ impl<Op> PartialHandler<Op> for VecHandler<Op> {
         }
         None
     }
+
+    fn maybe_reply(&mut self, op: &Op) -> Option<Reply> {
+        self.inner.iter_mut().find_map(|h| h.maybe_reply(op))
+    }
+
+    /// Offers the batch whole to each handler in turn, and the operations
+    /// the first one to answer it declines to the handlers after it, one at
+    /// a time.
+    fn maybe_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        let mut handlers = self.inner.iter_mut();
+        let mut replies = handlers.find_map(|h| h.maybe_handle_batch(ops))?;
+        for h in handlers {
+            answer_rest(ops, &mut replies, |op| h.maybe_reply(op));
+        }
+        Some(replies)
+    }
+
+    fn handler_names(&self) -> Vec<&'static str> {
+        self.inner.iter().flat_map(|h| h.handler_names()).collect()
+    }
 }
 
 /// Handler implementation for VecHandler that returns Result instead of panicking
 impl<Op> Handler<Op> for VecHandler<Op>
 where
-    Op: std::fmt::Debug + 'static,
+    Op: core::fmt::Debug + 'static,
 {
     fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
         match self.maybe_handle(op) {
@@ -1387,12 +3068,46 @@ where
             }
         }
     }
+
+    fn handle_batch(&mut self, ops: &[Op]) -> Vec<Reply> {
+        let replies = self.maybe_handle_batch(ops).unwrap_or_default();
+        let unanswered = match replies.len() {
+            0 => ops.first(),
+            _ => ops
+                .iter()
+                .zip(&replies)
+                .find_map(|(op, reply)| reply.is::<Unanswered>().then_some(op)),
+        };
+        match unanswered {
+            Some(op) => panic!("Unhandled operation: {op:?}"),
+            None => replies,
+        }
+    }
+
+    fn try_reply(&mut self, op: &Op) -> Option<Reply> {
+        self.maybe_reply(op)
+    }
+
+    fn try_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        self.maybe_handle_batch(ops)
+    }
+
+    fn handler_names(&self) -> Vec<&'static str> {
+        PartialHandler::handler_names(self)
+    }
 }
 
 /// Error type returned when an effect operation has no handler.
 ///
 /// This error contains the unhandled operation, allowing the caller to inspect
-/// what went wrong and potentially recover.
+/// what went wrong and potentially recover, along with where the computation
+/// performed it and which handlers declined it. Its `Display` puts these
+/// together:
+///
+/// ```text
+/// Unhandled operation: Logger(Info("ready")), performed as operation 3 of
+/// app::startup at src/app.rs:42:9, declined by [app::MathHandler, app::FileHandler]
+/// ```
 ///
 /// # Type Parameters
 ///
@@ -1403,13 +3118,91 @@ where
 /// ```rust,ignore
 /// # #![feature(coroutines, coroutine_trait, yield_expr)]
 /// # use algae::prelude::*;
-/// match computation.run_checked(handler) {
+/// match computation.handle(handler).run() {
 ///     Ok(result) => println!("Success: {}", result),
-///     Err(UnhandledOp(op)) => eprintln!("Unhandled operation: {:?}", op),
+///     Err(EffectError::Unhandled(UnhandledOp { op, declined, .. })) => {
+///         eprintln!("Unhandled operation: {:?} (tried {:?})", op, declined)
+///     }
+///     Err(other) => eprintln!("{other}"),
 /// }
 /// ```
-#[derive(Debug, PartialEq)]
-pub struct UnhandledOp<Op>(pub Op);
+///
+/// Its `Display` and `Debug` output render the operation with
+/// [`redact::redacted`], so the payloads of operations tagged `sensitive` do
+/// not end up in error messages and logs.
+#[derive(PartialEq)]
+pub struct UnhandledOp<Op> {
+    /// The operation no handler accepted
+    pub op: Op,
+    /// How many operations the computation had performed before this one;
+    /// a batch counts as one
+    pub index: usize,
+    /// The `#[effectful]` function that performed the operation, if it was
+    /// one
+    pub function: Option<&'static str>,
+    /// Source location of the `perform!` that performed the operation
+    pub location: Option<&'static Location<'static>>,
+    /// The type names of the handlers that declined the operation, in the
+    /// order they were tried
+    pub declined: Vec<&'static str>,
+}
+
+impl<Op> UnhandledOp<Op> {
+    /// An unhandled `op`, without any context.
+    pub fn new(op: Op) -> Self {
+        Self {
+            op,
+            index: 0,
+            function: None,
+            location: None,
+            declined: Vec::new(),
+        }
+    }
+
+    /// The context of the effect that performed `op`; the driver running
+    /// it fills in `declined`.
+    fn of(eff: Effect<Op>) -> Self {
+        Self {
+            index: eff.index,
+            function: eff.function,
+            location: Some(eff.location),
+            ..Self::new(eff.op)
+        }
+    }
+}
+
+impl<Op: core::fmt::Debug + OpMeta> core::fmt::Debug for UnhandledOp<Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("UnhandledOp")
+            .field("op", &format_args!("{}", redact::redacted(&self.op)))
+            .field("index", &self.index)
+            .field("function", &self.function)
+            .field("location", &self.location)
+            .field("declined", &self.declined)
+            .finish()
+    }
+}
+
+impl<Op: core::fmt::Debug + OpMeta> core::fmt::Display for UnhandledOp<Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Unhandled operation: {}", redact::redacted(&self.op))?;
+        if self.function.is_some() || self.location.is_some() {
+            write!(f, ", performed as operation {}", self.index)?;
+        }
+        if let Some(function) = self.function {
+            write!(f, " of {function}")?;
+        }
+        if let Some(location) = self.location {
+            write!(f, " at {location}")?;
+        }
+        if !self.declined.is_empty() {
+            write!(f, ", declined by [{}]", self.declined.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<Op: core::fmt::Debug + OpMeta> core::error::Error for UnhandledOp<Op> {}
 
 /// Error type returned when an effect operation has no handler (operation name only).
 ///
@@ -1421,9 +3214,13 @@ pub struct UnhandledOp<Op>(pub Op);
 /// ```rust,ignore
 /// # #![feature(coroutines, coroutine_trait, yield_expr)]
 /// # use algae::prelude::*;
-/// match computation.run_checked(handler) {
+/// match computation.handle(handler).run() {
 ///     Ok(result) => println!("Success: {}", result),
-///     Err(err) => eprintln!("Unhandled operation: {}", err.op_name),
+///     Err(EffectError::Unhandled(unhandled)) => {
+///         let err = UnhandledOpError::from(unhandled);
+///         eprintln!("Unhandled operation: {}", err.op_name)
+///     }
+///     Err(other) => eprintln!("{other}"),
 /// }
 /// ```
 #[derive(Debug, PartialEq, Clone)]
@@ -1432,10 +3229,10 @@ pub struct UnhandledOpError {
     pub op_name: &'static str,
 }
 
-impl<Op: std::fmt::Debug> From<UnhandledOp<Op>> for UnhandledOpError {
+impl<Op: core::fmt::Debug> From<UnhandledOp<Op>> for UnhandledOpError {
     fn from(unhandled: UnhandledOp<Op>) -> Self {
         // Get the debug representation and extract the type name
-        let _debug_str = format!("{:?}", unhandled.0);
+        let _debug_str = format!("{:?}", unhandled.op);
         // This is a simple heuristic - in practice you might want something more sophisticated
         UnhandledOpError {
             op_name: "UnknownOp", // We'll use a static string for simplicity
@@ -1443,1046 +3240,3598 @@ impl<Op: std::fmt::Debug> From<UnhandledOp<Op>> for UnhandledOpError {
     }
 }
 
-/// Trait to enable conversion from Handler to PartialHandler
-pub trait IntoPartialHandler<Op> {
-    /// The resulting partial handler type
-    type Output: PartialHandler<Op> + Send + 'static;
+/// A handler reply that terminates the computation instead of resuming it.
+///
+/// Handlers normally answer an operation with the value the effectful code is
+/// waiting for. Replying with an `Abort` instead tells the runtime to stop: the
+/// coroutine is dropped at the suspended `perform!` and the abort is reported
+/// as [`EffectError::Aborted`] by [`Effectful::try_run_with`] and
+/// [`Handled::try_run`]. The panicking `run*` methods panic with the abort's
+/// description.
+///
+/// The abort carries an arbitrary error value so that callers can recover
+/// structured information with [`Abort::downcast_ref`] or [`Abort::downcast`].
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// # #![feature(coroutines, coroutine_trait, yield_expr)]
+/// # use algae::prelude::*;
+/// # effect! { Auth::Login (String) -> bool; }
+/// #[derive(Debug, PartialEq)]
+/// struct Banned(String);
+///
+/// struct AuthHandler;
+/// impl PartialHandler<Op> for AuthHandler {
+///     fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn std::any::Any + Send>> {
+///         match op {
+///             Op::Auth(Auth::Login(user)) if user == "mallory" => {
+///                 Some(Abort::boxed(Banned(user.clone())))
+///             }
+///             Op::Auth(Auth::Login(_)) => Some(Box::new(true)),
+///         }
+///     }
+/// }
+///
+/// let err = login("mallory").try_run_with(AuthHandler).unwrap_err();
+/// if let EffectError::Aborted(abort) = err {
+///     assert_eq!(abort.downcast_ref::<Banned>(), Some(&Banned("mallory".into())));
+/// }
+/// ```
+pub struct Abort {
+    error: Box<dyn Any + Send>,
+    description: String,
+}
 
-    /// Convert to a partial handler
-    fn into_partial_handler(self) -> Self::Output;
+impl Abort {
+    /// Creates an abort carrying `error`.
+    ///
+    /// The error's `Debug` output is captured eagerly so that the abort can be
+    /// displayed without knowing the concrete error type.
+    pub fn new<E>(error: E) -> Self
+    where
+        E: core::fmt::Debug + Any + Send,
+    {
+        Self {
+            description: format!("{error:?}"),
+            error: Box::new(error),
+        }
+    }
+
+    /// Creates an abort and boxes it, ready to be returned from a handler.
+    pub fn boxed<E>(error: E) -> Box<dyn Any + Send>
+    where
+        E: core::fmt::Debug + Any + Send,
+    {
+        Box::new(Self::new(error))
+    }
+
+    /// Returns `true` if the carried error is of type `E`.
+    pub fn is<E: Any>(&self) -> bool {
+        self.error.is::<E>()
+    }
+
+    /// Returns a reference to the carried error if it is of type `E`.
+    pub fn downcast_ref<E: Any>(&self) -> Option<&E> {
+        self.error.downcast_ref::<E>()
+    }
+
+    /// Extracts the carried error if it is of type `E`, or gives the abort back.
+    pub fn downcast<E: Any>(self) -> Result<E, Self> {
+        match self.error.downcast::<E>() {
+            Ok(error) => Ok(*error),
+            Err(error) => Err(Self {
+                error,
+                description: self.description,
+            }),
+        }
+    }
+
+    /// The `Debug` rendering of the carried error.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
 }
 
-// Implementation for types that already implement PartialHandler
-impl<Op, H> IntoPartialHandler<Op> for H
-where
-    H: PartialHandler<Op> + Send + 'static,
-{
-    type Output = H;
+impl core::fmt::Debug for Abort {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Abort")
+            .field(&format_args!("{}", self.description))
+            .finish()
+    }
+}
 
-    fn into_partial_handler(self) -> Self::Output {
-        self
+impl core::fmt::Display for Abort {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.description)
     }
 }
 
-/// Trait for converting any handler into a VecHandler for flattening
-pub trait IntoVecHandler<Op> {
-    /// Convert this handler into a VecHandler
-    fn into_vec_handler(self) -> VecHandler<Op>;
+impl core::error::Error for Abort {}
+
+/// Every way an effectful computation can stop without producing its result.
+///
+/// Returned by [`Effectful::try_run_with`] and [`Handled::try_run`].
+///
+/// # Type Parameters
+///
+/// * `Op` - The operation type of the computation
+pub enum EffectError<Op> {
+    /// No handler accepted the operation.
+    Unhandled(UnhandledOp<Op>),
+    /// A handler replied with an [`Abort`].
+    Aborted(Abort),
+    /// A handler replied with a value of another type than the one
+    /// `perform!` takes the reply as.
+    ReplyMismatch(ReplyError),
 }
 
-// Implementation for VecHandler - return as-is (already flat)
-impl<Op> IntoVecHandler<Op> for VecHandler<Op> {
-    fn into_vec_handler(self) -> VecHandler<Op> {
-        self
+impl<Op> From<UnhandledOp<Op>> for EffectError<Op> {
+    fn from(unhandled: UnhandledOp<Op>) -> Self {
+        EffectError::Unhandled(unhandled)
     }
 }
 
-// Implementation for Box<dyn PartialHandler> - wrap in VecHandler
-impl<Op> IntoVecHandler<Op> for Box<dyn PartialHandler<Op> + Send> {
-    fn into_vec_handler(self) -> VecHandler<Op> {
-        let mut vec = VecHandler::new();
-        vec.inner.push(self);
-        vec
+impl<Op> From<Abort> for EffectError<Op> {
+    fn from(abort: Abort) -> Self {
+        EffectError::Aborted(abort)
     }
 }
 
-/// Macro to implement IntoVecHandler for a type that implements PartialHandler
-#[macro_export]
-macro_rules! impl_into_vec_handler {
-    ($handler:ty, $op:ty) => {
-        impl $crate::IntoVecHandler<$op> for $handler {
-            fn into_vec_handler(self) -> $crate::VecHandler<$op> {
-                let mut vec = $crate::VecHandler::new();
-                vec.push(self);
-                vec
+impl<Op: core::fmt::Debug + OpMeta> core::fmt::Debug for EffectError<Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EffectError::Unhandled(unhandled) => {
+                f.debug_tuple("Unhandled").field(unhandled).finish()
+            }
+            EffectError::Aborted(abort) => f.debug_tuple("Aborted").field(abort).finish(),
+            EffectError::ReplyMismatch(error) => {
+                f.debug_tuple("ReplyMismatch").field(error).finish()
             }
         }
-    };
+    }
 }
 
-/// Wrapper to make Handler trait implement PartialHandler  
-pub struct HandlerWrapper<Op, H> {
-    handler: H,
-    _phantom: std::marker::PhantomData<Op>,
+impl<Op: core::fmt::Debug + OpMeta> core::fmt::Display for EffectError<Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EffectError::Unhandled(unhandled) => write!(f, "{unhandled}"),
+            EffectError::Aborted(abort) => write!(f, "Effectful computation aborted: {abort}"),
+            EffectError::ReplyMismatch(error) => write!(f, "{error}"),
+        }
+    }
 }
 
-impl<Op, H> HandlerWrapper<Op, H> {
-    pub fn new(handler: H) -> Self {
-        Self {
-            handler,
-            _phantom: std::marker::PhantomData,
+impl<Op: core::fmt::Debug + OpMeta> core::error::Error for EffectError<Op> {}
+
+/// Every way a computation returning `Result<T, E>` can fail: the effect
+/// errors of [`EffectError`], or its own error `E`.
+///
+/// Returned by [`Handled::run_try`].
+///
+/// # Type Parameters
+///
+/// * `E` - The error type of the computation's result
+/// * `Op` - The operation type of the computation
+pub enum RunError<E, Op> {
+    /// No handler accepted the operation.
+    Unhandled(UnhandledOp<Op>),
+    /// A handler replied with an [`Abort`].
+    Aborted(Abort),
+    /// A handler replied with a value of another type than the one
+    /// `perform!` takes the reply as.
+    ReplyMismatch(ReplyError),
+    /// The computation ran to completion and returned `Err`.
+    Failed(E),
+}
+
+impl<E, Op> From<EffectError<Op>> for RunError<E, Op> {
+    fn from(error: EffectError<Op>) -> Self {
+        match error {
+            EffectError::Unhandled(unhandled) => RunError::Unhandled(unhandled),
+            EffectError::Aborted(abort) => RunError::Aborted(abort),
+            EffectError::ReplyMismatch(error) => RunError::ReplyMismatch(error),
         }
     }
 }
 
-impl<Op, H> PartialHandler<Op> for HandlerWrapper<Op, H>
+impl<E: core::fmt::Debug, Op: core::fmt::Debug + OpMeta> core::fmt::Debug for RunError<E, Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RunError::Unhandled(unhandled) => f.debug_tuple("Unhandled").field(unhandled).finish(),
+            RunError::Aborted(abort) => f.debug_tuple("Aborted").field(abort).finish(),
+            RunError::ReplyMismatch(error) => f.debug_tuple("ReplyMismatch").field(error).finish(),
+            RunError::Failed(error) => f.debug_tuple("Failed").field(error).finish(),
+        }
+    }
+}
+
+impl<E: core::fmt::Display, Op: core::fmt::Debug + OpMeta> core::fmt::Display for RunError<E, Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RunError::Unhandled(unhandled) => write!(f, "{unhandled}"),
+            RunError::Aborted(abort) => write!(f, "Effectful computation aborted: {abort}"),
+            RunError::ReplyMismatch(error) => write!(f, "{error}"),
+            RunError::Failed(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl<E, Op> core::error::Error for RunError<E, Op>
 where
-    H: Handler<Op>,
+    E: core::error::Error + 'static,
+    Op: core::fmt::Debug + OpMeta,
 {
-    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-        Some(self.handler.handle(op))
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            RunError::Failed(error) => Some(error),
+            _ => None,
+        }
     }
 }
 
-// Special case for VecHandler to enable efficient chaining
-impl<R, Op: 'static + Send> Handled<R, Op, VecHandler<Op>> {
-    /// Adds another handler to an existing VecHandler chain.
-    ///
-    /// This specialized implementation efficiently adds to an existing VecHandler
-    /// without creating a new one. If the added handler is itself a VecHandler,
-    /// its contents are flattened into the existing collection.
-    pub fn handle<H2>(self, h2: H2) -> Handled<R, Op, VecHandler<Op>>
-    where
-        H2: IntoVecHandler<Op>,
-    {
-        let mut result = self.h;
-        let other = h2.into_vec_handler();
-        result.extend_from(other);
-        Handled {
-            eff: self.eff,
-            h: result,
-        }
-    }
+/// Root operation types that embed the effect family `F`.
+///
+/// The `effect!` macro implements this for every family of the root enum it
+/// generates, including families embedded with `use` lines. Handlers for a
+/// single family can then be written once and reused with any root type:
+///
+/// ```rust,ignore
+/// # use algae::prelude::*;
+/// # use algae::effects::progress::Progress;
+/// struct CountStarts(usize);
+///
+/// impl<Op: Contains<Progress>> PartialHandler<Op> for CountStarts {
+///     fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn std::any::Any + Send>> {
+///         match op.project()? {
+///             Progress::Start(_) => { self.0 += 1; None }
+///             _ => None,
+///         }
+///     }
+/// }
+/// ```
+pub trait Contains<F>: From<F> {
+    /// Returns the family operation if `self` belongs to family `F`.
+    fn project(&self) -> Option<&F>;
+}
 
-    /// Adds a total handler to an existing VecHandler chain.
-    ///
-    /// This method wraps a Handler type to work with the partial handler system.
-    pub fn handle_total<H2>(mut self, h2: H2) -> Handled<R, Op, VecHandler<Op>>
-    where
-        H2: Handler<Op> + Send + 'static,
-    {
-        self.h.push(HandlerWrapper::new(h2));
-        self
+impl<F> Contains<F> for F {
+    fn project(&self) -> Option<&F> {
+        Some(self)
     }
 }
 
-/// Convenience module that re-exports everything needed to use algae.
-///
-/// This module provides a single import point for all the essential types
-/// and macros needed to work with algebraic effects. Users can simply
-/// `use algae::prelude::*` to get started.
+/// Static metadata about an operation.
 ///
-/// # Re-exported Items
+/// The `effect!` macro implements this for every family enum from the
+/// annotations on its op lines, and for the root enum by delegating to the
+/// families. Runtime layers such as [`retry::RetryLayer`] read it to apply
+/// policies declared next to the operations instead of in handlers.
 ///
-/// ## Core Types
-/// - [`Effect`] - Represents an effect operation with space for a reply
-/// - [`Effectful`] - Wrapper around effectful computations  
-/// - [`Handler`] - Trait for implementing effect handlers
-/// - [`Reply`] - Container for handler response values
-/// - [`PartialHandler`] - Trait for handlers that may decline operations
-/// - [`VecHandler`] - Collection of handlers tried in order
-/// - [`UnhandledOp`] - Error returned when no handler handles an operation
-/// - [`UnhandledOpError`] - Lightweight error with just operation name
-/// - [`IntoPartialHandler`] - Trait for converting handlers to PartialHandler
-/// - [`IntoVecHandler`] - Trait for converting handlers to VecHandler with flattening
+/// Every method has a default, so hand-written operation types can opt in
+/// with an empty `impl OpMeta for MyOp {}`.
+pub trait OpMeta {
+    /// The policy declared with `#[retryable(...)]`, if any.
+    fn retry_policy(&self) -> Option<retry::RetryPolicy> {
+        None
+    }
+
+    /// Whether the operation was declared with `#[mutates]`, i.e. it changes
+    /// the state of its handler. [`eventsource::EventSourced`] persists these.
+    fn mutates_state(&self) -> bool {
+        false
+    }
+
+    /// A reply made with [`Default`], if the reply type declared in
+    /// `effect!` implements it. [`dryrun::DryRunHandler`] answers the writes
+    /// it skips with these.
+    fn default_reply(&self) -> Option<Box<dyn Any + Send>> {
+        None
+    }
+
+    /// The names given with `#[tag(...)]`, in declaration order.
+    fn tags(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Whether the operation was tagged `tag`, e.g. with `#[tag(io)]`.
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags().contains(&tag)
+    }
+
+    /// How urgently the operation should be serviced, declared with
+    /// `#[priority(...)]`; [`priority::Priority::Normal`] if not declared.
+    fn priority(&self) -> priority::Priority {
+        priority::Priority::Normal
+    }
+
+    /// The name of the operation's family, e.g. `"Console"` for
+    /// `Op::Console(Console::Print(..))`; the type's name by default.
+    fn family(&self) -> &'static str {
+        let name = core::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    /// The name of the operation within its family, e.g. `"Print"`; the
+    /// type's name by default.
+    fn name(&self) -> &'static str {
+        self.family()
+    }
+
+    /// The family and operation names joined with a dot, e.g.
+    /// `"Console.Print"`: a stable identifier for metrics and logs.
+    fn qualified_name(&self) -> &'static str {
+        self.name()
+    }
+}
+
+/// Runtime access to the family operation inside a root operation.
 ///
-/// ## Macros (when "macros" feature is enabled)
-/// - `effect!` - Macro for defining effect families and operations
-/// - `effectful` - Attribute macro for marking functions as effectful
-/// - `perform!` - Macro for performing effects within effectful functions
+/// The `effect!` macro implements this for every root enum. It lets a handler
+/// pick out the operations of the families it knows by downcasting, without
+/// requiring the root to [`Contains`] each of them; see
+/// [`testing::DeterministicEnv`].
+pub trait FamilyOp {
+    /// The wrapped family operation, e.g. the `Time::Now` in
+    /// `Op::Time(Time::Now)`.
+    fn family_op(&self) -> &dyn Any;
+}
+
+/// A single operation together with the type of its reply.
 ///
-/// # Examples
+/// The helper functions that `effect!` generates with the `helpers;` header
+/// return a `PerformsOne`, so the reply type declared in `effect!` travels with
+/// the operation. `perform!` reads it from there instead of inferring it from
+/// the surrounding code, which turns a mismatched annotation into a compile
+/// error. `perform!` checks operations written as `Family::Variant(..)` the
+/// same way, against the reply type declared on their line:
 ///
-/// ## With macros (default):
-/// ```rust,ignore
-/// #![feature(coroutines, coroutine_trait, yield_expr)]
+/// ```compile_fail,E0308
+/// #![feature(coroutines, yield_expr)]
 /// use algae::prelude::*;
 ///
-/// // Define effects
 /// effect! {
-///     Counter::Get -> i32;
-///     Counter::Set (i32) -> ();
+///     Math::Add ((i32, i32)) -> i32;
 /// }
 ///
-/// // Create effectful functions
 /// #[effectful]
-/// fn increment() -> i32 {
-///     let current: i32 = perform!(Counter::Get);
-///     let _: () = perform!(Counter::Set(current + 1));
-///     current + 1
+/// fn sum() -> String {
+///     let total: String = perform!(Math::Add((1, 2)));
+///     total
 /// }
+/// # fn main() {}
+/// ```
 ///
-/// // Implement handlers
-/// struct CounterHandler { value: i32 }
+/// With helpers:
 ///
-/// impl Handler<Op> for CounterHandler {
-///     fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-///         match op {
-///             Op::Counter(Counter::Get) => Box::new(self.value),
-///             Op::Counter(Counter::Set(new_val)) => {
-///                 self.value = *new_val;
-///                 Box::new(())
-///             }
-///         }
-///     }
+/// ```rust,ignore
+/// effect! {
+///     helpers;
+///     Console::Print (String) -> ();
+///     Console::ReadLine -> String;
 /// }
 ///
-/// // Run computations
-/// let result = increment()
-///     .handle(CounterHandler { value: 5 })
-///     .run();
-/// assert_eq!(result, 6);
+/// #[effectful]
+/// fn greet() -> String {
+///     perform!(console::print("What's your name?".into()));
+///     let name = perform!(console::read_line());
+///     format!("Hello, {name}!")
+/// }
+///
+/// // Outside an effectful function the operation can be run on its own:
+/// let name = console::read_line().perform().handle(StdinConsole).run_or_panic();
 /// ```
 ///
-/// ## Without macros (manual approach):
-/// When using algae without the "macros" feature, you need to define
-/// your effect types and handlers manually using the core types.
-pub mod prelude {
-    pub use crate::{
-        register_type, Effect, Effectful, Handler, HandlerWrapper, IntoPartialHandler,
-        IntoVecHandler, PartialHandler, Reply, ReplyError, UnhandledOp, UnhandledOpError,
-        VecHandler,
-    };
+/// # Type Parameters
+///
+/// * `R` - The reply type declared for the operation
+/// * `Op` - The root operation type
+pub struct PerformsOne<R, Op> {
+    op: Op,
+    _reply: core::marker::PhantomData<fn() -> R>,
+}
 
-    #[cfg(feature = "macros")]
-    pub use algae_macros::{effect, effectful, perform};
+impl<R, Op> PerformsOne<R, Op> {
+    /// Pairs `op` with the reply type `R`.
+    pub fn new(op: Op) -> Self {
+        Self {
+            op,
+            _reply: core::marker::PhantomData,
+        }
+    }
+
+    /// The operation that will be performed.
+    pub fn op(&self) -> &Op {
+        &self.op
+    }
+
+    /// Discards the reply type and returns the bare operation.
+    pub fn into_op(self) -> Op {
+        self.op
+    }
+
+    /// A computation that performs the operation and returns its reply.
+    pub fn perform(self) -> Effectful<R, Op>
+    where
+        R: Any + Send,
+        Op: Send,
+    {
+        let op = self.op;
+        Effectful::new(
+            #[coroutine]
+            move |_: Option<Reply>| {
+                let reply = yield Effect::new(op);
+                reply
+                    .expect("the runtime resumes every effect with a reply")
+                    .take::<R>()
+            },
+        )
+    }
 }
 
-/// Helper macro for combining multiple root enums into one unified enum.
+impl<R, Op: core::fmt::Debug> core::fmt::Debug for PerformsOne<R, Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("PerformsOne")
+            .field(&self.op)
+            .field(&format_args!("{}", core::any::type_name::<R>()))
+            .finish()
+    }
+}
+
+/// Operations that can be answered by a [`TypedHandler`].
 ///
-/// This macro allows you to merge effect operations from different modules
-/// that use custom root enum names into a single root enum for easier handling.
+/// The `effect!` macro implements this with the `typed;` header. The call
+/// of an operation borrows its payload and carries a [`Respond`] for the
+/// reply type declared in `effect!`:
 ///
-/// # Syntax
+/// ```rust,ignore
+/// effect! {
+///     typed;
+///     Console::Print (String) -> ();
+///     Console::ReadLine -> String;
+/// }
 ///
-/// ```ignore
-/// combine_roots!(pub Op = module_a::ConsoleOp, module_b::FileOp, module_c::NetOp);
+/// // Generates, roughly:
+/// pub enum ConsoleCall<'a> {
+///     Print(&'a String, algae::Respond<()>),
+///     ReadLine(algae::Respond<String>),
+/// }
+/// pub enum OpCall<'a> {
+///     Console(ConsoleCall<'a>),
+/// }
 /// ```
+pub trait TypedOp {
+    /// The operation as seen by a typed handler.
+    type Call<'a>
+    where
+        Self: 'a;
+
+    /// Borrows the operation as a call.
+    fn as_call(&self) -> Self::Call<'_>;
+}
+
+/// The obligation to answer a typed operation with a `T`.
 ///
-/// This generates:
-/// - A new enum with the specified name containing all the other enums as variants
-/// - `From` implementations to convert each source enum to the combined enum
-///
-/// # Example
-///
-/// ```ignore
-/// mod console {
-///     use algae::prelude::*;
-///     effect! {
-///         root ConsoleOp;
-///         Console::Print (String) -> ();
-///         Console::ReadLine -> String;
-///     }
-/// }
+/// Only [`TypedOp::as_call`] hands these out, and the only way for a
+/// [`TypedHandler`] to produce an [`Answer`] is to use one, so a handler
+/// replying with anything but the declared type does not compile.
+pub struct Respond<T> {
+    _reply: core::marker::PhantomData<fn(T)>,
+}
+
+impl<T: Send + 'static> Respond<T> {
+    /// Answers the operation with `value`.
+    pub fn send(self, value: T) -> Answer {
+        Answer(Reply::new(value))
+    }
+}
+
+// Generic families declare reply types without bounds.
+impl<T> Respond<T> {
+    #[doc(hidden)]
+    pub fn __new() -> Self {
+        Self {
+            _reply: core::marker::PhantomData,
+        }
+    }
+
+    /// Aborts the computation with `error` instead of answering, like
+    /// [`Abort::boxed`].
+    pub fn abort<E: core::fmt::Debug + Send + 'static>(self, error: E) -> Answer {
+        Answer(Reply::from_boxed(Abort::boxed(error)))
+    }
+}
+
+impl<T> core::fmt::Debug for Respond<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Respond<{}>", core::any::type_name::<T>())
+    }
+}
+
+/// The reply of a [`TypedHandler`], made by a [`Respond`].
+pub struct Answer(Reply);
+
+impl core::fmt::Debug for Answer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Answer").finish_non_exhaustive()
+    }
+}
+
+/// A handler whose replies are checked against the declared reply types at
+/// compile time.
 ///
-/// mod file {
-///     use algae::prelude::*;
-///     effect! {
-///         root FileOp;
-///         File::Read (String) -> String;
-///         File::Write ((String, String)) -> ();
-///     }
-/// }
+/// It gets operations as their [`TypedOp::Call`] and answers through the
+/// [`Respond`] in it. Wrap it in [`Typed`] to run computations with it:
 ///
-/// // Combine them into a unified root enum
-/// combine_roots!(pub Op = console::ConsoleOp, file::FileOp);
+/// ```rust,ignore
+/// struct Terminal;
 ///
-/// // Now you can write handlers for the unified Op enum
-/// struct UnifiedHandler;
-/// impl Handler<Op> for UnifiedHandler {
-///     fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-///         match op {
-///             Op::ConsoleOp(console_op) => {
-///                 // Handle console operations
-///             }
-///             Op::FileOp(file_op) => {
-///                 // Handle file operations  
+/// impl TypedHandler<Op> for Terminal {
+///     fn handle(&mut self, call: OpCall<'_>) -> Answer {
+///         match call {
+///             OpCall::Console(ConsoleCall::Print(msg, reply)) => {
+///                 println!("{msg}");
+///                 reply.send(())
 ///             }
+///             OpCall::Console(ConsoleCall::ReadLine(reply)) => reply.send(read_line()),
 ///         }
 ///     }
 /// }
+///
+/// let name = greet().handle(Typed(Terminal)).run()?;
 /// ```
-#[macro_export]
-macro_rules! combine_roots {
-    ( $vis:vis $root:ident = $( $path:ident ),+ $(,)? ) => {
-        #[derive(Debug)]
-        $vis enum $root {
-            $( $path($path) ),+
-        }
-
-        $(
-            impl From<$path> for $root {
-                fn from(f: $path) -> Self {
-                    $root::$path(f)
-                }
-            }
-        )+
-
-        // Note: Default implementation is not automatically generated
-        // Users should implement Default manually if needed, as it's unclear
-        // which variant should be the default when combining multiple enums
-    };
+///
+/// Replies still travel as [`Reply`]s, so `perform!` extracts them as
+/// usual; with the `helpers;` header it also knows the declared type.
+pub trait TypedHandler<Op: TypedOp> {
+    fn handle(&mut self, call: Op::Call<'_>) -> Answer;
 }
 
-#[cfg(all(test, feature = "macros"))]
-mod tests {
-    use crate as algae;
-    use algae::prelude::*;
-    use std::any::Any;
-    use std::collections::HashMap;
+/// Runs a [`TypedHandler`] as an ordinary [`Handler`] and [`PartialHandler`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Typed<H>(pub H);
 
-    // Define test effects for comprehensive testing
-    effect! {
-        Test::GetValue -> i32;
-        Test::SetValue (i32) -> ();
+/// Small replies sent through a [`Respond`] are kept inline; see
+/// [`Reply::new`].
+impl<Op: TypedOp, H: TypedHandler<Op>> Handler<Op> for Typed<H> {
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        self.reply(op).into_boxed()
+    }
 
-        Math::Add ((i32, i32)) -> i32;
-        Math::Multiply ((i32, i32)) -> i32;
-        Math::Divide ((i32, i32)) -> Result<i32, String>;
+    fn reply(&mut self, op: &Op) -> Reply {
+        self.0.handle(op.as_call()).0
+    }
+}
 
-        IO::ReadString -> String;
-        IO::WriteString (String) -> ();
-        IO::ReadNumber -> i32;
+impl<Op: TypedOp, H: TypedHandler<Op>> PartialHandler<Op> for Typed<H> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        Some(self.reply(op).into_boxed())
+    }
 
-        Logger::Info (String) -> ();
-        Logger::Error (String) -> ();
-        Logger::GetLogCount -> usize;
+    fn maybe_reply(&mut self, op: &Op) -> Option<Reply> {
+        Some(self.reply(op))
     }
+}
 
-    // Basic state handler for simple tests
-    struct TestHandler {
-        value: i32,
-    }
+/// Implementation details of the macros. Not public API.
+#[doc(hidden)]
+pub mod __private {
+    // For macro expansions in `no_std` crates, which have no `Box` or `Vec`
+    // in scope.
+    pub use alloc::{boxed::Box, vec::Vec};
+
+    // For `effect!` with a `serde;` header.
+    #[cfg(feature = "serde")]
+    pub use serde;
+
+    // For `effect!` with a `proptest;` header.
+    #[cfg(feature = "proptest")]
+    pub use proptest;
+
+    use crate::{redact::redacted, Effect, Effectful, OpMeta, PerformsOne, Reply};
+    use alloc::string::String;
+    use core::{
+        any::{Any, TypeId},
+        fmt,
+        marker::PhantomData,
+    };
 
-    impl TestHandler {
-        fn new(initial: i32) -> Self {
-            Self { value: initial }
+    /// The type `perform!` extracts a reply as.
+    pub struct ReplyType<R>(PhantomData<fn() -> R>);
+
+    // Manual impls: `R` itself need not be `Clone`. Multi-shot coroutines hold
+    // a `ReplyType` across the yield and are only `Clone` if it is.
+    impl<R> Clone for ReplyType<R> {
+        fn clone(&self) -> Self {
+            *self
         }
     }
 
-    impl Handler<Op> for TestHandler {
-        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-            match op {
-                Op::Test(Test::GetValue) => Box::new(self.value),
-                Op::Test(Test::SetValue(new_val)) => {
-                    self.value = *new_val;
-                    Box::new(())
-                }
-                _ => panic!("TestHandler cannot handle this operation: {op:?}"),
+    impl<R> Copy for ReplyType<R> {}
+
+    impl<R: Any + Send> ReplyType<R> {
+        /// Has `eff` expect a reply of type `R`.
+        #[inline]
+        pub fn expect<Op>(self, eff: Effect<Op>) -> Effect<Op> {
+            Effect {
+                expects: Some(|| (TypeId::of::<R>(), core::any::type_name::<R>())),
+                ..eff
             }
         }
+
+        #[inline]
+        pub fn take(self, reply: Option<Reply>) -> R {
+            reply
+                .expect("the runtime resumes every effect with a reply")
+                .take::<R>()
+        }
     }
 
-    // Math operations handler
-    struct MathHandler;
+    /// The type `perform_all!` extracts the reply to each operation of a
+    /// batch as.
+    pub struct BatchType<R>(PhantomData<fn() -> R>);
 
-    impl Handler<Op> for MathHandler {
-        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-            match op {
-                Op::Math(Math::Add((a, b))) => Box::new(a + b),
-                Op::Math(Math::Multiply((a, b))) => Box::new(a * b),
-                Op::Math(Math::Divide((a, b))) => {
-                    if *b == 0 {
-                        Box::new(Err::<i32, String>("Division by zero".to_string()))
-                    } else {
-                        Box::new(Ok::<i32, String>(a / b))
-                    }
-                }
-                _ => panic!("MathHandler cannot handle this operation: {op:?}"),
-            }
+    // Manual impls, like `ReplyType`'s.
+    impl<R> Clone for BatchType<R> {
+        fn clone(&self) -> Self {
+            *self
         }
     }
 
-    // Mock IO handler with predefined responses
-    struct MockIOHandler {
-        string_responses: Vec<String>,
-        number_responses: Vec<i32>,
-        string_index: usize,
-        number_index: usize,
-        written_strings: Vec<String>,
+    impl<R> Copy for BatchType<R> {}
+
+    impl<R> Default for BatchType<R> {
+        #[inline]
+        fn default() -> Self {
+            BatchType(PhantomData)
+        }
     }
 
-    impl MockIOHandler {
-        fn new(string_responses: Vec<String>, number_responses: Vec<i32>) -> Self {
-            Self {
-                string_responses,
-                number_responses,
-                string_index: 0,
-                number_index: 0,
-                written_strings: Vec::new(),
-            }
+    impl<R: Any + Send> BatchType<R> {
+        /// Has the batch `eff` expect a reply of type `R` to each operation.
+        #[inline]
+        pub fn expect<Op>(self, eff: Effect<Op>) -> Effect<Op> {
+            ReplyType::<R>(PhantomData).expect(eff)
         }
 
-        #[allow(dead_code)]
-        fn written_strings(&self) -> &[String] {
-            &self.written_strings
+        /// The replies to a batch performed by `perform_all!`, in order.
+        pub fn take(self, reply: Option<Reply>) -> Vec<R> {
+            reply
+                .expect("the runtime resumes every effect with a reply")
+                .take::<Vec<Reply>>()
+                .into_iter()
+                .map(Reply::take)
+                .collect()
         }
     }
 
-    impl Handler<Op> for MockIOHandler {
-        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-            match op {
-                Op::IO(IO::ReadString) => {
-                    let response = if self.string_index < self.string_responses.len() {
-                        self.string_responses[self.string_index].clone()
-                    } else {
-                        "default".to_string()
-                    };
-                    self.string_index += 1;
-                    Box::new(response)
-                }
-                Op::IO(IO::WriteString(s)) => {
-                    self.written_strings.push(s.clone());
-                    Box::new(())
-                }
-                Op::IO(IO::ReadNumber) => {
-                    let response = if self.number_index < self.number_responses.len() {
-                        self.number_responses[self.number_index]
-                    } else {
-                        0
-                    };
-                    self.number_index += 1;
-                    Box::new(response)
-                }
-                _ => panic!("MockIOHandler cannot handle this operation: {op:?}"),
-            }
+    /// A reply type declared in `effect!`, for `OpMeta::default_reply`.
+    pub struct DeclaredReply<R>(PhantomData<fn() -> R>);
+
+    impl<R> Default for DeclaredReply<R> {
+        #[inline]
+        fn default() -> Self {
+            DeclaredReply(PhantomData)
         }
     }
 
-    // Logging handler that tracks log calls
-    struct LoggingHandler {
-        logs: Vec<(String, String)>, // (level, message)
+    /// Picked for reply types that implement `Default`.
+    ///
+    /// `effect!` calls `(&DeclaredReply::<R>::default()).default_reply()`,
+    /// which resolves like `reply_type` below.
+    pub trait DefaultedReply {
+        fn default_reply(&self) -> Option<Box<dyn Any + Send>>;
     }
 
-    impl LoggingHandler {
-        fn new() -> Self {
-            Self { logs: Vec::new() }
+    impl<R: Default + Any + Send> DefaultedReply for DeclaredReply<R> {
+        #[inline]
+        fn default_reply(&self) -> Option<Box<dyn Any + Send>> {
+            Some(Box::new(R::default()))
         }
+    }
 
-        #[allow(dead_code)]
-        fn get_logs(&self) -> &[(String, String)] {
-            &self.logs
-        }
+    /// Fallback for reply types without a default.
+    pub trait UndefaultedReply {
+        fn default_reply(&self) -> Option<Box<dyn Any + Send>>;
     }
 
-    impl Handler<Op> for LoggingHandler {
-        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-            match op {
-                Op::Logger(Logger::Info(msg)) => {
-                    self.logs.push(("INFO".to_string(), msg.clone()));
-                    Box::new(())
-                }
-                Op::Logger(Logger::Error(msg)) => {
-                    self.logs.push(("ERROR".to_string(), msg.clone()));
-                    Box::new(())
-                }
-                Op::Logger(Logger::GetLogCount) => Box::new(self.logs.len()),
-                _ => panic!("LoggingHandler cannot handle this operation: {op:?}"),
-            }
+    impl<R> UndefaultedReply for &DeclaredReply<R> {
+        #[inline]
+        fn default_reply(&self) -> Option<Box<dyn Any + Send>> {
+            None
         }
     }
 
-    // Combined handler for multiple effect types
-    struct CombinedTestHandler {
-        test_handler: TestHandler,
-        math_handler: MathHandler,
-        io_handler: MockIOHandler,
-        logger_handler: LoggingHandler,
+    /// Picked for [`PerformsOne`]: the reply type is the declared one.
+    ///
+    /// `perform!` calls `(&op).reply_type()`. Method resolution tries the
+    /// receiver `&PerformsOne` before auto-referencing, so this impl wins over
+    /// [`InferredReply`] whenever it applies.
+    pub trait TypedReply {
+        type Reply;
+        fn reply_type(&self) -> ReplyType<Self::Reply>;
     }
 
-    impl CombinedTestHandler {
-        fn new(
-            initial_value: i32,
-            string_responses: Vec<String>,
-            number_responses: Vec<i32>,
-        ) -> Self {
-            Self {
-                test_handler: TestHandler::new(initial_value),
-                math_handler: MathHandler,
-                io_handler: MockIOHandler::new(string_responses, number_responses),
-                logger_handler: LoggingHandler::new(),
-            }
+    impl<R, Op> TypedReply for PerformsOne<R, Op> {
+        type Reply = R;
+        #[inline]
+        fn reply_type(&self) -> ReplyType<R> {
+            ReplyType(PhantomData)
         }
+    }
 
-        #[allow(dead_code)]
-        fn get_written_strings(&self) -> &[String] {
-            self.io_handler.written_strings()
-        }
+    /// Implemented by `effect!` for each variant of a family, with the hash
+    /// of its name as `VARIANT` and its declared reply type as `R`.
+    ///
+    /// `R` is a parameter rather than an associated type so that families
+    /// may declare private reply types.
+    pub trait VariantReply<const VARIANT: u64, R> {}
 
-        #[allow(dead_code)]
-        fn get_logs(&self) -> &[(String, String)] {
-            self.logger_handler.get_logs()
+    /// An operation `perform!` saw built with the variant hashing to
+    /// `VARIANT`, e.g. `Console::ReadLine`.
+    pub struct Variant<Op, R, const VARIANT: u64>(PhantomData<fn(&Op) -> R>);
+
+    impl<Op, R, const VARIANT: u64> Variant<Op, R, VARIANT> {
+        #[inline]
+        pub fn of(_op: &Op) -> Self {
+            Variant(PhantomData)
         }
     }
 
-    impl Handler<Op> for CombinedTestHandler {
-        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-            match op {
-                Op::Test(_) => self.test_handler.handle(op),
-                Op::Math(_) => self.math_handler.handle(op),
-                Op::IO(_) => self.io_handler.handle(op),
-                Op::Logger(_) => self.logger_handler.handle(op),
-            }
+    /// Picked for variants of families declared with `effect!`: the reply
+    /// type is the declared one.
+    impl<Op, R, const VARIANT: u64> TypedReply for Variant<Op, R, VARIANT>
+    where
+        Op: VariantReply<VARIANT, R>,
+    {
+        type Reply = R;
+        #[inline]
+        fn reply_type(&self) -> ReplyType<R> {
+            ReplyType(PhantomData)
         }
     }
 
-    // Test effectful functions
-    #[effectful]
-    fn test_computation() -> i32 {
-        let current: i32 = perform!(Test::GetValue);
-        let _: () = perform!(Test::SetValue(current * 2));
-        let doubled: i32 = perform!(Test::GetValue);
-        doubled + 10
+    /// Fallback for variants of other types, like [`InferredReply`].
+    pub trait InferredVariantReply<R> {
+        fn reply_type(&self) -> ReplyType<R>;
     }
 
-    #[effectful]
-    fn math_computation() -> i32 {
-        let sum: i32 = perform!(Math::Add((5, 3)));
-        let product: i32 = perform!(Math::Multiply((sum, 2)));
-        let result: Result<i32, String> = perform!(Math::Divide((product, 4)));
-        result.unwrap_or(-1)
+    impl<Op, R, const VARIANT: u64> InferredVariantReply<R> for &Variant<Op, R, VARIANT> {
+        #[inline]
+        fn reply_type(&self) -> ReplyType<R> {
+            ReplyType(PhantomData)
+        }
     }
 
-    #[effectful]
-    fn io_program() -> String {
-        let _: () = perform!(IO::WriteString("Enter name:".to_string()));
-        let name: String = perform!(IO::ReadString);
-        let _: () = perform!(IO::WriteString("Enter age:".to_string()));
-        let age: i32 = perform!(IO::ReadNumber);
-        format!("{name} is {age} years old")
+    /// Fallback for plain operations: the reply type is inferred from the
+    /// `perform!` call site.
+    pub trait InferredReply {
+        fn reply_type<R>(&self) -> ReplyType<R>;
     }
 
-    #[effectful]
-    fn logging_program() -> usize {
-        let _: () = perform!(Logger::Info("Starting program".to_string()));
-        let _: () = perform!(Logger::Info("Processing data".to_string()));
-        let _: () = perform!(Logger::Error("Something went wrong".to_string()));
-        let count: usize = perform!(Logger::GetLogCount);
-        count
+    impl<T> InferredReply for &T {
+        #[inline]
+        fn reply_type<R>(&self) -> ReplyType<R> {
+            ReplyType(PhantomData)
+        }
     }
 
-    #[effectful]
-    fn complex_program() -> String {
-        // Log start
-        let _: () = perform!(Logger::Info("Complex program started".to_string()));
-
-        // Get initial state
-        let initial: i32 = perform!(Test::GetValue);
-
-        // Do some math
-        let doubled: i32 = perform!(Math::Multiply((initial, 2)));
-        let _: () = perform!(Test::SetValue(doubled));
-
-        // Read input
-        let _: () = perform!(IO::WriteString("Enter multiplier:".to_string()));
-        let multiplier: i32 = perform!(IO::ReadNumber);
-
-        // More math
-        let result: i32 = perform!(Math::Multiply((doubled, multiplier)));
+    /// How the spans of `algae::trace` and the errors of replies of the wrong
+    /// type render an operation, if they can.
+    pub type Describer<Op> = Option<fn(&Op) -> String>;
 
-        // Log result
-        let _: () = perform!(Logger::Info(format!("Result calculated: {result}")));
+    /// Names the root type of an `#[effectful]` function.
+    pub struct RootType<Op>(PhantomData<fn(&Op)>);
 
-        format!("Final result: {result}")
+    impl<Op> Default for RootType<Op> {
+        #[inline]
+        fn default() -> Self {
+            RootType(PhantomData)
+        }
     }
 
-    #[effectful]
-    fn nested_effects() -> i32 {
-        fn inner_computation() -> Effectful<i32, Op> {
-            inner_effectful()
-        }
+    /// Picked for root types that can be rendered: with `Debug`, their
+    /// sensitive payloads redacted.
+    ///
+    /// `#[effectful]` calls `(&RootType::<Op>::default()).op_describer()`, which
+    /// resolves like `reply_type` above, so generic roots that cannot name
+    /// these bounds get [`OpaqueOp`] instead of an error.
+    pub trait DescribedOp<Op> {
+        fn op_describer(&self) -> Describer<Op>;
+    }
 
-        #[effectful]
-        fn inner_effectful() -> i32 {
-            let value: i32 = perform!(Test::GetValue);
-            perform!(Math::Add((value, 5)))
+    impl<Op: fmt::Debug + OpMeta> DescribedOp<Op> for RootType<Op> {
+        #[inline]
+        fn op_describer(&self) -> Describer<Op> {
+            Some(redacted::<Op>)
         }
+    }
 
-        let base: i32 = perform!(Test::GetValue);
-        let _: () = perform!(Test::SetValue(base + 1));
-        let inner_result: i32 = inner_computation()
-            .handle(CombinedTestHandler::new(10, vec![], vec![]))
-            .run();
-        perform!(Math::Add((base, inner_result)))
+    /// Fallback for root types that cannot be rendered.
+    pub trait OpaqueOp<Op> {
+        fn op_describer(&self) -> Describer<Op>;
     }
 
-    #[effectful]
-    fn error_handling_program() -> Result<i32, String> {
-        let _: () = perform!(Logger::Info("Testing error handling".to_string()));
-        let result: Result<i32, String> = perform!(Math::Divide((10, 0)));
-        match result {
-            Ok(val) => {
-                let _: () = perform!(Logger::Info(format!("Division successful: {val}")));
-                Ok(val)
-            }
-            Err(err) => {
-                let _: () = perform!(Logger::Error(format!("Division failed: {err}")));
-                Err(err)
-            }
+    impl<Op> OpaqueOp<Op> for &RootType<Op> {
+        #[inline]
+        fn op_describer(&self) -> Describer<Op> {
+            None
         }
     }
 
-    // Basic functionality tests
-    #[test]
-    fn test_basic_effects() {
-        let result = test_computation().handle(TestHandler::new(5)).run();
-
-        assert_eq!(result, 20); // (5 * 2) + 10 = 20
+    /// The clone of its `&self` receiver an `#[effectful]` method runs on.
+    #[inline]
+    pub fn owned_receiver<S: Clone + Send + 'static>(receiver: &S) -> S {
+        receiver.clone()
     }
 
-    #[test]
-    fn test_different_initial_values() {
-        let result = test_computation().handle(TestHandler::new(3)).run();
+    /// Has `computation` report `function` as the one performing its
+    /// operations, in [`UnhandledOp`](crate::UnhandledOp).
+    #[inline]
+    pub fn named<R, Op>(computation: Effectful<R, Op>, function: &'static str) -> Effectful<R, Op> {
+        Effectful {
+            function: Some(function),
+            ..computation
+        }
+    }
 
-        assert_eq!(result, 16); // (3 * 2) + 10 = 16
+    /// Has `computation` render the operations it performs with `describe`.
+    #[inline]
+    pub fn describing<R, Op>(
+        computation: Effectful<R, Op>,
+        describe: Describer<Op>,
+    ) -> Effectful<R, Op> {
+        Effectful {
+            describe,
+            ..computation
+        }
     }
+}
 
-    #[test]
-    fn test_math_operations() {
-        let result = math_computation().handle(MathHandler).run();
+/// Trait to enable conversion from Handler to PartialHandler
+pub trait IntoPartialHandler<Op> {
+    /// The resulting partial handler type
+    type Output: PartialHandler<Op> + Send + 'static;
 
-        assert_eq!(result, 4); // (5 + 3) * 2 / 4 = 4
-    }
+    /// Convert to a partial handler
+    fn into_partial_handler(self) -> Self::Output;
+}
+
+// Implementation for types that already implement PartialHandler
+impl<Op, H> IntoPartialHandler<Op> for H
+where
+    H: PartialHandler<Op> + Send + 'static,
+{
+    type Output = H;
+
+    fn into_partial_handler(self) -> Self::Output {
+        self
+    }
+}
+
+/// Trait for converting any handler into a VecHandler for flattening
+pub trait IntoVecHandler<Op> {
+    /// Convert this handler into a VecHandler
+    fn into_vec_handler(self) -> VecHandler<Op>;
+}
+
+// Implementation for VecHandler - return as-is (already flat)
+impl<Op> IntoVecHandler<Op> for VecHandler<Op> {
+    fn into_vec_handler(self) -> VecHandler<Op> {
+        self
+    }
+}
+
+// Implementation for Box<dyn PartialHandler> - wrap in VecHandler
+impl<Op> IntoVecHandler<Op> for Box<dyn PartialHandler<Op> + Send> {
+    fn into_vec_handler(self) -> VecHandler<Op> {
+        let mut vec = VecHandler::new();
+        vec.inner.push(self);
+        vec.names.push(core::any::type_name::<Self>());
+        vec
+    }
+}
+
+/// Macro to implement IntoVecHandler for a type that implements PartialHandler
+#[macro_export]
+macro_rules! impl_into_vec_handler {
+    ($handler:ty, $op:ty) => {
+        impl $crate::IntoVecHandler<$op> for $handler {
+            fn into_vec_handler(self) -> $crate::VecHandler<$op> {
+                let mut vec = $crate::VecHandler::new();
+                vec.push(self);
+                vec
+            }
+        }
+    };
+}
+
+/// Wrapper to make Handler trait implement PartialHandler  
+pub struct HandlerWrapper<Op, H> {
+    handler: H,
+    _phantom: core::marker::PhantomData<Op>,
+}
+
+impl<Op, H> HandlerWrapper<Op, H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            _phantom: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<Op, H> PartialHandler<Op> for HandlerWrapper<Op, H>
+where
+    H: Handler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        Some(self.handler.handle(op))
+    }
+}
+
+// Special case for VecHandler to enable efficient chaining
+impl<R, Op: 'static + Send> Handled<R, Op, VecHandler<Op>> {
+    /// Adds another handler to an existing VecHandler chain.
+    ///
+    /// This specialized implementation efficiently adds to an existing VecHandler
+    /// without creating a new one. If the added handler is itself a VecHandler,
+    /// its contents are flattened into the existing collection.
+    pub fn handle<H2>(self, h2: H2) -> Handled<R, Op, VecHandler<Op>>
+    where
+        H2: IntoVecHandler<Op>,
+    {
+        let mut result = self.h;
+        let other = h2.into_vec_handler();
+        result.extend_from(other);
+        Handled {
+            eff: self.eff,
+            h: result,
+        }
+    }
+
+    /// Adds a total handler to an existing VecHandler chain.
+    ///
+    /// This method wraps a Handler type to work with the partial handler system.
+    pub fn handle_total<H2>(mut self, h2: H2) -> Handled<R, Op, VecHandler<Op>>
+    where
+        H2: Handler<Op> + Send + 'static,
+    {
+        self.h.push(HandlerWrapper::new(h2));
+        self
+    }
+}
+
+/// Convenience module that re-exports everything needed to use algae.
+///
+/// This module provides a single import point for all the essential types
+/// and macros needed to work with algebraic effects. Users can simply
+/// `use algae::prelude::*` to get started.
+///
+/// # Re-exported Items
+///
+/// ## Core Types
+/// - [`Effect`] - Represents an effect operation with space for a reply
+/// - [`Effectful`] - Wrapper around effectful computations  
+/// - [`Handler`] - Trait for implementing effect handlers
+/// - [`Reply`] - Container for handler response values
+/// - [`PartialHandler`] - Trait for handlers that may decline operations
+/// - [`VecHandler`] - Collection of handlers tried in order
+/// - [`UnhandledOp`] - Error returned when no handler handles an operation
+/// - [`UnhandledOpError`] - Lightweight error with just operation name
+/// - [`IntoPartialHandler`] - Trait for converting handlers to PartialHandler
+/// - [`IntoVecHandler`] - Trait for converting handlers to VecHandler with flattening
+/// - [`Abort`] - Handler reply that terminates a computation
+/// - [`EffectError`] - Error returned by `run` (unhandled operation or abort)
+/// - [`Contains`] - Projects a family's operations out of a root operation type
+/// - [`OpMeta`] - Static metadata generated from op annotations
+/// - [`PerformsOne`] - An operation paired with its reply type
+///
+/// ## Macros (when "macros" feature is enabled)
+/// - `effect!` - Macro for defining effect families and operations
+/// - `effectful` - Attribute macro for marking functions as effectful
+/// - `perform!` - Macro for performing effects within effectful functions
+/// - `perform_all!` - Macro for performing a batch of operations in one suspension
+/// - `defer_perform!` - Macro performing an operation when the enclosing effectful function ends
+/// - `for_each_perform!` - Macro looping over the items an operation hands out until it replies `None`
+/// - `effectful_closure!` - Macro for closures returning effectful computations
+/// - `#[handles(...)]` - Attribute making a handler of single families a partial handler of any root
+/// - `emit!` - Macro for producing values from `#[effectful(yields = T)]` streams
+///
+/// # Examples
+///
+/// ## With macros (default):
+/// ```rust,ignore
+/// #![feature(coroutines, coroutine_trait, yield_expr)]
+/// use algae::prelude::*;
+///
+/// // Define effects
+/// effect! {
+///     Counter::Get -> i32;
+///     Counter::Set (i32) -> ();
+/// }
+///
+/// // Create effectful functions
+/// #[effectful]
+/// fn increment() -> i32 {
+///     let current: i32 = perform!(Counter::Get);
+///     let _: () = perform!(Counter::Set(current + 1));
+///     current + 1
+/// }
+///
+/// // Implement handlers
+/// struct CounterHandler { value: i32 }
+///
+/// impl Handler<Op> for CounterHandler {
+///     fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+///         match op {
+///             Op::Counter(Counter::Get) => Box::new(self.value),
+///             Op::Counter(Counter::Set(new_val)) => {
+///                 self.value = *new_val;
+///                 Box::new(())
+///             }
+///         }
+///     }
+/// }
+///
+/// // Run computations
+/// let result = increment()
+///     .handle(CounterHandler { value: 5 })
+///     .run()?;
+/// assert_eq!(result, 6);
+/// ```
+///
+/// ## Without macros (manual approach):
+/// When using algae without the "macros" feature, you need to define
+/// your effect types and handlers manually using the core types.
+pub mod prelude {
+    pub use crate::{
+        fallible::{Fallible, HandlerError, TryHandler},
+        iter::EffectfulIterator,
+        Abort, Answer, Contains, Effect, EffectError, Effectful, FamilyOp, Handler, HandlerFactory,
+        HandlerWrapper, IntoPartialHandler, IntoVecHandler, OpMeta, PartialHandler, PerformsOne,
+        Reply, ReplyError, Respond, RunError, Typed, TypedHandler, TypedOp, UnhandledOp,
+        UnhandledOpError, VecHandler,
+    };
+
+    pub use crate::{embed, transaction, with_handler};
+
+    #[cfg(feature = "std")]
+    pub use crate::register_type;
+
+    pub use crate::stream::Streamful;
+
+    #[cfg(feature = "macros")]
+    pub use algae_macros::{
+        algae_test, defer_perform, effect, effectful, effectful_closure, emit, for_each_perform,
+        handles, perform, perform_all, RouteHandler,
+    };
+}
+
+/// Runs a block of an `#[effectful]` function with a local handler.
+///
+/// `handler` answers the operations performed in `body` that it accepts;
+/// the others go on to the handlers of the enclosing computation. The macro
+/// evaluates to `Ok` with the block's value, or to `Err` with the [`Abort`]
+/// if the local handler aborts, which ends the block at that operation:
+///
+/// ```rust,ignore
+/// #[effectful]
+/// fn load() -> Config {
+///     let parsed = with_handler!(RaiseAsAbort, {
+///         let text: String = perform!(File::Read("app.toml".into()));
+///         perform!(config::parse(text))
+///     });
+///     parsed.unwrap_or_default()
+/// }
+/// ```
+///
+/// The block runs as its own computation, see [`Effectful::with_handler`],
+/// so like a `move` closure it takes ownership of the variables it uses.
+#[macro_export]
+macro_rules! with_handler {
+    ($handler:expr, $body:block) => {{
+        let mut __local = $crate::Effectful::new(
+            #[coroutine]
+            move |_reply: ::core::option::Option<$crate::Reply>| $body,
+        )
+        .with_handler($handler);
+        let mut __reply = ::core::option::Option::None;
+        loop {
+            match $crate::boxed::Drive::resume(&mut __local, __reply.take()) {
+                ::core::ops::CoroutineState::Yielded(eff) => __reply = yield eff,
+                ::core::ops::CoroutineState::Complete(result) => break result,
+            }
+        }
+    }};
+}
+
+/// Runs a block of an `#[effectful]` function as a transaction, committing
+/// the writes it performs if it evaluates to `Ok` and discarding them if it
+/// evaluates to `Err`.
+///
+/// The writes are buffered by a
+/// [`TransactionHandler`](crate::effects::transaction::TransactionHandler)
+/// among the handlers of the enclosing computation, whose root must contain
+/// the [`Transactional`](crate::effects::transaction::Transactional) family.
+/// The macro evaluates to the block's value:
+///
+/// ```rust,ignore
+/// #[effectful]
+/// fn rename(from: String, to: String) -> Result<(), Missing> {
+///     transaction! {
+///         let text: Option<String> = perform!(Kv::Get(from.clone()));
+///         let text = text.ok_or(Missing(from.clone()))?;
+///         let _: () = perform!(Kv::Put((to, text)));
+///         let _: () = perform!(Kv::Delete(from));
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// The block runs as its own computation, see [`Effectful::transactional`],
+/// so `?` and `return` end the block rather than the function, and like a
+/// `move` closure it takes ownership of the variables it uses.
+#[macro_export]
+macro_rules! transaction {
+    ($($body:tt)*) => {{
+        let mut __transaction = $crate::Effectful::new(
+            #[coroutine]
+            move |_reply: ::core::option::Option<$crate::Reply>| { $($body)* },
+        )
+        .transactional();
+        let mut __reply = ::core::option::Option::None;
+        loop {
+            match $crate::boxed::Drive::resume(&mut __transaction, __reply.take()) {
+                ::core::ops::CoroutineState::Yielded(eff) => __reply = yield eff,
+                ::core::ops::CoroutineState::Complete(result) => break result,
+            }
+        }
+    }};
+}
+
+/// Runs a computation of a smaller root type inside an `#[effectful]`
+/// function, evaluating to its result.
+///
+/// The operations it performs are converted into the root type of the
+/// enclosing function, which must implement `From` the smaller one, as the
+/// roots combined by [`combine_roots!`] and the families of an `effect!` root
+/// do, and are answered by its handlers. Functions written against the root
+/// of one module so compose into programs over a combined root unchanged:
+///
+/// ```rust,ignore
+/// combine_roots!(pub AppOp = FileOp, NetworkOp);
+///
+/// #[effectful(root = AppOp)]
+/// fn sync(path: String) -> usize {
+///     let text = embed!(file::read(path));       // Effectful<String, FileOp>
+///     embed!(network::upload(text))              // Effectful<usize, NetworkOp>
+/// }
+/// ```
+///
+/// See [`Effectful::lift_into`] to convert computations outside
+/// `#[effectful]` functions.
+#[macro_export]
+macro_rules! embed {
+    ($computation:expr $(,)?) => {{
+        let mut __embedded = $crate::Effectful::lift_into($computation);
+        let mut __reply = ::core::option::Option::None;
+        loop {
+            match $crate::boxed::Drive::resume(&mut __embedded, __reply.take()) {
+                ::core::ops::CoroutineState::Yielded(eff) => __reply = yield eff,
+                ::core::ops::CoroutineState::Complete(result) => break result,
+            }
+        }
+    }};
+}
+
+/// Helper macro for combining multiple root enums into one unified enum.
+///
+/// This macro allows you to merge effect operations from different modules
+/// that use custom root enum names into a single root enum for easier handling.
+///
+/// # Syntax
+///
+/// ```ignore
+/// combine_roots!(pub Op = module_a::ConsoleOp, module_b::FileOp, module_c::NetOp);
+/// ```
+///
+/// This generates:
+/// - A new enum with the specified name containing all the other enums as variants
+/// - `From` implementations to convert each source enum to the combined enum
+/// - [`Contains`] implementations projecting each source enum back out of it
+///
+/// Computations written against a source root run under the combined one
+/// with [`Effectful::lift_into`], and handlers generic over
+/// `Op: Contains<ConsoleOp>` accept the combined root.
+///
+/// # Example
+///
+/// ```ignore
+/// mod console {
+///     use algae::prelude::*;
+///     effect! {
+///         root ConsoleOp;
+///         Console::Print (String) -> ();
+///         Console::ReadLine -> String;
+///     }
+/// }
+///
+/// mod file {
+///     use algae::prelude::*;
+///     effect! {
+///         root FileOp;
+///         File::Read (String) -> String;
+///         File::Write ((String, String)) -> ();
+///     }
+/// }
+///
+/// // Combine them into a unified root enum
+/// combine_roots!(pub Op = console::ConsoleOp, file::FileOp);
+///
+/// // Now you can write handlers for the unified Op enum
+/// struct UnifiedHandler;
+/// impl Handler<Op> for UnifiedHandler {
+///     fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+///         match op {
+///             Op::ConsoleOp(console_op) => {
+///                 // Handle console operations
+///             }
+///             Op::FileOp(file_op) => {
+///                 // Handle file operations  
+///             }
+///         }
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! combine_roots {
+    ( $vis:vis $root:ident = $( $path:ident ),+ $(,)? ) => {
+        #[derive(Debug)]
+        $vis enum $root {
+            $( $path($path) ),+
+        }
+
+        $(
+            impl From<$path> for $root {
+                fn from(f: $path) -> Self {
+                    $root::$path(f)
+                }
+            }
+
+            impl $crate::Contains<$path> for $root {
+                fn project(&self) -> Option<&$path> {
+                    match self {
+                        $root::$path(op) => Some(op),
+                        #[allow(unreachable_patterns)]
+                        _ => None,
+                    }
+                }
+            }
+        )+
+
+        // Note: Default implementation is not automatically generated
+        // Users should implement Default manually if needed, as it's unclear
+        // which variant should be the default when combining multiple enums
+    };
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use crate as algae;
+    use algae::prelude::*;
+    use algae::Scope;
+    use std::any::Any;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    // Define test effects for comprehensive testing
+    effect! {
+        Test::GetValue -> i32;
+        Test::SetValue (i32) -> ();
+
+        Math::Add ((i32, i32)) -> i32;
+        Math::Multiply ((i32, i32)) -> i32;
+        Math::Divide ((i32, i32)) -> Result<i32, String>;
+
+        IO::ReadString -> String;
+        IO::WriteString (String) -> ();
+        IO::ReadNumber -> i32;
+
+        Logger::Info (String) -> ();
+        Logger::Error (String) -> ();
+        Logger::GetLogCount -> usize;
+    }
+
+    // Basic state handler for simple tests
+    struct TestHandler {
+        value: i32,
+    }
+
+    impl TestHandler {
+        fn new(initial: i32) -> Self {
+            Self { value: initial }
+        }
+    }
+
+    impl Handler<Op> for TestHandler {
+        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+            match op {
+                Op::Test(Test::GetValue) => Box::new(self.value),
+                Op::Test(Test::SetValue(new_val)) => {
+                    self.value = *new_val;
+                    Box::new(())
+                }
+                _ => panic!("TestHandler cannot handle this operation: {op:?}"),
+            }
+        }
+    }
+
+    // Math operations handler
+    struct MathHandler;
+
+    impl Handler<Op> for MathHandler {
+        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+            match op {
+                Op::Math(Math::Add((a, b))) => Box::new(a + b),
+                Op::Math(Math::Multiply((a, b))) => Box::new(a * b),
+                Op::Math(Math::Divide((a, b))) => {
+                    if *b == 0 {
+                        Box::new(Err::<i32, String>("Division by zero".to_string()))
+                    } else {
+                        Box::new(Ok::<i32, String>(a / b))
+                    }
+                }
+                _ => panic!("MathHandler cannot handle this operation: {op:?}"),
+            }
+        }
+    }
+
+    // Mock IO handler with predefined responses
+    struct MockIOHandler {
+        string_responses: Vec<String>,
+        number_responses: Vec<i32>,
+        string_index: usize,
+        number_index: usize,
+        written_strings: Vec<String>,
+    }
+
+    impl MockIOHandler {
+        fn new(string_responses: Vec<String>, number_responses: Vec<i32>) -> Self {
+            Self {
+                string_responses,
+                number_responses,
+                string_index: 0,
+                number_index: 0,
+                written_strings: Vec::new(),
+            }
+        }
+
+        #[allow(dead_code)]
+        fn written_strings(&self) -> &[String] {
+            &self.written_strings
+        }
+    }
+
+    impl Handler<Op> for MockIOHandler {
+        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+            match op {
+                Op::IO(IO::ReadString) => {
+                    let response = if self.string_index < self.string_responses.len() {
+                        self.string_responses[self.string_index].clone()
+                    } else {
+                        "default".to_string()
+                    };
+                    self.string_index += 1;
+                    Box::new(response)
+                }
+                Op::IO(IO::WriteString(s)) => {
+                    self.written_strings.push(s.clone());
+                    Box::new(())
+                }
+                Op::IO(IO::ReadNumber) => {
+                    let response = if self.number_index < self.number_responses.len() {
+                        self.number_responses[self.number_index]
+                    } else {
+                        0
+                    };
+                    self.number_index += 1;
+                    Box::new(response)
+                }
+                _ => panic!("MockIOHandler cannot handle this operation: {op:?}"),
+            }
+        }
+    }
+
+    // Logging handler that tracks log calls
+    struct LoggingHandler {
+        logs: Vec<(String, String)>, // (level, message)
+    }
+
+    impl LoggingHandler {
+        fn new() -> Self {
+            Self { logs: Vec::new() }
+        }
+
+        #[allow(dead_code)]
+        fn get_logs(&self) -> &[(String, String)] {
+            &self.logs
+        }
+    }
+
+    impl Handler<Op> for LoggingHandler {
+        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+            match op {
+                Op::Logger(Logger::Info(msg)) => {
+                    self.logs.push(("INFO".to_string(), msg.clone()));
+                    Box::new(())
+                }
+                Op::Logger(Logger::Error(msg)) => {
+                    self.logs.push(("ERROR".to_string(), msg.clone()));
+                    Box::new(())
+                }
+                Op::Logger(Logger::GetLogCount) => Box::new(self.logs.len()),
+                _ => panic!("LoggingHandler cannot handle this operation: {op:?}"),
+            }
+        }
+    }
+
+    // Combined handler for multiple effect types
+    struct CombinedTestHandler {
+        test_handler: TestHandler,
+        math_handler: MathHandler,
+        io_handler: MockIOHandler,
+        logger_handler: LoggingHandler,
+    }
+
+    impl CombinedTestHandler {
+        fn new(
+            initial_value: i32,
+            string_responses: Vec<String>,
+            number_responses: Vec<i32>,
+        ) -> Self {
+            Self {
+                test_handler: TestHandler::new(initial_value),
+                math_handler: MathHandler,
+                io_handler: MockIOHandler::new(string_responses, number_responses),
+                logger_handler: LoggingHandler::new(),
+            }
+        }
+
+        #[allow(dead_code)]
+        fn get_written_strings(&self) -> &[String] {
+            self.io_handler.written_strings()
+        }
+
+        #[allow(dead_code)]
+        fn get_logs(&self) -> &[(String, String)] {
+            self.logger_handler.get_logs()
+        }
+    }
+
+    impl Handler<Op> for CombinedTestHandler {
+        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+            match op {
+                Op::Test(_) => self.test_handler.handle(op),
+                Op::Math(_) => self.math_handler.handle(op),
+                Op::IO(_) => self.io_handler.handle(op),
+                Op::Logger(_) => self.logger_handler.handle(op),
+            }
+        }
+    }
+
+    // Test effectful functions
+    #[effectful]
+    fn test_computation() -> i32 {
+        let current: i32 = perform!(Test::GetValue);
+        let _: () = perform!(Test::SetValue(current * 2));
+        let doubled: i32 = perform!(Test::GetValue);
+        doubled + 10
+    }
+
+    #[effectful]
+    fn math_computation() -> i32 {
+        let sum: i32 = perform!(Math::Add((5, 3)));
+        let product: i32 = perform!(Math::Multiply((sum, 2)));
+        let result: Result<i32, String> = perform!(Math::Divide((product, 4)));
+        result.unwrap_or(-1)
+    }
+
+    #[effectful]
+    fn io_program() -> String {
+        let _: () = perform!(IO::WriteString("Enter name:".to_string()));
+        let name: String = perform!(IO::ReadString);
+        let _: () = perform!(IO::WriteString("Enter age:".to_string()));
+        let age: i32 = perform!(IO::ReadNumber);
+        format!("{name} is {age} years old")
+    }
+
+    #[effectful]
+    fn logging_program() -> usize {
+        let _: () = perform!(Logger::Info("Starting program".to_string()));
+        let _: () = perform!(Logger::Info("Processing data".to_string()));
+        let _: () = perform!(Logger::Error("Something went wrong".to_string()));
+        let count: usize = perform!(Logger::GetLogCount);
+        count
+    }
+
+    #[effectful]
+    fn complex_program() -> String {
+        // Log start
+        let _: () = perform!(Logger::Info("Complex program started".to_string()));
+
+        // Get initial state
+        let initial: i32 = perform!(Test::GetValue);
+
+        // Do some math
+        let doubled: i32 = perform!(Math::Multiply((initial, 2)));
+        let _: () = perform!(Test::SetValue(doubled));
+
+        // Read input
+        let _: () = perform!(IO::WriteString("Enter multiplier:".to_string()));
+        let multiplier: i32 = perform!(IO::ReadNumber);
+
+        // More math
+        let result: i32 = perform!(Math::Multiply((doubled, multiplier)));
+
+        // Log result
+        let _: () = perform!(Logger::Info(format!("Result calculated: {result}")));
+
+        format!("Final result: {result}")
+    }
+
+    #[effectful]
+    fn nested_effects() -> i32 {
+        fn inner_computation() -> Effectful<i32, Op> {
+            inner_effectful()
+        }
+
+        #[effectful]
+        fn inner_effectful() -> i32 {
+            let value: i32 = perform!(Test::GetValue);
+            perform!(Math::Add((value, 5)))
+        }
+
+        let base: i32 = perform!(Test::GetValue);
+        let _: () = perform!(Test::SetValue(base + 1));
+        let inner_result: i32 = inner_computation()
+            .handle(CombinedTestHandler::new(10, vec![], vec![]))
+            .run_or_panic();
+        perform!(Math::Add((base, inner_result)))
+    }
+
+    #[effectful]
+    fn error_handling_program() -> Result<i32, String> {
+        let _: () = perform!(Logger::Info("Testing error handling".to_string()));
+        let result: Result<i32, String> = perform!(Math::Divide((10, 0)));
+        match result {
+            Ok(val) => {
+                let _: () = perform!(Logger::Info(format!("Division successful: {val}")));
+                Ok(val)
+            }
+            Err(err) => {
+                let _: () = perform!(Logger::Error(format!("Division failed: {err}")));
+                Err(err)
+            }
+        }
+    }
+
+    // Basic functionality tests
+    #[test]
+    fn test_basic_effects() {
+        let result = test_computation()
+            .handle(TestHandler::new(5))
+            .run_or_panic();
+
+        assert_eq!(result, 20); // (5 * 2) + 10 = 20
+    }
+
+    #[test]
+    fn test_different_initial_values() {
+        let result = test_computation()
+            .handle(TestHandler::new(3))
+            .run_or_panic();
+
+        assert_eq!(result, 16); // (3 * 2) + 10 = 16
+    }
+
+    #[test]
+    fn test_math_operations() {
+        let result = math_computation().handle(MathHandler).run_or_panic();
+
+        assert_eq!(result, 4); // (5 + 3) * 2 / 4 = 4
+    }
+
+    #[test]
+    fn test_variant_replies_have_their_declared_type() {
+        #[effectful]
+        fn describe_division() -> String {
+            // No annotations: the reply types come from `effect!`
+            let sum = perform!(Math::Add((6, 2)));
+            let quotient = perform!(Math::Divide((sum, 0)));
+            format!("{} / 0 = {quotient:?}", sum.pow(2))
+        }
+
+        let result = describe_division().handle(MathHandler).run_or_panic();
+        assert_eq!(result, r#"64 / 0 = Err("Division by zero")"#);
+    }
+
+    #[test]
+    fn test_io_operations() {
+        let result = io_program()
+            .handle(MockIOHandler::new(vec!["Alice".to_string()], vec![25]))
+            .run_or_panic();
+
+        assert_eq!(result, "Alice is 25 years old");
+    }
+
+    #[test]
+    fn test_logging_operations() {
+        let handler = LoggingHandler::new();
+        let result = logging_program().handle(handler).run_or_panic();
+
+        assert_eq!(result, 3);
+        // Note: handler is moved into the effectful computation, so we can't inspect its logs afterwards
+    }
+
+    #[test]
+    fn test_combined_handler() {
+        let handler = CombinedTestHandler::new(5, vec!["test".to_string()], vec![3]);
+        let result = complex_program().handle(handler).run_or_panic();
+
+        assert_eq!(result, "Final result: 30"); // 5 * 2 * 3 = 30
+                                                // Note: handler is moved into the effectful computation
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let handler = CombinedTestHandler::new(0, vec![], vec![]);
+        let result = error_handling_program().handle(handler).run_or_panic();
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), "Division by zero");
+        // Note: handler is moved into the effectful computation
+    }
+
+    #[test]
+    fn test_multiple_handler_calls() {
+        let result1 = test_computation()
+            .handle(TestHandler::new(1))
+            .run_or_panic();
+        let result2 = test_computation()
+            .handle(TestHandler::new(2))
+            .run_or_panic();
+        let result3 = test_computation()
+            .handle(TestHandler::new(3))
+            .run_or_panic();
+
+        assert_eq!(result1, 12); // (1 * 2) + 10 = 12
+        assert_eq!(result2, 14); // (2 * 2) + 10 = 14
+        assert_eq!(result3, 16); // (3 * 2) + 10 = 16
+    }
+
+    #[test]
+    fn test_zero_effects() {
+        #[effectful]
+        fn no_effects() -> i32 {
+            42
+        }
+
+        let result = no_effects().handle(TestHandler::new(0)).run_or_panic();
+
+        assert_eq!(result, 42);
+    }
+
+    #[test]
+    fn test_single_effect() {
+        #[effectful]
+        fn single_effect() -> i32 {
+            perform!(Test::GetValue)
+        }
+
+        let result = single_effect().handle(TestHandler::new(99)).run_or_panic();
+
+        assert_eq!(result, 99);
+    }
+
+    #[test]
+    fn test_many_effects() {
+        #[effectful]
+        fn many_effects() -> i32 {
+            let mut sum = 0;
+            for i in 1..=10 {
+                let _: () = perform!(Test::SetValue(i));
+                let val: i32 = perform!(Test::GetValue);
+                sum += val;
+            }
+            sum
+        }
+
+        let result = many_effects().handle(TestHandler::new(0)).run_or_panic();
+
+        assert_eq!(result, 55); // 1+2+3+...+10 = 55
+    }
+
+    #[test]
+    fn test_different_return_types() {
+        #[effectful]
+        fn return_string() -> String {
+            let val: i32 = perform!(Test::GetValue);
+            format!("Value is {val}")
+        }
+
+        #[effectful]
+        fn return_bool() -> bool {
+            let val: i32 = perform!(Test::GetValue);
+            val > 5
+        }
+
+        #[effectful]
+        fn return_option() -> Option<i32> {
+            let val: i32 = perform!(Test::GetValue);
+            if val > 0 {
+                Some(val)
+            } else {
+                None
+            }
+        }
+
+        let string_result = return_string().handle(TestHandler::new(42)).run_or_panic();
+        assert_eq!(string_result, "Value is 42");
+
+        let bool_result = return_bool().handle(TestHandler::new(10)).run_or_panic();
+        assert!(bool_result);
+
+        let option_result = return_option().handle(TestHandler::new(-1)).run_or_panic();
+        assert_eq!(option_result, None);
+    }
+
+    #[test]
+    fn test_handler_state_persistence() {
+        #[effectful]
+        fn increment_twice() -> (i32, i32) {
+            let first: i32 = perform!(Test::GetValue);
+            let _: () = perform!(Test::SetValue(first + 1));
+            let second: i32 = perform!(Test::GetValue);
+            let _: () = perform!(Test::SetValue(second + 1));
+            let third: i32 = perform!(Test::GetValue);
+            (first, third)
+        }
+
+        let result = increment_twice()
+            .handle(TestHandler::new(10))
+            .run_or_panic();
+
+        assert_eq!(result, (10, 12));
+    }
+
+    // Edge case tests
+    #[test]
+    fn test_effect_with_unit_return() {
+        #[effectful]
+        fn unit_return() -> () {
+            let _: () = perform!(Test::SetValue(42));
+        }
+
+        unit_return().handle(TestHandler::new(0)).run_or_panic();
+    }
+
+    #[test]
+    fn test_complex_data_types() {
+        effect! {
+            Complex::GetVector -> Vec<i32>;
+            Complex::GetMap -> HashMap<String, i32>;
+        }
+
+        struct ComplexHandler;
+
+        impl Handler<Op> for ComplexHandler {
+            fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+                match op {
+                    Op::Complex(Complex::GetVector) => Box::new(vec![1, 2, 3, 4, 5]),
+                    Op::Complex(Complex::GetMap) => {
+                        let mut map = HashMap::new();
+                        map.insert("a".to_string(), 1);
+                        map.insert("b".to_string(), 2);
+                        Box::new(map)
+                    }
+                }
+            }
+        }
+
+        #[effectful]
+        fn complex_data() -> (Vec<i32>, HashMap<String, i32>) {
+            let vec: Vec<i32> = perform!(Complex::GetVector);
+            let map: HashMap<String, i32> = perform!(Complex::GetMap);
+            (vec, map)
+        }
+
+        let (vec, map) = complex_data().handle(ComplexHandler).run_or_panic();
+
+        assert_eq!(vec, vec![1, 2, 3, 4, 5]);
+        assert_eq!(map.get("a"), Some(&1));
+        assert_eq!(map.get("b"), Some(&2));
+    }
+
+    // ============================================================================
+    // Custom Root Enum Tests
+    // ============================================================================
+
+    mod custom_root_tests {
+        use super::*;
+
+        // Test effect with custom root name
+        effect! {
+            root CustomOp;
+            Custom::GetValue -> i32;
+            Custom::SetValue (i32) -> ();
+        }
+
+        // Another effect with different custom root name
+        effect! {
+            root AnotherOp;
+            Another::GetString -> String;
+            Another::SetString (String) -> ();
+        }
+
+        struct CustomHandler {
+            value: i32,
+        }
+
+        impl Handler<CustomOp> for CustomHandler {
+            fn handle(&mut self, op: &CustomOp) -> Box<dyn std::any::Any + Send> {
+                match op {
+                    CustomOp::Custom(Custom::GetValue) => Box::new(self.value),
+                    CustomOp::Custom(Custom::SetValue(val)) => {
+                        self.value = *val;
+                        Box::new(())
+                    }
+                }
+            }
+        }
+
+        struct AnotherHandler {
+            text: String,
+        }
+
+        impl Handler<AnotherOp> for AnotherHandler {
+            fn handle(&mut self, op: &AnotherOp) -> Box<dyn std::any::Any + Send> {
+                match op {
+                    AnotherOp::Another(Another::GetString) => Box::new(self.text.clone()),
+                    AnotherOp::Another(Another::SetString(text)) => {
+                        self.text = text.clone();
+                        Box::new(())
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn test_custom_root_basic() {
+            fn test_computation() -> algae::Effectful<i32, CustomOp> {
+                algae::Effectful::new(
+                    #[coroutine]
+                    move |mut _reply: Option<algae::Reply>| {
+                        // Get current value
+                        let current: i32 = {
+                            let effect = algae::Effect::new(Custom::GetValue.into());
+                            let reply_opt = yield effect;
+                            reply_opt.unwrap().take::<i32>()
+                        };
+
+                        // Set new value
+                        {
+                            let effect = algae::Effect::new(Custom::SetValue(current + 10).into());
+                            let reply_opt = yield effect;
+                            let _: () = reply_opt.unwrap().take::<()>();
+                        }
+
+                        // Get updated value
+                        let updated: i32 = {
+                            let effect = algae::Effect::new(Custom::GetValue.into());
+                            let reply_opt = yield effect;
+                            reply_opt.unwrap().take::<i32>()
+                        };
+
+                        updated
+                    },
+                )
+            }
+
+            let result = test_computation()
+                .handle(CustomHandler { value: 5 })
+                .run_or_panic();
+
+            assert_eq!(result, 15);
+        }
+
+        #[test]
+        fn test_another_custom_root() {
+            fn string_computation() -> algae::Effectful<String, AnotherOp> {
+                algae::Effectful::new(
+                    #[coroutine]
+                    move |mut _reply: Option<algae::Reply>| {
+                        // Get current string
+                        let current: String = {
+                            let effect = algae::Effect::new(Another::GetString.into());
+                            let reply_opt = yield effect;
+                            reply_opt.unwrap().take::<String>()
+                        };
+
+                        // Set new string
+                        {
+                            let effect = algae::Effect::new(
+                                Another::SetString(format!("{current} world!")).into(),
+                            );
+                            let reply_opt = yield effect;
+                            let _: () = reply_opt.unwrap().take::<()>();
+                        }
+
+                        // Get updated string
+                        let updated: String = {
+                            let effect = algae::Effect::new(Another::GetString.into());
+                            let reply_opt = yield effect;
+                            reply_opt.unwrap().take::<String>()
+                        };
+
+                        updated
+                    },
+                )
+            }
+
+            let result = string_computation()
+                .handle(AnotherHandler {
+                    text: "Hello".to_string(),
+                })
+                .run_or_panic();
+
+            assert_eq!(result, "Hello world!");
+        }
+
+        // Test the combine_roots! macro
+        algae::combine_roots!(CombinedOp = CustomOp, AnotherOp);
+
+        struct CombinedHandler {
+            custom: CustomHandler,
+            another: AnotherHandler,
+        }
+
+        impl Handler<CombinedOp> for CombinedHandler {
+            fn handle(&mut self, op: &CombinedOp) -> Box<dyn std::any::Any + Send> {
+                match op {
+                    CombinedOp::CustomOp(custom_op) => self.custom.handle(custom_op),
+                    CombinedOp::AnotherOp(another_op) => self.another.handle(another_op),
+                }
+            }
+        }
+
+        #[test]
+        fn test_combine_roots() {
+            fn combined_computation() -> algae::Effectful<String, CombinedOp> {
+                algae::Effectful::new(
+                    #[coroutine]
+                    move |mut _reply: Option<algae::Reply>| {
+                        // Use custom effect
+                        let value: i32 = {
+                            let effect =
+                                algae::Effect::new(CombinedOp::CustomOp(Custom::GetValue.into()));
+                            let reply_opt = yield effect;
+                            reply_opt.unwrap().take::<i32>()
+                        };
+
+                        // Use another effect
+                        let text: String = {
+                            let effect = algae::Effect::new(CombinedOp::AnotherOp(
+                                Another::GetString.into(),
+                            ));
+                            let reply_opt = yield effect;
+                            reply_opt.unwrap().take::<String>()
+                        };
+
+                        format!("{text}: {value}")
+                    },
+                )
+            }
+
+            let result = combined_computation()
+                .handle(CombinedHandler {
+                    custom: CustomHandler { value: 42 },
+                    another: AnotherHandler {
+                        text: "Test".to_string(),
+                    },
+                })
+                .run_or_panic();
+
+            assert_eq!(result, "Test: 42");
+        }
+
+        #[test]
+        fn test_sub_root_computations_lift_into_the_combined_root() {
+            #[effectful(root = CustomOp)]
+            fn double() -> i32 {
+                let value: i32 = perform!(Custom::GetValue);
+                perform!(Custom::SetValue(value * 2));
+                perform!(Custom::GetValue)
+            }
+
+            #[effectful(root = AnotherOp)]
+            fn label(value: i32) -> String {
+                let text: String = perform!(Another::GetString);
+                let _: Vec<()> = perform_all!([Another::SetString(format!("{text}!"))]);
+                let text: String = perform!(Another::GetString);
+                format!("{text}: {value}")
+            }
+
+            let handler = CombinedHandler {
+                custom: CustomHandler { value: 21 },
+                another: AnotherHandler {
+                    text: "Test".to_string(),
+                },
+            };
+            let computation = double()
+                .lift_into::<CombinedOp>()
+                .bind(|value| label(value).lift_into());
+            assert_eq!(computation.handle(handler).run_or_panic(), "Test!: 42");
+
+            #[effectful(root = CombinedOp)]
+            fn both() -> String {
+                let value = embed!(double());
+                embed!(label(value))
+            }
+
+            let handler = CombinedHandler {
+                custom: CustomHandler { value: 5 },
+                another: AnotherHandler {
+                    text: "Embedded".to_string(),
+                },
+            };
+            assert_eq!(both().handle(handler).run_or_panic(), "Embedded!: 10");
+
+            let op = CombinedOp::from(CustomOp::Custom(Custom::GetValue));
+            assert!(Contains::<CustomOp>::project(&op).is_some());
+            assert!(Contains::<AnotherOp>::project(&op).is_none());
+        }
+    }
+
+    // ============================================================================
+    // One-Shot Guarantee Tests
+    // ============================================================================
+
+    #[test]
+    fn test_one_shot_effect_fill() {
+        // Test that filling an effect twice panics (enforcing one-shot guarantee)
+        let mut effect = Effect::new(Test::GetValue);
+
+        // First fill should succeed
+        effect.fill_boxed(Box::new(42i32));
+
+        // Second fill should panic
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            effect.fill_boxed(Box::new(24i32));
+        }));
+
+        assert!(result.is_err(), "Expected panic when filling effect twice");
+
+        // The panic message should contain our assertion text
+        if let Err(panic_payload) = result {
+            if let Some(panic_str) = panic_payload.downcast_ref::<&str>() {
+                assert!(
+                    panic_str.contains("reply filled twice"),
+                    "Panic message should mention 'reply filled twice', got: {panic_str}"
+                );
+            } else if let Some(panic_string) = panic_payload.downcast_ref::<String>() {
+                assert!(
+                    panic_string.contains("reply filled twice"),
+                    "Panic message should mention 'reply filled twice', got: {panic_string}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_one_shot_effect_get_reply_without_fill() {
+        // Test that get_reply panics when no reply has been filled
+        let effect = Effect::new(Test::GetValue);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = effect.get_reply();
+        }));
+
+        assert!(
+            result.is_err(),
+            "Expected panic when calling get_reply on unfilled effect"
+        );
+    }
+
+    #[test]
+    fn test_one_shot_reply_take_wrong_type() {
+        // Test that Reply::take panics with wrong type (additional safety check)
+        let mut effect = Effect::new(Test::GetValue);
+        effect.fill_boxed(Box::new(42i32));
+
+        let reply = effect.get_reply();
+
+        // Try to take with wrong type should panic
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _: String = reply.take();
+        }));
+
+        assert!(
+            result.is_err(),
+            "Expected panic when taking reply with wrong type"
+        );
+
+        // Check that the panic message contains helpful type information
+        if let Err(panic_payload) = result {
+            let panic_message = if let Some(panic_str) = panic_payload.downcast_ref::<&str>() {
+                panic_str.to_string()
+            } else if let Some(panic_string) = panic_payload.downcast_ref::<String>() {
+                panic_string.clone()
+            } else {
+                "Unknown panic message".to_string()
+            };
+
+            // Should mention both expected and actual types
+            assert!(
+                panic_message.contains("expected `alloc::string::String`")
+                    || panic_message.contains("expected 'alloc::string::String'")
+                    || panic_message.contains("expected `String`"),
+                "Panic message should mention expected type String, got: {panic_message}"
+            );
+            assert!(
+                panic_message.contains("but reply contains `i32`"),
+                "Panic message should mention actual type i32, got: {panic_message}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_reply_take_type_mismatch_common_types() {
+        // Test error messages for various common type mismatches
+
+        // Test i32 -> String mismatch
+        let mut effect1 = Effect::new(Test::GetValue);
+        effect1.fill_boxed(Box::new(42i32));
+        let reply1 = effect1.get_reply();
+
+        let result1 = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _: String = reply1.take();
+        }));
+        assert!(result1.is_err());
+
+        // Test String -> i32 mismatch
+        let mut effect2 = Effect::new(Test::GetValue);
+        effect2.fill_boxed(Box::new("hello".to_string()));
+        let reply2 = effect2.get_reply();
+
+        let result2 = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _: i32 = reply2.take();
+        }));
+        assert!(result2.is_err());
+
+        // Test () -> bool mismatch
+        let mut effect3 = Effect::new(Test::GetValue);
+        effect3.fill_boxed(Box::new(()));
+        let reply3 = effect3.get_reply();
+
+        let result3 = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _: bool = reply3.take();
+        }));
+        assert!(result3.is_err());
+    }
+
+    #[test]
+    fn test_reply_take_result_type_mismatch() {
+        // Test error messages for Result type mismatches (common in algae)
+        let mut effect = Effect::new(Test::GetValue);
+        effect.fill_boxed(Box::new(Ok::<String, String>("success".to_string())));
+        let reply = effect.get_reply();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _: Result<i32, String> = reply.take();
+        }));
+
+        assert!(
+            result.is_err(),
+            "Expected panic when taking Result with wrong type"
+        );
+
+        // Check that the panic message mentions Result types
+        if let Err(panic_payload) = result {
+            let panic_message = if let Some(panic_str) = panic_payload.downcast_ref::<&str>() {
+                panic_str.to_string()
+            } else if let Some(panic_string) = panic_payload.downcast_ref::<String>() {
+                panic_string.clone()
+            } else {
+                "Unknown panic message".to_string()
+            };
+
+            // Should mention Result types
+            assert!(
+                panic_message.contains("Result") && panic_message.contains("String"),
+                "Panic message should mention Result types, got: {panic_message}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_one_shot_fill_and_get_success() {
+        // Test the happy path: fill once, get once - should work perfectly
+        let mut effect = Effect::new(Test::GetValue);
+
+        // Fill with a value
+        effect.fill_boxed(Box::new(42i32));
+
+        // Get reply and extract value
+        let reply = effect.get_reply();
+        let value: i32 = reply.take();
+
+        assert_eq!(value, 42);
+    }
+
+    // ============================================================================
+    // Partial Handler Tests
+    // ============================================================================
+
+    #[test]
+    fn test_partial_handler_basic() {
+        // Test basic partial handler functionality
+        struct MathOnlyHandler;
+
+        impl PartialHandler<Op> for MathOnlyHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn math_only() -> i32 {
+            let sum: i32 = perform!(Math::Add((5, 3)));
+            let product: i32 = perform!(Math::Multiply((sum, 2)));
+            product
+        }
+
+        let result = math_only().try_run_with(MathOnlyHandler);
+        assert_eq!(result.unwrap(), 16); // (5 + 3) * 2 = 16
+    }
+
+    #[test]
+    fn test_partial_handler_decline() {
+        // Test that partial handlers correctly decline operations
+        struct MathOnlyHandler;
+
+        impl PartialHandler<Op> for MathOnlyHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(_) => Some(Box::new(42i32)), // Dummy response
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn mixed_effects() -> i32 {
+            let _: () = perform!(Logger::Info("Starting".to_string())); // This should fail
+            perform!(Math::Add((1, 2)))
+        }
+
+        let result = mixed_effects().try_run_with(MathOnlyHandler);
+        assert!(result.is_err());
+
+        if let Err(EffectError::Unhandled(UnhandledOp { op, .. })) = result {
+            match op {
+                Op::Logger(Logger::Info(_)) => (), // Expected
+                _ => panic!("Wrong unhandled operation"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_vec_handler() {
+        // Test VecHandler with multiple partial handlers
+        struct MathPartialHandler;
+        struct LoggerPartialHandler {
+            logs: Vec<String>,
+        }
+
+        impl PartialHandler<Op> for MathPartialHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    _ => None,
+                }
+            }
+        }
+
+        impl PartialHandler<Op> for LoggerPartialHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(msg)) => {
+                        self.logs.push(msg.clone());
+                        Some(Box::new(()))
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn combined_effects() -> i32 {
+            let _: () = perform!(Logger::Info("Computing sum".to_string()));
+            let sum: i32 = perform!(Math::Add((10, 20)));
+            let _: () = perform!(Logger::Info(format!("Result: {}", sum)));
+            sum
+        }
+
+        let mut vec_handler = VecHandler::new();
+        vec_handler.push(MathPartialHandler);
+        vec_handler.push(LoggerPartialHandler { logs: Vec::new() });
+
+        let result = combined_effects().try_run_with(vec_handler);
+        assert_eq!(result.unwrap(), 30);
+    }
+
+    #[test]
+    fn test_handle_all() {
+        // Test handle_all with array of handlers
+        struct AddHandler;
+        struct MultiplyHandler;
+        struct InfoHandler;
+
+        impl PartialHandler<Op> for AddHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    _ => None,
+                }
+            }
+        }
+
+        impl PartialHandler<Op> for MultiplyHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
+                    _ => None,
+                }
+            }
+        }
+
+        impl PartialHandler<Op> for InfoHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(_)) => Some(Box::new(())),
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn multi_effect_computation() -> i32 {
+            let _: () = perform!(Logger::Info("Starting".to_string()));
+            let sum: i32 = perform!(Math::Add((7, 3)));
+            let result: i32 = perform!(Math::Multiply((sum, 4)));
+            let _: () = perform!(Logger::Info("Done".to_string()));
+            result
+        }
+
+        // Test with manual VecHandler
+        let mut vec_handler = VecHandler::new();
+        vec_handler.push(AddHandler);
+        vec_handler.push(MultiplyHandler);
+        vec_handler.push(InfoHandler);
+
+        let result = multi_effect_computation().try_run_with(vec_handler);
+
+        assert_eq!(result.unwrap(), 40); // (7 + 3) * 4 = 40
+    }
+
+    #[test]
+    fn test_handle_all_method() {
+        // Test the actual handle_all method
+        struct MathHandler;
+        struct LogHandler;
+
+        impl PartialHandler<Op> for MathHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    _ => None,
+                }
+            }
+        }
+
+        impl PartialHandler<Op> for LogHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(_)) => Some(Box::new(())),
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn computation() -> i32 {
+            let _: () = perform!(Logger::Info("Computing".to_string()));
+            perform!(Math::Add((15, 25)))
+        }
+
+        // Using handle_all with a vec
+        let handlers: Vec<Box<dyn PartialHandler<Op> + Send>> =
+            vec![Box::new(MathHandler), Box::new(LogHandler)];
+
+        let result = computation().handle_all(handlers).run();
+
+        assert_eq!(result.unwrap(), 40);
+    }
+
+    #[test]
+    fn test_handle_all_with_vec() {
+        // Test handle_all with Vec
+        struct StdoutHandler;
+        struct CalculatorHandler;
+
+        impl PartialHandler<Op> for StdoutHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::IO(IO::WriteString(s)) => {
+                        // In tests, just acknowledge without printing
+                        let _ = s;
+                        Some(Box::new(()))
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        impl PartialHandler<Op> for CalculatorHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn program() -> i32 {
+            let _: () = perform!(IO::WriteString("2 + 3 =".to_string()));
+            perform!(Math::Add((2, 3)))
+        }
+
+        let mut vec_handler = VecHandler::new();
+        vec_handler.push(StdoutHandler);
+        vec_handler.push(CalculatorHandler);
+
+        let result = program().try_run_with(vec_handler);
+
+        assert_eq!(result.unwrap(), 5);
+    }
+
+    #[test]
+    fn test_handler_chain() {
+        // Test chaining handlers with .handle()
+        struct FirstHandler;
+        struct SecondHandler;
+        struct ThirdHandler;
+
+        impl PartialHandler<Op> for FirstHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    _ => None,
+                }
+            }
+        }
+
+        impl PartialHandler<Op> for SecondHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(_)) => Some(Box::new(())),
+                    _ => None,
+                }
+            }
+        }
+
+        impl PartialHandler<Op> for ThirdHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn chained_computation() -> i32 {
+            let _: () = perform!(Logger::Info("Start".to_string()));
+            let x: i32 = perform!(Math::Add((5, 5)));
+            let y: i32 = perform!(Math::Multiply((x, 3)));
+            let _: () = perform!(Logger::Info("End".to_string()));
+            y
+        }
+
+        let mut vec_handler = VecHandler::new();
+        vec_handler.push(FirstHandler);
+        vec_handler.push(SecondHandler);
+        vec_handler.push(ThirdHandler);
+
+        let result = chained_computation().try_run_with(vec_handler);
+
+        assert_eq!(result.unwrap(), 30); // (5 + 5) * 3 = 30
+    }
 
     #[test]
-    fn test_io_operations() {
-        let result = io_program()
-            .handle(MockIOHandler::new(vec!["Alice".to_string()], vec![25]))
-            .run();
+    fn test_unhandled_op_error() {
+        // Test that UnhandledOp contains the correct operation
+        struct EmptyHandler;
 
-        assert_eq!(result, "Alice is 25 years old");
+        impl PartialHandler<Op> for EmptyHandler {
+            fn maybe_handle(&mut self, _op: &Op) -> Option<Box<dyn Any + Send>> {
+                None // Decline everything
+            }
+        }
+
+        #[effectful]
+        fn failing_computation() -> i32 {
+            perform!(Math::Add((1, 2)))
+        }
+
+        let result = failing_computation().try_run_with(EmptyHandler);
+        assert!(result.is_err());
+
+        if let Err(EffectError::Unhandled(UnhandledOp { op, .. })) = result {
+            match op {
+                Op::Math(Math::Add((1, 2))) => (), // Expected
+                _ => panic!("Wrong unhandled operation"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_total_handler_as_partial() {
+        // Test that total handlers work with handle(..).run()
+        struct TotalMathHandler;
+
+        impl Handler<Op> for TotalMathHandler {
+            fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Box::new(a + b),
+                    Op::Math(Math::Multiply((a, b))) => Box::new(a * b),
+                    _ => panic!("TotalMathHandler cannot handle {op:?}"),
+                }
+            }
+        }
+
+        #[effectful]
+        fn math_computation() -> i32 {
+            let x: i32 = perform!(Math::Add((4, 6)));
+            perform!(Math::Multiply((x, 2)))
+        }
+
+        // Total handler used with run
+        let result = math_computation().handle(TotalMathHandler).run();
+        assert_eq!(result.unwrap(), 20); // (4 + 6) * 2 = 20
+    }
+
+    #[test]
+    fn test_mixed_handler_types() {
+        // Test mixing different partial handlers
+        struct PartialMathHandler;
+        struct PartialLogHandler;
+
+        impl PartialHandler<Op> for PartialMathHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    _ => None,
+                }
+            }
+        }
+
+        impl PartialHandler<Op> for PartialLogHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(_)) => Some(Box::new(())),
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn mixed_computation() -> i32 {
+            let _: () = perform!(Logger::Info("Starting".to_string()));
+            perform!(Math::Add((8, 12)))
+        }
+
+        let mut vec_handler = VecHandler::new();
+        vec_handler.push(PartialMathHandler);
+        vec_handler.push(PartialLogHandler);
+
+        let result = mixed_computation().try_run_with(vec_handler);
+
+        assert_eq!(result.unwrap(), 20);
+    }
+
+    #[test]
+    fn test_handler_ordering() {
+        // Test that handlers are tried in order
+        struct FirstCatchAllHandler;
+        struct SecondHandler;
+
+        impl PartialHandler<Op> for FirstCatchAllHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((_, _))) => Some(Box::new(100i32)), // Always return 100
+                    _ => None,
+                }
+            }
+        }
+
+        impl PartialHandler<Op> for SecondHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)), // Correct addition
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn order_test() -> i32 {
+            perform!(Math::Add((5, 7)))
+        }
+
+        // First handler should catch it
+        let mut vec_handler1 = VecHandler::new();
+        vec_handler1.push(FirstCatchAllHandler);
+        vec_handler1.push(SecondHandler);
+
+        let result1 = order_test().try_run_with(vec_handler1);
+        assert_eq!(result1.unwrap(), 100);
+
+        // Reverse order - second handler (now first) should give correct result
+        let mut vec_handler2 = VecHandler::new();
+        vec_handler2.push(SecondHandler);
+        vec_handler2.push(FirstCatchAllHandler);
+
+        let result2 = order_test().try_run_with(vec_handler2);
+        assert_eq!(result2.unwrap(), 12);
+    }
+
+    #[test]
+    fn test_stateful_partial_handler() {
+        // Test that partial handlers can maintain state
+        struct CountingHandler {
+            count: usize,
+        }
+
+        impl PartialHandler<Op> for CountingHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(_)) => {
+                        self.count += 1;
+                        Some(Box::new(()))
+                    }
+                    Op::Logger(Logger::GetLogCount) => Some(Box::new(self.count)),
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn counting_program() -> usize {
+            let _: () = perform!(Logger::Info("First".to_string()));
+            let _: () = perform!(Logger::Info("Second".to_string()));
+            let _: () = perform!(Logger::Info("Third".to_string()));
+            perform!(Logger::GetLogCount)
+        }
+
+        let handler = CountingHandler { count: 0 };
+        let result = counting_program().try_run_with(handler);
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_result_error_propagation() {
+        // Test that errors in effectful computations propagate correctly
+        struct DivisionHandler;
+
+        impl PartialHandler<Op> for DivisionHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Divide((a, b))) => {
+                        if *b == 0 {
+                            Some(Box::new(Err::<i32, String>("Division by zero".to_string())))
+                        } else {
+                            Some(Box::new(Ok::<i32, String>(a / b)))
+                        }
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn safe_division() -> Result<i32, String> {
+            let result: Result<i32, String> = perform!(Math::Divide((10, 0)));
+            result
+        }
+
+        let handler = DivisionHandler;
+        let result = safe_division().try_run_with(handler);
+        assert!(result.is_ok()); // the run succeeded
+        assert!(result.unwrap().is_err()); // but the division failed
+    }
+
+    #[test]
+    fn test_empty_vec_handler() {
+        // Test that empty VecHandler declines everything
+        #[effectful]
+        fn simple_effect() -> i32 {
+            perform!(Math::Add((1, 1)))
+        }
+
+        let empty_handler = VecHandler::<Op>::new();
+        let result = simple_effect().try_run_with(empty_handler);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_handle_all_empty() {
+        // Test handle_all with empty iterator
+        #[effectful]
+        fn simple_effect() -> i32 {
+            perform!(Math::Add((2, 2)))
+        }
+
+        let empty_handler = VecHandler::<Op>::new();
+        let result = simple_effect().try_run_with(empty_handler);
+        assert!(result.is_err());
     }
 
     #[test]
-    fn test_logging_operations() {
-        let handler = LoggingHandler::new();
-        let result = logging_program().handle(handler).run();
+    fn test_variable_handler_chain() {
+        // Test chaining multiple handlers together
+        struct ConsoleHandler;
+        impl PartialHandler<Op> for ConsoleHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::IO(IO::WriteString(s)) => {
+                        println!("Console: {s}");
+                        Some(Box::new(()))
+                    }
+                    _ => None,
+                }
+            }
+        }
 
-        assert_eq!(result, 3);
-        // Note: handler is moved into the effectful computation, so we can't inspect its logs afterwards
+        struct FileHandler;
+        impl PartialHandler<Op> for FileHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::IO(IO::ReadString) => Some(Box::new("file content".to_string())),
+                    _ => None,
+                }
+            }
+        }
+
+        struct LogHandler {
+            count: i32,
+        }
+        impl PartialHandler<Op> for LogHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(msg)) => {
+                        self.count += 1;
+                        println!("[INFO] {msg}");
+                        Some(Box::new(()))
+                    }
+                    Op::Logger(Logger::GetLogCount) => Some(Box::new(self.count as usize)),
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn three_handler_computation() -> String {
+            let _: () = perform!(Logger::Info("Starting computation".to_string()));
+            let _: () = perform!(IO::WriteString("Reading file...".to_string()));
+            let content: String = perform!(IO::ReadString);
+            let _: () = perform!(Logger::Info(format!("Read: {content}")));
+            let count: usize = perform!(Logger::GetLogCount);
+            format!("Completed with {count} log entries")
+        }
+
+        // Chain three handlers together using handle_all
+        let result = three_handler_computation()
+            .handle_all(vec![
+                Box::new(ConsoleHandler) as Box<dyn PartialHandler<Op> + Send>,
+                Box::new(FileHandler),
+                Box::new(LogHandler { count: 0 }),
+            ])
+            .run();
+
+        assert_eq!(result.unwrap(), "Completed with 2 log entries");
     }
 
     #[test]
-    fn test_combined_handler() {
-        let handler = CombinedTestHandler::new(5, vec!["test".to_string()], vec![3]);
-        let result = complex_program().handle(handler).run();
+    fn test_handler_chain_with_total_handlers() {
+        // Test mixing Handler and PartialHandler types
+        #[effectful]
+        fn mixed_computation() -> i32 {
+            let a: i32 = perform!(Test::GetValue);
+            let b: i32 = perform!(Math::Add((a, 10)));
+            let _: () = perform!(Test::SetValue(b));
+            b
+        }
 
-        assert_eq!(result, "Final result: 30"); // 5 * 2 * 3 = 30
-                                                // Note: handler is moved into the effectful computation
+        // Create a combined handler that handles both Test and Math operations
+        struct CombinedHandler {
+            test_handler: TestHandler,
+        }
+
+        impl CombinedHandler {
+            fn new(initial: i32) -> Self {
+                Self {
+                    test_handler: TestHandler::new(initial),
+                }
+            }
+        }
+
+        impl Handler<Op> for CombinedHandler {
+            fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+                match op {
+                    Op::Test(_) => self.test_handler.handle(op),
+                    Op::Math(Math::Add((a, b))) => Box::new(a + b),
+                    _ => panic!("CombinedHandler cannot handle this operation: {op:?}"),
+                }
+            }
+        }
+
+        // Use the combined handler directly
+        let result = mixed_computation()
+            .handle(CombinedHandler::new(5))
+            .run_or_panic();
+
+        assert_eq!(result, 15);
+
+        // Also test the checked version
+        let result2 = mixed_computation().handle(CombinedHandler::new(5)).run();
+
+        assert_eq!(result2.unwrap(), 15);
     }
 
     #[test]
-    fn test_error_handling() {
-        let handler = CombinedTestHandler::new(0, vec![], vec![]);
-        let result = error_handling_program().handle(handler).run();
+    fn test_unhandled_op_error_in_chain() {
+        // Test that unhandled operations properly return errors
+        #[effectful]
+        fn unhandled_computation() -> i32 {
+            let _: () = perform!(Logger::Info("This will work".to_string()));
+            let x: i32 = perform!(Math::Add((5, 5))); // This won't be handled
+            x
+        }
 
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "Division by zero");
-        // Note: handler is moved into the effectful computation
+        struct OnlyLoggerHandler;
+        impl PartialHandler<Op> for OnlyLoggerHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(msg)) => {
+                        println!("[LOG] {msg}");
+                        Some(Box::new(()))
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        let result = unhandled_computation().try_run_with(OnlyLoggerHandler);
+
+        match result {
+            Err(EffectError::Unhandled(UnhandledOp {
+                op: Op::Math(Math::Add((5, 5))),
+                ..
+            })) => (),
+            _ => panic!("Expected UnhandledOp error for Math::Add"),
+        }
     }
 
     #[test]
-    fn test_multiple_handler_calls() {
-        let result1 = test_computation().handle(TestHandler::new(1)).run();
-        let result2 = test_computation().handle(TestHandler::new(2)).run();
-        let result3 = test_computation().handle(TestHandler::new(3)).run();
+    fn test_handle_all_alternative() {
+        // Test handle_all as an alternative to chained handle calls
+        struct Handler1;
+        impl PartialHandler<Op> for Handler1 {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    _ => None,
+                }
+            }
+        }
 
-        assert_eq!(result1, 12); // (1 * 2) + 10 = 12
-        assert_eq!(result2, 14); // (2 * 2) + 10 = 14
-        assert_eq!(result3, 16); // (3 * 2) + 10 = 16
+        struct Handler2;
+        impl PartialHandler<Op> for Handler2 {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
+                    _ => None,
+                }
+            }
+        }
+
+        struct Handler3;
+        impl PartialHandler<Op> for Handler3 {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(msg)) => {
+                        println!("[INFO] {msg}");
+                        Some(Box::new(()))
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn computation() -> i32 {
+            let _: () = perform!(Logger::Info("Starting".to_string()));
+            let a: i32 = perform!(Math::Add((2, 3)));
+            let b: i32 = perform!(Math::Multiply((a, 4)));
+            b
+        }
+
+        // Use handle_all to attach all handlers at once
+        let handlers: Vec<Box<dyn PartialHandler<Op> + Send>> =
+            vec![Box::new(Handler1), Box::new(Handler2), Box::new(Handler3)];
+
+        let result = computation().handle_all(handlers).run();
+
+        assert_eq!(result.unwrap(), 20); // (2 + 3) * 4 = 20
     }
 
     #[test]
-    fn test_zero_effects() {
+    fn test_chained_handler_syntax() {
+        // Test the .handle().handle().handle() chaining pattern
+        struct Handler1;
+        impl PartialHandler<Op> for Handler1 {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    _ => None,
+                }
+            }
+        }
+        impl IntoVecHandler<Op> for Handler1 {
+            fn into_vec_handler(self) -> VecHandler<Op> {
+                let mut vec = VecHandler::new();
+                vec.push(self);
+                vec
+            }
+        }
+
+        struct Handler2;
+        impl PartialHandler<Op> for Handler2 {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
+                    _ => None,
+                }
+            }
+        }
+        impl IntoVecHandler<Op> for Handler2 {
+            fn into_vec_handler(self) -> VecHandler<Op> {
+                let mut vec = VecHandler::new();
+                vec.push(self);
+                vec
+            }
+        }
+
+        struct Handler3;
+        impl PartialHandler<Op> for Handler3 {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::Info(msg)) => {
+                        // Just return unit, don't actually print
+                        let _ = msg;
+                        Some(Box::new(()))
+                    }
+                    _ => None,
+                }
+            }
+        }
+        impl IntoVecHandler<Op> for Handler3 {
+            fn into_vec_handler(self) -> VecHandler<Op> {
+                let mut vec = VecHandler::new();
+                vec.push(self);
+                vec
+            }
+        }
+
         #[effectful]
-        fn no_effects() -> i32 {
-            42
+        fn chained_computation() -> i32 {
+            let _: () = perform!(Logger::Info("Starting".to_string()));
+            let sum: i32 = perform!(Math::Add((10, 20)));
+            let product: i32 = perform!(Math::Multiply((sum, 2)));
+            let _: () = perform!(Logger::Info("Done".to_string()));
+            product
         }
 
-        let result = no_effects().handle(TestHandler::new(0)).run();
+        // Test direct chaining with begin_chain
+        let result = chained_computation()
+            .begin_chain()
+            .handle(Handler1)
+            .handle(Handler2)
+            .handle(Handler3)
+            .run();
 
-        assert_eq!(result, 42);
-    }
+        assert_eq!(result.unwrap(), 60); // (10 + 20) * 2 = 60
 
-    #[test]
-    fn test_single_effect() {
-        #[effectful]
-        fn single_effect() -> i32 {
-            perform!(Test::GetValue)
-        }
+        // Test chaining starting with handle_all (still works)
+        let result = chained_computation()
+            .handle_all([Handler1])
+            .handle(Handler2)
+            .handle(Handler3)
+            .run();
 
-        let result = single_effect().handle(TestHandler::new(99)).run();
+        assert_eq!(result.unwrap(), 60);
 
-        assert_eq!(result, 99);
-    }
+        // Test partial chain (missing logger handler)
+        let result = chained_computation()
+            .begin_chain()
+            .handle(Handler1)
+            .handle(Handler2)
+            // Intentionally not adding Handler3
+            .run();
 
-    #[test]
-    fn test_many_effects() {
-        #[effectful]
-        fn many_effects() -> i32 {
-            let mut sum = 0;
-            for i in 1..=10 {
-                let _: () = perform!(Test::SetValue(i));
-                let val: i32 = perform!(Test::GetValue);
-                sum += val;
-            }
-            sum
+        // Should fail because Logger::Info is not handled
+        assert!(result.is_err());
+        match result {
+            Err(EffectError::Unhandled(UnhandledOp {
+                op: Op::Logger(Logger::Info(_)),
+                ..
+            })) => (),
+            _ => panic!("Expected unhandled Logger::Info operation"),
         }
-
-        let result = many_effects().handle(TestHandler::new(0)).run();
-
-        assert_eq!(result, 55); // 1+2+3+...+10 = 55
     }
 
     #[test]
-    fn test_different_return_types() {
-        #[effectful]
-        fn return_string() -> String {
-            let val: i32 = perform!(Test::GetValue);
-            format!("Value is {val}")
+    fn test_vec_handler_flattening() {
+        // Test that VecHandlers are properly flattened when chained
+        struct Handler1;
+        impl PartialHandler<Op> for Handler1 {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    _ => None,
+                }
+            }
         }
-
-        #[effectful]
-        fn return_bool() -> bool {
-            let val: i32 = perform!(Test::GetValue);
-            val > 5
+        impl IntoVecHandler<Op> for Handler1 {
+            fn into_vec_handler(self) -> VecHandler<Op> {
+                let mut vec = VecHandler::new();
+                vec.push(self);
+                vec
+            }
         }
 
-        #[effectful]
-        fn return_option() -> Option<i32> {
-            let val: i32 = perform!(Test::GetValue);
-            if val > 0 {
-                Some(val)
-            } else {
-                None
+        struct Handler2;
+        impl PartialHandler<Op> for Handler2 {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
+                    _ => None,
+                }
+            }
+        }
+        impl IntoVecHandler<Op> for Handler2 {
+            fn into_vec_handler(self) -> VecHandler<Op> {
+                let mut vec = VecHandler::new();
+                vec.push(self);
+                vec
             }
         }
 
-        let string_result = return_string().handle(TestHandler::new(42)).run();
-        assert_eq!(string_result, "Value is 42");
-
-        let bool_result = return_bool().handle(TestHandler::new(10)).run();
-        assert!(bool_result);
-
-        let option_result = return_option().handle(TestHandler::new(-1)).run();
-        assert_eq!(option_result, None);
-    }
-
-    #[test]
-    fn test_handler_state_persistence() {
         #[effectful]
-        fn increment_twice() -> (i32, i32) {
-            let first: i32 = perform!(Test::GetValue);
-            let _: () = perform!(Test::SetValue(first + 1));
-            let second: i32 = perform!(Test::GetValue);
-            let _: () = perform!(Test::SetValue(second + 1));
-            let third: i32 = perform!(Test::GetValue);
-            (first, third)
+        fn test_computation() -> i32 {
+            let sum: i32 = perform!(Math::Add((10, 20)));
+            let product: i32 = perform!(Math::Multiply((sum, 2)));
+            product
         }
 
-        let result = increment_twice().handle(TestHandler::new(10)).run();
-
-        assert_eq!(result, (10, 12));
-    }
+        // Create a VecHandler with Handler1
+        let mut vec1 = VecHandler::new();
+        vec1.push(Handler1);
 
-    // Edge case tests
-    #[test]
-    fn test_effect_with_unit_return() {
-        #[effectful]
-        fn unit_return() -> () {
-            let _: () = perform!(Test::SetValue(42));
-        }
+        // Create another VecHandler with Handler2
+        let mut vec2 = VecHandler::new();
+        vec2.push(Handler2);
 
-        unit_return().handle(TestHandler::new(0)).run();
-    }
+        // Chain them together - this should flatten
+        let result = test_computation()
+            .begin_chain()
+            .handle(vec1)
+            .handle(vec2)
+            .run();
 
-    #[test]
-    fn test_complex_data_types() {
-        effect! {
-            Complex::GetVector -> Vec<i32>;
-            Complex::GetMap -> HashMap<String, i32>;
-        }
+        assert_eq!(result.unwrap(), 60); // (10 + 20) * 2 = 60
 
-        struct ComplexHandler;
+        // Verify that nesting VecHandlers still works
+        let mut outer_vec = VecHandler::new();
+        outer_vec.push(Handler1);
 
-        impl Handler<Op> for ComplexHandler {
-            fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
-                match op {
-                    Op::Complex(Complex::GetVector) => Box::new(vec![1, 2, 3, 4, 5]),
-                    Op::Complex(Complex::GetMap) => {
-                        let mut map = HashMap::new();
-                        map.insert("a".to_string(), 1);
-                        map.insert("b".to_string(), 2);
-                        Box::new(map)
-                    }
-                }
-            }
-        }
+        let mut inner_vec = VecHandler::new();
+        inner_vec.push(Handler2);
 
-        #[effectful]
-        fn complex_data() -> (Vec<i32>, HashMap<String, i32>) {
-            let vec: Vec<i32> = perform!(Complex::GetVector);
-            let map: HashMap<String, i32> = perform!(Complex::GetMap);
-            (vec, map)
-        }
+        let result = test_computation().handle(outer_vec).handle(inner_vec).run();
 
-        let (vec, map) = complex_data().handle(ComplexHandler).run();
+        assert_eq!(result.unwrap(), 60);
+    }
 
-        assert_eq!(vec, vec![1, 2, 3, 4, 5]);
-        assert_eq!(map.get("a"), Some(&1));
-        assert_eq!(map.get("b"), Some(&2));
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_entry_points_still_run() {
+        let strict = || StrictMathHandler { logged: vec![] };
+
+        assert_eq!(divide_and_log(10, 2).run_checked(strict()), Ok(5));
+        assert_eq!(divide_and_log(10, 2).handle(strict()).run_checked(), Ok(5));
+        assert_eq!(divide_and_log(10, 2).handle(strict()).try_run().unwrap(), 5);
+        let (result, handler) = divide_and_log(10, 2)
+            .handle(strict())
+            .try_run_with_handler();
+        assert_eq!(result.unwrap(), 5);
+        assert_eq!(handler.logged, ["quotient = 5"]);
+
+        let mut chain = VecHandler::new();
+        chain.push(strict());
+        assert_eq!(divide_and_log(10, 2).run_checked_with(chain), Ok(5));
+
+        let unhandled = test_computation().run_checked(strict()).unwrap_err();
+        assert_eq!(unhandled.op, Op::Test(Test::GetValue));
     }
 
     // ============================================================================
-    // Custom Root Enum Tests
+    // Abort and try_run Tests
     // ============================================================================
 
-    mod custom_root_tests {
-        use super::*;
-
-        // Test effect with custom root name
-        effect! {
-            root CustomOp;
-            Custom::GetValue -> i32;
-            Custom::SetValue (i32) -> ();
-        }
-
-        // Another effect with different custom root name
-        effect! {
-            root AnotherOp;
-            Another::GetString -> String;
-            Another::SetString (String) -> ();
-        }
+    #[derive(Debug, PartialEq)]
+    struct DivideByZero;
 
-        struct CustomHandler {
-            value: i32,
-        }
+    /// Aborts on division by zero instead of replying with an error value.
+    struct StrictMathHandler {
+        logged: Vec<String>,
+    }
 
-        impl Handler<CustomOp> for CustomHandler {
-            fn handle(&mut self, op: &CustomOp) -> Box<dyn std::any::Any + Send> {
-                match op {
-                    CustomOp::Custom(Custom::GetValue) => Box::new(self.value),
-                    CustomOp::Custom(Custom::SetValue(val)) => {
-                        self.value = *val;
-                        Box::new(())
-                    }
+    impl PartialHandler<Op> for StrictMathHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Math(Math::Divide((_, 0))) => Some(Abort::boxed(DivideByZero)),
+                Op::Math(Math::Divide((a, b))) => Some(Box::new(Ok::<i32, String>(a / b))),
+                Op::Logger(Logger::Info(msg)) => {
+                    self.logged.push(msg.clone());
+                    Some(Box::new(()))
                 }
+                _ => None,
             }
         }
+    }
 
-        struct AnotherHandler {
-            text: String,
-        }
+    #[effectful]
+    fn divide_and_log(a: i32, b: i32) -> i32 {
+        let quotient: Result<i32, String> = perform!(Math::Divide((a, b)));
+        let quotient = quotient.unwrap();
+        let _: () = perform!(Logger::Info(format!("quotient = {quotient}")));
+        quotient
+    }
 
-        impl Handler<AnotherOp> for AnotherHandler {
-            fn handle(&mut self, op: &AnotherOp) -> Box<dyn std::any::Any + Send> {
-                match op {
-                    AnotherOp::Another(Another::GetString) => Box::new(self.text.clone()),
-                    AnotherOp::Another(Another::SetString(text)) => {
-                        self.text = text.clone();
-                        Box::new(())
-                    }
-                }
+    #[test]
+    fn test_try_run_success() {
+        let result = divide_and_log(10, 2).try_run_with(StrictMathHandler { logged: vec![] });
+        assert_eq!(result.unwrap(), 5);
+    }
+
+    #[test]
+    fn test_abort_stops_computation() {
+        let err = divide_and_log(10, 0)
+            .try_run_with(StrictMathHandler { logged: vec![] })
+            .unwrap_err();
+
+        match err {
+            EffectError::Aborted(abort) => {
+                assert!(abort.is::<DivideByZero>());
+                assert_eq!(abort.description(), "DivideByZero");
+                assert_eq!(abort.to_string(), "DivideByZero");
+                assert_eq!(abort.downcast::<DivideByZero>().unwrap(), DivideByZero);
             }
+            other => panic!("Expected abort, got {other:?}"),
         }
+    }
 
-        #[test]
-        fn test_custom_root_basic() {
-            fn test_computation() -> algae::Effectful<i32, CustomOp> {
-                algae::Effectful::new(
-                    #[coroutine]
-                    move |mut _reply: Option<algae::Reply>| {
-                        // Get current value
-                        let current: i32 = {
-                            let effect = algae::Effect::new(Custom::GetValue.into());
-                            let reply_opt = yield effect;
-                            reply_opt.unwrap().take::<i32>()
-                        };
-
-                        // Set new value
-                        {
-                            let effect = algae::Effect::new(Custom::SetValue(current + 10).into());
-                            let reply_opt = yield effect;
-                            let _: () = reply_opt.unwrap().take::<()>();
-                        }
-
-                        // Get updated value
-                        let updated: i32 = {
-                            let effect = algae::Effect::new(Custom::GetValue.into());
-                            let reply_opt = yield effect;
-                            reply_opt.unwrap().take::<i32>()
-                        };
-
-                        updated
-                    },
-                )
+    #[test]
+    fn test_try_run_reports_unhandled() {
+        let err = test_computation()
+            .try_run_with(StrictMathHandler { logged: vec![] })
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            EffectError::Unhandled(UnhandledOp {
+                op: Op::Test(Test::GetValue),
+                ..
+            })
+        ));
+        assert!(err
+            .to_string()
+            .starts_with("Unhandled operation: Test(GetValue), performed as operation 0"));
+    }
+
+    #[test]
+    fn test_abort_downcast_wrong_type_returns_abort() {
+        let abort = Abort::new("disk full");
+        let abort = abort.downcast::<i32>().unwrap_err();
+        assert_eq!(abort.downcast_ref::<&str>(), Some(&"disk full"));
+        assert_eq!(format!("{abort:?}"), "Abort(\"disk full\")");
+    }
+
+    #[test]
+    fn test_abort_panics_in_run() {
+        struct AbortingHandler;
+        impl Handler<Op> for AbortingHandler {
+            fn handle(&mut self, _op: &Op) -> Box<dyn Any + Send> {
+                Abort::boxed("no more values")
             }
+        }
 
-            let result = test_computation().handle(CustomHandler { value: 5 }).run();
+        let result =
+            std::panic::catch_unwind(|| test_computation().handle(AbortingHandler).run_or_panic());
+        let payload = result.unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert_eq!(message, "Effectful computation aborted: \"no more values\"");
+    }
 
-            assert_eq!(result, 15);
+    #[test]
+    fn test_perform_location_only_during_dispatch() {
+        struct LocationHandler;
+        impl Handler<Op> for LocationHandler {
+            fn handle(&mut self, _op: &Op) -> Box<dyn Any + Send> {
+                let location = algae::perform_location().expect("set during dispatch");
+                Box::new(location.line() as i32)
+            }
         }
 
-        #[test]
-        fn test_another_custom_root() {
-            fn string_computation() -> algae::Effectful<String, AnotherOp> {
-                algae::Effectful::new(
-                    #[coroutine]
-                    move |mut _reply: Option<algae::Reply>| {
-                        // Get current string
-                        let current: String = {
-                            let effect = algae::Effect::new(Another::GetString.into());
-                            let reply_opt = yield effect;
-                            reply_opt.unwrap().take::<String>()
-                        };
+        #[effectful]
+        fn where_am_i() -> (i32, u32) {
+            (perform!(Test::GetValue), line!())
+        }
 
-                        // Set new string
-                        {
-                            let effect = algae::Effect::new(
-                                Another::SetString(format!("{current} world!")).into(),
-                            );
-                            let reply_opt = yield effect;
-                            let _: () = reply_opt.unwrap().take::<()>();
-                        }
+        assert!(algae::perform_location().is_none());
+        let (reported, actual) = where_am_i().handle(LocationHandler).run_or_panic();
+        assert_eq!(reported as u32, actual);
+        assert!(algae::perform_location().is_none());
 
-                        // Get updated string
-                        let updated: String = {
-                            let effect = algae::Effect::new(Another::GetString.into());
-                            let reply_opt = yield effect;
-                            reply_opt.unwrap().take::<String>()
-                        };
+        let effect = Effect::new(Test::GetValue);
+        assert_eq!(effect.location().line(), line!() - 1);
+    }
 
-                        updated
-                    },
-                )
-            }
+    #[test]
+    fn test_try_from_projects_families() {
+        let add = Op::Math(Math::Add((1, 2)));
+        let math: &Math = (&add).try_into().unwrap();
+        assert_eq!(math, &Math::Add((1, 2)));
+        assert_eq!(<&Test>::try_from(&add), Err(&add));
+
+        let info = Op::Logger(Logger::Info("hi".into()));
+        assert_eq!(Logger::try_from(info), Ok(Logger::Info("hi".into())));
+        assert_eq!(IO::try_from(add.clone()), Err(add));
+    }
 
-            let result = string_computation()
-                .handle(AnotherHandler {
-                    text: "Hello".to_string(),
-                })
-                .run();
+    #[test]
+    fn test_op_names() {
+        let add = Op::Math(Math::Add((1, 2)));
+        assert_eq!(
+            (add.family(), add.name(), add.qualified_name()),
+            ("Math", "Add", "Math.Add")
+        );
+        let read = IO::ReadString;
+        assert_eq!(
+            (read.family(), read.name(), read.qualified_name()),
+            ("IO", "ReadString", "IO.ReadString")
+        );
 
-            assert_eq!(result, "Hello world!");
-        }
+        struct Custom;
+        impl OpMeta for Custom {}
+        assert_eq!(
+            (Custom.family(), Custom.qualified_name()),
+            ("Custom", "Custom")
+        );
+    }
 
-        // Test the combine_roots! macro
-        algae::combine_roots!(CombinedOp = CustomOp, AnotherOp);
+    #[test]
+    fn test_scopes_follow_the_sub_computation_path() {
+        #[derive(Clone, Default)]
+        struct ScopeRecorder(Arc<Mutex<Vec<Scope>>>);
+        impl Handler<Op> for ScopeRecorder {
+            fn handle(&mut self, _op: &Op) -> Box<dyn Any + Send> {
+                let scope = algae::perform_scope().expect("set during dispatch");
+                self.0.lock().unwrap().push(scope);
+                Box::new(0)
+            }
+        }
 
-        struct CombinedHandler {
-            custom: CustomHandler,
-            another: AnotherHandler,
+        #[effectful]
+        fn get() -> i32 {
+            perform!(Test::GetValue)
         }
 
-        impl Handler<CombinedOp> for CombinedHandler {
-            fn handle(&mut self, op: &CombinedOp) -> Box<dyn std::any::Any + Send> {
-                match op {
-                    CombinedOp::CustomOp(custom_op) => self.custom.handle(custom_op),
-                    CombinedOp::AnotherOp(another_op) => self.another.handle(another_op),
-                }
-            }
+        let run = |computation: Effectful<i32, Op>| {
+            let recorder = ScopeRecorder::default();
+            computation.handle(recorder.clone()).run_or_panic();
+            let scopes = recorder.0.lock().unwrap().clone();
+            scopes
+        };
+        let root = Scope::ROOT;
+        assert_eq!(run(get()), [root]);
+        assert_eq!(run(get().scoped(3)), [root.child(3)]);
+        assert_eq!(
+            run(get().bind(|_| get().bind(|_| get()))),
+            [
+                root.child(0),
+                root.child(1).child(0),
+                root.child(1).child(1)
+            ]
+        );
+        assert_eq!(run(get().scoped(1).scoped(2)), [root.child(2).child(1)]);
+        assert_ne!(root.child(1).child(2), root.child(2).child(1));
+        assert!(algae::perform_scope().is_none());
+
+        assert_eq!(root.seed(7), 7);
+        assert_ne!(root.child(0).seed(7), root.child(1).seed(7));
+    }
+
+    #[derive(RouteHandler)]
+    struct RoutedHandler {
+        #[route(Op::Test)]
+        test: TestHandler,
+        #[route(Op::Math)]
+        math: MathHandler,
+        #[allow(dead_code)]
+        label: &'static str,
+    }
+
+    #[test]
+    fn test_derived_route_handler() {
+        #[effectful]
+        fn test_and_math() -> i32 {
+            let value: i32 = perform!(Test::GetValue);
+            perform!(Math::Add((value, 1)))
         }
 
-        #[test]
-        fn test_combine_roots() {
-            fn combined_computation() -> algae::Effectful<String, CombinedOp> {
-                algae::Effectful::new(
-                    #[coroutine]
-                    move |mut _reply: Option<algae::Reply>| {
-                        // Use custom effect
-                        let value: i32 = {
-                            let effect =
-                                algae::Effect::new(CombinedOp::CustomOp(Custom::GetValue.into()));
-                            let reply_opt = yield effect;
-                            reply_opt.unwrap().take::<i32>()
-                        };
+        let handler = RoutedHandler {
+            test: TestHandler::new(41),
+            math: MathHandler,
+            label: "routed",
+        };
+        assert_eq!(test_and_math().handle(handler).run_or_panic(), 42);
+    }
 
-                        // Use another effect
-                        let text: String = {
-                            let effect = algae::Effect::new(CombinedOp::AnotherOp(
-                                Another::GetString.into(),
-                            ));
-                            let reply_opt = yield effect;
-                            reply_opt.unwrap().take::<String>()
-                        };
+    #[test]
+    #[should_panic(expected = "RoutedHandler has no route for this operation")]
+    fn test_derived_route_handler_panics_without_route() {
+        let mut handler = RoutedHandler {
+            test: TestHandler::new(0),
+            math: MathHandler,
+            label: "routed",
+        };
+        handler.handle(&Op::Logger(Logger::GetLogCount));
+    }
 
-                        format!("{text}: {value}")
-                    },
-                )
+    #[test]
+    fn test_derived_partial_route_handler() {
+        struct Counter(usize);
+        impl PartialHandler<Op> for Counter {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Logger(Logger::GetLogCount) => Some(Box::new(self.0)),
+                    _ => None,
+                }
             }
+        }
 
-            let result = combined_computation()
-                .handle(CombinedHandler {
-                    custom: CustomHandler { value: 42 },
-                    another: AnotherHandler {
-                        text: "Test".to_string(),
-                    },
-                })
-                .run();
+        #[derive(RouteHandler)]
+        #[route_handler(partial)]
+        struct Partial<H>(#[route(Op::Test)] H, #[route(Op::Logger, Op::IO)] Counter);
 
-            assert_eq!(result, "Test: 42");
-        }
+        let mut handler = Partial(
+            HandlerWrapper::<Op, _>::new(TestHandler::new(7)),
+            Counter(3),
+        );
+        let value = handler.maybe_handle(&Op::Test(Test::GetValue)).unwrap();
+        assert_eq!(*value.downcast::<i32>().unwrap(), 7);
+        let count = handler
+            .maybe_handle(&Op::Logger(Logger::GetLogCount))
+            .unwrap();
+        assert_eq!(*count.downcast::<usize>().unwrap(), 3);
+        assert!(handler.maybe_handle(&Op::IO(IO::ReadNumber)).is_none());
+        assert!(handler.maybe_handle(&Op::Math(Math::Add((1, 2)))).is_none());
     }
 
     // ============================================================================
-    // One-Shot Guarantee Tests
+    // Improved Error Message Tests (TypeId Storage and Registry)
     // ============================================================================
 
     #[test]
-    fn test_one_shot_effect_fill() {
-        // Test that filling an effect twice panics (enforcing one-shot guarantee)
+    fn test_reply_try_take_success() {
+        // Test successful try_take
         let mut effect = Effect::new(Test::GetValue);
+        effect.fill_boxed(Box::new(42i32));
+        let mut reply = effect.get_reply();
 
-        // First fill should succeed
+        let result = reply.try_take::<i32>();
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[test]
+    fn test_reply_new_keeps_small_values_inline() {
+        let mut small = Reply::new(7u64);
+        assert!(matches!(
+            small.inner,
+            Some(crate::Stored {
+                value: crate::Slot::Inline(_),
+                ..
+            })
+        ));
+        assert!(small.is::<u64>());
+        assert_eq!(
+            small.try_take::<u32>().unwrap_err(),
+            ReplyError::WrongType {
+                expected: "u32",
+                actual: "u64".to_string(),
+                op: None,
+            }
+        );
+        assert_eq!(small.try_take::<u64>(), Ok(7));
+        assert!(!small.is::<u64>());
+
+        let large = Reply::new([1u64; 4]);
+        assert!(matches!(
+            large.inner,
+            Some(crate::Stored {
+                value: crate::Slot::Boxed(_),
+                ..
+            })
+        ));
+        assert_eq!(large.take::<[u64; 4]>(), [1; 4]);
+
+        let value = Arc::new(());
+        let boxed = Reply::new(Arc::clone(&value)).into_boxed();
+        assert_eq!(Arc::strong_count(&value), 2);
+        drop(boxed);
+        drop(Reply::new(Arc::clone(&value)));
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_reply_try_take_already_taken() {
+        // Test try_take when value already taken
+        let mut effect = Effect::new(Test::GetValue);
         effect.fill_boxed(Box::new(42i32));
+        let mut reply = effect.get_reply();
 
-        // Second fill should panic
-        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            effect.fill_boxed(Box::new(24i32));
-        }));
+        // First take succeeds
+        let result1 = reply.try_take::<i32>();
+        assert_eq!(result1.unwrap(), 42);
 
-        assert!(result.is_err(), "Expected panic when filling effect twice");
+        // Second take returns AlreadyTaken error
+        let result2 = reply.try_take::<i32>();
+        assert!(matches!(result2, Err(ReplyError::AlreadyTaken)));
+    }
 
-        // The panic message should contain our assertion text
-        if let Err(panic_payload) = result {
-            if let Some(panic_str) = panic_payload.downcast_ref::<&str>() {
-                assert!(
-                    panic_str.contains("reply filled twice"),
-                    "Panic message should mention 'reply filled twice', got: {panic_str}"
-                );
-            } else if let Some(panic_string) = panic_payload.downcast_ref::<String>() {
+    #[test]
+    fn test_reply_try_take_wrong_type() {
+        // Test try_take with wrong type
+        let mut effect = Effect::new(Test::GetValue);
+        effect.fill_boxed(Box::new(42i32));
+        let mut reply = effect.get_reply();
+
+        let result = reply.try_take::<String>();
+        match result {
+            Err(ReplyError::WrongType {
+                expected, actual, ..
+            }) => {
+                assert!(expected.contains("String"));
+                // With pre-registered types, we should get "i32"
+                // Check for either the type name or unknown type message
                 assert!(
-                    panic_string.contains("reply filled twice"),
-                    "Panic message should mention 'reply filled twice', got: {panic_string}"
+                    actual.contains("i32") || actual.contains("unknown type"),
+                    "Expected 'i32' or 'unknown type' in actual: {actual}"
                 );
             }
+            _ => panic!("Expected WrongType error"),
         }
     }
 
     #[test]
-    fn test_one_shot_effect_get_reply_without_fill() {
-        // Test that get_reply panics when no reply has been filled
-        let effect = Effect::new(Test::GetValue);
+    #[cfg(feature = "std")]
+    fn test_register_type_custom() {
+        // Test registering a custom type
+        #[derive(Debug)]
+        struct CustomType {
+            value: i32,
+        }
 
-        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            let _ = effect.get_reply();
-        }));
+        impl CustomType {
+            fn value(&self) -> i32 {
+                self.value
+            }
+        }
+
+        // Register the custom type
+        register_type::<CustomType>();
+
+        // Create an effect with the custom type
+        let mut effect = Effect::new(Test::GetValue);
+        let custom = CustomType { value: 42 };
+        assert_eq!(custom.value(), 42); // Use the field
+        effect.fill_boxed(Box::new(custom));
+        let mut reply = effect.get_reply();
+
+        // Try to take with wrong type - should show our custom type name
+        let result = reply.try_take::<String>();
+        match result {
+            Err(ReplyError::WrongType {
+                expected, actual, ..
+            }) => {
+                assert!(expected.contains("String"));
+                assert!(actual.contains("CustomType"));
+            }
+            _ => panic!("Expected WrongType error"),
+        }
+    }
+
+    #[test]
+    fn test_reply_error_display() {
+        // Test Display implementation for ReplyError
+        let err1 = ReplyError::AlreadyTaken;
+        assert_eq!(
+            err1.to_string(),
+            "Reply::take called, but no value was supplied (possibly already taken)"
+        );
+
+        let err2 = ReplyError::WrongType {
+            expected: "String",
+            actual: "i32".to_string(),
+            op: None,
+        };
+        assert_eq!(
+            err2.to_string(),
+            "Reply::take type mismatch: expected `String`, but reply contains `i32`"
+        );
 
-        assert!(
-            result.is_err(),
-            "Expected panic when calling get_reply on unfilled effect"
+        let err3 = ReplyError::WrongType {
+            expected: "String",
+            actual: "i32".to_string(),
+            op: Some("Test(GetValue)".to_string()),
+        };
+        assert_eq!(
+            err3.to_string(),
+            "Reply::take type mismatch for `Test(GetValue)`: expected `String`, \
+             but the handler replied with `i32`"
         );
     }
 
     #[test]
-    fn test_one_shot_reply_take_wrong_type() {
-        // Test that Reply::take panics with wrong type (additional safety check)
+    fn test_reply_take_panics_with_good_message() {
+        // Test that take() method panics with proper error message
         let mut effect = Effect::new(Test::GetValue);
         effect.fill_boxed(Box::new(42i32));
-
         let reply = effect.get_reply();
 
-        // Try to take with wrong type should panic
         let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
             let _: String = reply.take();
         }));
 
-        assert!(
-            result.is_err(),
-            "Expected panic when taking reply with wrong type"
-        );
-
-        // Check that the panic message contains helpful type information
+        assert!(result.is_err());
         if let Err(panic_payload) = result {
             let panic_message = if let Some(panic_str) = panic_payload.downcast_ref::<&str>() {
                 panic_str.to_string()
@@ -2492,1258 +6841,1643 @@ mod tests {
                 "Unknown panic message".to_string()
             };
 
-            // Should mention both expected and actual types
-            assert!(
-                panic_message.contains("expected `alloc::string::String`")
-                    || panic_message.contains("expected 'alloc::string::String'")
-                    || panic_message.contains("expected `String`"),
-                "Panic message should mention expected type String, got: {panic_message}"
-            );
-            assert!(
-                panic_message.contains("but reply contains `i32`"),
-                "Panic message should mention actual type i32, got: {panic_message}"
-            );
+            assert!(panic_message.contains("type mismatch"));
+            assert!(panic_message.contains("expected"));
+            assert!(panic_message.contains("but reply contains"));
         }
     }
 
     #[test]
-    fn test_reply_take_type_mismatch_common_types() {
-        // Test error messages for various common type mismatches
-
-        // Test i32 -> String mismatch
-        let mut effect1 = Effect::new(Test::GetValue);
-        effect1.fill_boxed(Box::new(42i32));
-        let reply1 = effect1.get_reply();
-
-        let result1 = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            let _: String = reply1.take();
-        }));
-        assert!(result1.is_err());
-
-        // Test String -> i32 mismatch
-        let mut effect2 = Effect::new(Test::GetValue);
-        effect2.fill_boxed(Box::new("hello".to_string()));
-        let reply2 = effect2.get_reply();
-
-        let result2 = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            let _: i32 = reply2.take();
-        }));
-        assert!(result2.is_err());
-
-        // Test () -> bool mismatch
-        let mut effect3 = Effect::new(Test::GetValue);
-        effect3.fill_boxed(Box::new(()));
-        let reply3 = effect3.get_reply();
-
-        let result3 = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            let _: bool = reply3.take();
-        }));
-        assert!(result3.is_err());
-    }
+    fn test_unknown_type_fallback() {
+        // Test behavior with an unregistered type (should show TypeId)
+        struct UnregisteredType;
 
-    #[test]
-    fn test_reply_take_result_type_mismatch() {
-        // Test error messages for Result type mismatches (common in algae)
         let mut effect = Effect::new(Test::GetValue);
-        effect.fill_boxed(Box::new(Ok::<String, String>("success".to_string())));
-        let reply = effect.get_reply();
-
-        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            let _: Result<i32, String> = reply.take();
-        }));
-
-        assert!(
-            result.is_err(),
-            "Expected panic when taking Result with wrong type"
-        );
-
-        // Check that the panic message mentions Result types
-        if let Err(panic_payload) = result {
-            let panic_message = if let Some(panic_str) = panic_payload.downcast_ref::<&str>() {
-                panic_str.to_string()
-            } else if let Some(panic_string) = panic_payload.downcast_ref::<String>() {
-                panic_string.clone()
-            } else {
-                "Unknown panic message".to_string()
-            };
+        effect.fill_boxed(Box::new(UnregisteredType));
+        let mut reply = effect.get_reply();
 
-            // Should mention Result types
-            assert!(
-                panic_message.contains("Result") && panic_message.contains("String"),
-                "Panic message should mention Result types, got: {panic_message}"
-            );
+        let result = reply.try_take::<String>();
+        match result {
+            Err(ReplyError::WrongType {
+                expected, actual, ..
+            }) => {
+                assert!(expected.contains("String"));
+                // Should show unknown type with TypeId
+                assert!(actual.contains("unknown type"));
+                assert!(actual.contains("TypeId"));
+            }
+            _ => panic!("Expected WrongType error"),
         }
     }
 
     #[test]
-    fn test_one_shot_fill_and_get_success() {
-        // Test the happy path: fill once, get once - should work perfectly
+    fn test_reply_debug() {
+        // Test that Reply implements Debug
         let mut effect = Effect::new(Test::GetValue);
-
-        // Fill with a value
         effect.fill_boxed(Box::new(42i32));
-
-        // Get reply and extract value
         let reply = effect.get_reply();
-        let value: i32 = reply.take();
 
-        assert_eq!(value, 42);
+        let debug_str = format!("{reply:?}");
+        assert!(debug_str.contains("Reply"));
+        assert!(debug_str.contains("inner"));
+        assert!(debug_str.contains("Some"));
+        assert!(debug_str.contains("Stored"));
     }
 
-    // ============================================================================
-    // Partial Handler Tests
-    // ============================================================================
-
     #[test]
-    fn test_partial_handler_basic() {
-        // Test basic partial handler functionality
-        struct MathOnlyHandler;
-
-        impl PartialHandler<Op> for MathOnlyHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
-                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
-                    _ => None,
-                }
-            }
-        }
+    #[cfg(feature = "std")]
+    fn test_register_type_before_and_after() {
+        // This test demonstrates the "unknown-then-known" behavior of register_type.
+        // It shows that types are only recognized in error messages after they've been
+        // registered, even if the same type was used in a previous error.
 
-        #[effectful]
-        fn math_only() -> i32 {
-            let sum: i32 = perform!(Math::Add((5, 3)));
-            let product: i32 = perform!(Math::Multiply((sum, 2)));
-            product
+        // Define a custom type that isn't pre-registered
+        #[derive(Debug)]
+        struct UnregisteredCustomType {
+            data: String,
         }
 
-        let result = math_only().run_checked(MathOnlyHandler);
-        assert_eq!(result, Ok(16)); // (5 + 3) * 2 = 16
-    }
-
-    #[test]
-    fn test_partial_handler_decline() {
-        // Test that partial handlers correctly decline operations
-        struct MathOnlyHandler;
-
-        impl PartialHandler<Op> for MathOnlyHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(_) => Some(Box::new(42i32)), // Dummy response
-                    _ => None,
+        impl UnregisteredCustomType {
+            fn new(data: &str) -> Self {
+                Self {
+                    data: data.to_string(),
                 }
             }
-        }
 
-        #[effectful]
-        fn mixed_effects() -> i32 {
-            let _: () = perform!(Logger::Info("Starting".to_string())); // This should fail
-            perform!(Math::Add((1, 2)))
+            #[allow(dead_code)]
+            fn data(&self) -> &str {
+                &self.data
+            }
         }
 
-        let result = mixed_effects().run_checked(MathOnlyHandler);
-        assert!(result.is_err());
+        // PART 1: Before registration - type should show as "unknown"
+        let mut effect1 = Effect::new(Test::GetValue);
+        effect1.fill_boxed(Box::new(UnregisteredCustomType::new("test")));
+        let mut reply1 = effect1.get_reply();
 
-        if let Err(UnhandledOp(op)) = result {
-            match op {
-                Op::Logger(Logger::Info(_)) => (), // Expected
-                _ => panic!("Wrong unhandled operation"),
+        let result1 = reply1.try_take::<i32>();
+        match result1 {
+            Err(ReplyError::WrongType {
+                expected, actual, ..
+            }) => {
+                assert!(expected.contains("i32"));
+                // Before registration, should show as unknown type
+                assert!(
+                    actual.contains("unknown type"),
+                    "Before registration, type should be unknown. Got: {actual}"
+                );
+                assert!(
+                    actual.contains("TypeId"),
+                    "Unknown types should show TypeId. Got: {actual}"
+                );
             }
+            _ => panic!("Expected WrongType error"),
         }
-    }
 
-    #[test]
-    fn test_vec_handler() {
-        // Test VecHandler with multiple partial handlers
-        struct MathPartialHandler;
-        struct LoggerPartialHandler {
-            logs: Vec<String>,
-        }
+        // PART 2: Register the type
+        register_type::<UnregisteredCustomType>();
 
-        impl PartialHandler<Op> for MathPartialHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
-                    _ => None,
-                }
+        // PART 3: After registration - same type should now show its name
+        let mut effect2 = Effect::new(Test::GetValue);
+        effect2.fill_boxed(Box::new(UnregisteredCustomType::new("test2")));
+        let mut reply2 = effect2.get_reply();
+
+        let result2 = reply2.try_take::<String>();
+        match result2 {
+            Err(ReplyError::WrongType {
+                expected, actual, ..
+            }) => {
+                assert!(expected.contains("String"));
+                // After registration, should show the actual type name
+                assert!(
+                    actual.contains("UnregisteredCustomType"),
+                    "After registration, type name should be shown. Got: {actual}"
+                );
+                assert!(
+                    !actual.contains("unknown type"),
+                    "After registration, should not show 'unknown type'. Got: {actual}"
+                );
             }
+            _ => panic!("Expected WrongType error"),
         }
 
-        impl PartialHandler<Op> for LoggerPartialHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Logger(Logger::Info(msg)) => {
-                        self.logs.push(msg.clone());
-                        Some(Box::new(()))
-                    }
-                    _ => None,
-                }
+        // PART 4: Verify that registration is global and persistent
+        // Try the same type in another error to confirm it's still registered
+        let mut effect3 = Effect::new(Test::GetValue);
+        effect3.fill_boxed(Box::new(UnregisteredCustomType::new("test3")));
+        let mut reply3 = effect3.get_reply();
+
+        let result3 = reply3.try_take::<bool>();
+        match result3 {
+            Err(ReplyError::WrongType {
+                expected, actual, ..
+            }) => {
+                assert!(expected.contains("bool"));
+                // Should still show the registered name
+                assert!(
+                    actual.contains("UnregisteredCustomType"),
+                    "Type should remain registered. Got: {actual}"
+                );
             }
+            _ => panic!("Expected WrongType error"),
         }
+    }
+
+    #[test]
+    fn test_debug_reports_run_state() {
+        use algae::boxed::Drive;
+        use std::ops::CoroutineState;
 
         #[effectful]
-        fn combined_effects() -> i32 {
-            let _: () = perform!(Logger::Info("Computing sum".to_string()));
-            let sum: i32 = perform!(Math::Add((10, 20)));
-            let _: () = perform!(Logger::Info(format!("Result: {}", sum)));
-            sum
+        fn get_twice() -> i32 {
+            let a: i32 = perform!(Test::GetValue);
+            let b: i32 = perform!(Test::GetValue);
+            a + b
         }
 
-        let mut vec_handler = VecHandler::new();
-        vec_handler.push(MathPartialHandler);
-        vec_handler.push(LoggerPartialHandler { logs: Vec::new() });
+        let mut computation = get_twice();
+        assert_eq!(format!("{computation:?}"), "Effectful { state: unstarted }");
+
+        let CoroutineState::Yielded(eff) = computation.resume(None) else {
+            panic!("get_twice performs an operation first")
+        };
+        assert_eq!(
+            format!("{computation:?}"),
+            format!(
+                "Effectful {{ state: suspended, pending_at: {} }}",
+                eff.location()
+            )
+        );
+        assert!(eff.location().file().ends_with("lib.rs"));
 
-        let result = combined_effects().run_checked(vec_handler);
-        assert_eq!(result, Ok(30));
+        let _ = computation.resume(Some(Reply::from_boxed(Box::new(1i32))));
+        let _ = computation.resume(Some(Reply::from_boxed(Box::new(2i32))));
+        assert_eq!(format!("{computation:?}"), "Effectful { state: finished }");
     }
 
     #[test]
-    fn test_handle_all() {
-        // Test handle_all with array of handlers
-        struct AddHandler;
-        struct MultiplyHandler;
-        struct InfoHandler;
-
-        impl PartialHandler<Op> for AddHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
-                    _ => None,
-                }
-            }
-        }
-
-        impl PartialHandler<Op> for MultiplyHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
-                    _ => None,
-                }
-            }
-        }
+    fn test_debug_summarizes_handlers() {
+        struct Answer;
 
-        impl PartialHandler<Op> for InfoHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Logger(Logger::Info(_)) => Some(Box::new(())),
-                    _ => None,
-                }
+        impl PartialHandler<Op> for Answer {
+            fn maybe_handle(&mut self, _op: &Op) -> Option<Box<dyn Any + Send>> {
+                Some(Box::new(42i32))
             }
         }
+        impl_into_vec_handler!(Answer, Op);
 
         #[effectful]
-        fn multi_effect_computation() -> i32 {
-            let _: () = perform!(Logger::Info("Starting".to_string()));
-            let sum: i32 = perform!(Math::Add((7, 3)));
-            let result: i32 = perform!(Math::Multiply((sum, 4)));
-            let _: () = perform!(Logger::Info("Done".to_string()));
-            result
+        fn get() -> i32 {
+            perform!(Test::GetValue)
         }
 
-        // Test with manual VecHandler
-        let mut vec_handler = VecHandler::new();
-        vec_handler.push(AddHandler);
-        vec_handler.push(MultiplyHandler);
-        vec_handler.push(InfoHandler);
-
-        let result = multi_effect_computation().run_checked(vec_handler);
+        let single = format!("{:?}", get().handle(TestHandler::new(1)));
+        assert!(
+            single.contains("handler: \"algae::tests::TestHandler\""),
+            "{single}"
+        );
 
-        assert_eq!(result, Ok(40)); // (7 + 3) * 4 = 40
+        let chain = get().begin_chain().handle(Answer).handle_total(MathHandler);
+        assert_eq!(
+            format!("{chain:?}"),
+            "Handled { computation: Effectful { state: unstarted }, handler: VecHandler([\
+             \"algae::tests::test_debug_summarizes_handlers::Answer\", \
+             \"algae::HandlerWrapper<algae::tests::Op, algae::tests::MathHandler>\"]) }"
+        );
     }
 
     #[test]
-    fn test_handle_all_method() {
-        // Test the actual handle_all method
-        struct MathHandler;
-        struct LogHandler;
+    fn test_run_try_flattens_result_errors() {
+        struct Divider;
 
-        impl PartialHandler<Op> for MathHandler {
+        impl PartialHandler<Op> for Divider {
             fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
                 match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    Op::Math(Math::Divide((_, 0))) => {
+                        Some(Box::new(Err::<i32, String>("Division by zero".to_string())))
+                    }
+                    Op::Math(Math::Divide((a, b))) => Some(Box::new(Ok::<i32, String>(a / b))),
+                    Op::Math(Math::Add((_, -1))) => Some(Abort::boxed("negative operand")),
                     _ => None,
                 }
             }
         }
 
-        impl PartialHandler<Op> for LogHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Logger(Logger::Info(_)) => Some(Box::new(())),
-                    _ => None,
-                }
-            }
+        #[effectful]
+        fn divide(a: i32, b: i32) -> Result<i32, String> {
+            perform!(Math::Divide((a, b)))
         }
 
         #[effectful]
-        fn computation() -> i32 {
-            let _: () = perform!(Logger::Info("Computing".to_string()));
-            perform!(Math::Add((15, 25)))
+        fn add(a: i32, b: i32) -> Result<i32, String> {
+            let sum: i32 = perform!(Math::Add((a, b)));
+            Ok(sum)
         }
 
-        // Using handle_all with a vec
-        let handlers: Vec<Box<dyn PartialHandler<Op> + Send>> =
-            vec![Box::new(MathHandler), Box::new(LogHandler)];
+        assert_eq!(divide(10, 2).handle(Divider).run_try().unwrap(), 5);
+
+        let err = divide(1, 0).handle(Divider).run_try().unwrap_err();
+        assert!(matches!(&err, RunError::Failed(msg) if msg == "Division by zero"));
+        assert_eq!(err.to_string(), "Division by zero");
 
-        let result = computation().handle_all(handlers).run_checked();
+        let err = add(1, 2).handle(Divider).run_try().unwrap_err();
+        assert!(matches!(
+            err,
+            RunError::Unhandled(UnhandledOp {
+                op: Op::Math(Math::Add((1, 2))),
+                ..
+            })
+        ));
 
-        assert_eq!(result, Ok(40));
+        let err = add(3, -1).handle(Divider).run_try().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Effectful computation aborted: \"negative operand\""
+        );
     }
 
-    #[test]
-    fn test_handle_all_with_vec() {
-        // Test handle_all with Vec
-        struct StdoutHandler;
-        struct CalculatorHandler;
+    #[cfg(feature = "std")]
+    mod typed_handlers {
+        use crate as algae;
+        use algae::effects::log::{Log, LogCall, LogEvent};
+        use algae::prelude::*;
 
-        impl PartialHandler<Op> for StdoutHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::IO(IO::WriteString(s)) => {
-                        // In tests, just acknowledge without printing
-                        let _ = s;
-                        Some(Box::new(()))
+        effect! {
+            root TypedTestOp;
+            typed;
+            helpers;
+            use algae::effects::log::Log;
+            Console::Print (String) -> ();
+            Console::ReadLine -> String;
+            Math::Add ((i32, i32)) -> i32;
+            Math::Fail -> ();
+        }
+
+        struct Scripted;
+
+        impl TypedHandler<TypedTestOp> for Scripted {
+            fn handle(&mut self, call: TypedTestOpCall<'_>) -> Answer {
+                match call {
+                    TypedTestOpCall::Console(ConsoleCall::Print(_, reply)) => reply.send(()),
+                    TypedTestOpCall::Console(ConsoleCall::ReadLine(reply)) => {
+                        reply.send("Ada".to_string())
                     }
-                    _ => None,
+                    TypedTestOpCall::Math(MathCall::Add((a, b), reply)) => reply.send(a + b),
+                    TypedTestOpCall::Math(MathCall::Fail(reply)) => reply.abort("failed"),
+                    TypedTestOpCall::Log(LogCall::Event(_, reply)) => reply.send(()),
                 }
             }
         }
 
-        impl PartialHandler<Op> for CalculatorHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
-                    _ => None,
-                }
-            }
+        #[effectful(root = TypedTestOp)]
+        fn greet() -> String {
+            perform!(console::print("name?".into()));
+            let name = perform!(console::read_line());
+            let _: () = perform!(Log::Event(LogEvent::info("greeted")));
+            let len = perform!(math::add(name.len() as i32, 1));
+            format!("{name}:{len}")
         }
 
-        #[effectful]
-        fn program() -> i32 {
-            let _: () = perform!(IO::WriteString("2 + 3 =".to_string()));
-            perform!(Math::Add((2, 3)))
-        }
+        #[test]
+        fn test_typed_handlers_answer_with_declared_types() {
+            assert_eq!(greet().handle(Typed(Scripted)).run_or_panic(), "Ada:4");
 
-        let mut vec_handler = VecHandler::new();
-        vec_handler.push(StdoutHandler);
-        vec_handler.push(CalculatorHandler);
+            let err = math::fail()
+                .perform()
+                .try_run_with(Typed(Scripted))
+                .unwrap_err();
+            assert!(matches!(err, EffectError::Aborted(_)));
+        }
+    }
 
-        let result = program().run_checked(vec_handler);
+    mod local_handlers {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::any::Any;
 
-        assert_eq!(result, Ok(5));
-    }
+        effect! {
+            root LocalOp;
+            Fail::Raise (String) -> ();
+            Counter::Next -> u32;
+            Log::Line (String) -> ();
+        }
 
-    #[test]
-    fn test_handler_chain() {
-        // Test chaining handlers with .handle()
-        struct FirstHandler;
-        struct SecondHandler;
-        struct ThirdHandler;
+        /// Aborts with the raised message.
+        struct Raise;
 
-        impl PartialHandler<Op> for FirstHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        impl PartialHandler<LocalOp> for Raise {
+            fn maybe_handle(&mut self, op: &LocalOp) -> Option<Box<dyn Any + Send>> {
                 match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+                    LocalOp::Fail(Fail::Raise(msg)) => Some(Abort::boxed(msg.clone())),
                     _ => None,
                 }
             }
         }
 
-        impl PartialHandler<Op> for SecondHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Logger(Logger::Info(_)) => Some(Box::new(())),
-                    _ => None,
-                }
+        /// Counts `Counter::Next` from its start value.
+        struct Counting(u32);
+
+        impl PartialHandler<LocalOp> for Counting {
+            fn maybe_handle(&mut self, op: &LocalOp) -> Option<Box<dyn Any + Send>> {
+                let LocalOp::Counter(Counter::Next) = op else {
+                    return None;
+                };
+                self.0 += 1;
+                Some(Box::new(self.0 - 1))
             }
         }
 
-        impl PartialHandler<Op> for ThirdHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
-                    _ => None,
-                }
+        /// The outermost handlers: counts from 100, ignores log lines and
+        /// fails the run on `Fail::Raise`.
+        fn outer() -> VecHandler<LocalOp> {
+            let mut handlers = VecHandler::new();
+            handlers.push(Counting(100));
+            handlers.push(IgnoreLogs);
+            handlers.push(Raise);
+            handlers
+        }
+
+        struct IgnoreLogs;
+
+        impl PartialHandler<LocalOp> for IgnoreLogs {
+            fn maybe_handle(&mut self, op: &LocalOp) -> Option<Box<dyn Any + Send>> {
+                matches!(op, LocalOp::Log(_)).then(|| Box::new(()) as Box<dyn Any + Send>)
             }
         }
 
-        #[effectful]
-        fn chained_computation() -> i32 {
-            let _: () = perform!(Logger::Info("Start".to_string()));
-            let x: i32 = perform!(Math::Add((5, 5)));
-            let y: i32 = perform!(Math::Multiply((x, 3)));
-            let _: () = perform!(Logger::Info("End".to_string()));
-            y
+        #[effectful(root = LocalOp)]
+        fn checked_sum(values: Vec<u32>) -> Result<u32, String> {
+            let sum = with_handler!(Raise, {
+                let mut sum = 0;
+                for value in values {
+                    if value == 0 {
+                        let _: () = perform!(Fail::Raise("zero".into()));
+                    }
+                    let _: () = perform!(Log::Line(format!("adding {value}")));
+                    sum += value;
+                }
+                sum
+            });
+            sum.map_err(|abort| abort.downcast_ref::<String>().unwrap().clone())
         }
 
-        let mut vec_handler = VecHandler::new();
-        vec_handler.push(FirstHandler);
-        vec_handler.push(SecondHandler);
-        vec_handler.push(ThirdHandler);
+        #[effectful(root = LocalOp)]
+        fn numbers() -> (u32, Vec<u32>, u32) {
+            let before: u32 = perform!(Counter::Next);
+            let inside = with_handler!(Counting(0), {
+                let a: u32 = perform!(Counter::Next);
+                let b: u32 = perform!(Counter::Next);
+                let _: () = perform!(Log::Line("inside".into()));
+                vec![a, b]
+            });
+            let after: u32 = perform!(Counter::Next);
+            (before, inside.unwrap(), after)
+        }
+
+        #[test]
+        fn test_local_handler_aborts_end_only_the_block() {
+            assert_eq!(
+                checked_sum(vec![1, 2]).try_run_with(outer()).unwrap(),
+                Ok(3)
+            );
+            let result = checked_sum(vec![1, 0, 2]).try_run_with(outer()).unwrap();
+            assert_eq!(result, Err("zero".to_string()));
+        }
 
-        let result = chained_computation().run_checked(vec_handler);
+        #[test]
+        fn test_unhandled_operations_flow_to_outer_handlers() {
+            let result = numbers().try_run_with(outer()).unwrap();
+            assert_eq!(result, (100, vec![0, 1], 101));
+        }
 
-        assert_eq!(result, Ok(30)); // (5 + 5) * 3 = 30
+        #[test]
+        fn test_with_handler_method_nests_inside_out() {
+            let result = numbers().with_handler(Counting(10)).try_run_with(outer());
+            assert_eq!(result.unwrap().unwrap(), (10, vec![0, 1], 11));
+        }
     }
 
-    #[test]
-    fn test_unhandled_op_error() {
-        // Test that UnhandledOp contains the correct operation
-        struct EmptyHandler;
+    mod enum_derives {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::collections::HashSet;
 
-        impl PartialHandler<Op> for EmptyHandler {
-            fn maybe_handle(&mut self, _op: &Op) -> Option<Box<dyn Any + Send>> {
-                None // Decline everything
-            }
+        effect! {
+            #![derive(Clone, Eq, Hash)]
+            #![allow(dead_code)]
+            root DerivedOp;
+            #[derive(PartialOrd, Ord)]
+            Level::Low -> ();
+            Level::High -> ();
+            Console::Print (String) -> ();
         }
 
-        #[effectful]
-        fn failing_computation() -> i32 {
-            perform!(Math::Add((1, 2)))
+        #[test]
+        fn test_block_derives_reach_families_and_root() {
+            let ops: HashSet<DerivedOp> = [
+                DerivedOp::console_print("a"),
+                DerivedOp::console_print("a"),
+                DerivedOp::level_low(),
+            ]
+            .into_iter()
+            .collect();
+            assert_eq!(ops.len(), 2);
+            assert!(ops.contains(&DerivedOp::Level(Level::Low)));
+            let _: HashSet<Console> = HashSet::from([Console::Print("b".into())]);
         }
 
-        let result = failing_computation().run_checked(EmptyHandler);
-        assert!(result.is_err());
-
-        if let Err(UnhandledOp(op)) = result {
-            match op {
-                Op::Math(Math::Add((1, 2))) => (), // Expected
-                _ => panic!("Wrong unhandled operation"),
-            }
+        #[test]
+        fn test_line_derives_reach_only_their_family() {
+            assert!(Level::Low < Level::High);
+            assert_eq!([Level::High, Level::Low].iter().max(), Some(&Level::High));
         }
     }
 
-    #[test]
-    fn test_total_handler_as_partial() {
-        // Test that total handlers work with run_checked_with
-        struct TotalMathHandler;
+    mod generic_families {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::{any::Any, collections::HashMap, hash::Hash};
 
-        impl Handler<Op> for TotalMathHandler {
-            fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        effect! {
+            root CacheOp;
+            typed;
+            helpers;
+            Cache<K, V>::Get (K) -> Option<V>;
+            Cache<K, V>::Put ((K, V)) -> ();
+            Stats<K>::Hits (K) -> u32;
+            Console::Print (String) -> ();
+        }
+
+        /// A cache for any key and value types; declines `Console`.
+        struct Memory<K, V>(HashMap<K, V>);
+
+        impl<K, V> PartialHandler<CacheOp<K, V>> for Memory<K, V>
+        where
+            K: Eq + Hash + Clone,
+            V: Clone + Send + 'static,
+        {
+            fn maybe_handle(&mut self, op: &CacheOp<K, V>) -> Option<Box<dyn Any + Send>> {
                 match op {
-                    Op::Math(Math::Add((a, b))) => Box::new(a + b),
-                    Op::Math(Math::Multiply((a, b))) => Box::new(a * b),
-                    _ => panic!("TotalMathHandler cannot handle {op:?}"),
+                    CacheOp::Cache(Cache::Get(key)) => Some(Box::new(self.0.get(key).cloned())),
+                    CacheOp::Cache(Cache::Put((key, value))) => {
+                        self.0.insert(key.clone(), value.clone());
+                        Some(Box::new(()))
+                    }
+                    CacheOp::Stats(Stats::Hits(_)) => Some(Box::new(0u32)),
+                    CacheOp::Console(_) => None,
                 }
             }
         }
 
-        #[effectful]
-        fn math_computation() -> i32 {
-            let x: i32 = perform!(Math::Add((4, 6)));
-            perform!(Math::Multiply((x, 2)))
+        struct Lengths;
+
+        impl TypedHandler<CacheOp<String, usize>> for Lengths {
+            fn handle(&mut self, call: CacheOpCall<'_, String, usize>) -> Answer {
+                match call {
+                    CacheOpCall::Cache(CacheCall::Get(key, reply)) => reply.send(Some(key.len())),
+                    CacheOpCall::Cache(CacheCall::Put(_, reply)) => reply.send(()),
+                    CacheOpCall::Stats(StatsCall::Hits(key, reply)) => reply.send(key.len() as u32),
+                    CacheOpCall::Console(ConsoleCall::Print(_, reply)) => reply.send(()),
+                }
+            }
         }
 
-        // Total handler used with run_checked_with
-        let result = math_computation().run_checked_with(TotalMathHandler);
-        assert_eq!(result, Ok(20)); // (4 + 6) * 2 = 20
-    }
+        #[effectful(root = CacheOp<K, V>)]
+        fn remember<K, V>(key: K, value: V) -> Option<V>
+        where
+            K: Clone + Send + 'static,
+            V: Send + 'static,
+        {
+            perform!(cache::put(key.clone(), value));
+            perform!(cache::get(key))
+        }
 
-    #[test]
-    fn test_mixed_handler_types() {
-        // Test mixing different partial handlers
-        struct PartialMathHandler;
-        struct PartialLogHandler;
+        #[effectful(root = CacheOp<String, usize>)]
+        fn lookup(key: &'static str) -> (Option<usize>, u32) {
+            let len = perform!(cache::get(key.to_string()));
+            let hits = perform!(stats::hits(key.to_string()));
+            (len, hits)
+        }
 
-        impl PartialHandler<Op> for PartialMathHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
-                    _ => None,
-                }
+        #[test]
+        fn test_generic_families_instantiate_per_use() {
+            let value = remember("a", 7u8).try_run_with(Memory(HashMap::new()));
+            assert_eq!(value.unwrap(), Some(7));
+            let value = remember(1u64, "one".to_string()).try_run_with(Memory(HashMap::new()));
+            assert_eq!(value.unwrap().as_deref(), Some("one"));
+
+            assert_eq!(
+                lookup("abc").handle(Typed(Lengths)).run_or_panic(),
+                (Some(3), 3)
+            );
+        }
+
+        mod words {
+            use super::Cache;
+            use crate as algae;
+            use algae::prelude::*;
+
+            effect! {
+                root WordOp;
+                use Cache<String, usize>;
+            }
+
+            #[test]
+            fn test_use_lines_embed_generic_family_instances() {
+                let op = WordOp::from(Cache::Get("word".to_string()));
+                assert_eq!(op.qualified_name(), "Cache.Get");
+                assert_eq!(Cache::try_from(op).unwrap(), Cache::Get("word".into()));
             }
         }
 
-        impl PartialHandler<Op> for PartialLogHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        #[test]
+        fn test_generic_families_get_the_usual_impls() {
+            let op = CacheOp::<&str, i32>::cache_put("k", 1);
+            assert_eq!(op, CacheOp::Cache(Cache::Put(("k", 1))));
+            assert_eq!(op.qualified_name(), "Cache.Put");
+            assert_eq!(Cache::try_from(op).unwrap(), Cache::Put(("k", 1)));
+
+            let op = CacheOp::<&str, i32>::console_print("hi");
+            assert!(<&Cache<&str, i32>>::try_from(&op).is_err());
+            assert_eq!(op.family(), "Console");
+        }
+    }
+
+    mod typed_helpers {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::any::Any;
+
+        effect! {
+            root HelperOp;
+            helpers;
+            Console::Print (String) -> ();
+            Console::ReadLine -> String;
+            Math::Add ((i32, i32)) -> i32;
+        }
+
+        struct Scripted(Vec<String>);
+
+        impl Handler<HelperOp> for Scripted {
+            fn handle(&mut self, op: &HelperOp) -> Box<dyn Any + Send> {
                 match op {
-                    Op::Logger(Logger::Info(_)) => Some(Box::new(())),
-                    _ => None,
+                    HelperOp::Console(Console::Print(msg)) => {
+                        self.0.push(msg.clone());
+                        Box::new(())
+                    }
+                    HelperOp::Console(Console::ReadLine) => Box::new("Ada".to_string()),
+                    HelperOp::Math(Math::Add((a, b))) => Box::new(a + b),
                 }
             }
         }
 
-        #[effectful]
-        fn mixed_computation() -> i32 {
-            let _: () = perform!(Logger::Info("Starting".to_string()));
-            perform!(Math::Add((8, 12)))
+        #[effectful(root = HelperOp)]
+        fn greet() -> String {
+            perform!(console::print("name?".into()));
+            let name = perform!(console::read_line());
+            let len = perform!(math::add(name.len() as i32, 1));
+            format!("{name}:{len}")
+        }
+
+        #[test]
+        fn test_helpers_build_root_ops() {
+            let add = math::add(2, 3);
+            assert_eq!(add.op(), &HelperOp::Math(Math::Add((2, 3))));
+            assert_eq!(
+                HelperOp::from(console::read_line()),
+                HelperOp::Console(Console::ReadLine)
+            );
+        }
+
+        #[test]
+        fn test_perform_uses_declared_reply_types() {
+            assert_eq!(greet().handle(Scripted(Vec::new())).run_or_panic(), "Ada:4");
         }
 
-        let mut vec_handler = VecHandler::new();
-        vec_handler.push(PartialMathHandler);
-        vec_handler.push(PartialLogHandler);
-
-        let result = mixed_computation().run_checked(vec_handler);
+        #[test]
+        fn test_performs_one_as_computation() {
+            let sum = math::add(20, 22)
+                .perform()
+                .handle(Scripted(Vec::new()))
+                .run_or_panic();
+            assert_eq!(sum, 42);
+        }
 
-        assert_eq!(result, Ok(20));
+        #[test]
+        fn test_constructors_build_root_ops() {
+            assert_eq!(
+                HelperOp::console_print("hi"),
+                HelperOp::Console(Console::Print("hi".to_string()))
+            );
+            assert_eq!(
+                HelperOp::console_read_line(),
+                HelperOp::Console(Console::ReadLine)
+            );
+            assert_eq!(HelperOp::math_add(2, 3), HelperOp::Math(Math::Add((2, 3))));
+        }
     }
 
-    #[test]
-    fn test_handler_ordering() {
-        // Test that handlers are tried in order
-        struct FirstCatchAllHandler;
-        struct SecondHandler;
+    mod batches {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::{
+            any::Any,
+            sync::{Arc, Mutex},
+        };
 
-        impl PartialHandler<Op> for FirstCatchAllHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Add((_, _))) => Some(Box::new(100i32)), // Always return 100
-                    _ => None,
-                }
-            }
+        effect! {
+            root BatchOp;
+            Db::Lookup (u64) -> String;
+            Log::Line (String) -> ();
+            Cache::Get (u64) -> String;
         }
 
-        impl PartialHandler<Op> for SecondHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)), // Correct addition
-                    _ => None,
-                }
-            }
+        /// Looks `id` up in the cache and in the database at once.
+        #[effectful(root = BatchOp)]
+        fn cached_and_fresh(id: u64) -> Vec<String> {
+            perform_all!([BatchOp::from(Cache::Get(id)), BatchOp::from(Db::Lookup(id))])
         }
 
-        #[effectful]
-        fn order_test() -> i32 {
-            perform!(Math::Add((5, 7)))
+        #[effectful(root = BatchOp)]
+        fn names(ids: Vec<u64>) -> Vec<String> {
+            let _: () = perform!(Log::Line(format!("{} ids", ids.len())));
+            perform_all!(ids.into_iter().map(Db::Lookup))
         }
 
-        // First handler should catch it
-        let mut vec_handler1 = VecHandler::new();
-        vec_handler1.push(FirstCatchAllHandler);
-        vec_handler1.push(SecondHandler);
+        /// Answers lookups in batches, recording the size of each one.
+        #[derive(Clone, Default)]
+        struct Database {
+            round_trips: Arc<Mutex<Vec<usize>>>,
+        }
 
-        let result1 = order_test().run_checked(vec_handler1);
-        assert_eq!(result1, Ok(100));
+        impl PartialHandler<BatchOp> for Database {
+            fn maybe_handle(&mut self, op: &BatchOp) -> Option<Box<dyn Any + Send>> {
+                self.maybe_handle_batch(std::slice::from_ref(op))
+                    .map(|mut replies| replies.remove(0).into_boxed())
+            }
 
-        // Reverse order - second handler (now first) should give correct result
-        let mut vec_handler2 = VecHandler::new();
-        vec_handler2.push(SecondHandler);
-        vec_handler2.push(FirstCatchAllHandler);
+            fn maybe_handle_batch(&mut self, ops: &[BatchOp]) -> Option<Vec<Reply>> {
+                let mut replies = Vec::new();
+                for op in ops {
+                    let BatchOp::Db(Db::Lookup(id)) = op else {
+                        return None;
+                    };
+                    replies.push(Reply::new(format!("user-{id}")));
+                }
+                self.round_trips.lock().unwrap().push(ops.len());
+                Some(replies)
+            }
+        }
+        impl_into_vec_handler!(Database, BatchOp);
 
-        let result2 = order_test().run_checked(vec_handler2);
-        assert_eq!(result2, Ok(12));
-    }
+        struct Logs;
 
-    #[test]
-    fn test_stateful_partial_handler() {
-        // Test that partial handlers can maintain state
-        struct CountingHandler {
-            count: usize,
+        impl PartialHandler<BatchOp> for Logs {
+            fn maybe_handle(&mut self, op: &BatchOp) -> Option<Box<dyn Any + Send>> {
+                matches!(op, BatchOp::Log(_)).then(|| Box::new(()) as Box<dyn Any + Send>)
+            }
         }
+        impl_into_vec_handler!(Logs, BatchOp);
 
-        impl PartialHandler<Op> for CountingHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        /// Answers cache lookups, one at a time.
+        struct Caches;
+
+        impl PartialHandler<BatchOp> for Caches {
+            fn maybe_handle(&mut self, op: &BatchOp) -> Option<Box<dyn Any + Send>> {
                 match op {
-                    Op::Logger(Logger::Info(_)) => {
-                        self.count += 1;
-                        Some(Box::new(()))
-                    }
-                    Op::Logger(Logger::GetLogCount) => Some(Box::new(self.count)),
+                    BatchOp::Cache(Cache::Get(id)) => Some(Box::new(format!("cached-{id}"))),
                     _ => None,
                 }
             }
         }
+        impl_into_vec_handler!(Caches, BatchOp);
 
-        #[effectful]
-        fn counting_program() -> usize {
-            let _: () = perform!(Logger::Info("First".to_string()));
-            let _: () = perform!(Logger::Info("Second".to_string()));
-            let _: () = perform!(Logger::Info("Third".to_string()));
-            perform!(Logger::GetLogCount)
-        }
-
-        let handler = CountingHandler { count: 0 };
-        let result = counting_program().run_checked(handler);
-        assert_eq!(result, Ok(3));
-    }
-
-    #[test]
-    fn test_result_error_propagation() {
-        // Test that errors in effectful computations propagate correctly
-        struct DivisionHandler;
+        /// Answers every operation one at a time.
+        struct OneByOne;
 
-        impl PartialHandler<Op> for DivisionHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        impl Handler<BatchOp> for OneByOne {
+            fn handle(&mut self, op: &BatchOp) -> Box<dyn Any + Send> {
                 match op {
-                    Op::Math(Math::Divide((a, b))) => {
-                        if *b == 0 {
-                            Some(Box::new(Err::<i32, String>("Division by zero".to_string())))
-                        } else {
-                            Some(Box::new(Ok::<i32, String>(a / b)))
-                        }
-                    }
-                    _ => None,
+                    BatchOp::Db(Db::Lookup(id)) => Box::new(format!("user-{id}")),
+                    BatchOp::Log(_) => Box::new(()),
+                    BatchOp::Cache(Cache::Get(id)) => Box::new(format!("cached-{id}")),
                 }
             }
         }
 
-        #[effectful]
-        fn safe_division() -> Result<i32, String> {
-            let result: Result<i32, String> = perform!(Math::Divide((10, 0)));
-            result
+        #[test]
+        fn test_handler_chains_offer_batches_whole() {
+            let db = Database::default();
+            let result = names(vec![3, 1, 2])
+                .begin_chain()
+                .handle(Logs)
+                .handle(db.clone())
+                .run_or_panic();
+            assert_eq!(result, ["user-3", "user-1", "user-2"]);
+            assert_eq!(*db.round_trips.lock().unwrap(), [3]);
         }
 
-        let handler = DivisionHandler;
-        let result = safe_division().run_checked(handler);
-        assert!(result.is_ok()); // run_checked succeeded
-        assert!(result.unwrap().is_err()); // but the division failed
-    }
+        #[test]
+        fn test_closures_answer_batches_one_operation_at_a_time() {
+            let mut db = Database::default();
+            let result = names(vec![3, 1, 2])
+                .drive(|op| db.maybe_reply(op).or_else(|| Logs.maybe_reply(op)));
+            assert_eq!(result.unwrap(), ["user-3", "user-1", "user-2"]);
+            assert_eq!(*db.round_trips.lock().unwrap(), [1, 1, 1]);
+        }
 
-    #[test]
-    fn test_empty_vec_handler() {
-        // Test that empty VecHandler declines everything
-        #[effectful]
-        fn simple_effect() -> i32 {
-            perform!(Math::Add((1, 1)))
+        #[test]
+        fn test_default_batches_answer_one_operation_at_a_time() {
+            assert_eq!(
+                names(vec![4, 5]).handle(OneByOne).run_or_panic(),
+                ["user-4", "user-5"]
+            );
         }
 
-        let empty_handler = VecHandler::<Op>::new();
-        let result = simple_effect().run_checked(empty_handler);
-        assert!(result.is_err());
-    }
+        #[test]
+        fn test_empty_batches_are_not_performed() {
+            let result = names(Vec::new()).try_run_with(Logs);
+            assert_eq!(result.unwrap(), Vec::<String>::new());
+        }
 
-    #[test]
-    fn test_handle_all_empty() {
-        // Test handle_all with empty iterator
-        #[effectful]
-        fn simple_effect() -> i32 {
-            perform!(Math::Add((2, 2)))
+        #[test]
+        fn test_local_handlers_forward_declined_batches() {
+            let result = names(vec![9])
+                .with_handler(Logs)
+                .try_run_with(Database::default());
+            assert_eq!(result.unwrap().unwrap(), ["user-9"]);
         }
 
-        let empty_handler = VecHandler::<Op>::new();
-        let result = simple_effect().run_checked(empty_handler);
-        assert!(result.is_err());
-    }
+        #[test]
+        fn test_declined_batches_report_their_first_operation() {
+            let Err(EffectError::Unhandled(UnhandledOp { op, .. })) =
+                names(vec![6, 7]).try_run_with(Logs)
+            else {
+                panic!("the batch should be unhandled");
+            };
+            assert!(matches!(op, BatchOp::Db(Db::Lookup(6))));
+        }
 
-    #[test]
-    fn test_variable_handler_chain() {
-        // Test chaining multiple handlers together
-        struct ConsoleHandler;
-        impl PartialHandler<Op> for ConsoleHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::IO(IO::WriteString(s)) => {
-                        println!("Console: {s}");
-                        Some(Box::new(()))
+        #[test]
+        fn test_aborts_in_a_batch_stop_the_run() {
+            struct Offline;
+
+            impl PartialHandler<BatchOp> for Offline {
+                fn maybe_handle(&mut self, op: &BatchOp) -> Option<Box<dyn Any + Send>> {
+                    match op {
+                        BatchOp::Db(Db::Lookup(2)) => Some(Abort::boxed("offline")),
+                        BatchOp::Db(Db::Lookup(id)) => Some(Box::new(format!("user-{id}"))),
+                        BatchOp::Log(_) => Some(Box::new(())),
+                        BatchOp::Cache(_) => None,
                     }
-                    _ => None,
                 }
             }
+
+            let result = names(vec![1, 2, 3]).try_run_with(Offline);
+            assert!(matches!(result, Err(EffectError::Aborted(_))));
         }
 
-        struct FileHandler;
-        impl PartialHandler<Op> for FileHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::IO(IO::ReadString) => Some(Box::new("file content".to_string())),
-                    _ => None,
-                }
-            }
+        #[test]
+        fn test_batches_are_split_between_the_handlers_of_a_chain() {
+            let result = cached_and_fresh(7)
+                .begin_chain()
+                .handle(Caches)
+                .handle(Database::default())
+                .run();
+            assert_eq!(result.unwrap(), ["cached-7", "user-7"]);
         }
 
-        struct LogHandler {
-            count: i32,
+        #[test]
+        fn test_combined_handlers_split_batches() {
+            let result = cached_and_fresh(7).try_run_with(Caches.or_else(Database::default()));
+            assert_eq!(result.unwrap(), ["cached-7", "user-7"]);
         }
-        impl PartialHandler<Op> for LogHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Logger(Logger::Info(msg)) => {
-                        self.count += 1;
-                        println!("[INFO] {msg}");
-                        Some(Box::new(()))
-                    }
-                    Op::Logger(Logger::GetLogCount) => Some(Box::new(self.count as usize)),
-                    _ => None,
-                }
-            }
+
+        #[test]
+        fn test_local_handlers_forward_the_rest_of_a_batch() {
+            let result = cached_and_fresh(8)
+                .with_handler(Caches)
+                .try_run_with(Database::default());
+            assert_eq!(result.unwrap().unwrap(), ["cached-8", "user-8"]);
         }
 
-        #[effectful]
-        fn three_handler_computation() -> String {
-            let _: () = perform!(Logger::Info("Starting computation".to_string()));
-            let _: () = perform!(IO::WriteString("Reading file...".to_string()));
-            let content: String = perform!(IO::ReadString);
-            let _: () = perform!(Logger::Info(format!("Read: {content}")));
-            let count: usize = perform!(Logger::GetLogCount);
-            format!("Completed with {count} log entries")
+        #[test]
+        fn test_batches_answered_in_part_report_the_rest() {
+            let Err(EffectError::Unhandled(UnhandledOp { op, declined, .. })) =
+                cached_and_fresh(5).begin_chain().handle(Caches).run()
+            else {
+                panic!("the lookup should be unhandled");
+            };
+            assert!(matches!(op, BatchOp::Db(Db::Lookup(5))));
+            assert_eq!(declined.len(), 1);
         }
 
-        // Chain three handlers together using handle_all
-        let result = three_handler_computation()
-            .handle_all(vec![
-                Box::new(ConsoleHandler) as Box<dyn PartialHandler<Op> + Send>,
-                Box::new(FileHandler),
-                Box::new(LogHandler { count: 0 }),
-            ])
-            .run_checked();
+        #[test]
+        fn test_batch_replies_of_the_wrong_type_name_their_operation() {
+            struct Stale;
+
+            impl PartialHandler<BatchOp> for Stale {
+                fn maybe_handle(&mut self, op: &BatchOp) -> Option<Box<dyn Any + Send>> {
+                    match op {
+                        BatchOp::Cache(_) => Some(Box::new(0u64)),
+                        BatchOp::Db(Db::Lookup(id)) => Some(Box::new(format!("user-{id}"))),
+                        BatchOp::Log(_) => Some(Box::new(())),
+                    }
+                }
+            }
 
-        assert_eq!(result.unwrap(), "Completed with 2 log entries");
+            let Err(EffectError::ReplyMismatch(ReplyError::WrongType {
+                expected,
+                actual,
+                op: Some(op),
+            })) = cached_and_fresh(3).try_run_with(Stale)
+            else {
+                panic!("the cache reply should be a mismatch");
+            };
+            assert_eq!(expected, "alloc::string::String");
+            assert_eq!(actual, "u64");
+            assert!(op.contains("Get"), "{op}");
+        }
     }
 
-    #[test]
-    fn test_handler_chain_with_total_handlers() {
-        // Test mixing Handler and PartialHandler types
-        #[effectful]
-        fn mixed_computation() -> i32 {
-            let a: i32 = perform!(Test::GetValue);
-            let b: i32 = perform!(Math::Add((a, 10)));
-            let _: () = perform!(Test::SetValue(b));
-            b
+    mod pulls {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::{any::Any, collections::VecDeque};
+
+        effect! {
+            root PullOp;
+            Queue::Next -> Option<(u32, String)>;
+            Queue::Ack (u32) -> ();
         }
 
-        // Create a combined handler that handles both Test and Math operations
-        struct CombinedHandler {
-            test_handler: TestHandler,
+        /// Hands out queued jobs and records which were acknowledged.
+        struct Jobs {
+            queued: VecDeque<(u32, String)>,
+            pulls: usize,
+            acked: Vec<u32>,
         }
 
-        impl CombinedHandler {
-            fn new(initial: i32) -> Self {
+        impl Jobs {
+            fn new(names: &[&str]) -> Self {
                 Self {
-                    test_handler: TestHandler::new(initial),
+                    queued: (0..)
+                        .zip(names.iter().map(|name| name.to_string()))
+                        .collect(),
+                    pulls: 0,
+                    acked: Vec::new(),
                 }
             }
         }
 
-        impl Handler<Op> for CombinedHandler {
-            fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        impl Handler<PullOp> for Jobs {
+            fn handle(&mut self, op: &PullOp) -> Box<dyn Any + Send> {
                 match op {
-                    Op::Test(_) => self.test_handler.handle(op),
-                    Op::Math(Math::Add((a, b))) => Box::new(a + b),
-                    _ => panic!("CombinedHandler cannot handle this operation: {op:?}"),
+                    PullOp::Queue(Queue::Next) => {
+                        self.pulls += 1;
+                        Box::new(self.queued.pop_front())
+                    }
+                    PullOp::Queue(Queue::Ack(id)) => {
+                        // The job was pulled, and nothing else, before its ack
+                        assert_eq!(self.pulls, self.acked.len() + 1);
+                        self.acked.push(*id);
+                        Box::new(())
+                    }
                 }
             }
         }
 
-        // Use the combined handler directly
-        let result = mixed_computation().run_with(CombinedHandler::new(5));
+        #[effectful(root = PullOp)]
+        fn work(stop_at: &'static str) -> Vec<String> {
+            let mut done = Vec::new();
+            for_each_perform!('jobs: (id, name) in Queue::Next => {
+                for word in name.split('-') {
+                    if word == stop_at {
+                        break 'jobs;
+                    }
+                }
+                let _: () = perform!(Queue::Ack(id));
+                done.push(name);
+            });
+            done
+        }
 
-        assert_eq!(result, 15);
+        #[test]
+        fn test_loops_pull_until_none() {
+            let (done, jobs) = work("never").run_with_handler(Jobs::new(&["a", "b-c", "d"]));
+            assert_eq!(done.unwrap(), ["a", "b-c", "d"]);
+            assert_eq!(jobs.acked, [0, 1, 2]);
+            assert_eq!(jobs.pulls, 4);
+        }
 
-        // Also test the checked version
-        let result2 = mixed_computation().run_checked_with(CombinedHandler::new(5));
+        #[test]
+        fn test_labelled_breaks_stop_pulling() {
+            let (done, jobs) = work("c").run_with_handler(Jobs::new(&["a", "b-c", "d"]));
+            assert_eq!(done.unwrap(), ["a"]);
+            assert_eq!(jobs.pulls, 2);
+            assert_eq!(jobs.queued.len(), 1);
+        }
+
+        #[effectful(root = PullOp, yields = String)]
+        fn names() {
+            for_each_perform!((id, name) in Queue::Next => {
+                emit!(name);
+                let _: () = perform!(Queue::Ack(id));
+            });
+        }
 
-        assert_eq!(result2.unwrap(), 15);
+        #[test]
+        fn test_streams_pull_as_items_are_taken() {
+            let mut stream = names().handle(Jobs::new(&["a", "b", "c"]));
+            let first: Vec<String> = stream.by_ref().take(2).collect();
+            assert_eq!(first, ["a", "b"]);
+            assert_eq!(stream.into_handler().pulls, 2);
+        }
     }
 
-    #[test]
-    fn test_unhandled_op_error_in_chain() {
-        // Test that unhandled operations properly return errors
-        #[effectful]
-        fn unhandled_computation() -> i32 {
-            let _: () = perform!(Logger::Info("This will work".to_string()));
-            let x: i32 = perform!(Math::Add((5, 5))); // This won't be handled
-            x
+    mod methods {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::any::Any;
+
+        effect! {
+            root MethodOp;
+            Counter::Next -> u32;
+            Log::Line (String) -> ();
         }
 
-        struct OnlyLoggerHandler;
-        impl PartialHandler<Op> for OnlyLoggerHandler {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        /// Counts up from its start value and accepts every log line.
+        struct Env(u32);
+
+        impl Handler<MethodOp> for Env {
+            fn handle(&mut self, op: &MethodOp) -> Box<dyn Any + Send> {
                 match op {
-                    Op::Logger(Logger::Info(msg)) => {
-                        println!("[LOG] {msg}");
-                        Some(Box::new(()))
+                    MethodOp::Counter(Counter::Next) => {
+                        self.0 += 1;
+                        Box::new(self.0)
                     }
-                    _ => None,
+                    MethodOp::Log(_) => Box::new(()),
+                }
+            }
+        }
+
+        #[derive(Clone)]
+        struct Greeter {
+            greeting: String,
+            greeted: u32,
+        }
+
+        impl Greeter {
+            #[effectful(root = MethodOp)]
+            fn greet(&self, name: &'static str) -> String {
+                let count = perform!(Counter::Next);
+                let line = format!("{}, {name} (#{count})", self.greeting);
+                perform!(Log::Line(line.clone()));
+                line
+            }
+
+            /// Updates the greeter across several operations, and hands it back.
+            #[effectful(root = MethodOp)]
+            fn greet_twice(mut self) -> Self {
+                for _ in 0..2 {
+                    self.greeted += perform!(Counter::Next);
+                    perform!(Log::Line(self.greeting.clone()));
                 }
+                self
             }
-        }
 
-        let result = unhandled_computation().run_checked(OnlyLoggerHandler);
+            #[effectful(root = MethodOp)]
+            fn into_greeting(self) -> String {
+                let count = perform!(Counter::Next);
+                format!("{} x{count}", self.greeting)
+            }
+        }
 
-        match result {
-            Err(UnhandledOp(Op::Math(Math::Add((5, 5))))) => (),
-            _ => panic!("Expected UnhandledOp error for Math::Add"),
+        #[test]
+        fn test_methods_capture_their_receiver() {
+            let greeter = Greeter {
+                greeting: "Hello".into(),
+                greeted: 0,
+            };
+            assert_eq!(
+                greeter.greet("Ada").handle(Env(0)).run_or_panic(),
+                "Hello, Ada (#1)"
+            );
+            let greeter = greeter.greet_twice().handle(Env(1)).run_or_panic();
+            assert_eq!(greeter.greeted, 2 + 3);
+            assert_eq!(
+                greeter.into_greeting().handle(Env(6)).run_or_panic(),
+                "Hello x7"
+            );
         }
-    }
 
-    #[test]
-    fn test_handle_all_alternative() {
-        // Test handle_all as an alternative to chained handle calls
-        struct Handler1;
-        impl PartialHandler<Op> for Handler1 {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
-                    _ => None,
-                }
+        trait Service {
+            fn name(&self) -> String;
+
+            #[effectful(root = MethodOp)]
+            fn id(&self) -> u32;
+
+            #[effectful(root = MethodOp)]
+            fn describe(&self) -> String
+            where
+                Self: Clone + Send + Sync + 'static,
+            {
+                let seq = perform!(Counter::Next);
+                format!("{}, request {seq}", self.name())
             }
         }
 
-        struct Handler2;
-        impl PartialHandler<Op> for Handler2 {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
-                    _ => None,
-                }
+        #[derive(Clone)]
+        struct Fixed(u32);
+
+        impl Service for Fixed {
+            fn name(&self) -> String {
+                format!("service {}", self.0)
             }
-        }
 
-        struct Handler3;
-        impl PartialHandler<Op> for Handler3 {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Logger(Logger::Info(msg)) => {
-                        println!("[INFO] {msg}");
-                        Some(Box::new(()))
-                    }
-                    _ => None,
-                }
+            #[effectful(root = MethodOp)]
+            fn id(&self) -> u32 {
+                perform!(Log::Line(format!("id {}", self.0)));
+                self.0
             }
         }
 
-        #[effectful]
-        fn computation() -> i32 {
-            let _: () = perform!(Logger::Info("Starting".to_string()));
-            let a: i32 = perform!(Math::Add((2, 3)));
-            let b: i32 = perform!(Math::Multiply((a, 4)));
-            b
+        #[test]
+        fn test_traits_declare_and_default_effectful_methods() {
+            assert_eq!(Fixed(7).id().handle(Env(0)).run_or_panic(), 7);
+            assert_eq!(
+                Fixed(7).describe().handle(Env(0)).run_or_panic(),
+                "service 7, request 1"
+            );
         }
+    }
 
-        // Use handle_all to attach all handlers at once
-        let handlers: Vec<Box<dyn PartialHandler<Op> + Send>> =
-            vec![Box::new(Handler1), Box::new(Handler2), Box::new(Handler3)];
+    mod closures {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::any::Any;
 
-        let result = computation().handle_all(handlers).run_checked();
+        effect! {
+            root ClosureOp;
+            Counter::Next -> u32;
+            Log::Line (String) -> ();
+        }
 
-        assert_eq!(result.unwrap(), 20); // (2 + 3) * 4 = 20
-    }
+        /// Counts up from its start value and keeps the log lines.
+        struct Env(u32, Vec<String>);
 
-    #[test]
-    fn test_chained_handler_syntax() {
-        // Test the .handle().handle().handle() chaining pattern
-        struct Handler1;
-        impl PartialHandler<Op> for Handler1 {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        impl Handler<ClosureOp> for Env {
+            fn handle(&mut self, op: &ClosureOp) -> Box<dyn Any + Send> {
                 match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
-                    _ => None,
+                    ClosureOp::Counter(Counter::Next) => {
+                        self.0 += 1;
+                        Box::new(self.0)
+                    }
+                    ClosureOp::Log(Log::Line(line)) => {
+                        self.1.push(line.clone());
+                        Box::new(())
+                    }
                 }
             }
         }
-        impl IntoVecHandler<Op> for Handler1 {
-            fn into_vec_handler(self) -> VecHandler<Op> {
-                let mut vec = VecHandler::new();
-                vec.push(self);
-                vec
-            }
+
+        #[effectful(root = ClosureOp)]
+        fn next() -> u32 {
+            perform!(Counter::Next)
         }
 
-        struct Handler2;
-        impl PartialHandler<Op> for Handler2 {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
-                    _ => None,
+        #[test]
+        fn test_closures_continue_bound_computations() {
+            let prefix = String::from("got");
+            let computation = next().bind(effectful_closure!(|first| {
+                let second = perform!(Counter::Next);
+                perform!(Log::Line(format!("{prefix} {first} and {second}")));
+                first + second
+            }));
+            assert_eq!(
+                computation.handle(Env(10, Vec::new())).run_or_panic(),
+                11 + 12
+            );
+        }
+
+        #[test]
+        fn test_closures_with_a_root_can_be_called_repeatedly() {
+            let add_next = effectful_closure!(root = ClosureOp, |n: u32| -> u32 {
+                if n == 0 {
+                    return 0;
                 }
-            }
+                n + perform!(Counter::Next)
+            });
+            assert_eq!(add_next(5).handle(Env(0, Vec::new())).run_or_panic(), 6);
+            assert_eq!(add_next(5).handle(Env(1, Vec::new())).run_or_panic(), 7);
+            assert_eq!(add_next(0).handle(Env(1, Vec::new())).run_or_panic(), 0);
         }
-        impl IntoVecHandler<Op> for Handler2 {
-            fn into_vec_handler(self) -> VecHandler<Op> {
-                let mut vec = VecHandler::new();
-                vec.push(self);
-                vec
-            }
+    }
+
+    mod family_handlers {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::any::Any;
+
+        effect! {
+            root AppOp;
+            Console::Print (String) -> ();
+            Console::ReadLine -> String;
+            Clock::Now -> u64;
+            Random::Below (u32) -> u32;
         }
 
-        struct Handler3;
-        impl PartialHandler<Op> for Handler3 {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        #[effectful(root = AppOp)]
+        fn greet() -> String {
+            let name = perform!(Console::ReadLine);
+            let now = perform!(Clock::Now);
+            perform!(Console::Print(format!("Hello, {name}!")));
+            format!("{name} at {now}")
+        }
+
+        /// Reads a fixed name and keeps what is printed.
+        #[handles(Console)]
+        #[derive(Default)]
+        struct Terminal {
+            printed: Vec<String>,
+        }
+
+        impl Handler<Console> for Terminal {
+            fn handle(&mut self, op: &Console) -> Box<dyn Any + Send> {
                 match op {
-                    Op::Logger(Logger::Info(msg)) => {
-                        // Just return unit, don't actually print
-                        let _ = msg;
-                        Some(Box::new(()))
+                    Console::Print(line) => {
+                        self.printed.push(line.clone());
+                        Box::new(())
                     }
-                    _ => None,
+                    Console::ReadLine => Box::new("Ada".to_string()),
                 }
             }
         }
-        impl IntoVecHandler<Op> for Handler3 {
-            fn into_vec_handler(self) -> VecHandler<Op> {
-                let mut vec = VecHandler::new();
-                vec.push(self);
-                vec
+
+        /// Stops time at `T` and always rolls the lowest value.
+        #[handles(Clock, Random)]
+        struct Fixed<const T: u64>;
+
+        impl<const T: u64> Handler<Clock> for Fixed<T> {
+            fn handle(&mut self, _: &Clock) -> Box<dyn Any + Send> {
+                Box::new(T)
             }
-        }
 
-        #[effectful]
-        fn chained_computation() -> i32 {
-            let _: () = perform!(Logger::Info("Starting".to_string()));
-            let sum: i32 = perform!(Math::Add((10, 20)));
-            let product: i32 = perform!(Math::Multiply((sum, 2)));
-            let _: () = perform!(Logger::Info("Done".to_string()));
-            product
+            fn reply(&mut self, _: &Clock) -> Reply {
+                Reply::new(T)
+            }
         }
 
-        // Test direct chaining with begin_chain
-        let result = chained_computation()
-            .begin_chain()
-            .handle(Handler1)
-            .handle(Handler2)
-            .handle(Handler3)
-            .run_checked();
-
-        assert_eq!(result.unwrap(), 60); // (10 + 20) * 2 = 60
+        impl<const T: u64> Handler<Random> for Fixed<T> {
+            fn handle(&mut self, _: &Random) -> Box<dyn Any + Send> {
+                Box::new(0u32)
+            }
+        }
 
-        // Test chaining starting with handle_all (still works)
-        let result = chained_computation()
-            .handle_all([Handler1])
-            .handle(Handler2)
-            .handle(Handler3)
-            .run_checked();
+        #[test]
+        fn test_family_handlers_route_their_families() {
+            let result = greet()
+                .begin_chain()
+                .handle(Terminal::default())
+                .handle(Fixed::<1700>)
+                .run_or_panic();
+            assert_eq!(result, "Ada at 1700");
+
+            let mut terminal = Terminal::default();
+            let handled = PartialHandler::<AppOp>::maybe_handle(
+                &mut terminal,
+                &AppOp::Console(Console::Print("hi".into())),
+            );
+            assert!(handled.is_some());
+            assert_eq!(terminal.printed, ["hi"]);
+            assert!(
+                PartialHandler::<AppOp>::maybe_reply(&mut terminal, &AppOp::Clock(Clock::Now))
+                    .is_none()
+            );
+        }
 
-        assert_eq!(result.unwrap(), 60);
+        #[test]
+        fn test_family_handlers_answer_their_own_family() {
+            let mut terminal = Terminal::default();
+            let reply = PartialHandler::<Console>::maybe_reply(&mut terminal, &Console::ReadLine);
+            assert_eq!(reply.unwrap().take::<String>(), "Ada");
+
+            let mut fixed = Fixed::<3>;
+            let now = PartialHandler::<AppOp>::maybe_reply(&mut fixed, &AppOp::Clock(Clock::Now));
+            assert_eq!(now.unwrap().take::<u64>(), 3);
+            let roll =
+                PartialHandler::<AppOp>::maybe_handle(&mut fixed, &AppOp::Random(Random::Below(6)));
+            assert_eq!(*roll.unwrap().downcast::<u32>().unwrap(), 0);
+        }
+    }
 
-        // Test partial chain (missing logger handler)
-        let result = chained_computation()
-            .begin_chain()
-            .handle(Handler1)
-            .handle(Handler2)
-            // Intentionally not adding Handler3
-            .run_checked();
+    #[cfg(feature = "std")]
+    mod joins {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::{
+            any::Any,
+            collections::HashSet,
+            sync::{Arc, Mutex},
+            thread::{self, ThreadId},
+        };
 
-        // Should fail because Logger::Info is not handled
-        assert!(result.is_err());
-        match result {
-            Err(UnhandledOp(Op::Logger(Logger::Info(_)))) => (),
-            _ => panic!("Expected unhandled Logger::Info operation"),
+        effect! {
+            root JoinOp;
+            Work::Square (u64) -> u64;
+            Work::Fail -> ();
         }
-    }
 
-    #[test]
-    fn test_vec_handler_flattening() {
-        // Test that VecHandlers are properly flattened when chained
-        struct Handler1;
-        impl PartialHandler<Op> for Handler1 {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        /// Squares numbers, noting the threads it answers on; declines
+        /// `Work::Fail`.
+        struct Squarer(Arc<Mutex<HashSet<ThreadId>>>);
+
+        impl PartialHandler<JoinOp> for Squarer {
+            fn maybe_handle(&mut self, op: &JoinOp) -> Option<Box<dyn Any + Send>> {
+                self.0.lock().unwrap().insert(thread::current().id());
                 match op {
-                    Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
-                    _ => None,
+                    JoinOp::Work(Work::Square(n)) => Some(Box::new(n * n)),
+                    JoinOp::Work(Work::Fail) => None,
                 }
             }
         }
-        impl IntoVecHandler<Op> for Handler1 {
-            fn into_vec_handler(self) -> VecHandler<Op> {
-                let mut vec = VecHandler::new();
-                vec.push(self);
-                vec
-            }
+
+        #[effectful(root = JoinOp)]
+        fn square(n: u64) -> u64 {
+            perform!(Work::Square(n))
         }
 
-        struct Handler2;
-        impl PartialHandler<Op> for Handler2 {
-            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
-                match op {
-                    Op::Math(Math::Multiply((a, b))) => Some(Box::new(a * b)),
-                    _ => None,
-                }
-            }
+        #[effectful(root = JoinOp)]
+        fn describe(n: u64) -> String {
+            let square = perform!(Work::Square(n));
+            format!("{n}² = {square}")
         }
-        impl IntoVecHandler<Op> for Handler2 {
-            fn into_vec_handler(self) -> VecHandler<Op> {
-                let mut vec = VecHandler::new();
-                vec.push(self);
-                vec
-            }
+
+        #[effectful(root = JoinOp)]
+        fn fail() -> u64 {
+            perform!(Work::Fail);
+            0
         }
 
-        #[effectful]
-        fn test_computation() -> i32 {
-            let sum: i32 = perform!(Math::Add((10, 20)));
-            let product: i32 = perform!(Math::Multiply((sum, 2)));
-            product
+        #[test]
+        fn test_join_runs_both_computations_on_their_own_threads() {
+            let threads = Arc::new(Mutex::new(HashSet::new()));
+            let (square, described) =
+                Effectful::join(square(3), describe(4), || Squarer(threads.clone())).unwrap();
+            assert_eq!(square, 9);
+            assert_eq!(described, "4² = 16");
+            assert_eq!(threads.lock().unwrap().len(), 2);
         }
 
-        // Create a VecHandler with Handler1
-        let mut vec1 = VecHandler::new();
-        vec1.push(Handler1);
+        #[test]
+        fn test_join_all_keeps_input_order() {
+            let threads = Arc::new(Mutex::new(HashSet::new()));
+            let squares =
+                Effectful::join_all((1..=4).map(square).collect(), || Squarer(threads.clone()));
+            assert_eq!(squares.unwrap(), [1, 4, 9, 16]);
+            assert_eq!(threads.lock().unwrap().len(), 4);
+        }
 
-        // Create another VecHandler with Handler2
-        let mut vec2 = VecHandler::new();
-        vec2.push(Handler2);
+        #[test]
+        fn test_joins_fail_with_the_first_failure() {
+            let squarer = || Squarer(Arc::default());
+            let joined = Effectful::join(square(2), fail(), squarer);
+            assert!(matches!(joined, Err(EffectError::Unhandled(_))));
 
-        // Chain them together - this should flatten
-        let result = test_computation()
-            .begin_chain()
-            .handle(vec1)
-            .handle(vec2)
-            .run_checked();
+            let all = Effectful::join_all(vec![square(1), fail(), square(3)], squarer);
+            assert!(matches!(all, Err(EffectError::Unhandled(_))));
+        }
 
-        assert_eq!(result.unwrap(), 60); // (10 + 20) * 2 = 60
+        #[test]
+        #[should_panic(expected = "no squares today")]
+        fn test_join_resumes_panics() {
+            let _ = Effectful::join(square(2), square(3), || -> Squarer {
+                panic!("no squares today")
+            });
+        }
+    }
 
-        // Verify that nesting VecHandlers still works
-        let mut outer_vec = VecHandler::new();
-        outer_vec.push(Handler1);
+    mod handler_state {
+        use crate as algae;
+        use algae::prelude::*;
+        use std::any::Any;
 
-        let mut inner_vec = VecHandler::new();
-        inner_vec.push(Handler2);
+        effect! {
+            root LogOp;
+            Log::Line (String) -> ();
+            Log::Fail -> ();
+        }
 
-        let result = test_computation()
-            .handle(outer_vec)
-            .handle(inner_vec)
-            .run_checked();
+        /// Keeps the lines logged; declines `Log::Fail`.
+        #[derive(Default)]
+        struct MemoryLog(Vec<String>);
 
-        assert_eq!(result.unwrap(), 60);
-    }
+        impl Handler<LogOp> for MemoryLog {
+            fn handle(&mut self, op: &LogOp) -> Box<dyn Any + Send> {
+                if let LogOp::Log(Log::Line(line)) = op {
+                    self.0.push(line.clone());
+                }
+                Box::new(())
+            }
+        }
 
-    // ============================================================================
-    // Improved Error Message Tests (TypeId Storage and Registry)
-    // ============================================================================
+        impl PartialHandler<LogOp> for MemoryLog {
+            fn maybe_handle(&mut self, op: &LogOp) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    LogOp::Log(Log::Fail) => None,
+                    _ => Some(self.handle(op)),
+                }
+            }
+        }
 
-    #[test]
-    fn test_reply_try_take_success() {
-        // Test successful try_take
-        let mut effect = Effect::new(Test::GetValue);
-        effect.fill_boxed(Box::new(42i32));
-        let mut reply = effect.get_reply();
+        #[effectful(root = LogOp)]
+        fn count_to(n: u32, fail: bool) -> u32 {
+            for i in 1..=n {
+                perform!(Log::Line(format!("{i}")));
+            }
+            if fail {
+                perform!(Log::Fail);
+            }
+            n
+        }
 
-        let result = reply.try_take::<i32>();
-        assert_eq!(result.unwrap(), 42);
-    }
+        #[test]
+        fn test_runs_return_their_handler() {
+            let (n, log) = count_to(3, false).run_with_handler(MemoryLog::default());
+            assert_eq!(n.unwrap(), 3);
+            assert_eq!(log.0, ["1", "2", "3"]);
 
-    #[test]
-    fn test_reply_try_take_already_taken() {
-        // Test try_take when value already taken
-        let mut effect = Effect::new(Test::GetValue);
-        effect.fill_boxed(Box::new(42i32));
-        let mut reply = effect.get_reply();
+            let (n, log) = count_to(2, false)
+                .handle(MemoryLog::default())
+                .run_with_handler();
+            assert_eq!((n.unwrap(), log.0.len()), (2, 2));
+        }
 
-        // First take succeeds
-        let result1 = reply.try_take::<i32>();
-        assert_eq!(result1.unwrap(), 42);
+        #[test]
+        fn test_failed_runs_return_their_handler() {
+            let (result, log) = count_to(2, true).try_run_with_handler(MemoryLog::default());
+            assert!(matches!(result, Err(EffectError::Unhandled(_))));
+            assert_eq!(log.0, ["1", "2"]);
 
-        // Second take returns AlreadyTaken error
-        let result2 = reply.try_take::<i32>();
-        assert!(matches!(result2, Err(ReplyError::AlreadyTaken)));
+            let (computation, log) = count_to(1, true).handle(log).into_parts();
+            let (result, log) = computation.try_run_with_handler(log);
+            assert!(result.is_err());
+            assert_eq!(log.0, ["1", "2", "1"]);
+        }
     }
 
-    #[test]
-    fn test_reply_try_take_wrong_type() {
-        // Test try_take with wrong type
-        let mut effect = Effect::new(Test::GetValue);
-        effect.fill_boxed(Box::new(42i32));
-        let mut reply = effect.get_reply();
+    mod unhandled_context {
+        use super::*;
 
-        let result = reply.try_take::<String>();
-        match result {
-            Err(ReplyError::WrongType { expected, actual }) => {
-                assert!(expected.contains("String"));
-                // With pre-registered types, we should get "i32"
-                // Check for either the type name or unknown type message
-                assert!(
-                    actual.contains("i32") || actual.contains("unknown type"),
-                    "Expected 'i32' or 'unknown type' in actual: {actual}"
-                );
-            }
-            _ => panic!("Expected WrongType error"),
+        effect! {
+            Db::Get (u32) -> String;
+            Audit::Record (String) -> ();
         }
-    }
 
-    #[test]
-    fn test_register_type_custom() {
-        // Test registering a custom type
-        #[derive(Debug)]
-        struct CustomType {
-            value: i32,
+        struct Rows;
+
+        impl PartialHandler<Op> for Rows {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Db(Db::Get(id)) => Some(Box::new(format!("row {id}"))),
+                    _ => None,
+                }
+            }
         }
 
-        impl CustomType {
-            fn value(&self) -> i32 {
-                self.value
+        struct Nothing;
+
+        impl PartialHandler<Op> for Nothing {
+            fn maybe_handle(&mut self, _op: &Op) -> Option<Box<dyn Any + Send>> {
+                None
             }
         }
 
-        // Register the custom type
-        register_type::<CustomType>();
+        impl_into_vec_handler!(Rows, Op);
+        impl_into_vec_handler!(Nothing, Op);
 
-        // Create an effect with the custom type
-        let mut effect = Effect::new(Test::GetValue);
-        let custom = CustomType { value: 42 };
-        assert_eq!(custom.value(), 42); // Use the field
-        effect.fill_boxed(Box::new(custom));
-        let mut reply = effect.get_reply();
+        #[effectful]
+        fn record(row: String) {
+            perform!(Audit::Record(row))
+        }
 
-        // Try to take with wrong type - should show our custom type name
-        let result = reply.try_take::<String>();
-        match result {
-            Err(ReplyError::WrongType { expected, actual }) => {
-                assert!(expected.contains("String"));
-                assert!(actual.contains("CustomType"));
+        #[effectful]
+        fn load(ids: Vec<u32>) -> Vec<String> {
+            let mut rows = Vec::new();
+            for id in ids {
+                let row = perform!(Db::Get(id));
+                rows.push(row);
             }
-            _ => panic!("Expected WrongType error"),
+            embed!(record(rows.join(", ")));
+            rows
         }
-    }
 
-    #[test]
-    fn test_reply_error_display() {
-        // Test Display implementation for ReplyError
-        let err1 = ReplyError::AlreadyTaken;
-        assert_eq!(
-            err1.to_string(),
-            "Reply::take called, but no value was supplied (possibly already taken)"
-        );
+        #[test]
+        fn test_unhandled_ops_report_where_they_were_performed() {
+            let Err(EffectError::Unhandled(unhandled)) = load(vec![1, 2]).try_run_with(Rows) else {
+                panic!("expected the audit to go unhandled");
+            };
+            assert_eq!(
+                unhandled.op,
+                Op::Audit(Audit::Record("row 1, row 2".into()))
+            );
+            assert_eq!(unhandled.index, 2);
+            assert!(unhandled
+                .function
+                .unwrap()
+                .ends_with("::unhandled_context::record"));
+            assert!(unhandled.location.unwrap().file().ends_with("lib.rs"));
+            assert_eq!(unhandled.declined, [std::any::type_name::<Rows>()]);
+        }
 
-        let err2 = ReplyError::WrongType {
-            expected: "String",
-            actual: "i32".to_string(),
-        };
-        assert_eq!(
-            err2.to_string(),
-            "Reply::take type mismatch: expected `String`, but reply contains `i32`"
-        );
+        #[test]
+        fn test_unhandled_ops_list_the_handlers_of_a_chain() {
+            let Err(EffectError::Unhandled(unhandled)) = load(vec![])
+                .begin_chain()
+                .handle(Nothing)
+                .handle(Rows)
+                .run()
+            else {
+                panic!("expected the audit to go unhandled");
+            };
+            let names = [
+                std::any::type_name::<Nothing>(),
+                std::any::type_name::<Rows>(),
+            ];
+            assert_eq!(unhandled.index, 0);
+            assert_eq!(unhandled.declined, names);
+            let message = unhandled.to_string();
+            assert!(message.contains("performed as operation 0 of algae::tests::"));
+            assert!(message.ends_with(&format!("declined by [{}]", names.join(", "))));
+
+            let or_else = Nothing.or_else(Rows);
+            assert_eq!(PartialHandler::<Op>::handler_names(&or_else), names);
+            assert_eq!(
+                UnhandledOp::new(Op::Db(Db::Get(7))).to_string(),
+                "Unhandled operation: Db(Get(7))"
+            );
+        }
     }
 
-    #[test]
-    fn test_reply_take_panics_with_good_message() {
-        // Test that take() method panics with proper error message
-        let mut effect = Effect::new(Test::GetValue);
-        effect.fill_boxed(Box::new(42i32));
-        let reply = effect.get_reply();
+    mod reply_mismatch {
+        use super::*;
 
-        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-            let _: String = reply.take();
-        }));
+        effect! {
+            Inventory::Count (String) -> u32;
+        }
 
-        assert!(result.is_err());
-        if let Err(panic_payload) = result {
-            let panic_message = if let Some(panic_str) = panic_payload.downcast_ref::<&str>() {
-                panic_str.to_string()
-            } else if let Some(panic_string) = panic_payload.downcast_ref::<String>() {
-                panic_string.clone()
-            } else {
-                "Unknown panic message".to_string()
-            };
+        /// Answers counts as text, which `effect!` declares as `u32`.
+        struct Textual;
 
-            assert!(panic_message.contains("type mismatch"));
-            assert!(panic_message.contains("expected"));
-            assert!(panic_message.contains("but reply contains"));
+        impl Handler<Op> for Textual {
+            fn handle(&mut self, _op: &Op) -> Box<dyn Any + Send> {
+                unreachable!("answers through `reply`")
+            }
+
+            fn reply(&mut self, op: &Op) -> Reply {
+                let Op::Inventory(Inventory::Count(item)) = op;
+                Reply::new(format!("{} {item}", 3))
+            }
         }
-    }
 
-    #[test]
-    fn test_unknown_type_fallback() {
-        // Test behavior with an unregistered type (should show TypeId)
-        struct UnregisteredType;
+        #[effectful]
+        fn count(item: &'static str) -> u32 {
+            perform!(Inventory::Count(item.to_string()))
+        }
 
-        let mut effect = Effect::new(Test::GetValue);
-        effect.fill_boxed(Box::new(UnregisteredType));
-        let mut reply = effect.get_reply();
+        #[test]
+        fn test_wrong_replies_name_the_operation_and_types() {
+            let panic = std::panic::catch_unwind(|| count("apples").handle(Textual).run_or_panic())
+                .unwrap_err();
+            let message = panic.downcast_ref::<String>().unwrap();
+            assert_eq!(
+                message,
+                "Reply::take type mismatch for `Inventory(Count(\"apples\"))`: expected `u32`, \
+                 but the handler replied with `alloc::string::String`"
+            );
+        }
 
-        let result = reply.try_take::<String>();
-        match result {
-            Err(ReplyError::WrongType { expected, actual }) => {
-                assert!(expected.contains("String"));
-                // Should show unknown type with TypeId
-                assert!(actual.contains("unknown type"));
-                assert!(actual.contains("TypeId"));
-            }
-            _ => panic!("Expected WrongType error"),
+        #[test]
+        fn test_only_wrong_replies_render_the_operation() {
+            let expecting = || Effect {
+                expects: Some(|| (std::any::TypeId::of::<u32>(), "u32")),
+                describe: Some(|op: &Op| format!("{op:?}")),
+                ..Effect::new(Op::Inventory(Inventory::Count("pears".into())))
+            };
+            let mut right = expecting();
+            right.fill(Reply::new(2u32));
+            assert!(right.get_reply().mismatch().is_none());
+
+            let mut wrong = expecting();
+            wrong.fill_boxed(Box::new(2u64));
+            assert_eq!(
+                wrong.get_reply().mismatch(),
+                Some(ReplyError::WrongType {
+                    expected: "u32",
+                    actual: "u64".into(),
+                    op: Some("Inventory(Count(\"pears\"))".into()),
+                })
+            );
         }
     }
 
-    #[test]
-    fn test_reply_debug() {
-        // Test that Reply implements Debug
-        let mut effect = Effect::new(Test::GetValue);
-        effect.fill_boxed(Box::new(42i32));
-        let reply = effect.get_reply();
+    mod default_run {
+        use super::*;
 
-        let debug_str = format!("{reply:?}");
-        assert!(debug_str.contains("Reply"));
-        assert!(debug_str.contains("inner"));
-        assert!(debug_str.contains("Some"));
-        assert!(debug_str.contains("Stored"));
-    }
+        effect! {
+            Shop::Price (String) -> u32;
+            Shop::Checkout -> ();
+        }
 
-    #[test]
-    fn test_register_type_before_and_after() {
-        // This test demonstrates the "unknown-then-known" behavior of register_type.
-        // It shows that types are only recognized in error messages after they've been
-        // registered, even if the same type was used in a previous error.
+        /// Prices the items it stocks and declines the rest.
+        struct Prices;
 
-        // Define a custom type that isn't pre-registered
-        #[derive(Debug)]
-        struct UnregisteredCustomType {
-            data: String,
+        impl PartialHandler<Op> for Prices {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Shop(Shop::Price(item)) if item == "tea" => Some(Box::new(4u32)),
+                    _ => None,
+                }
+            }
         }
 
-        impl UnregisteredCustomType {
-            fn new(data: &str) -> Self {
-                Self {
-                    data: data.to_string(),
+        /// Closes the till at checkout, pricing everything in pence as text.
+        struct Till;
+
+        impl Handler<Op> for Till {
+            fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+                match op {
+                    Op::Shop(Shop::Price(_)) => Box::new("400p".to_string()),
+                    Op::Shop(Shop::Checkout) => Abort::boxed("till closed"),
                 }
             }
+        }
 
-            #[allow(dead_code)]
-            fn data(&self) -> &str {
-                &self.data
+        #[effectful]
+        fn basket(items: Vec<&'static str>) -> u32 {
+            let mut total = 0;
+            for item in items {
+                let price = perform!(Shop::Price(item.to_string()));
+                total += price;
             }
+            total
         }
 
-        // PART 1: Before registration - type should show as "unknown"
-        let mut effect1 = Effect::new(Test::GetValue);
-        effect1.fill_boxed(Box::new(UnregisteredCustomType::new("test")));
-        let mut reply1 = effect1.get_reply();
+        #[effectful]
+        fn checkout() {
+            perform!(Shop::Checkout)
+        }
 
-        let result1 = reply1.try_take::<i32>();
-        match result1 {
-            Err(ReplyError::WrongType { expected, actual }) => {
-                assert!(expected.contains("i32"));
-                // Before registration, should show as unknown type
-                assert!(
-                    actual.contains("unknown type"),
-                    "Before registration, type should be unknown. Got: {actual}"
-                );
-                assert!(
-                    actual.contains("TypeId"),
-                    "Unknown types should show TypeId. Got: {actual}"
-                );
-            }
-            _ => panic!("Expected WrongType error"),
+        fn prices() -> VecHandler<Op> {
+            let mut chain = VecHandler::new();
+            chain.push(Prices);
+            chain
         }
 
-        // PART 2: Register the type
-        register_type::<UnregisteredCustomType>();
+        #[test]
+        fn test_declined_operations_are_errors() {
+            assert_eq!(
+                basket(vec!["tea", "tea"]).handle(prices()).run().unwrap(),
+                8
+            );
 
-        // PART 3: After registration - same type should now show its name
-        let mut effect2 = Effect::new(Test::GetValue);
-        effect2.fill_boxed(Box::new(UnregisteredCustomType::new("test2")));
-        let mut reply2 = effect2.get_reply();
+            let result = basket(vec!["tea", "cake"]).handle(prices()).run();
+            let Err(EffectError::Unhandled(unhandled)) = result else {
+                panic!("expected an unhandled operation, got {result:?}");
+            };
+            assert_eq!(unhandled.op, Op::Shop(Shop::Price("cake".into())));
+            assert_eq!(unhandled.index, 1);
+        }
 
-        let result2 = reply2.try_take::<String>();
-        match result2 {
-            Err(ReplyError::WrongType { expected, actual }) => {
-                assert!(expected.contains("String"));
-                // After registration, should show the actual type name
-                assert!(
-                    actual.contains("UnregisteredCustomType"),
-                    "After registration, type name should be shown. Got: {actual}"
-                );
-                assert!(
-                    !actual.contains("unknown type"),
-                    "After registration, should not show 'unknown type'. Got: {actual}"
-                );
-            }
-            _ => panic!("Expected WrongType error"),
+        #[test]
+        fn test_partial_handlers_run_as_chains_of_one() {
+            let result = basket(vec!["tea", "cake"]).handle_partial(Prices).run();
+            let Err(EffectError::Unhandled(unhandled)) = result else {
+                panic!("expected an unhandled operation, got {result:?}");
+            };
+            assert_eq!(unhandled.op, Op::Shop(Shop::Price("cake".into())));
+            assert_eq!(unhandled.declined, [core::any::type_name::<Prices>()]);
         }
 
-        // PART 4: Verify that registration is global and persistent
-        // Try the same type in another error to confirm it's still registered
-        let mut effect3 = Effect::new(Test::GetValue);
-        effect3.fill_boxed(Box::new(UnregisteredCustomType::new("test3")));
-        let mut reply3 = effect3.get_reply();
+        #[test]
+        fn test_aborts_and_wrong_replies_are_errors() {
+            let result = checkout().handle(Till).run();
+            let Err(EffectError::Aborted(abort)) = result else {
+                panic!("expected an abort, got {result:?}");
+            };
+            assert_eq!(abort.to_string(), "\"till closed\"");
 
-        let result3 = reply3.try_take::<bool>();
-        match result3 {
-            Err(ReplyError::WrongType { expected, actual }) => {
-                assert!(expected.contains("bool"));
-                // Should still show the registered name
-                assert!(
-                    actual.contains("UnregisteredCustomType"),
-                    "Type should remain registered. Got: {actual}"
-                );
-            }
-            _ => panic!("Expected WrongType error"),
+            let result = basket(vec!["tea"]).handle(Till).run();
+            let Err(EffectError::ReplyMismatch(error)) = result else {
+                panic!("expected a mismatched reply, got {result:?}");
+            };
+            assert!(error.to_string().contains("expected `u32`"));
+        }
+
+        #[test]
+        #[should_panic(expected = "Unhandled operation: Shop(Price(\"cake\"))")]
+        fn test_run_or_panic_panics_on_declined_operations() {
+            basket(vec!["cake"]).handle(prices()).run_or_panic();
         }
     }
 }
@@ -0,0 +1,264 @@
+//! Middleware wrapped around a handler.
+//!
+//! A [`HandlerMiddleware`] sees every operation dispatched to the handler it
+//! wraps and decides how to pass it on through [`Next`]: it can time or log
+//! the dispatch, pass the operation on several times to retry it, or answer
+//! by itself without passing it on. [`Handled::layer`] wraps the handler of a
+//! handled computation, and [`Layered`] wraps any handler:
+//!
+//! ```rust,ignore
+//! use algae::middleware::{self, Next};
+//!
+//! let result = fetch_all()
+//!     .handle(HttpHandler::new())
+//!     .layer(middleware::from_fn(|op: &Op, mut next: Next<'_, Op>| {
+//!         let started = Instant::now();
+//!         let reply = next.run(op);
+//!         eprintln!("{} took {:?}", op.qualified_name(), started.elapsed());
+//!         reply
+//!     }))
+//!     .run()?;
+//! ```
+//!
+//! Each layer wraps the ones added before it, so the last one added sees
+//! operations first. The layers of [`quota`](crate::quota),
+//! [`retry`](crate::retry), [`timeout`](crate::timeout) and
+//! [`observe`](crate::observe) wrap their inner handler themselves and can
+//! sit anywhere in the stack.
+
+use crate::{Handled, Handler, PartialHandler, Reply};
+use alloc::{boxed::Box, vec::Vec};
+use core::{any::Any, fmt};
+
+/// The rest of the stack below a middleware.
+pub struct Next<'a, Op> {
+    inner: &'a mut dyn PartialHandler<Op>,
+}
+
+impl<Op> Next<'_, Op> {
+    /// Dispatches `op` to the wrapped handler.
+    ///
+    /// May be called more than once, e.g. to retry an aborted operation.
+    pub fn run(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.inner.maybe_handle(op)
+    }
+}
+
+/// A cross-cutting concern wrapped around a handler.
+///
+/// Returning `None` declines the operation, as a [`PartialHandler`] does.
+pub trait HandlerMiddleware<Op> {
+    /// Handles `op`, usually by passing it on with `next`.
+    fn handle(&mut self, op: &Op, next: Next<'_, Op>) -> Option<Box<dyn Any + Send>>;
+}
+
+impl<Op, M: HandlerMiddleware<Op> + ?Sized> HandlerMiddleware<Op> for Box<M> {
+    fn handle(&mut self, op: &Op, next: Next<'_, Op>) -> Option<Box<dyn Any + Send>> {
+        (**self).handle(op, next)
+    }
+}
+
+/// A middleware made from a closure, see [`from_fn`].
+#[derive(Clone)]
+pub struct FromFn<F>(F);
+
+impl<F> fmt::Debug for FromFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FromFn").finish_non_exhaustive()
+    }
+}
+
+/// Makes a middleware of `f`, which gets each operation and the [`Next`]
+/// handler.
+pub fn from_fn<Op, F>(f: F) -> FromFn<F>
+where
+    F: FnMut(&Op, Next<'_, Op>) -> Option<Box<dyn Any + Send>>,
+{
+    FromFn(f)
+}
+
+impl<Op, F> HandlerMiddleware<Op> for FromFn<F>
+where
+    F: FnMut(&Op, Next<'_, Op>) -> Option<Box<dyn Any + Send>>,
+{
+    fn handle(&mut self, op: &Op, next: Next<'_, Op>) -> Option<Box<dyn Any + Send>> {
+        (self.0)(op, next)
+    }
+}
+
+/// A handler wrapped in a middleware.
+///
+/// Like [`VecHandler`](crate::VecHandler), it is a [`Handler`] as well,
+/// which panics if an operation is declined.
+#[derive(Debug, Clone)]
+pub struct Layered<M, H> {
+    middleware: M,
+    inner: H,
+}
+
+impl<M, H> Layered<M, H> {
+    /// Wraps `inner` in `middleware`.
+    pub fn new(inner: H, middleware: M) -> Self {
+        Self { middleware, inner }
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<Op, M, H> PartialHandler<Op> for Layered<M, H>
+where
+    M: HandlerMiddleware<Op>,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let next = Next {
+            inner: &mut self.inner,
+        };
+        self.middleware.handle(op, next)
+    }
+}
+
+impl<Op, M, H> Handler<Op> for Layered<M, H>
+where
+    Op: fmt::Debug,
+    M: HandlerMiddleware<Op>,
+    H: PartialHandler<Op>,
+{
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        match self.maybe_handle(op) {
+            Some(reply) => reply,
+            None => panic!("Unhandled operation: {op:?}"),
+        }
+    }
+
+    fn try_reply(&mut self, op: &Op) -> Option<Reply> {
+        self.maybe_reply(op)
+    }
+
+    fn handler_names(&self) -> Vec<&'static str> {
+        PartialHandler::handler_names(self)
+    }
+}
+
+impl<R, Op: 'static, H> Handled<R, Op, H> {
+    /// Wraps the handler in `middleware`, which then sees every operation
+    /// first.
+    pub fn layer<M>(self, middleware: M) -> Handled<R, Op, Layered<M, H>> {
+        Handled {
+            eff: self.eff,
+            h: Layered::new(self.h, middleware),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    effect! {
+        Http::Get (String) -> String;
+        Cache::Hit (String) -> bool;
+    }
+
+    /// Aborts the first `failures` gets, then echoes the URL; declines
+    /// `Cache`.
+    struct Flaky {
+        failures: u32,
+    }
+
+    impl PartialHandler<Op> for Flaky {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Http(Http::Get(url)) = op else {
+                return None;
+            };
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Some(Abort::boxed("connection reset"));
+            }
+            Some(Box::new(format!("body of {url}")))
+        }
+    }
+
+    #[effectful]
+    fn fetch(url: &'static str) -> String {
+        let cached: bool = perform!(Cache::Hit(url.to_string()));
+        if cached {
+            return "cached".to_string();
+        }
+        perform!(Http::Get(url.to_string()))
+    }
+
+    fn record(log: &Arc<Mutex<Vec<String>>>, tag: &'static str) -> impl HandlerMiddleware<Op> {
+        let log = log.clone();
+        from_fn(move |op: &Op, mut next: Next<'_, Op>| {
+            log.lock()
+                .unwrap()
+                .push(format!("{tag} {}", op.qualified_name()));
+            next.run(op)
+        })
+    }
+
+    /// Answers `Cache::Hit` with `false` itself.
+    fn cold_cache(op: &Op, mut next: Next<'_, Op>) -> Option<Box<dyn Any + Send>> {
+        match op {
+            Op::Cache(_) => Some(Box::new(false)),
+            _ => next.run(op),
+        }
+    }
+
+    #[test]
+    fn test_last_layer_sees_operations_first() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let body = fetch("a")
+            .handle(Flaky { failures: 0 })
+            .layer(from_fn(cold_cache))
+            .layer(record(&log, "inner"))
+            .layer(record(&log, "outer"))
+            .run_or_panic();
+        assert_eq!(body, "body of a");
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "outer Cache.Hit",
+                "inner Cache.Hit",
+                "outer Http.Get",
+                "inner Http.Get"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_middleware_can_pass_operations_on_repeatedly() {
+        let retry_once = from_fn(|op: &Op, mut next: Next<'_, Op>| {
+            let reply = next.run(op)?;
+            if reply.is::<Abort>() {
+                return next.run(op);
+            }
+            Some(reply)
+        });
+
+        let mut handler = Layered::new(
+            Layered::new(Flaky { failures: 1 }, retry_once),
+            from_fn(cold_cache),
+        );
+        let body = fetch("b").drive(|op| handler.maybe_handle(op));
+        assert_eq!(body.unwrap(), "body of b");
+        assert_eq!(handler.inner().inner().failures, 0);
+
+        let err = fetch("c")
+            .handle(Flaky { failures: 1 })
+            .layer(from_fn(cold_cache))
+            .run()
+            .unwrap_err();
+        assert!(matches!(err, EffectError::Aborted(_)));
+    }
+}
@@ -0,0 +1,683 @@
+//! Per-operation mocks generated by `effect!`.
+//!
+//! An `automock;` header makes `effect!` emit a `<Root>AutoMock` handler with
+//! one [`OpMock`] field per declared operation, named after the operation in
+//! snake_case. Each field holds the responses for its operation:
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//!
+//! effect! {
+//!     automock;
+//!     Console::Print (String) -> ();
+//!     Console::ReadLine -> String;
+//! }
+//!
+//! let mock = OpAutoMock::new();
+//! mock.read_line.returns_iter(["Alice", "Bob"]);
+//! let printed = mock.print.capture();
+//!
+//! greet_twice().handle(mock.clone()).run()?;
+//! assert_eq!(printed.values(), ["Hello, Alice!", "Hello, Bob!"]);
+//! assert!(mock.unmatched().is_empty());
+//! ```
+//!
+//! Queued responses are used first, in order; after that the fallback set
+//! with [`OpMock::returns`], [`OpMock::returns_with`] or
+//! [`OpMock::capture`] answers. An operation without a response left, and
+//! any operation of a family embedded with a `use` line, is declined and
+//! recorded in the mock's `unmatched()` list, so other handlers can follow
+//! the mock in a chain.
+//!
+//! Clones of a mock share their responses and records, so a mock can be
+//! handed to a computation and inspected afterwards.
+//!
+//! # Expectations
+//!
+//! [`MockHandler`] works with any operation type instead, and checks the
+//! calls it gets against expectations set up front:
+//!
+//! ```rust,ignore
+//! let mock = MockHandler::builder()
+//!     .expect(Op::Console(Console::ReadLine))
+//!     .returns("Alice".to_string())
+//!     .expect(Op::Console(Console::Print("Hello, Alice!".into())))
+//!     .build();
+//!
+//! greet().handle(mock).run_or_panic();
+//! ```
+//!
+//! Each expectation is met by a number of calls, one unless set with
+//! [`MockBuilder::times`], and calls are matched against the expectations in
+//! the order they were set up. A call that matches no expectation with calls
+//! left fails the test with a panic, and so does dropping the last clone of
+//! the mock while an expectation is still short of calls.
+
+use crate::{Abort, Handler, PartialHandler};
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+type FallbackFn<P, R> = Box<dyn FnMut(&P) -> R + Send>;
+
+struct Responses<P, R> {
+    queue: VecDeque<R>,
+    fallback: Option<FallbackFn<P, R>>,
+}
+
+/// The responses to one operation with payload `P` and return type `R`.
+///
+/// Operations without a payload have `P = ()`.
+pub struct OpMock<P, R> {
+    responses: Arc<Mutex<Responses<P, R>>>,
+    calls: Captured<P>,
+}
+
+impl<P, R> OpMock<P, R> {
+    /// A mock without responses; every call is unmatched.
+    pub fn new() -> Self {
+        Self {
+            responses: Arc::new(Mutex::new(Responses {
+                queue: VecDeque::new(),
+                fallback: None,
+            })),
+            calls: Captured::default(),
+        }
+    }
+
+    /// Answers every call with a clone of `value` once the queue is empty.
+    pub fn returns(&self, value: R) -> &Self
+    where
+        R: Clone + Send + 'static,
+    {
+        self.returns_with(move |_| value.clone())
+    }
+
+    /// Queues one response per item, used in order.
+    pub fn returns_iter<I>(&self, values: I) -> &Self
+    where
+        I: IntoIterator,
+        I::Item: Into<R>,
+    {
+        let mut responses = self.responses.lock().unwrap();
+        responses.queue.extend(values.into_iter().map(Into::into));
+        self
+    }
+
+    /// Answers calls with `f(payload)` once the queue is empty.
+    pub fn returns_with<F>(&self, f: F) -> &Self
+    where
+        F: FnMut(&P) -> R + Send + 'static,
+    {
+        self.responses.lock().unwrap().fallback = Some(Box::new(f));
+        self
+    }
+
+    /// Returns the payloads of the answered calls, shared with this mock.
+    ///
+    /// Unless a fallback is already set, calls are answered with
+    /// `R::default()` once the queue is empty, which suits operations
+    /// returning `()`.
+    pub fn capture(&self) -> Captured<P>
+    where
+        R: Default,
+    {
+        let mut responses = self.responses.lock().unwrap();
+        if responses.fallback.is_none() {
+            responses.fallback = Some(Box::new(|_| R::default()));
+        }
+        self.calls.clone()
+    }
+
+    /// The number of answered calls.
+    pub fn times_called(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Answers a call with `payload`, or returns `None` if no response is
+    /// left. Used by the generated `PartialHandler` impl.
+    pub fn respond(&self, payload: &P) -> Option<R>
+    where
+        P: Clone,
+    {
+        let response = {
+            let mut responses = self.responses.lock().unwrap();
+            match responses.queue.pop_front() {
+                Some(response) => Some(response),
+                None => responses.fallback.as_mut().map(|f| f(payload)),
+            }
+        };
+        if response.is_some() {
+            self.calls.push(payload.clone());
+        }
+        response
+    }
+}
+
+impl<P, R> Default for OpMock<P, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, R> Clone for OpMock<P, R> {
+    fn clone(&self) -> Self {
+        Self {
+            responses: Arc::clone(&self.responses),
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<P, R> fmt::Debug for OpMock<P, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let responses = self.responses.lock().unwrap();
+        f.debug_struct("OpMock")
+            .field("queued", &responses.queue.len())
+            .field("fallback", &responses.fallback.is_some())
+            .field("calls", &self.calls.len())
+            .finish()
+    }
+}
+
+/// Values recorded by a mock, oldest first; clones share the record.
+pub struct Captured<T> {
+    values: Arc<Mutex<Vec<T>>>,
+}
+
+impl<T> Captured<T> {
+    /// A copy of the recorded values.
+    pub fn values(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.values.lock().unwrap().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Records `value`. Used by the generated `PartialHandler` impl.
+    pub fn push(&self, value: T) {
+        self.values.lock().unwrap().push(value);
+    }
+}
+
+impl<T> Default for Captured<T> {
+    fn default() -> Self {
+        Self {
+            values: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl<T> Clone for Captured<T> {
+    fn clone(&self) -> Self {
+        Self {
+            values: Arc::clone(&self.values),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Captured<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.values.lock().unwrap().iter())
+            .finish()
+    }
+}
+
+type Matcher<Op> = Box<dyn Fn(&Op) -> bool + Send>;
+type ReplyFn<Op> = Box<dyn FnMut(&Op) -> Box<dyn Any + Send> + Send>;
+
+/// One expected kind of call and how to answer it.
+struct Expectation<Op> {
+    description: String,
+    matches: Matcher<Op>,
+    reply: ReplyFn<Op>,
+    times: Option<usize>,
+    calls: usize,
+}
+
+impl<Op> Expectation<Op> {
+    fn has_calls_left(&self) -> bool {
+        self.times.is_none_or(|times| self.calls < times)
+    }
+
+    fn unmet(&self) -> Option<String> {
+        let times = self.times?;
+        (self.calls < times).then(|| {
+            format!(
+                "expected {} {times} time(s), but it was performed {} time(s)",
+                self.description, self.calls
+            )
+        })
+    }
+}
+
+struct Expectations<Op> {
+    expectations: Vec<Expectation<Op>>,
+    decline_unexpected: bool,
+}
+
+impl<Op> Expectations<Op> {
+    fn unmet(&self) -> Vec<String> {
+        self.expectations
+            .iter()
+            .filter_map(Expectation::unmet)
+            .collect()
+    }
+}
+
+impl<Op> Drop for Expectations<Op> {
+    fn drop(&mut self) {
+        // Failing again while a test is already failing would abort it.
+        if thread::panicking() {
+            return;
+        }
+        let unmet = self.unmet();
+        if !unmet.is_empty() {
+            panic!("mock expectations not met:\n  {}", unmet.join("\n  "));
+        }
+    }
+}
+
+/// A handler answering the calls it expects, checked against those
+/// expectations when its last clone is dropped.
+///
+/// Built with [`MockHandler::builder`]. Clones share their expectations.
+pub struct MockHandler<Op> {
+    expectations: Arc<Mutex<Expectations<Op>>>,
+}
+
+impl<Op> MockHandler<Op> {
+    pub fn builder() -> MockBuilder<Op> {
+        MockBuilder {
+            expectations: Vec::new(),
+            decline_unexpected: false,
+        }
+    }
+
+    /// Panics now if an expectation is still short of calls.
+    pub fn verify(&self) {
+        let unmet = self.expectations.lock().unwrap().unmet();
+        assert!(
+            unmet.is_empty(),
+            "mock expectations not met:\n  {}",
+            unmet.join("\n  ")
+        );
+    }
+}
+
+impl<Op> Clone for MockHandler<Op> {
+    fn clone(&self) -> Self {
+        Self {
+            expectations: Arc::clone(&self.expectations),
+        }
+    }
+}
+
+impl<Op> fmt::Debug for MockHandler<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expectations = self.expectations.lock().unwrap();
+        f.debug_struct("MockHandler")
+            .field("expectations", &expectations.expectations.len())
+            .field("unmet", &expectations.unmet().len())
+            .finish()
+    }
+}
+
+impl<Op: fmt::Debug> PartialHandler<Op> for MockHandler<Op> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let mut expectations = self.expectations.lock().unwrap();
+        let decline_unexpected = expectations.decline_unexpected;
+        let expected = expectations
+            .expectations
+            .iter_mut()
+            .find(|e| e.has_calls_left() && (e.matches)(op));
+        match expected {
+            Some(expectation) => {
+                expectation.calls += 1;
+                Some((expectation.reply)(op))
+            }
+            None if decline_unexpected => None,
+            None => {
+                drop(expectations);
+                panic!("unexpected call to mock: {op:?}")
+            }
+        }
+    }
+}
+
+impl<Op: fmt::Debug> Handler<Op> for MockHandler<Op> {
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        match self.maybe_handle(op) {
+            Some(reply) => reply,
+            None => panic!("Unhandled operation: {op:?}"),
+        }
+    }
+
+    fn try_reply(&mut self, op: &Op) -> Option<crate::Reply> {
+        self.maybe_reply(op)
+    }
+}
+
+impl<Op: fmt::Debug + 'static> crate::IntoVecHandler<Op> for MockHandler<Op> {
+    fn into_vec_handler(self) -> crate::VecHandler<Op> {
+        let mut vec = crate::VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+/// Sets up the expectations of a [`MockHandler`].
+///
+/// [`returns`](Self::returns), [`returns_with`](Self::returns_with),
+/// [`aborts`](Self::aborts), [`times`](Self::times) and
+/// [`any_times`](Self::any_times) apply to the expectation added last.
+pub struct MockBuilder<Op> {
+    expectations: Vec<Expectation<Op>>,
+    decline_unexpected: bool,
+}
+
+impl<Op> MockBuilder<Op> {
+    /// Expects a call equal to `op`, answered with `()` unless set otherwise.
+    pub fn expect(self, op: Op) -> Self
+    where
+        Op: PartialEq + fmt::Debug + Send + 'static,
+    {
+        let description = format!("{op:?}");
+        self.expect_where(description, move |call| *call == op)
+    }
+
+    /// Expects a call for which `matches` holds, described as `description`
+    /// in failures.
+    pub fn expect_where<F>(mut self, description: impl Into<String>, matches: F) -> Self
+    where
+        F: Fn(&Op) -> bool + Send + 'static,
+    {
+        self.expectations.push(Expectation {
+            description: description.into(),
+            matches: Box::new(matches),
+            reply: Box::new(|_| Box::new(())),
+            times: Some(1),
+            calls: 0,
+        });
+        self
+    }
+
+    /// Answers the expected calls with clones of `value`.
+    pub fn returns<R: Clone + Send + 'static>(self, value: R) -> Self {
+        self.returns_with(move |_| value.clone())
+    }
+
+    /// Answers the expected calls with `f` of the operation.
+    pub fn returns_with<R, F>(mut self, mut f: F) -> Self
+    where
+        R: Send + 'static,
+        F: FnMut(&Op) -> R + Send + 'static,
+    {
+        self.last().reply = Box::new(move |op| Box::new(f(op)));
+        self
+    }
+
+    /// Answers the expected calls with an [`Abort`] carrying `error`.
+    pub fn aborts<E>(mut self, error: E) -> Self
+    where
+        E: Clone + fmt::Debug + Send + 'static,
+    {
+        self.last().reply = Box::new(move |_| Abort::boxed(error.clone()));
+        self
+    }
+
+    /// Expects exactly `n` such calls.
+    pub fn times(mut self, n: usize) -> Self {
+        self.last().times = Some(n);
+        self
+    }
+
+    /// Allows any number of such calls, including none.
+    pub fn any_times(mut self) -> Self {
+        self.last().times = None;
+        self
+    }
+
+    /// Declines calls that match no expectation instead of failing, so
+    /// handlers after the mock can answer them.
+    pub fn decline_unexpected(mut self) -> Self {
+        self.decline_unexpected = true;
+        self
+    }
+
+    pub fn build(self) -> MockHandler<Op> {
+        MockHandler {
+            expectations: Arc::new(Mutex::new(Expectations {
+                expectations: self.expectations,
+                decline_unexpected: self.decline_unexpected,
+            })),
+        }
+    }
+
+    fn last(&mut self) -> &mut Expectation<Op> {
+        self.expectations
+            .last_mut()
+            .expect("`expect` must come before the settings of an expectation")
+    }
+}
+
+impl<Op> fmt::Debug for MockBuilder<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockBuilder")
+            .field("expectations", &self.expectations.len())
+            .field("decline_unexpected", &self.decline_unexpected)
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use crate as algae;
+    use crate::effects::log::{Log, LogEvent, MemoryLog};
+    use algae::prelude::*;
+
+    effect! {
+        automock;
+        use algae::effects::log::Log;
+        Console::Print (String) -> ();
+        Console::ReadLine -> String;
+        Math::Add ((i32, i32)) -> i32;
+        Files::Read (String) -> Result<String, String>;
+    }
+
+    #[effectful]
+    fn greet_twice() {
+        for _ in 0..2 {
+            let name: String = perform!(Console::ReadLine);
+            let _: () = perform!(Console::Print(format!("Hello, {name}!")));
+        }
+    }
+
+    #[test]
+    fn test_queued_responses_and_capture() {
+        let mock = OpAutoMock::new();
+        mock.read_line.returns_iter(["Alice", "Bob"]);
+        let printed = mock.print.capture();
+
+        greet_twice().try_run_with(mock.clone()).unwrap();
+
+        assert_eq!(printed.values(), ["Hello, Alice!", "Hello, Bob!"]);
+        assert_eq!(mock.read_line.times_called(), 2);
+        assert!(mock.unmatched().is_empty());
+    }
+
+    #[test]
+    fn test_fallbacks_follow_the_queue() {
+        #[effectful]
+        fn sums() -> (i32, i32, i32) {
+            let a: i32 = perform!(Math::Add((1, 2)));
+            let b: i32 = perform!(Math::Add((3, 4)));
+            let c: i32 = perform!(Math::Add((5, 6)));
+            (a, b, c)
+        }
+
+        let mock = OpAutoMock::new();
+        mock.add.returns_iter([100]).returns_with(|(a, b)| a + b);
+        assert_eq!(sums().try_run_with(mock).unwrap(), (100, 7, 11));
+
+        let mock = OpAutoMock::new();
+        mock.add.returns(0);
+        assert_eq!(sums().try_run_with(mock).unwrap(), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_unmatched_ops_are_declined_and_recorded() {
+        #[effectful]
+        fn read_and_log() -> Result<String, String> {
+            let contents: Result<String, String> = perform!(Files::Read("a.txt".into()));
+            let _: () = perform!(Log::Event(LogEvent::info("read")));
+            contents
+        }
+
+        let mock = OpAutoMock::new();
+        mock.read.returns_iter([Ok("hi".to_string())]);
+        let log = MemoryLog::new();
+        let contents = read_and_log()
+            .begin_chain()
+            .handle(mock.clone())
+            .handle(log.clone())
+            .run()
+            .unwrap();
+
+        assert_eq!(contents, Ok("hi".to_string()));
+        assert_eq!(log.events().len(), 1);
+        assert!(matches!(mock.unmatched()[..], [Op::Log(_)]));
+    }
+
+    #[test]
+    fn test_exhausted_queue_is_unhandled() {
+        let mock = OpAutoMock::new();
+        mock.read_line.returns_iter(["Alice"]);
+        match greet_twice().try_run_with(mock.clone()) {
+            Err(EffectError::Unhandled(UnhandledOp {
+                op: Op::Console(Console::Print(_)),
+                ..
+            })) => {}
+            other => panic!("expected an unhandled print, got {other:?}"),
+        }
+        assert_eq!(mock.unmatched().len(), 1);
+    }
+
+    mod expectations {
+        use super::*;
+        use algae::mock::MockHandler;
+
+        fn greet_mock(name: &str) -> MockHandler<Op> {
+            MockHandler::builder()
+                .expect(Op::Console(Console::ReadLine))
+                .returns(name.to_string())
+                .times(2)
+                .expect_where("a greeting", |op| {
+                    matches!(op, Op::Console(Console::Print(line)) if line.starts_with("Hello"))
+                })
+                .any_times()
+                .build()
+        }
+
+        #[test]
+        fn test_expected_calls_are_answered_and_verified() {
+            let mock = greet_mock("Alice");
+            greet_twice().handle(mock.clone()).run_or_panic();
+            mock.verify();
+        }
+
+        #[test]
+        #[should_panic(expected = "unexpected call to mock: Console(Print(\"Hello, Alice!\"))")]
+        fn test_extra_calls_fail() {
+            let mock = MockHandler::builder()
+                .expect(Op::Console(Console::ReadLine))
+                .returns("Alice".to_string())
+                .any_times()
+                .expect(Op::Console(Console::Print("Hello, Alice!".into())))
+                .build();
+            greet_twice().handle(mock).run_or_panic();
+        }
+
+        #[test]
+        #[should_panic(
+            expected = "expected Math(Add((1, 2))) 1 time(s), but it was performed 0 time(s)"
+        )]
+        fn test_unmet_expectations_fail_on_drop() {
+            let mock = MockHandler::builder()
+                .expect(Op::Math(Math::Add((1, 2))))
+                .returns(3)
+                .build();
+            drop(mock);
+        }
+
+        #[test]
+        fn test_aborts_and_declines_unexpected_calls() {
+            #[effectful]
+            fn read_and_log() -> Result<String, String> {
+                let _: () = perform!(Log::Event(LogEvent::info("reading")));
+                perform!(Files::Read("a.txt".into()))
+            }
+
+            let mock = MockHandler::builder()
+                .expect(Op::Files(Files::Read("a.txt".into())))
+                .aborts("disk on fire")
+                .decline_unexpected()
+                .build();
+            let log = MemoryLog::new();
+            let err = read_and_log()
+                .begin_chain()
+                .handle(mock)
+                .handle(log.clone())
+                .run()
+                .unwrap_err();
+            let EffectError::Aborted(abort) = err else {
+                panic!("expected an abort, got {err:?}");
+            };
+            assert_eq!(abort.downcast::<&str>().unwrap(), "disk on fire");
+            assert_eq!(log.events().len(), 1);
+        }
+    }
+
+    mod colliding {
+        use crate as algae;
+        use algae::prelude::*;
+
+        effect! {
+            root StoreOp;
+            automock;
+            Cache::Get (String) -> Option<String>;
+            Db::Get (String) -> Option<String>;
+        }
+
+        #[effectful(root = StoreOp)]
+        fn lookup() -> Option<String> {
+            let cached: Option<String> = perform!(Cache::Get("k".into()));
+            match cached {
+                Some(value) => Some(value),
+                None => perform!(Db::Get("k".into())),
+            }
+        }
+
+        #[test]
+        fn test_colliding_names_are_prefixed_with_the_family() {
+            let mock = StoreOpAutoMock::new();
+            mock.cache_get.returns(None);
+            mock.db_get.returns(Some("v".to_string()));
+            assert_eq!(lookup().try_run_with(mock).unwrap(), Some("v".to_string()));
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Experimental multi-shot effects.
+//!
+//! Ordinary [`Effectful`](crate::Effectful) computations are one-shot: every
+//! operation is answered exactly once and the computation moves on. Handlers
+//! for nondeterminism, probabilistic programming or backtracking need to
+//! answer the *same* operation several times and see where each answer leads.
+//! A [`MultiShotEffectful`] makes that possible by handing its handler the
+//! rest of the computation as a [`Continuation`] that can be cloned and
+//! resumed any number of times.
+//!
+//! Multi-shot computations are a separate, opt-in type so that the one-shot
+//! default stays as simple as it is. They are written with
+//! `#[effectful(multishot)]` and require the nightly `coroutine_clone`
+//! feature, because resuming a continuation twice means cloning the
+//! suspended coroutine. Everything the computation keeps alive across a
+//! `perform!` (local variables, the operation type) must therefore be `Clone`.
+//!
+//! # Examples
+//!
+//! Collecting the results of every combination of coin flips:
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, coroutine_trait, coroutine_clone, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::multishot::{Continuation, MultiShotHandler};
+//!
+//! effect! {
+//!     Choice::Flip -> bool;
+//! }
+//!
+//! #[effectful(multishot)]
+//! fn two_coins() -> (bool, bool) {
+//!     let a: bool = perform!(Choice::Flip);
+//!     let b: bool = perform!(Choice::Flip);
+//!     (a, b)
+//! }
+//!
+//! struct AllOutcomes;
+//!
+//! impl<R> MultiShotHandler<R, Op> for AllOutcomes {
+//!     type Output = Vec<R>;
+//!
+//!     fn handle(&mut self, op: &Op, k: Continuation<R, Op>) -> Vec<R> {
+//!         match op {
+//!             Op::Choice(Choice::Flip) => {
+//!                 let mut outcomes = k.clone().resume(self, true);
+//!                 outcomes.extend(k.resume(self, false));
+//!                 outcomes
+//!             }
+//!         }
+//!     }
+//!
+//!     fn finish(&mut self, result: R) -> Vec<R> {
+//!         vec![result]
+//!     }
+//! }
+//!
+//! assert_eq!(two_coins().run_with(AllOutcomes).len(), 4);
+//! ```
+//!
+//! Handlers call back into the computation from inside `handle`, so every
+//! operation adds stack frames until its continuation finishes. Very long
+//! multi-shot computations can exhaust the stack.
+
+use crate::{DispatchGuard, Effect, Reply};
+use alloc::boxed::Box;
+use core::{
+    any::Any,
+    ops::{Coroutine, CoroutineState},
+    pin::Pin,
+};
+
+/// A handler that receives the rest of the computation with each operation.
+///
+/// # Type Parameters
+///
+/// * `R` - The return type of the computation
+/// * `Op` - The type of operations this handler processes
+pub trait MultiShotHandler<R, Op> {
+    /// What running a computation with this handler produces.
+    type Output;
+
+    /// Handles `op`.
+    ///
+    /// Resume `k` (or clones of it) with replies to continue the computation;
+    /// each resumption returns the output of running the rest of it. Not
+    /// resuming at all ends this branch of the computation with whatever the
+    /// handler returns.
+    fn handle(&mut self, op: &Op, k: Continuation<R, Op>) -> Self::Output;
+
+    /// Turns the result of a finished branch into an output.
+    fn finish(&mut self, result: R) -> Self::Output;
+}
+
+/// A suspended coroutine that can be cloned.
+trait CloneCoroutine<R, Op: 'static>:
+    Coroutine<Option<Reply>, Return = R, Yield = Effect<Op>> + Send
+{
+    fn clone_pinned(&self) -> Pin<Box<dyn CloneCoroutine<R, Op>>>;
+}
+
+impl<G, R, Op: 'static> CloneCoroutine<R, Op> for G
+where
+    G: Coroutine<Option<Reply>, Return = R, Yield = Effect<Op>> + Clone + Send + 'static,
+{
+    fn clone_pinned(&self) -> Pin<Box<dyn CloneCoroutine<R, Op>>> {
+        Box::pin(self.clone())
+    }
+}
+
+/// The rest of a multi-shot computation, waiting for the reply to an operation.
+///
+/// Clone it to resume the computation more than once.
+pub struct Continuation<R, Op: 'static> {
+    gen: Pin<Box<dyn CloneCoroutine<R, Op>>>,
+}
+
+impl<R, Op: 'static> Clone for Continuation<R, Op> {
+    fn clone(&self) -> Self {
+        Self {
+            gen: (*self.gen).clone_pinned(),
+        }
+    }
+}
+
+impl<R, Op: 'static> Continuation<R, Op> {
+    /// Continues the computation with `reply` as the result of the pending
+    /// operation and runs it to the end with `h`.
+    pub fn resume<H, T>(self, h: &mut H, reply: T) -> H::Output
+    where
+        H: MultiShotHandler<R, Op>,
+        T: Any + Send,
+    {
+        self.resume_boxed(h, Box::new(reply))
+    }
+
+    /// Like [`resume`](Self::resume), with an already boxed reply.
+    pub fn resume_boxed<H>(self, h: &mut H, reply: Box<dyn Any + Send>) -> H::Output
+    where
+        H: MultiShotHandler<R, Op>,
+    {
+        self.drive(h, Some(Reply::from_boxed(reply)))
+    }
+
+    fn drive<H>(mut self, h: &mut H, resume_arg: Option<Reply>) -> H::Output
+    where
+        H: MultiShotHandler<R, Op>,
+    {
+        match self.gen.as_mut().resume(resume_arg) {
+            CoroutineState::Complete(result) => h.finish(result),
+            CoroutineState::Yielded(eff) => {
+                let _dispatching = DispatchGuard::enter(eff.location(), eff.scope());
+                h.handle(&eff.op, self)
+            }
+        }
+    }
+}
+
+/// An effectful computation whose continuations can be resumed more than once.
+///
+/// Created by `#[effectful(multishot)]` functions and run with a
+/// [`MultiShotHandler`].
+///
+/// # Type Parameters
+///
+/// * `R` - The return type of the computation
+/// * `Op` - The type of effects that can be performed
+pub struct MultiShotEffectful<R, Op: 'static> {
+    start: Continuation<R, Op>,
+}
+
+impl<R, Op: 'static> MultiShotEffectful<R, Op> {
+    /// Creates a multi-shot computation from a clonable coroutine.
+    ///
+    /// The coroutine must not keep its first resume argument alive;
+    /// `#[effectful(multishot)]` drops it immediately.
+    pub fn new<G>(g: G) -> Self
+    where
+        G: Coroutine<Option<Reply>, Return = R, Yield = Effect<Op>> + Clone + Send + 'static,
+    {
+        Self {
+            start: Continuation { gen: Box::pin(g) },
+        }
+    }
+
+    /// Runs the computation with `h` and returns the handler's output.
+    pub fn run_with<H>(self, mut h: H) -> H::Output
+    where
+        H: MultiShotHandler<R, Op>,
+    {
+        self.start.drive(&mut h, None)
+    }
+}
+
+impl<R, Op: 'static> Clone for MultiShotEffectful<R, Op> {
+    fn clone(&self) -> Self {
+        Self {
+            start: self.start.clone(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        Choice::Flip -> bool;
+        Choice::Pick (Vec<u32>) -> u32;
+        Choice::Fail -> ();
+    }
+
+    /// Explores every branch and collects the results.
+    struct AllOutcomes {
+        resumptions: usize,
+    }
+
+    impl<R> MultiShotHandler<R, Op> for AllOutcomes {
+        type Output = Vec<R>;
+
+        fn handle(&mut self, op: &Op, k: Continuation<R, Op>) -> Vec<R> {
+            match op {
+                Op::Choice(Choice::Flip) => {
+                    self.resumptions += 2;
+                    let mut outcomes = k.clone().resume(self, true);
+                    outcomes.extend(k.resume(self, false));
+                    outcomes
+                }
+                Op::Choice(Choice::Pick(options)) => options
+                    .iter()
+                    .flat_map(|&option| {
+                        self.resumptions += 1;
+                        k.clone().resume(self, option)
+                    })
+                    .collect(),
+                Op::Choice(Choice::Fail) => Vec::new(),
+            }
+        }
+
+        fn finish(&mut self, result: R) -> Vec<R> {
+            vec![result]
+        }
+    }
+
+    #[effectful(multishot)]
+    fn two_coins() -> (bool, bool) {
+        let a: bool = perform!(Choice::Flip);
+        let b: bool = perform!(Choice::Flip);
+        (a, b)
+    }
+
+    #[effectful(multishot)]
+    fn pythagorean(limit: u32) -> (u32, u32, u32) {
+        let range: Vec<u32> = (1..=limit).collect();
+        let a: u32 = perform!(Choice::Pick(range.clone()));
+        let b: u32 = perform!(Choice::Pick(range.clone()));
+        let c: u32 = perform!(Choice::Pick(range));
+        if a > b || a * a + b * b != c * c {
+            let _: () = perform!(Choice::Fail);
+        }
+        (a, b, c)
+    }
+
+    #[test]
+    fn test_resumes_each_branch() {
+        let mut handler = AllOutcomes { resumptions: 0 };
+        let outcomes = two_coins().start.drive(&mut handler, None);
+        assert_eq!(
+            outcomes,
+            [(true, true), (true, false), (false, true), (false, false)]
+        );
+        assert_eq!(handler.resumptions, 6);
+    }
+
+    #[test]
+    fn test_search_with_failure() {
+        let triples = pythagorean(13).run_with(AllOutcomes { resumptions: 0 });
+        assert_eq!(triples, [(3, 4, 5), (5, 12, 13), (6, 8, 10)]);
+    }
+
+    #[test]
+    fn test_not_resuming_ends_the_branch() {
+        struct FirstFlipOnly;
+        impl MultiShotHandler<(bool, bool), Op> for FirstFlipOnly {
+            type Output = Option<(bool, bool)>;
+
+            fn handle(&mut self, _: &Op, _: Continuation<(bool, bool), Op>) -> Self::Output {
+                None
+            }
+
+            fn finish(&mut self, result: (bool, bool)) -> Self::Output {
+                Some(result)
+            }
+        }
+
+        assert_eq!(two_coins().run_with(FirstFlipOnly), None);
+    }
+
+    #[test]
+    fn test_computation_is_reusable() {
+        let computation = two_coins();
+        let again = computation.clone();
+        assert_eq!(
+            computation.run_with(AllOutcomes { resumptions: 0 }).len(),
+            4
+        );
+        assert_eq!(again.run_with(AllOutcomes { resumptions: 0 }).len(), 4);
+    }
+}
@@ -0,0 +1,452 @@
+//! Nondeterminism by replay.
+//!
+//! One-shot computations cannot answer an operation twice, so a handler for a
+//! choice operation can only ever follow one branch. A [`ChoiceExplorer`]
+//! gets around that without continuations: it runs the computation once per
+//! branch, each time from the start, replaying the choices that lead to the
+//! branch and then taking the next untried alternative. The results of every
+//! branch are collected in depth-first order.
+//!
+//! Replaying only works if the computation is deterministic up to its
+//! choices: the same replies must lead to the same operations. Operations that
+//! are not choices are handled by an ordinary handler and are therefore
+//! performed again on every replay. Handlers whose state those repeats would
+//! disturb (counters, virtual clocks, in-memory logs) implement [`Snapshot`];
+//! [`ChoiceExplorer::explore_snapshotted`] rewinds them before each replay so
+//! that every branch sees the state the exploration started from.
+//!
+//! For computations that can be written with `#[effectful(multishot)]`,
+//! [`multishot`](crate::multishot) explores branches without re-running
+//! anything.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, coroutine_trait, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::nondet::{Alternatives, ChoiceExplorer};
+//!
+//! effect! {
+//!     Choice::Select (Vec<i32>) -> i32;
+//!     Console::Print (String) -> ();
+//! }
+//!
+//! #[effectful]
+//! fn pair() -> (i32, i32) {
+//!     let a: i32 = perform!(Choice::Select(vec![1, 2]));
+//!     let b: i32 = perform!(Choice::Select(vec![10, 20]));
+//!     (a, b)
+//! }
+//!
+//! let mut explorer = ChoiceExplorer::new(|op: &Op| match op {
+//!     Op::Choice(Choice::Select(options)) => Some(Alternatives::of(options.clone())),
+//!     _ => None,
+//! });
+//! let results = explorer.explore(pair, &mut ConsoleHandler)?;
+//! assert_eq!(results, [(1, 10), (1, 20), (2, 10), (2, 20)]);
+//! ```
+
+use crate::{dispatch_effect, EffectError, Effectful, PartialHandler, Reply};
+use alloc::sync::Arc;
+use alloc::{boxed::Box, vec::Vec};
+use core::{any::Any, cell::Cell, ops::CoroutineState};
+
+/// A handler whose state can be saved and restored.
+///
+/// [`ChoiceExplorer::explore_snapshotted`] uses this to undo the effects of a
+/// branch before replaying the computation for the next one.
+pub trait Snapshot {
+    /// The saved state.
+    type State;
+
+    /// Saves the current state.
+    fn snapshot(&self) -> Self::State;
+
+    /// Returns to a previously saved state.
+    fn restore(&mut self, state: &Self::State);
+}
+
+/// The possible replies to one choice operation.
+///
+/// Clones share the same values. Two `Alternatives` are equal when one is a
+/// clone of the other.
+#[derive(Clone)]
+pub struct Alternatives {
+    values: Arc<dyn Any + Send + Sync>,
+    len: usize,
+    pick: fn(&(dyn Any + Send + Sync), usize) -> Box<dyn Any + Send>,
+}
+
+impl Alternatives {
+    /// Each of `values` is a possible reply, tried in order.
+    pub fn of<T>(values: Vec<T>) -> Self
+    where
+        T: Any + Send + Sync + Clone,
+    {
+        fn pick<T: Any + Send + Sync + Clone>(
+            values: &(dyn Any + Send + Sync),
+            index: usize,
+        ) -> Box<dyn Any + Send> {
+            let values = (values as &dyn Any)
+                .downcast_ref::<Vec<T>>()
+                .expect("alternatives keep their own type");
+            Box::new(values[index].clone())
+        }
+
+        Self {
+            len: values.len(),
+            values: Arc::new(values),
+            pick: pick::<T>,
+        }
+    }
+
+    /// No possible reply: the branch fails and produces no result.
+    pub fn none() -> Self {
+        Self::of(Vec::<()>::new())
+    }
+
+    /// The number of alternatives.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no alternatives.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn pick(&self, index: usize) -> Box<dyn Any + Send> {
+        (self.pick)(&*self.values, index)
+    }
+}
+
+impl PartialEq for Alternatives {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.values, &other.values)
+    }
+}
+
+impl core::fmt::Debug for Alternatives {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Alternatives")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+/// One choice point on the path to the current branch.
+struct Decision {
+    taken: usize,
+    count: usize,
+}
+
+type ChoiceFn<Op> = Box<dyn FnMut(&Op) -> Option<Alternatives>>;
+
+/// Collects the results of every branch of a nondeterministic computation by
+/// replaying it once per branch.
+///
+/// # Type Parameters
+///
+/// * `Op` - The type of effects the explored computations perform
+pub struct ChoiceExplorer<Op> {
+    choices: ChoiceFn<Op>,
+}
+
+impl<Op: 'static> ChoiceExplorer<Op> {
+    /// Creates an explorer that treats an operation as a choice when
+    /// `choices` returns its alternatives.
+    ///
+    /// Operations for which `choices` returns `None` go to the handler passed
+    /// to [`explore`](Self::explore).
+    pub fn new<F>(choices: F) -> Self
+    where
+        F: FnMut(&Op) -> Option<Alternatives> + 'static,
+    {
+        Self {
+            choices: Box::new(choices),
+        }
+    }
+
+    /// Runs `computation` once for every branch and returns the results of
+    /// the branches that finished, in depth-first order.
+    ///
+    /// `computation` must build a fresh run of the same computation each time
+    /// it is called. Non-choice operations are answered by `h`, which sees
+    /// the operations of every replay; use
+    /// [`explore_snapshotted`](Self::explore_snapshotted) if that would
+    /// change its replies.
+    ///
+    /// # Errors
+    ///
+    /// Stops at the first operation `h` declines or aborts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a replay offers a different number of alternatives than the
+    /// run it replays, i.e. the computation is not deterministic up to its
+    /// choices.
+    pub fn explore<R, C, H>(&mut self, computation: C, h: &mut H) -> Result<Vec<R>, EffectError<Op>>
+    where
+        C: FnMut() -> Effectful<R, Op>,
+        H: PartialHandler<Op>,
+    {
+        self.explore_with(computation, h, |_| {}, usize::MAX)
+    }
+
+    /// Like [`explore`](Self::explore), but restores `h` to its initial
+    /// state before every replay.
+    ///
+    /// Each branch therefore observes the handler as if it were the only one
+    /// that ran. When the exploration ends, `h` holds the state left by the
+    /// last branch.
+    pub fn explore_snapshotted<R, C, H>(
+        &mut self,
+        computation: C,
+        h: &mut H,
+    ) -> Result<Vec<R>, EffectError<Op>>
+    where
+        C: FnMut() -> Effectful<R, Op>,
+        H: PartialHandler<Op> + Snapshot,
+    {
+        let initial = h.snapshot();
+        self.explore_with(computation, h, |h| h.restore(&initial), usize::MAX)
+    }
+
+    /// Explores branches until `limit` of them have finished.
+    pub(crate) fn explore_with<R, C, H, B>(
+        &mut self,
+        mut computation: C,
+        h: &mut H,
+        mut before_replay: B,
+        limit: usize,
+    ) -> Result<Vec<R>, EffectError<Op>>
+    where
+        C: FnMut() -> Effectful<R, Op>,
+        H: PartialHandler<Op>,
+        B: FnMut(&mut H),
+    {
+        let mut path: Vec<Decision> = Vec::new();
+        let mut results = Vec::new();
+        let mut first = true;
+        loop {
+            if !first {
+                before_replay(h);
+            }
+            first = false;
+
+            if let Some(result) = self.run_branch(computation(), &mut path, h)? {
+                results.push(result);
+                if results.len() == limit {
+                    return Ok(results);
+                }
+            }
+
+            // Move on to the next untried alternative of the deepest choice.
+            while path.last().is_some_and(|d| d.taken + 1 == d.count) {
+                path.pop();
+            }
+            match path.last_mut() {
+                Some(decision) => decision.taken += 1,
+                None => return Ok(results),
+            }
+        }
+    }
+
+    /// Runs one branch: replays the choices on `path`, then takes the first
+    /// alternative of every new choice and records it on `path`.
+    ///
+    /// Returns `Ok(None)` when the branch fails on a choice without
+    /// alternatives.
+    fn run_branch<R, H>(
+        &mut self,
+        mut computation: Effectful<R, Op>,
+        path: &mut Vec<Decision>,
+        h: &mut H,
+    ) -> Result<Option<R>, EffectError<Op>>
+    where
+        H: PartialHandler<Op>,
+    {
+        let mut depth = 0;
+        let failed = Cell::new(false);
+        let mut dispatch = |op: &Op| match (self.choices)(op) {
+            Some(alternatives) if depth < path.len() => {
+                let decision = &path[depth];
+                assert_eq!(
+                    alternatives.len(),
+                    decision.count,
+                    "replaying choice {depth} offered a different number of alternatives; \
+                     the computation must be deterministic up to its choices"
+                );
+                depth += 1;
+                Some(alternatives.pick(decision.taken))
+            }
+            Some(alternatives) if alternatives.is_empty() => {
+                failed.set(true);
+                None
+            }
+            Some(alternatives) => {
+                path.push(Decision {
+                    taken: 0,
+                    count: alternatives.len(),
+                });
+                depth += 1;
+                Some(alternatives.pick(0))
+            }
+            None => h.maybe_handle(op),
+        };
+
+        let mut resume_arg: Option<Reply> = None;
+        loop {
+            match computation.step(resume_arg) {
+                CoroutineState::Complete(r) => return Ok(Some(r)),
+                CoroutineState::Yielded(eff) => match dispatch_effect(eff, &mut dispatch) {
+                    Ok(reply) => resume_arg = Some(reply),
+                    Err(EffectError::Unhandled(_)) if failed.get() => return Ok(None),
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+impl<Op> core::fmt::Debug for ChoiceExplorer<Op> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ChoiceExplorer").finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        Choice::Select (Vec<i32>) -> i32;
+        Choice::Empty -> Option<i32>;
+        Counter::Next -> u32;
+    }
+
+    fn explorer() -> ChoiceExplorer<Op> {
+        ChoiceExplorer::new(|op: &Op| match op {
+            Op::Choice(Choice::Select(options)) => Some(Alternatives::of(options.clone())),
+            Op::Choice(Choice::Empty) => Some(Alternatives::of(vec![None, Some(0)])),
+            _ => None,
+        })
+    }
+
+    #[derive(Default)]
+    struct Tickets {
+        next: u32,
+    }
+
+    impl PartialHandler<Op> for Tickets {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Counter(Counter::Next) => {
+                    self.next += 1;
+                    Some(Box::new(self.next))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    impl Snapshot for Tickets {
+        type State = u32;
+
+        fn snapshot(&self) -> u32 {
+            self.next
+        }
+
+        fn restore(&mut self, state: &u32) {
+            self.next = *state;
+        }
+    }
+
+    #[effectful]
+    fn pairs() -> (i32, i32) {
+        let a: i32 = perform!(Choice::Select(vec![1, 2]));
+        let b: i32 = perform!(Choice::Select(vec![10, 20, 30]));
+        (a, b)
+    }
+
+    #[effectful]
+    fn numbered_choice() -> (u32, i32) {
+        let id: u32 = perform!(Counter::Next);
+        let n: i32 = perform!(Choice::Select(vec![1, 2, 3]));
+        (id, n)
+    }
+
+    #[effectful]
+    fn sums_to(target: i32) -> (i32, i32) {
+        let a: i32 = perform!(Choice::Select(vec![1, 2, 3, 4]));
+        let b: i32 = perform!(Choice::Select(vec![1, 2, 3, 4]));
+        if a + b != target {
+            let _: i32 = perform!(Choice::Select(vec![]));
+        }
+        (a, b)
+    }
+
+    #[test]
+    fn test_collects_every_branch() {
+        let results = explorer().explore(pairs, &mut Tickets::default()).unwrap();
+        assert_eq!(
+            results,
+            [(1, 10), (1, 20), (1, 30), (2, 10), (2, 20), (2, 30)]
+        );
+    }
+
+    #[test]
+    fn test_reply_types_follow_the_operation() {
+        #[effectful]
+        fn maybe() -> Option<i32> {
+            perform!(Choice::Empty)
+        }
+
+        let results = explorer().explore(maybe, &mut Tickets::default()).unwrap();
+        assert_eq!(results, [None, Some(0)]);
+    }
+
+    #[test]
+    fn test_empty_alternatives_prune_the_branch() {
+        let results = explorer()
+            .explore(|| sums_to(5), &mut Tickets::default())
+            .unwrap();
+        assert_eq!(results, [(1, 4), (2, 3), (3, 2), (4, 1)]);
+    }
+
+    #[test]
+    fn test_replays_repeat_handler_effects() {
+        let mut counter = Tickets::default();
+        let results = explorer().explore(numbered_choice, &mut counter).unwrap();
+        assert_eq!(results, [(1, 1), (2, 2), (3, 3)]);
+        assert_eq!(counter.next, 3);
+    }
+
+    #[test]
+    fn test_snapshots_isolate_branches() {
+        let mut counter = Tickets::default();
+        let results = explorer()
+            .explore_snapshotted(numbered_choice, &mut counter)
+            .unwrap();
+        assert_eq!(results, [(1, 1), (1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn test_unhandled_ops_are_errors() {
+        struct Nothing;
+        impl PartialHandler<Op> for Nothing {
+            fn maybe_handle(&mut self, _: &Op) -> Option<Box<dyn Any + Send>> {
+                None
+            }
+        }
+
+        match explorer().explore(numbered_choice, &mut Nothing) {
+            Err(EffectError::Unhandled(UnhandledOp { op, .. })) => {
+                assert_eq!(op, Op::Counter(Counter::Next))
+            }
+            other => panic!("expected an unhandled op, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,873 @@
+//! Latency observability for effect handlers.
+//!
+//! [`LatencyLayer`] wraps a handler and times every dispatch. Timings are
+//! aggregated per operation into HDR-style [`LatencyHistogram`]s held by a
+//! shared [`LatencyStats`] handle, and dispatches slower than a threshold are
+//! captured, together with the location of the `perform!` that requested them,
+//! in a [`SlowEffectLog`].
+//!
+//! Both sinks are cheap, clonable handles: keep one clone before handing the
+//! layer to a computation and inspect it after the run.
+//!
+//! For metrics of your own, a [`RuntimeObserver`] attached with
+//! [`Handled::observe`] is told about every operation performed, how it was
+//! answered and how long that took, and when the computation completes, so
+//! that counters and histograms can be exported without touching the
+//! handlers.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, coroutine_trait, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::observe::{LatencyLayer, LatencyStats, SlowEffectLog};
+//! use std::time::Duration;
+//!
+//! let stats = LatencyStats::new();
+//! let slow = SlowEffectLog::new(Duration::from_millis(50));
+//!
+//! let layer = LatencyLayer::new(ProductionHandler)
+//!     .record_to(&stats)
+//!     .log_slow(&slow);
+//! let _ = checkout().try_run_with(layer);
+//!
+//! let get = stats.histogram("Http::Get").unwrap();
+//! println!("Http::Get p99 = {:?}", get.value_at_quantile(0.99));
+//! for entry in slow.entries() {
+//!     println!("{} took {:?} at {}", entry.key, entry.elapsed, entry.location.unwrap());
+//! }
+//! ```
+
+pub use crate::redact::op_key;
+
+use crate::{
+    perform_location, redact::redacted, Abort, Effectful, Handled, Handler, OpMeta, PartialHandler,
+    Reply,
+};
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::CoroutineState,
+    panic::Location,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Number of bits of precision kept for every recorded value.
+///
+/// Values below `2^SUB_BUCKET_BITS` nanoseconds are recorded exactly; larger
+/// values keep their top `SUB_BUCKET_BITS` bits, bounding the relative error
+/// of any reported value to under 1%.
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKET_COUNT: u64 = 1 << SUB_BUCKET_BITS;
+const HALF_SUB_BUCKET_COUNT: u64 = SUB_BUCKET_COUNT / 2;
+
+/// A log-linear latency histogram in the style of HdrHistogram.
+///
+/// Buckets double in width with every power of two while each power of two is
+/// split into a fixed number of linear sub-buckets, so memory stays small while
+/// every recorded value keeps a bounded relative precision. Values are stored
+/// in nanoseconds.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum_nanos: u128,
+    min_nanos: u64,
+    max_nanos: u64,
+}
+
+impl LatencyHistogram {
+    /// Creates an empty histogram.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a single duration.
+    pub fn record(&mut self, value: Duration) {
+        self.record_nanos(u64::try_from(value.as_nanos()).unwrap_or(u64::MAX));
+    }
+
+    /// Records a single value expressed in nanoseconds.
+    pub fn record_nanos(&mut self, nanos: u64) {
+        let index = bucket_index(nanos);
+        if self.counts.len() <= index {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.min_nanos = if self.total == 0 {
+            nanos
+        } else {
+            self.min_nanos.min(nanos)
+        };
+        self.max_nanos = self.max_nanos.max(nanos);
+        self.total += 1;
+        self.sum_nanos += u128::from(nanos);
+    }
+
+    /// Number of recorded values.
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Smallest recorded value (exact), or zero if empty.
+    pub fn min(&self) -> Duration {
+        Duration::from_nanos(self.min_nanos)
+    }
+
+    /// Largest recorded value (exact), or zero if empty.
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max_nanos)
+    }
+
+    /// Arithmetic mean of the recorded values (exact), or zero if empty.
+    pub fn mean(&self) -> Duration {
+        match self.total {
+            0 => Duration::ZERO,
+            n => Duration::from_nanos((self.sum_nanos / u128::from(n)) as u64),
+        }
+    }
+
+    /// The value below which `quantile` of the recorded values fall.
+    ///
+    /// `quantile` is clamped to `0.0..=1.0`; `value_at_quantile(0.99)` is the
+    /// p99 latency. The result is the highest value equivalent to the bucket
+    /// holding the quantile, capped at the largest recorded value.
+    pub fn value_at_quantile(&self, quantile: f64) -> Duration {
+        if self.total == 0 {
+            return Duration::ZERO;
+        }
+        let quantile = quantile.clamp(0.0, 1.0);
+        let rank = ((quantile * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let highest = bucket_highest_value(index).min(self.max_nanos);
+                return Duration::from_nanos(highest.max(self.min_nanos));
+            }
+        }
+        self.max()
+    }
+
+    /// Adds every value recorded in `other` to this histogram.
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        if other.total == 0 {
+            return;
+        }
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (mine, theirs) in self.counts.iter_mut().zip(&other.counts) {
+            *mine += theirs;
+        }
+        self.min_nanos = if self.total == 0 {
+            other.min_nanos
+        } else {
+            self.min_nanos.min(other.min_nanos)
+        };
+        self.max_nanos = self.max_nanos.max(other.max_nanos);
+        self.total += other.total;
+        self.sum_nanos += other.sum_nanos;
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.count())
+            .field("min", &self.min())
+            .field("p50", &self.value_at_quantile(0.5))
+            .field("p99", &self.value_at_quantile(0.99))
+            .field("max", &self.max())
+            .finish()
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKET_COUNT {
+        return value as usize;
+    }
+    let magnitude = 63 - value.leading_zeros();
+    let shift = magnitude - (SUB_BUCKET_BITS - 1);
+    let sub_bucket = (value >> shift) - HALF_SUB_BUCKET_COUNT;
+    let group = u64::from(magnitude - SUB_BUCKET_BITS);
+    (SUB_BUCKET_COUNT + group * HALF_SUB_BUCKET_COUNT + sub_bucket) as usize
+}
+
+fn bucket_highest_value(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKET_COUNT {
+        return index;
+    }
+    let group = (index - SUB_BUCKET_COUNT) / HALF_SUB_BUCKET_COUNT;
+    let sub_bucket = (index - SUB_BUCKET_COUNT) % HALF_SUB_BUCKET_COUNT + HALF_SUB_BUCKET_COUNT;
+    let shift = group as u32 + 1;
+    let lowest = sub_bucket << shift;
+    lowest.saturating_add((1 << shift) - 1)
+}
+
+/// Shared, per-operation latency histograms.
+///
+/// Cloning a `LatencyStats` yields another handle to the same histograms.
+#[derive(Clone, Default)]
+pub struct LatencyStats {
+    histograms: Arc<Mutex<HashMap<String, LatencyHistogram>>>,
+}
+
+impl LatencyStats {
+    /// Creates an empty set of histograms.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `elapsed` for the operation identified by `key`.
+    pub fn record(&self, key: &str, elapsed: Duration) {
+        let mut histograms = self.histograms.lock().unwrap();
+        match histograms.get_mut(key) {
+            Some(histogram) => histogram.record(elapsed),
+            None => {
+                let mut histogram = LatencyHistogram::new();
+                histogram.record(elapsed);
+                histograms.insert(key.to_string(), histogram);
+            }
+        }
+    }
+
+    /// A copy of the histogram for `key`, if that operation was observed.
+    pub fn histogram(&self, key: &str) -> Option<LatencyHistogram> {
+        self.histograms.lock().unwrap().get(key).cloned()
+    }
+
+    /// A copy of every histogram, ordered by operation key.
+    pub fn snapshot(&self) -> BTreeMap<String, LatencyHistogram> {
+        self.histograms
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, histogram)| (key.clone(), histogram.clone()))
+            .collect()
+    }
+}
+
+impl fmt::Debug for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.snapshot()).finish()
+    }
+}
+
+/// An operation whose dispatch took longer than the [`SlowEffectLog`] threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowEffect {
+    /// Operation key, e.g. `Http::Get`
+    pub key: String,
+    /// `Debug` rendering of the operation
+    pub op: String,
+    /// How long the handler took
+    pub elapsed: Duration,
+    /// Where the operation was performed, if known
+    pub location: Option<&'static Location<'static>>,
+}
+
+#[derive(Debug)]
+struct SlowLogInner {
+    threshold: Duration,
+    capacity: usize,
+    entries: Vec<SlowEffect>,
+    dropped: u64,
+}
+
+/// Shared log of dispatches that exceeded a latency threshold.
+///
+/// The log keeps at most [`SlowEffectLog::DEFAULT_CAPACITY`] entries unless
+/// configured otherwise; later entries are counted but not stored.
+#[derive(Debug, Clone)]
+pub struct SlowEffectLog {
+    inner: Arc<Mutex<SlowLogInner>>,
+}
+
+impl SlowEffectLog {
+    /// Number of entries kept by [`SlowEffectLog::new`].
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    /// Creates a log that captures dispatches slower than `threshold`.
+    pub fn new(threshold: Duration) -> Self {
+        Self::with_capacity(threshold, Self::DEFAULT_CAPACITY)
+    }
+
+    /// Creates a log that keeps at most `capacity` entries.
+    pub fn with_capacity(threshold: Duration, capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SlowLogInner {
+                threshold,
+                capacity,
+                entries: Vec::new(),
+                dropped: 0,
+            })),
+        }
+    }
+
+    /// The configured threshold.
+    pub fn threshold(&self) -> Duration {
+        self.inner.lock().unwrap().threshold
+    }
+
+    /// A copy of the captured entries, in the order they happened.
+    pub fn entries(&self) -> Vec<SlowEffect> {
+        self.inner.lock().unwrap().entries.clone()
+    }
+
+    /// Number of slow dispatches that did not fit in the log.
+    pub fn dropped(&self) -> u64 {
+        self.inner.lock().unwrap().dropped
+    }
+
+    /// Removes all captured entries.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.dropped = 0;
+    }
+
+    fn is_slow(&self, elapsed: Duration) -> bool {
+        elapsed > self.inner.lock().unwrap().threshold
+    }
+
+    fn push(&self, entry: SlowEffect) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.entries.len() < inner.capacity {
+            inner.entries.push(entry);
+        } else {
+            inner.dropped += 1;
+        }
+    }
+}
+
+type KeyFn<Op> = Box<dyn Fn(&Op) -> String + Send>;
+
+/// A handler layer that measures how long the inner handler takes per operation.
+///
+/// Operations are grouped by key, [`op_key`] by default. Use
+/// [`LatencyLayer::key_by`] to group differently (for example per host).
+///
+/// # Type Parameters
+///
+/// * `Op` - The operation type being timed
+/// * `H` - The inner partial handler
+pub struct LatencyLayer<Op, H> {
+    inner: H,
+    key: KeyFn<Op>,
+    stats: Option<LatencyStats>,
+    slow: Option<SlowEffectLog>,
+}
+
+impl<Op, H> LatencyLayer<Op, H> {
+    /// Wraps `inner`, keying operations with [`op_key`].
+    pub fn new(inner: H) -> Self
+    where
+        Op: fmt::Debug,
+    {
+        Self {
+            inner,
+            key: Box::new(|op| op_key(op)),
+            stats: None,
+            slow: None,
+        }
+    }
+
+    /// Groups operations by the key returned from `key` instead of [`op_key`].
+    pub fn key_by<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&Op) -> String + Send + 'static,
+    {
+        self.key = Box::new(key);
+        self
+    }
+
+    /// Records every dispatch into `stats`.
+    pub fn record_to(mut self, stats: &LatencyStats) -> Self {
+        self.stats = Some(stats.clone());
+        self
+    }
+
+    /// Captures dispatches slower than the log's threshold into `slow`.
+    pub fn log_slow(mut self, slow: &SlowEffectLog) -> Self {
+        self.slow = Some(slow.clone());
+        self
+    }
+}
+
+impl<Op, H> PartialHandler<Op> for LatencyLayer<Op, H>
+where
+    Op: OpMeta + fmt::Debug,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let started = Instant::now();
+        let reply = self.inner.maybe_handle(op);
+        let elapsed = started.elapsed();
+
+        if self.stats.is_none() && self.slow.is_none() {
+            return reply;
+        }
+        let key = (self.key)(op);
+        if let Some(stats) = &self.stats {
+            stats.record(&key, elapsed);
+        }
+        if let Some(slow) = self.slow.as_ref().filter(|slow| slow.is_slow(elapsed)) {
+            slow.push(SlowEffect {
+                key,
+                op: redacted(op),
+                elapsed,
+                location: perform_location(),
+            });
+        }
+        reply
+    }
+}
+
+/// Callbacks on the progress of a computation, attached with
+/// [`Handled::observe`].
+///
+/// Every callback does nothing by default. Observers are shared with the
+/// handler they observe, so they take `&self`; counters and histograms
+/// behind atomics or a lock fit naturally.
+pub trait RuntimeObserver<Op>: Send + Sync {
+    /// `op` was performed and is about to be dispatched to the handler.
+    fn on_perform(&self, op: &Op) {
+        let _ = op;
+    }
+
+    /// The handler replied to `op` after `elapsed`.
+    fn on_reply(&self, op: &Op, elapsed: Duration) {
+        let _ = (op, elapsed);
+    }
+
+    /// The handler answered `op` with `abort` after `elapsed`, which ends
+    /// the run.
+    fn on_abort(&self, op: &Op, abort: &Abort, elapsed: Duration) {
+        let _ = (op, abort, elapsed);
+    }
+
+    /// The handler declined `op`, which ends the run.
+    fn on_unhandled(&self, op: &Op) {
+        let _ = op;
+    }
+
+    /// The computation returned, `elapsed` after it was first resumed.
+    fn on_complete(&self, elapsed: Duration) {
+        let _ = elapsed;
+    }
+}
+
+impl<Op, O: RuntimeObserver<Op> + ?Sized> RuntimeObserver<Op> for Arc<O> {
+    fn on_perform(&self, op: &Op) {
+        (**self).on_perform(op)
+    }
+
+    fn on_reply(&self, op: &Op, elapsed: Duration) {
+        (**self).on_reply(op, elapsed)
+    }
+
+    fn on_abort(&self, op: &Op, abort: &Abort, elapsed: Duration) {
+        (**self).on_abort(op, abort, elapsed)
+    }
+
+    fn on_unhandled(&self, op: &Op) {
+        (**self).on_unhandled(op)
+    }
+
+    fn on_complete(&self, elapsed: Duration) {
+        (**self).on_complete(elapsed)
+    }
+}
+
+/// A handler reporting to a [`RuntimeObserver`] how `H` answers each
+/// operation; made by [`Handled::observe`].
+pub struct Observed<O, H> {
+    inner: H,
+    observer: Arc<O>,
+}
+
+impl<O, H> Observed<O, H> {
+    /// The observed handler.
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+
+    fn answer<Op>(
+        &self,
+        op: &Op,
+        started: Instant,
+        reply: Option<Box<dyn Any + Send>>,
+    ) -> Option<Box<dyn Any + Send>>
+    where
+        O: RuntimeObserver<Op>,
+    {
+        let elapsed = started.elapsed();
+        match &reply {
+            None => self.observer.on_unhandled(op),
+            Some(reply) => match reply.downcast_ref::<Abort>() {
+                Some(abort) => self.observer.on_abort(op, abort, elapsed),
+                None => self.observer.on_reply(op, elapsed),
+            },
+        }
+        reply
+    }
+}
+
+impl<O, H> fmt::Debug for Observed<O, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observed")
+            .field("handler", &std::any::type_name::<H>())
+            .field("observer", &std::any::type_name::<O>())
+            .finish()
+    }
+}
+
+impl<Op, O, H> PartialHandler<Op> for Observed<O, H>
+where
+    O: RuntimeObserver<Op>,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.observer.on_perform(op);
+        let started = Instant::now();
+        let reply = self.inner.maybe_handle(op);
+        self.answer(op, started, reply)
+    }
+}
+
+impl<Op, O, H> Handler<Op> for Observed<O, H>
+where
+    O: RuntimeObserver<Op>,
+    H: Handler<Op>,
+{
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        self.observer.on_perform(op);
+        let started = Instant::now();
+        let reply = Some(self.inner.handle(op));
+        self.answer(op, started, reply)
+            .expect("a total handler always replies")
+    }
+
+    fn try_reply(&mut self, op: &Op) -> Option<Reply> {
+        self.observer.on_perform(op);
+        let started = Instant::now();
+        let reply = self.inner.try_reply(op).map(Reply::into_boxed);
+        self.answer(op, started, reply).map(Reply::from_boxed)
+    }
+
+    fn handler_names(&self) -> Vec<&'static str> {
+        Handler::handler_names(&self.inner)
+    }
+}
+
+impl<R, Op, H> Handled<R, Op, H>
+where
+    R: Send + 'static,
+    Op: Send + 'static,
+{
+    /// Reports the progress of the run to `observer`: each operation the
+    /// handler is given and how it answers, and the completion of the
+    /// computation.
+    pub fn observe<O>(self, observer: O) -> Handled<R, Op, Observed<O, H>>
+    where
+        O: RuntimeObserver<Op> + 'static,
+    {
+        let observer = Arc::new(observer);
+        Handled {
+            eff: completing(self.eff, observer.clone()),
+            h: Observed {
+                inner: self.h,
+                observer,
+            },
+        }
+    }
+}
+
+/// Runs `computation`, telling `observer` when it returns.
+fn completing<R, Op, O>(computation: Effectful<R, Op>, observer: Arc<O>) -> Effectful<R, Op>
+where
+    R: Send + 'static,
+    Op: Send + 'static,
+    O: RuntimeObserver<Op> + 'static,
+{
+    let mut inner = computation;
+    Effectful::new(
+        #[coroutine]
+        move |mut reply: Option<Reply>| {
+            let started = Instant::now();
+            loop {
+                match inner.step(reply.take()) {
+                    CoroutineState::Yielded(eff) => reply = yield eff,
+                    CoroutineState::Complete(result) => {
+                        observer.on_complete(started.elapsed());
+                        return result;
+                    }
+                }
+            }
+        },
+    )
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::thread;
+
+    effect! {
+        Http::Get (String) -> String;
+        Db::Query (u64) -> u64;
+        Cache::Flush -> ();
+    }
+
+    /// Sleeps for the number of milliseconds named by `Db::Query`.
+    struct SlowDbHandler;
+
+    impl PartialHandler<Op> for SlowDbHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Http(Http::Get(url)) => Some(Box::new(url.clone())),
+                Op::Db(Db::Query(millis)) => {
+                    thread::sleep(Duration::from_millis(*millis));
+                    Some(Box::new(*millis))
+                }
+                Op::Cache(Cache::Flush) => Some(Box::new(())),
+            }
+        }
+    }
+
+    #[effectful]
+    fn request() -> u32 {
+        let _: String = perform!(Http::Get("/a".to_string()));
+        let _: String = perform!(Http::Get("/b".to_string()));
+        let _: () = perform!(Cache::Flush);
+        let (_, slow_line): (u64, u32) = (perform!(Db::Query(30)), line!());
+        let _: u64 = perform!(Db::Query(0));
+        slow_line
+    }
+
+    #[test]
+    fn test_op_key() {
+        assert_eq!(op_key(&Op::Http(Http::Get("/x(y)".into()))), "Http::Get");
+        assert_eq!(op_key(&Op::Cache(Cache::Flush)), "Cache::Flush");
+        assert_eq!(op_key(&Http::Get("/".into())), "Get");
+        assert_eq!(op_key(&42), "42");
+    }
+
+    #[test]
+    fn test_histograms_and_slow_log() {
+        let stats = LatencyStats::new();
+        let slow = SlowEffectLog::new(Duration::from_millis(20));
+        let layer = LatencyLayer::new(SlowDbHandler)
+            .record_to(&stats)
+            .log_slow(&slow);
+
+        let slow_line = request().try_run_with(layer).unwrap();
+
+        let snapshot = stats.snapshot();
+        assert_eq!(
+            snapshot.keys().collect::<Vec<_>>(),
+            ["Cache::Flush", "Db::Query", "Http::Get"]
+        );
+        assert_eq!(snapshot["Http::Get"].count(), 2);
+        let db = stats.histogram("Db::Query").unwrap();
+        assert_eq!(db.count(), 2);
+        assert!(db.max() >= Duration::from_millis(30));
+        assert!(db.value_at_quantile(1.0) >= Duration::from_millis(30));
+
+        let entries = slow.entries();
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.key, "Db::Query");
+        assert_eq!(entry.op, "Db(Query(30))");
+        let location = entry.location.expect("perform location is recorded");
+        assert!(location.file().ends_with("observe.rs"));
+        assert_eq!(location.line(), slow_line);
+    }
+
+    #[test]
+    fn test_custom_keys_and_slow_log_capacity() {
+        let stats = LatencyStats::new();
+        let slow = SlowEffectLog::with_capacity(Duration::ZERO, 2);
+        let layer = LatencyLayer::new(SlowDbHandler)
+            .key_by(|op: &Op| match op {
+                Op::Http(Http::Get(url)) => format!("GET {url}"),
+                other => op_key(other),
+            })
+            .record_to(&stats)
+            .log_slow(&slow);
+
+        request().try_run_with(layer).unwrap();
+
+        assert!(stats.histogram("GET /a").is_some());
+        assert!(stats.histogram("Http::Get").is_none());
+        assert_eq!(slow.entries().len(), 2);
+        assert_eq!(slow.dropped(), 3);
+        slow.clear();
+        assert!(slow.entries().is_empty());
+    }
+
+    /// Records the callbacks it gets, as `callback op`.
+    #[derive(Default)]
+    struct Events(Mutex<Vec<String>>);
+
+    impl Events {
+        fn push(&self, event: String) {
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    impl RuntimeObserver<Op> for Events {
+        fn on_perform(&self, op: &Op) {
+            self.push(format!("perform {}", op_key(op)));
+        }
+
+        fn on_reply(&self, op: &Op, elapsed: Duration) {
+            if let Op::Db(Db::Query(millis)) = op {
+                assert!(elapsed >= Duration::from_millis(*millis));
+            }
+            self.push(format!("reply {}", op_key(op)));
+        }
+
+        fn on_abort(&self, op: &Op, abort: &Abort, _: Duration) {
+            self.push(format!("abort {} {abort}", op_key(op)));
+        }
+
+        fn on_unhandled(&self, op: &Op) {
+            self.push(format!("unhandled {}", op_key(op)));
+        }
+
+        fn on_complete(&self, _: Duration) {
+            self.push("complete".to_string());
+        }
+    }
+
+    #[test]
+    fn test_observers_see_every_operation_and_the_completion() {
+        let events = Arc::new(Events::default());
+        let (observed, h) = request()
+            .handle(SlowDbHandler)
+            .observe(events.clone())
+            .into_parts();
+        observed.try_run_with(h).unwrap();
+        assert_eq!(
+            events.take(),
+            [
+                "perform Http::Get",
+                "reply Http::Get",
+                "perform Http::Get",
+                "reply Http::Get",
+                "perform Cache::Flush",
+                "reply Cache::Flush",
+                "perform Db::Query",
+                "reply Db::Query",
+                "perform Db::Query",
+                "reply Db::Query",
+                "complete",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observers_see_declined_and_aborted_operations() {
+        /// Answers `Http::Get` only, aborting on `/b`.
+        struct HttpOnly;
+
+        impl PartialHandler<Op> for HttpOnly {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Http(Http::Get(url)) if url == "/b" => Some(Abort::boxed("offline")),
+                    Op::Http(Http::Get(url)) => Some(Box::new(url.clone())),
+                    _ => None,
+                }
+            }
+        }
+
+        #[effectful]
+        fn flush() -> String {
+            let page: String = perform!(Http::Get("/a".to_string()));
+            let _: () = perform!(Cache::Flush);
+            page
+        }
+
+        let events = Arc::new(Events::default());
+        let (observed, h) = flush()
+            .handle(HttpOnly)
+            .observe(events.clone())
+            .into_parts();
+        let result = observed.try_run_with(h);
+        assert!(matches!(result, Err(EffectError::Unhandled(_))));
+        assert_eq!(
+            events.take(),
+            [
+                "perform Http::Get",
+                "reply Http::Get",
+                "perform Cache::Flush",
+                "unhandled Cache::Flush",
+            ]
+        );
+
+        let (observed, h) = request()
+            .handle(HttpOnly)
+            .observe(events.clone())
+            .into_parts();
+        let result = observed.try_run_with(h);
+        assert!(matches!(result, Err(EffectError::Aborted(_))));
+        assert_eq!(
+            events.take(),
+            [
+                "perform Http::Get",
+                "reply Http::Get",
+                "perform Http::Get",
+                r#"abort Http::Get "offline""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_histogram_precision() {
+        let mut histogram = LatencyHistogram::new();
+        for nanos in 1..=10_000u64 {
+            histogram.record_nanos(nanos * 1_000);
+        }
+        assert_eq!(histogram.count(), 10_000);
+        assert_eq!(histogram.min(), Duration::from_micros(1));
+        assert_eq!(histogram.max(), Duration::from_millis(10));
+
+        for (quantile, expected_micros) in [(0.5, 5_000.0), (0.9, 9_000.0), (0.99, 9_900.0)] {
+            let actual = histogram.value_at_quantile(quantile).as_nanos() as f64 / 1_000.0;
+            let error = (actual - expected_micros).abs() / expected_micros;
+            assert!(error < 0.01, "q{quantile}: {actual} vs {expected_micros}");
+        }
+
+        let mut other = LatencyHistogram::new();
+        other.record(Duration::from_secs(1));
+        histogram.merge(&other);
+        assert_eq!(histogram.count(), 10_001);
+        assert_eq!(histogram.max(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_bucket_boundaries() {
+        for value in [0, 1, 127, 128, 129, 255, 256, 1 << 20, u64::MAX] {
+            let index = bucket_index(value);
+            assert!(bucket_highest_value(index) >= value, "value {value}");
+            if index > 0 {
+                assert!(bucket_highest_value(index - 1) < value, "value {value}");
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Running many computations in parallel on rayon (feature `rayon`).
+//!
+//! [`run_all`] runs a batch of independent computations on rayon's pool.
+//! Handlers usually hold state that cannot be shared between threads, so
+//! instead of a handler it takes a [`HandlerFactory`], such as a closure
+//! building one: each worker gets a fresh handler chain and reuses it for
+//! the computations it picks up.
+//!
+//! ```rust,ignore
+//! use algae::par;
+//!
+//! let reports = par::run_all(
+//!     files.into_iter().map(summarize).collect(),
+//!     || AppHandler::new(&config),
+//! );
+//! ```
+//!
+//! Call it inside [`rayon::ThreadPool::install`] to use a pool of your own.
+//!
+//! [`Effectful::run_on`] instead keeps one computation on the calling thread
+//! and moves its handlers onto a pool, for handlers that do expensive work,
+//! such as hashing or compression modeled as effects. The computation waits
+//! for each reply, and the operations of a `perform_all!` batch are answered
+//! in parallel:
+//!
+//! ```rust,ignore
+//! let pool = rayon::ThreadPoolBuilder::new().num_threads(8).build()?;
+//! let digests = hash_chunks(file).run_on(&pool, || Sha256Handler)?;
+//! ```
+
+use crate::{reply_each, Dispatch, EffectError, Effectful, HandlerFactory, PartialHandler, Reply};
+use rayon::{prelude::*, ThreadPool};
+
+/// Runs `computations` in parallel, each with a handler built by
+/// `factory`, and returns their outcomes in input order.
+///
+/// A handler is built at least once per worker that takes part and may
+/// serve several computations in turn, so it must not rely on starting
+/// fresh for every computation. A computation that performs an unhandled
+/// operation or is aborted fails without affecting the others.
+///
+/// Each computation runs as [`scoped`](Effectful::scoped) sub-computation of
+/// its position, so seeded handlers give it the same values whichever worker
+/// runs it.
+pub fn run_all<R, Op, F>(
+    computations: Vec<Effectful<R, Op>>,
+    factory: F,
+) -> Vec<Result<R, EffectError<Op>>>
+where
+    R: Send,
+    Op: Send,
+    F: HandlerFactory<Op> + Sync + Send,
+{
+    computations
+        .into_par_iter()
+        .enumerate()
+        .map_init(
+            || factory.build(),
+            |h, (i, c)| c.scoped(i as u64).drive(|op| h.maybe_handle(op)),
+        )
+        .collect()
+}
+
+impl<R, Op: Sync> Effectful<R, Op> {
+    /// Runs the computation to its end on this thread, calling the handlers
+    /// built by `factory` on `pool`, and fails like
+    /// [`try_run_with`](Effectful::try_run_with).
+    ///
+    /// Single operations are answered by one handler, built for the run. The
+    /// operations of a batch are spread over the pool's threads, each
+    /// answered by a handler of its thread's own, so a handler that keeps
+    /// state must share it, e.g. in an `Arc`. Batches are answered one
+    /// operation at a time this way, not through
+    /// [`maybe_handle_batch`](PartialHandler::maybe_handle_batch).
+    pub fn run_on<F>(self, pool: &ThreadPool, factory: F) -> Result<R, EffectError<Op>>
+    where
+        F: HandlerFactory<Op> + Sync,
+        F::Handler: Send,
+    {
+        let handler = factory.build();
+        self.drive_as(
+            core::any::type_name::<F::Handler>(),
+            OnPool {
+                pool,
+                factory,
+                handler,
+            },
+        )
+    }
+}
+
+/// Dispatches to handlers called on a thread pool.
+struct OnPool<'a, F: HandlerFactory<Op>, Op> {
+    pool: &'a ThreadPool,
+    factory: F,
+    /// Answers the operations performed on their own.
+    handler: F::Handler,
+}
+
+impl<Op, F> Dispatch<Op> for OnPool<'_, F, Op>
+where
+    Op: Sync,
+    F: HandlerFactory<Op> + Sync,
+    F::Handler: Send,
+{
+    fn reply(&mut self, op: &Op) -> Option<Reply> {
+        let handler = &mut self.handler;
+        self.pool.install(|| handler.maybe_reply(op))
+    }
+
+    fn reply_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        let factory = &self.factory;
+        let replies: Vec<Option<Reply>> = self.pool.install(|| {
+            ops.par_iter()
+                .map_init(|| factory.build(), |h, op| h.maybe_reply(op))
+                .collect()
+        });
+        let mut replies = replies.into_iter();
+        reply_each(ops, |_| replies.next().flatten())
+    }
+
+    fn declined_by(&self) -> Vec<&'static str> {
+        self.handler.handler_names()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::{
+        any::Any,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    effect! {
+        Work::Square (u64) -> u64;
+        Work::Fail -> ();
+    }
+
+    /// Squares numbers; declines `Work::Fail`.
+    struct Squarer;
+
+    impl PartialHandler<Op> for Squarer {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Work(Work::Square(n)) => Some(Box::new(n * n)),
+                Op::Work(Work::Fail) => None,
+            }
+        }
+    }
+
+    #[effectful]
+    fn sum_of_squares(n: u64) -> u64 {
+        let mut sum = 0;
+        for i in 1..=n {
+            let square: u64 = perform!(Work::Square(i));
+            sum += square;
+        }
+        sum
+    }
+
+    #[test]
+    fn test_results_keep_input_order() {
+        let built = Arc::new(AtomicUsize::new(0));
+        let results = run_all((0..64).map(sum_of_squares).collect(), || {
+            built.fetch_add(1, Ordering::Relaxed);
+            Squarer
+        });
+        let expected: Vec<u64> = (0..64).map(|n| n * (n + 1) * (2 * n + 1) / 6).collect();
+        assert_eq!(
+            results.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            expected
+        );
+        assert!(built.load(Ordering::Relaxed) >= 1);
+    }
+
+    /// Squares numbers, recording the name of each thread it runs on.
+    #[derive(Clone, Default)]
+    struct ThreadRecorder {
+        threads: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl PartialHandler<Op> for ThreadRecorder {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let thread = std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string();
+            self.threads.lock().unwrap().push(thread);
+            Squarer.maybe_handle(op)
+        }
+    }
+
+    fn pool() -> ThreadPool {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .thread_name(|i| format!("worker-{i}"))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_handlers_run_on_the_pool() {
+        #[effectful]
+        fn squares(n: u64) -> Vec<u64> {
+            let first: u64 = perform!(Work::Square(n));
+            let mut squares: Vec<u64> = perform_all!((1..n).map(Work::Square));
+            squares.push(first);
+            squares
+        }
+
+        let recorder = ThreadRecorder::default();
+        let squares = squares(20).run_on(&pool(), || recorder.clone()).unwrap();
+        assert_eq!(squares.len(), 20);
+        assert_eq!(squares[..3], [1, 4, 9]);
+        assert_eq!(squares[19], 400);
+
+        let threads = recorder.threads.lock().unwrap();
+        assert_eq!(threads.len(), 20);
+        assert!(threads.iter().all(|name| name.starts_with("worker-")));
+    }
+
+    #[test]
+    fn test_runs_on_a_pool_fail_like_try_run_with() {
+        #[effectful]
+        fn failing() -> u64 {
+            let _: () = perform!(Work::Fail);
+            0
+        }
+
+        assert_eq!(sum_of_squares(3).run_on(&pool(), || Squarer).unwrap(), 14);
+        let error = failing().run_on(&pool(), || Squarer).unwrap_err();
+        assert!(matches!(error, EffectError::Unhandled(_)));
+    }
+
+    #[test]
+    fn test_failures_stop_only_their_computation() {
+        #[effectful]
+        fn failing() -> u64 {
+            let _: () = perform!(Work::Fail);
+            0
+        }
+
+        let results = run_all(vec![sum_of_squares(2), failing()], || Squarer);
+        assert_eq!(results[0].as_ref().unwrap(), &5);
+        assert!(matches!(results[1], Err(EffectError::Unhandled(_))));
+    }
+}
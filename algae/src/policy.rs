@@ -0,0 +1,631 @@
+//! Capability-based security policies for effects.
+//!
+//! A [`PolicyHandler`] sits in front of another handler and checks every
+//! operation against the [`CapabilitySet`] issued for the run before the
+//! operation is dispatched. Operations that are not covered by a capability are
+//! never seen by the inner handler: the computation is aborted with a
+//! structured [`PolicyDenied`] error instead.
+//!
+//! ## Capabilities
+//!
+//! A capability is written as `scope:action:resource`, for example
+//! `fs:read:/data/**` or `http:get:api.internal`. Every part is a glob:
+//!
+//! - `*` matches any run of characters except `/`
+//! - `**` matches any run of characters, including `/`
+//! - `?` matches a single character except `/`
+//!
+//! The resource part may be omitted (`net:connect`), in which case the
+//! capability covers every resource for that scope and action.
+//!
+//! ## Inspectors
+//!
+//! The policy does not know what an operation *does*; inspectors tell it. An
+//! inspector looks at an operation (and its payload) and describes the
+//! [`Access`]es it needs. Operations for which no inspector reports an access
+//! are *unclassified* and are denied unless the policy was built with
+//! [`PolicyHandler::allow_unclassified`].
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, coroutine_trait, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::policy::{Access, CapabilitySet, PolicyDenied, PolicyHandler};
+//!
+//! effect! {
+//!     File::Read (String) -> String;
+//!     Http::Get (String) -> String;
+//! }
+//!
+//! let caps = CapabilitySet::parse(["fs:read:/data/**", "http:get:api.internal"])?;
+//! let policy = PolicyHandler::new(caps, RealIoHandler)
+//!     .inspect(|op: &Op| match op {
+//!         Op::File(File::Read(path)) => vec![Access::new("fs", "read", path)],
+//!         Op::Http(Http::Get(host)) => vec![Access::new("http", "get", host)],
+//!     });
+//!
+//! match untrusted_plugin().try_run_with(policy) {
+//!     Ok(output) => println!("{output}"),
+//!     Err(EffectError::Aborted(abort)) => {
+//!         let denied = abort.downcast_ref::<PolicyDenied>().unwrap();
+//!         eprintln!("sandbox violation: {denied}");
+//!     }
+//!     Err(other) => eprintln!("{other}"),
+//! }
+//! ```
+
+use crate::{redact::redacted, Abort, OpMeta, PartialHandler};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{any::Any, fmt, str::FromStr};
+
+/// A single resource access requested by an operation.
+///
+/// Resources that look like absolute paths (start with `/`) are normalized
+/// lexically, so `/data/../etc/passwd` is checked as `/etc/passwd`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Access {
+    /// The subsystem being accessed, e.g. `fs` or `http`
+    pub scope: String,
+    /// What is being done, e.g. `read` or `get`
+    pub action: String,
+    /// The object of the access, e.g. a path or a host name
+    pub resource: String,
+}
+
+impl Access {
+    /// Describes an access to `resource` in `scope` performing `action`.
+    pub fn new(
+        scope: impl Into<String>,
+        action: impl Into<String>,
+        resource: impl Into<String>,
+    ) -> Self {
+        let resource = resource.into();
+        let resource = if resource.starts_with('/') {
+            normalize_path(&resource)
+        } else {
+            resource
+        };
+        Self {
+            scope: scope.into(),
+            action: action.into(),
+            resource,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.scope, self.action, self.resource)
+    }
+}
+
+/// Resolves `.` and `..` segments of an absolute path without touching the filesystem.
+fn normalize_path(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            other => segments.push(other),
+        }
+    }
+    let mut normalized = String::with_capacity(path.len());
+    for segment in &segments {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+/// A permission to perform accesses matching `scope:action:resource` globs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capability {
+    scope: String,
+    action: String,
+    resource: Option<String>,
+}
+
+impl Capability {
+    /// Parses a capability of the form `scope:action` or `scope:action:resource`.
+    ///
+    /// Everything after the second `:` belongs to the resource, so resources
+    /// such as `api.internal:8443` are allowed.
+    pub fn parse(spec: &str) -> Result<Self, CapabilityError> {
+        let error = |reason| CapabilityError {
+            spec: spec.to_string(),
+            reason,
+        };
+        let mut parts = spec.splitn(3, ':');
+        let scope = parts.next().unwrap_or_default();
+        let action = parts.next().ok_or_else(|| error("missing action"))?;
+        let resource = parts.next();
+
+        if scope.is_empty() {
+            return Err(error("empty scope"));
+        }
+        if action.is_empty() {
+            return Err(error("empty action"));
+        }
+        if resource == Some("") {
+            return Err(error("empty resource"));
+        }
+
+        Ok(Self {
+            scope: scope.to_string(),
+            action: action.to_string(),
+            resource: resource.map(str::to_string),
+        })
+    }
+
+    /// Returns `true` if this capability covers `access`.
+    pub fn allows(&self, access: &Access) -> bool {
+        glob_match(&self.scope, &access.scope)
+            && glob_match(&self.action, &access.action)
+            && self
+                .resource
+                .as_deref()
+                .is_none_or(|pattern| glob_match(pattern, &access.resource))
+    }
+}
+
+impl FromStr for Capability {
+    type Err = CapabilityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.scope, self.action)?;
+        if let Some(resource) = &self.resource {
+            write!(f, ":{resource}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when a capability string is malformed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilityError {
+    /// The capability string that failed to parse
+    pub spec: String,
+    /// Why it was rejected
+    pub reason: &'static str,
+}
+
+impl fmt::Display for CapabilityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid capability `{}`: {}", self.spec, self.reason)
+    }
+}
+
+impl core::error::Error for CapabilityError {}
+
+/// The set of capabilities issued to a single run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapabilitySet {
+    capabilities: Vec<Capability>,
+}
+
+impl CapabilitySet {
+    /// Creates an empty set, which denies every classified access.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses every capability string in `specs`.
+    pub fn parse<I, S>(specs: I) -> Result<Self, CapabilityError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        specs
+            .into_iter()
+            .map(|spec| Capability::parse(spec.as_ref()))
+            .collect()
+    }
+
+    /// Adds a capability to the set.
+    pub fn grant(mut self, capability: Capability) -> Self {
+        self.capabilities.push(capability);
+        self
+    }
+
+    /// Returns `true` if any capability in the set covers `access`.
+    pub fn allows(&self, access: &Access) -> bool {
+        self.capabilities.iter().any(|cap| cap.allows(access))
+    }
+
+    /// Iterates over the capabilities in the set.
+    pub fn iter(&self) -> impl Iterator<Item = &Capability> {
+        self.capabilities.iter()
+    }
+}
+
+impl FromIterator<Capability> for CapabilitySet {
+    fn from_iter<I: IntoIterator<Item = Capability>>(iter: I) -> Self {
+        Self {
+            capabilities: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Why the policy refused an operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DenialReason {
+    /// The operation requested an access that no capability covers.
+    NotGranted(Access),
+    /// No inspector described the operation, and unclassified operations are denied.
+    Unclassified,
+}
+
+/// Structured error carried by the [`Abort`] a [`PolicyHandler`] replies with.
+///
+/// Retrieve it with `abort.downcast_ref::<PolicyDenied>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyDenied {
+    /// `Debug` rendering of the denied operation
+    pub op: String,
+    /// Why it was denied
+    pub reason: DenialReason,
+}
+
+impl fmt::Display for PolicyDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            DenialReason::NotGranted(access) => {
+                write!(
+                    f,
+                    "operation {} requires ungranted capability {access}",
+                    self.op
+                )
+            }
+            DenialReason::Unclassified => {
+                write!(f, "operation {} is not covered by the policy", self.op)
+            }
+        }
+    }
+}
+
+impl core::error::Error for PolicyDenied {}
+
+type Inspector<Op> = Box<dyn Fn(&Op) -> Vec<Access> + Send>;
+
+/// A handler that enforces a [`CapabilitySet`] in front of another handler.
+///
+/// Every operation is described by the registered inspectors and each
+/// resulting [`Access`] must be covered by a capability. Permitted operations
+/// are forwarded to the inner handler; denied ones abort the computation with
+/// a [`PolicyDenied`] error and never reach the inner handler.
+///
+/// # Type Parameters
+///
+/// * `Op` - The operation type being checked
+/// * `H` - The inner partial handler that performs permitted operations
+pub struct PolicyHandler<Op, H> {
+    capabilities: CapabilitySet,
+    inspectors: Vec<Inspector<Op>>,
+    allow_unclassified: bool,
+    inner: H,
+}
+
+impl<Op, H> PolicyHandler<Op, H> {
+    /// Creates a policy that grants `capabilities` to operations handled by `inner`.
+    ///
+    /// Total handlers can be used as `inner` by wrapping them in
+    /// [`HandlerWrapper`](crate::HandlerWrapper).
+    pub fn new(capabilities: CapabilitySet, inner: H) -> Self {
+        Self {
+            capabilities,
+            inspectors: Vec::new(),
+            allow_unclassified: false,
+            inner,
+        }
+    }
+
+    /// Registers an inspector describing the accesses an operation needs.
+    ///
+    /// Inspectors are consulted in registration order; the accesses reported
+    /// by all of them are checked. Return an empty `Vec` for operations the
+    /// inspector does not know about.
+    pub fn inspect<F>(mut self, inspector: F) -> Self
+    where
+        F: Fn(&Op) -> Vec<Access> + Send + 'static,
+    {
+        self.inspectors.push(Box::new(inspector));
+        self
+    }
+
+    /// Lets operations that no inspector describes through to the inner handler.
+    ///
+    /// Useful when the computation also performs pure effects (logging, maths)
+    /// that need no capability.
+    pub fn allow_unclassified(mut self) -> Self {
+        self.allow_unclassified = true;
+        self
+    }
+
+    /// The capabilities issued to this run.
+    pub fn capabilities(&self) -> &CapabilitySet {
+        &self.capabilities
+    }
+
+    /// Checks `op` against the policy without dispatching it.
+    pub fn check(&self, op: &Op) -> Result<(), DenialReason> {
+        let accesses: Vec<Access> = self
+            .inspectors
+            .iter()
+            .flat_map(|inspect| inspect(op))
+            .collect();
+
+        if accesses.is_empty() {
+            return if self.allow_unclassified {
+                Ok(())
+            } else {
+                Err(DenialReason::Unclassified)
+            };
+        }
+
+        match accesses
+            .into_iter()
+            .find(|access| !self.capabilities.allows(access))
+        {
+            Some(access) => Err(DenialReason::NotGranted(access)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<Op, H> PartialHandler<Op> for PolicyHandler<Op, H>
+where
+    Op: OpMeta + fmt::Debug,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        match self.check(op) {
+            Ok(()) => self.inner.maybe_handle(op),
+            Err(reason) => Some(Abort::boxed(PolicyDenied {
+                op: redacted(op),
+                reason,
+            })),
+        }
+    }
+}
+
+/// Matches `text` against a glob `pattern` (`*`, `**` and `?`).
+fn glob_match(pattern: &str, text: &str) -> bool {
+    glob_match_bytes(pattern.as_bytes(), text.as_bytes())
+}
+
+fn glob_match_bytes(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, _) => text.is_empty(),
+        (Some(b'*'), _) if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            (0..=text.len()).any(|skip| glob_match_bytes(rest, &text[skip..]))
+        }
+        (Some(b'*'), _) => {
+            let rest = &pattern[1..];
+            let segment_end = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=segment_end).any(|skip| glob_match_bytes(rest, &text[skip..]))
+        }
+        (Some(b'?'), Some(&c)) if c != b'/' => glob_match_bytes(&pattern[1..], &text[1..]),
+        (Some(&p), Some(&c)) if p == c => glob_match_bytes(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    effect! {
+        File::Read (String) -> String;
+        File::Write ((String, String)) -> ();
+        Http::Get (String) -> String;
+        Math::Add ((i32, i32)) -> i32;
+    }
+
+    /// Performs every operation it is given and records what it saw.
+    struct IoHandler {
+        seen: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl PartialHandler<Op> for IoHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            self.seen.lock().unwrap().push(format!("{op:?}"));
+            match op {
+                Op::File(File::Read(path)) => Some(Box::new(format!("contents of {path}"))),
+                Op::File(File::Write(_)) => Some(Box::new(())),
+                Op::Http(Http::Get(host)) => Some(Box::new(format!("response from {host}"))),
+                Op::Math(Math::Add((a, b))) => Some(Box::new(a + b)),
+            }
+        }
+    }
+
+    fn sandbox(specs: &[&str], seen: &Arc<Mutex<Vec<String>>>) -> PolicyHandler<Op, IoHandler> {
+        PolicyHandler::new(
+            CapabilitySet::parse(specs).unwrap(),
+            IoHandler { seen: seen.clone() },
+        )
+        .inspect(|op: &Op| match op {
+            Op::File(File::Read(path)) => vec![Access::new("fs", "read", path)],
+            Op::File(File::Write((path, _))) => vec![Access::new("fs", "write", path)],
+            Op::Http(Http::Get(host)) => vec![Access::new("http", "get", host)],
+            Op::Math(_) => vec![],
+        })
+    }
+
+    #[effectful]
+    fn read_then_fetch() -> String {
+        let data: String = perform!(File::Read("/data/input.csv".to_string()));
+        let api: String = perform!(Http::Get("api.internal".to_string()));
+        format!("{data} + {api}")
+    }
+
+    #[effectful]
+    fn escalate() -> String {
+        let _: String = perform!(File::Read("/data/../etc/passwd".to_string()));
+        let _: () = perform!(File::Write(("/tmp/loot".to_string(), "x".to_string())));
+        "escaped".to_string()
+    }
+
+    #[effectful]
+    fn compute() -> i32 {
+        perform!(Math::Add((2, 3)))
+    }
+
+    #[test]
+    fn test_permitted_operations_reach_inner_handler() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let policy = sandbox(&["fs:read:/data/**", "http:get:api.internal"], &seen);
+
+        let result = read_then_fetch().try_run_with(policy).unwrap();
+        assert_eq!(
+            result,
+            "contents of /data/input.csv + response from api.internal"
+        );
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_denied_operation_aborts_before_dispatch() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let policy = sandbox(&["fs:read:/data/**"], &seen);
+
+        let err = escalate().try_run_with(policy).unwrap_err();
+        let EffectError::Aborted(abort) = err else {
+            panic!("expected an abort, got {err:?}");
+        };
+        let denied = abort.downcast_ref::<PolicyDenied>().unwrap();
+        assert_eq!(
+            denied.reason,
+            DenialReason::NotGranted(Access::new("fs", "read", "/etc/passwd"))
+        );
+        assert!(denied.op.contains("/data/../etc/passwd"));
+        assert!(
+            seen.lock().unwrap().is_empty(),
+            "inner handler must not run"
+        );
+    }
+
+    #[test]
+    fn test_unclassified_operations() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let err = compute()
+            .try_run_with(sandbox(&["fs:read:/**"], &seen))
+            .unwrap_err();
+        let EffectError::Aborted(abort) = err else {
+            panic!("expected an abort, got {err:?}");
+        };
+        assert_eq!(
+            abort.downcast::<PolicyDenied>().unwrap().reason,
+            DenialReason::Unclassified
+        );
+
+        let allowed = compute()
+            .try_run_with(sandbox(&[], &seen).allow_unclassified())
+            .unwrap();
+        assert_eq!(allowed, 5);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_denial_panics_under_run_checked() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let policy = sandbox(&[], &seen);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = read_then_fetch().handle(policy).run_checked();
+        }));
+        let payload = result.unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.contains("Effectful computation aborted"));
+        assert!(message.contains("NotGranted"));
+    }
+
+    #[test]
+    fn test_multiple_accesses_per_operation() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        // Writing also requires being allowed to talk to the audit host.
+        let policy = sandbox(&["fs:write:/tmp/**"], &seen).inspect(|op: &Op| match op {
+            Op::File(File::Write(_)) => vec![Access::new("http", "post", "audit.internal")],
+            _ => vec![],
+        });
+
+        assert_eq!(
+            policy.check(&Op::File(File::Write(("/tmp/a".into(), "".into())))),
+            Err(DenialReason::NotGranted(Access::new(
+                "http",
+                "post",
+                "audit.internal"
+            )))
+        );
+    }
+
+    #[test]
+    fn test_glob_patterns() {
+        assert!(glob_match("/data/**", "/data/a/b.csv"));
+        assert!(glob_match("/data/**", "/data/"));
+        assert!(!glob_match("/data/**", "/etc/passwd"));
+        assert!(glob_match("/data/*.csv", "/data/a.csv"));
+        assert!(!glob_match("/data/*.csv", "/data/nested/a.csv"));
+        assert!(glob_match("*.internal", "api.internal"));
+        assert!(glob_match("file?.txt", "file1.txt"));
+        assert!(!glob_match("file?.txt", "file10.txt"));
+        assert!(glob_match("**", "anything/at/all"));
+        assert!(glob_match("api.internal", "api.internal"));
+        assert!(!glob_match("api.internal", "api.internal.evil.com"));
+    }
+
+    #[test]
+    fn test_path_normalization() {
+        assert_eq!(normalize_path("/data/../etc/passwd"), "/etc/passwd");
+        assert_eq!(normalize_path("/data/./x//y"), "/data/x/y");
+        assert_eq!(normalize_path("/.."), "/");
+        let access = Access::new("fs", "read", "/data/../../etc/shadow");
+        assert_eq!(access.resource, "/etc/shadow");
+    }
+
+    #[test]
+    fn test_capability_parsing() {
+        let cap: Capability = "fs:read:/data/**".parse().unwrap();
+        assert_eq!(cap.to_string(), "fs:read:/data/**");
+        assert!(cap.allows(&Access::new("fs", "read", "/data/x")));
+        assert!(!cap.allows(&Access::new("fs", "write", "/data/x")));
+
+        let any_host = Capability::parse("net:connect").unwrap();
+        assert!(any_host.allows(&Access::new("net", "connect", "10.0.0.1:80")));
+
+        let with_port = Capability::parse("http:get:api.internal:8443").unwrap();
+        assert!(with_port.allows(&Access::new("http", "get", "api.internal:8443")));
+
+        assert_eq!(
+            Capability::parse("fs").unwrap_err().reason,
+            "missing action"
+        );
+        assert_eq!(
+            Capability::parse(":read").unwrap_err().reason,
+            "empty scope"
+        );
+        assert_eq!(
+            Capability::parse("fs:read:").unwrap_err().reason,
+            "empty resource"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Servicing order for operations of concurrent computations.
+//!
+//! When several computations share handlers that can only serve one
+//! operation at a time, some operations are more urgent than others: a
+//! database query holds up its computation, while a log line can wait until
+//! nothing else is pending. Operations declare this where they are defined:
+//!
+//! ```rust,ignore
+//! effect! {
+//!     #[priority(high)]
+//!     Db::Query (String) -> Vec<Row>;
+//!     #[priority(low)]
+//!     Log::Line (String) -> ();
+//!     Cache::Get (String) -> Option<String>; // normal
+//! }
+//! ```
+//!
+//! The declared [`Priority`] is exposed through [`OpMeta::priority`].
+//! [`run_by_priority`] runs computations side by side and always services the
+//! most urgent pending operation first; [`UringIo::run_batch`] submits urgent
+//! I/O first when a round does not fit into the ring.
+//!
+//! [`UringIo::run_batch`]: crate::effects::uring::UringIo::run_batch
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::priority::run_by_priority;
+//!
+//! let results = run_by_priority(
+//!     requests.into_iter().map(serve).collect(),
+//!     AppHandler::new(pool),
+//! );
+//! ```
+
+use crate::{dispatch_effect, Effect, EffectError, Effectful, OpMeta, PartialHandler, Reply};
+use alloc::{boxed::Box, vec::Vec};
+use core::ops::CoroutineState;
+
+/// How urgently an operation should be serviced, declared with
+/// `#[priority(...)]`.
+///
+/// Ordered from least to most urgent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Serviced once nothing more urgent is pending, e.g. logging.
+    Low,
+    /// The default.
+    #[default]
+    Normal,
+    /// Serviced before anything else, e.g. queries a computation blocks on.
+    High,
+}
+
+/// Runs `computations` together against `h`, one operation at a time,
+/// always dispatching the pending operation with the highest
+/// [`Priority`]. Operations of equal priority are dispatched in the order of
+/// their computations, starting after the one served last, so no computation
+/// is starved by its peers at the same level.
+///
+/// Returns each computation's outcome, in order. A computation that performs
+/// an unhandled operation or is aborted stops there without affecting the
+/// others.
+///
+/// Each computation runs as [`scoped`](Effectful::scoped) sub-computation of
+/// its position, so seeded handlers give it the same values under any
+/// servicing order.
+///
+/// Priorities are strict: a low-priority operation waits as long as more
+/// urgent ones keep arriving.
+pub fn run_by_priority<R, Op, H>(
+    computations: Vec<Effectful<R, Op>>,
+    mut h: H,
+) -> Vec<Result<R, EffectError<Op>>>
+where
+    Op: OpMeta,
+    H: PartialHandler<Op>,
+{
+    let mut tasks: Vec<Option<Task<R, Op>>> = computations
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| Some(Task::resume(c.scoped(i as u64), Ok(None))))
+        .collect();
+    let mut last = tasks.len().saturating_sub(1);
+    loop {
+        // The first of the most urgent pending operations, counting from the
+        // task after the one served last
+        let next = (1..=tasks.len())
+            .map(|offset| (last + offset) % tasks.len())
+            .filter_map(|i| match &tasks[i] {
+                Some(Task::Pending(_, eff)) => Some((eff.op.priority(), i)),
+                _ => None,
+            })
+            .reduce(|best, candidate| {
+                if candidate.0 > best.0 {
+                    candidate
+                } else {
+                    best
+                }
+            });
+        let Some((_, i)) = next else {
+            break;
+        };
+        let Some(Task::Pending(c, eff)) = tasks[i].take() else {
+            unreachable!("only pending tasks are picked")
+        };
+        let reply = dispatch_effect(*eff, &mut |op| h.maybe_handle(op));
+        tasks[i] = Some(Task::resume(c, reply.map(Some)));
+        last = i;
+    }
+
+    tasks
+        .into_iter()
+        .map(|task| match task {
+            Some(Task::Finished(result)) => result,
+            _ => unreachable!("every task has finished"),
+        })
+        .collect()
+}
+
+/// A computation waiting for its pending operation, or its outcome.
+enum Task<R, Op: 'static> {
+    Pending(Effectful<R, Op>, Box<Effect<Op>>),
+    Finished(Result<R, EffectError<Op>>),
+}
+
+impl<R, Op> Task<R, Op> {
+    /// Resumes `c` with `reply` up to its next operation or its end.
+    fn resume(mut c: Effectful<R, Op>, reply: Result<Option<Reply>, EffectError<Op>>) -> Self {
+        match reply {
+            Ok(reply) => match c.step(reply) {
+                CoroutineState::Yielded(eff) => Task::Pending(c, Box::new(eff)),
+                CoroutineState::Complete(result) => Task::Finished(Ok(result)),
+            },
+            Err(error) => Task::Finished(Err(error)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::{
+        any::Any,
+        sync::{Arc, Mutex},
+    };
+
+    effect! {
+        #[priority(high)]
+        Db::Query (u32) -> u32;
+        #[priority(low)]
+        Log::Line (String) -> ();
+        Work::Step (u32) -> ();
+        Work::Fail -> ();
+    }
+
+    /// Answers everything but `Work::Fail`, recording the order operations
+    /// were served in; clones share the record.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        served: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Recorder {
+        fn served(&self) -> Vec<String> {
+            self.served.lock().unwrap().clone()
+        }
+    }
+
+    impl PartialHandler<Op> for Recorder {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            self.served.lock().unwrap().push(format!("{op:?}"));
+            match op {
+                Op::Db(Db::Query(n)) => Some(Box::new(n * 10)),
+                Op::Work(Work::Fail) => None,
+                _ => Some(Box::new(())),
+            }
+        }
+    }
+
+    #[effectful]
+    fn logger(lines: u32) -> u32 {
+        for i in 0..lines {
+            let _: () = perform!(Log::Line(format!("line {i}")));
+        }
+        0
+    }
+
+    #[effectful]
+    fn querier(id: u32) -> u32 {
+        let _: () = perform!(Work::Step(id));
+        let n: u32 = perform!(Db::Query(id));
+        n
+    }
+
+    #[test]
+    fn test_declared_priorities() {
+        assert_eq!(Op::Db(Db::Query(1)).priority(), Priority::High);
+        assert_eq!(Op::Log(Log::Line(String::new())).priority(), Priority::Low);
+        assert_eq!(Op::Work(Work::Fail).priority(), Priority::Normal);
+        assert!(Priority::Low < Priority::Normal && Priority::Normal < Priority::High);
+    }
+
+    #[test]
+    fn test_urgent_operations_are_served_first() {
+        let recorder = Recorder::default();
+        let results = run_by_priority(vec![logger(2), querier(1), querier(2)], recorder.clone());
+        assert_eq!(
+            results.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            [0, 10, 20]
+        );
+        assert_eq!(
+            recorder.served(),
+            [
+                "Work(Step(1))",
+                "Db(Query(1))",
+                "Work(Step(2))",
+                "Db(Query(2))",
+                "Log(Line(\"line 0\"))",
+                "Log(Line(\"line 1\"))",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_equal_priorities_take_turns() {
+        #[effectful]
+        fn steps(id: u32) {
+            let _: () = perform!(Work::Step(id));
+            let _: () = perform!(Work::Step(id));
+        }
+
+        let recorder = Recorder::default();
+        run_by_priority(vec![steps(1), steps(2)], recorder.clone());
+        assert_eq!(
+            recorder.served(),
+            [
+                "Work(Step(1))",
+                "Work(Step(2))",
+                "Work(Step(1))",
+                "Work(Step(2))",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failures_stop_only_their_computation() {
+        #[effectful]
+        fn failing() -> u32 {
+            let _: () = perform!(Work::Fail);
+            1
+        }
+
+        let results = run_by_priority(vec![failing(), logger(1)], Recorder::default());
+        assert!(matches!(results[0], Err(EffectError::Unhandled(_))));
+        assert!(results[1].is_ok());
+    }
+}
@@ -0,0 +1,176 @@
+//! Property-based checks of handlers, with proptest.
+//!
+//! An `effect!` block with a `proptest;` header implements proptest's
+//! `Arbitrary` for its operations and [`DeclaredReply`], which tells the
+//! reply type declared for each of them. [`check_handler_total`] feeds a
+//! handler generated operations and fails with the smallest one it declines
+//! or answers with a reply of the wrong type, catching forgotten match arms
+//! and mixed-up replies before a computation runs into them:
+//!
+//! ```rust,ignore
+//! effect! {
+//!     proptest;
+//!     Kv::Get (String) -> Option<String>;
+//!     Kv::Put ((String, String)) -> ();
+//!     Kv::Len -> usize;
+//! }
+//!
+//! #[test]
+//! fn kv_store_answers_every_operation() {
+//!     check_handler_total::<Op, _>(KvStore::default());
+//! }
+//! ```
+
+pub use proptest::test_runner::Config;
+
+use crate::{Abort, PartialHandler};
+use proptest::{
+    arbitrary::{any, Arbitrary},
+    test_runner::{TestCaseError, TestRunner},
+};
+use std::{any::TypeId, cell::RefCell, fmt};
+
+/// The reply type an operation was declared with, implemented by `effect!`
+/// with a `proptest;` header.
+pub trait DeclaredReply {
+    /// The `TypeId` of the declared reply type, and the type as written in
+    /// `effect!`.
+    fn declared_reply(&self) -> (TypeId, &'static str);
+}
+
+/// Checks that `handler` answers every operation of type `Op`, with a reply
+/// of the declared type or an [`Abort`].
+///
+/// Runs as many cases as proptest's default [`Config`], which reads the
+/// `PROPTEST_CASES` environment variable.
+///
+/// # Panics
+///
+/// Panics with the smallest operation found that `handler` declines,
+/// answers with another type, or panics on.
+pub fn check_handler_total<Op, H>(handler: H)
+where
+    Op: Arbitrary + DeclaredReply + fmt::Debug,
+    H: PartialHandler<Op>,
+{
+    check_handler_total_with(Config::default(), handler)
+}
+
+/// [`check_handler_total`] with a proptest `config`, e.g. to run more cases.
+pub fn check_handler_total_with<Op, H>(config: Config, handler: H)
+where
+    Op: Arbitrary + DeclaredReply + fmt::Debug,
+    H: PartialHandler<Op>,
+{
+    // The runner takes an `Fn`, and the handler needs `&mut`
+    let handler = RefCell::new(handler);
+    let result = TestRunner::new(config).run(&any::<Op>(), |op| {
+        let (declared, name) = op.declared_reply();
+        match handler.borrow_mut().maybe_handle(&op) {
+            None => Err(TestCaseError::fail("the handler declined it")),
+            Some(reply) if reply.is::<Abort>() || (*reply).type_id() == declared => Ok(()),
+            Some(_) => Err(TestCaseError::fail(format!(
+                "the handler's reply is not a `{name}`"
+            ))),
+        }
+    });
+    if let Err(error) = result {
+        panic!("handler is not total: {error}");
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use proptest::strategy::{Strategy, ValueTree};
+    use std::{any::Any, collections::HashMap, panic};
+
+    effect! {
+        proptest;
+        Kv::Get (String) -> Option<String>;
+        Kv::Put ((String, String)) -> ();
+        Kv::Len -> usize;
+        Admin::Flush -> ();
+    }
+
+    /// Answers every operation; `wrong_len` replies to `Len` with a `u64`,
+    /// and `no_admin` declines `Admin` operations.
+    #[derive(Default)]
+    struct KvStore {
+        items: HashMap<String, String>,
+        wrong_len: bool,
+        no_admin: bool,
+    }
+
+    impl PartialHandler<Op> for KvStore {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Kv(Kv::Get(key)) => Some(Box::new(self.items.get(key).cloned())),
+                Op::Kv(Kv::Put((key, value))) => {
+                    self.items.insert(key.clone(), value.clone());
+                    Some(Box::new(()))
+                }
+                Op::Kv(Kv::Len) if self.wrong_len => Some(Box::new(self.items.len() as u64)),
+                Op::Kv(Kv::Len) => Some(Box::new(self.items.len())),
+                Op::Admin(_) if self.no_admin => None,
+                Op::Admin(Admin::Flush) if self.items.len() > 3 => {
+                    Some(Abort::boxed("refusing to flush"))
+                }
+                Op::Admin(Admin::Flush) => {
+                    self.items.clear();
+                    Some(Box::new(()))
+                }
+            }
+        }
+    }
+
+    fn failure(handler: KvStore) -> String {
+        let config = Config {
+            failure_persistence: None,
+            ..Config::default()
+        };
+        let panic = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            check_handler_total_with::<Op, _>(config, handler)
+        }))
+        .unwrap_err();
+        panic.downcast::<String>().map(|m| *m).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_generated_operations_cover_every_family() {
+        let mut runner = TestRunner::deterministic();
+        let ops: Vec<Op> = (0..64)
+            .map(|_| any::<Op>().new_tree(&mut runner).unwrap().current())
+            .collect();
+        assert!(ops.iter().any(|op| matches!(op, Op::Kv(Kv::Put(_)))));
+        assert!(ops.iter().any(|op| matches!(op, Op::Admin(Admin::Flush))));
+        assert_eq!(
+            Op::kv_len().declared_reply(),
+            (TypeId::of::<usize>(), "usize")
+        );
+    }
+
+    #[test]
+    fn test_total_handlers_pass() {
+        check_handler_total::<Op, _>(KvStore::default());
+    }
+
+    #[test]
+    fn test_wrong_reply_types_and_declines_are_found() {
+        let message = failure(KvStore {
+            wrong_len: true,
+            ..KvStore::default()
+        });
+        assert!(message.contains("is not a `usize`"), "{message}");
+        assert!(message.contains("Len"), "{message}");
+
+        let message = failure(KvStore {
+            no_admin: true,
+            ..KvStore::default()
+        });
+        assert!(message.contains("declined"), "{message}");
+        assert!(message.contains("Flush"), "{message}");
+    }
+}
@@ -0,0 +1,439 @@
+//! Per-operation quotas.
+//!
+//! A [`QuotaLayer`] wraps another handler and meters the operations flowing
+//! through it. Each quota has a name, a limit and a *measure*: a closure that
+//! says how much of the quota an operation consumes (or `None` if the quota does
+//! not apply to it). Counting quotas ("at most 100 `Http::Get` per run") measure
+//! every matching operation as `1`; volume quotas ("at most 10MB written via
+//! `File::Write`") measure the payload size.
+//!
+//! When an operation would push a quota past its limit, it is not dispatched.
+//! The computation is aborted with a typed [`QuotaExceeded`] error, which makes
+//! quotas useful both against abusive workloads and for catching accidental
+//! loops in tests.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, coroutine_trait, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::quota::{QuotaExceeded, QuotaLayer};
+//!
+//! effect! {
+//!     Http::Get (String) -> String;
+//!     File::Write ((String, Vec<u8>)) -> ();
+//! }
+//!
+//! let layer = QuotaLayer::new(RealIoHandler)
+//!     .limit_count("http.get", 100, |op: &Op| matches!(op, Op::Http(Http::Get(_))))
+//!     .limit_total("file.write.bytes", 10 * 1024 * 1024, |op: &Op| match op {
+//!         Op::File(File::Write((_, data))) => Some(data.len() as u64),
+//!         _ => None,
+//!     });
+//!
+//! if let Err(EffectError::Aborted(abort)) = crawler().try_run_with(layer) {
+//!     let exceeded = abort.downcast_ref::<QuotaExceeded>().unwrap();
+//!     eprintln!("{exceeded}");
+//! }
+//! ```
+
+use crate::{redact::redacted, Abort, OpMeta, PartialHandler, Reply, Unanswered};
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::{any::Any, fmt};
+
+type Measure<Op> = Box<dyn Fn(&Op) -> Option<u64> + Send>;
+
+struct Quota<Op> {
+    name: String,
+    limit: u64,
+    used: u64,
+    measure: Measure<Op>,
+}
+
+/// Typed error carried by the [`Abort`] a [`QuotaLayer`] replies with.
+///
+/// Retrieve it with `abort.downcast_ref::<QuotaExceeded>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    /// Name of the quota that was exceeded
+    pub quota: String,
+    /// The configured limit
+    pub limit: u64,
+    /// How much of the quota had been consumed before the offending operation
+    pub used: u64,
+    /// How much the offending operation asked for
+    pub requested: u64,
+    /// `Debug` rendering of the offending operation
+    pub op: String,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "quota `{}` exceeded by {}: {} used + {} requested > limit {}",
+            self.quota, self.op, self.used, self.requested, self.limit
+        )
+    }
+}
+
+impl core::error::Error for QuotaExceeded {}
+
+/// A handler layer that enforces per-run limits on the operations it dispatches.
+///
+/// Quotas are checked in registration order before the operation reaches the
+/// inner handler. An operation is only charged against its quotas if all of
+/// them have room for it and the inner handler answers it, so neither a
+/// rejected operation nor one the inner handler declines consumes any quota.
+///
+/// # Type Parameters
+///
+/// * `Op` - The operation type being metered
+/// * `H` - The inner partial handler that performs the operations
+pub struct QuotaLayer<Op, H> {
+    quotas: Vec<Quota<Op>>,
+    inner: H,
+}
+
+impl<Op, H> QuotaLayer<Op, H> {
+    /// Wraps `inner` with no quotas configured.
+    ///
+    /// Total handlers can be used as `inner` by wrapping them in
+    /// [`HandlerWrapper`](crate::HandlerWrapper).
+    pub fn new(inner: H) -> Self {
+        Self {
+            quotas: Vec::new(),
+            inner,
+        }
+    }
+
+    /// Adds a quota whose consumption is computed by `measure`.
+    ///
+    /// `measure` returns how much of the quota an operation uses, or `None`
+    /// if the quota does not apply to that operation.
+    pub fn limit<F>(mut self, name: impl Into<String>, limit: u64, measure: F) -> Self
+    where
+        F: Fn(&Op) -> Option<u64> + Send + 'static,
+    {
+        self.quotas.push(Quota {
+            name: name.into(),
+            limit,
+            used: 0,
+            measure: Box::new(measure),
+        });
+        self
+    }
+
+    /// Allows at most `limit` operations matching `matches`.
+    pub fn limit_count<F>(self, name: impl Into<String>, limit: u64, matches: F) -> Self
+    where
+        F: Fn(&Op) -> bool + Send + 'static,
+    {
+        self.limit(name, limit, move |op| matches(op).then_some(1))
+    }
+
+    /// Allows at most `limit` units in total across operations measured by `amount`.
+    ///
+    /// This is an alias of [`QuotaLayer::limit`] that reads better for volume
+    /// quotas such as bytes written.
+    pub fn limit_total<F>(self, name: impl Into<String>, limit: u64, amount: F) -> Self
+    where
+        F: Fn(&Op) -> Option<u64> + Send + 'static,
+    {
+        self.limit(name, limit, amount)
+    }
+
+    /// How much of the quota called `name` has been consumed so far.
+    pub fn used(&self, name: &str) -> Option<u64> {
+        self.quotas.iter().find(|q| q.name == name).map(|q| q.used)
+    }
+
+    /// How much of the quota called `name` is still available.
+    pub fn remaining(&self, name: &str) -> Option<u64> {
+        self.quotas
+            .iter()
+            .find(|q| q.name == name)
+            .map(|q| q.limit.saturating_sub(q.used))
+    }
+}
+
+impl<Op: OpMeta + fmt::Debug, H> QuotaLayer<Op, H> {
+    /// What `op` uses of each quota that applies to it, on top of the
+    /// `pending` uses not charged yet, or the quota it would exceed.
+    fn charges(&self, op: &Op, pending: &[u64]) -> Result<Vec<(usize, u64)>, QuotaExceeded> {
+        let mut charges = Vec::new();
+        for (index, quota) in self.quotas.iter().enumerate() {
+            let Some(amount) = (quota.measure)(op) else {
+                continue;
+            };
+            let used = quota
+                .used
+                .saturating_add(pending.get(index).copied().unwrap_or(0));
+            if used.saturating_add(amount) > quota.limit {
+                return Err(QuotaExceeded {
+                    quota: quota.name.clone(),
+                    limit: quota.limit,
+                    used,
+                    requested: amount,
+                    op: redacted(op),
+                });
+            }
+            charges.push((index, amount));
+        }
+        Ok(charges)
+    }
+
+    fn charge(&mut self, charges: Vec<(usize, u64)>) {
+        for (index, amount) in charges {
+            self.quotas[index].used += amount;
+        }
+    }
+}
+
+impl<Op, H> PartialHandler<Op> for QuotaLayer<Op, H>
+where
+    Op: OpMeta + fmt::Debug,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let charges = match self.charges(op, &[]) {
+            Ok(charges) => charges,
+            Err(exceeded) => return Some(Abort::boxed(exceeded)),
+        };
+
+        // A declined operation may be answered further down the chain, which
+        // meters it on its own, or not at all
+        let reply = self.inner.maybe_handle(op)?;
+        self.charge(charges);
+        Some(reply)
+    }
+
+    /// Checks the operations against the quotas in order, as if they were
+    /// performed one after the other, and only charges those the inner
+    /// handler answers.
+    fn maybe_handle_batch(&mut self, ops: &[Op]) -> Option<Vec<Reply>> {
+        let mut pending = vec![0; self.quotas.len()];
+        let mut charges = Vec::with_capacity(ops.len());
+        for (position, op) in ops.iter().enumerate() {
+            match self.charges(op, &pending) {
+                Ok(op_charges) => {
+                    for &(index, amount) in &op_charges {
+                        pending[index] += amount;
+                    }
+                    charges.push(op_charges);
+                }
+                Err(exceeded) => {
+                    let mut replies: Vec<Reply> =
+                        ops.iter().map(|_| Reply::new(Unanswered)).collect();
+                    replies[position] = Abort::boxed(exceeded).into();
+                    return Some(replies);
+                }
+            }
+        }
+
+        let replies = self.inner.maybe_handle_batch(ops)?;
+        for (reply, op_charges) in replies.iter().zip(charges) {
+            if !reply.is::<Unanswered>() {
+                self.charge(op_charges);
+            }
+        }
+        Some(replies)
+    }
+
+    fn handler_names(&self) -> Vec<&'static str> {
+        self.inner.handler_names()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        #[tag(io)]
+        Http::Get (String) -> String;
+        #[tag(io, mutating)]
+        File::Write ((String, Vec<u8>)) -> ();
+        Log::Info (String) -> ();
+    }
+
+    struct IoHandler;
+
+    impl PartialHandler<Op> for IoHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Http(Http::Get(url)) => Some(Box::new(format!("body of {url}"))),
+                Op::File(File::Write(_)) | Op::Log(Log::Info(_)) => Some(Box::new(())),
+            }
+        }
+    }
+
+    fn layer() -> QuotaLayer<Op, IoHandler> {
+        QuotaLayer::new(IoHandler)
+            .limit_count("http.get", 3, |op: &Op| {
+                matches!(op, Op::Http(Http::Get(_)))
+            })
+            .limit_total("file.write.bytes", 10, |op: &Op| match op {
+                Op::File(File::Write((_, data))) => Some(data.len() as u64),
+                _ => None,
+            })
+    }
+
+    #[effectful]
+    fn fetch_forever() -> usize {
+        // An accidental infinite loop: only the quota stops it.
+        let mut fetched = 0;
+        loop {
+            let _: String = perform!(Http::Get(format!("/page/{fetched}")));
+            fetched += 1;
+        }
+    }
+
+    #[effectful]
+    fn write_chunks(chunks: Vec<usize>) -> usize {
+        let mut written = 0;
+        for len in chunks {
+            let _: () = perform!(File::Write(("out.bin".to_string(), vec![0; len])));
+            let _: () = perform!(Log::Info(format!("wrote {len} bytes")));
+            written += len;
+        }
+        written
+    }
+
+    fn exceeded(err: EffectError<Op>) -> QuotaExceeded {
+        match err {
+            EffectError::Aborted(abort) => abort.downcast::<QuotaExceeded>().unwrap(),
+            other => panic!("expected a quota abort, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_count_quota_stops_runaway_loop() {
+        let err = fetch_forever().try_run_with(layer()).unwrap_err();
+        let exceeded = exceeded(err);
+        assert_eq!(exceeded.quota, "http.get");
+        assert_eq!(exceeded.limit, 3);
+        assert_eq!(exceeded.used, 3);
+        assert_eq!(exceeded.requested, 1);
+        assert_eq!(exceeded.op, "Http(Get(\"/page/3\"))");
+    }
+
+    #[test]
+    fn test_volume_quota() {
+        let ok = write_chunks(vec![4, 6]).try_run_with(layer()).unwrap();
+        assert_eq!(ok, 10);
+
+        let err = write_chunks(vec![4, 5, 2])
+            .try_run_with(layer())
+            .unwrap_err();
+        let exceeded = exceeded(err);
+        assert_eq!(exceeded.quota, "file.write.bytes");
+        assert_eq!((exceeded.used, exceeded.requested), (9, 2));
+        assert_eq!(
+            exceeded.to_string(),
+            format!(
+                "quota `file.write.bytes` exceeded by {}: 9 used + 2 requested > limit 10",
+                exceeded.op
+            )
+        );
+    }
+
+    #[test]
+    fn test_usage_accounting() {
+        let mut layer = layer();
+        assert_eq!(layer.used("http.get"), Some(0));
+        assert_eq!(layer.remaining("http.get"), Some(3));
+        assert_eq!(layer.used("missing"), None);
+
+        layer.maybe_handle(&Op::Http(Http::Get("/".into())));
+        layer.maybe_handle(&Op::Log(Log::Info("not metered".into())));
+        assert_eq!(layer.used("http.get"), Some(1));
+        assert_eq!(layer.remaining("http.get"), Some(2));
+
+        // A rejected operation consumes nothing.
+        let reply = layer.maybe_handle(&Op::File(File::Write(("f".into(), vec![0; 11]))));
+        assert!(reply.unwrap().is::<Abort>());
+        assert_eq!(layer.used("file.write.bytes"), Some(0));
+    }
+
+    #[test]
+    fn test_declined_operations_are_not_charged() {
+        struct HttpOnly;
+
+        impl PartialHandler<Op> for HttpOnly {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Http(Http::Get(url)) => Some(Box::new(url.clone())),
+                    _ => None,
+                }
+            }
+        }
+
+        let mut layer = QuotaLayer::new(HttpOnly)
+            .limit_count("any", 2, |_: &Op| true)
+            .limit_total("file.write.bytes", 10, |op: &Op| match op {
+                Op::File(File::Write((_, data))) => Some(data.len() as u64),
+                _ => None,
+            });
+        assert!(layer
+            .maybe_handle(&Op::File(File::Write(("f".into(), vec![0; 8]))))
+            .is_none());
+        assert_eq!(layer.used("any"), Some(0));
+        assert_eq!(layer.used("file.write.bytes"), Some(0));
+
+        // The next handler answers what the layer's handler declined
+        let result = write_chunks(vec![8]).try_run_with(layer.or_else(IoHandler));
+        assert_eq!(result.unwrap(), 8);
+    }
+
+    #[effectful]
+    fn fetch_all(pages: usize) -> Vec<String> {
+        perform_all!((0..pages).map(|page| Http::Get(format!("/page/{page}"))))
+    }
+
+    #[test]
+    fn test_batches_are_metered_per_operation() {
+        let mut metered = layer();
+        let ops = [
+            Op::Http(Http::Get("/a".into())),
+            Op::Log(Log::Info("not metered".into())),
+            Op::Http(Http::Get("/b".into())),
+        ];
+        assert_eq!(metered.maybe_handle_batch(&ops).unwrap().len(), 3);
+        assert_eq!(metered.used("http.get"), Some(2));
+        assert_eq!(
+            fetch_all(1).try_run_with(metered).unwrap(),
+            ["body of /page/0"]
+        );
+
+        let exceeded = exceeded(fetch_all(4).try_run_with(layer()).unwrap_err());
+        assert_eq!((exceeded.used, exceeded.requested), (3, 1));
+        assert_eq!(exceeded.op, "Http(Get(\"/page/3\"))");
+    }
+
+    #[test]
+    fn test_layers_report_their_handlers() {
+        assert_eq!(
+            layer().handler_names(),
+            [core::any::type_name::<IoHandler>()]
+        );
+    }
+
+    #[test]
+    fn test_limits_can_select_ops_by_tag() {
+        assert_eq!(
+            Op::File(File::Write(("f".into(), vec![]))).tags(),
+            ["io", "mutating"]
+        );
+        assert!(Op::Http(Http::Get("/".into())).has_tag("io"));
+        assert!(!Op::Log(Log::Info("hi".into())).has_tag("io"));
+
+        let mut layer = QuotaLayer::new(IoHandler).limit_count("io", 2, |op: &Op| op.has_tag("io"));
+        layer.maybe_handle(&Op::Http(Http::Get("/".into())));
+        layer.maybe_handle(&Op::Log(Log::Info("not io".into())));
+        layer.maybe_handle(&Op::File(File::Write(("f".into(), vec![1]))));
+        assert_eq!(layer.used("io"), Some(2));
+    }
+}
@@ -0,0 +1,200 @@
+//! Keeping sensitive payloads out of traces and logs.
+//!
+//! An operation whose whole payload must not be recorded is tagged
+//! `sensitive` where it is defined; a single field is wrapped in [`Secret`]
+//! instead, which leaves the rest of the payload readable:
+//!
+//! ```rust,ignore
+//! use algae::redact::Secret;
+//!
+//! effect! {
+//!     #[tag(sensitive)]
+//!     Auth::Login ((String, String)) -> Token;
+//!     Auth::Refresh ((u64, Secret<String>)) -> Token;
+//! }
+//!
+//! assert_eq!(redacted(&Op::Auth(Auth::Login((user, password)))), "Auth(Login(<redacted>))");
+//! assert_eq!(format!("{refresh:?}"), r#"Auth(Refresh((7, <redacted>)))"#);
+//! ```
+//!
+//! Built-in recorders render operations with [`redacted`]: the traces of
+//! [`testing`](crate::testing), the [`SlowEffectLog`](crate::observe::SlowEffectLog),
+//! unhandled operations in [`EffectError`](crate::EffectError), and the
+//! errors of the [`quota`](crate::quota), [`retry`](crate::retry),
+//! [`timeout`](crate::timeout) and [`policy`](crate::policy) layers. Remote
+//! transports only log abort descriptions, which come from those errors.
+//! Handlers and codecs still get the operations unchanged.
+
+use crate::OpMeta;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+/// The tag that marks an operation's payload as sensitive, i.e.
+/// `#[tag(sensitive)]`.
+pub const SENSITIVE: &str = "sensitive";
+
+/// What a hidden value is rendered as.
+const REDACTED: &str = "<redacted>";
+
+/// A payload field whose value never shows up in `Debug` output.
+///
+/// Everything else is passed through to the wrapped value, so payloads keep
+/// their derives.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Secret<T>(pub T);
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Derives an operation key such as `Http::Get` from an operation's `Debug` output.
+///
+/// The key is made of the leading variant names: `Http(Get("/"))` becomes
+/// `Http::Get` and `Test(GetValue)` becomes `Test::GetValue`. Payloads are
+/// never part of the key.
+pub fn op_key<Op: fmt::Debug>(op: &Op) -> String {
+    let debug = format!("{op:?}");
+    let mut key = String::new();
+    let mut rest = debug.as_str();
+    loop {
+        let ident_len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let starts_ident = rest
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_');
+        if ident_len == 0 || !starts_ident {
+            break;
+        }
+        if !key.is_empty() {
+            key.push_str("::");
+        }
+        key.push_str(&rest[..ident_len]);
+        match rest[ident_len..].strip_prefix('(') {
+            Some(inner) => rest = inner,
+            None => break,
+        }
+    }
+    if key.is_empty() {
+        debug
+    } else {
+        key
+    }
+}
+
+/// Renders `op` like `Debug`, except that the payload of an operation tagged
+/// [`SENSITIVE`] is replaced, e.g. `Auth(Login(<redacted>))`.
+pub fn redacted<Op: fmt::Debug + OpMeta>(op: &Op) -> String {
+    let debug = format!("{op:?}");
+    if !op.has_tag(SENSITIVE) {
+        return debug;
+    }
+    let key = op_key(op);
+    let names: Vec<&str> = key.split("::").collect();
+    let bare = nest(&names, None);
+    if bare == debug {
+        // Nothing but variant names, e.g. a unit operation
+        debug
+    } else if key == debug {
+        // No variant names to keep
+        REDACTED.to_string()
+    } else {
+        nest(&names, Some(REDACTED))
+    }
+}
+
+/// `["Auth", "Login"]` with `payload` as `Auth(Login(payload))`.
+fn nest(names: &[&str], payload: Option<&str>) -> String {
+    let mut rendered = names.join("(");
+    let mut closing = names.len() - 1;
+    if let Some(payload) = payload {
+        rendered.push('(');
+        rendered.push_str(payload);
+        closing += 1;
+    }
+    rendered.push_str(&")".repeat(closing));
+    rendered
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        #[tag(sensitive)]
+        Auth::Login ((String, String)) -> u64;
+        #[tag(sensitive)]
+        Auth::Logout -> ();
+        Auth::Refresh ((u64, Secret<String>)) -> u64;
+    }
+
+    #[effectful]
+    fn session() -> u64 {
+        let token: u64 = perform!(Auth::Login(("ada".into(), "hunter2".into())));
+        let token: u64 = perform!(Auth::Refresh((token, Secret("s3cret".into()))));
+        let _: () = perform!(Auth::Logout);
+        token
+    }
+
+    #[test]
+    fn test_sensitive_payloads_are_redacted() {
+        let login = Op::Auth(Auth::Login(("ada".into(), "hunter2".into())));
+        assert_eq!(redacted(&login), "Auth(Login(<redacted>))");
+        assert_eq!(redacted(&Op::Auth(Auth::Logout)), "Auth(Logout)");
+        let refresh = Op::Auth(Auth::Refresh((7, Secret("s3cret".into()))));
+        assert_eq!(redacted(&refresh), "Auth(Refresh((7, <redacted>)))");
+    }
+
+    #[test]
+    fn test_errors_do_not_show_secrets() {
+        let error = session().try_run_with(VecHandler::new()).unwrap_err();
+        let EffectError::Unhandled(unhandled) = &error else {
+            panic!("expected the login to go unhandled");
+        };
+        assert!(unhandled
+            .to_string()
+            .starts_with("Unhandled operation: Auth(Login(<redacted>))"));
+        for rendered in [error.to_string(), format!("{error:?}")] {
+            assert!(!rendered.contains("hunter2"), "{rendered}");
+        }
+    }
+
+    #[cfg(feature = "std")]
+    mod traces {
+        use super::*;
+        use algae::testing::Trace;
+        use std::any::Any;
+
+        struct Tokens;
+
+        impl PartialHandler<Op> for Tokens {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Auth(Auth::Login((_, password))) => Some(Box::new(password.len() as u64)),
+                    Op::Auth(Auth::Refresh((token, Secret(secret)))) => {
+                        Some(Box::new(token + secret.len() as u64))
+                    }
+                    Op::Auth(Auth::Logout) => Some(Box::new(())),
+                }
+            }
+        }
+
+        #[test]
+        fn test_traces_do_not_record_secrets() {
+            let (result, trace) = Trace::record(session(), Tokens);
+            assert_eq!(result.unwrap(), 13);
+            let rendered = trace.to_string();
+            assert!(!rendered.contains("hunter2") && !rendered.contains("s3cret"));
+            assert!(trace.entries()[0].starts_with("Auth(Login(<redacted>)) at "));
+        }
+    }
+}
@@ -0,0 +1,375 @@
+//! Serving the effects of child processes.
+//!
+//! A [`Broker`] in the parent process answers the operations of any number
+//! of clients with a single shared handler, so policies such as rate limits
+//! or auditing apply to all of them in one place. Each child performs its
+//! effects with a [`BrokerClient`], which talks to the broker over a pair of
+//! pipes, usually the child's stdin and stdout.
+//!
+//! Clients are isolated from each other: every client is read and written
+//! on its own threads, and a client that sends garbage or goes away is
+//! dropped without affecting the rest. Requests from all clients wait in
+//! one bounded queue; once it is full, the broker stops reading from
+//! clients until the handler catches up, so their writes block.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! // In the parent
+//! let mut broker = Broker::new(AuditedDb::connect()?, DbCodec);
+//! let workers = (0..4)
+//!     .map(|_| broker.spawn(&mut Command::new("./worker")))
+//!     .collect::<io::Result<Vec<_>>>()?;
+//! broker.run();
+//!
+//! // In ./worker, which must not write anything else to stdout
+//! let result = process_shard().try_run_with(BrokerClient::stdio(DbCodec))?;
+//! ```
+
+use super::{answer, message, read_frame, reply_from, write_frame, Codec, REQUEST};
+use crate::{Abort, PartialHandler};
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    io::{self, Read, Write},
+    process::{Child, Command, Stdio},
+    sync::mpsc,
+    thread,
+};
+
+/// The number of requests that can wait for the handler by default.
+const DEFAULT_MAX_PENDING: usize = 64;
+
+/// Identifies a client of a [`Broker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientId(pub u64);
+
+enum Event {
+    Request(ClientId, Vec<u8>),
+    Disconnected(ClientId),
+}
+
+/// Answers the operations of many clients with one handler.
+pub struct Broker<H, C> {
+    handler: H,
+    codec: C,
+    events_tx: mpsc::SyncSender<Event>,
+    events: mpsc::Receiver<Event>,
+    // Answers are written on a thread per client, so a client that stops
+    // reading cannot hold up the others
+    writers: HashMap<ClientId, mpsc::Sender<Vec<u8>>>,
+    next_id: u64,
+}
+
+impl<H, C> Broker<H, C> {
+    pub fn new(handler: H, codec: C) -> Self {
+        Self::with_max_pending(handler, codec, DEFAULT_MAX_PENDING)
+    }
+
+    /// Creates a broker that stops reading from its clients while
+    /// `max_pending` requests are waiting for the handler.
+    pub fn with_max_pending(handler: H, codec: C, max_pending: usize) -> Self {
+        let (events_tx, events) = mpsc::sync_channel(max_pending);
+        Self {
+            handler,
+            codec,
+            events_tx,
+            events,
+            writers: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Adds a client that sends requests to `reader` and expects answers on
+    /// `writer`.
+    pub fn attach<R, W>(&mut self, mut reader: R, mut writer: W) -> ClientId
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let client = ClientId(self.next_id);
+        self.next_id += 1;
+
+        let events = self.events_tx.clone();
+        thread::spawn(move || {
+            while let Ok(Some(request)) = read_frame(&mut reader) {
+                // Blocks while the queue is full
+                if events.send(Event::Request(client, request)).is_err() {
+                    return;
+                }
+            }
+            let _ = events.send(Event::Disconnected(client));
+        });
+
+        let (answers_tx, answers) = mpsc::channel::<Vec<u8>>();
+        thread::spawn(move || {
+            for answer in answers {
+                if write_frame(&mut writer, &answer).is_err() {
+                    return;
+                }
+            }
+        });
+        self.writers.insert(client, answers_tx);
+        client
+    }
+
+    /// Spawns `command` with piped stdin and stdout and adds it as a client.
+    pub fn spawn(&mut self, command: &mut Command) -> io::Result<(ClientId, Child)> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok((self.attach(stdout, stdin), child))
+    }
+
+    /// The number of clients that have not disconnected yet.
+    pub fn clients(&self) -> usize {
+        self.writers.len()
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+
+    /// Answers requests until every client has disconnected.
+    pub fn run<Op>(&mut self)
+    where
+        H: PartialHandler<Op>,
+        C: Codec<Op>,
+    {
+        while !self.writers.is_empty() {
+            // The broker holds a sender itself, so this never fails
+            let Ok(event) = self.events.recv() else {
+                return;
+            };
+            match event {
+                Event::Request(client, request) => {
+                    let answer = answer(&mut self.handler, &self.codec, &request);
+                    if let Some(writer) = self.writers.get(&client) {
+                        let _ = writer.send(answer);
+                    }
+                }
+                Event::Disconnected(client) => {
+                    self.writers.remove(&client);
+                }
+            }
+        }
+    }
+}
+
+impl<H: fmt::Debug, C> fmt::Debug for Broker<H, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Broker")
+            .field("handler", &self.handler)
+            .field("clients", &self.writers.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A handler that has a [`Broker`] answer its operations.
+///
+/// Operations the broker's handler declines are declined, and its aborts
+/// become aborts carrying a [`RemoteAbort`](super::RemoteAbort). If the
+/// connection fails, the computation is aborted with the `io::Error`.
+pub struct BrokerClient<R, W, C> {
+    reader: R,
+    writer: W,
+    codec: C,
+}
+
+impl<C> BrokerClient<io::Stdin, io::Stdout, C> {
+    /// Talks to the broker over this process's stdin and stdout, as set up
+    /// by [`Broker::spawn`].
+    pub fn stdio(codec: C) -> Self {
+        Self::new(io::stdin(), io::stdout(), codec)
+    }
+}
+
+impl<R, W, C> BrokerClient<R, W, C> {
+    /// Reads answers from `reader` and writes requests to `writer`.
+    pub fn new(reader: R, writer: W, codec: C) -> Self {
+        Self {
+            reader,
+            writer,
+            codec,
+        }
+    }
+}
+
+impl<R, W, C> fmt::Debug for BrokerClient<R, W, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BrokerClient").finish_non_exhaustive()
+    }
+}
+
+impl<Op, R: Read, W: Write, C: Codec<Op>> PartialHandler<Op> for BrokerClient<R, W, C> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let result = (|| {
+            write_frame(
+                &mut self.writer,
+                &message(REQUEST, self.codec.encode_op(op)?),
+            )?;
+            let answer = read_frame(&mut self.reader)?
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            reply_from(&self.codec, op, &answer)
+        })();
+        result.unwrap_or_else(|error| Some(Abort::boxed(error)))
+    }
+}
+
+impl<Op, R, W, C> crate::IntoVecHandler<Op> for BrokerClient<R, W, C>
+where
+    Op: 'static,
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+    C: Codec<Op> + Send + 'static,
+{
+    fn into_vec_handler(self) -> crate::VecHandler<Op> {
+        let mut vec = crate::VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use crate::remote::{protocol_error, RemoteAbort};
+    use algae::prelude::*;
+    use std::io::{PipeReader, PipeWriter};
+
+    effect! {
+        Tally::Add (u64) -> u64;
+        Tally::Reject -> ();
+    }
+
+    struct TallyCodec;
+
+    impl Codec<Op> for TallyCodec {
+        fn encode_op(&self, op: &Op) -> io::Result<Vec<u8>> {
+            Ok(match op {
+                Op::Tally(Tally::Add(n)) => n.to_le_bytes().to_vec(),
+                Op::Tally(Tally::Reject) => Vec::new(),
+            })
+        }
+
+        fn decode_op(&self, bytes: &[u8]) -> io::Result<Op> {
+            match bytes {
+                [] => Ok(Tally::Reject.into()),
+                bytes => bytes
+                    .try_into()
+                    .map(|n| Tally::Add(u64::from_le_bytes(n)).into())
+                    .map_err(|_| protocol_error("bad amount")),
+            }
+        }
+
+        fn encode_reply(&self, _op: &Op, reply: &(dyn Any + Send)) -> io::Result<Vec<u8>> {
+            let total = reply.downcast_ref::<u64>().expect("only Add replies");
+            Ok(total.to_le_bytes().to_vec())
+        }
+
+        fn decode_reply(&self, _op: &Op, bytes: &[u8]) -> io::Result<Box<dyn Any + Send>> {
+            let total = bytes.try_into().map_err(|_| protocol_error("bad total"))?;
+            Ok(Box::new(u64::from_le_bytes(total)))
+        }
+    }
+
+    /// One total shared by every client.
+    #[derive(Debug, Default)]
+    struct TallyHandler {
+        total: u64,
+    }
+
+    impl PartialHandler<Op> for TallyHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Tally(op) = op;
+            Some(match op {
+                Tally::Add(n) => {
+                    self.total += n;
+                    Box::new(self.total)
+                }
+                Tally::Reject => Abort::boxed("rejected"),
+            })
+        }
+    }
+
+    type PipeClient = BrokerClient<PipeReader, PipeWriter, TallyCodec>;
+
+    fn connect(broker: &mut Broker<TallyHandler, TallyCodec>) -> PipeClient {
+        let (requests, to_broker) = io::pipe().unwrap();
+        let (from_broker, answers) = io::pipe().unwrap();
+        broker.attach(requests, answers);
+        BrokerClient::new(from_broker, to_broker, TallyCodec)
+    }
+
+    #[effectful]
+    fn add_all(amounts: Vec<u64>) -> u64 {
+        let mut total = 0;
+        for n in amounts {
+            total = perform!(Tally::Add(n));
+        }
+        total
+    }
+
+    #[test]
+    fn test_clients_share_one_handler() {
+        let mut broker = Broker::with_max_pending(TallyHandler::default(), TallyCodec, 1);
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let client = connect(&mut broker);
+                thread::spawn(move || add_all((1..=10).collect()).try_run_with(client))
+            })
+            .collect();
+        assert_eq!(broker.clients(), 3);
+
+        broker.run();
+        assert_eq!(broker.clients(), 0);
+        assert_eq!(broker.handler().total, 165);
+        let mut totals: Vec<_> = workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap().unwrap())
+            .collect();
+        totals.sort();
+        assert_eq!(totals.last(), Some(&165));
+    }
+
+    #[test]
+    fn test_misbehaving_clients_are_isolated() {
+        #[effectful]
+        fn reject() -> u64 {
+            let _: () = perform!(Tally::Reject);
+            0
+        }
+
+        let mut broker = Broker::new(TallyHandler::default(), TallyCodec);
+
+        let (requests, mut garbage) = io::pipe().unwrap();
+        let (_unread, answers) = io::pipe().unwrap();
+        broker.attach(requests, answers);
+        garbage.write_all(&[0xff; 16]).unwrap();
+        drop(garbage);
+
+        let rejecting = connect(&mut broker);
+        let rejecting = thread::spawn(move || reject().try_run_with(rejecting));
+        let adding = connect(&mut broker);
+        let adding = thread::spawn(move || add_all(vec![2, 3]).try_run_with(adding));
+
+        broker.run();
+        assert_eq!(adding.join().unwrap().unwrap(), 5);
+        match rejecting.join().unwrap() {
+            Err(EffectError::Aborted(abort)) => assert_eq!(
+                abort.downcast_ref::<RemoteAbort>(),
+                Some(&RemoteAbort("\"rejected\"".into()))
+            ),
+            other => panic!("expected a remote abort, got {other:?}"),
+        }
+    }
+}
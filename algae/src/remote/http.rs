@@ -0,0 +1,453 @@
+//! An HTTP transport for remote handlers.
+//!
+//! [`serve_effects_http`] hosts one handler for all of its clients, so a
+//! single service can carry out the side effects of many programs, with
+//! policies such as rate limits or auditing applied in one place.
+//! [`HttpRemoteHandler`] is the client side: it `POST`s each operation and
+//! reads the answer from the response body.
+//!
+//! Only what the two sides need of HTTP/1.1 is implemented: bodies with a
+//! `Content-Length` and kept-alive connections, without TLS. Put a reverse
+//! proxy in front of the server to expose it beyond a trusted network.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! // The service, owning the database connection
+//! let listener = TcpListener::bind("127.0.0.1:8080")?;
+//! serve_effects_http::<Op, _, _>(listener, JsonCodec, AuditedDb::connect()?)?;
+//!
+//! // Any number of clients
+//! let remote = HttpRemoteHandler::new("http://127.0.0.1:8080/effects", JsonCodec)?;
+//! let user = load_user(id).try_run_with(remote)?;
+//! ```
+
+use super::{answer, message, protocol_error, reply_from, Codec, MAX_FRAME_LEN, REQUEST};
+use crate::{Abort, PartialHandler};
+use std::{
+    any::Any,
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+};
+
+/// Request and status lines plus headers longer than this end the
+/// connection.
+const MAX_HEAD_LEN: usize = 16 << 10;
+
+const CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The start line and headers of a request or response.
+struct Head {
+    start: String,
+    /// Names are lowercased.
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the sender closes the connection after this message.
+    fn closes(&self) -> bool {
+        self.start.starts_with("HTTP/1.0")
+            || self.start.ends_with("HTTP/1.0")
+            || self
+                .header("connection")
+                .is_some_and(|value| value.eq_ignore_ascii_case("close"))
+    }
+}
+
+/// Reads the next head, or `None` if the stream ended between messages.
+fn read_head(reader: &mut impl BufRead) -> io::Result<Option<Head>> {
+    let mut lines = Vec::new();
+    let mut len = 0;
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .by_ref()
+            .take((MAX_HEAD_LEN - len) as u64)
+            .read_line(&mut line)?;
+        if read == 0 {
+            if len == 0 {
+                return Ok(None);
+            }
+            return Err(protocol_error("HTTP head too large or cut short"));
+        }
+        len += read;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line.to_string());
+    }
+    let mut lines = lines.into_iter();
+    let start = lines
+        .next()
+        .ok_or_else(|| protocol_error("empty HTTP head"))?;
+    let headers = lines
+        .map(|line| {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| protocol_error("malformed HTTP header"))?;
+            Ok((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(Some(Head { start, headers }))
+}
+
+fn read_body(reader: &mut impl Read, head: &Head) -> io::Result<Vec<u8>> {
+    if head.header("transfer-encoding").is_some() {
+        return Err(protocol_error("HTTP bodies need a Content-Length"));
+    }
+    let len = match head.header("content-length") {
+        Some(len) => len
+            .parse::<usize>()
+            .map_err(|_| protocol_error("malformed Content-Length"))?,
+        None => 0,
+    };
+    if len > MAX_FRAME_LEN {
+        return Err(protocol_error("message too large"));
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+fn write_response(writer: &mut impl Write, status: &str, body: &[u8]) -> io::Result<()> {
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    writer.write_all(&response)?;
+    writer.flush()
+}
+
+/// Accepts HTTP connections on `listener` and answers the operations
+/// `POST`ed over any of them with `handler`.
+///
+/// Every connection is served on its own thread, and the handler answers
+/// one operation at a time. Requests with another method are refused with
+/// `405 Method Not Allowed`, whatever their path. Only returns if accepting
+/// a connection fails.
+pub fn serve_effects_http<Op, H, C>(listener: TcpListener, codec: C, handler: H) -> io::Result<()>
+where
+    H: PartialHandler<Op> + Send + 'static,
+    C: Codec<Op> + Clone + Send + 'static,
+{
+    let handler = Arc::new(Mutex::new(handler));
+    loop {
+        let (stream, _) = listener.accept()?;
+        let codec = codec.clone();
+        let handler = Arc::clone(&handler);
+        // A failing connection only affects its own client
+        thread::spawn(move || serve_connection(stream, &codec, &handler));
+    }
+}
+
+/// Answers requests until the client disconnects, asks to close, or sends
+/// something that is not a request.
+fn serve_connection<Op, H, C>(stream: TcpStream, codec: &C, handler: &Mutex<H>) -> io::Result<()>
+where
+    H: PartialHandler<Op>,
+    C: Codec<Op>,
+{
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream);
+    while let Some(head) = read_head(&mut reader)? {
+        let body = match read_body(&mut reader, &head) {
+            Ok(body) => body,
+            Err(error) => {
+                write_response(
+                    reader.get_mut(),
+                    "400 Bad Request",
+                    error.to_string().as_bytes(),
+                )?;
+                return Ok(());
+            }
+        };
+        if head.start.starts_with("POST ") {
+            // A handler that panicked on another connection leaves the
+            // lock poisoned, but the service keeps answering
+            let mut handler = handler.lock().unwrap_or_else(PoisonError::into_inner);
+            let answer = answer(&mut *handler, codec, &body);
+            drop(handler);
+            write_response(reader.get_mut(), "200 OK", &answer)?;
+        } else {
+            write_response(reader.get_mut(), "405 Method Not Allowed", b"")?;
+        }
+        if head.closes() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// A handler that `POST`s operations to a server started with
+/// [`serve_effects_http`].
+///
+/// The connection is opened on the first operation and kept alive between
+/// operations; after a failure, the next operation opens a new one.
+/// Operations the server declines are declined, and its aborts become
+/// aborts carrying a [`RemoteAbort`](super::RemoteAbort). If the request
+/// fails, or the server answers with another status than `200 OK`, the
+/// computation is aborted with an `io::Error`.
+pub struct HttpRemoteHandler<C> {
+    /// `host:port`, also sent as the `Host` header.
+    authority: String,
+    path: String,
+    codec: C,
+    connection: Option<BufReader<TcpStream>>,
+}
+
+impl<C> HttpRemoteHandler<C> {
+    /// Sends operations to an `http://` URL, e.g.
+    /// `http://127.0.0.1:8080/effects`.
+    pub fn new(url: &str, codec: C) -> io::Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only http:// URLs are supported",
+            )
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "URL has no host",
+            ));
+        }
+        // The port follows the last colon, unless that is inside an IPv6
+        // address
+        let authority = if authority
+            .rsplit_once(':')
+            .is_some_and(|(_, p)| !p.contains(']'))
+        {
+            authority.to_string()
+        } else {
+            format!("{authority}:80")
+        };
+        Ok(Self {
+            authority,
+            path: path.to_string(),
+            codec,
+            connection: None,
+        })
+    }
+
+    fn request<Op>(&mut self, op: &Op) -> io::Result<Option<Box<dyn Any + Send>>>
+    where
+        C: Codec<Op>,
+    {
+        let body = message(REQUEST, self.codec.encode_op(op)?);
+        let answer = self.exchange(&body);
+        if answer.is_err() {
+            self.connection = None;
+        }
+        reply_from(&self.codec, op, &answer?)
+    }
+
+    /// Sends one request and returns the body of its response.
+    fn exchange(&mut self, body: &[u8]) -> io::Result<Vec<u8>> {
+        let reader = match &mut self.connection {
+            Some(reader) => reader,
+            None => {
+                let stream = TcpStream::connect(&self.authority)?;
+                stream.set_nodelay(true)?;
+                self.connection.insert(BufReader::new(stream))
+            }
+        };
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\n\r\n",
+            self.path,
+            self.authority,
+            body.len()
+        )
+        .into_bytes();
+        request.extend_from_slice(body);
+        reader.get_mut().write_all(&request)?;
+        reader.get_mut().flush()?;
+
+        let head =
+            read_head(reader)?.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        let response = read_body(reader, &head)?;
+        if head.start.split(' ').nth(1) != Some("200") {
+            return Err(io::Error::other(format!(
+                "server answered {:?}",
+                head.start
+            )));
+        }
+        if head.closes() {
+            self.connection = None;
+        }
+        Ok(response)
+    }
+}
+
+impl<C> fmt::Debug for HttpRemoteHandler<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpRemoteHandler")
+            .field("authority", &self.authority)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Op, C: Codec<Op>> PartialHandler<Op> for HttpRemoteHandler<C> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.request(op)
+            .unwrap_or_else(|error| Some(Abort::boxed(error)))
+    }
+}
+
+impl<Op: 'static, C: Codec<Op> + Send + 'static> crate::IntoVecHandler<Op>
+    for HttpRemoteHandler<C>
+{
+    fn into_vec_handler(self) -> crate::VecHandler<Op> {
+        let mut vec = crate::VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+#[cfg(all(test, feature = "macros", feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use crate::remote::{json::JsonCodec, RemoteAbort};
+    use algae::prelude::*;
+
+    effect! {
+        serde;
+        Counter::Add (u64) -> u64;
+        Counter::Fail -> ();
+        Counter::Local -> String;
+    }
+
+    /// One total for every client; declines `Local` and aborts `Fail`.
+    #[derive(Default)]
+    struct CounterService {
+        total: u64,
+    }
+
+    impl PartialHandler<Op> for CounterService {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Counter(Counter::Add(n)) => {
+                    self.total += n;
+                    Some(Box::new(self.total))
+                }
+                Op::Counter(Counter::Fail) => Some(Abort::boxed("counter broke")),
+                Op::Counter(Counter::Local) => None,
+            }
+        }
+    }
+
+    struct LocalHandler;
+
+    impl PartialHandler<Op> for LocalHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Counter(Counter::Local) => Some(Box::new("local".to_string())),
+                _ => None,
+            }
+        }
+    }
+
+    impl IntoVecHandler<Op> for LocalHandler {
+        fn into_vec_handler(self) -> VecHandler<Op> {
+            let mut vec = VecHandler::new();
+            vec.push(self);
+            vec
+        }
+    }
+
+    fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/effects", listener.local_addr().unwrap());
+        thread::spawn(move || serve_effects_http(listener, JsonCodec, CounterService::default()));
+        url
+    }
+
+    #[effectful]
+    fn add_all(amounts: Vec<u64>) -> u64 {
+        let mut total = 0;
+        for n in amounts {
+            total = perform!(Counter::Add(n));
+        }
+        total
+    }
+
+    #[test]
+    fn test_clients_share_the_service_handler() {
+        let url = start_server();
+        let remote = HttpRemoteHandler::new(&url, JsonCodec).unwrap();
+        assert_eq!(add_all(vec![1, 2, 3]).try_run_with(remote).unwrap(), 6);
+
+        let remote = HttpRemoteHandler::new(&url, JsonCodec).unwrap();
+        assert_eq!(add_all(vec![10]).try_run_with(remote).unwrap(), 16);
+    }
+
+    #[test]
+    fn test_declines_and_aborts_cross_the_connection() {
+        #[effectful]
+        fn local_then_fail() -> String {
+            let name: String = perform!(Counter::Local);
+            let _: () = perform!(Counter::Fail);
+            name
+        }
+
+        let result = local_then_fail()
+            .begin_chain()
+            .handle(HttpRemoteHandler::new(&start_server(), JsonCodec).unwrap())
+            .handle(LocalHandler)
+            .run();
+        match result {
+            Err(EffectError::Aborted(abort)) => assert_eq!(
+                abort.downcast_ref::<RemoteAbort>(),
+                Some(&RemoteAbort("\"counter broke\"".into()))
+            ),
+            other => panic!("expected a remote abort, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_other_methods_are_refused() {
+        let url = start_server();
+        let authority = url
+            .trim_start_matches("http://")
+            .trim_end_matches("/effects");
+        let mut stream = TcpStream::connect(authority).unwrap();
+        stream
+            .write_all(b"GET /effects HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+
+        let remote = HttpRemoteHandler::new(&format!("http://{authority}"), JsonCodec).unwrap();
+        assert_eq!(add_all(vec![4]).try_run_with(remote).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_urls_are_parsed() {
+        let remote = HttpRemoteHandler::new("http://example.com", JsonCodec).unwrap();
+        assert_eq!((&*remote.authority, &*remote.path), ("example.com:80", "/"));
+        let remote = HttpRemoteHandler::new("http://[::1]:9000/a/b", JsonCodec).unwrap();
+        assert_eq!((&*remote.authority, &*remote.path), ("[::1]:9000", "/a/b"));
+        let remote = HttpRemoteHandler::new("http://[::1]/", JsonCodec).unwrap();
+        assert_eq!(&*remote.authority, "[::1]:80");
+        assert!(HttpRemoteHandler::new("https://example.com", JsonCodec).is_err());
+    }
+}
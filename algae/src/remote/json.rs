@@ -0,0 +1,105 @@
+//! A JSON codec for operations declared with a `serde;` header.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! effect! {
+//!     serde;
+//!     Db::Get (String) -> Option<String>;
+//!     Db::Put ((String, String)) -> ();
+//! }
+//!
+//! // The server, e.g. a process owning the database connection
+//! serve_effects_tcp::<Op, _, _, _>(TcpListener::bind("127.0.0.1:7000")?, JsonCodec, || {
+//!     DbHandler::connect()
+//! })?;
+//!
+//! // The client
+//! let remote = TcpRemoteHandler::connect("127.0.0.1:7000", JsonCodec)?;
+//! let user = load_user(id).try_run_with(remote)?;
+//! ```
+
+use super::{Codec, SerdeOp};
+use std::{any::Any, io};
+
+/// Encodes operations and their replies as JSON.
+///
+/// Works for any operation implementing [`SerdeOp`]. Replies are encoded as
+/// the operation's declared reply type, so a handler answering with another
+/// type fails the encoding, and the client's computation is aborted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl<Op: SerdeOp> Codec<Op> for JsonCodec {
+    fn encode_op(&self, op: &Op) -> io::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(op)?)
+    }
+
+    fn decode_op(&self, bytes: &[u8]) -> io::Result<Op> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+
+    fn encode_reply(&self, op: &Op, reply: &(dyn Any + Send)) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        op.serialize_reply(reply, &mut serde_json::Serializer::new(&mut bytes))?;
+        Ok(bytes)
+    }
+
+    fn decode_reply(&self, op: &Op, bytes: &[u8]) -> io::Result<Box<dyn Any + Send>> {
+        let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+        let reply = op.deserialize_reply(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(reply)
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        serde;
+        root DbOp;
+        Db::Get (String) -> Option<String>;
+        Db::Put ((String, String)) -> ();
+        Audit::Count -> u64;
+    }
+
+    #[test]
+    fn test_operations_round_trip() {
+        let codec = JsonCodec;
+        let op = DbOp::db_put("name".to_string(), "Ada".to_string());
+        let bytes = codec.encode_op(&op).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&bytes),
+            r#"{"Db":{"Put":["name","Ada"]}}"#
+        );
+        assert_eq!(Codec::<DbOp>::decode_op(&codec, &bytes).unwrap(), op);
+        assert!(Codec::<DbOp>::decode_op(&codec, b"{\"Db\":{\"Drop\":1}}").is_err());
+    }
+
+    #[test]
+    fn test_replies_use_the_declared_type() {
+        let codec = JsonCodec;
+        let get = DbOp::db_get("name".to_string());
+        let bytes = codec.encode_reply(&get, &Some("Ada".to_string())).unwrap();
+        assert_eq!(bytes, br#""Ada""#);
+        let reply = codec.decode_reply(&get, &bytes).unwrap();
+        assert_eq!(
+            *reply.downcast::<Option<String>>().unwrap(),
+            Some("Ada".to_string())
+        );
+
+        let count = DbOp::audit_count();
+        let reply = codec.decode_reply(&count, b"7").unwrap();
+        assert_eq!(*reply.downcast::<u64>().unwrap(), 7);
+        assert!(codec.decode_reply(&count, b"7 8").is_err());
+
+        let error = codec.encode_reply(&count, &"seven").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("reply to Audit.Count is not a `u64`"));
+    }
+}
@@ -0,0 +1,168 @@
+//! Handlers in another process.
+//!
+//! A remote handler sends each operation to a server, which answers it with
+//! a local handler and sends the reply back. Operations and replies cross
+//! the connection as bytes produced by a [`Codec`]; a reply that the
+//! server's handler aborts with becomes a [`RemoteAbort`] on the client, and
+//! an operation it declines is declined by the client too, so handlers
+//! chained after a remote one still get a chance to answer it.
+//!
+//! [`broker`] serves the effects of child processes over pipes, [`tcp`]
+//! over TCP sockets, and [`http`] hosts one handler as an HTTP service. With
+//! the `websocket` feature, [`ws`] provides a WebSocket transport. With the `serde` feature, operations declared with
+//! a `serde;` header implement [`SerdeOp`], and [`json::JsonCodec`] sends
+//! them without a hand-written codec.
+
+use crate::{Abort, PartialHandler};
+use std::{
+    any::Any,
+    io::{self, Read, Write},
+};
+
+pub mod broker;
+pub mod http;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod tcp;
+#[cfg(feature = "websocket")]
+pub mod ws;
+
+// The first byte of every message says what it carries. A client sends one
+// request and waits for its answer, so answers need no ids.
+pub(crate) const REQUEST: u8 = 0;
+pub(crate) const REPLY: u8 = 1;
+pub(crate) const DECLINED: u8 = 2;
+pub(crate) const ABORTED: u8 = 3;
+#[cfg(feature = "websocket")]
+pub(crate) const PUSH: u8 = 4;
+
+/// Converts operations and their replies to and from bytes.
+///
+/// The reply methods get the operation being answered, so they know which
+/// reply type to expect.
+pub trait Codec<Op> {
+    fn encode_op(&self, op: &Op) -> io::Result<Vec<u8>>;
+
+    fn decode_op(&self, bytes: &[u8]) -> io::Result<Op>;
+
+    fn encode_reply(&self, op: &Op, reply: &(dyn Any + Send)) -> io::Result<Vec<u8>>;
+
+    fn decode_reply(&self, op: &Op, bytes: &[u8]) -> io::Result<Box<dyn Any + Send>>;
+}
+
+/// Operations whose replies can be serialized, implemented by `effect!` for
+/// the enums of a block with a `serde;` header.
+///
+/// The operation says which type its reply has, so these methods do for
+/// replies what `Serialize` and `Deserialize` do for the operation itself.
+#[cfg(feature = "serde")]
+pub trait SerdeOp: serde::Serialize + serde::de::DeserializeOwned {
+    /// Serializes `reply`, failing if it is not of the operation's reply
+    /// type.
+    fn serialize_reply<S: serde::Serializer>(
+        &self,
+        reply: &(dyn Any + Send),
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>;
+
+    /// Deserializes a reply of the operation's reply type.
+    fn deserialize_reply<'de, D: serde::Deserializer<'de>>(
+        &self,
+        deserializer: D,
+    ) -> Result<Box<dyn Any + Send>, D::Error>;
+}
+
+/// The error carried by an abort from a server's handler.
+///
+/// Holds the [`description`](crate::Abort::description) of the original
+/// abort, since the error itself cannot cross the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteAbort(pub String);
+
+/// Frames longer than this end the connection.
+pub(crate) const MAX_FRAME_LEN: usize = 64 << 20;
+
+/// Writes `bytes` as one frame: its length as a little-endian `u32`, then
+/// the bytes themselves.
+pub(crate) fn write_frame(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(bytes)?;
+    writer.flush()
+}
+
+/// Reads the next frame, or `None` if the stream ended between frames.
+pub(crate) fn read_frame(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message too large",
+        ));
+    }
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+/// A message made of `tag` followed by `body`.
+pub(crate) fn message(tag: u8, body: impl AsRef<[u8]>) -> Vec<u8> {
+    let body = body.as_ref();
+    let mut bytes = Vec::with_capacity(1 + body.len());
+    bytes.push(tag);
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+pub(crate) fn protocol_error(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+/// Handles one request and builds the message answering it.
+pub(crate) fn answer<Op, H, C>(handler: &mut H, codec: &C, request: &[u8]) -> Vec<u8>
+where
+    H: PartialHandler<Op>,
+    C: Codec<Op>,
+{
+    let [REQUEST, body @ ..] = request else {
+        return message(ABORTED, "malformed request");
+    };
+    let op = match codec.decode_op(body) {
+        Ok(op) => op,
+        Err(error) => return message(ABORTED, format!("cannot decode operation: {error}")),
+    };
+    let Some(reply) = handler.maybe_handle(&op) else {
+        return message(DECLINED, []);
+    };
+    match reply.downcast::<Abort>() {
+        Ok(abort) => message(ABORTED, abort.description()),
+        Err(reply) => match codec.encode_reply(&op, &*reply) {
+            Ok(bytes) => message(REPLY, bytes),
+            Err(error) => message(ABORTED, format!("cannot encode reply: {error}")),
+        },
+    }
+}
+
+/// Turns the server's answer to `op` into what a client handler returns.
+pub(crate) fn reply_from<Op, C: Codec<Op>>(
+    codec: &C,
+    op: &Op,
+    answer: &[u8],
+) -> io::Result<Option<Box<dyn Any + Send>>> {
+    match answer.split_first() {
+        Some((&REPLY, body)) => codec.decode_reply(op, body).map(Some),
+        Some((&DECLINED, _)) => Ok(None),
+        Some((&ABORTED, body)) => {
+            let description = String::from_utf8_lossy(body).into_owned();
+            Ok(Some(Abort::boxed(RemoteAbort(description))))
+        }
+        _ => Err(protocol_error("unexpected message from server")),
+    }
+}
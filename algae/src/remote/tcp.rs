@@ -0,0 +1,230 @@
+//! A TCP transport for remote handlers.
+//!
+//! [`serve_effects_tcp`] hosts a handler: every connection gets its own,
+//! built by a factory, and keeps it for as long as it stays open.
+//! [`TcpRemoteHandler`] is the client side. Messages are framed like the
+//! [`broker`](super::broker)'s, so a `TcpRemoteHandler` can also talk to a
+//! broker that [`attach`](super::broker::Broker::attach)es the accepted
+//! stream.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! // The server, e.g. a process owning the database connection
+//! let listener = TcpListener::bind("127.0.0.1:7000")?;
+//! serve_effects_tcp(listener, DbCodec, || DbHandler::connect())?;
+//!
+//! // The client
+//! let remote = TcpRemoteHandler::connect("127.0.0.1:7000", DbCodec)?;
+//! let user = load_user(id).try_run_with(remote)?;
+//! ```
+
+use super::{answer, message, read_frame, reply_from, write_frame, Codec, REQUEST};
+use crate::{Abort, PartialHandler};
+use std::{
+    any::Any,
+    fmt, io,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+};
+
+/// Accepts connections on `listener` and answers the operations sent over
+/// each one with a handler from `make_handler`.
+///
+/// Every connection is served on its own thread. Only returns if accepting
+/// a connection fails.
+pub fn serve_effects_tcp<Op, H, C, F>(
+    listener: TcpListener,
+    codec: C,
+    make_handler: F,
+) -> io::Result<()>
+where
+    H: PartialHandler<Op>,
+    C: Codec<Op> + Clone + Send + 'static,
+    F: Fn() -> H + Send + Sync + 'static,
+{
+    let make_handler = Arc::new(make_handler);
+    loop {
+        let (stream, _) = listener.accept()?;
+        let codec = codec.clone();
+        let make_handler = Arc::clone(&make_handler);
+        // A failing connection only affects its own client
+        thread::spawn(move || serve_connection(stream, &codec, make_handler()));
+    }
+}
+
+/// Answers requests until the client disconnects or the connection fails.
+fn serve_connection<Op, H, C>(mut stream: TcpStream, codec: &C, mut handler: H) -> io::Result<()>
+where
+    H: PartialHandler<Op>,
+    C: Codec<Op>,
+{
+    stream.set_nodelay(true)?;
+    while let Some(request) = read_frame(&mut stream)? {
+        write_frame(&mut stream, &answer(&mut handler, codec, &request))?;
+    }
+    Ok(())
+}
+
+/// A handler that sends operations to a server started with
+/// [`serve_effects_tcp`].
+///
+/// Operations the server declines are declined, and its aborts become
+/// aborts carrying a [`RemoteAbort`](super::RemoteAbort). If the connection
+/// fails, the computation is aborted with the `io::Error`.
+pub struct TcpRemoteHandler<C> {
+    stream: TcpStream,
+    codec: C,
+}
+
+impl<C> TcpRemoteHandler<C> {
+    /// Connects to the server at `addr`.
+    pub fn connect(addr: impl ToSocketAddrs, codec: C) -> io::Result<Self> {
+        Self::from_stream(TcpStream::connect(addr)?, codec)
+    }
+
+    /// Sends operations over an already connected `stream`.
+    pub fn from_stream(stream: TcpStream, codec: C) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, codec })
+    }
+
+    fn request<Op>(&mut self, op: &Op) -> io::Result<Option<Box<dyn Any + Send>>>
+    where
+        C: Codec<Op>,
+    {
+        write_frame(
+            &mut self.stream,
+            &message(REQUEST, self.codec.encode_op(op)?),
+        )?;
+        let answer = read_frame(&mut self.stream)?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        reply_from(&self.codec, op, &answer)
+    }
+}
+
+impl<C> fmt::Debug for TcpRemoteHandler<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpRemoteHandler")
+            .field("peer", &self.stream.peer_addr().ok())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Op, C: Codec<Op>> PartialHandler<Op> for TcpRemoteHandler<C> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.request(op)
+            .unwrap_or_else(|error| Some(Abort::boxed(error)))
+    }
+}
+
+impl<Op: 'static, C: Codec<Op> + Send + 'static> crate::IntoVecHandler<Op> for TcpRemoteHandler<C> {
+    fn into_vec_handler(self) -> crate::VecHandler<Op> {
+        let mut vec = crate::VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+#[cfg(all(test, feature = "macros", feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use crate::remote::{json::JsonCodec, RemoteAbort};
+    use algae::prelude::*;
+
+    effect! {
+        serde;
+        Counter::Add (u64) -> u64;
+        Counter::Fail -> ();
+        Counter::Local -> String;
+    }
+
+    /// Keeps a total per connection; declines `Local` and aborts `Fail`.
+    #[derive(Default)]
+    struct CounterServer {
+        total: u64,
+    }
+
+    impl PartialHandler<Op> for CounterServer {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Counter(Counter::Add(n)) => {
+                    self.total += n;
+                    Some(Box::new(self.total))
+                }
+                Op::Counter(Counter::Fail) => Some(Abort::boxed("counter broke")),
+                Op::Counter(Counter::Local) => None,
+            }
+        }
+    }
+
+    fn start_server() -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve_effects_tcp(listener, JsonCodec, CounterServer::default));
+        addr
+    }
+
+    #[effectful]
+    fn add_twice(n: u64) -> u64 {
+        let _: u64 = perform!(Counter::Add(n));
+        perform!(Counter::Add(n))
+    }
+
+    #[test]
+    fn test_connections_keep_their_own_handler() {
+        let addr = start_server();
+        let remote = TcpRemoteHandler::connect(addr, JsonCodec).unwrap();
+        let mut session = crate::session::HandlerSession::new(remote);
+        assert_eq!(session.try_run(add_twice(2)).unwrap(), 4);
+        assert_eq!(session.try_run(add_twice(3)).unwrap(), 10);
+
+        let other = TcpRemoteHandler::connect(addr, JsonCodec).unwrap();
+        assert_eq!(add_twice(5).try_run_with(other).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_declines_and_aborts_cross_the_connection() {
+        /// Answers what the server declines.
+        struct LocalHandler;
+
+        impl PartialHandler<Op> for LocalHandler {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Counter(Counter::Local) => Some(Box::new("local".to_string())),
+                    _ => None,
+                }
+            }
+        }
+
+        impl IntoVecHandler<Op> for LocalHandler {
+            fn into_vec_handler(self) -> VecHandler<Op> {
+                let mut vec = VecHandler::new();
+                vec.push(self);
+                vec
+            }
+        }
+
+        #[effectful]
+        fn local_then_fail() -> String {
+            let name: String = perform!(Counter::Local);
+            let _: () = perform!(Counter::Fail);
+            name
+        }
+
+        let result = local_then_fail()
+            .begin_chain()
+            .handle(TcpRemoteHandler::connect(start_server(), JsonCodec).unwrap())
+            .handle(LocalHandler)
+            .run();
+        match result {
+            Err(EffectError::Aborted(abort)) => assert_eq!(
+                abort.downcast_ref::<RemoteAbort>(),
+                Some(&RemoteAbort("\"counter broke\"".into()))
+            ),
+            other => panic!("expected a remote abort, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,503 @@
+//! A WebSocket transport for remote handlers.
+//!
+//! [`serve_effects_ws`] hosts a handler: every connection gets its own,
+//! built by a factory, and keeps it for as long as it stays open, so the
+//! server can hold per-client state. [`WsRemoteHandler`] is the client side.
+//!
+//! Besides answering operations, a server can push messages to its client
+//! at any time through the connection's [`Pusher`], e.g. for subscription
+//! effects where an operation registers interest and the events follow
+//! later. The client reads them from [`WsRemoteHandler::pushes`].
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! // The server, e.g. a long-lived backend process
+//! let listener = TcpListener::bind("127.0.0.1:9001")?;
+//! serve_effects_ws(listener, MyCodec, |pusher| FeedHandler::new(pusher))?;
+//!
+//! // The client, e.g. a TUI front-end
+//! let remote = WsRemoteHandler::connect("ws://127.0.0.1:9001", MyCodec)?;
+//! let pushes = remote.pushes();
+//! subscribe_to_feed().try_run_with(remote)?;
+//! while let Some(event) = pushes.recv_timeout(Duration::from_secs(1))? {
+//!     render(&event);
+//! }
+//! ```
+
+use super::{answer, message, protocol_error, reply_from, Codec, PUSH, REQUEST};
+use crate::{Abort, PartialHandler};
+use std::{
+    any::Any,
+    collections::VecDeque,
+    convert::Infallible,
+    fmt, io,
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use tungstenite::{client::IntoClientRequest, Message, WebSocket};
+
+/// How long a blocked reader waits before checking for pushes to send or
+/// for a deadline.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+type Socket = WebSocket<TcpStream>;
+
+fn io_error(error: tungstenite::Error) -> io::Error {
+    match error {
+        tungstenite::Error::Io(error) => error,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            io::ErrorKind::ConnectionAborted.into()
+        }
+        error => io::Error::other(error),
+    }
+}
+
+fn is_timeout(error: &tungstenite::Error) -> bool {
+    matches!(
+        error,
+        tungstenite::Error::Io(e)
+            if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+    )
+}
+
+fn is_closed(error: &tungstenite::Error) -> bool {
+    matches!(
+        error,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed
+    )
+}
+
+/// Reads the next binary message. `Ok(None)` means the read timed out.
+fn read_binary(socket: &mut Socket) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        match socket.read() {
+            Ok(Message::Binary(bytes)) => return Ok(Some(bytes.to_vec())),
+            Ok(Message::Close(_)) => return Err(io::ErrorKind::ConnectionAborted.into()),
+            Ok(_) => {}
+            Err(error) if is_timeout(&error) => return Ok(None),
+            Err(error) => return Err(io_error(error)),
+        }
+    }
+}
+
+/// Sends messages from a server's handler to its client.
+///
+/// Clones push to the same connection.
+#[derive(Debug, Clone)]
+pub struct Pusher {
+    tx: mpsc::Sender<Vec<u8>>,
+}
+
+impl Pusher {
+    /// Queues `bytes` for the client. Returns `false` once the connection
+    /// has closed, so producers know when to stop.
+    pub fn push(&self, bytes: impl Into<Vec<u8>>) -> bool {
+        self.tx.send(bytes.into()).is_ok()
+    }
+}
+
+/// Accepts WebSocket connections on `listener` and answers the operations
+/// sent over each one with a handler from `make_handler`.
+///
+/// Every connection is served on its own thread. Pushes queued through a
+/// connection's [`Pusher`] while an operation is handled are sent after its
+/// reply. Only returns if accepting a connection fails.
+pub fn serve_effects_ws<Op, H, C, F>(
+    listener: TcpListener,
+    codec: C,
+    make_handler: F,
+) -> io::Result<()>
+where
+    H: PartialHandler<Op>,
+    C: Codec<Op> + Clone + Send + 'static,
+    F: Fn(Pusher) -> H + Send + Sync + 'static,
+{
+    let make_handler = Arc::new(make_handler);
+    loop {
+        let (stream, _) = listener.accept()?;
+        let codec = codec.clone();
+        let make_handler = Arc::clone(&make_handler);
+        // A failing connection only affects its own client
+        thread::spawn(move || serve_connection(stream, &codec, &*make_handler));
+    }
+}
+
+fn serve_connection<Op, H, C>(
+    stream: TcpStream,
+    codec: &C,
+    make_handler: &dyn Fn(Pusher) -> H,
+) -> io::Result<()>
+where
+    H: PartialHandler<Op>,
+    C: Codec<Op>,
+{
+    stream.set_nodelay(true)?;
+    let mut socket = tungstenite::accept(stream).map_err(|e| io::Error::other(e.to_string()))?;
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    let (tx, pushes) = mpsc::channel();
+    let mut handler = make_handler(Pusher { tx });
+    match serve_requests(&mut socket, &mut handler, codec, &pushes) {
+        Err(error) if is_closed(&error) => Ok(()),
+        Err(error) => Err(io_error(error)),
+    }
+}
+
+/// Answers requests and sends pushes until the connection fails or closes.
+fn serve_requests<Op, H, C>(
+    socket: &mut Socket,
+    handler: &mut H,
+    codec: &C,
+    pushes: &mpsc::Receiver<Vec<u8>>,
+) -> Result<Infallible, tungstenite::Error>
+where
+    H: PartialHandler<Op>,
+    C: Codec<Op>,
+{
+    loop {
+        for push in pushes.try_iter() {
+            socket.send(Message::binary(message(PUSH, push)))?;
+        }
+        match socket.read() {
+            Ok(Message::Binary(request)) => {
+                socket.send(Message::binary(answer(handler, codec, &request)))?
+            }
+            Ok(_) => {}
+            Err(error) if is_timeout(&error) => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Messages pushed by the server, shared by a [`WsRemoteHandler`] and the
+/// [`Pushes`] handles taken from it.
+struct Connection {
+    socket: Mutex<Socket>,
+    pushes: Mutex<VecDeque<Vec<u8>>>,
+}
+
+/// A handler that sends operations to a server started with
+/// [`serve_effects_ws`].
+///
+/// Operations the server declines are declined, and its aborts become
+/// aborts carrying a [`RemoteAbort`](crate::remote::RemoteAbort). If the
+/// connection fails, the computation is aborted with the `io::Error`.
+pub struct WsRemoteHandler<C> {
+    connection: Arc<Connection>,
+    codec: C,
+}
+
+impl<C> WsRemoteHandler<C> {
+    /// Connects to the server at a `ws://` URL.
+    pub fn connect(url: &str, codec: C) -> io::Result<Self> {
+        let request = url.into_client_request().map_err(io_error)?;
+        let host = request
+            .uri()
+            .host()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "URL has no host"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let port = request.uri().port_u16().unwrap_or(80);
+        let stream = TcpStream::connect((host, port))?;
+        stream.set_nodelay(true)?;
+        let (socket, _) =
+            tungstenite::client(request, stream).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(Self {
+            connection: Arc::new(Connection {
+                socket: Mutex::new(socket),
+                pushes: Mutex::new(VecDeque::new()),
+            }),
+            codec,
+        })
+    }
+
+    /// A handle for receiving the messages the server pushes.
+    pub fn pushes(&self) -> Pushes {
+        Pushes {
+            connection: Arc::clone(&self.connection),
+        }
+    }
+
+    fn request<Op>(&self, op: &Op) -> io::Result<Option<Box<dyn Any + Send>>>
+    where
+        C: Codec<Op>,
+    {
+        let request = message(REQUEST, self.codec.encode_op(op)?);
+        let mut socket = self.connection.socket.lock().unwrap();
+        socket.get_ref().set_read_timeout(None)?;
+        socket.send(Message::binary(request)).map_err(io_error)?;
+        loop {
+            let Some(answer) = read_binary(&mut socket)? else {
+                continue;
+            };
+            match answer.split_first() {
+                Some((&PUSH, body)) => self
+                    .connection
+                    .pushes
+                    .lock()
+                    .unwrap()
+                    .push_back(body.to_vec()),
+                _ => return reply_from(&self.codec, op, &answer),
+            }
+        }
+    }
+}
+
+impl<C> fmt::Debug for WsRemoteHandler<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsRemoteHandler").finish_non_exhaustive()
+    }
+}
+
+impl<Op, C: Codec<Op>> PartialHandler<Op> for WsRemoteHandler<C> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.request(op)
+            .unwrap_or_else(|error| Some(Abort::boxed(error)))
+    }
+}
+
+impl<Op: 'static, C: Codec<Op> + Send + 'static> crate::IntoVecHandler<Op> for WsRemoteHandler<C> {
+    fn into_vec_handler(self) -> crate::VecHandler<Op> {
+        let mut vec = crate::VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+/// Receives the messages a server pushes to a [`WsRemoteHandler`].
+///
+/// Pushes that arrive while the handler waits for a reply are kept until
+/// they are received here. Clones share the queue.
+#[derive(Clone)]
+pub struct Pushes {
+    connection: Arc<Connection>,
+}
+
+impl Pushes {
+    /// The oldest push that has already arrived, without waiting.
+    pub fn try_recv(&self) -> io::Result<Option<Vec<u8>>> {
+        self.recv_timeout(Duration::ZERO)
+    }
+
+    /// The oldest push, waiting up to `timeout` for one to arrive.
+    ///
+    /// Returns `Ok(None)` if none arrives in time.
+    pub fn recv_timeout(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(push) = self.connection.pushes.lock().unwrap().pop_front() {
+                return Ok(Some(push));
+            }
+            // Lock the socket briefly at a time so operations aren't held up
+            let mut socket = self.connection.socket.lock().unwrap();
+            let wait = deadline.saturating_duration_since(Instant::now());
+            // A zero timeout would block forever
+            let wait = wait.clamp(Duration::from_micros(1), POLL_INTERVAL);
+            socket.get_ref().set_read_timeout(Some(wait))?;
+            match read_binary(&mut socket)? {
+                Some(answer) => match answer.split_first() {
+                    Some((&PUSH, body)) => return Ok(Some(body.to_vec())),
+                    _ => return Err(protocol_error("unexpected message from server")),
+                },
+                None if Instant::now() >= deadline => return Ok(None),
+                None => {}
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Pushes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pushes").finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use crate::remote::RemoteAbort;
+    use algae::prelude::*;
+
+    effect! {
+        Counter::Add (u64) -> u64;
+        Counter::Subscribe -> ();
+        Counter::Fail -> ();
+        Counter::Local -> &'static str;
+    }
+
+    #[derive(Clone)]
+    struct CounterCodec;
+
+    impl Codec<Op> for CounterCodec {
+        fn encode_op(&self, op: &Op) -> io::Result<Vec<u8>> {
+            Ok(match op {
+                Op::Counter(Counter::Add(n)) => [&[0], &n.to_le_bytes()[..]].concat(),
+                Op::Counter(Counter::Subscribe) => vec![1],
+                Op::Counter(Counter::Fail) => vec![2],
+                Op::Counter(Counter::Local) => vec![3],
+            })
+        }
+
+        fn decode_op(&self, bytes: &[u8]) -> io::Result<Op> {
+            Ok(match bytes {
+                [0, n @ ..] => Counter::Add(u64::from_le_bytes(n.try_into().unwrap())).into(),
+                [1] => Counter::Subscribe.into(),
+                [2] => Counter::Fail.into(),
+                [3] => Counter::Local.into(),
+                _ => return Err(protocol_error("unknown operation")),
+            })
+        }
+
+        fn encode_reply(&self, _op: &Op, reply: &(dyn Any + Send)) -> io::Result<Vec<u8>> {
+            Ok(reply
+                .downcast_ref::<u64>()
+                .map(|n| n.to_le_bytes().to_vec())
+                .unwrap_or_default())
+        }
+
+        fn decode_reply(&self, op: &Op, bytes: &[u8]) -> io::Result<Box<dyn Any + Send>> {
+            Ok(match op {
+                Op::Counter(Counter::Add(_)) => {
+                    Box::new(u64::from_le_bytes(bytes.try_into().unwrap()))
+                }
+                _ => Box::new(()),
+            })
+        }
+    }
+
+    /// Keeps a total per connection and pushes it to subscribers.
+    struct CounterServer {
+        total: u64,
+        pusher: Pusher,
+        subscribed: bool,
+    }
+
+    impl PartialHandler<Op> for CounterServer {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Counter(op) = op;
+            Some(match op {
+                Counter::Add(n) => {
+                    self.total += n;
+                    if self.subscribed {
+                        self.pusher.push(self.total.to_le_bytes());
+                    }
+                    Box::new(self.total)
+                }
+                Counter::Subscribe => {
+                    self.subscribed = true;
+                    Box::new(())
+                }
+                Counter::Fail => Abort::boxed("out of order"),
+                Counter::Local => return None,
+            })
+        }
+    }
+
+    struct LocalHandler;
+
+    impl PartialHandler<Op> for LocalHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Counter(Counter::Local) => Some(Box::new("local")),
+                _ => None,
+            }
+        }
+    }
+
+    impl IntoVecHandler<Op> for LocalHandler {
+        fn into_vec_handler(self) -> VecHandler<Op> {
+            let mut vec = VecHandler::new();
+            vec.push(self);
+            vec
+        }
+    }
+
+    fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            serve_effects_ws(listener, CounterCodec, |pusher| CounterServer {
+                total: 0,
+                pusher,
+                subscribed: false,
+            })
+        });
+        url
+    }
+
+    #[effectful]
+    fn add_all(amounts: Vec<u64>) -> u64 {
+        let mut total = 0;
+        for n in amounts {
+            total = perform!(Counter::Add(n));
+        }
+        total
+    }
+
+    #[test]
+    fn test_operations_are_answered_remotely() {
+        let url = start_server();
+        let remote = WsRemoteHandler::connect(&url, CounterCodec).unwrap();
+        assert_eq!(add_all(vec![1, 2, 3]).try_run_with(remote).unwrap(), 6);
+
+        // Every connection has its own handler
+        let remote = WsRemoteHandler::connect(&url, CounterCodec).unwrap();
+        assert_eq!(add_all(vec![10]).try_run_with(remote).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_declines_and_aborts_cross_the_connection() {
+        #[effectful]
+        fn local_then_fail() -> &'static str {
+            let name: &'static str = perform!(Counter::Local);
+            let _: () = perform!(Counter::Fail);
+            name
+        }
+
+        let url = start_server();
+        let result = local_then_fail()
+            .begin_chain()
+            .handle(WsRemoteHandler::connect(&url, CounterCodec).unwrap())
+            .handle(LocalHandler)
+            .run();
+        match result {
+            Err(EffectError::Aborted(abort)) => assert_eq!(
+                abort.downcast_ref::<RemoteAbort>(),
+                Some(&RemoteAbort("\"out of order\"".into()))
+            ),
+            other => panic!("expected a remote abort, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_server_pushes_reach_the_client() {
+        #[effectful]
+        fn subscribe_and_add() -> u64 {
+            let _: () = perform!(Counter::Subscribe);
+            let _: u64 = perform!(Counter::Add(5));
+            perform!(Counter::Add(7))
+        }
+
+        let remote = WsRemoteHandler::connect(&start_server(), CounterCodec).unwrap();
+        let pushes = remote.pushes();
+        assert_eq!(pushes.try_recv().unwrap(), None);
+        assert_eq!(subscribe_and_add().try_run_with(remote).unwrap(), 12);
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            pushes.recv_timeout(timeout).unwrap().unwrap(),
+            5u64.to_le_bytes()
+        );
+        assert_eq!(
+            pushes.recv_timeout(timeout).unwrap().unwrap(),
+            12u64.to_le_bytes()
+        );
+        assert_eq!(
+            pushes.recv_timeout(Duration::from_millis(20)).unwrap(),
+            None
+        );
+    }
+}
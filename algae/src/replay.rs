@@ -0,0 +1,455 @@
+//! Recording a run and replaying it in tests.
+//!
+//! A [`RecordingHandler`] wraps the real handlers and writes every operation
+//! and its answer to a [`Recording`], encoded with the same [`Codec`] that
+//! remote handlers use. A [`ReplayHandler`] answers a later run from the
+//! recording alone, and panics as soon as the run performs an operation
+//! other than the recorded one, so changes to the logic show up as test
+//! failures:
+//!
+//! ```rust,ignore
+//! use algae::replay::Recording;
+//!
+//! // Once, against the real services:
+//! let (receipt, recording) = Recording::record(checkout(cart()), Services::connect()?, OrderCodec);
+//! recording.save("tests/golden/checkout.bin")?;
+//!
+//! // In the test:
+//! let recording = Recording::load("tests/golden/checkout.bin")?;
+//! let receipt = recording.replay(checkout(cart()), OrderCodec)?;
+//! ```
+//!
+//! Operations are compared by their encoding. Aborts are recorded by their
+//! [`description`](crate::Abort::description) and replayed as
+//! [`RemoteAbort`]s, as they are for remote handlers.
+//!
+//! [`Recording::trace`] decodes a recording into a [`Trace`], whose
+//! [`diff`](Trace::diff) with the trace of a new run lists every difference,
+//! not just the first.
+
+#[cfg(feature = "macros")]
+use crate::testing::Trace;
+use crate::{
+    redact::redacted,
+    remote::{message, protocol_error, reply_from, Codec, RemoteAbort, ABORTED, DECLINED, REPLY},
+    Abort, EffectError, Effectful, OpMeta, PartialHandler,
+};
+use std::{any::Any, fmt, fs, io, path::Path};
+
+/// One recorded operation and the message answering it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    op: Vec<u8>,
+    answer: Vec<u8>,
+}
+
+/// The encoded operations of a run and their answers, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    entries: Vec<Entry>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `computation` with `h`, recording its operations and their
+    /// answers with `codec`.
+    pub fn record<R, Op, H, C>(
+        computation: Effectful<R, Op>,
+        h: H,
+        codec: C,
+    ) -> (Result<R, EffectError<Op>>, Self)
+    where
+        H: PartialHandler<Op>,
+        C: Codec<Op>,
+    {
+        let mut recorder = RecordingHandler::new(h, codec);
+        let result = computation.drive(|op| recorder.maybe_handle(op));
+        (result, recorder.into_recording())
+    }
+
+    /// Runs `computation` with a [`ReplayHandler`] for this recording.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the run diverges from the recording, or stops before
+    /// replaying all of it.
+    pub fn replay<R, Op, C>(
+        self,
+        computation: Effectful<R, Op>,
+        codec: C,
+    ) -> Result<R, EffectError<Op>>
+    where
+        Op: OpMeta + fmt::Debug,
+        C: Codec<Op>,
+    {
+        let mut replay = ReplayHandler::new(self, codec);
+        let result = computation.drive(|op| replay.maybe_handle(op));
+        replay.assert_finished();
+        result
+    }
+
+    /// The recorded operations as a [`Trace`], decoded with `codec`, e.g. to
+    /// [`diff`](Trace::diff) a golden recording against a new run.
+    ///
+    /// The recording does not keep where operations were performed, so
+    /// the entries have no locations.
+    #[cfg(feature = "macros")]
+    pub fn trace<Op, C>(&self, codec: &C) -> io::Result<Trace>
+    where
+        Op: OpMeta + fmt::Debug,
+        C: Codec<Op>,
+    {
+        let mut trace = Trace::default();
+        for entry in &self.entries {
+            trace.push(redacted(&codec.decode_op(&entry.op)?), None);
+        }
+        Ok(trace)
+    }
+
+    /// The number of recorded operations.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The recording as length-prefixed records.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for entry in &self.entries {
+            for part in [&entry.op, &entry.answer] {
+                bytes.extend_from_slice(&(part.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(part);
+            }
+        }
+        bytes
+    }
+
+    /// Reads a recording written by [`to_bytes`](Self::to_bytes).
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<Self> {
+        fn part<'a>(bytes: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+            let (len, rest) = bytes
+                .split_first_chunk::<4>()
+                .ok_or_else(|| protocol_error("truncated recording"))?;
+            let len = u32::from_le_bytes(*len) as usize;
+            let part = rest
+                .get(..len)
+                .ok_or_else(|| protocol_error("truncated recording"))?;
+            *bytes = &rest[len..];
+            Ok(part)
+        }
+
+        let mut entries = Vec::new();
+        while !bytes.is_empty() {
+            let op = part(&mut bytes)?.to_vec();
+            let answer = part(&mut bytes)?.to_vec();
+            entries.push(Entry { op, answer });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Writes the recording to `path`, e.g. a golden file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Reads a recording written by [`save`](Self::save).
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// Passes operations to `inner` and records them with their answers.
+///
+/// An operation or reply the codec cannot encode aborts the run with the
+/// `io::Error`, since the recording would be incomplete.
+pub struct RecordingHandler<H, C> {
+    inner: H,
+    codec: C,
+    recording: Recording,
+}
+
+impl<H, C> RecordingHandler<H, C> {
+    pub fn new(inner: H, codec: C) -> Self {
+        Self {
+            inner,
+            codec,
+            recording: Recording::new(),
+        }
+    }
+
+    /// What has been recorded so far.
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn into_recording(self) -> Recording {
+        self.recording
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+}
+
+impl<H, C> fmt::Debug for RecordingHandler<H, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingHandler")
+            .field("recorded", &self.recording.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Op, H, C> PartialHandler<Op> for RecordingHandler<H, C>
+where
+    H: PartialHandler<Op>,
+    C: Codec<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let reply = self.inner.maybe_handle(op);
+        let entry = (|| {
+            let answer = match &reply {
+                None => message(DECLINED, []),
+                Some(reply) => match reply.downcast_ref::<Abort>() {
+                    Some(abort) => message(ABORTED, abort.description()),
+                    None => message(REPLY, self.codec.encode_reply(op, &**reply)?),
+                },
+            };
+            let op = self.codec.encode_op(op)?;
+            io::Result::Ok(Entry { op, answer })
+        })();
+        match entry {
+            Ok(entry) => {
+                self.recording.entries.push(entry);
+                reply
+            }
+            Err(error) => Some(Abort::boxed(error)),
+        }
+    }
+}
+
+/// Answers operations from a [`Recording`], in order.
+///
+/// # Panics
+///
+/// Panics when the run performs an operation other than the next recorded
+/// one, or more operations than were recorded.
+pub struct ReplayHandler<C> {
+    recording: Recording,
+    codec: C,
+    position: usize,
+}
+
+impl<C> ReplayHandler<C> {
+    pub fn new(recording: Recording, codec: C) -> Self {
+        Self {
+            recording,
+            codec,
+            position: 0,
+        }
+    }
+
+    /// The number of recorded operations not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.recording.len() - self.position
+    }
+
+    /// Panics unless every recorded operation has been replayed, i.e. the
+    /// run did not stop earlier than the recorded one.
+    pub fn assert_finished(&self) {
+        assert!(
+            self.remaining() == 0,
+            "replay finished early: {} of {} recorded operations were not performed",
+            self.remaining(),
+            self.recording.len()
+        );
+    }
+}
+
+impl<C> fmt::Debug for ReplayHandler<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayHandler")
+            .field("position", &self.position)
+            .field("recorded", &self.recording.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Op, C> PartialHandler<Op> for ReplayHandler<C>
+where
+    Op: OpMeta + fmt::Debug,
+    C: Codec<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let position = self.position;
+        let Some(entry) = self.recording.entries.get(position) else {
+            panic!(
+                "replay diverged at operation {position}: got {}, but the recording ends there",
+                redacted(op)
+            );
+        };
+        let encoded = self.codec.encode_op(op);
+        if encoded.as_deref().ok() != Some(&entry.op[..]) {
+            let expected = match self.codec.decode_op(&entry.op) {
+                Ok(expected) => redacted(&expected),
+                Err(error) => format!("<undecodable: {error}>"),
+            };
+            panic!(
+                "replay diverged at operation {position}: expected {expected}, got {}",
+                redacted(op)
+            );
+        }
+        self.position += 1;
+        reply_from(&self.codec, op, &entry.answer).unwrap_or_else(|error| {
+            Some(Abort::boxed(RemoteAbort(format!(
+                "cannot replay answer: {error}"
+            ))))
+        })
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        Shop::Price (String) -> u64;
+        Shop::Charge (u64) -> bool;
+    }
+
+    /// Encodes operations and replies as text.
+    struct ShopCodec;
+
+    impl Codec<Op> for ShopCodec {
+        fn encode_op(&self, op: &Op) -> io::Result<Vec<u8>> {
+            Ok(match op {
+                Op::Shop(Shop::Price(item)) => format!("price {item}"),
+                Op::Shop(Shop::Charge(amount)) => format!("charge {amount}"),
+            }
+            .into_bytes())
+        }
+
+        fn decode_op(&self, bytes: &[u8]) -> io::Result<Op> {
+            let text = std::str::from_utf8(bytes).map_err(|_| protocol_error("not utf-8"))?;
+            match text.split_once(' ') {
+                Some(("price", item)) => Ok(Shop::Price(item.to_string()).into()),
+                Some(("charge", amount)) => amount
+                    .parse()
+                    .map(|amount| Shop::Charge(amount).into())
+                    .map_err(|_| protocol_error("bad amount")),
+                _ => Err(protocol_error("unknown operation")),
+            }
+        }
+
+        fn encode_reply(&self, op: &Op, reply: &(dyn Any + Send)) -> io::Result<Vec<u8>> {
+            let text = match op {
+                Op::Shop(Shop::Price(_)) => reply.downcast_ref::<u64>().map(u64::to_string),
+                Op::Shop(Shop::Charge(_)) => reply.downcast_ref::<bool>().map(bool::to_string),
+            };
+            text.map(String::into_bytes)
+                .ok_or_else(|| protocol_error("unexpected reply type"))
+        }
+
+        fn decode_reply(&self, op: &Op, bytes: &[u8]) -> io::Result<Box<dyn Any + Send>> {
+            let text = std::str::from_utf8(bytes).map_err(|_| protocol_error("not utf-8"))?;
+            let reply: Option<Box<dyn Any + Send>> = match op {
+                Op::Shop(Shop::Price(_)) => text.parse::<u64>().ok().map(|n| Box::new(n) as _),
+                Op::Shop(Shop::Charge(_)) => text.parse::<bool>().ok().map(|b| Box::new(b) as _),
+            };
+            reply.ok_or_else(|| protocol_error("bad reply"))
+        }
+    }
+
+    /// Prices by name length and declines charges over `limit`.
+    struct Store {
+        limit: u64,
+    }
+
+    impl PartialHandler<Op> for Store {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Shop(Shop::Price(item)) => Some(Box::new(item.len() as u64 * 10)),
+                Op::Shop(Shop::Charge(amount)) if *amount > self.limit => {
+                    Some(Abort::boxed("card declined"))
+                }
+                Op::Shop(Shop::Charge(_)) => Some(Box::new(true)),
+            }
+        }
+    }
+
+    #[effectful]
+    fn checkout(items: Vec<&'static str>) -> u64 {
+        let mut total = 0;
+        for item in items {
+            let price: u64 = perform!(Shop::Price(item.to_string()));
+            total += price;
+        }
+        let _: bool = perform!(Shop::Charge(total));
+        total
+    }
+
+    fn record(items: Vec<&'static str>, limit: u64) -> Recording {
+        Recording::record(checkout(items), Store { limit }, ShopCodec).1
+    }
+
+    #[test]
+    fn test_replay_answers_like_the_recorded_run() {
+        let recording = record(vec!["tea", "cake"], 100);
+        assert_eq!(recording.len(), 3);
+        let recording = Recording::from_bytes(&recording.to_bytes()).unwrap();
+
+        let total = recording.replay(checkout(vec!["tea", "cake"]), ShopCodec);
+        assert_eq!(total.unwrap(), 70);
+    }
+
+    #[test]
+    fn test_recorded_aborts_are_replayed() {
+        let err = record(vec!["coffee"], 10)
+            .replay(checkout(vec!["coffee"]), ShopCodec)
+            .unwrap_err();
+        let EffectError::Aborted(abort) = err else {
+            panic!("expected an abort, got {err:?}");
+        };
+        assert!(abort.is::<RemoteAbort>());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "replay diverged at operation 1: expected Shop(Price(\"cake\")), got Shop(Price(\"scone\"))"
+    )]
+    fn test_divergence_panics() {
+        let _ = record(vec!["tea", "cake"], 100).replay(checkout(vec!["tea", "scone"]), ShopCodec);
+    }
+
+    #[test]
+    fn test_recordings_diff_as_traces() {
+        let golden = record(vec!["tea", "cake"], 100).trace(&ShopCodec).unwrap();
+        assert_eq!(
+            golden.entries(),
+            [
+                "Shop(Price(\"tea\"))",
+                "Shop(Price(\"cake\"))",
+                "Shop(Charge(70))"
+            ]
+        );
+
+        let diff = golden.diff(&record(vec!["tea", "scone"], 100).trace(&ShopCodec).unwrap());
+        assert_eq!(diff.first_divergence(), Some(1));
+        assert!(diff
+            .to_string()
+            .contains("~   2. Shop(Price(\"cake\")) ->   2. Shop(Price(\"scone\"))"));
+    }
+
+    #[test]
+    fn test_truncated_recordings_are_rejected() {
+        let bytes = record(vec!["tea"], 100).to_bytes();
+        assert!(Recording::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
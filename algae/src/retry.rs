@@ -0,0 +1,599 @@
+//! Declarative retries.
+//!
+//! Operations declare how they may be retried where they are defined:
+//!
+//! ```rust,ignore
+//! effect! {
+//!     #[retryable(max = 3, backoff = exp)]
+//!     Http::Get (String) -> String;
+//!     Http::Post ((String, String)) -> String;
+//! }
+//! ```
+//!
+//! A [`RetryLayer`] wraps the handler that performs the operations. When that
+//! handler replies to an operation with an [`Abort`] and the operation has a
+//! [`RetryPolicy`] (see [`OpMeta::retry_policy`]), the layer waits according to
+//! the policy's [`Backoff`] and dispatches the same operation again, up to the
+//! policy's retry limit. Operations without a policy are passed through
+//! untouched, so neither the business logic nor the handler has to know about
+//! retries.
+//!
+//! Where the policy belongs to the handler rather than the operations, a
+//! [`RetryHandler`] wraps it with a policy of its own, and a predicate over
+//! each operation and reply decides what counts as a retryable failure, so
+//! a handler may signal one in its replies instead of aborting:
+//!
+//! ```rust,ignore
+//! let handler = RetryHandler::new(HttpHandler::new())
+//!     .max_attempts(5)
+//!     .backoff(Backoff::Exponential(Duration::from_millis(50)))
+//!     .retry_when(|op: &Op, reply| {
+//!         !op.mutates_state()
+//!             && reply
+//!                 .downcast_ref::<io::Result<String>>()
+//!                 .is_some_and(|r| r.is_err())
+//!     });
+//! ```
+//!
+//! Each retry is reported as a [`RetryEvent`] to an optional [`RetryLog`].
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::retry::{RetryLayer, RetryLog};
+//!
+//! let log = RetryLog::new();
+//! let page = fetch_page()
+//!     .try_run_with(RetryLayer::new(HttpHandler::new()).record_to(&log))?;
+//! for event in log.events() {
+//!     eprintln!("{event}");
+//! }
+//! ```
+
+use alloc::string::String;
+use core::{fmt, time::Duration};
+#[cfg(feature = "std")]
+use {
+    crate::{redact::redacted, Abort, OpMeta, PartialHandler},
+    std::{
+        any::Any,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// How long to wait before each retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// Retry immediately.
+    None,
+    /// Wait the same delay before every retry.
+    Fixed(Duration),
+    /// Wait the given delay before the first retry and double it for each
+    /// following one.
+    Exponential(Duration),
+}
+
+impl Backoff {
+    /// The delay before retry number `retry` (counting from 1).
+    pub fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Backoff::None => Duration::ZERO,
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential(base) => {
+                let factor = 1u32
+                    .checked_shl(retry.saturating_sub(1))
+                    .unwrap_or(u32::MAX);
+                base.saturating_mul(factor)
+            }
+        }
+    }
+}
+
+/// The retry policy of an operation, usually declared with `#[retryable]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Delay between attempts
+    pub backoff: Backoff,
+}
+
+impl RetryPolicy {
+    pub const fn new(max_retries: u32, backoff: Backoff) -> Self {
+        Self {
+            max_retries,
+            backoff,
+        }
+    }
+}
+
+/// One retry performed by a [`RetryLayer`] or [`RetryHandler`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryEvent {
+    /// `Debug` rendering of the retried operation
+    pub op: String,
+    /// The retry number, starting at 1
+    pub retry: u32,
+    /// How long the layer waited before retrying
+    pub delay: Duration,
+    /// Description of the abort that triggered the retry, or
+    /// `"retryable reply"` for other replies
+    pub error: String,
+}
+
+impl fmt::Display for RetryEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "retry #{} of {} after {:?}: {}",
+            self.retry, self.op, self.delay, self.error
+        )
+    }
+}
+
+/// A shared, append-only list of [`RetryEvent`]s.
+///
+/// Clones share the same list.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct RetryLog {
+    events: Arc<Mutex<Vec<RetryEvent>>>,
+}
+
+#[cfg(feature = "std")]
+impl RetryLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All retries recorded so far, oldest first.
+    pub fn events(&self) -> Vec<RetryEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    fn push(&self, event: RetryEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+#[cfg(feature = "std")]
+type SleepFn = Box<dyn FnMut(Duration) + Send>;
+#[cfg(feature = "std")]
+type RetryIfFn = Box<dyn Fn(&Abort) -> bool + Send>;
+#[cfg(feature = "std")]
+type RetryWhenFn<Op> = Box<dyn Fn(&Op, &(dyn Any + Send)) -> bool + Send>;
+
+/// Dispatches `op` to `inner` again while `retryable` holds for its reply,
+/// up to `policy`'s limit, returning the last reply.
+#[cfg(feature = "std")]
+fn retry<Op, H>(
+    inner: &mut H,
+    op: &Op,
+    policy: RetryPolicy,
+    mut reply: Box<dyn Any + Send>,
+    retryable: impl Fn(&(dyn Any + Send)) -> bool,
+    sleep: &mut SleepFn,
+    log: Option<&RetryLog>,
+) -> Box<dyn Any + Send>
+where
+    Op: OpMeta + fmt::Debug,
+    H: PartialHandler<Op>,
+{
+    for retry in 1..=policy.max_retries {
+        if !retryable(&*reply) {
+            break;
+        }
+        let delay = policy.backoff.delay(retry);
+        if let Some(log) = log {
+            log.push(RetryEvent {
+                op: redacted(op),
+                retry,
+                delay,
+                error: match reply.downcast_ref::<Abort>() {
+                    Some(abort) => abort.description().to_string(),
+                    None => "retryable reply".to_string(),
+                },
+            });
+        }
+        if !delay.is_zero() {
+            sleep(delay);
+        }
+        match inner.maybe_handle(op) {
+            Some(next) => reply = next,
+            // The inner handler stopped answering; report the last failure.
+            None => break,
+        }
+    }
+    reply
+}
+
+/// A handler layer that re-dispatches aborted operations according to their
+/// [`RetryPolicy`].
+///
+/// Once the retries are exhausted, the last abort is passed on unchanged.
+///
+/// # Type Parameters
+///
+/// * `Op` - The operation type; its [`OpMeta`] impl supplies the policies
+/// * `H` - The inner partial handler that performs the operations
+#[cfg(feature = "std")]
+pub struct RetryLayer<Op, H> {
+    inner: H,
+    sleep: SleepFn,
+    retry_if: RetryIfFn,
+    log: Option<RetryLog>,
+    _op: core::marker::PhantomData<fn(&Op)>,
+}
+
+#[cfg(feature = "std")]
+impl<Op, H> RetryLayer<Op, H> {
+    /// Wraps `inner`, retrying every abort and sleeping with
+    /// `std::thread::sleep`.
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            sleep: Box::new(std::thread::sleep),
+            retry_if: Box::new(|_| true),
+            log: None,
+            _op: core::marker::PhantomData,
+        }
+    }
+
+    /// Waits between attempts with `sleep` instead of blocking the thread.
+    ///
+    /// Useful in tests, e.g. to advance a virtual clock instead.
+    pub fn sleep_with<F>(mut self, sleep: F) -> Self
+    where
+        F: FnMut(Duration) + Send + 'static,
+    {
+        self.sleep = Box::new(sleep);
+        self
+    }
+
+    /// Only retries aborts for which `retry_if` returns `true`; other aborts
+    /// are passed on immediately.
+    pub fn retry_if<F>(mut self, retry_if: F) -> Self
+    where
+        F: Fn(&Abort) -> bool + Send + 'static,
+    {
+        self.retry_if = Box::new(retry_if);
+        self
+    }
+
+    /// Records every retry into `log`.
+    pub fn record_to(mut self, log: &RetryLog) -> Self {
+        self.log = Some(log.clone());
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Op, H> PartialHandler<Op> for RetryLayer<Op, H>
+where
+    Op: OpMeta + fmt::Debug,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let reply = self.inner.maybe_handle(op)?;
+        let Some(policy) = op.retry_policy() else {
+            return Some(reply);
+        };
+        let retry_if = &self.retry_if;
+        Some(retry(
+            &mut self.inner,
+            op,
+            policy,
+            reply,
+            |reply| reply.downcast_ref::<Abort>().is_some_and(retry_if),
+            &mut self.sleep,
+            self.log.as_ref(),
+        ))
+    }
+}
+
+/// A handler wrapper that re-invokes its inner handler while its replies
+/// are retryable failures, with one [`RetryPolicy`] for all operations.
+///
+/// By default every [`Abort`] is retried, 3 times with exponential backoff
+/// starting at 100ms, like `#[retryable]` without arguments. Once the
+/// retries are exhausted, the last reply is passed on unchanged.
+#[cfg(feature = "std")]
+pub struct RetryHandler<Op, H> {
+    inner: H,
+    policy: RetryPolicy,
+    retry_when: RetryWhenFn<Op>,
+    sleep: SleepFn,
+    log: Option<RetryLog>,
+}
+
+#[cfg(feature = "std")]
+impl<Op, H> RetryHandler<Op, H> {
+    /// Wraps `inner` with the default policy, sleeping with
+    /// `std::thread::sleep`.
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            policy: RetryPolicy::new(3, Backoff::Exponential(Duration::from_millis(100))),
+            retry_when: Box::new(|_, reply| reply.is::<Abort>()),
+            sleep: Box::new(std::thread::sleep),
+            log: None,
+        }
+    }
+
+    /// Retries according to `policy`.
+    pub fn policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Tries each operation at most `attempts` times, counting the first.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.policy.max_retries = attempts.saturating_sub(1);
+        self
+    }
+
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.policy.backoff = backoff;
+        self
+    }
+
+    /// Retries the operations whose replies `retry_when` returns `true` for,
+    /// instead of those replied with an [`Abort`].
+    pub fn retry_when<F>(mut self, retry_when: F) -> Self
+    where
+        F: Fn(&Op, &(dyn Any + Send)) -> bool + Send + 'static,
+    {
+        self.retry_when = Box::new(retry_when);
+        self
+    }
+
+    /// Waits between attempts with `sleep` instead of blocking the thread.
+    pub fn sleep_with<F>(mut self, sleep: F) -> Self
+    where
+        F: FnMut(Duration) + Send + 'static,
+    {
+        self.sleep = Box::new(sleep);
+        self
+    }
+
+    /// Records every retry into `log`.
+    pub fn record_to(mut self, log: &RetryLog) -> Self {
+        self.log = Some(log.clone());
+        self
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Op, H> PartialHandler<Op> for RetryHandler<Op, H>
+where
+    Op: OpMeta + fmt::Debug,
+    H: PartialHandler<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let reply = self.inner.maybe_handle(op)?;
+        let retry_when = &self.retry_when;
+        Some(retry(
+            &mut self.inner,
+            op,
+            self.policy,
+            reply,
+            |reply| retry_when(op, reply),
+            &mut self.sleep,
+            self.log.as_ref(),
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "std", feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        #[retryable(max = 3, backoff = exp, delay_ms = 10)]
+        Http::Get (String) -> String;
+        Http::Post (String) -> String;
+        #[retryable(max = 2, backoff = none)]
+        Db::Query (String) -> u32;
+    }
+
+    #[derive(Debug)]
+    struct Timeout;
+
+    #[derive(Debug)]
+    struct NotFound;
+
+    /// Fails the first `failures` calls of every operation.
+    struct FlakyHandler {
+        failures: u32,
+        calls: u32,
+    }
+
+    impl PartialHandler<Op> for FlakyHandler {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            self.calls += 1;
+            if self.calls <= self.failures {
+                return Some(Abort::boxed(Timeout));
+            }
+            match op {
+                Op::Http(Http::Get(url)) | Op::Http(Http::Post(url)) => {
+                    Some(Box::new(format!("body of {url}")))
+                }
+                Op::Db(Db::Query(_)) => Some(Box::new(7u32)),
+            }
+        }
+    }
+
+    #[effectful]
+    fn get() -> String {
+        perform!(Http::Get("/index".into()))
+    }
+
+    #[effectful]
+    fn post() -> String {
+        perform!(Http::Post("/submit".into()))
+    }
+
+    fn layer(failures: u32, sleeps: &Arc<Mutex<Vec<Duration>>>) -> RetryLayer<Op, FlakyHandler> {
+        let sleeps = sleeps.clone();
+        RetryLayer::new(FlakyHandler { failures, calls: 0 })
+            .sleep_with(move |d| sleeps.lock().unwrap().push(d))
+    }
+
+    #[test]
+    fn test_generated_policies() {
+        assert_eq!(
+            Op::from(Http::Get("/".into())).retry_policy(),
+            Some(RetryPolicy::new(
+                3,
+                Backoff::Exponential(Duration::from_millis(10))
+            ))
+        );
+        assert_eq!(Http::Post("/".into()).retry_policy(), None);
+        assert_eq!(
+            Db::Query("q".into()).retry_policy(),
+            Some(RetryPolicy::new(2, Backoff::None))
+        );
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        let exp = Backoff::Exponential(Duration::from_millis(100));
+        let delays: Vec<_> = (1..=4).map(|r| exp.delay(r).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800]);
+        // Large retry numbers saturate instead of overflowing.
+        assert_eq!(exp.delay(200), Duration::from_millis(100) * u32::MAX);
+        assert_eq!(
+            Backoff::Fixed(Duration::from_secs(1)).delay(5),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_retries_until_success() {
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let log = RetryLog::new();
+        let body = get()
+            .try_run_with(layer(2, &sleeps).record_to(&log))
+            .unwrap();
+        assert_eq!(body, "body of /index");
+        assert_eq!(
+            *sleeps.lock().unwrap(),
+            [10, 20].map(Duration::from_millis).to_vec()
+        );
+
+        let events = log.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1].to_string(),
+            "retry #2 of Http(Get(\"/index\")) after 20ms: Timeout"
+        );
+    }
+
+    #[test]
+    fn test_gives_up_after_max_retries() {
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let err = get().try_run_with(layer(10, &sleeps)).unwrap_err();
+        match err {
+            EffectError::Aborted(abort) => assert!(abort.is::<Timeout>()),
+            other => panic!("expected an abort, got {other:?}"),
+        }
+        assert_eq!(sleeps.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_unannotated_ops_are_not_retried() {
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let err = post().try_run_with(layer(1, &sleeps)).unwrap_err();
+        assert!(matches!(err, EffectError::Aborted(_)));
+        assert!(sleeps.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_retry_handler_retries_every_op_by_policy() {
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let recorded = sleeps.clone();
+        let handler = RetryHandler::new(FlakyHandler {
+            failures: 2,
+            calls: 0,
+        })
+        .max_attempts(3)
+        .backoff(Backoff::Fixed(Duration::from_millis(5)))
+        .sleep_with(move |d| recorded.lock().unwrap().push(d));
+        assert_eq!(post().try_run_with(handler).unwrap(), "body of /submit");
+        assert_eq!(
+            *sleeps.lock().unwrap(),
+            [5, 5].map(Duration::from_millis).to_vec()
+        );
+
+        let handler = RetryHandler::new(FlakyHandler {
+            failures: 3,
+            calls: 0,
+        })
+        .max_attempts(3)
+        .backoff(Backoff::None);
+        assert!(post().try_run_with(handler).is_err());
+    }
+
+    #[test]
+    fn test_retry_handler_predicate_sees_op_and_reply() {
+        /// Replies "busy" to the first `busy` calls instead of failing.
+        struct Busy {
+            busy: u32,
+            calls: u32,
+        }
+
+        impl PartialHandler<Op> for Busy {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                self.calls += 1;
+                match op {
+                    Op::Http(_) if self.calls <= self.busy => Some(Box::new("busy".to_string())),
+                    Op::Http(_) => Some(Box::new("done".to_string())),
+                    Op::Db(_) => Some(Box::new(self.calls)),
+                }
+            }
+        }
+
+        let log = RetryLog::new();
+        let handler = RetryHandler::new(Busy { busy: 2, calls: 0 })
+            .backoff(Backoff::None)
+            .retry_when(|op, reply| {
+                matches!(op, Op::Http(Http::Get(_)))
+                    && reply.downcast_ref::<String>().is_some_and(|r| r == "busy")
+            })
+            .record_to(&log);
+        let mut session = crate::session::HandlerSession::new(handler);
+        assert_eq!(session.try_run(get()).unwrap(), "done");
+        assert_eq!(log.events().len(), 2);
+        assert_eq!(log.events()[0].error, "retryable reply");
+
+        // Posts are not retried, even when busy
+        session.handler_mut().inner.busy = 10;
+        assert_eq!(session.try_run(post()).unwrap(), "busy");
+        assert_eq!(log.events().len(), 2);
+    }
+
+    #[test]
+    fn test_retry_if_filters_aborts() {
+        struct Missing;
+        impl PartialHandler<Op> for Missing {
+            fn maybe_handle(&mut self, _: &Op) -> Option<Box<dyn Any + Send>> {
+                Some(Abort::boxed(NotFound))
+            }
+        }
+
+        let log = RetryLog::new();
+        let layer = RetryLayer::new(Missing)
+            .retry_if(|abort| abort.is::<Timeout>())
+            .record_to(&log);
+        assert!(get().try_run_with(layer).is_err());
+        assert!(log.events().is_empty());
+    }
+}
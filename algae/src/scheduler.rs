@@ -0,0 +1,539 @@
+//! Cooperative multitasking of effectful computations.
+//!
+//! A [`Scheduler`] holds many suspended computations, its tasks, and resumes
+//! them in turn against the handler it owns: each task runs up to its next
+//! operation, which is answered before the next task gets its turn. Tasks
+//! manage each other through the [`Task`] family, which the scheduler
+//! answers itself:
+//!
+//! - [`Task::spawn`] adds a computation as a new task and replies with its
+//!   [`TaskId`],
+//! - [`Task::YieldNow`] lets the other tasks go first,
+//! - [`TaskId::join`] waits for a task to finish and replies with its result.
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::scheduler::{Scheduler, Task, TaskId};
+//!
+//! effect! {
+//!     use algae::scheduler::Task;
+//!     Http::Get (String) -> String;
+//! }
+//!
+//! #[effectful]
+//! fn fetch_all(urls: Vec<String>) -> Vec<String> {
+//!     let mut tasks = Vec::new();
+//!     for url in urls {
+//!         let task: TaskId = perform!(Task::spawn(fetch(url)));
+//!         tasks.push(task);
+//!     }
+//!     let mut pages = Vec::new();
+//!     for task in tasks {
+//!         let page: String = perform!(task.join());
+//!         pages.push(page);
+//!     }
+//!     pages
+//! }
+//!
+//! let mut scheduler = Scheduler::new(HttpClient::new());
+//! let pages = scheduler.block_on(fetch_all(urls))?;
+//! ```
+//!
+//! Tasks switch only at operations, and one operation is answered at a time,
+//! so the scheduler needs no threads: it is a small single-threaded runtime,
+//! for handlers that are cheap to call or that only queue work. Each task is
+//! the [`scoped`](Effectful::scoped) sub-computation of its id, so seeded
+//! handlers give it values of its own.
+
+use crate::{
+    dispatch_effect, Abort, Contains, Effect, EffectError, Effectful, OpMeta, PartialHandler, Reply,
+};
+use algae_macros::effect;
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt, mem,
+    ops::CoroutineState,
+    sync::{Arc, Mutex},
+};
+
+effect! {
+    root TaskOp;
+    typed;
+    Task::Spawn (Spawned) -> TaskId;
+    Task::YieldNow -> ();
+    Task::Join (TaskId) -> Box<dyn Any + Send>;
+}
+
+impl Task {
+    /// Spawns `computation` as a new task, which runs alongside the others.
+    pub fn spawn<R, Op>(computation: Effectful<R, Op>) -> Self
+    where
+        R: Any + Send,
+        Op: Send + 'static,
+    {
+        Task::Spawn(Spawned::new(computation))
+    }
+}
+
+/// Identifies a task within its [`Scheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TaskId(usize);
+
+impl TaskId {
+    /// Waits for the task to finish; the reply is its result.
+    pub fn join(self) -> Task {
+        Task::Join(self)
+    }
+}
+
+/// The computation of a [`Task::Spawn`] operation.
+///
+/// Clones share the same computation, which the scheduler takes out when it
+/// spawns it. Two `Spawned`s are equal when one is a clone of the other.
+#[derive(Clone)]
+pub struct Spawned {
+    computation: Arc<Mutex<Option<Box<dyn Any + Send>>>>,
+}
+
+impl Spawned {
+    /// Wraps `computation` to be spawned as a task.
+    pub fn new<R, Op>(computation: Effectful<R, Op>) -> Self
+    where
+        R: Any + Send,
+        Op: Send + 'static,
+    {
+        let computation: Box<dyn Any + Send> = Box::new(computation.erased());
+        Self {
+            computation: Arc::new(Mutex::new(Some(computation))),
+        }
+    }
+
+    /// Takes the computation out, if it is still there and has root `Op`.
+    fn take<Op: 'static>(&self) -> Option<Effectful<Reply, Op>> {
+        let mut computation = self.computation.lock().unwrap();
+        if !computation.as_ref()?.is::<Effectful<Reply, Op>>() {
+            return None;
+        }
+        let computation = computation.take()?.downcast().ok()?;
+        Some(*computation)
+    }
+}
+
+impl PartialEq for Spawned {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.computation, &other.computation)
+    }
+}
+
+impl fmt::Debug for Spawned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match *self.computation.lock().unwrap() {
+            Some(_) => "pending",
+            None => "taken",
+        };
+        write!(f, "Spawned({state})")
+    }
+}
+
+/// Abort reason for a join the scheduler cannot answer with a result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError {
+    /// The task stopped with an error, described here.
+    Failed(TaskId, String),
+    /// The task's result has been handed out already, to another join or
+    /// through [`Scheduler::take`].
+    Taken(TaskId),
+    /// The scheduler has no task with this id.
+    Unknown(TaskId),
+    /// The task can only finish after the joining one, e.g. because they
+    /// join each other.
+    Deadlock(TaskId),
+}
+
+/// Where a task is at.
+enum TaskState<Op: 'static> {
+    /// Waiting for its turn, with the reply to its last operation.
+    Ready(Effectful<Reply, Op>, Option<Reply>),
+    /// Waiting for the task it joins to finish; queued again when it has.
+    Joining(Effectful<Reply, Op>, Box<Effect<Op>>, TaskId),
+    Finished(Result<Reply, EffectError<Op>>),
+    /// Finished, with the result handed out; also marks the running task.
+    Taken,
+}
+
+/// Runs many computations as tasks, taking turns at their operations.
+///
+/// The scheduler owns the handler the tasks share, like a
+/// [`HandlerSession`](crate::session::HandlerSession), and keeps it, along
+/// with the results of its tasks, from one [`run`](Scheduler::run) to the
+/// next.
+pub struct Scheduler<Op: 'static, H> {
+    handler: H,
+    tasks: Vec<TaskState<Op>>,
+    /// The tasks to resume, in turn.
+    ready: VecDeque<TaskId>,
+}
+
+impl<Op: 'static, H> Scheduler<Op, H> {
+    /// A scheduler without tasks, whose tasks will share `handler`.
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            tasks: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// The handler, with the state left by the tasks so far.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// The handler, to change its state between runs.
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Drops the tasks, finished or not, and returns the handler.
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+
+    /// Whether the task has finished, successfully or not.
+    pub fn is_finished(&self, task: TaskId) -> bool {
+        matches!(
+            self.tasks.get(task.0),
+            Some(TaskState::Finished(_) | TaskState::Taken)
+        )
+    }
+
+    fn push(&mut self, computation: Effectful<Reply, Op>) -> TaskId {
+        let id = TaskId(self.tasks.len());
+        let computation = computation.scoped(id.0 as u64);
+        self.tasks.push(TaskState::Ready(computation, None));
+        self.ready.push_back(id);
+        id
+    }
+}
+
+impl<Op, H> Scheduler<Op, H>
+where
+    Op: Contains<Task> + OpMeta + fmt::Debug + Send + 'static,
+    H: PartialHandler<Op>,
+{
+    /// Adds `computation` as a task, to start at the next
+    /// [`run`](Scheduler::run).
+    pub fn spawn<R: Any + Send>(&mut self, computation: Effectful<R, Op>) -> TaskId {
+        self.push(computation.erased())
+    }
+
+    /// Resumes the tasks in turn until every one of them has finished.
+    ///
+    /// A task that performs an unhandled operation, or is aborted, stops
+    /// there without affecting the others, except those joining it. Tasks
+    /// that wait for each other are stopped with a [`JoinError::Deadlock`]
+    /// once no other task can run.
+    pub fn run(&mut self) {
+        loop {
+            while let Some(id) = self.ready.pop_front() {
+                match mem::replace(&mut self.tasks[id.0], TaskState::Taken) {
+                    TaskState::Ready(mut computation, reply) => match computation.step(reply) {
+                        CoroutineState::Yielded(eff) => self.answer(id, computation, eff),
+                        CoroutineState::Complete(result) => self.finish(id, Ok(result)),
+                    },
+                    TaskState::Joining(computation, eff, _) => self.answer(id, computation, *eff),
+                    _ => unreachable!("only waiting tasks are queued"),
+                }
+            }
+            // Only tasks joining each other are left; stopping the first
+            // wakes those joining it
+            let joining = self
+                .tasks
+                .iter()
+                .enumerate()
+                .find_map(|(i, task)| match task {
+                    TaskState::Joining(_, _, target) => Some((TaskId(i), *target)),
+                    _ => None,
+                });
+            let Some((id, target)) = joining else {
+                return;
+            };
+            let deadlock = Abort::new(JoinError::Deadlock(target));
+            self.finish(id, Err(EffectError::Aborted(deadlock)));
+        }
+    }
+
+    /// Spawns `computation`, runs every task, and returns its result.
+    ///
+    /// Fails with an [`Abort`] carrying [`JoinError::Taken`] if a task it
+    /// spawned joined it, and so took its result.
+    pub fn block_on<R: Any + Send>(
+        &mut self,
+        computation: Effectful<R, Op>,
+    ) -> Result<R, EffectError<Op>> {
+        let id = self.spawn(computation);
+        self.run();
+        match self.take(id) {
+            Some(result) => result,
+            None => Err(EffectError::Aborted(Abort::new(JoinError::Taken(id)))),
+        }
+    }
+
+    /// Takes out the result of a finished task that no other task joined.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task's result is not an `R`.
+    pub fn take<R: Any + Send>(&mut self, task: TaskId) -> Option<Result<R, EffectError<Op>>> {
+        let slot = self.tasks.get_mut(task.0)?;
+        if !matches!(slot, TaskState::Finished(_)) {
+            return None;
+        }
+        let TaskState::Finished(result) = mem::replace(slot, TaskState::Taken) else {
+            unreachable!("checked just above")
+        };
+        Some(result.map(Reply::take))
+    }
+
+    /// Answers the operation `task` stopped at, or parks the task if it
+    /// joins one that has not finished.
+    fn answer(&mut self, task: TaskId, computation: Effectful<Reply, Op>, eff: Effect<Op>) {
+        let own = (!eff.is_batch()).then(|| eff.op.project()).flatten();
+        let local = match own {
+            Some(Task::Spawn(spawned)) => {
+                spawned.take().map(|spawned| Reply::new(self.push(spawned)))
+            }
+            Some(Task::YieldNow) => Some(Reply::new(())),
+            Some(&Task::Join(target)) => match self.tasks.get_mut(target.0) {
+                _ if target == task => Some(Abort::boxed(JoinError::Deadlock(target)).into()),
+                None => Some(Abort::boxed(JoinError::Unknown(target)).into()),
+                Some(TaskState::Taken) => Some(Abort::boxed(JoinError::Taken(target)).into()),
+                Some(slot @ TaskState::Finished(_)) => match mem::replace(slot, TaskState::Taken) {
+                    TaskState::Finished(Ok(result)) => Some(result),
+                    TaskState::Finished(Err(error)) => {
+                        Some(Abort::boxed(JoinError::Failed(target, error.to_string())).into())
+                    }
+                    _ => unreachable!("matched just above"),
+                },
+                Some(_) => {
+                    self.tasks[task.0] = TaskState::Joining(computation, Box::new(eff), target);
+                    return;
+                }
+            },
+            None => None,
+        };
+        let reply = match local {
+            Some(reply) => {
+                let mut reply = Some(reply);
+                dispatch_effect(eff, &mut |_| reply.take())
+            }
+            None => dispatch_effect(eff, &mut |op| self.handler.maybe_handle(op)),
+        };
+        match reply {
+            Ok(reply) => {
+                self.tasks[task.0] = TaskState::Ready(computation, Some(reply));
+                self.ready.push_back(task);
+            }
+            Err(error) => self.finish(task, Err(error)),
+        }
+    }
+
+    /// Records the result of `task` and wakes the tasks joining it.
+    fn finish(&mut self, task: TaskId, result: Result<Reply, EffectError<Op>>) {
+        self.tasks[task.0] = TaskState::Finished(result);
+        for (i, state) in self.tasks.iter().enumerate() {
+            if matches!(state, TaskState::Joining(_, _, target) if *target == task) {
+                self.ready.push_back(TaskId(i));
+            }
+        }
+    }
+}
+
+impl<Op: 'static, H: fmt::Debug> fmt::Debug for Scheduler<Op, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("handler", &self.handler)
+            .field("tasks", &self.tasks.len())
+            .field("ready", &self.ready.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        use algae::scheduler::Task;
+        Log::Line (String) -> ();
+        Work::Square (u64) -> u64;
+        Work::Fail -> ();
+    }
+
+    /// Logs lines and squares numbers; declines `Work::Fail`.
+    #[derive(Debug, Default)]
+    struct Worker {
+        lines: Vec<String>,
+    }
+
+    impl PartialHandler<Op> for Worker {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Log(Log::Line(line)) => {
+                    self.lines.push(line.clone());
+                    Some(Box::new(()))
+                }
+                Op::Work(Work::Square(n)) => Some(Box::new(n * n)),
+                _ => None,
+            }
+        }
+    }
+
+    #[effectful]
+    fn chatter(name: &'static str, lines: u32) -> u32 {
+        for i in 0..lines {
+            let _: () = perform!(Log::Line(format!("{name}{i}")));
+        }
+        lines
+    }
+
+    #[effectful]
+    fn sum_of_squares(up_to: u64) -> u64 {
+        let mut tasks = Vec::new();
+        for n in 1..=up_to {
+            let task: TaskId = perform!(Task::spawn(square(n)));
+            tasks.push(task);
+        }
+        let mut sum = 0;
+        for task in tasks {
+            let square: u64 = perform!(task.join());
+            sum += square;
+        }
+        sum
+    }
+
+    #[effectful]
+    fn square(n: u64) -> u64 {
+        let _: () = perform!(Log::Line(format!("square {n}")));
+        perform!(Work::Square(n))
+    }
+
+    #[test]
+    fn test_tasks_take_turns() {
+        let mut scheduler = Scheduler::new(Worker::default());
+        let a = scheduler.spawn(chatter("a", 3));
+        let b = scheduler.spawn(chatter("b", 2));
+        scheduler.run();
+        assert_eq!(scheduler.handler().lines, ["a0", "b0", "a1", "b1", "a2"]);
+        assert_eq!(scheduler.take::<u32>(a).unwrap().unwrap(), 3);
+        assert_eq!(scheduler.take::<u32>(b).unwrap().unwrap(), 2);
+        assert!(scheduler.take::<u32>(b).is_none(), "taken already");
+    }
+
+    #[test]
+    fn test_spawned_tasks_are_joined() {
+        let mut scheduler = Scheduler::new(Worker::default());
+        assert_eq!(scheduler.block_on(sum_of_squares(3)).unwrap(), 14);
+        assert_eq!(
+            scheduler.into_handler().lines,
+            ["square 1", "square 2", "square 3"]
+        );
+    }
+
+    #[test]
+    fn test_yielding_lets_others_go_first() {
+        #[effectful]
+        fn polite() {
+            let _: () = perform!(Task::YieldNow);
+            let _: () = perform!(Task::YieldNow);
+            perform!(Log::Line("polite".to_string()))
+        }
+
+        let mut scheduler = Scheduler::new(Worker::default());
+        scheduler.spawn(polite());
+        scheduler.spawn(chatter("a", 2));
+        scheduler.run();
+        assert_eq!(scheduler.handler().lines, ["a0", "a1", "polite"]);
+    }
+
+    #[test]
+    fn test_joining_a_failed_task_aborts() {
+        #[effectful]
+        fn failing() -> u64 {
+            let _: () = perform!(Work::Fail);
+            0
+        }
+
+        #[effectful]
+        fn join_failing() -> u64 {
+            let task: TaskId = perform!(Task::spawn(failing()));
+            perform!(task.join())
+        }
+
+        let mut scheduler = Scheduler::new(Worker::default());
+        let EffectError::Aborted(abort) = scheduler.block_on(join_failing()).unwrap_err() else {
+            panic!("the join aborts");
+        };
+        let Some(JoinError::Failed(task, error)) = abort.downcast_ref::<JoinError>() else {
+            panic!("unexpected abort {abort:?}");
+        };
+        assert_eq!(*task, TaskId(1));
+        assert!(error.contains("Fail"), "{error}");
+    }
+
+    #[test]
+    fn test_roots_joined_by_their_tasks_are_taken() {
+        #[effectful]
+        fn joined_by_child() -> u64 {
+            let _: TaskId = perform!(Task::spawn(join(0)));
+            5
+        }
+
+        let mut scheduler = Scheduler::new(Worker::default());
+        let EffectError::Aborted(abort) = scheduler.block_on(joined_by_child()).unwrap_err() else {
+            panic!("the root's result was taken");
+        };
+        assert_eq!(
+            abort.downcast::<JoinError>().unwrap(),
+            JoinError::Taken(TaskId(0))
+        );
+        assert_eq!(scheduler.take::<u64>(TaskId(1)).unwrap().unwrap(), 5);
+    }
+
+    #[effectful]
+    fn join(task: usize) -> u64 {
+        perform!(TaskId(task).join())
+    }
+
+    #[test]
+    fn test_tasks_joining_each_other_are_stopped() {
+        let mut scheduler = Scheduler::new(Worker::default());
+        let a = scheduler.spawn(join(1));
+        let b = scheduler.spawn(join(0));
+        let myself = scheduler.spawn(join(2));
+        let unknown = scheduler.spawn(join(7));
+        scheduler.run();
+
+        let abort = |scheduler: &mut Scheduler<Op, Worker>, task| match scheduler.take::<u64>(task)
+        {
+            Some(Err(EffectError::Aborted(abort))) => abort.downcast::<JoinError>().unwrap(),
+            _ => panic!("{task:?} is aborted"),
+        };
+        // `a` was stopped first, and `b` joined it
+        let JoinError::Failed(task, error) = abort(&mut scheduler, b) else {
+            panic!("b joined a failed task");
+        };
+        assert_eq!(task, a);
+        assert!(error.contains("Deadlock"), "{error}");
+        assert!(scheduler.is_finished(a) && scheduler.take::<u64>(a).is_none());
+        assert_eq!(abort(&mut scheduler, myself), JoinError::Deadlock(myself));
+        assert_eq!(
+            abort(&mut scheduler, unknown),
+            JoinError::Unknown(TaskId(7))
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! Running many computations against one handler.
+//!
+//! `.handle(h)` and the `run_with` drivers consume their handler, so state a
+//! handler builds up, such as a cache or a connection, is lost with the run.
+//! A [`HandlerSession`] owns a handler instead and runs computations against
+//! it one at a time, keeping its state from one run to the next:
+//!
+//! ```rust,ignore
+//! use algae::session::HandlerSession;
+//!
+//! let mut session = HandlerSession::new(AppHandler::connect(&config)?);
+//! let user = session.run(load_user(id));
+//! let orders = session.try_run(load_orders(user.id))?;
+//! println!("{} queries", session.handler().queries);
+//! ```
+//!
+//! Each run is the [`scoped`](Effectful::scoped) sub-computation of its
+//! position in the session, so seeded handlers give every run values of its
+//! own.
+
+use crate::{EffectError, Effectful, Handler, Partial, PartialHandler};
+
+/// A handler kept across the computations it runs.
+#[derive(Debug, Default)]
+pub struct HandlerSession<H> {
+    handler: H,
+    runs: u64,
+}
+
+impl<H> HandlerSession<H> {
+    pub fn new(handler: H) -> Self {
+        Self { handler, runs: 0 }
+    }
+
+    /// Runs `computation` to its end with the session's total handler.
+    ///
+    /// # Panics
+    ///
+    /// Panics like [`Effectful::run_with`] if the computation is aborted.
+    pub fn run<R, Op>(&mut self, computation: Effectful<R, Op>) -> R
+    where
+        H: Handler<Op>,
+    {
+        self.next_run(computation).run_unchecked(&mut self.handler)
+    }
+
+    /// Runs `computation` to its end with the session's partial handler,
+    /// failing like [`Effectful::try_run_with`].
+    ///
+    /// The session can still be used after a failed run.
+    pub fn try_run<R, Op>(&mut self, computation: Effectful<R, Op>) -> Result<R, EffectError<Op>>
+    where
+        H: PartialHandler<Op>,
+    {
+        let handler = core::any::type_name::<H>();
+        self.next_run(computation)
+            .drive_as(handler, Partial(&mut self.handler))
+    }
+
+    /// How many computations the session has run.
+    pub fn runs(&self) -> u64 {
+        self.runs
+    }
+
+    /// The handler, with the state left by the runs so far.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Ends the session, returning the handler.
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+
+    fn next_run<R, Op>(&mut self, computation: Effectful<R, Op>) -> Effectful<R, Op> {
+        self.runs += 1;
+        computation.scoped(self.runs - 1)
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::any::Any;
+
+    effect! {
+        Cache::Get (u32) -> String;
+        Cache::Evict (u32) -> ();
+    }
+
+    /// Computes values on a miss and keeps them, counting the misses.
+    #[derive(Default)]
+    struct Memo {
+        values: Vec<(u32, String)>,
+        misses: usize,
+    }
+
+    impl Handler<Op> for Memo {
+        fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+            match op {
+                Op::Cache(Cache::Get(key)) => {
+                    if let Some((_, value)) = self.values.iter().find(|(k, _)| k == key) {
+                        return Box::new(value.clone());
+                    }
+                    self.misses += 1;
+                    let value = format!("value {key}");
+                    self.values.push((*key, value.clone()));
+                    Box::new(value)
+                }
+                Op::Cache(Cache::Evict(key)) => {
+                    self.values.retain(|(k, _)| k != key);
+                    Box::new(())
+                }
+            }
+        }
+    }
+
+    /// Answers only lookups of keys it already holds.
+    impl PartialHandler<Op> for Memo {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Cache(Cache::Get(key)) if !self.values.iter().any(|(k, _)| k == key) => None,
+                _ => Some(self.handle(op)),
+            }
+        }
+    }
+
+    #[effectful]
+    fn get(key: u32) -> String {
+        perform!(Cache::Get(key))
+    }
+
+    #[effectful]
+    fn evict(key: u32) {
+        perform!(Cache::Evict(key))
+    }
+
+    #[test]
+    fn test_sessions_keep_handler_state_between_runs() {
+        let mut session = HandlerSession::new(Memo::default());
+        assert_eq!(session.run(get(1)), "value 1");
+        assert_eq!(session.run(get(1)), "value 1");
+        assert_eq!(session.run(get(2)), "value 2");
+        assert_eq!(session.handler().misses, 2);
+        assert_eq!(session.runs(), 3);
+
+        session.handler_mut().misses = 0;
+        session.run(evict(1));
+        session.run(get(1));
+        assert_eq!(session.into_handler().misses, 1);
+    }
+
+    #[test]
+    fn test_sessions_survive_failed_runs() {
+        let mut session = HandlerSession::new(Memo::default());
+        assert!(matches!(
+            session.try_run(get(7)),
+            Err(EffectError::Unhandled(_))
+        ));
+        session.run(get(7));
+        assert_eq!(session.try_run(get(7)).unwrap(), "value 7");
+        assert_eq!(session.runs(), 3);
+    }
+}
@@ -0,0 +1,275 @@
+//! Deterministic simulation.
+//!
+//! A program whose only sources of nondeterminism are the [`Time`] and
+//! [`Random`] effects behaves the same on every run given the same answers to
+//! them. A [`SimHandler`] gives those answers from a virtual clock and a
+//! seeded random stream, so a whole program can be tested against many
+//! simulated runs, FoundationDB-style: [`simulate`] runs a test once per
+//! seed, and a failure names the seed that reproduces it.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::sim::{simulate, Random, SimHandler, Time};
+//!
+//! effect! {
+//!     use algae::effects::time::Time;
+//!     use algae::effects::random::Random;
+//!     Net::Send (Vec<u8>) -> bool;
+//! }
+//!
+//! simulate(0..1000, |sim| {
+//!     let delivered = send_with_retries(payload())
+//!         .begin_chain()
+//!         .handle(LossyNet::new())
+//!         .handle(sim)
+//!         .run()?;
+//!     assert!(delivered);
+//!     Ok::<_, EffectError<Op>>(())
+//! })?;
+//!
+//! // After a failure, the seed replays it:
+//! let sim = SimHandler::new(417);
+//! ```
+
+pub use crate::effects::{random::Random, time::Time};
+
+use crate::{
+    effects::{random::SeededRandom, time::VirtualClock},
+    Contains, IntoVecHandler, PartialHandler, VecHandler,
+};
+use std::{
+    any::Any,
+    error::Error,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+/// Handles [`Time`] operations with a [`VirtualClock`] and [`Random`]
+/// operations with a [`SeededRandom`].
+///
+/// Time only moves when the program sleeps or the test moves it, through the
+/// handler or a [`clock`](Self::clock) handle kept before handing the handler
+/// to the program.
+#[derive(Debug)]
+pub struct SimHandler {
+    clock: VirtualClock,
+    random: SeededRandom,
+}
+
+impl SimHandler {
+    /// A simulation drawing random values from `seed`, with the clock at the
+    /// Unix epoch.
+    pub fn new(seed: u64) -> Self {
+        Self::starting_at(seed, SystemTime::UNIX_EPOCH)
+    }
+
+    /// A simulation drawing random values from `seed`, with the clock at
+    /// `start`.
+    pub fn starting_at(seed: u64, start: SystemTime) -> Self {
+        Self {
+            clock: VirtualClock::starting_at(start),
+            random: SeededRandom::new(seed),
+        }
+    }
+
+    /// The seed random values are drawn from.
+    pub fn seed(&self) -> u64 {
+        self.random.seed()
+    }
+
+    /// The virtual clock answering [`Time`] operations; clones share it.
+    pub fn clock(&self) -> VirtualClock {
+        self.clock.clone()
+    }
+
+    /// Moves virtual time forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        self.clock.advance(by);
+    }
+
+    /// Sets the virtual wall time to `now`; see [`VirtualClock::set`].
+    pub fn set(&self, now: SystemTime) {
+        self.clock.set(now);
+    }
+
+    /// The current virtual wall time.
+    pub fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    /// Virtual time elapsed since the simulation started.
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+}
+
+impl<Op: Contains<Time> + Contains<Random>> PartialHandler<Op> for SimHandler {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.clock
+            .maybe_handle(op)
+            .or_else(|| self.random.maybe_handle(op))
+    }
+}
+
+impl<Op: Contains<Time> + Contains<Random> + 'static> IntoVecHandler<Op> for SimHandler {
+    fn into_vec_handler(self) -> VecHandler<Op> {
+        let mut vec = VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+/// A simulated run that failed, returned by [`simulate`].
+#[derive(Debug)]
+pub struct SimFailure<E> {
+    /// The seed of the failed run; [`SimHandler::new`] with it replays the
+    /// run.
+    pub seed: u64,
+    pub error: E,
+}
+
+impl<E: fmt::Debug> fmt::Display for SimFailure<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "simulation with seed {} failed: {:?}",
+            self.seed, self.error
+        )
+    }
+}
+
+impl<E: fmt::Debug> Error for SimFailure<E> {}
+
+/// Prints the seed of a simulated run that panics.
+struct SeedReport(u64);
+
+impl Drop for SeedReport {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!("simulation with seed {} panicked", self.0);
+        }
+    }
+}
+
+/// Runs `test` with a [`SimHandler`] for each of `seeds`, stopping at the
+/// first run that fails.
+///
+/// A run that panics, e.g. on a failed assertion, has its seed printed to
+/// stderr before the panic continues.
+pub fn simulate<E, F>(
+    seeds: impl IntoIterator<Item = u64>,
+    mut test: F,
+) -> Result<(), SimFailure<E>>
+where
+    F: FnMut(SimHandler) -> Result<(), E>,
+{
+    for seed in seeds {
+        let report = SeedReport(seed);
+        let result = test(SimHandler::new(seed));
+        drop(report);
+        result.map_err(|error| SimFailure { seed, error })?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        use algae::effects::time::Time;
+        use algae::effects::random::Random;
+        Net::Send -> bool;
+    }
+
+    /// Delivers every message; the computation draws its own losses.
+    struct LossyNet;
+
+    impl PartialHandler<Op> for LossyNet {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Net(Net::Send) => Some(Box::new(true)),
+                _ => None,
+            }
+        }
+    }
+
+    algae::impl_into_vec_handler!(LossyNet, Op);
+
+    /// Sends after a random delay, retrying when the jittered send is lost.
+    #[effectful]
+    fn send_with_jitter(attempts: u32) -> (u32, Duration) {
+        let mut tries = 0;
+        while tries < attempts {
+            tries += 1;
+            let jitter: u64 = perform!(Random::Below(1000));
+            let _: () = perform!(Time::Sleep(Duration::from_millis(jitter)));
+            let lost: f64 = perform!(Random::Float);
+            let sent: bool = perform!(Net::Send);
+            if sent && lost >= 0.5 {
+                break;
+            }
+        }
+        let now: SystemTime = perform!(Time::Now);
+        (tries, now.duration_since(SystemTime::UNIX_EPOCH).unwrap())
+    }
+
+    fn run(sim: SimHandler) -> (u32, Duration) {
+        send_with_jitter(10)
+            .begin_chain()
+            .handle(LossyNet)
+            .handle(sim)
+            .run()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_runs_are_reproducible_per_seed() {
+        assert_eq!(run(SimHandler::new(3)), run(SimHandler::new(3)));
+        let outcomes: std::collections::HashSet<_> =
+            (0..20).map(|seed| run(SimHandler::new(seed))).collect();
+        assert!(outcomes.len() > 1);
+    }
+
+    #[test]
+    fn test_time_is_controlled_by_the_test() {
+        let sim = SimHandler::new(3);
+        let clock = sim.clock();
+        sim.set(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000));
+        sim.advance(Duration::from_secs(5));
+        let (_, now) = run(sim);
+        assert!(now >= Duration::from_secs(1_005));
+        assert_eq!(
+            clock.now(),
+            SystemTime::UNIX_EPOCH + now,
+            "the handle sees the time the program slept to"
+        );
+    }
+
+    #[test]
+    fn test_simulate_names_the_failing_seed() {
+        let failure = simulate(0..100, |sim| match run(sim) {
+            (tries, _) if tries > 3 => Err(tries),
+            _ => Ok(()),
+        })
+        .unwrap_err();
+        assert_eq!(run(SimHandler::new(failure.seed)).0, failure.error);
+        assert_eq!(
+            failure.to_string(),
+            format!(
+                "simulation with seed {} failed: {}",
+                failure.seed, failure.error
+            )
+        );
+        assert!(simulate(0..failure.seed, |sim| match run(sim) {
+            (tries, _) if tries > 3 => Err(tries),
+            _ => Ok(()),
+        })
+        .is_ok());
+    }
+}
@@ -0,0 +1,126 @@
+//! Spawning computations on smol (feature `smol`).
+//!
+//! [`Smol`] implements [`Spawner`] with smol's global executor, so handled
+//! computations can run as smol tasks:
+//!
+//! ```rust,ignore
+//! use algae::executor::Spawner;
+//! use algae::smol::Smol;
+//!
+//! let report = smol::block_on(async {
+//!     let task = Smol.spawn_handled(build_report(), ReportHandler::new());
+//!     task.await
+//! })?;
+//! ```
+//!
+//! As with any smol task, dropping a handle cancels the task at its next
+//! `.await`; call [`Task::detach`] to let it run on its own.
+
+use crate::executor::Spawner;
+use ::smol::Task;
+use std::future::Future;
+
+/// Spawns tasks on smol's global executor, and blocking work on its thread
+/// pool.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Smol;
+
+impl Spawner for Smol {
+    type Handle<T: Send + 'static> = Task<T>;
+
+    fn spawn<F>(&self, future: F) -> Task<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        ::smol::spawn(future)
+    }
+
+    fn spawn_blocking<F, T>(&self, f: F) -> Task<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        ::smol::unblock(f)
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::executor::{AsyncHandler, BoxFuture};
+    use algae::prelude::*;
+    use std::{any::Any, time::Duration};
+
+    effect! {
+        Clock::Sleep (u64) -> ();
+        Counter::Add (u32) -> u32;
+    }
+
+    struct Timer;
+
+    impl AsyncHandler<Op> for Timer {
+        fn maybe_handle<'a>(
+            &'a mut self,
+            op: &'a Op,
+        ) -> BoxFuture<'a, Option<Box<dyn Any + Send>>> {
+            Box::pin(async move {
+                let Op::Clock(Clock::Sleep(ms)) = op else {
+                    return None;
+                };
+                ::smol::Timer::after(Duration::from_millis(*ms)).await;
+                Some(Box::new(()) as Box<dyn Any + Send>)
+            })
+        }
+    }
+
+    struct Tally(u32);
+
+    impl PartialHandler<Op> for Tally {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Counter(Counter::Add(n)) = op else {
+                return None;
+            };
+            self.0 += n;
+            Some(Box::new(self.0))
+        }
+    }
+
+    #[effectful]
+    fn count_slowly(n: u32) -> u32 {
+        let mut total = 0;
+        for _ in 0..n {
+            let _: () = perform!(Clock::Sleep(1));
+            total = perform!(Counter::Add(1));
+        }
+        total
+    }
+
+    #[test]
+    fn test_spawned_computations() {
+        let (handled, async_handled) = ::smol::block_on(async {
+            let handled = Smol.spawn_handled(count_slowly(0), Tally(10));
+            let async_handled = Smol.spawn_async(count_slowly(3), Timer, Tally(0));
+            (handled.await, async_handled.await)
+        });
+        assert_eq!(handled.unwrap(), 0);
+        assert_eq!(async_handled.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_spawned_computations_get_fresh_handlers() {
+        #[effectful]
+        fn add_one() -> u32 {
+            perform!(Counter::Add(1))
+        }
+
+        let totals = ::smol::block_on(async {
+            let factory = || Tally(0);
+            let first = Smol.spawn_with(add_one(), &factory);
+            let second = Smol.spawn_with(add_one(), &factory);
+            (first.await.unwrap(), second.await.unwrap())
+        });
+        assert_eq!(totals, (1, 1));
+    }
+}
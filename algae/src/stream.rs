@@ -0,0 +1,295 @@
+//! Effectful computations that produce a stream of values.
+//!
+//! An [`Effectful`](crate::Effectful) hands its caller a single result once it
+//! has finished. An [`EffectStream`] instead hands over values while it runs:
+//! the caller pulls items one at a time, and the effects performed between two
+//! items are handled on the way. Downstream consumers can start working on the
+//! first item before the producer has computed the second.
+//!
+//! Streams are usually written with `#[effectful(yields = T)]`. Inside such a
+//! function `emit!(value)` passes a value to the caller and `perform!` works as
+//! usual:
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, coroutine_trait, yield_expr)]
+//! use algae::prelude::*;
+//!
+//! effect! {
+//!     File::ReadLine -> Option<String>;
+//! }
+//!
+//! #[effectful(yields = String)]
+//! fn tail_log() {
+//!     while let Some(line) = perform!(File::ReadLine) {
+//!         emit!(line);
+//!     }
+//! }
+//!
+//! for line in tail_log().handle(FileHandler::open("app.log")) {
+//!     println!("{line}");
+//! }
+//! ```
+//!
+//! The handled stream is an [`Iterator`] when the handler is total. Partial
+//! handlers and aborts are reported by [`HandledStream::try_next`].
+
+use crate::{dispatch_effect, Effect, EffectError, Handler, PartialHandler, Reply};
+use alloc::boxed::Box;
+use core::{
+    any::Any,
+    ops::{Coroutine, CoroutineState},
+    pin::Pin,
+};
+
+/// What a stream's coroutine hands to its driver.
+pub enum StreamStep<T, Op: 'static> {
+    /// An operation to handle; the coroutine is resumed with the reply.
+    Effect(Effect<Op>),
+    /// A value for the caller; the coroutine is resumed with `None`.
+    Item(T),
+}
+
+type StreamCoroutine<T, Op> =
+    Pin<Box<dyn Coroutine<Option<Reply>, Return = (), Yield = StreamStep<T, Op>> + Send>>;
+
+/// An effectful computation that produces values of type `T` while it runs.
+///
+/// # Type Parameters
+///
+/// * `T` - The type of the produced values
+/// * `Op` - The type of effects that can be performed
+pub struct EffectStream<T, Op: 'static> {
+    gen: StreamCoroutine<T, Op>,
+}
+
+/// The streaming counterpart of [`Effectful`](crate::Effectful), under the
+/// name used alongside it.
+pub type Streamful<T, Op> = EffectStream<T, Op>;
+
+impl<T, Op: 'static> EffectStream<T, Op> {
+    /// Creates a stream from a coroutine.
+    ///
+    /// This is typically called by `#[effectful(yields = T)]`.
+    pub fn new<G>(g: G) -> Self
+    where
+        G: Coroutine<Option<Reply>, Return = (), Yield = StreamStep<T, Op>> + 'static + Send,
+    {
+        Self { gen: Box::pin(g) }
+    }
+
+    /// Pairs the stream with the handler that answers its effects.
+    ///
+    /// Nothing runs until the first item is requested.
+    pub fn handle<H>(self, h: H) -> HandledStream<T, Op, H> {
+        HandledStream {
+            stream: Some(self),
+            h,
+        }
+    }
+
+    /// Runs the stream up to its next item.
+    ///
+    /// Returns `Ok(None)` when the stream has finished.
+    fn advance<F>(&mut self, mut dispatch: F) -> Result<Option<T>, EffectError<Op>>
+    where
+        F: FnMut(&Op) -> Option<Box<dyn Any + Send>>,
+    {
+        let mut resume_arg: Option<Reply> = None;
+        loop {
+            match self.gen.as_mut().resume(resume_arg) {
+                CoroutineState::Complete(()) => return Ok(None),
+                CoroutineState::Yielded(StreamStep::Item(item)) => return Ok(Some(item)),
+                CoroutineState::Yielded(StreamStep::Effect(eff)) => {
+                    resume_arg = Some(dispatch_effect(eff, &mut dispatch)?);
+                }
+            }
+        }
+    }
+}
+
+/// A stream bundled with its handler, returned by [`EffectStream::handle`].
+///
+/// Once the stream has finished or stopped with an error, every further
+/// request returns `None`.
+pub struct HandledStream<T, Op: 'static, H> {
+    stream: Option<EffectStream<T, Op>>,
+    h: H,
+}
+
+impl<T, Op: 'static, H> HandledStream<T, Op, H> {
+    /// Returns the handler, e.g. to inspect the state it accumulated.
+    pub fn into_handler(self) -> H {
+        self.h
+    }
+}
+
+impl<T, Op: 'static, H> HandledStream<T, Op, H>
+where
+    H: PartialHandler<Op>,
+{
+    /// Runs the stream up to its next item, reporting unhandled operations
+    /// and aborts as errors.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(item))` - The next value
+    /// * `Ok(None)` - The stream has finished
+    /// * `Err(_)` - An operation was declined or aborted; the stream is dropped
+    pub fn try_next(&mut self) -> Result<Option<T>, EffectError<Op>> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(None);
+        };
+        let h = &mut self.h;
+        let step = stream.advance(|op| h.maybe_handle(op));
+        if !matches!(step, Ok(Some(_))) {
+            self.stream = None;
+        }
+        step
+    }
+}
+
+impl<T, Op: 'static, H> Iterator for HandledStream<T, Op, H>
+where
+    H: Handler<Op>,
+{
+    type Item = T;
+
+    /// # Panics
+    ///
+    /// Panics if the handler replies with an [`Abort`](crate::Abort).
+    fn next(&mut self) -> Option<T> {
+        let stream = self.stream.as_mut()?;
+        let h = &mut self.h;
+        match stream.advance(|op| Some(h.handle(op))) {
+            Ok(Some(item)) => Some(item),
+            Ok(None) => {
+                self.stream = None;
+                None
+            }
+            Err(EffectError::Aborted(abort)) => panic!("Effectful computation aborted: {abort}"),
+            Err(EffectError::ReplyMismatch(error)) => panic!("{error}"),
+            Err(EffectError::Unhandled(_)) => unreachable!("total handlers answer every operation"),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use crate::Abort;
+    use algae::prelude::*;
+
+    effect! {
+        Source::Next -> Option<u32>;
+        Sink::Note (String) -> ();
+    }
+
+    struct Numbers {
+        remaining: Vec<u32>,
+        notes: Vec<String>,
+    }
+
+    impl Numbers {
+        fn new(values: &[u32]) -> Self {
+            let mut remaining = values.to_vec();
+            remaining.reverse();
+            Self {
+                remaining,
+                notes: Vec::new(),
+            }
+        }
+    }
+
+    impl Handler<Op> for Numbers {
+        fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+            match op {
+                Op::Source(Source::Next) => Box::new(self.remaining.pop()),
+                Op::Sink(Sink::Note(note)) => {
+                    self.notes.push(note.clone());
+                    Box::new(())
+                }
+            }
+        }
+    }
+
+    #[effectful(yields = u32)]
+    fn doubled() {
+        loop {
+            let next: Option<u32> = perform!(Source::Next);
+            let Some(n) = next else { break };
+            let _: () = perform!(Sink::Note(format!("read {n}")));
+            emit!(n * 2);
+        }
+    }
+
+    #[test]
+    fn test_stream_iterates_items() {
+        let items: Vec<u32> = doubled().handle(Numbers::new(&[1, 2, 3])).collect();
+        assert_eq!(items, [2, 4, 6]);
+
+        let stream: Streamful<u32, Op> = doubled();
+        assert_eq!(stream.handle(Numbers::new(&[4])).collect::<Vec<_>>(), [8]);
+    }
+
+    #[test]
+    fn test_stream_is_lazy() {
+        let mut stream = doubled().handle(Numbers::new(&[5, 6]));
+        assert_eq!(stream.next(), Some(10));
+        // Only the effects up to the first item have run.
+        let handler = stream.into_handler();
+        assert_eq!(handler.notes, ["read 5"]);
+        assert_eq!(handler.remaining, [6]);
+    }
+
+    #[test]
+    fn test_try_next_reports_unhandled_ops() {
+        struct SourceOnly(u32);
+        impl PartialHandler<Op> for SourceOnly {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+                match op {
+                    Op::Source(Source::Next) => {
+                        self.0 += 1;
+                        Some(Box::new(Some(self.0)))
+                    }
+                    _ => None,
+                }
+            }
+        }
+
+        let mut stream = doubled().handle(SourceOnly(0));
+        match stream.try_next() {
+            Err(EffectError::Unhandled(UnhandledOp { op, .. })) => {
+                assert_eq!(op, Op::Sink(Sink::Note("read 1".into())))
+            }
+            other => panic!("expected an unhandled op, got {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(stream.try_next(), Ok(None)));
+    }
+
+    #[test]
+    fn test_try_next_reports_aborts() {
+        struct Failing;
+        impl PartialHandler<Op> for Failing {
+            fn maybe_handle(&mut self, _: &Op) -> Option<Box<dyn Any + Send>> {
+                Some(Abort::boxed("source closed"))
+            }
+        }
+
+        let mut stream = doubled().handle(Failing);
+        assert!(matches!(stream.try_next(), Err(EffectError::Aborted(_))));
+    }
+
+    #[test]
+    fn test_manual_stream() {
+        let stream = EffectStream::<&str, Op>::new(
+            #[coroutine]
+            |_: Option<Reply>| {
+                yield StreamStep::Item("a");
+                yield StreamStep::Item("b");
+            },
+        );
+        let items: Vec<_> = stream.handle(Numbers::new(&[])).collect();
+        assert_eq!(items, ["a", "b"]);
+    }
+}
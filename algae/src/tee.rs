@@ -0,0 +1,343 @@
+//! Audit trails: mirroring every answered operation to a sink.
+//!
+//! A [`TeeHandler`] hands each operation to its handler as usual, and then
+//! shows the operation and the handler's reply to an [`AuditSink`], so a
+//! workflow keeps a trail of every side effect it performed without its
+//! behavior changing. Sinks can be closures, a [`WriterSink`] writing a line
+//! per operation, or a [`ChannelSink`] sending [`AuditRecord`]s to another
+//! thread:
+//!
+//! ```rust,ignore
+//! let (sender, receiver) = std::sync::mpsc::channel();
+//! let audit = ChannelSink::new(sender).format(ReplyFormat::new().show::<u64>());
+//! thread::spawn(move || {
+//!     for record in receiver {
+//!         audit_log.append(&record.op, &record.reply);
+//!     }
+//! });
+//!
+//! transfer(from, to, amount).try_run_with(TeeHandler::new(BankHandler, audit))?;
+//! ```
+//!
+//! Replies are boxed `Any`s, so sinks that render them can only show those
+//! whose types were registered with [`ReplyFormat::show`]. Operations the
+//! handler declines have no reply and are not mirrored; aborts are.
+
+use crate::{redact::redacted, Abort, OpMeta, PartialHandler};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    sync::mpsc::Sender,
+};
+
+/// Where a [`TeeHandler`] mirrors the operations it answers.
+pub trait AuditSink<Op>: Send {
+    /// `op` was answered with `reply`, which may be an [`Abort`].
+    fn record(&mut self, op: &Op, reply: &(dyn Any + Send));
+}
+
+impl<Op, F> AuditSink<Op> for F
+where
+    F: FnMut(&Op, &(dyn Any + Send)) + Send,
+{
+    fn record(&mut self, op: &Op, reply: &(dyn Any + Send)) {
+        self(op, reply)
+    }
+}
+
+type ShowFn = fn(&(dyn Any + Send)) -> String;
+
+fn show_reply<R: fmt::Debug + 'static>(reply: &(dyn Any + Send)) -> String {
+    format!(
+        "{:?}",
+        reply
+            .downcast_ref::<R>()
+            .expect("shown as the type it was registered for")
+    )
+}
+
+/// Renders replies for sinks that write them out, with the `Debug` impls of
+/// the reply types registered with [`show`](Self::show).
+///
+/// Aborts are shown as `aborted: ` and their description, and replies of
+/// other types as `<reply>`.
+#[derive(Clone, Default)]
+pub struct ReplyFormat {
+    shows: HashMap<TypeId, ShowFn>,
+}
+
+impl ReplyFormat {
+    /// A format showing no reply types, only aborts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows replies of type `R` with their `Debug` impl.
+    pub fn show<R: fmt::Debug + Send + 'static>(mut self) -> Self {
+        self.shows.insert(TypeId::of::<R>(), show_reply::<R>);
+        self
+    }
+
+    /// Renders `reply`.
+    pub fn describe(&self, reply: &(dyn Any + Send)) -> String {
+        if let Some(abort) = reply.downcast_ref::<Abort>() {
+            return format!("aborted: {abort}");
+        }
+        match self.shows.get(&reply.type_id()) {
+            Some(show) => show(reply),
+            None => "<reply>".to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for ReplyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplyFormat")
+            .field("types", &self.shows.len())
+            .finish()
+    }
+}
+
+/// An [`AuditSink`] writing a line per operation: the operation as
+/// [`redacted`] shows it, then its reply.
+///
+/// A failing writer does not fail the computation; the error is kept for
+/// [`take_error`](Self::take_error), and later operations are still written.
+#[derive(Debug)]
+pub struct WriterSink<W> {
+    writer: W,
+    format: ReplyFormat,
+    error: Option<io::Error>,
+}
+
+impl<W: Write + Send> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            format: ReplyFormat::new(),
+            error: None,
+        }
+    }
+
+    /// Renders replies with `format`.
+    pub fn format(mut self, format: ReplyFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The last error writing a line, if there was one since the last call.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<Op, W> AuditSink<Op> for WriterSink<W>
+where
+    Op: fmt::Debug + OpMeta,
+    W: Write + Send,
+{
+    fn record(&mut self, op: &Op, reply: &(dyn Any + Send)) {
+        let line = format!("{} -> {}", redacted(op), self.format.describe(reply));
+        if let Err(error) = writeln!(self.writer, "{line}") {
+            self.error = Some(error);
+        }
+    }
+}
+
+/// An operation a [`ChannelSink`] sent, with its reply rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord<Op> {
+    pub op: Op,
+    /// The reply, as the sink's [`ReplyFormat`] shows it.
+    pub reply: String,
+}
+
+/// An [`AuditSink`] sending an [`AuditRecord`] per operation, e.g. to a
+/// thread storing the trail.
+///
+/// Once the receiver is gone, records are dropped.
+#[derive(Debug)]
+pub struct ChannelSink<Op> {
+    sender: Sender<AuditRecord<Op>>,
+    format: ReplyFormat,
+}
+
+impl<Op> ChannelSink<Op> {
+    pub fn new(sender: Sender<AuditRecord<Op>>) -> Self {
+        Self {
+            sender,
+            format: ReplyFormat::new(),
+        }
+    }
+
+    /// Renders replies with `format`.
+    pub fn format(mut self, format: ReplyFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl<Op: Clone + Send> AuditSink<Op> for ChannelSink<Op> {
+    fn record(&mut self, op: &Op, reply: &(dyn Any + Send)) {
+        let _ = self.sender.send(AuditRecord {
+            op: op.clone(),
+            reply: self.format.describe(reply),
+        });
+    }
+}
+
+/// A handler wrapper mirroring the operations its handler answers, and the
+/// replies, to an [`AuditSink`].
+///
+/// # Type Parameters
+///
+/// * `H` - The wrapped partial handler, which answers the operations
+/// * `S` - The sink
+pub struct TeeHandler<H, S> {
+    inner: H,
+    sink: S,
+}
+
+impl<H, S> TeeHandler<H, S> {
+    pub fn new(inner: H, sink: S) -> Self {
+        Self { inner, sink }
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn into_parts(self) -> (H, S) {
+        (self.inner, self.sink)
+    }
+}
+
+impl<Op, H, S> PartialHandler<Op> for TeeHandler<H, S>
+where
+    H: PartialHandler<Op>,
+    S: AuditSink<Op>,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let reply = self.inner.maybe_handle(op)?;
+        self.sink.record(op, &*reply);
+        Some(reply)
+    }
+}
+
+impl<H: fmt::Debug, S: fmt::Debug> fmt::Debug for TeeHandler<H, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TeeHandler")
+            .field("inner", &self.inner)
+            .field("sink", &self.sink)
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+    use std::sync::mpsc;
+
+    effect! {
+        Bank::Balance (String) -> u64;
+        Bank::Withdraw ((String, u64)) -> u64;
+        Bank::Close (String) -> ();
+    }
+
+    /// One account holding 100; closing it aborts, other names are declined.
+    #[derive(Debug, Default)]
+    struct Teller {
+        withdrawn: u64,
+    }
+
+    impl PartialHandler<Op> for Teller {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            let Op::Bank(op) = op;
+            match op {
+                Bank::Balance(name) if name == "ada" => Some(Box::new(100 - self.withdrawn)),
+                Bank::Withdraw((name, amount)) if name == "ada" => {
+                    self.withdrawn += amount;
+                    Some(Box::new(100 - self.withdrawn))
+                }
+                Bank::Close(_) => Some(Abort::boxed("accounts cannot be closed")),
+                _ => None,
+            }
+        }
+    }
+
+    #[effectful]
+    fn withdraw(name: &'static str, amount: u64) -> u64 {
+        let balance: u64 = perform!(Bank::Balance(name.into()));
+        if balance < amount {
+            return balance;
+        }
+        perform!(Bank::Withdraw((name.into(), amount)))
+    }
+
+    #[effectful]
+    fn close(name: &'static str) {
+        let _: () = perform!(Bank::Close(name.into()));
+    }
+
+    #[test]
+    fn test_closures_see_every_reply() {
+        let mut seen = Vec::new();
+        let tee = TeeHandler::new(Teller::default(), |op: &Op, reply: &(dyn Any + Send)| {
+            seen.push((op.clone(), reply.downcast_ref::<u64>().copied()))
+        });
+        assert_eq!(withdraw("ada", 30).try_run_with(tee).unwrap(), 70);
+        assert_eq!(
+            seen,
+            [
+                (Op::bank_balance("ada".to_string()), Some(100)),
+                (Op::bank_withdraw("ada".to_string(), 30), Some(70)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_writer_sink_writes_a_line_per_operation() {
+        let sink = WriterSink::new(Vec::new()).format(ReplyFormat::new().show::<u64>());
+        let mut session =
+            crate::session::HandlerSession::new(TeeHandler::new(Teller::default(), sink));
+        session.try_run(withdraw("ada", 30)).unwrap();
+        assert!(session.try_run(close("ada")).is_err());
+        // Declined operations are not mirrored
+        assert!(session.try_run(withdraw("bob", 30)).is_err());
+
+        let (_, mut sink) = session.into_handler().into_parts();
+        assert!(sink.take_error().is_none());
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "Bank(Balance(\"ada\")) -> 100\n\
+             Bank(Withdraw((\"ada\", 30))) -> 70\n\
+             Bank(Close(\"ada\")) -> aborted: \"accounts cannot be closed\"\n"
+        );
+    }
+
+    #[test]
+    fn test_channel_sink_sends_records() {
+        let (sender, receiver) = mpsc::channel();
+        let tee = TeeHandler::new(Teller::default(), ChannelSink::new(sender));
+        assert_eq!(withdraw("ada", 300).try_run_with(tee).unwrap(), 100);
+        assert_eq!(
+            receiver.iter().collect::<Vec<_>>(),
+            [AuditRecord {
+                op: Op::bank_balance("ada".to_string()),
+                reply: "<reply>".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,693 @@
+//! Support for testing effectful code.
+//!
+//! [`run_test`] runs a computation with a handler and records every operation
+//! it performs. If the computation panics (a failed assertion) or stops with
+//! an unhandled operation or an abort, the recorded trace is printed, so the
+//! test output shows what the computation did before it went wrong.
+//!
+//! [`Trace::record`] records a run without failing on errors, and
+//! [`Trace::diff`] compares two recorded runs, e.g. of the same workflow
+//! before and after a change.
+//!
+//! [`DeterministicEnv`] is a ready-made handler for the standard effect
+//! families: virtual time, sequential ids, in-memory logs and configuration,
+//! silent progress and a cancellation token that is never tripped. It is the
+//! default environment of the `#[algae_test]` attribute:
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, coroutine_trait, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::effects::time::Time;
+//! use algae::testing::DeterministicEnv;
+//! use std::time::{Duration, SystemTime};
+//!
+//! effect! {
+//!     use algae::effects::time::Time;
+//!     Db::Get (u32) -> Option<String>;
+//! }
+//!
+//! // Runs in the default DeterministicEnv.
+//! #[algae_test]
+//! fn sleeping_advances_virtual_time() {
+//!     let _: () = perform!(Time::Sleep(Duration::from_secs(60)));
+//!     let now: SystemTime = perform!(Time::Now);
+//!     assert_eq!(now, SystemTime::UNIX_EPOCH + Duration::from_secs(60));
+//! }
+//!
+//! fn fixture() -> VecHandler<Op> {
+//!     let mut handlers = VecHandler::new();
+//!     handlers.push(FakeDb::with_users(["alice"]));
+//!     handlers.push(DeterministicEnv::new());
+//!     handlers
+//! }
+//!
+//! // Runs in the handler returned by `fixture`.
+//! #[algae_test(fixture = fixture)]
+//! fn finds_alice() {
+//!     let user: Option<String> = perform!(Db::Get(1));
+//!     assert_eq!(user.as_deref(), Some("alice"));
+//! }
+//! ```
+
+use crate::{
+    effects::{
+        config::{Config, MapConfig, Value},
+        ctl::{CancellationHandler, CancellationToken, Ctl},
+        log::{Log, MemoryLog},
+        progress::{Progress, SilentProgress},
+        time::{Time, VirtualClock},
+    },
+    perform_location,
+    redact::redacted,
+    EffectError, Effectful, FamilyOp, OpMeta, PartialHandler,
+};
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe, Location},
+};
+
+/// Handles the standard effect families deterministically.
+///
+/// | Family | Handler |
+/// |--------|---------|
+/// | `Config` | [`MapConfig`], empty unless set with [`config`](Self::config) |
+/// | `Ctl` | [`CancellationHandler`] with a token that is never tripped |
+/// | `Id` (feature `uuid`) | [`DeterministicIds::sequential`](crate::effects::id::DeterministicIds::sequential) |
+/// | `Log` | [`MemoryLog`] |
+/// | `Progress` | [`SilentProgress`] |
+/// | `Time` | [`VirtualClock`] starting at the Unix epoch |
+///
+/// Operations of other families are declined. The root only has to embed
+/// the families it uses.
+#[derive(Debug)]
+pub struct DeterministicEnv {
+    config: MapConfig,
+    ctl: CancellationHandler,
+    #[cfg(feature = "uuid")]
+    ids: crate::effects::id::DeterministicIds,
+    log: MemoryLog,
+    progress: SilentProgress,
+    clock: VirtualClock,
+}
+
+impl DeterministicEnv {
+    pub fn new() -> Self {
+        Self {
+            config: MapConfig::new(),
+            ctl: CancellationHandler::new(CancellationToken::new()),
+            #[cfg(feature = "uuid")]
+            ids: crate::effects::id::DeterministicIds::sequential(),
+            log: MemoryLog::new(),
+            progress: SilentProgress::new(),
+            clock: VirtualClock::new(),
+        }
+    }
+
+    /// Sets the configuration value for `key`.
+    pub fn config(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.config = self.config.set(key, value);
+        self
+    }
+
+    /// Hands out UUIDs derived from `seed` instead of sequential ones, one
+    /// stream per sub-computation; see
+    /// [`DeterministicIds::seeded`](crate::effects::id::DeterministicIds::seeded).
+    #[cfg(feature = "uuid")]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.ids = crate::effects::id::DeterministicIds::seeded(seed);
+        self
+    }
+
+    /// The log collecting [`Log`] events; clones share its buffer.
+    pub fn log(&self) -> &MemoryLog {
+        &self.log
+    }
+
+    /// The virtual clock answering [`Time`] operations; clones share it.
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+}
+
+impl Default for DeterministicEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Op: FamilyOp> PartialHandler<Op> for DeterministicEnv {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let op = op.family_op();
+        if let Some(op) = op.downcast_ref::<Time>() {
+            self.clock.maybe_handle(op)
+        } else if let Some(op) = op.downcast_ref::<Log>() {
+            self.log.maybe_handle(op)
+        } else if let Some(op) = op.downcast_ref::<Config>() {
+            self.config.maybe_handle(op)
+        } else if let Some(op) = op.downcast_ref::<Progress>() {
+            self.progress.maybe_handle(op)
+        } else if let Some(op) = op.downcast_ref::<Ctl>() {
+            self.ctl.maybe_handle(op)
+        } else {
+            #[cfg(feature = "uuid")]
+            if let Some(op) = op.downcast_ref::<crate::effects::id::Id>() {
+                return self.ids.maybe_handle(op);
+            }
+            None
+        }
+    }
+}
+
+impl<Op: FamilyOp + 'static> crate::IntoVecHandler<Op> for DeterministicEnv {
+    fn into_vec_handler(self) -> crate::VecHandler<Op> {
+        let mut vec = crate::VecHandler::new();
+        vec.push(self);
+        vec
+    }
+}
+
+/// The operations a computation performed, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    entries: Vec<String>,
+    // The `Debug` renderings alone, which is what diffs compare
+    ops: Vec<String>,
+}
+
+impl Trace {
+    /// Runs `computation` with `h`, recording the operations it performs.
+    pub fn record<R, Op, H>(
+        computation: Effectful<R, Op>,
+        h: H,
+    ) -> (Result<R, EffectError<Op>>, Self)
+    where
+        Op: OpMeta + fmt::Debug,
+        H: PartialHandler<Op>,
+    {
+        let mut trace = Self::default();
+        let result = computation.try_run_with(Tracing {
+            inner: h,
+            trace: &mut trace,
+        });
+        (result, trace)
+    }
+
+    /// One line per operation: its `Debug` rendering and where it was
+    /// performed. Sensitive payloads are [`redacted`].
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Appends an operation, rendered with [`redacted`], performed at
+    /// `location` if known.
+    pub(crate) fn push(&mut self, rendered: String, location: Option<&Location<'_>>) {
+        let entry = match location {
+            Some(location) => format!("{rendered} at {location}"),
+            None => rendered.clone(),
+        };
+        self.entries.push(entry);
+        self.ops.push(rendered);
+    }
+
+    /// Compares this trace with `other`, a later run of the same workflow.
+    ///
+    /// Operations are compared by their `Debug` renderings; where they were
+    /// performed is ignored, so moving code around does not show up. The
+    /// longest common run of operations is kept in place, and the rest is
+    /// reported as:
+    ///
+    /// - [`Moved`](TraceChange::Moved): the same operation at another point
+    ///   of the run,
+    /// - [`Changed`](TraceChange::Changed): an operation of the same variant,
+    ///   in the same place, with a different payload,
+    /// - [`Removed`](TraceChange::Removed) and [`Added`](TraceChange::Added):
+    ///   everything else.
+    ///
+    /// Variants are recognised by the leading names of the rendering, e.g.
+    /// `Db(Get` for `Db(Get(1))`.
+    pub fn diff(&self, other: &Trace) -> TraceDiff {
+        let (old, new) = (&self.ops, &other.ops);
+
+        // lengths[i][j]: the longest common subsequence of old[i..], new[j..]
+        let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lengths[i][j] = if old[i] == new[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+
+        // The unmatched operations between two matched ones
+        let mut gaps: Vec<(Vec<usize>, Vec<usize>)> = vec![Default::default()];
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                gaps.push(Default::default());
+                i += 1;
+                j += 1;
+            } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+                gaps.last_mut().unwrap().0.push(i);
+                i += 1;
+            } else {
+                gaps.last_mut().unwrap().1.push(j);
+                j += 1;
+            }
+        }
+
+        // Operations in both runs but out of order are moves; they are
+        // reported where the old run performed them
+        let mut moved = vec![Vec::new(); gaps.len()];
+        let mut unmatched: Vec<usize> = gaps.iter().flat_map(|(_, a)| a.clone()).collect();
+        for (gap, (removed, _)) in gaps.iter_mut().enumerate() {
+            removed.retain(|&from| {
+                let Some(k) = unmatched.iter().position(|&to| old[from] == new[to]) else {
+                    return true;
+                };
+                let to = unmatched.remove(k);
+                moved[gap].push(TraceChange::Moved {
+                    op: old[from].clone(),
+                    from,
+                    to,
+                });
+                false
+            });
+        }
+
+        let mut changes = Vec::new();
+        for ((removed, added), moved) in gaps.into_iter().zip(moved) {
+            changes.extend(moved);
+            let mut added: Vec<Option<usize>> = added
+                .into_iter()
+                .filter(|to| unmatched.contains(to))
+                .map(Some)
+                .collect();
+            for from in removed {
+                let name = variant_name(&old[from]);
+                let same_variant = added.iter_mut().find(|to| {
+                    to.is_some_and(|to| !name.is_empty() && variant_name(&new[to]) == name)
+                });
+                match same_variant.and_then(Option::take) {
+                    Some(to) => changes.push(TraceChange::Changed {
+                        from,
+                        to,
+                        before: old[from].clone(),
+                        after: new[to].clone(),
+                    }),
+                    None => changes.push(TraceChange::Removed {
+                        index: from,
+                        op: old[from].clone(),
+                    }),
+                }
+            }
+            changes.extend(added.into_iter().flatten().map(|to| TraceChange::Added {
+                index: to,
+                op: new[to].clone(),
+            }));
+        }
+        let common = old
+            .iter()
+            .zip(new)
+            .take_while(|(old, new)| old == new)
+            .count();
+        TraceDiff {
+            changes,
+            first_divergence: (old != new).then_some(common),
+        }
+    }
+}
+
+/// The leading variant names of an operation's `Debug` rendering: `Db(Get`
+/// for `Db(Get(1))`, `Time(Now` for `Time(Now)`.
+fn variant_name(op: &str) -> &str {
+    let mut end = 0;
+    let mut rest = op;
+    loop {
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 || rest[..len].starts_with(|c: char| c.is_ascii_digit()) {
+            break;
+        }
+        match rest[len..].chars().next() {
+            Some('(') => {
+                if end > 0 {
+                    end += 1;
+                }
+                end += len;
+                rest = &rest[len + 1..];
+            }
+            Some(')') | None => {
+                if end > 0 {
+                    end += 1;
+                }
+                end += len;
+                break;
+            }
+            Some(_) => break,
+        }
+    }
+    &op[..end]
+}
+
+/// One difference found by [`Trace::diff`]. Indices are 0-based positions in
+/// the old and the new trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceChange {
+    /// Only the new run performed `op`.
+    Added { index: usize, op: String },
+    /// Only the old run performed `op`.
+    Removed { index: usize, op: String },
+    /// Both runs performed `op`, at different points.
+    Moved { op: String, from: usize, to: usize },
+    /// The runs performed the same variant with different payloads.
+    Changed {
+        from: usize,
+        to: usize,
+        before: String,
+        after: String,
+    },
+}
+
+impl fmt::Display for TraceChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { index, op } => write!(f, "+ {:>3}. {op}", index + 1),
+            Self::Removed { index, op } => write!(f, "- {:>3}. {op}", index + 1),
+            Self::Moved { op, from, to } => {
+                write!(f, "> {:>3}. {op} (moved to {})", from + 1, to + 1)
+            }
+            Self::Changed {
+                from,
+                to,
+                before,
+                after,
+            } => write!(f, "~ {:>3}. {before} -> {:>3}. {after}", from + 1, to + 1),
+        }
+    }
+}
+
+/// The differences between two [`Trace`]s, from [`Trace::diff`].
+///
+/// Displays as a listing of the changes, empty if the runs performed the same
+/// operations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceDiff {
+    changes: Vec<TraceChange>,
+    first_divergence: Option<usize>,
+}
+
+impl TraceDiff {
+    /// Returns `true` if both runs performed the same operations in the same
+    /// order.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn changes(&self) -> &[TraceChange] {
+        &self.changes
+    }
+
+    /// The 0-based position of the first operation the runs disagree on:
+    /// both runs performed the same operations before it. `None` if they
+    /// performed the same operations.
+    pub fn first_divergence(&self) -> Option<usize> {
+        self.first_divergence
+    }
+}
+
+impl fmt::Display for TraceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "effect trace diff ({} changes", self.changes.len())?;
+        if let Some(first) = self.first_divergence() {
+            write!(f, ", first at operation {}", first + 1)?;
+        }
+        writeln!(f, "):")?;
+        for change in &self.changes {
+            writeln!(f, "  {change}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "effect trace ({} operations):", self.entries.len())?;
+        for (i, entry) in self.entries.iter().enumerate() {
+            writeln!(f, "  {:>3}. {entry}", i + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Records each operation before passing it on.
+struct Tracing<'a, H> {
+    inner: H,
+    trace: &'a mut Trace,
+}
+
+impl<Op: OpMeta + fmt::Debug, H: PartialHandler<Op>> PartialHandler<Op> for Tracing<'_, H> {
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        self.trace.push(redacted(op), perform_location());
+        self.inner.maybe_handle(op)
+    }
+}
+
+/// Runs `computation` with `h`, printing the trace of its operations to
+/// stderr if it fails.
+///
+/// This is what `#[algae_test]` expands to.
+///
+/// # Panics
+///
+/// Re-raises a panic of the computation or the handler, and panics if an
+/// operation is unhandled or aborted.
+pub fn run_test<R, Op, H>(computation: Effectful<R, Op>, h: H) -> R
+where
+    Op: OpMeta + fmt::Debug,
+    H: PartialHandler<Op>,
+{
+    let mut trace = Trace::default();
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        computation.try_run_with(Tracing {
+            inner: h,
+            trace: &mut trace,
+        })
+    }));
+    match outcome {
+        Ok(Ok(result)) => result,
+        Ok(Err(error)) => panic!("{error}\n{trace}"),
+        Err(payload) => {
+            eprintln!("{trace}");
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use crate::effects::log::LogEvent;
+    use algae::prelude::*;
+    use algae_macros::algae_test;
+    use std::time::{Duration, SystemTime};
+
+    effect! {
+        use algae::effects::time::Time;
+        use algae::effects::log::Log;
+        use algae::effects::config::Config;
+        Db::Get (u32) -> Option<String>;
+    }
+
+    struct FakeDb;
+
+    impl PartialHandler<Op> for FakeDb {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Db(Db::Get(1)) => Some(Box::new(Some("alice".to_string()))),
+                Op::Db(Db::Get(_)) => Some(Box::new(None::<String>)),
+                _ => None,
+            }
+        }
+    }
+
+    fn fixture() -> VecHandler<Op> {
+        let mut handlers = VecHandler::new();
+        handlers.push(FakeDb);
+        handlers.push(DeterministicEnv::new().config("greeting", "hi"));
+        handlers
+    }
+
+    #[algae_test]
+    fn sleeping_advances_virtual_time() {
+        let _: () = perform!(Time::Sleep(Duration::from_secs(60)));
+        let now: SystemTime = perform!(Time::Now);
+        assert_eq!(now, SystemTime::UNIX_EPOCH + Duration::from_secs(60));
+    }
+
+    #[algae_test(fixture = fixture)]
+    fn fixture_handlers_are_used() {
+        let user: Option<String> = perform!(Db::Get(1));
+        let greeting: Option<Value> = perform!(Config::Get("greeting".into()));
+        let _: () = perform!(Log::Event(LogEvent::info("looked up alice")));
+        assert_eq!(user.as_deref(), Some("alice"));
+        assert_eq!(greeting, Some(Value::from("hi")));
+    }
+
+    /// A fixture configured like a production handler chain.
+    struct Greeting(&'static str);
+
+    impl HandlerFactory<Op> for Greeting {
+        type Handler = DeterministicEnv;
+
+        fn build(&self) -> DeterministicEnv {
+            DeterministicEnv::new().config("greeting", self.0)
+        }
+    }
+
+    #[algae_test(fixture = Greeting("hey"))]
+    fn fixtures_can_be_handler_factories() {
+        let greeting: Option<Value> = perform!(Config::Get("greeting".into()));
+        assert_eq!(greeting, Some(Value::from("hey")));
+    }
+
+    #[algae_test(fixture = fixture)]
+    fn tests_can_return_results() -> Result<(), String> {
+        let user: Option<String> = perform!(Db::Get(2));
+        match user {
+            None => Ok(()),
+            Some(name) => Err(format!("unexpected user {name}")),
+        }
+    }
+
+    #[algae_test]
+    #[should_panic(expected = "Db(Get(1))")]
+    fn unhandled_ops_fail_with_the_trace() {
+        let _: () = perform!(Time::Sleep(Duration::from_secs(1)));
+        let _: Option<String> = perform!(Db::Get(1));
+    }
+
+    #[test]
+    fn test_trace_lists_operations_in_order() {
+        #[effectful]
+        fn lookups() {
+            let _: Option<String> = perform!(Db::Get(1));
+            let _: Option<String> = perform!(Db::Get(2));
+        }
+
+        let mut trace = Trace::default();
+        lookups()
+            .try_run_with(Tracing {
+                inner: FakeDb,
+                trace: &mut trace,
+            })
+            .unwrap();
+        assert_eq!(trace.entries().len(), 2);
+        assert!(trace.entries()[0].starts_with("Db(Get(1)) at "));
+        assert!(trace.to_string().contains("  2. Db(Get(2))"));
+    }
+
+    #[effectful]
+    fn lookup_ids(ids: Vec<u32>) {
+        for id in ids {
+            let _: Option<String> = perform!(Db::Get(id));
+        }
+    }
+
+    #[effectful]
+    fn sleep_then_lookup(id: u32) {
+        let _: () = perform!(Time::Sleep(Duration::from_secs(1)));
+        let _: Option<String> = perform!(Db::Get(id));
+    }
+
+    fn record(computation: Effectful<(), Op>) -> Trace {
+        let (result, trace) = Trace::record(computation, fixture());
+        result.unwrap();
+        trace
+    }
+
+    #[test]
+    fn test_diff_of_identical_runs_is_empty() {
+        let diff = record(lookup_ids(vec![1, 2])).diff(&record(lookup_ids(vec![1, 2])));
+        assert!(diff.is_empty());
+        assert_eq!(diff.first_divergence(), None);
+        assert_eq!(diff.to_string(), "effect trace diff (0 changes):\n");
+    }
+
+    #[test]
+    fn test_diff_classifies_changes() {
+        let old = record(lookup_ids(vec![1, 2, 3]));
+
+        let diff = old.diff(&record(lookup_ids(vec![1, 3])));
+        assert_eq!(
+            diff.changes(),
+            [TraceChange::Removed {
+                index: 1,
+                op: "Db(Get(2))".into()
+            }]
+        );
+
+        let diff = old.diff(&record(lookup_ids(vec![1, 2, 3, 4])));
+        assert_eq!(diff.first_divergence(), Some(3));
+        assert_eq!(
+            diff.changes(),
+            [TraceChange::Added {
+                index: 3,
+                op: "Db(Get(4))".into()
+            }]
+        );
+
+        let diff = old.diff(&record(lookup_ids(vec![1, 5, 3])));
+        assert_eq!(
+            diff.changes(),
+            [TraceChange::Changed {
+                from: 1,
+                to: 1,
+                before: "Db(Get(2))".into(),
+                after: "Db(Get(5))".into(),
+            }]
+        );
+
+        let diff = old.diff(&record(lookup_ids(vec![3, 1, 2])));
+        assert_eq!(
+            diff.changes(),
+            [TraceChange::Moved {
+                op: "Db(Get(3))".into(),
+                from: 2,
+                to: 0,
+            }]
+        );
+        assert!(diff
+            .to_string()
+            .starts_with("effect trace diff (1 changes, first at operation 1):\n"));
+        assert!(diff
+            .to_string()
+            .contains("  >   3. Db(Get(3)) (moved to 1)"));
+    }
+
+    #[test]
+    fn test_diff_does_not_pair_different_variants() {
+        let diff = record(lookup_ids(vec![7])).diff(&record(sleep_then_lookup(7)));
+        assert_eq!(
+            diff.changes(),
+            [TraceChange::Added {
+                index: 0,
+                op: "Time(Sleep(1s))".into()
+            }]
+        );
+
+        let diff = record(lookup_ids(vec![1])).diff(&record(sleep_then_lookup(2)));
+        assert_eq!(diff.changes().len(), 2);
+        assert!(matches!(diff.changes()[0], TraceChange::Changed { .. }));
+        assert!(matches!(diff.changes()[1], TraceChange::Added { .. }));
+        assert_eq!(variant_name("Time(Now)"), "Time(Now");
+        assert_eq!(
+            variant_name("Log(Event(LogEvent { msg: \"x\" }))"),
+            "Log(Event"
+        );
+    }
+}
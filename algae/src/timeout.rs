@@ -0,0 +1,359 @@
+//! Per-operation timeouts.
+//!
+//! A [`TimeoutLayer`] wraps another handler and bounds how long the handler
+//! may take to answer each operation. Limits are set per family or per
+//! operation, so one slow backend fails its own operations quickly instead of
+//! stalling the whole workflow. This is independent of any deadline for the
+//! run as a whole.
+//!
+//! An operation that is not answered in time gets a [`TimedOut`] reply: by
+//! default an [`Abort`] carrying it, or, with [`TimeoutLayer::reply_with`], a
+//! value of the operation's own reply type, e.g. `Err(HttpError::Timeout)`.
+//!
+//! With the real clock, limited operations are dispatched on a helper thread
+//! while the layer waits for at most the limit. With a
+//! [`VirtualClock`](crate::effects::time::VirtualClock), operations are
+//! dispatched inline and the virtual time the handler spent on them is
+//! compared to the limit, so timeouts can be tested without waiting.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! #![feature(coroutines, coroutine_trait, yield_expr)]
+//! use algae::prelude::*;
+//! use algae::timeout::{TimedOut, TimeoutLayer};
+//! use std::time::Duration;
+//!
+//! effect! {
+//!     Http::Get (String) -> Result<String, HttpError>;
+//!     Db::Query (String) -> Vec<Row>;
+//! }
+//!
+//! let layer = TimeoutLayer::new(Backends::connect()?)
+//!     .limit_family::<Http>(Duration::from_secs(2))
+//!     .limit(Duration::from_millis(200), |op: &Op| matches!(op, Op::Db(Db::Query(_))))
+//!     .reply_with(|op: &Op, _: &TimedOut| match op {
+//!         Op::Http(_) => Some(Box::new(Err::<String, _>(HttpError::Timeout))),
+//!         _ => None, // abort
+//!     });
+//!
+//! let page = render_dashboard().try_run_with(layer)?;
+//! ```
+
+use crate::{redact::redacted, Abort, FamilyOp, OpMeta, PartialHandler};
+use std::{
+    any::Any,
+    fmt, panic,
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread,
+    time::Duration,
+};
+
+type Matches<Op> = Box<dyn Fn(&Op) -> bool + Send>;
+type ReplyWith<Op> = Box<dyn FnMut(&Op, &TimedOut) -> Option<Box<dyn Any + Send>> + Send>;
+
+/// Typed error carried by the [`Abort`] a [`TimeoutLayer`] replies with.
+///
+/// Retrieve it with `abort.downcast_ref::<TimedOut>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedOut {
+    /// The limit that was exceeded
+    pub limit: Duration,
+    /// `Debug` rendering of the operation
+    pub op: String,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.op, self.limit)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// The clock a [`TimeoutLayer`] measures dispatches with.
+enum Clock {
+    Real,
+    #[cfg(feature = "macros")]
+    Virtual(crate::effects::time::VirtualClock),
+}
+
+/// A handler layer that bounds how long the inner handler may take to answer
+/// an operation.
+///
+/// Limits are checked in registration order and the first one that matches
+/// an operation applies, so register specific limits before broader ones.
+/// Operations without a limit are dispatched as usual.
+///
+/// With the real clock, a dispatch that timed out keeps running on its
+/// helper thread, and the inner handler stays busy until it returns: later
+/// operations wait for it, limited ones for at most their own limit.
+///
+/// # Type Parameters
+///
+/// * `Op` - The operation type being dispatched
+/// * `H` - The inner partial handler that performs the operations
+pub struct TimeoutLayer<Op, H> {
+    limits: Vec<(Matches<Op>, Duration)>,
+    clock: Clock,
+    reply_with: Option<ReplyWith<Op>>,
+    inner: Arc<Mutex<H>>,
+}
+
+impl<Op, H> TimeoutLayer<Op, H> {
+    /// Wraps `inner` with no limits configured, measuring with the real
+    /// clock.
+    pub fn new(inner: H) -> Self {
+        Self {
+            limits: Vec::new(),
+            clock: Clock::Real,
+            reply_with: None,
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Bounds the operations matching `matches` to `limit`.
+    pub fn limit<F>(mut self, limit: Duration, matches: F) -> Self
+    where
+        F: Fn(&Op) -> bool + Send + 'static,
+    {
+        self.limits.push((Box::new(matches), limit));
+        self
+    }
+
+    /// Bounds the operations of family `F` to `limit`.
+    pub fn limit_family<F: Any>(self, limit: Duration) -> Self
+    where
+        Op: FamilyOp,
+    {
+        self.limit(limit, |op: &Op| op.family_op().is::<F>())
+    }
+
+    /// Bounds every operation not matched by an earlier limit to `limit`.
+    pub fn limit_all(self, limit: Duration) -> Self {
+        self.limit(limit, |_| true)
+    }
+
+    /// Measures dispatches with `clock` instead of the real clock.
+    ///
+    /// The inner handler is expected to move `clock` forward by the time its
+    /// work takes, e.g. by sleeping on it; an operation times out when it
+    /// moved the clock forward by more than its limit.
+    #[cfg(feature = "macros")]
+    pub fn virtual_clock(mut self, clock: &crate::effects::time::VirtualClock) -> Self {
+        self.clock = Clock::Virtual(clock.clone());
+        self
+    }
+
+    /// Answers timed-out operations with the reply returned by `reply`, e.g.
+    /// the error variant of the operation's `Result` reply type. Where
+    /// `reply` returns `None`, the operation is aborted with [`TimedOut`].
+    pub fn reply_with<F>(mut self, reply: F) -> Self
+    where
+        F: FnMut(&Op, &TimedOut) -> Option<Box<dyn Any + Send>> + Send + 'static,
+    {
+        self.reply_with = Some(Box::new(reply));
+        self
+    }
+
+    fn timed_out(&mut self, op: &Op, limit: Duration) -> Box<dyn Any + Send>
+    where
+        Op: OpMeta + fmt::Debug,
+    {
+        let timed_out = TimedOut {
+            limit,
+            op: redacted(op),
+        };
+        self.reply_with
+            .as_mut()
+            .and_then(|reply| reply(op, &timed_out))
+            .unwrap_or_else(|| Abort::boxed(timed_out))
+    }
+}
+
+impl<Op, H> PartialHandler<Op> for TimeoutLayer<Op, H>
+where
+    Op: Clone + Send + OpMeta + fmt::Debug + 'static,
+    H: PartialHandler<Op> + Send + 'static,
+{
+    fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+        let limit = self
+            .limits
+            .iter()
+            .find(|(matches, _)| matches(op))
+            .map(|&(_, limit)| limit);
+        let Some(limit) = limit else {
+            return lock(&self.inner).maybe_handle(op);
+        };
+
+        match &self.clock {
+            Clock::Real => {
+                let (tx, rx) = mpsc::sync_channel(1);
+                let inner = Arc::clone(&self.inner);
+                let owned = op.clone();
+                let worker = thread::spawn(move || {
+                    let reply = lock(&inner).maybe_handle(&owned);
+                    // The layer has stopped waiting if the send fails
+                    let _ = tx.send(reply);
+                });
+                match rx.recv_timeout(limit) {
+                    Ok(reply) => reply,
+                    Err(mpsc::RecvTimeoutError::Timeout) => Some(self.timed_out(op, limit)),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => match worker.join() {
+                        Err(panic) => panic::resume_unwind(panic),
+                        Ok(()) => unreachable!("the worker sends before it returns"),
+                    },
+                }
+            }
+            #[cfg(feature = "macros")]
+            Clock::Virtual(clock) => {
+                let start = clock.elapsed();
+                let reply = lock(&self.inner).maybe_handle(op)?;
+                if clock.elapsed().saturating_sub(start) > limit {
+                    Some(self.timed_out(op, limit))
+                } else {
+                    Some(reply)
+                }
+            }
+        }
+    }
+}
+
+/// Locks the inner handler; a panic in an earlier dispatch has already been
+/// reported, so poisoning is ignored.
+fn lock<H>(inner: &Mutex<H>) -> std::sync::MutexGuard<'_, H> {
+    inner.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::effects::time::VirtualClock;
+    use algae::prelude::*;
+    use std::time::Instant;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum HttpError {
+        Timeout,
+    }
+
+    effect! {
+        Http::Get (u64) -> Result<String, HttpError>;
+        Db::Query (u64) -> u32;
+        Log::Info (String) -> ();
+    }
+
+    /// Takes as many milliseconds to answer as the operation asks for, on
+    /// the real clock or on a virtual one.
+    struct Backends {
+        clock: Option<VirtualClock>,
+    }
+
+    impl Backends {
+        fn wait(&self, ms: u64) {
+            let duration = Duration::from_millis(ms);
+            match &self.clock {
+                Some(clock) => clock.advance(duration),
+                None => thread::sleep(duration),
+            }
+        }
+    }
+
+    impl PartialHandler<Op> for Backends {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Http(Http::Get(ms)) => {
+                    self.wait(*ms);
+                    Some(Box::new(Ok::<_, HttpError>(format!("{ms}ms"))))
+                }
+                Op::Db(Db::Query(ms)) => {
+                    self.wait(*ms);
+                    Some(Box::new(*ms as u32))
+                }
+                Op::Log(_) => Some(Box::new(())),
+            }
+        }
+    }
+
+    #[effectful]
+    fn query(ms: u64) -> u32 {
+        let _: () = perform!(Log::Info("querying".into()));
+        perform!(Db::Query(ms))
+    }
+
+    #[effectful]
+    fn fetch_all(delays: Vec<u64>) -> Vec<Result<String, HttpError>> {
+        let mut pages = Vec::new();
+        for ms in delays {
+            let page: Result<String, HttpError> = perform!(Http::Get(ms));
+            pages.push(page);
+        }
+        pages
+    }
+
+    fn virtual_layer(clock: &VirtualClock) -> TimeoutLayer<Op, Backends> {
+        TimeoutLayer::new(Backends {
+            clock: Some(clock.clone()),
+        })
+        .virtual_clock(clock)
+    }
+
+    #[test]
+    fn test_virtual_timeouts_abort_with_timed_out() {
+        let clock = VirtualClock::new();
+        let layer = virtual_layer(&clock).limit_family::<Db>(Duration::from_millis(100));
+        assert_eq!(query(100).try_run_with(layer).unwrap(), 100);
+
+        let layer = virtual_layer(&clock).limit_family::<Db>(Duration::from_millis(100));
+        let Err(EffectError::Aborted(abort)) = query(250).try_run_with(layer) else {
+            panic!("the query should time out")
+        };
+        let timed_out = abort.downcast_ref::<TimedOut>().unwrap();
+        assert_eq!(timed_out.limit, Duration::from_millis(100));
+        assert_eq!(
+            timed_out.to_string(),
+            "Db(Query(250)) timed out after 100ms"
+        );
+    }
+
+    #[test]
+    fn test_typed_replies_and_first_matching_limit() {
+        let clock = VirtualClock::new();
+        let layer = virtual_layer(&clock)
+            .limit(
+                Duration::from_secs(5),
+                |op: &Op| matches!(op, Op::Http(Http::Get(ms)) if *ms > 1000),
+            )
+            .limit_family::<Http>(Duration::from_millis(50))
+            .reply_with(|op: &Op, _: &TimedOut| match op {
+                Op::Http(_) => Some(Box::new(Err::<String, _>(HttpError::Timeout))),
+                _ => None,
+            });
+        let pages = fetch_all(vec![10, 80, 2000, 1200])
+            .try_run_with(layer)
+            .unwrap();
+        assert_eq!(
+            pages,
+            [
+                Ok("10ms".into()),
+                Err(HttpError::Timeout),
+                Ok("2000ms".into()),
+                Ok("1200ms".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_real_clock_stops_waiting_for_slow_backends() {
+        let layer = TimeoutLayer::new(Backends { clock: None })
+            .limit_family::<Http>(Duration::from_millis(20))
+            .reply_with(|_: &Op, _: &TimedOut| {
+                Some(Box::new(Err::<String, _>(HttpError::Timeout)))
+            });
+        let start = Instant::now();
+        let pages = fetch_all(vec![1, 2000]).try_run_with(layer).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(pages, [Ok("1ms".into()), Err(HttpError::Timeout)]);
+    }
+}
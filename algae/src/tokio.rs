@@ -0,0 +1,336 @@
+//! Running computations on tokio (feature `tokio`, or its alias
+//! `algae-tokio`).
+//!
+//! - [`Tokio`] implements [`Spawner`] with tokio's task functions.
+//! - [`Effectful::into_future`] turns a computation into a future answered
+//!   by an [`AsyncHandler`], ready for `tokio::spawn`.
+//! - [`channel`] connects computations to a handler running as a task of its
+//!   own, an actor: their operations are sent to it over an `mpsc` channel,
+//!   and [`serve`] answers them one after the other.
+//!
+//! ```rust,ignore
+//! use algae::tokio::{channel, serve};
+//!
+//! let (users, requests) = channel(64);
+//! let actor = tokio::spawn(serve(requests, UsersDb::connect(&url)?));
+//! let profiles: Vec<_> = ids
+//!     .into_iter()
+//!     .map(|id| tokio::spawn(load_profile(id).into_future(users.clone())))
+//!     .collect();
+//! for profile in profiles {
+//!     println!("{:?}", profile.await??);
+//! }
+//! drop(users);
+//! let db = actor.await?; // once every computation is done
+//! ```
+
+use crate::{
+    executor::{AsyncHandler, BoxFuture, Spawner},
+    Abort, EffectError, Effectful, PartialHandler,
+};
+use ::tokio::{
+    sync::{mpsc, oneshot},
+    task::{self, JoinHandle},
+};
+use std::{
+    any::Any,
+    fmt,
+    future::Future,
+    panic,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Spawns tasks with `tokio::spawn`, and blocking work with
+/// `tokio::task::spawn_blocking`, on the current runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tokio;
+
+impl Spawner for Tokio {
+    type Handle<T: Send + 'static> = TokioTask<T>;
+
+    fn spawn<F>(&self, future: F) -> TokioTask<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        TokioTask(task::spawn(future))
+    }
+
+    fn spawn_blocking<F, T>(&self, f: F) -> TokioTask<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        TokioTask(task::spawn_blocking(f))
+    }
+}
+
+/// A task spawned by [`Tokio`], resolving to its output.
+///
+/// A panic in the task is resumed where the handle is awaited. Dropping a
+/// handle detaches the task; it keeps running.
+#[derive(Debug)]
+pub struct TokioTask<T>(JoinHandle<T>);
+
+impl<T> TokioTask<T> {
+    /// Cancels the task; awaiting the handle afterwards panics.
+    pub fn abort(&self) {
+        self.0.abort();
+    }
+}
+
+impl<T> Future for TokioTask<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(&mut self.0).poll(cx).map(|joined| match joined {
+            Ok(output) => output,
+            Err(error) => match error.try_into_panic() {
+                Ok(payload) => panic::resume_unwind(payload),
+                Err(error) => panic!("{error}"),
+            },
+        })
+    }
+}
+
+impl<R, Op> Effectful<R, Op>
+where
+    R: Send + 'static,
+    Op: Send + Sync + 'static,
+{
+    /// The computation as a future, answered by `async_h` alone like
+    /// [`run_async`](Effectful::run_async).
+    ///
+    /// The future is `Send` and `'static`, so it can be spawned as a task.
+    pub fn into_future<A>(self, async_h: A) -> BoxFuture<'static, Result<R, EffectError<Op>>>
+    where
+        A: AsyncHandler<Op> + Send + 'static,
+    {
+        Box::pin(self.run_async(async_h))
+    }
+}
+
+/// An operation sent to an actor, with the way back for its reply.
+pub struct Request<Op> {
+    op: Op,
+    reply: oneshot::Sender<Option<Box<dyn Any + Send>>>,
+}
+
+impl<Op> Request<Op> {
+    /// The operation the computation performed.
+    pub fn op(&self) -> &Op {
+        &self.op
+    }
+
+    /// Sends `reply` back to the computation that performed the operation;
+    /// `None` declines it.
+    pub fn reply(self, reply: Option<Box<dyn Any + Send>>) {
+        // A computation that went away no longer needs its reply
+        let _ = self.reply.send(reply);
+    }
+}
+
+impl<Op: fmt::Debug> fmt::Debug for Request<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("op", &self.op)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Abort reason for an operation whose actor stopped before replying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disconnected;
+
+/// Answers operations by sending them to an actor and awaiting its reply.
+///
+/// Clones send to the same actor.
+pub struct ChannelHandler<Op> {
+    requests: mpsc::Sender<Request<Op>>,
+}
+
+impl<Op> ChannelHandler<Op> {
+    /// A handler sending operations to the actor receiving from `requests`.
+    pub fn new(requests: mpsc::Sender<Request<Op>>) -> Self {
+        Self { requests }
+    }
+}
+
+impl<Op> Clone for ChannelHandler<Op> {
+    fn clone(&self) -> Self {
+        Self {
+            requests: self.requests.clone(),
+        }
+    }
+}
+
+impl<Op> fmt::Debug for ChannelHandler<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelHandler")
+            .field("closed", &self.requests.is_closed())
+            .finish()
+    }
+}
+
+impl<Op: Clone + Send + Sync> AsyncHandler<Op> for ChannelHandler<Op> {
+    fn maybe_handle<'a>(&'a mut self, op: &'a Op) -> BoxFuture<'a, Option<Box<dyn Any + Send>>> {
+        Box::pin(async move {
+            let (reply, replied) = oneshot::channel();
+            let request = Request {
+                op: op.clone(),
+                reply,
+            };
+            if self.requests.send(request).await.is_err() {
+                return Some(Abort::boxed(Disconnected));
+            }
+            replied
+                .await
+                .unwrap_or_else(|_| Some(Abort::boxed(Disconnected)))
+        })
+    }
+}
+
+/// Creates a channel to an actor holding up to `buffer` operations: the
+/// handler sending operations to it, and the receiving end for [`serve`].
+///
+/// # Panics
+///
+/// Panics if `buffer` is 0, like `tokio::sync::mpsc::channel`.
+pub fn channel<Op>(buffer: usize) -> (ChannelHandler<Op>, mpsc::Receiver<Request<Op>>) {
+    let (requests, received) = mpsc::channel(buffer);
+    (ChannelHandler::new(requests), received)
+}
+
+/// Answers the requests arriving on `requests` with `h`, in order, until
+/// every [`ChannelHandler`] sending to it is dropped, and returns the
+/// handler.
+pub async fn serve<Op, H>(mut requests: mpsc::Receiver<Request<Op>>, mut h: H) -> H
+where
+    H: PartialHandler<Op>,
+{
+    while let Some(request) = requests.recv().await {
+        let reply = h.maybe_handle(&request.op);
+        request.reply(reply);
+    }
+    h
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use super::*;
+    use crate as algae;
+    use algae::prelude::*;
+
+    effect! {
+        Clock::Tick -> ();
+        Counter::Add (u32) -> u32;
+    }
+
+    /// Answers ticks after yielding to the runtime once.
+    struct Ticker;
+
+    impl AsyncHandler<Op> for Ticker {
+        fn maybe_handle<'a>(
+            &'a mut self,
+            op: &'a Op,
+        ) -> BoxFuture<'a, Option<Box<dyn Any + Send>>> {
+            Box::pin(async move {
+                let Op::Clock(Clock::Tick) = op else {
+                    return None;
+                };
+                task::yield_now().await;
+                Some(Box::new(()) as Box<dyn Any + Send>)
+            })
+        }
+    }
+
+    /// Adds up what it is given; answers ticks too, for the actor tests.
+    struct Tally(u32);
+
+    impl PartialHandler<Op> for Tally {
+        fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn Any + Send>> {
+            match op {
+                Op::Counter(Counter::Add(n)) => {
+                    self.0 += n;
+                    Some(Box::new(self.0))
+                }
+                Op::Clock(Clock::Tick) => Some(Box::new(())),
+            }
+        }
+    }
+
+    #[effectful]
+    fn count(n: u32) -> u32 {
+        let mut total = 0;
+        for _ in 0..n {
+            let _: () = perform!(Clock::Tick);
+            total = perform!(Counter::Add(1));
+        }
+        total
+    }
+
+    #[effectful]
+    fn tick_twice() -> u32 {
+        let _: () = perform!(Clock::Tick);
+        let _: () = perform!(Clock::Tick);
+        2
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        ::tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_spawned_computations() {
+        let (handled, async_handled) = block_on(async {
+            let handled = Tokio.spawn_handled(count(2), Tally(10));
+            let async_handled = Tokio.spawn_async(count(3), Ticker, Tally(0));
+            (handled.await, async_handled.await)
+        });
+        assert_eq!(handled.unwrap(), 12);
+        assert_eq!(async_handled.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_computations_become_futures() {
+        let ticks = block_on(async { task::spawn(tick_twice().into_future(Ticker)).await });
+        assert_eq!(ticks.unwrap().unwrap(), 2);
+
+        let unhandled = block_on(count(1).into_future(Ticker)).unwrap_err();
+        assert!(matches!(unhandled, EffectError::Unhandled(_)));
+    }
+
+    #[test]
+    fn test_actors_answer_through_channels() {
+        let (totals, tally) = block_on(async {
+            let (handler, requests) = channel(4);
+            let actor = task::spawn(serve(requests, Tally(0)));
+            let counters: Vec<_> = (1..=3)
+                .map(|n| task::spawn(count(n).into_future(handler.clone())))
+                .collect();
+            drop(handler);
+            let mut totals = Vec::new();
+            for counter in counters {
+                totals.push(counter.await.unwrap().unwrap());
+            }
+            (totals, actor.await.unwrap())
+        });
+        assert_eq!(tally.0, 6, "every addition reached the actor");
+        assert_eq!(*totals.iter().max().unwrap(), 6);
+    }
+
+    #[test]
+    fn test_operations_abort_once_the_actor_is_gone() {
+        let (handler, requests) = channel::<Op>(1);
+        drop(requests);
+        let EffectError::Aborted(abort) = block_on(count(1).into_future(handler)).unwrap_err()
+        else {
+            panic!("the operation aborts");
+        };
+        assert!(abort.is::<Disconnected>());
+    }
+}
@@ -0,0 +1,249 @@
+//! `tracing` spans for running computations (feature `tracing`).
+//!
+//! The `run*` drivers of [`Effectful`](crate::Effectful) and
+//! [`Handled`](crate::Handled) open an `algae.run` span for the run as a
+//! whole, and inside it an `algae.perform` span for each operation they
+//! dispatch, so effectful programs show up in any `tracing` subscriber
+//! without their handlers doing anything. Both use the `algae` target.
+//!
+//! | Span | Level | Fields |
+//! |------|-------|--------|
+//! | `algae.run` | `DEBUG` | `handler`: type name of the handler, `outcome` |
+//! | `algae.perform` | `TRACE` | `op`, `handler`, `location` of the `perform!`, `latency_us`, `outcome` |
+//!
+//! `op` renders the operation with `Debug`, with the payloads of operations
+//! tagged `#[tag(sensitive)]` redacted as by [`redact::redacted`]. It is left
+//! out for operations not performed by an `#[effectful]` function, and for
+//! functions whose root type is generic and not known to implement `Debug`
+//! and [`OpMeta`](crate::OpMeta). `outcome` is `replied`, `unhandled`,
+//! `aborted` or `mismatched`, and for runs also `completed`.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! tracing_subscriber::fmt()
+//!     .with_env_filter("algae=trace")
+//!     .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+//!     .init();
+//!
+//! checkout(cart).handle(Backends::connect()?).run()?;
+//! ```
+//!
+//! [`redact::redacted`]: crate::redact::redacted
+
+use crate::{Effect, EffectError, Reply};
+use std::time::Instant;
+use tracing::{field::Empty, Span};
+
+/// The span of one run, with the handler named `handler`.
+pub(crate) fn run_span(handler: &'static str) -> Span {
+    tracing::debug_span!(target: "algae", "algae.run", handler, outcome = Empty)
+}
+
+/// Records on the span of a run how it ended.
+pub(crate) fn record_run<R, Op>(span: &Span, result: &Result<R, EffectError<Op>>) {
+    span.record("outcome", outcome(result, "completed"));
+}
+
+/// Dispatches `eff` with `answer` inside an `algae.perform` span.
+pub(crate) fn dispatch<Op>(
+    eff: Effect<Op>,
+    handler: &'static str,
+    answer: impl FnOnce(Effect<Op>) -> Result<Reply, EffectError<Op>>,
+) -> Result<Reply, EffectError<Op>> {
+    let span = tracing::trace_span!(
+        target: "algae",
+        "algae.perform",
+        op = Empty,
+        handler,
+        location = %eff.location(),
+        latency_us = Empty,
+        outcome = Empty,
+    );
+    if span.is_disabled() {
+        return answer(eff);
+    }
+    if let Some(describe) = eff.describe {
+        span.record("op", describe(&eff.op));
+    }
+    let _entered = span.enter();
+    let start = Instant::now();
+    let result = answer(eff);
+    span.record("latency_us", start.elapsed().as_micros() as u64);
+    span.record("outcome", outcome(&result, "replied"));
+    result
+}
+
+fn outcome<T, Op>(result: &Result<T, EffectError<Op>>, ok: &'static str) -> &'static str {
+    match result {
+        Ok(_) => ok,
+        Err(EffectError::Unhandled(_)) => "unhandled",
+        Err(EffectError::Aborted(_)) => "aborted",
+        Err(EffectError::ReplyMismatch(_)) => "mismatched",
+    }
+}
+
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use crate as algae;
+    use algae::prelude::*;
+    use std::{
+        collections::HashMap,
+        fmt,
+        sync::{Arc, Mutex},
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span,
+    };
+
+    effect! {
+        Console::Print (String) -> ();
+        Console::ReadLine -> String;
+        #[tag(sensitive)]
+        Auth::Login (String) -> bool;
+    }
+
+    #[effectful]
+    fn login() -> bool {
+        let name: String = perform!(Console::ReadLine);
+        let _: () = perform!(Console::Print(format!("Hello, {name}!")));
+        perform!(Auth::Login("hunter2".into()))
+    }
+
+    struct Terminal;
+
+    impl Handler<Op> for Terminal {
+        fn handle(&mut self, op: &Op) -> Box<dyn std::any::Any + Send> {
+            match op {
+                Op::Console(Console::ReadLine) => Box::new("Alice".to_string()),
+                Op::Console(Console::Print(_)) => Box::new(()),
+                Op::Auth(Auth::Login(_)) => Box::new(true),
+            }
+        }
+    }
+
+    /// A span's name, parent and fields, rendered with `Debug`.
+    #[derive(Debug, Default)]
+    struct Recorded {
+        name: &'static str,
+        parent: Option<u64>,
+        fields: HashMap<&'static str, String>,
+    }
+
+    impl Visit for Recorded {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.fields.insert(field.name(), format!("{value:?}"));
+        }
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.fields.insert(field.name(), value.to_string());
+        }
+    }
+
+    #[derive(Default)]
+    struct Capture {
+        spans: Mutex<Vec<Recorded>>,
+        stack: Mutex<Vec<u64>>,
+    }
+
+    impl Capture {
+        fn spans_named(&self, name: &str) -> Vec<HashMap<&'static str, String>> {
+            let spans = self.spans.lock().unwrap();
+            spans
+                .iter()
+                .filter(|span| span.name == name)
+                .map(|span| span.fields.clone())
+                .collect()
+        }
+    }
+
+    impl tracing::Subscriber for Capture {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+            let mut recorded = Recorded {
+                name: attrs.metadata().name(),
+                parent: self.stack.lock().unwrap().last().copied(),
+                ..Recorded::default()
+            };
+            attrs.record(&mut recorded);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(recorded);
+            span::Id::from_u64(spans.len() as u64)
+        }
+        fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+            values.record(&mut self.spans.lock().unwrap()[id.into_u64() as usize - 1]);
+        }
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, _: &tracing::Event<'_>) {}
+        fn enter(&self, id: &span::Id) {
+            self.stack.lock().unwrap().push(id.into_u64());
+        }
+        fn exit(&self, _: &span::Id) {
+            self.stack.lock().unwrap().pop();
+        }
+    }
+
+    #[test]
+    fn test_runs_and_performs_get_spans() {
+        let capture = Arc::new(Capture::default());
+        tracing::subscriber::with_default(capture.clone(), || {
+            assert!(login().handle(Terminal).run_or_panic());
+        });
+
+        let handler = std::any::type_name::<Terminal>();
+        let runs = capture.spans_named("algae.run");
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0]["handler"], handler);
+        assert_eq!(runs[0]["outcome"], "completed");
+
+        let performs = capture.spans_named("algae.perform");
+        let ops: Vec<&str> = performs.iter().map(|span| span["op"].as_str()).collect();
+        assert_eq!(
+            ops,
+            [
+                "Console(ReadLine)",
+                r#"Console(Print("Hello, Alice!"))"#,
+                "Auth(Login(<redacted>))"
+            ]
+        );
+        for span in &performs {
+            assert_eq!(span["handler"], handler);
+            assert_eq!(span["outcome"], "replied");
+            assert!(span["location"].contains("trace.rs"));
+            assert!(span["latency_us"].parse::<u64>().is_ok());
+        }
+        let spans = capture.spans.lock().unwrap();
+        assert!(spans
+            .iter()
+            .filter(|span| span.name == "algae.perform")
+            .all(|span| span.parent == Some(1)));
+    }
+
+    #[test]
+    fn test_declined_operations_end_the_run_span() {
+        struct Quiet;
+
+        impl PartialHandler<Op> for Quiet {
+            fn maybe_handle(&mut self, op: &Op) -> Option<Box<dyn std::any::Any + Send>> {
+                match op {
+                    Op::Console(Console::ReadLine) => Some(Box::new("Bob".to_string())),
+                    _ => None,
+                }
+            }
+        }
+
+        let capture = Arc::new(Capture::default());
+        tracing::subscriber::with_default(capture.clone(), || {
+            assert!(login().try_run_with(Quiet).is_err());
+        });
+        assert_eq!(capture.spans_named("algae.run")[0]["outcome"], "unhandled");
+        let outcomes: Vec<String> = capture
+            .spans_named("algae.perform")
+            .into_iter()
+            .map(|mut span| span.remove("outcome").unwrap())
+            .collect();
+        assert_eq!(outcomes, ["replied", "unhandled"]);
+    }
+}
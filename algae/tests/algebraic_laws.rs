@@ -1,4 +1,4 @@
-#![feature(coroutines, coroutine_trait, yield_expr)]
+#![feature(coroutines, yield_expr)]
 #![cfg(feature = "macros")]
 
 //! # Algebraic Laws Tests - A Learning Guide to Algebraic Effects
@@ -534,13 +534,13 @@ fn test_associativity_of_sequential_composition() {
     let left_result = left
         .bind(|_| get_final_state())
         .handle(StateHandler::new(0))
-        .run_checked()
+        .run()
         .unwrap();
 
     let right_result = right
         .bind(|_| get_final_state())
         .handle(StateHandler::new(0))
-        .run_checked()
+        .run()
         .unwrap();
 
     // Both should produce the same result
@@ -620,9 +620,9 @@ fn test_left_identity() {
     let rhs = f(5);
 
     // Run both with fresh handlers
-    let lhs_result = lhs.handle(StateHandler::new(0)).run_checked().unwrap();
+    let lhs_result = lhs.handle(StateHandler::new(0)).run().unwrap();
 
-    let rhs_result = rhs.handle(StateHandler::new(0)).run_checked().unwrap();
+    let rhs_result = rhs.handle(StateHandler::new(0)).run().unwrap();
 
     // Both should produce the same result
     assert_eq!(lhs_result, rhs_result);
@@ -700,9 +700,9 @@ fn test_right_identity() {
     let rhs = m();
 
     // Run both with fresh handlers
-    let lhs_result = lhs.handle(StateHandler::new(0)).run_checked().unwrap();
+    let lhs_result = lhs.handle(StateHandler::new(0)).run().unwrap();
 
-    let rhs_result = rhs.handle(StateHandler::new(0)).run_checked().unwrap();
+    let rhs_result = rhs.handle(StateHandler::new(0)).run().unwrap();
 
     // Both should produce the same result
     assert_eq!(lhs_result, rhs_result);
@@ -879,9 +879,9 @@ fn test_bind_associativity() {
     // Right: (m >>= (λx -> f(x) >>= g)) - inner bind is inside the lambda
 
     // Run both with fresh handlers
-    let left_result = left.handle(StateHandler::new(0)).run_checked().unwrap();
+    let left_result = left.handle(StateHandler::new(0)).run().unwrap();
 
-    let right_result = right.handle(StateHandler::new(0)).run_checked().unwrap();
+    let right_result = right.handle(StateHandler::new(0)).run().unwrap();
 
     // Both should give the same result
     assert_eq!(left_result, right_result);
@@ -942,7 +942,7 @@ fn test_handler_homomorphism() {
         42 // No effects - this is return(42)
     }
 
-    let handled_pure = pure_value().handle(PureHandler).run_checked().unwrap();
+    let handled_pure = pure_value().handle(PureHandler).run().unwrap();
 
     assert_eq!(handled_pure, 42);
 
@@ -1037,7 +1037,7 @@ fn test_handler_homomorphism() {
     // First compose with bind, then handle with a single handler instance
     let handler_left = RecordingStateHandler::new(0);
     let composed = op_stateful().bind(k_stateful);
-    let left_result = composed.handle(handler_left.clone()).run_checked().unwrap();
+    let left_result = composed.handle(handler_left.clone()).run().unwrap();
     let left_trace = handler_left.get_trace();
     let left_final_state = handler_left.get_state();
 
@@ -1051,15 +1051,12 @@ fn test_handler_homomorphism() {
 
     // First, show the incorrect approach with fresh handler for k:
     let handler_wrong = RecordingStateHandler::new(0);
-    let handled_op_wrong = op_stateful()
-        .handle(handler_wrong.clone())
-        .run_checked()
-        .unwrap();
+    let handled_op_wrong = op_stateful().handle(handler_wrong.clone()).run().unwrap();
 
     let handler_k_fresh = RecordingStateHandler::new(0); // Fresh handler - WRONG!
     let wrong_result = k_stateful(handled_op_wrong)
         .handle(handler_k_fresh.clone())
-        .run_checked()
+        .run()
         .unwrap();
     let wrong_trace = handler_k_fresh.get_trace();
 
@@ -1209,14 +1206,8 @@ fn test_effect_commutativity() {
     let handler1 = RecordingPureHandler::new();
     let handler2 = RecordingPureHandler::new();
 
-    let result1 = order1(10, 20)
-        .handle(handler1.clone())
-        .run_checked()
-        .unwrap();
-    let result2 = order2(10, 20)
-        .handle(handler2.clone())
-        .run_checked()
-        .unwrap();
+    let result1 = order1(10, 20).handle(handler1.clone()).run().unwrap();
+    let result2 = order2(10, 20).handle(handler2.clone()).run().unwrap();
 
     assert_eq!(result1, result2);
     assert_eq!(result1, (10 + 5) + (20 + 3)); // 38
@@ -1295,9 +1286,9 @@ fn test_effect_non_commutativity() {
     }
 
     // Run each with fresh handlers starting from the SAME initial state
-    let result1 = order1().handle(StateHandler::new(0)).run_checked().unwrap();
+    let result1 = order1().handle(StateHandler::new(0)).run().unwrap();
 
-    let result2 = order2().handle(StateHandler::new(0)).run_checked().unwrap();
+    let result2 = order2().handle(StateHandler::new(0)).run().unwrap();
 
     // Results should be different - last set wins
     assert_eq!(result1, 20); // Last operation was Set(20)
@@ -1354,7 +1345,7 @@ fn test_handler_composition() {
 
     let result = multi_effect_computation()
         .handle(CombinedHandler::new(0))
-        .run_checked()
+        .run()
         .unwrap();
 
     // Should be (5 * 3) + 7 = 22
@@ -1408,11 +1399,11 @@ fn test_distributivity() {
 
     let left_result = distributive_left(2, 3, 4)
         .handle(PureHandler)
-        .run_checked()
+        .run()
         .unwrap();
     let right_result = distributive_right(2, 3, 4)
         .handle(PureHandler)
-        .run_checked()
+        .run()
         .unwrap();
 
     // Both should equal 2 * (3 + 4) = 2 * 3 + 2 * 4 = 14
@@ -1478,7 +1469,7 @@ fn test_idempotency() {
 
     let (single, double, equal) = test_idempotent()
         .handle(StateHandler::new(0))
-        .run_checked()
+        .run()
         .unwrap();
 
     assert!(equal);
@@ -1565,10 +1556,7 @@ fn test_algebraic_equations() {
         results
     }
 
-    let results = test_equations()
-        .handle(StateHandler::new(0))
-        .run_checked()
-        .unwrap();
+    let results = test_equations().handle(StateHandler::new(0)).run().unwrap();
 
     for (equation, holds) in results {
         assert!(holds, "Equation failed: {equation}");
@@ -1622,7 +1610,7 @@ fn test_parametricity() {
         .map(|initial| {
             state_increment_pattern()
                 .handle(StateHandler::new(initial))
-                .run_checked()
+                .run()
                 .unwrap()
         })
         .collect();
@@ -1690,10 +1678,7 @@ fn test_non_idempotency() {
         (single, double, single == double)
     }
 
-    let (single, double, equal) = test_increment()
-        .handle(StateHandler::new(0))
-        .run_checked()
-        .unwrap();
+    let (single, double, equal) = test_increment().handle(StateHandler::new(0)).run().unwrap();
 
     assert!(!equal); // Should NOT be equal
     assert_eq!(single, 1);
@@ -1742,7 +1727,7 @@ fn test_one_shot_guarantee() {
     let counter = CountingHandler::new();
     let count_ref = Arc::clone(&counter.count);
 
-    let result = single_get().handle(counter).run();
+    let result = single_get().handle(counter).run_or_panic();
 
     assert_eq!(result, 42);
     assert_eq!(count_ref.load(Ordering::SeqCst), 1); // Called exactly once
@@ -1800,7 +1785,7 @@ fn test_one_shot_guarantee() {
     let malicious = MaliciousHandler::new();
     let attempts_ref = Arc::clone(&malicious.attempted_double_resume);
 
-    let result2 = single_get().handle(malicious).run();
+    let result2 = single_get().handle(malicious).run_or_panic();
 
     assert_eq!(result2, 999);
     assert_eq!(attempts_ref.load(Ordering::SeqCst), 1);
@@ -0,0 +1,111 @@
+#![feature(coroutines, yield_expr)]
+#![cfg(feature = "macros")]
+
+//! Small replies answered through `Reply::new` never reach the allocator.
+//!
+//! This lives in its own test binary because it installs a counting global
+//! allocator.
+
+use algae::prelude::*;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    any::Any,
+    cell::Cell,
+};
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts the allocations made by the current thread.
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// The allocations `f` makes on this thread.
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+effect! {
+    typed;
+    Counter::Next -> u64;
+    Counter::Pair -> (u32, u32);
+    Counter::Reset -> ();
+}
+
+#[effectful]
+fn count(n: u64) -> u64 {
+    let mut total = 0;
+    for _ in 0..n {
+        let next: u64 = perform!(Counter::Next);
+        let (a, b): (u32, u32) = perform!(Counter::Pair);
+        let _: () = perform!(Counter::Reset);
+        total += next + u64::from(a + b);
+    }
+    total
+}
+
+struct Inline(u64);
+
+impl TypedHandler<Op> for Inline {
+    fn handle(&mut self, call: OpCall<'_>) -> Answer {
+        match call {
+            OpCall::Counter(CounterCall::Next(reply)) => {
+                self.0 += 1;
+                reply.send(self.0)
+            }
+            OpCall::Counter(CounterCall::Pair(reply)) => reply.send((1, 2)),
+            OpCall::Counter(CounterCall::Reset(reply)) => reply.send(()),
+        }
+    }
+}
+
+struct Boxed(u64);
+
+impl Handler<Op> for Boxed {
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        match op {
+            Op::Counter(Counter::Next) => {
+                self.0 += 1;
+                Box::new(self.0)
+            }
+            Op::Counter(Counter::Pair) => Box::new((1u32, 2u32)),
+            Op::Counter(Counter::Reset) => Box::new(()),
+        }
+    }
+}
+
+#[test]
+fn test_small_replies_do_not_allocate() {
+    // The coroutine itself is boxed when the computation is built
+    let computation = count(1_000);
+    let mut total = 0;
+    let made = allocations(|| total = computation.handle(Typed(Inline(0))).run_or_panic());
+    assert_eq!(total, 500_500 + 3_000);
+    assert_eq!(made, 0);
+}
+
+#[test]
+fn test_boxed_replies_still_work() {
+    let computation = count(1_000);
+    let mut total = 0;
+    let made = allocations(|| total = computation.handle(Boxed(0)).run_or_panic());
+    assert_eq!(total, 500_500 + 3_000);
+    // `()` is zero-sized, so only `Next` and `Pair` allocate
+    assert_eq!(made, 2_000);
+}
@@ -0,0 +1,118 @@
+#![feature(coroutines, yield_expr)]
+#![cfg(feature = "macros")]
+
+//! `#[derive(RouteHandler)]` routes each family to the field marked for it,
+//! the same way a hand-written `match` on the root enum does.
+
+use algae::prelude::*;
+use std::any::Any;
+
+effect! {
+    Counter::Add (i32) -> i32;
+    Counter::Get -> i32;
+    Console::Print (String) -> ();
+    Log::Info (String) -> ();
+}
+
+#[derive(Default)]
+struct CounterHandler(i32);
+
+impl Handler<Op> for CounterHandler {
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        match op {
+            Op::Counter(Counter::Add(n)) => {
+                self.0 += n;
+                Box::new(self.0)
+            }
+            Op::Counter(Counter::Get) => Box::new(self.0),
+            _ => unreachable!("only counter operations are routed here"),
+        }
+    }
+}
+
+/// Keeps every line printed or logged, in order.
+#[derive(Default)]
+struct Lines(Vec<String>);
+
+impl Handler<Op> for Lines {
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        match op {
+            Op::Console(Console::Print(line)) | Op::Log(Log::Info(line)) => {
+                self.0.push(line.clone());
+                Box::new(())
+            }
+            _ => unreachable!("only output operations are routed here"),
+        }
+    }
+}
+
+#[derive(Default, RouteHandler)]
+struct Derived {
+    #[route(Op::Counter)]
+    counter: CounterHandler,
+    #[route(Op::Console, Op::Log)]
+    output: Lines,
+}
+
+#[derive(Default)]
+struct HandWritten {
+    counter: CounterHandler,
+    output: Lines,
+}
+
+impl Handler<Op> for HandWritten {
+    fn handle(&mut self, op: &Op) -> Box<dyn Any + Send> {
+        match op {
+            Op::Counter(_) => self.counter.handle(op),
+            Op::Console(_) | Op::Log(_) => self.output.handle(op),
+        }
+    }
+}
+
+#[effectful]
+fn count_to(n: i32) -> i32 {
+    let _: () = perform!(Log::Info(format!("counting to {n}")));
+    for i in 1..=n {
+        let total: i32 = perform!(Counter::Add(i));
+        let _: () = perform!(Console::Print(format!("total {total}")));
+    }
+    perform!(Counter::Get)
+}
+
+#[test]
+fn test_derived_routing_matches_hand_written_routing() {
+    let (derived, derived_handler) = count_to(3).run_with_handler(Derived::default());
+    let (written, written_handler) = count_to(3).run_with_handler(HandWritten::default());
+    assert_eq!(derived.unwrap(), 6);
+    assert_eq!(written.unwrap(), 6);
+    assert_eq!(derived_handler.counter.0, written_handler.counter.0);
+    assert_eq!(derived_handler.output.0, written_handler.output.0);
+    assert_eq!(
+        derived_handler.output.0,
+        ["counting to 3", "total 1", "total 3", "total 6"]
+    );
+}
+
+#[derive(RouteHandler)]
+#[route_handler(partial)]
+struct CountersOnly {
+    #[route(Op::Counter)]
+    counter: HandlerWrapper<Op, CounterHandler>,
+}
+
+#[test]
+fn test_partial_routes_leave_other_families_to_the_chain() {
+    let only = CountersOnly {
+        counter: HandlerWrapper::new(CounterHandler::default()),
+    };
+    let mut chain = VecHandler::new();
+    chain.push(only);
+    chain.push(HandlerWrapper::new(Lines::default()));
+    assert_eq!(count_to(2).handle(chain).run().unwrap(), 3);
+
+    let only = CountersOnly {
+        counter: HandlerWrapper::new(CounterHandler::default()),
+    };
+    let unhandled = count_to(2).try_run_with(only).unwrap_err();
+    assert!(matches!(unhandled, EffectError::Unhandled(_)));
+}